@@ -9,6 +9,7 @@ use chat_server::{
     cache::CacheManager,  
     presence::{PresenceManager, UserStatus, NotificationManager},
     reactions::{ReactionType, ReactionManager},
+    room_id::RoomId,
     monitoring::{ChatMetrics, MetricsExport},
     moderation::{ModerationSystem, SanctionType, SanctionReason},
     hub::common::ChatHub,
@@ -72,7 +73,7 @@ pub async fn demo_advanced_features() -> Result<()> {
     // Validation nom de salon
     let room_names = vec!["salon-general", "test_room", "invalid room!", "ADMIN-ONLY"];
     for name in room_names {
-        match content_filter.validate_room_name(name) {
+        match RoomId::new(name) {
             Ok(sanitized) => println!("✅ Salon '{}' → '{}'", name, sanitized),
             Err(e) => println!("❌ Nom de salon invalide '{}' : {}", name, e),
         }
@@ -0,0 +1,57 @@
+//! Chargement des réactions d'une page d'historique : par message ou en lot
+//!
+//! Compare `get_message_reactions` appelé pour chacun des 50 derniers messages
+//! à un unique `get_reactions_for_messages` sur la même page.
+//!
+//! ```bash
+//! DATABASE_URL=postgres://... cargo run --release --example reaction_fetch_bench
+//! ```
+
+use std::time::{Duration, Instant};
+use sqlx::{PgPool, Row};
+use chat_server::message_store::MessageStore;
+
+/// Taille d'une page d'historique
+const PAGE_SIZE: i64 = 50;
+/// Nombre de répétitions par stratégie
+const ITERATIONS: u32 = 20;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let database_url = std::env::var("DATABASE_URL")?;
+    let db = PgPool::connect(&database_url).await?;
+    let store = MessageStore::new(db.clone());
+
+    let message_ids: Vec<i64> = sqlx::query("SELECT id FROM messages WHERE status != 'deleted' ORDER BY id DESC LIMIT $1")
+        .bind(PAGE_SIZE)
+        .fetch_all(&db)
+        .await?
+        .into_iter()
+        .map(|row| row.get("id"))
+        .collect();
+
+    println!("📊 Page de {} messages, {} répétitions", message_ids.len(), ITERATIONS);
+
+    let mut per_message = Duration::ZERO;
+    let mut batched = Duration::ZERO;
+
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        for &message_id in &message_ids {
+            store.get_message_reactions(message_id).await?;
+        }
+        per_message += start.elapsed();
+
+        let start = Instant::now();
+        store.get_reactions_for_messages(&message_ids).await?;
+        batched += start.elapsed();
+    }
+
+    println!("🐢 Par message : {:?} par page ({} requêtes)", per_message / ITERATIONS, message_ids.len());
+    println!("🚀 En lot      : {:?} par page (1 requête)", batched / ITERATIONS);
+    if !batched.is_zero() {
+        println!("📈 Gain        : x{:.1}", per_message.as_secs_f64() / batched.as_secs_f64());
+    }
+
+    Ok(())
+}
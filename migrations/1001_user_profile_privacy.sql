@@ -0,0 +1,9 @@
+-- Migration pour la résolution groupée des profils - Veza Chat Server
+-- Ajoute le paramètre de confidentialité de la présence
+
+BEGIN;
+
+-- Permet à un utilisateur de masquer son statut en ligne aux autres
+ALTER TABLE users ADD COLUMN IF NOT EXISTS show_presence BOOLEAN NOT NULL DEFAULT TRUE;
+
+COMMIT;
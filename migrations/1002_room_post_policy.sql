@@ -0,0 +1,21 @@
+-- Migration pour la politique de publication des salons - Veza Chat Server
+-- Permet les salons d'annonces (publication réservée, réactions ouvertes)
+
+BEGIN;
+
+-- 'everyone' : tout membre publie et réagit
+-- 'moderators_only' : seuls les modérateurs publient et réagissent
+-- 'announcement' : seuls les modérateurs publient, tous les membres réagissent
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS post_policy VARCHAR(32) NOT NULL DEFAULT 'everyone';
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'conversations_post_policy_check'
+    ) THEN
+        ALTER TABLE conversations ADD CONSTRAINT conversations_post_policy_check
+            CHECK (post_policy IN ('everyone', 'moderators_only', 'announcement'));
+    END IF;
+END $$;
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Migration pour la gestion des profils utilisateur - Veza Chat Server
+-- Ajoute le fuseau horaire et la date de dernière modification du profil
+
+BEGIN;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS profile_updated_at TIMESTAMPTZ;
+
+COMMIT;
@@ -0,0 +1,11 @@
+-- Migration pour les fenêtres d'édition/suppression par salon - Veza Chat Server
+-- Les valeurs NULL reprennent la configuration globale du serveur
+
+BEGIN;
+
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS edit_window_seconds INTEGER CHECK (edit_window_seconds >= 0);
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS delete_window_seconds INTEGER CHECK (delete_window_seconds >= 0);
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS edit_lock_after_reactions INTEGER CHECK (edit_lock_after_reactions > 0);
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS edit_lock_after_replies INTEGER CHECK (edit_lock_after_replies > 0);
+
+COMMIT;
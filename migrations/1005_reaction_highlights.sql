@@ -0,0 +1,28 @@
+-- Migration pour la mise en avant par réactions (starboard) - Veza Chat Server
+-- Règles par salon (emoji + seuil) et trace des messages déjà mis en avant
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS room_highlight_rules (
+    id BIGSERIAL PRIMARY KEY,
+    conversation_id BIGINT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    emoji VARCHAR(50) NOT NULL,
+    threshold INTEGER NOT NULL CHECK (threshold > 0),
+    showcase_room_id BIGINT REFERENCES conversations(id) ON DELETE SET NULL,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT room_highlight_rules_unique_emoji UNIQUE (conversation_id, emoji)
+);
+
+-- Un message n'est mis en avant qu'une seule fois
+CREATE TABLE IF NOT EXISTS message_highlights (
+    message_id BIGINT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    rule_id BIGINT REFERENCES room_highlight_rules(id) ON DELETE SET NULL,
+    showcase_message_id BIGINT REFERENCES messages(id) ON DELETE SET NULL,
+    highlighted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_room_highlight_rules_conversation ON room_highlight_rules(conversation_id);
+
+COMMIT;
@@ -0,0 +1,18 @@
+-- Migration pour les mentions de masse - Veza Chat Server
+-- Conserve le type de mention (user, everyone, here, role) pour le rendu client
+
+BEGIN;
+
+ALTER TABLE message_mentions ADD COLUMN IF NOT EXISTS mention_kind VARCHAR(16) NOT NULL DEFAULT 'user';
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'message_mentions_kind_check'
+    ) THEN
+        ALTER TABLE message_mentions ADD CONSTRAINT message_mentions_kind_check
+            CHECK (mention_kind IN ('user', 'everyone', 'here', 'role'));
+    END IF;
+END $$;
+
+COMMIT;
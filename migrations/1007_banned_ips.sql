@@ -0,0 +1,16 @@
+-- Migration pour la persistance des IP bannies - Veza Chat Server
+-- Liste noire partagée entre instances, avec raison, auteur et expiration
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS banned_ips (
+    ip_address VARCHAR(45) PRIMARY KEY,
+    reason TEXT NOT NULL DEFAULT '',
+    banned_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_banned_ips_expires_at ON banned_ips(expires_at) WHERE expires_at IS NOT NULL;
+
+COMMIT;
@@ -0,0 +1,12 @@
+-- Migration pour les messages longs - Veza Chat Server
+-- La ligne messages conserve un aperçu, le corps complet est stocké à part
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS message_bodies (
+    message_id BIGINT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMIT;
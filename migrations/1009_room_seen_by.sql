@@ -0,0 +1,12 @@
+-- Migration pour la liste « vu par » des salons - Veza Chat Server
+-- Visibilité par salon (compteur seul par défaut) et index des marqueurs de lecture
+
+BEGIN;
+
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS seen_by_mode VARCHAR(20) NOT NULL DEFAULT 'count_only'
+    CHECK (seen_by_mode IN ('count_only', 'members'));
+
+CREATE INDEX IF NOT EXISTS idx_conversation_members_last_read
+    ON conversation_members(conversation_id, last_read_message_id);
+
+COMMIT;
@@ -0,0 +1,10 @@
+-- Migration pour les messages à visibilité restreinte - Veza Chat Server
+-- NULL = visible par tous les membres ; sinon liste des destinataires (hors auteur)
+
+BEGIN;
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS visible_to BIGINT[];
+
+CREATE INDEX IF NOT EXISTS idx_messages_visible_to ON messages USING GIN (visible_to) WHERE visible_to IS NOT NULL;
+
+COMMIT;
@@ -0,0 +1,12 @@
+-- Migration pour la déduplication des renvois - Veza Chat Server
+-- Nonce client associé à chaque envoi (NULL si le client n'en fournit pas)
+
+BEGIN;
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS client_nonce VARCHAR(64);
+
+CREATE INDEX IF NOT EXISTS idx_messages_client_nonce
+    ON messages(author_id, conversation_id, client_nonce)
+    WHERE client_nonce IS NOT NULL;
+
+COMMIT;
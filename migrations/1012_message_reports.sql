@@ -0,0 +1,22 @@
+-- Migration pour le signalement des messages - Veza Chat Server
+-- Un signalement par utilisateur et par message ; le message est marqué
+-- (file de modération) au-delà d'un nombre configurable de signaleurs
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS message_reports (
+    id BIGSERIAL PRIMARY KEY,
+    message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    reporter_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(message_id, reporter_id)
+);
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS is_flagged BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS flagged_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_messages_flagged ON messages(flagged_at) WHERE is_flagged;
+
+COMMIT;
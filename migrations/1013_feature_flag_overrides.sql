@@ -0,0 +1,14 @@
+-- Migration pour les drapeaux de fonctionnalités - Veza Chat Server
+-- Surcharges posées à chaud par les administrateurs ; les valeurs de base
+-- restent celles de la section [features] de la configuration
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS feature_flag_overrides (
+    name VARCHAR(50) PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMIT;
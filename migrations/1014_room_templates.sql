@@ -0,0 +1,18 @@
+-- Migration pour les modèles de réponse des salons - Veza Chat Server
+-- Réponses préenregistrées (nommées, avec variables {{nom}}) gérées par les
+-- modérateurs du salon, utilisables par les membres si autorisé
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS room_templates (
+    id BIGSERIAL PRIMARY KEY,
+    conversation_id BIGINT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    content TEXT NOT NULL,
+    members_can_use BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(conversation_id, name)
+);
+
+COMMIT;
@@ -0,0 +1,13 @@
+-- Migration pour l'ordre d'affichage des messages épinglés - Veza Chat Server
+-- Position choisie par les modérateurs ; les épingles sans position suivent,
+-- de la plus récente à la plus ancienne
+
+BEGIN;
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS pin_order INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_messages_pin_order
+    ON messages(conversation_id, pin_order) WHERE is_pinned;
+
+COMMIT;
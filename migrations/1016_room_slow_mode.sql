@@ -0,0 +1,11 @@
+-- Migration pour le mode lent des salons - Veza Chat Server
+-- Choix des modérateurs : NULL = automatique (selon le débit du salon),
+-- 0 = désactivé, > 0 = délai imposé en secondes entre deux messages d'un membre
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS slow_mode_seconds INTEGER
+        CHECK (slow_mode_seconds IS NULL OR slow_mode_seconds BETWEEN 0 AND 3600);
+
+COMMIT;
@@ -0,0 +1,11 @@
+-- Migration pour les mentions de masse sans matérialisation - Veza Chat Server
+-- @everyone et @here deviennent des indicateurs sur le message au lieu d'une
+-- ligne message_mentions par membre
+
+BEGIN;
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS mentions_everyone BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS mentions_here BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMIT;
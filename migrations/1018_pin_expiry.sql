@@ -0,0 +1,13 @@
+-- Migration pour l'expiration des épingles - Veza Chat Server
+-- Une épingle peut porter une date de fin ; la tâche de balayage la retire
+-- automatiquement une fois cette date passée
+
+BEGIN;
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS pinned_until TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_messages_pinned_until
+    ON messages(pinned_until) WHERE is_pinned AND pinned_until IS NOT NULL;
+
+COMMIT;
@@ -0,0 +1,11 @@
+-- Migration pour la confidentialité des messages directs - Veza Chat Server
+-- Chaque utilisateur choisit qui peut lui écrire : tout le monde, les membres
+-- d'un salon commun, ou personne
+
+BEGIN;
+
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS dm_privacy VARCHAR(20) NOT NULL DEFAULT 'everyone'
+        CHECK (dm_privacy IN ('everyone', 'shared_rooms', 'nobody'));
+
+COMMIT;
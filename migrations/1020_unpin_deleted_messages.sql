@@ -0,0 +1,11 @@
+-- Migration pour les épingles des messages supprimés - Veza Chat Server
+-- Un message supprimé n'est plus épinglé ; les suppressions antérieures
+-- laissaient l'épingle en place
+
+BEGIN;
+
+UPDATE messages
+SET is_pinned = FALSE, pin_order = NULL, pinned_until = NULL
+WHERE status = 'deleted' AND is_pinned = TRUE;
+
+COMMIT;
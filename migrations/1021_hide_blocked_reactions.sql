@@ -0,0 +1,10 @@
+-- Migration pour le masquage des réactions au blocage - Veza Chat Server
+-- Celui qui bloque peut masquer les réactions de l'autre participant sur ses
+-- propres messages ; les réactions sont conservées
+
+BEGIN;
+
+ALTER TABLE dm_conversations
+    ADD COLUMN IF NOT EXISTS hide_reactions BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMIT;
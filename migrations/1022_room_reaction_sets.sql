@@ -0,0 +1,9 @@
+-- Migration pour les palettes de réactions des salons - Veza Chat Server
+-- NULL : toute réaction valide est acceptée (comportement historique)
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS allowed_reactions TEXT[];
+
+COMMIT;
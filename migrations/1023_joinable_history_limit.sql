@@ -0,0 +1,10 @@
+-- Migration pour la profondeur d'historique des nouveaux membres - Veza Chat Server
+-- NULL : historique complet (comportement historique)
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS joinable_history_limit INTEGER
+        CHECK (joinable_history_limit BETWEEN 0 AND 1000);
+
+COMMIT;
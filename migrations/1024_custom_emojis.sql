@@ -0,0 +1,21 @@
+-- Migration pour les émojis personnalisés - Veza Chat Server
+-- Portée serveur (room_id NULL) ou salon ; un nom court est unique par portée
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS custom_emojis (
+    id BIGSERIAL PRIMARY KEY,
+    shortcode VARCHAR(18) NOT NULL CHECK (shortcode ~ '^[a-z0-9_-]{2,18}$'),
+    room_id BIGINT REFERENCES conversations(id) ON DELETE CASCADE,
+    object_key TEXT NOT NULL,
+    image_url TEXT NOT NULL,
+    content_type VARCHAR(32) NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_custom_emojis_scope_shortcode
+    ON custom_emojis (COALESCE(room_id, 0), shortcode);
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Migration pour les citations exemptées du filtre dans les signalements - Veza Chat Server
+-- Règles déclenchées par la citation du message signalé, relevées pour les modérateurs
+
+BEGIN;
+
+ALTER TABLE message_reports
+    ADD COLUMN IF NOT EXISTS exempted_rules TEXT[] NOT NULL DEFAULT '{}';
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Migration pour les comptes de bot - Veza Chat Server
+-- Les bots ont leur propre quota quotidien de messages
+
+BEGIN;
+
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS is_bot BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMIT;
@@ -0,0 +1,16 @@
+-- Migration pour les messages retenus par le filtre - Veza Chat Server
+-- Salons en mode « flag » : les messages signalés attendent l'examen d'un modérateur
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS filter_mode TEXT NOT NULL DEFAULT 'off'
+        CHECK (filter_mode IN ('off', 'flag'));
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS is_held BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS held_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_held ON messages(conversation_id) WHERE is_held;
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Migration pour la politique des liens par salon - Veza Chat Server
+-- Surcharges des listes de domaines et des actions (NULL = politique du serveur)
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS link_policy JSONB;
+
+COMMIT;
@@ -0,0 +1,9 @@
+-- Migration pour les langues de filtrage par salon - Veza Chat Server
+-- Langues dont les listes de mots interdits s'ajoutent à celles du serveur
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS languages TEXT[] NOT NULL DEFAULT '{}';
+
+COMMIT;
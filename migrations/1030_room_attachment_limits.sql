@@ -0,0 +1,10 @@
+-- Migration pour les limites de pièces jointes par salon - Veza Chat Server
+-- Nombre et taille cumulée par message (NULL = limites de la configuration)
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS max_attachments INTEGER CHECK (max_attachments >= 0),
+    ADD COLUMN IF NOT EXISTS max_attachments_size BIGINT CHECK (max_attachments_size > 0);
+
+COMMIT;
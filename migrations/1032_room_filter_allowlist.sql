@@ -0,0 +1,9 @@
+-- Migration pour les termes autorisés par salon - Veza Chat Server
+-- Termes légitimes (noms d'équipe, jargon) exemptés des mots interdits du filtre
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS filter_allowlist TEXT[] NOT NULL DEFAULT '{}';
+
+COMMIT;
@@ -0,0 +1,18 @@
+-- Migration pour le chiffrement au repos des salons sensibles - Veza Chat Server
+-- Contenu scellé par une clé de données propre au message, elle-même
+-- enveloppée par la clé maîtresse `encryption_key_id` du serveur
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS encrypt_at_rest BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS encryption_key_id TEXT,
+    ADD COLUMN IF NOT EXISTS wrapped_key BYTEA;
+
+-- Messages à réenvelopper après une rotation de clé
+CREATE INDEX IF NOT EXISTS idx_messages_encryption_key
+    ON messages(encryption_key_id) WHERE encryption_key_id IS NOT NULL;
+
+COMMIT;
@@ -0,0 +1,21 @@
+-- Migration pour le quota de stockage des pièces jointes - Veza Chat Server
+-- Occupation par utilisateur, tenue à jour au téléversement et à la suppression
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS user_storage_usage (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    used_bytes BIGINT NOT NULL DEFAULT 0 CHECK (used_bytes >= 0),
+    file_count INTEGER NOT NULL DEFAULT 0 CHECK (file_count >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Occupation initiale à partir des fichiers existants
+INSERT INTO user_storage_usage (user_id, used_bytes, file_count)
+SELECT uploaded_by, SUM(file_size), COUNT(*)
+FROM files
+WHERE uploaded_by IS NOT NULL
+GROUP BY uploaded_by
+ON CONFLICT (user_id) DO NOTHING;
+
+COMMIT;
@@ -0,0 +1,26 @@
+-- Migration pour les messages directs chiffrés de bout en bout - Veza Chat Server
+-- Marqueur des messages opaques et clés publiques pour l'échange de clés
+
+BEGIN;
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS is_e2ee BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS e2ee_key_bundles (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    identity_key TEXT NOT NULL,
+    signed_prekey_id BIGINT NOT NULL,
+    signed_prekey TEXT NOT NULL,
+    signed_prekey_signature TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Clés à usage unique, supprimées lorsqu'elles sont remises à un correspondant
+CREATE TABLE IF NOT EXISTS e2ee_one_time_prekeys (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key_id BIGINT NOT NULL,
+    public_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, key_id)
+);
+
+COMMIT;
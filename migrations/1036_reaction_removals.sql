@@ -0,0 +1,21 @@
+-- Migration pour les changements de réactions - Veza Chat Server
+-- Trace des retraits, pour que les clients appliquent des deltas
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS message_reaction_removals (
+    id BIGSERIAL PRIMARY KEY,
+    message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    emoji VARCHAR(100) NOT NULL,
+    removed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_reaction_removals_message_time
+    ON message_reaction_removals (message_id, removed_at);
+
+-- Les ajouts sont lus par date sur les mêmes messages
+CREATE INDEX IF NOT EXISTS idx_message_reactions_message_time
+    ON message_reactions (message_id, created_at);
+
+COMMIT;
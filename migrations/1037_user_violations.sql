@@ -0,0 +1,16 @@
+-- Migration pour l'escalade des violations du filtre - Veza Chat Server
+-- Compteur par utilisateur et échéances des sanctions automatiques
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS user_violations (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    count INTEGER NOT NULL DEFAULT 0 CHECK (count >= 0),
+    last_violation_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_action VARCHAR(32),
+    muted_until TIMESTAMPTZ,
+    review_until TIMESTAMPTZ,
+    banned_until TIMESTAMPTZ
+);
+
+COMMIT;
@@ -0,0 +1,11 @@
+-- Migration pour la pré-modération des salons - Veza Chat Server
+-- Mode `approve` : messages des membres retenus jusqu'à approbation
+
+BEGIN;
+
+ALTER TABLE conversations DROP CONSTRAINT IF EXISTS conversations_filter_mode_check;
+ALTER TABLE conversations
+    ADD CONSTRAINT conversations_filter_mode_check
+        CHECK (filter_mode IN ('off', 'flag', 'approve'));
+
+COMMIT;
@@ -0,0 +1,13 @@
+-- Migration pour la liste des salons rejoints - Veza Chat Server
+-- Épinglage d'un salon dans la liste de l'utilisateur ; état muet garanti
+
+BEGIN;
+
+ALTER TABLE conversation_members
+    ADD COLUMN IF NOT EXISTS is_pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS is_muted BOOLEAN DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_conversation_members_active_user
+    ON conversation_members(user_id, conversation_id) WHERE left_at IS NULL;
+
+COMMIT;
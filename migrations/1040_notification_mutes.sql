@@ -0,0 +1,16 @@
+-- Migration pour la sourdine des salons et DM - Veza Chat Server
+-- Sourdine côté utilisateur : supprime les notifications, pas les messages en direct
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS dm_mutes (
+    conversation_id BIGINT NOT NULL REFERENCES dm_conversations(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    muted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (conversation_id, user_id)
+);
+
+COMMENT ON COLUMN conversation_members.is_muted IS
+    'Sourdine choisie par le membre (notifications). La sourdine de modération est user_violations.muted_until';
+
+COMMIT;
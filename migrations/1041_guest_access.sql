@@ -0,0 +1,23 @@
+-- Migration pour les connexions invitées - Veza Chat Server
+-- Accès des invités par salon public ; messages d'invités conservés sur option
+
+BEGIN;
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS guest_access VARCHAR(20) NOT NULL DEFAULT 'closed'
+        CHECK (guest_access IN ('closed', 'read_only', 'limited'));
+
+-- Un invité n'a pas de compte : pas de clé étrangère vers users
+CREATE TABLE IF NOT EXISTS guest_messages (
+    id BIGSERIAL PRIMARY KEY,
+    conversation_id BIGINT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    guest_id INTEGER NOT NULL CHECK (guest_id < 0),
+    guest_name VARCHAR(50) NOT NULL,
+    content TEXT NOT NULL CHECK (LENGTH(content) <= 4000),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_guest_messages_conversation
+    ON guest_messages(conversation_id, created_at);
+
+COMMIT;
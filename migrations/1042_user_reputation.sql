@@ -0,0 +1,32 @@
+-- Migration pour la réputation des comptes - Veza Chat Server
+-- Compteurs mis à jour à chaque message et sanction ; score recalculé par le
+-- serveur selon la formule configurée (`[reputation]`)
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS user_reputation (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    message_count BIGINT NOT NULL DEFAULT 0,
+    warnings INTEGER NOT NULL DEFAULT 0,
+    mutes INTEGER NOT NULL DEFAULT 0,
+    bans INTEGER NOT NULL DEFAULT 0,
+    -- Ajustement manuel des administrateurs (audité)
+    adjustment INTEGER NOT NULL DEFAULT 0,
+    -- Dernier score calculé, NULL tant qu'il ne l'a pas été
+    score INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_reputation_score ON user_reputation(score);
+
+-- Reprise de l'historique : messages publiés et sanctions déjà prononcées
+INSERT INTO user_reputation (user_id, message_count, warnings, mutes, bans)
+SELECT u.id,
+       (SELECT COUNT(*) FROM messages m WHERE m.author_id = u.id AND m.status != 'deleted'),
+       (SELECT COUNT(*) FROM sanctions s WHERE s.user_id = u.id AND s.sanction_type = '"Warning"'),
+       (SELECT COUNT(*) FROM sanctions s WHERE s.user_id = u.id AND s.sanction_type = '"Mute"'),
+       (SELECT COUNT(*) FROM sanctions s WHERE s.user_id = u.id AND s.sanction_type IN ('"TempBan"', '"PermaBan"'))
+FROM users u
+ON CONFLICT (user_id) DO NOTHING;
+
+COMMIT;
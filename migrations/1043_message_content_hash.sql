@@ -0,0 +1,9 @@
+-- Migration pour la déduplication des renvois dans les salons chiffrés - Veza Chat Server
+-- Empreinte SHA-256 du contenu en clair, renseignée avec le nonce client : un
+-- salon chiffré au repos stocke un contenu scellé différent à chaque envoi
+
+BEGIN;
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS content_hash BYTEA;
+
+COMMIT;
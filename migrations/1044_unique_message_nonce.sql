@@ -0,0 +1,24 @@
+-- Migration pour l'unicité des nonces clients - Veza Chat Server
+-- Un nonce identifie un seul envoi par auteur et conversation : deux renvois
+-- simultanés ne peuvent plus créer deux messages
+
+BEGIN;
+
+-- Doublons antérieurs : seul le premier message garde son nonce
+UPDATE messages m SET client_nonce = NULL
+WHERE m.client_nonce IS NOT NULL
+  AND EXISTS (
+      SELECT 1 FROM messages earlier
+      WHERE earlier.author_id = m.author_id
+        AND earlier.conversation_id = m.conversation_id
+        AND earlier.client_nonce = m.client_nonce
+        AND earlier.id < m.id
+  );
+
+DROP INDEX IF EXISTS idx_messages_client_nonce;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_client_nonce
+    ON messages(author_id, conversation_id, client_nonce)
+    WHERE client_nonce IS NOT NULL;
+
+COMMIT;
@@ -1,9 +1,23 @@
 //file: backend/modules/chat_server/src/auth.rs
 
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm, TokenData};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation, Algorithm, TokenData};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use crate::error::{ChatError, Result};
 use crate::config::ServerConfig;
+use crate::validation::{check_reserved_username, normalize_username};
+
+/// Rôle des connexions invitées (`Role::Guest`)
+pub const GUEST_ROLE: &str = "guest";
+
+/// Identifiant invité : négatif (jamais celui d'un compte) et tiré au hasard
+///
+/// Un compteur du processus repartirait de -1 au redémarrage ou sur une autre
+/// instance, alors que les jetons déjà émis restent valides `guests.session_ttl`.
+fn random_guest_id() -> i32 {
+    let bits = (Uuid::new_v4().as_u128() % i32::MAX as u128) as i32;
+    -1 - bits
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
@@ -14,6 +28,52 @@ pub struct Claims {
     pub iat: usize,
 }
 
+impl Claims {
+    /// Connexion invitée, sans compte
+    pub fn is_guest(&self) -> bool {
+        self.role == GUEST_ROLE
+    }
+}
+
+/// Claims d'une connexion invitée (`[guests]`)
+///
+/// L'identifiant est éphémère, négatif et aléatoire ; le nom est préfixé par `guest-`
+/// (nom choisi, ou numéro de l'invité).
+pub fn issue_guest_claims(config: &ServerConfig, display_name: Option<&str>) -> Result<Claims> {
+    if !config.guests.enabled {
+        return Err(ChatError::unauthorized("guest_access_disabled"));
+    }
+
+    let user_id = random_guest_id();
+    let suffix = match display_name {
+        Some(name) => {
+            let name = normalize_username(name)?;
+            check_reserved_username(&name, &config.security.reserved_usernames)?;
+            name
+        }
+        None => user_id.unsigned_abs().to_string(),
+    };
+    let username = normalize_username(&format!("guest-{}", suffix))?;
+
+    let now = chrono::Utc::now().timestamp() as usize;
+    tracing::info!(user_id = %user_id, username = %username, "👤 Session invitée créée");
+    Ok(Claims {
+        user_id,
+        username,
+        role: GUEST_ROLE.to_string(),
+        exp: now + config.guests.session_ttl.as_secs() as usize,
+        iat: now,
+    })
+}
+
+/// Comme `issue_guest_claims`, avec le jeton signé à présenter à la connexion
+pub fn issue_guest_token(config: &ServerConfig, display_name: Option<&str>) -> Result<(Claims, String)> {
+    let claims = issue_guest_claims(config, display_name)?;
+    let token = encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(config.security.jwt_secret.as_bytes()))
+        .map_err(|e| ChatError::configuration_error(&format!("Signature du jeton invité impossible: {}", e)))?;
+    Ok((claims, token))
+}
+
 pub fn validate_token(token: &str, config: &ServerConfig) -> Result<TokenData<Claims>> {
     tracing::debug!(token_length = %token.len(), token_preview = %&token[..std::cmp::min(20, token.len())], "🔧 Début validation token");
     
@@ -41,6 +101,12 @@ pub fn validate_token(token: &str, config: &ServerConfig) -> Result<TokenData<Cl
                 return Err(ChatError::unauthorized("token expired"));
             }
 
+            // Jeton invité émis avant la désactivation des invités
+            if token_data.claims.is_guest() && (!config.guests.enabled || token_data.claims.user_id >= 0) {
+                tracing::warn!(user_id = %token_data.claims.user_id, "🔐 Jeton invité refusé");
+                return Err(ChatError::unauthorized("guest_access_disabled"));
+            }
+
             tracing::debug!(
                 user_id = %token_data.claims.user_id, 
                 username = %token_data.claims.username,
@@ -57,3 +123,36 @@ pub fn validate_token(token: &str, config: &ServerConfig) -> Result<TokenData<Cl
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_claims_require_config_and_use_synthetic_ids() {
+        let mut config = ServerConfig::default();
+        assert!(issue_guest_claims(&config, None).is_err());
+
+        config.guests.enabled = true;
+        let first = issue_guest_claims(&config, None).unwrap();
+        let second = issue_guest_claims(&config, Some("Visiteur")).unwrap();
+        assert!(first.is_guest());
+        assert!(first.user_id < 0 && second.user_id < 0);
+        assert_ne!(first.user_id, second.user_id);
+        assert!(first.username.starts_with("guest-"));
+        assert_eq!(second.username, "guest-Visiteur");
+        assert_eq!(first.exp - first.iat, 3600);
+    }
+
+    #[test]
+    fn test_guest_token_round_trip_until_guests_disabled() {
+        let mut config = ServerConfig::default();
+        config.security.jwt_secret = "x".repeat(32);
+        config.guests.enabled = true;
+        let (claims, token) = issue_guest_token(&config, None).unwrap();
+        assert_eq!(validate_token(&token, &config).unwrap().claims.user_id, claims.user_id);
+
+        config.guests.enabled = false;
+        assert!(validate_token(&token, &config).is_err());
+    }
+}
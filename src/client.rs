@@ -1,8 +1,133 @@
 //file: backend/modules/chat_server/src/client.rs
 
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio_tungstenite::tungstenite::Message;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
+use crate::auth::Claims;
+use crate::close_codes::CloseReason;
+use crate::message_schema::{negotiated_version, VersionedFrame, CURRENT_SCHEMA_VERSION};
+use crate::security::ConnectionMetadata;
+
+/// Capacité par défaut de la file ordinaire d'un client
+pub const DEFAULT_NORMAL_LANE_CAPACITY: usize = 256;
+
+/// Types de trames jamais retardées derrière les messages ordinaires :
+/// erreurs, accusés de réception, modération et avis de déconnexion
+const CRITICAL_FRAME_TYPES: &[&str] = &[
+    "error", "message_sent", "dm_message_sent", "join_ack", "batch_result", "pong_diag",
+    "message_held", "message_flagged", "message_approved", "message_rejected", "held_message_reviewed",
+    "role_notice", "moderation_notice", "message_quota", "session_expiring", "disconnect",
+];
+
+/// File d'envoi d'une trame vers le client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// Livrée avant toute trame ordinaire, jamais abandonnée
+    Critical,
+    /// Première abandonnée quand le client ne suit pas
+    Normal,
+}
+
+#[derive(Deserialize)]
+struct FrameType<'a> {
+    #[serde(rename = "type", borrow)]
+    kind: Option<&'a str>,
+}
+
+impl Lane {
+    /// File d'une trame texte d'après son `type` (ordinaire si illisible)
+    pub fn of_text(text: &str) -> Self {
+        match serde_json::from_str::<FrameType>(text) {
+            Ok(FrameType { kind: Some(kind) }) if CRITICAL_FRAME_TYPES.contains(&kind) => Lane::Critical,
+            _ => Lane::Normal,
+        }
+    }
+}
+
+/// Issue de l'envoi d'une trame vers le client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// Trame placée dans sa file
+    Sent,
+    /// Trame ordinaire abandonnée, file pleine : la connexion reste ouverte
+    Dropped,
+    /// Connexion fermée
+    Closed,
+}
+
+/// Accusé de réception des envois de messages
+///
+/// `Confirm` fait attendre au client la persistance (un aller-retour base de
+/// plus avant de considérer le message envoyé) ; `FireAndForget` épargne cette
+/// attente et une trame par envoi, le message n'apparaissant qu'à sa diffusion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AckMode {
+    /// Accusé après persistance, avec l'identifiant et l'horodatage stockés
+    #[default]
+    Confirm,
+    /// Aucun accusé en cas de succès ; les erreurs restent signalées
+    FireAndForget,
+}
+
+impl AckMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "confirm" => Some(AckMode::Confirm),
+            "fire_and_forget" => Some(AckMode::FireAndForget),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AckMode::Confirm => "confirm",
+            AckMode::FireAndForget => "fire_and_forget",
+        }
+    }
+
+    /// Mode d'un envoi : le drapeau `ack` de la commande l'emporte sur le défaut de la connexion
+    pub fn for_command(self, ack: Option<bool>) -> Self {
+        match ack {
+            Some(true) => AckMode::Confirm,
+            Some(false) => AckMode::FireAndForget,
+            None => self,
+        }
+    }
+
+    /// Réponse à un envoi réussi : l'accusé en mode `Confirm`, rien sinon
+    pub fn ack(self, frame: serde_json::Value) -> Option<String> {
+        match self {
+            AckMode::Confirm => Some(frame.to_string()),
+            AckMode::FireAndForget => None,
+        }
+    }
+}
+
+/// Côté écriture des files d'un client : à vider par la tâche qui écrit sur la socket
+#[derive(Debug)]
+pub struct OutboundLanes {
+    critical: UnboundedReceiver<Message>,
+    normal: mpsc::Receiver<Message>,
+}
+
+impl OutboundLanes {
+    /// Prochaine trame à écrire, la file prioritaire d'abord ; `None` une fois les deux fermées
+    pub async fn recv(&mut self) -> Option<Message> {
+        tokio::select! {
+            biased;
+            Some(message) = self.critical.recv() => Some(message),
+            Some(message) = self.normal.recv() => Some(message),
+            else => None,
+        }
+    }
+
+    /// Comme `recv`, sans attendre
+    pub fn try_recv(&mut self) -> Option<Message> {
+        self.critical.try_recv().ok().or_else(|| self.normal.try_recv().ok())
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Client {
@@ -10,7 +135,23 @@ pub struct Client {
     pub username: String,
     pub sender: UnboundedSender<Message>,
     pub last_heartbeat: std::sync::Arc<std::sync::RwLock<Instant>>,
+    /// Dernière trame reçue du client (partagé entre les clones)
+    pub last_activity: std::sync::Arc<std::sync::RwLock<Instant>>,
     pub connected_at: Instant,
+    /// User-Agent et version du client capturés au handshake
+    pub metadata: ConnectionMetadata,
+    /// Expiration du jeton d'authentification (timestamp Unix, claim `exp`)
+    pub token_expires_at: Option<i64>,
+    /// Version du schéma des messages négociée (partagée entre les clones)
+    pub schema_version: std::sync::Arc<AtomicU16>,
+    /// Envois sans accusé par défaut (partagé entre les clones)
+    fire_and_forget: std::sync::Arc<AtomicBool>,
+    /// File bornée des trames ordinaires ; sans elle, tout passe par `sender`
+    normal_lane: Option<mpsc::Sender<Message>>,
+    /// Trames ordinaires abandonnées, file pleine (partagé entre les clones)
+    dropped_frames: std::sync::Arc<AtomicU64>,
+    /// Connexion invitée (rôle `guest` du jeton présenté)
+    guest: bool,
 }
 
 impl Client {
@@ -20,26 +161,141 @@ impl Client {
             username,
             sender,
             last_heartbeat: std::sync::Arc::new(std::sync::RwLock::new(Instant::now())),
+            last_activity: std::sync::Arc::new(std::sync::RwLock::new(Instant::now())),
             connected_at: Instant::now(),
+            metadata: ConnectionMetadata::default(),
+            token_expires_at: None,
+            schema_version: std::sync::Arc::new(AtomicU16::new(CURRENT_SCHEMA_VERSION)),
+            fire_and_forget: std::sync::Arc::new(AtomicBool::new(false)),
+            normal_lane: None,
+            dropped_frames: std::sync::Arc::new(AtomicU64::new(0)),
+            guest: false,
         }
     }
 
+    /// Client d'une connexion authentifiée : identité, rôle invité et expiration du jeton
+    pub fn from_claims(claims: &Claims, sender: UnboundedSender<Message>) -> Self {
+        let mut client = Self::new(claims.user_id, claims.username.clone(), sender)
+            .with_token_expiry(claims.exp as i64);
+        client.guest = claims.is_guest();
+        client
+    }
+
+    /// Connexion invitée : lecture seule et limites `[guests]` selon le salon
+    pub fn is_guest(&self) -> bool {
+        self.guest
+    }
+
+    /// Client à deux files : prioritaire (`sender`, non bornée) et ordinaire
+    /// (bornée à `normal_capacity`)
+    ///
+    /// Les erreurs, accusés, trames de modération, pings et fermetures passent
+    /// devant les messages ordinaires. Quand le client ne suit pas, seules les
+    /// trames ordinaires sont abandonnées.
+    pub fn with_priority_lanes(user_id: i32, username: String, normal_capacity: usize) -> (Self, OutboundLanes) {
+        let (critical_sender, critical) = mpsc::unbounded_channel();
+        let (normal_sender, normal) = mpsc::channel(normal_capacity.max(1));
+        let mut client = Self::new(user_id, username, critical_sender);
+        client.normal_lane = Some(normal_sender);
+        (client, OutboundLanes { critical, normal })
+    }
+
+    /// Trames ordinaires abandonnées faute de place dans la file
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Attache les métadonnées de connexion capturées au handshake
+    pub fn with_metadata(mut self, metadata: ConnectionMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Retient l'expiration du jeton présenté à la connexion
+    pub fn with_token_expiry(mut self, exp: i64) -> Self {
+        self.token_expires_at = Some(exp);
+        self
+    }
+
+    /// Version du schéma des messages annoncée à la connexion
+    pub fn with_schema_version(self, version: u16) -> Self {
+        self.set_schema_version(version);
+        self
+    }
+
+    pub fn schema_version(&self) -> u16 {
+        self.schema_version.load(Ordering::Relaxed)
+    }
+
+    /// Retient la version demandée, ramenée aux versions servies ; retourne la version retenue
+    pub fn set_schema_version(&self, requested: u16) -> u16 {
+        let version = negotiated_version(requested);
+        self.schema_version.store(version, Ordering::Relaxed);
+        version
+    }
+
+    /// Accusé des envois par défaut sur cette connexion
+    pub fn ack_mode(&self) -> AckMode {
+        if self.fire_and_forget.load(Ordering::Relaxed) { AckMode::FireAndForget } else { AckMode::Confirm }
+    }
+
+    pub fn set_ack_mode(&self, mode: AckMode) {
+        self.fire_and_forget.store(mode == AckMode::FireAndForget, Ordering::Relaxed);
+    }
+
+    /// Le jeton de la connexion a-t-il expiré à `now` (timestamp Unix) ?
+    pub fn is_token_expired(&self, now: i64) -> bool {
+        self.token_expires_at.is_some_and(|exp| exp <= now)
+    }
+
+    /// Ferme la connexion avec un code applicatif (voir `close_codes`)
+    pub fn close(&self, reason: CloseReason, message: Option<&str>) -> bool {
+        tracing::info!(user_id = %self.user_id, username = %self.username, reason = %reason, "🔌 Fermeture de la connexion par le serveur");
+        
+        self.sender.send(Message::Close(Some(reason.close_frame(message)))).is_ok()
+    }
+
     /// Envoie un message texte au client
+    ///
+    /// Avec des files prioritaires, une trame ordinaire est abandonnée (retour
+    /// `false`) si sa file est pleine.
     pub fn send_text(&self, text: &str) -> bool {
+        self.deliver(text) == SendOutcome::Sent
+    }
+
+    /// Envoie un message texte en distinguant trame abandonnée et connexion fermée
+    pub fn deliver(&self, text: &str) -> SendOutcome {
         tracing::debug!(user_id = %self.user_id, username = %self.username, text_length = %text.len(), "🔧 Tentative d'envoi de message texte");
         
+        if let Some(normal_lane) = self.normal_lane.as_ref().filter(|_| Lane::of_text(text) == Lane::Normal) {
+            return match normal_lane.try_send(Message::Text(text.to_string())) {
+                Ok(()) => SendOutcome::Sent,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    let dropped = self.dropped_frames.fetch_add(1, Ordering::Relaxed) + 1;
+                    tracing::warn!(user_id = %self.user_id, username = %self.username, dropped = %dropped, "🐌 Client lent : trame ordinaire abandonnée");
+                    SendOutcome::Dropped
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => SendOutcome::Closed,
+            };
+        }
+        
         match self.sender.send(Message::Text(text.to_string())) {
             Ok(_) => {
                 tracing::debug!(user_id = %self.user_id, username = %self.username, "✅ Message texte envoyé au canal");
-                true
+                SendOutcome::Sent
             }
             Err(e) => {
                 tracing::error!(user_id = %self.user_id, username = %self.username, error = %e, "❌ Erreur envoi message texte au canal");
-                false
+                SendOutcome::Closed
             }
         }
     }
 
+    /// Envoie une trame rendue à la version de schéma du client
+    pub fn send_frame(&self, frame: &VersionedFrame) -> bool {
+        self.send_text(&frame.text_for(self.schema_version()))
+    }
+
     /// Envoie un ping pour vérifier la connexion
     pub fn send_ping(&self) -> bool {
         tracing::debug!(user_id = %self.user_id, username = %self.username, "🏓 Envoi ping");
@@ -72,8 +328,78 @@ impl Client {
         }
     }
 
+    /// Retient la réception d'une trame du client
+    pub fn mark_activity(&self) {
+        if let Ok(mut last_activity) = self.last_activity.write() {
+            *last_activity = Instant::now();
+        }
+    }
+
+    /// Aucune trame reçue depuis au moins `timeout` ?
+    pub fn is_idle(&self, timeout: Duration) -> bool {
+        self.last_activity.read().is_ok_and(|last_activity| last_activity.elapsed() >= timeout)
+    }
+
     /// Retourne la durée de connexion
     pub fn connection_duration(&self) -> Duration {
         self.connected_at.elapsed()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn frame(kind: &str, n: usize) -> String {
+        json!({ "type": kind, "data": { "n": n } }).to_string()
+    }
+
+    #[test]
+    fn test_frame_lanes() {
+        assert_eq!(Lane::of_text(&frame("error", 0)), Lane::Critical);
+        assert_eq!(Lane::of_text(&frame("message_held", 0)), Lane::Critical);
+        assert_eq!(Lane::of_text(&frame("room_message", 0)), Lane::Normal);
+        assert_eq!(Lane::of_text("pas du json"), Lane::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_critical_frame_passes_full_normal_lane() {
+        let (client, mut lanes) = Client::with_priority_lanes(1, "alice".to_string(), 2);
+
+        assert!(client.send_text(&frame("room_message", 1)));
+        assert!(client.send_text(&frame("room_message", 2)));
+        // File ordinaire pleine : la trame ordinaire suivante est abandonnée
+        assert_eq!(client.deliver(&frame("room_message", 3)), SendOutcome::Dropped);
+        assert_eq!(client.dropped_frames(), 1);
+
+        // La trame critique passe malgré tout, et avant le bavardage en attente
+        assert!(client.send_text(&frame("error", 4)));
+        assert!(client.send_ping());
+
+        let first = lanes.recv().await.unwrap();
+        assert_eq!(first, Message::Text(frame("error", 4)));
+        assert_eq!(lanes.recv().await.unwrap(), Message::Ping(vec![]));
+        assert_eq!(lanes.recv().await.unwrap(), Message::Text(frame("room_message", 1)));
+        assert_eq!(lanes.try_recv(), Some(Message::Text(frame("room_message", 2))));
+        assert_eq!(lanes.try_recv(), None);
+    }
+
+    #[test]
+    fn test_ack_follows_command_flag_then_connection_default() {
+        let sent = json!({ "type": "message_sent", "data": { "messageId": 7 } });
+
+        let client = Client::new(1, "alice".to_string(), mpsc::unbounded_channel().0);
+        assert_eq!(client.ack_mode(), AckMode::Confirm);
+        assert_eq!(client.ack_mode().for_command(None).ack(sent.clone()), Some(sent.to_string()));
+        assert_eq!(client.ack_mode().for_command(Some(false)).ack(sent.clone()), None);
+
+        // Défaut partagé par les clones de la connexion
+        client.clone().set_ack_mode(AckMode::FireAndForget);
+        assert_eq!(client.ack_mode().for_command(None).ack(sent.clone()), None);
+        assert_eq!(client.ack_mode().for_command(Some(true)).ack(sent.clone()), Some(sent.to_string()));
+
+        assert_eq!(AckMode::from_name("fire_and_forget"), Some(AckMode::FireAndForget));
+        assert_eq!(AckMode::from_name("sometimes"), None);
+    }
+}
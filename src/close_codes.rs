@@ -0,0 +1,237 @@
+//! Codes de fermeture WebSocket de l'application
+//!
+//! Toute fermeture à l'initiative du serveur porte un code de la plage
+//! 4000–4999 et une raison JSON compacte, pour que le client choisisse entre
+//! reconnexion immédiate, reconnexion différée, nouvelle authentification ou
+//! abandon :
+//!
+//! | Code | Raison              | Conduite du client                       |
+//! |------|---------------------|------------------------------------------|
+//! | 4000 | `server_restart`    | reconnexion différée (backoff)           |
+//! | 4001 | `auth_expired`      | nouveau jeton, puis reconnexion          |
+//! | 4002 | `kicked`            | pas de reconnexion automatique           |
+//! | 4003 | `banned`            | pas de reconnexion (`retryAfter` si temporaire) |
+//! | 4004 | `rate_limited`      | reconnexion après `retryAfter` secondes  |
+//! | 4005 | `session_replaced`  | pas de reconnexion (session plus récente) |
+//! | 4006 | `heartbeat_timeout` | reconnexion immédiate                    |
+//! | 4007 | `idle_timeout`      | reconnexion différée, au retour de l'utilisateur |
+//! | 4008 | `force_disconnect`  | reconnexion différée (décision d'un administrateur) |
+//!
+//! Exemple de raison : `{"reason":"rate_limited","retry":"backoff","retryAfter":5}`.
+
+use std::borrow::Cow;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+/// Taille maximale de la raison d'une trame de fermeture (RFC 6455)
+pub const MAX_CLOSE_REASON_BYTES: usize = 123;
+
+/// Conduite attendue du client après la fermeture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryPolicy {
+    Immediate,
+    Backoff,
+    Reauthenticate,
+    Never,
+}
+
+/// Motif d'une fermeture à l'initiative du serveur
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    ServerRestart,
+    AuthExpired,
+    Kicked,
+    /// `retry_after_secs` : durée restante d'un bannissement temporaire
+    Banned { retry_after_secs: Option<u64> },
+    RateLimited { retry_after_secs: u64 },
+    SessionReplaced,
+    HeartbeatTimeout,
+    /// Aucune trame reçue pendant `server.idle_timeout`
+    IdleTimeout,
+    /// Déconnexion imposée par un administrateur
+    ForceDisconnect,
+}
+
+impl CloseReason {
+    pub fn code(&self) -> u16 {
+        match self {
+            CloseReason::ServerRestart => 4000,
+            CloseReason::AuthExpired => 4001,
+            CloseReason::Kicked => 4002,
+            CloseReason::Banned { .. } => 4003,
+            CloseReason::RateLimited { .. } => 4004,
+            CloseReason::SessionReplaced => 4005,
+            CloseReason::HeartbeatTimeout => 4006,
+            CloseReason::IdleTimeout => 4007,
+            CloseReason::ForceDisconnect => 4008,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CloseReason::ServerRestart => "server_restart",
+            CloseReason::AuthExpired => "auth_expired",
+            CloseReason::Kicked => "kicked",
+            CloseReason::Banned { .. } => "banned",
+            CloseReason::RateLimited { .. } => "rate_limited",
+            CloseReason::SessionReplaced => "session_replaced",
+            CloseReason::HeartbeatTimeout => "heartbeat_timeout",
+            CloseReason::IdleTimeout => "idle_timeout",
+            CloseReason::ForceDisconnect => "force_disconnect",
+        }
+    }
+
+    pub fn retry(&self) -> RetryPolicy {
+        match self {
+            CloseReason::ServerRestart
+            | CloseReason::RateLimited { .. }
+            | CloseReason::IdleTimeout
+            | CloseReason::ForceDisconnect => RetryPolicy::Backoff,
+            CloseReason::AuthExpired => RetryPolicy::Reauthenticate,
+            CloseReason::Kicked | CloseReason::Banned { .. } | CloseReason::SessionReplaced => RetryPolicy::Never,
+            CloseReason::HeartbeatTimeout => RetryPolicy::Immediate,
+        }
+    }
+
+    fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            CloseReason::Banned { retry_after_secs } => *retry_after_secs,
+            CloseReason::RateLimited { retry_after_secs } => Some(*retry_after_secs),
+            _ => None,
+        }
+    }
+
+    /// Motif correspondant à un code reçu ; les données portées par le motif
+    /// (`retryAfter`) ne voyagent pas dans le code et restent à leur valeur par défaut
+    pub fn from_code(code: u16) -> Option<CloseReason> {
+        let reason = match code {
+            4000 => CloseReason::ServerRestart,
+            4001 => CloseReason::AuthExpired,
+            4002 => CloseReason::Kicked,
+            4003 => CloseReason::Banned { retry_after_secs: None },
+            4004 => CloseReason::RateLimited { retry_after_secs: 0 },
+            4005 => CloseReason::SessionReplaced,
+            4006 => CloseReason::HeartbeatTimeout,
+            4007 => CloseReason::IdleTimeout,
+            4008 => CloseReason::ForceDisconnect,
+            _ => return None,
+        };
+        debug_assert_eq!(reason.code(), code);
+        Some(reason)
+    }
+
+    /// Nom du motif correspondant à un code reçu (côté client et tests)
+    pub fn name_of_code(code: u16) -> Option<&'static str> {
+        CloseReason::from_code(code).as_ref().map(CloseReason::name)
+    }
+
+    /// Raison JSON ; `message` (lisible, facultatif) est tronqué pour tenir dans la trame
+    pub fn payload(&self, message: Option<&str>) -> Value {
+        let mut payload = json!({
+            "reason": self.name(),
+            "retry": self.retry()
+        });
+        if let Some(secs) = self.retry_after_secs() {
+            payload["retryAfter"] = json!(secs);
+        }
+
+        if let Some(message) = message.filter(|m| !m.is_empty()) {
+            // Place restante : `,"message":""` et l'échappement éventuel
+            let used = payload.to_string().len() + r#","message":"""#.len();
+            let mut budget = MAX_CLOSE_REASON_BYTES.saturating_sub(used);
+            let mut kept = String::new();
+            for c in message.chars() {
+                let cost = json!(c.to_string()).to_string().len() - 2;
+                if cost > budget {
+                    break;
+                }
+                budget -= cost;
+                kept.push(c);
+            }
+            if !kept.is_empty() {
+                payload["message"] = json!(kept);
+            }
+        }
+
+        payload
+    }
+
+    /// Trame de fermeture envoyée au client
+    pub fn close_frame(&self, message: Option<&str>) -> CloseFrame<'static> {
+        CloseFrame {
+            code: CloseCode::from(self.code()),
+            reason: Cow::Owned(self.payload(message).to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name(), self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_reasons() -> Vec<CloseReason> {
+        vec![
+            CloseReason::ServerRestart,
+            CloseReason::AuthExpired,
+            CloseReason::Kicked,
+            CloseReason::Banned { retry_after_secs: Some(3600) },
+            CloseReason::RateLimited { retry_after_secs: 5 },
+            CloseReason::SessionReplaced,
+            CloseReason::HeartbeatTimeout,
+            CloseReason::IdleTimeout,
+            CloseReason::ForceDisconnect,
+        ]
+    }
+
+    #[test]
+    fn test_codes_are_unique_and_in_application_range() {
+        let reasons = all_reasons();
+        for reason in &reasons {
+            assert!((4000..=4999).contains(&reason.code()), "{}", reason);
+            assert_eq!(CloseReason::name_of_code(reason.code()), Some(reason.name()));
+            assert_eq!(CloseReason::from_code(reason.code()).map(|r| r.code()), Some(reason.code()));
+            assert_eq!(u16::from(reason.close_frame(None).code), reason.code());
+        }
+
+        let mut codes: Vec<u16> = reasons.iter().map(CloseReason::code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), reasons.len());
+        assert_eq!(CloseReason::name_of_code(1000), None);
+        assert_eq!(CloseReason::from_code(4999), None);
+    }
+
+    #[test]
+    fn test_payload_tells_client_what_to_do() {
+        let payload = CloseReason::RateLimited { retry_after_secs: 5 }.payload(None);
+        assert_eq!(payload, json!({"reason": "rate_limited", "retry": "backoff", "retryAfter": 5}));
+
+        assert_eq!(CloseReason::AuthExpired.payload(None)["retry"], "reauthenticate");
+        assert_eq!(CloseReason::HeartbeatTimeout.payload(None)["retry"], "immediate");
+        assert_eq!(CloseReason::IdleTimeout.payload(None)["retry"], "backoff");
+        assert_eq!(CloseReason::Banned { retry_after_secs: None }.payload(None).get("retryAfter"), None);
+        assert_eq!(CloseReason::Kicked.payload(Some("Spam"))["message"], "Spam");
+    }
+
+    #[test]
+    fn test_reason_fits_in_close_frame() {
+        let long = "é\"".repeat(200);
+        for reason in all_reasons() {
+            let frame = reason.close_frame(Some(&long));
+            assert!(frame.reason.len() <= MAX_CLOSE_REASON_BYTES, "{}: {}", reason, frame.reason.len());
+
+            let parsed: Value = serde_json::from_str(&frame.reason).unwrap();
+            assert_eq!(parsed["reason"], reason.name());
+            assert!(parsed["message"].as_str().is_some_and(|m| !m.is_empty()));
+        }
+    }
+}
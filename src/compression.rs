@@ -0,0 +1,698 @@
+//! Compression `permessage-deflate` des trames WebSocket (RFC 7692)
+//!
+//! Négociée au handshake (`accept`) quand le client la propose et que
+//! `[server.compression]` l'autorise. Chaque message est compressé
+//! indépendamment (`server_no_context_takeover` et
+//! `client_no_context_takeover` imposés) : aucun dictionnaire n'est conservé
+//! entre deux trames, la mémoire par connexion reste constante.
+//!
+//! tungstenite refuse à la lecture toute trame marquée RSV1 : le socket est
+//! donc enveloppé dans un `InflatingStream`, qui décompresse les messages
+//! entrants avant que tungstenite ne les analyse.
+//!
+//! - Sortie (`write_frames`) : une trame plus petite que `min_size`, ou que
+//!   la compression n'allège pas, part telle quelle (bit RSV1 à zéro).
+//! - Entrée : `server.max_inbound_message_size` borne chaque trame et chaque
+//!   message (`WebSocketConfig`), compressé ou non. La décompression s'arrête
+//!   dès que la limite est dépassée, avant d'allouer davantage (bombes de
+//!   compression), et tungstenite refuse le message comme une trame trop
+//!   grande.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use futures_util::{Sink, SinkExt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use crate::config::{CompressionConfig, ServerSettings};
+use crate::error::{ChatError, Result};
+
+/// Nom de l'extension dans `Sec-WebSocket-Extensions`
+pub const EXTENSION_NAME: &str = "permessage-deflate";
+
+/// En-tête de négociation des extensions
+pub const EXTENSIONS_HEADER: &str = "sec-websocket-extensions";
+
+/// Fin d'un bloc vide `BFINAL=0`, retirée à l'émission et rajoutée à la réception
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Taille des allocations successives pendant la décompression
+const INFLATE_CHUNK: usize = 16 * 1024;
+
+/// Fenêtre LZ77 de zlib, la seule que le serveur sache produire
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Octets lus sur le socket à chaque appel
+const READ_CHUNK: usize = 8 * 1024;
+
+// ================================================================
+// NÉGOCIATION
+// ================================================================
+
+/// Compression négociée pour une connexion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerMessageDeflate {
+    min_size: usize,
+    max_inflated_size: usize,
+}
+
+impl PerMessageDeflate {
+    pub fn new(config: &CompressionConfig, max_inflated_size: usize) -> Self {
+        Self {
+            min_size: config.min_size,
+            max_inflated_size,
+        }
+    }
+
+    /// Choisit la première offre acceptable de l'en-tête `Sec-WebSocket-Extensions`
+    pub fn negotiate(settings: &ServerSettings, offers: &str) -> Option<Self> {
+        if !settings.compression.enabled {
+            return None;
+        }
+        offers.split(',')
+            .any(offer_acceptable)
+            .then(|| Self::new(&settings.compression, settings.max_inbound_message_size))
+    }
+
+    /// Valeur de `Sec-WebSocket-Extensions` renvoyée au client
+    pub fn response_header(&self) -> String {
+        format!("{}; server_no_context_takeover; client_no_context_takeover", EXTENSION_NAME)
+    }
+}
+
+/// Offre `permessage-deflate` dont le serveur peut respecter tous les paramètres
+fn offer_acceptable(offer: &str) -> bool {
+    let mut parts = offer.split(';').map(str::trim);
+    if !parts.next().is_some_and(|name| name.eq_ignore_ascii_case(EXTENSION_NAME)) {
+        return false;
+    }
+
+    let mut seen: Vec<String> = Vec::new();
+    for param in parts {
+        let (name, value) = match param.split_once('=') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), Some(value.trim().trim_matches('"'))),
+            None => (param.to_ascii_lowercase(), None),
+        };
+        // Un paramètre répété invalide l'offre (RFC 7692, 7.1)
+        if seen.contains(&name) {
+            return false;
+        }
+
+        let valid = match (name.as_str(), value) {
+            ("server_no_context_takeover" | "client_no_context_takeover", None) => true,
+            // Le client accepte une fenêtre réduite pour ses trames : la nôtre (15) reste lisible
+            ("client_max_window_bits", None) => true,
+            ("client_max_window_bits", Some(bits)) => window_bits(bits).is_some(),
+            // Le serveur ne sait compresser qu'avec la fenêtre complète
+            ("server_max_window_bits", Some(bits)) => window_bits(bits) == Some(MAX_WINDOW_BITS),
+            _ => false,
+        };
+        if !valid {
+            return false;
+        }
+        seen.push(name);
+    }
+    true
+}
+
+fn window_bits(value: &str) -> Option<u8> {
+    value.parse::<u8>().ok().filter(|bits| (8..=MAX_WINDOW_BITS).contains(bits))
+}
+
+/// Négocie la compression au handshake et complète la réponse
+///
+/// `None` si le client ne propose aucune offre acceptable ou que la
+/// compression est désactivée (la réponse n'annonce alors rien).
+pub fn accept_compression(settings: &ServerSettings, request: &Request, response: &mut Response) -> Option<PerMessageDeflate> {
+    let offers: Vec<&str> = request.headers()
+        .get_all(EXTENSIONS_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    let deflate = PerMessageDeflate::negotiate(settings, &offers.join(","))?;
+
+    let header = HeaderValue::from_str(&deflate.response_header()).ok()?;
+    response.headers_mut().insert(EXTENSIONS_HEADER, header);
+    tracing::debug!(min_size = %deflate.min_size, "🗜️ Compression permessage-deflate négociée");
+    Some(deflate)
+}
+
+/// Limites tungstenite des trames entrantes (`server.max_inbound_message_size`)
+pub fn websocket_config(settings: &ServerSettings) -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(settings.max_inbound_message_size),
+        max_frame_size: Some(settings.max_inbound_message_size),
+        ..WebSocketConfig::default()
+    }
+}
+
+/// Handshake WebSocket côté serveur, compression négociée comprise
+///
+/// Retourne le flux (messages entrants déjà décompressés) et la compression
+/// à appliquer aux trames sortantes (`write_frames`).
+pub async fn accept<S>(stream: S, settings: &ServerSettings) -> Result<(WebSocketStream<InflatingStream<S>>, Option<PerMessageDeflate>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let stream = InflatingStream::new(stream, settings.max_inbound_message_size);
+    let negotiated = stream.negotiated.clone();
+
+    let websocket = tokio_tungstenite::accept_hdr_async_with_config(stream, |request: &Request, mut response: Response| {
+        // Activée avant l'envoi de la réponse : aucune trame du client ne la précède
+        if let Some(deflate) = accept_compression(settings, request, &mut response) {
+            let _ = negotiated.set(deflate);
+        }
+        Ok(response)
+    }, Some(websocket_config(settings))).await.map_err(|e| ChatError::InvalidFormat {
+        field: "handshake".to_string(),
+        reason: e.to_string(),
+    })?;
+
+    let deflate = websocket.get_ref().negotiated();
+    Ok((websocket, deflate))
+}
+
+// ================================================================
+// COMPRESSION DES TRAMES
+// ================================================================
+
+impl PerMessageDeflate {
+    /// Compresse une charge utile ; `None` si elle doit partir telle quelle
+    pub fn compress(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        if payload.len() < self.min_size {
+            return None;
+        }
+
+        let mut compressor = Compress::new(Compression::default(), false);
+        let mut output = Vec::with_capacity(payload.len() / 2 + 64);
+        loop {
+            let consumed = compressor.total_in() as usize;
+            output.reserve(payload.len() / 2 + 64);
+            compressor.compress_vec(&payload[consumed..], &mut output, FlushCompress::Sync).ok()?;
+            if compressor.total_in() as usize == payload.len() && output.len() < output.capacity() {
+                break;
+            }
+        }
+
+        if output.ends_with(&DEFLATE_TAIL) {
+            output.truncate(output.len() - DEFLATE_TAIL.len());
+        }
+        (output.len() < payload.len()).then_some(output)
+    }
+
+    /// Décompresse un message entrant marqué RSV1, dans la limite configurée
+    pub fn decompress(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut input = Vec::with_capacity(payload.len() + DEFLATE_TAIL.len());
+        input.extend_from_slice(payload);
+        input.extend_from_slice(&DEFLATE_TAIL);
+
+        let mut inflater = Decompress::new(false);
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            if output.len() > self.max_inflated_size {
+                return Err(ChatError::MessageTooLong { actual: output.len(), max: self.max_inflated_size });
+            }
+            // Jamais plus d'un octet au-delà de la limite : le dépassement suffit à conclure
+            if output.len() == output.capacity() {
+                output.reserve_exact(INFLATE_CHUNK.min(self.max_inflated_size + 1 - output.len()));
+            }
+
+            let consumed = inflater.total_in() as usize;
+            let produced = output.len();
+            let status = inflater.decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|e| ChatError::InvalidFormat { field: "frame".to_string(), reason: e.to_string() })?;
+
+            let input_done = inflater.total_in() as usize == input.len();
+            if status == Status::StreamEnd || (input_done && output.len() < output.capacity()) {
+                break;
+            }
+            if inflater.total_in() as usize == consumed && output.len() == produced {
+                return Err(ChatError::InvalidFormat {
+                    field: "frame".to_string(),
+                    reason: "Flux deflate tronqué".to_string(),
+                });
+            }
+        }
+
+        if output.len() > self.max_inflated_size {
+            return Err(ChatError::MessageTooLong { actual: output.len(), max: self.max_inflated_size });
+        }
+        Ok(output)
+    }
+
+    /// Trame texte sortante, compressée (RSV1) si cela en vaut la peine
+    pub fn outbound(&self, text: String) -> Message {
+        match self.compress(text.as_bytes()) {
+            Some(compressed) => {
+                let mut frame = Frame::message(compressed, OpCode::Data(Data::Text), true);
+                frame.header_mut().rsv1 = true;
+                Message::Frame(frame)
+            }
+            None => Message::Text(text),
+        }
+    }
+}
+
+/// Envoie sur le socket les trames destinées au client, jusqu'à la fermeture de la file
+///
+/// Pendant de `inbound::read_frames` pour l'écriture : les trames texte sont
+/// compressées selon `deflate` ; la boucle s'arrête après une trame `Close`
+/// ou sur erreur d'écriture.
+pub async fn write_frames<W>(mut receiver: UnboundedReceiver<Message>, mut sink: W, deflate: Option<PerMessageDeflate>)
+where
+    W: Sink<Message> + Unpin,
+    W::Error: std::fmt::Display,
+{
+    while let Some(message) = receiver.recv().await {
+        let closing = matches!(message, Message::Close(_));
+        let message = match (message, deflate) {
+            (Message::Text(text), Some(deflate)) => deflate.outbound(text),
+            (message, _) => message,
+        };
+        if let Err(e) = sink.send(message).await {
+            tracing::warn!(error = %e, "❌ Erreur d'écriture sur le socket");
+            break;
+        }
+        if closing {
+            break;
+        }
+    }
+}
+
+// ================================================================
+// DÉCOMPRESSION SOUS TUNGSTENITE
+// ================================================================
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+
+/// En-tête de trame lu dans le flux brut
+struct RawHeader {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: u64,
+}
+
+/// En-tête complet en tête de `raw` ; `None` s'il faut lire davantage
+fn parse_header(raw: &[u8]) -> Option<RawHeader> {
+    if raw.len() < 2 {
+        return None;
+    }
+    let (first, second) = (raw[0], raw[1]);
+    let (payload_len, mut header_len) = match second & 0x7f {
+        126 => (u16::from_be_bytes([*raw.get(2)?, *raw.get(3)?]) as u64, 4),
+        127 => (u64::from_be_bytes(raw.get(2..10)?.try_into().ok()?), 10),
+        len => (len as u64, 2),
+    };
+    let mask = if second & 0x80 != 0 {
+        let key: [u8; 4] = raw.get(header_len..header_len + 4)?.try_into().ok()?;
+        header_len += 4;
+        Some(key)
+    } else {
+        None
+    };
+
+    Some(RawHeader {
+        fin: first & 0x80 != 0,
+        rsv1: first & 0x40 != 0,
+        opcode: first & 0x0f,
+        mask,
+        header_len,
+        payload_len,
+    })
+}
+
+fn apply_mask(payload: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+/// En-tête d'une trame finale (`FIN`, RSV à zéro) annonçant `payload_len` octets
+fn write_header(out: &mut Vec<u8>, opcode: u8, mask: Option<[u8; 4]>, payload_len: u64) {
+    out.push(0x80 | opcode);
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload_len {
+        0..=125 => out.push(mask_bit | payload_len as u8),
+        126..=0xffff => {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(payload_len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&payload_len.to_be_bytes());
+        }
+    }
+    if let Some(key) = mask {
+        out.extend_from_slice(&key);
+    }
+}
+
+/// Socket dont les messages entrants compressés sont décompressés à la lecture
+///
+/// Tant qu'aucune compression n'est négociée, les octets passent tels quels.
+/// Ensuite, chaque message marqué RSV1 (fragments réunis) est rendu à
+/// tungstenite en une trame non compressée, masquée avec la clé d'origine ;
+/// les trames de contrôle et les messages non compressés sont inchangés.
+///
+/// Un message qui dépasse `max_size` une fois décompressé n'est pas
+/// décompressé davantage : tungstenite en reçoit seulement l'en-tête, qui
+/// annonce la taille atteinte, et le refuse (`max_frame_size`) ; le flux
+/// s'arrête là.
+pub struct InflatingStream<S> {
+    inner: S,
+    negotiated: Arc<OnceLock<PerMessageDeflate>>,
+    max_size: usize,
+    /// Octets lus sur le socket, pas encore analysés
+    raw: Vec<u8>,
+    /// Octets prêts pour tungstenite
+    ready: Vec<u8>,
+    ready_pos: usize,
+    /// Message compressé fragmenté en cours : opcode et charge déjà reçue
+    fragments: Option<(u8, Vec<u8>)>,
+    /// Message refusé : plus rien n'est lu
+    rejected: bool,
+}
+
+impl<S> InflatingStream<S> {
+    pub fn new(inner: S, max_size: usize) -> Self {
+        Self {
+            inner,
+            negotiated: Arc::new(OnceLock::new()),
+            max_size,
+            raw: Vec::new(),
+            ready: Vec::new(),
+            ready_pos: 0,
+            fragments: None,
+            rejected: false,
+        }
+    }
+
+    /// Active la décompression (handshake terminé, extension acceptée)
+    pub fn enable(&self, deflate: PerMessageDeflate) {
+        let _ = self.negotiated.set(deflate);
+    }
+
+    /// Compression négociée sur ce socket
+    pub fn negotiated(&self) -> Option<PerMessageDeflate> {
+        self.negotiated.get().copied()
+    }
+
+    /// Refuse le message en cours : seul son en-tête, trop grand, est transmis
+    fn reject(&mut self, opcode: u8, mask: Option<[u8; 4]>, size: u64) {
+        tracing::warn!(size = %size, max = %self.max_size, "🗜️ Message entrant trop grand une fois décompressé");
+        write_header(&mut self.ready, opcode, mask, size);
+        self.raw.clear();
+        self.fragments = None;
+        self.rejected = true;
+    }
+
+    /// Transforme les trames complètes de `raw` en octets prêts pour tungstenite
+    fn process(&mut self) -> io::Result<()> {
+        let Some(deflate) = self.negotiated.get().copied() else {
+            self.ready.append(&mut self.raw);
+            return Ok(());
+        };
+
+        while let Some(header) = parse_header(&self.raw) {
+            if header.payload_len > self.max_size as u64 {
+                self.reject(header.opcode, header.mask, header.payload_len);
+                return Ok(());
+            }
+            let total = header.header_len + header.payload_len as usize;
+            if self.raw.len() < total {
+                break;
+            }
+            let frame: Vec<u8> = self.raw.drain(..total).collect();
+
+            let compressed = header.rsv1 && matches!(header.opcode, OPCODE_TEXT | OPCODE_BINARY);
+            let continuing = header.opcode == OPCODE_CONTINUATION && self.fragments.is_some();
+            if !compressed && !continuing {
+                // Contrôle, message non compressé ou fragment de celui-ci
+                self.ready.extend_from_slice(&frame);
+                continue;
+            }
+
+            let mut payload = frame[header.header_len..].to_vec();
+            if let Some(key) = header.mask {
+                apply_mask(&mut payload, key);
+            }
+            let (opcode, data) = match self.fragments.take() {
+                Some((opcode, mut data)) if continuing => {
+                    data.extend_from_slice(&payload);
+                    (opcode, data)
+                }
+                _ => (header.opcode, payload),
+            };
+            if data.len() > self.max_size {
+                self.reject(opcode, header.mask, data.len() as u64);
+                return Ok(());
+            }
+            if !header.fin {
+                self.fragments = Some((opcode, data));
+                continue;
+            }
+
+            match deflate.decompress(&data) {
+                Ok(mut plain) => {
+                    write_header(&mut self.ready, opcode, header.mask, plain.len() as u64);
+                    if let Some(key) = header.mask {
+                        apply_mask(&mut plain, key);
+                    }
+                    self.ready.extend_from_slice(&plain);
+                }
+                Err(ChatError::MessageTooLong { actual, .. }) => {
+                    self.reject(opcode, header.mask, actual as u64);
+                    return Ok(());
+                }
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for InflatingStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.ready_pos < this.ready.len() {
+                let count = buf.remaining().min(this.ready.len() - this.ready_pos);
+                buf.put_slice(&this.ready[this.ready_pos..this.ready_pos + count]);
+                this.ready_pos += count;
+                if this.ready_pos == this.ready.len() {
+                    this.ready.clear();
+                    this.ready_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.rejected {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                // Fin du flux
+                return Poll::Ready(Ok(()));
+            }
+            this.raw.extend_from_slice(chunk_buf.filled());
+            this.process()?;
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for InflatingStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::error::{CapacityError, Error};
+
+    fn settings() -> ServerSettings {
+        ServerSettings {
+            max_inbound_message_size: 64 * 1024,
+            compression: CompressionConfig { enabled: true, min_size: 64 },
+            ..ServerSettings::default()
+        }
+    }
+
+    fn deflate() -> PerMessageDeflate {
+        PerMessageDeflate::new(&settings().compression, 64 * 1024)
+    }
+
+    fn large_payload() -> String {
+        let messages: Vec<String> = (0..200)
+            .map(|i| format!(r#"{{"type":"message","data":{{"id":{},"roomId":7,"content":"bonjour à tous"}}}}"#, i))
+            .collect();
+        format!("[{}]", messages.join(","))
+    }
+
+    /// Serveur (`accept`) et client reliés en mémoire ; le client propose `offer`
+    async fn connect(
+        settings: ServerSettings,
+        offer: Option<&'static str>,
+    ) -> (
+        WebSocketStream<InflatingStream<tokio::io::DuplexStream>>,
+        Option<PerMessageDeflate>,
+        WebSocketStream<InflatingStream<tokio::io::DuplexStream>>,
+        Option<HeaderValue>,
+    ) {
+        // Assez grand pour une trame en clair au-delà de la limite
+        let (client_io, server_io) = tokio::io::duplex(256 * 1024);
+        let server = tokio::spawn(async move { accept(server_io, &settings).await.unwrap() });
+
+        let mut request = "ws://localhost/ws".into_client_request().unwrap();
+        if let Some(offer) = offer {
+            request.headers_mut().insert(EXTENSIONS_HEADER, HeaderValue::from_static(offer));
+        }
+        let client_io = InflatingStream::new(client_io, usize::MAX);
+        let (client, response) = tokio_tungstenite::client_async(request, client_io).await.unwrap();
+        let accepted = response.headers().get(EXTENSIONS_HEADER).cloned();
+        if accepted.is_some() {
+            client.get_ref().enable(deflate());
+        }
+
+        let (server, negotiated) = server.await.unwrap();
+        (server, negotiated, client, accepted)
+    }
+
+    #[test]
+    fn test_negotiates_when_client_offers_deflate() {
+        let offered = PerMessageDeflate::negotiate(&settings(), "permessage-deflate; client_max_window_bits").unwrap();
+        assert_eq!(offered.response_header(), "permessage-deflate; server_no_context_takeover; client_no_context_takeover");
+
+        // Offre inacceptable (fenêtre serveur réduite) : on retient la suivante
+        let fallback = "permessage-deflate; server_max_window_bits=10, permessage-deflate; server_no_context_takeover";
+        assert!(PerMessageDeflate::negotiate(&settings(), fallback).is_some());
+
+        assert!(PerMessageDeflate::negotiate(&settings(), "permessage-deflate; server_max_window_bits=10").is_none());
+        assert!(PerMessageDeflate::negotiate(&settings(), "permessage-deflate; client_max_window_bits; client_max_window_bits").is_none());
+        assert!(PerMessageDeflate::negotiate(&settings(), "x-webkit-deflate-frame").is_none());
+        assert!(PerMessageDeflate::negotiate(&settings(), "").is_none());
+
+        let mut disabled = settings();
+        disabled.compression.enabled = false;
+        assert!(PerMessageDeflate::negotiate(&disabled, "permessage-deflate").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_accepts_offer_and_inflates_compressed_frames() {
+        let (mut server, negotiated, mut client, accepted) = connect(settings(), Some("permessage-deflate; client_max_window_bits")).await;
+        assert_eq!(negotiated, Some(deflate()));
+        assert_eq!(accepted.unwrap(), "permessage-deflate; server_no_context_takeover; client_no_context_takeover");
+
+        // Client → serveur : trame compressée (RSV1) puis petite trame en clair
+        let payload = large_payload();
+        client.send(deflate().outbound(payload.clone())).await.unwrap();
+        client.send(Message::Text("{}".to_string())).await.unwrap();
+        assert_eq!(server.next().await.unwrap().unwrap(), Message::Text(payload.clone()));
+        assert_eq!(server.next().await.unwrap().unwrap(), Message::Text("{}".to_string()));
+
+        // Serveur → client par la file du client, compressée au-delà de `min_size`
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        sender.send(Message::Text(payload.clone())).unwrap();
+        drop(sender);
+        write_frames(receiver, &mut server, negotiated).await;
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::Text(payload));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_without_offer_stays_uncompressed() {
+        let (_server, negotiated, _client, accepted) = connect(settings(), None).await;
+        assert!(negotiated.is_none());
+        assert!(accepted.is_none());
+
+        let mut disabled = settings();
+        disabled.compression.enabled = false;
+        let (_server, negotiated, _client, accepted) = connect(disabled, Some("permessage-deflate")).await;
+        assert!(negotiated.is_none());
+        assert!(accepted.is_none());
+    }
+
+    #[test]
+    fn test_large_frames_round_trip_and_small_ones_skip() {
+        let deflate = deflate();
+        let payload = large_payload();
+
+        let compressed = deflate.compress(payload.as_bytes()).unwrap();
+        assert!(compressed.len() < payload.len() / 4);
+        assert!(!compressed.ends_with(&DEFLATE_TAIL));
+        assert_eq!(deflate.decompress(&compressed).unwrap(), payload.as_bytes());
+
+        // Sous `min_size` (64 octets) : en clair
+        assert!(deflate.compress(br#"{"type":"pong"}"#).is_none());
+        assert!(matches!(deflate.outbound("{}".to_string()), Message::Text(_)));
+        match deflate.outbound(payload) {
+            Message::Frame(frame) => assert!(frame.header().rsv1),
+            other => panic!("trame compressée attendue, reçu {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_oversized_inflated_frames_rejected() {
+        // 10 Mo de zéros tiennent en quelques kilo-octets compressés
+        let generous = PerMessageDeflate::new(&settings().compression, 64 * 1024 * 1024);
+        let bomb = generous.compress(&vec![0u8; 10 * 1024 * 1024]).unwrap();
+        assert!(bomb.len() < 64 * 1024);
+
+        match deflate().decompress(&bomb) {
+            Err(ChatError::MessageTooLong { actual, max }) => {
+                assert_eq!(max, 64 * 1024);
+                assert!(actual > max);
+            }
+            other => panic!("refus attendu, reçu {:?}", other.map(|data| data.len())),
+        }
+
+        assert!(matches!(deflate().decompress(&[0xff, 0xff, 0xff]), Err(ChatError::InvalidFormat { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_inbound_message_closes_the_stream() {
+        let (mut server, _, mut client, _) = connect(settings(), Some("permessage-deflate")).await;
+
+        // Bombe de compression : petite sur le fil, 10 Mo une fois décompressée
+        let generous = PerMessageDeflate::new(&settings().compression, 64 * 1024 * 1024);
+        client.send(generous.outbound("0".repeat(10 * 1024 * 1024))).await.unwrap();
+        match server.next().await.unwrap() {
+            Err(Error::Capacity(CapacityError::MessageTooLong { size, max_size })) => {
+                assert_eq!(max_size, 64 * 1024);
+                assert!(size > max_size);
+            }
+            other => panic!("refus attendu, reçu {:?}", other),
+        }
+
+        // Même limite pour une trame en clair
+        let (mut server, _, mut client, _) = connect(settings(), None).await;
+        client.send(Message::Text("x".repeat(64 * 1024 + 1))).await.unwrap();
+        assert!(matches!(server.next().await.unwrap(), Err(Error::Capacity(CapacityError::MessageTooLong { .. }))));
+    }
+}
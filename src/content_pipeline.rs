@@ -0,0 +1,348 @@
+//! Chaîne de transformation du contenu des messages
+//!
+//! Appliquée au contenu déjà validé, avant la mise en forme (messages longs)
+//! et la persistance. Chaque déploiement choisit ses transformations et leur
+//! ordre dans la section `[content_pipeline]` :
+//! - `emoji_shortcodes` : `:smile:` → 😄
+//! - `url_links` : extraction des liens `http(s)://` pour les clients
+//! - `mention_links` : extraction des mentions `@utilisateur`
+//!
+//! Chaque transformation reçoit et rend le contenu avec les métadonnées
+//! extraites jusque-là ; celles-ci sont jointes aux métadonnées du message.
+
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::config::ContentPipelineConfig;
+use crate::error::Result;
+use crate::hub::mentions::{parse_mentions, MentionKind};
+
+/// Raccourcis émoji connus par défaut (complétés par la configuration)
+pub const DEFAULT_EMOJI_SHORTCODES: &[(&str, &str)] = &[
+    ("smile", "😄"),
+    ("grin", "😁"),
+    ("joy", "😂"),
+    ("wink", "😉"),
+    ("heart", "❤️"),
+    ("thumbsup", "👍"),
+    ("+1", "👍"),
+    ("thumbsdown", "👎"),
+    ("-1", "👎"),
+    ("tada", "🎉"),
+    ("fire", "🔥"),
+    ("rocket", "🚀"),
+    ("wave", "👋"),
+    ("eyes", "👀"),
+    ("thinking", "🤔"),
+    ("cry", "😢"),
+    ("check", "✅"),
+    ("x", "❌"),
+];
+
+static URL_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://[^\s<>"']+"#).unwrap());
+
+// ================================================================
+// CONTENU TRANSFORMÉ
+// ================================================================
+
+/// Informations extraites du contenu par les transformations
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ContentMetadata {
+    /// Noms d'utilisateur mentionnés, dans l'ordre d'apparition
+    pub mentions: Vec<String>,
+    /// Liens détectés, dans l'ordre d'apparition
+    pub links: Vec<String>,
+}
+
+impl ContentMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.mentions.is_empty() && self.links.is_empty()
+    }
+
+    /// Joint les informations extraites aux métadonnées du message
+    pub fn annotate_metadata(&self, metadata: &mut Value) {
+        if !self.links.is_empty() {
+            metadata["links"] = json!(self.links);
+        }
+        if !self.mentions.is_empty() {
+            metadata["mentionedUsernames"] = json!(self.mentions);
+        }
+    }
+}
+
+/// Contenu et métadonnées transmis d'une transformation à la suivante
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformedContent {
+    pub content: String,
+    pub metadata: ContentMetadata,
+}
+
+impl TransformedContent {
+    pub fn new(content: &str) -> Self {
+        Self { content: content.to_string(), metadata: ContentMetadata::default() }
+    }
+}
+
+/// Étape de la chaîne
+pub trait ContentTransform: Send + Sync {
+    /// Nom utilisé dans la configuration
+    fn name(&self) -> &str;
+
+    fn apply(&self, input: TransformedContent) -> Result<TransformedContent>;
+}
+
+// ================================================================
+// TRANSFORMATIONS FOURNIES
+// ================================================================
+
+/// Remplace les raccourcis `:nom:` connus par l'émoji correspondant
+pub struct EmojiShortcodes {
+    table: HashMap<String, String>,
+}
+
+impl EmojiShortcodes {
+    pub const NAME: &'static str = "emoji_shortcodes";
+
+    /// Table par défaut, complétée ou surchargée par `extra`
+    pub fn new(extra: &HashMap<String, String>) -> Self {
+        let mut table: HashMap<String, String> = DEFAULT_EMOJI_SHORTCODES.iter()
+            .map(|(code, emoji)| (code.to_string(), emoji.to_string()))
+            .collect();
+        table.extend(extra.iter().map(|(code, emoji)| (code.clone(), emoji.clone())));
+        Self { table }
+    }
+
+    fn is_shortcode_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')
+    }
+
+    /// Remplacement gauche → droite ; un `:` non suivi d'un raccourci connu est conservé
+    pub fn expand(&self, content: &str) -> String {
+        let mut expanded = String::with_capacity(content.len());
+        let mut rest = content;
+
+        while let Some(start) = rest.find(':') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+
+            let replacement = after.find(':')
+                .map(|end| &after[..end])
+                .filter(|code| !code.is_empty() && code.chars().all(Self::is_shortcode_char))
+                .and_then(|code| self.table.get(code).map(|emoji| (code.len(), emoji)));
+
+            match replacement {
+                Some((code_len, emoji)) => {
+                    expanded.push_str(emoji);
+                    rest = &after[code_len + 1..];
+                }
+                None => {
+                    expanded.push(':');
+                    rest = after;
+                }
+            }
+        }
+
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+impl ContentTransform for EmojiShortcodes {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn apply(&self, input: TransformedContent) -> Result<TransformedContent> {
+        Ok(TransformedContent { content: self.expand(&input.content), ..input })
+    }
+}
+
+/// Détecte les liens `http(s)://` ; le contenu reste inchangé, les clients
+/// rendent les liens listés dans les métadonnées
+pub struct UrlAutoLink;
+
+impl UrlAutoLink {
+    pub const NAME: &'static str = "url_links";
+
+    /// Liens du contenu, sans la ponctuation finale (`.`, `,`, `)`...)
+    pub fn extract(content: &str) -> Vec<String> {
+        URL_PATTERN.find_iter(content)
+            .map(|m| m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']']).to_string())
+            .collect()
+    }
+}
+
+impl ContentTransform for UrlAutoLink {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn apply(&self, mut input: TransformedContent) -> Result<TransformedContent> {
+        for link in Self::extract(&input.content) {
+            if !input.metadata.links.contains(&link) {
+                input.metadata.links.push(link);
+            }
+        }
+        Ok(input)
+    }
+}
+
+/// Relève les mentions d'utilisateurs (`@nom`) pour les clients
+pub struct MentionLinks;
+
+impl MentionLinks {
+    pub const NAME: &'static str = "mention_links";
+}
+
+impl ContentTransform for MentionLinks {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn apply(&self, mut input: TransformedContent) -> Result<TransformedContent> {
+        for mention in parse_mentions(&input.content) {
+            if mention.kind == MentionKind::User && !input.metadata.mentions.contains(&mention.target) {
+                input.metadata.mentions.push(mention.target);
+            }
+        }
+        Ok(input)
+    }
+}
+
+/// Transformation fournie désignée par son nom de configuration
+pub fn builtin_transform(name: &str, config: &ContentPipelineConfig) -> Option<Box<dyn ContentTransform>> {
+    match name {
+        EmojiShortcodes::NAME => Some(Box::new(EmojiShortcodes::new(&config.emoji_shortcodes))),
+        UrlAutoLink::NAME => Some(Box::new(UrlAutoLink)),
+        MentionLinks::NAME => Some(Box::new(MentionLinks)),
+        _ => None,
+    }
+}
+
+// ================================================================
+// CHAÎNE
+// ================================================================
+
+/// Transformations appliquées dans l'ordre
+#[derive(Default)]
+pub struct ContentPipeline {
+    transforms: Vec<Box<dyn ContentTransform>>,
+}
+
+impl ContentPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Chaîne décrite par la section `[content_pipeline]`
+    ///
+    /// Les noms inconnus sont refusés à la validation de la configuration ;
+    /// ici ils sont ignorés.
+    pub fn from_config(config: &ContentPipelineConfig) -> Self {
+        config.transforms.iter().fold(Self::new(), |pipeline, name| {
+            match builtin_transform(name, config) {
+                Some(transform) => pipeline.with_transform(transform),
+                None => {
+                    tracing::warn!(transform = %name, "⚠️ Transformation de contenu inconnue ignorée");
+                    pipeline
+                }
+            }
+        })
+    }
+
+    /// Ajoute une transformation en fin de chaîne
+    pub fn with_transform(mut self, transform: Box<dyn ContentTransform>) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    /// Noms des transformations, dans l'ordre d'application
+    pub fn names(&self) -> Vec<&str> {
+        self.transforms.iter().map(|transform| transform.name()).collect()
+    }
+
+    pub fn apply(&self, content: &str) -> Result<TransformedContent> {
+        self.transforms.iter()
+            .try_fold(TransformedContent::new(content), |current, transform| transform.apply(current))
+    }
+}
+
+impl std::fmt::Debug for ContentPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentPipeline").field("transforms", &self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(names: &[&str]) -> ContentPipeline {
+        ContentPipeline::from_config(&ContentPipelineConfig {
+            transforms: names.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_pipeline_expands_shortcode_and_extracts_url() {
+        let result = pipeline(&["emoji_shortcodes", "url_links"])
+            .apply("Salut :smile: la doc est sur https://veza.example/docs.")
+            .unwrap();
+
+        assert_eq!(result.content, "Salut 😄 la doc est sur https://veza.example/docs.");
+        assert_eq!(result.metadata.links, vec!["https://veza.example/docs".to_string()]);
+        assert!(result.metadata.mentions.is_empty());
+    }
+
+    #[test]
+    fn test_shortcode_expansion_edge_cases() {
+        let emoji = EmojiShortcodes::new(&HashMap::new());
+
+        assert_eq!(emoji.expand("rdv à 10:30:45"), "rdv à 10:30:45");
+        assert_eq!(emoji.expand(":unknown: :fire:"), ":unknown: 🔥");
+        assert_eq!(emoji.expand("a::smile::b"), "a:😄:b");
+        assert_eq!(emoji.expand(":+1::tada:"), "👍🎉");
+        assert_eq!(emoji.expand("fin :"), "fin :");
+    }
+
+    #[test]
+    fn test_configured_shortcodes_extend_defaults() {
+        let config = ContentPipelineConfig {
+            transforms: vec!["emoji_shortcodes".to_string()],
+            emoji_shortcodes: HashMap::from([("veza".to_string(), "🎵".to_string())]),
+        };
+
+        let result = ContentPipeline::from_config(&config).apply(":veza: :smile:").unwrap();
+        assert_eq!(result.content, "🎵 😄");
+    }
+
+    #[test]
+    fn test_mentions_and_metadata_annotation() {
+        let result = pipeline(&["mention_links", "url_links"])
+            .apply("@alice regarde https://a.example et @alice")
+            .unwrap();
+
+        assert_eq!(result.metadata.mentions, vec!["alice".to_string()]);
+
+        let mut metadata = json!({});
+        result.metadata.annotate_metadata(&mut metadata);
+        assert_eq!(metadata["links"], json!(["https://a.example"]));
+        assert_eq!(metadata["mentionedUsernames"], json!(["alice"]));
+    }
+
+    #[test]
+    fn test_order_and_empty_pipeline() {
+        let empty = ContentPipeline::from_config(&ContentPipelineConfig::default());
+        assert!(empty.is_empty());
+        assert_eq!(empty.apply(":smile:").unwrap().content, ":smile:");
+
+        assert_eq!(pipeline(&["url_links", "bogus", "emoji_shortcodes"]).names(), vec!["url_links", "emoji_shortcodes"]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Pool de connexions PostgreSQL
+//!
+//! Le pool est construit à partir de la section `[database]` :
+//! - `max_connections` : taille du pool
+//! - `min_connections` : connexions gardées ouvertes même au repos
+//! - `acquire_timeout`, `idle_timeout` et `max_lifetime`
+//!
+//! Son état est exporté à chaque passage de maintenance (`report_pool_stats`) :
+//! connexions utilisées et inactives, attente d'une connexion libre. Une attente
+//! au-delà de `acquire_timeout` devient `ChatError::DatabasePoolExhausted`,
+//! comptée par `record_pool_exhausted` quelle que soit la requête concernée.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use crate::config::DatabaseConfig;
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// CONSTRUCTION
+// ================================================================
+
+/// Options du pool tirées de la configuration
+pub fn pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout)
+        .idle_timeout(config.idle_timeout)
+        .max_lifetime(config.max_lifetime)
+}
+
+/// Ouvre le pool et ses `min_connections` premières connexions
+pub async fn connect(config: &DatabaseConfig) -> Result<PgPool> {
+    let pool = tokio::time::timeout(config.connect_timeout, pool_options(config).connect(config.url.as_str()))
+        .await
+        .map_err(|_| ChatError::ConnectionTimeout { seconds: config.connect_timeout.as_secs() })?
+        .map_err(|e| ChatError::from_sqlx_error("connect_pool", e))?;
+
+    tracing::info!(
+        max_connections = %config.max_connections,
+        min_connections = %config.min_connections,
+        acquire_timeout_ms = %config.acquire_timeout.as_millis(),
+        "🗄️ Pool de connexions ouvert"
+    );
+    Ok(pool)
+}
+
+/// Construit le pool sans ouvrir de connexion avant la première requête
+pub fn connect_lazy(config: &DatabaseConfig) -> Result<PgPool> {
+    pool_options(config)
+        .connect_lazy(config.url.as_str())
+        .map_err(|e| ChatError::from_sqlx_error("connect_pool", e))
+}
+
+// ================================================================
+// ÉTAT DU POOL
+// ================================================================
+
+/// Instantané de l'occupation du pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Connexions ouvertes
+    pub size: u32,
+    /// Connexions ouvertes et libres
+    pub idle: u32,
+    /// Connexions empruntées par une requête
+    pub in_use: u32,
+    /// Taille maximale du pool
+    pub max: u32,
+}
+
+impl PoolStats {
+    pub fn of(pool: &PgPool) -> Self {
+        let size = pool.size();
+        let idle = pool.num_idle() as u32;
+        Self {
+            size,
+            idle,
+            in_use: size.saturating_sub(idle),
+            max: pool.options().get_max_connections(),
+        }
+    }
+
+    /// Toutes les connexions sont empruntées : les requêtes suivantes attendent
+    pub fn is_saturated(&self) -> bool {
+        self.in_use >= self.max
+    }
+}
+
+/// Requêtes restées sans connexion au-delà de `acquire_timeout` depuis le démarrage
+static POOL_EXHAUSTED: AtomicU64 = AtomicU64::new(0);
+
+/// Compte une attente de connexion expirée (`ChatError::from_sqlx_error`)
+pub fn record_pool_exhausted() {
+    POOL_EXHAUSTED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Attentes de connexion expirées depuis le démarrage, toutes requêtes confondues
+pub fn pool_exhausted_total() -> u64 {
+    POOL_EXHAUSTED.load(Ordering::Relaxed)
+}
+
+/// Mesure l'attente d'une connexion libre, rendue aussitôt au pool
+pub async fn probe_acquire(pool: &PgPool) -> Result<Duration> {
+    let started = Instant::now();
+    let connection = pool.acquire().await
+        .map_err(|e| ChatError::from_sqlx_error("probe_acquire", e))?;
+    drop(connection);
+    Ok(started.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DatabaseConfig {
+        DatabaseConfig {
+            max_connections: 7,
+            min_connections: 2,
+            acquire_timeout: Duration::from_millis(1500),
+            idle_timeout: Duration::from_secs(90),
+            max_lifetime: Duration::from_secs(900),
+            ..DatabaseConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_values_are_applied() {
+        let pool = connect_lazy(&config()).unwrap();
+        let options = pool.options();
+        assert_eq!(options.get_max_connections(), 7);
+        assert_eq!(options.get_min_connections(), 2);
+        assert_eq!(options.get_acquire_timeout(), Duration::from_millis(1500));
+        assert_eq!(options.get_idle_timeout(), Some(Duration::from_secs(90)));
+        assert_eq!(options.get_max_lifetime(), Some(Duration::from_secs(900)));
+
+        // Aucune connexion ouverte par un pool paresseux
+        assert_eq!(PoolStats::of(&pool), PoolStats { size: 0, idle: 0, in_use: 0, max: 7 });
+    }
+
+    #[tokio::test]
+    async fn test_acquire_timeout_maps_to_pool_exhausted() {
+        // Port fermé : sqlx réessaie la connexion refusée jusqu'à `acquire_timeout`,
+        // la même expiration qu'un pool saturé (`test_saturated_pool_times_out_as_exhausted`)
+        let mut config = config();
+        config.url = "postgresql://veza@127.0.0.1:1/veza_chat".parse().unwrap();
+        config.max_connections = 1;
+        config.min_connections = 0;
+        config.acquire_timeout = Duration::from_millis(100);
+        let pool = connect_lazy(&config).unwrap();
+
+        let started = Instant::now();
+        let error = probe_acquire(&pool).await.unwrap_err();
+        assert!(matches!(&error, ChatError::DatabasePoolExhausted { operation } if operation == "probe_acquire"));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(error.http_status(), 503);
+    }
+
+    #[tokio::test]
+    #[ignore = "nécessite PostgreSQL (VEZA_TEST_DATABASE_URL)"]
+    async fn test_saturated_pool_times_out_as_exhausted() {
+        let mut config = config();
+        config.url = std::env::var("VEZA_TEST_DATABASE_URL").expect("VEZA_TEST_DATABASE_URL").parse().unwrap();
+        config.max_connections = 2;
+        config.min_connections = 0;
+        config.acquire_timeout = Duration::from_millis(100);
+        let pool = connect_lazy(&config).unwrap();
+
+        // Toutes les connexions empruntées : la suivante attend jusqu'à `acquire_timeout`
+        let held = vec![pool.acquire().await.unwrap(), pool.acquire().await.unwrap()];
+        assert!(PoolStats::of(&pool).is_saturated());
+        let before = pool_exhausted_total();
+        let started = Instant::now();
+        let error = probe_acquire(&pool).await.unwrap_err();
+        assert!(matches!(&error, ChatError::DatabasePoolExhausted { operation } if operation == "probe_acquire"));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(pool_exhausted_total() > before);
+
+        // Une connexion rendue est de nouveau disponible
+        drop(held);
+        assert!(probe_acquire(&pool).await.is_ok());
+    }
+
+    #[test]
+    fn test_saturation_when_every_connection_is_in_use() {
+        let stats = PoolStats { size: 4, idle: 0, in_use: 4, max: 4 };
+        assert!(stats.is_saturated());
+        assert!(!PoolStats { idle: 1, in_use: 3, ..stats }.is_saturated());
+    }
+}
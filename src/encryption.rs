@@ -0,0 +1,236 @@
+//! Chiffrement au repos du contenu des messages, pour les salons sensibles
+//!
+//! Chiffrement par enveloppe (AES-256-GCM) :
+//! - Chaque message reçoit une clé de données aléatoire qui chiffre son contenu
+//!   (et son corps complet s'il est long)
+//! - La clé de données est elle-même chiffrée (« enveloppée ») par la clé
+//!   maîtresse active du serveur (`[security.encryption_at_rest]`)
+//! - `messages.encryption_key_id` désigne la clé maîtresse, `messages.wrapped_key`
+//!   porte la clé de données enveloppée ; le contenu est stocké scellé
+//!   (`enc:v1:` suivi du nonce et du chiffré en base64)
+//!
+//! Rotation : ajouter une clé à `keys` et la désigner par `active_key_id`. Les
+//! messages existants restent lisibles tant que leur clé est configurée ;
+//! `rewrap` les fait passer à la clé active sans toucher à leur contenu.
+
+use std::collections::HashMap;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use crate::config::EncryptionAtRestConfig;
+use crate::error::{ChatError, Result};
+
+/// Préfixe d'un contenu scellé
+const SEALED_PREFIX: &str = "enc:v1:";
+
+/// Taille des clés (maîtresses et de données)
+pub const KEY_LEN: usize = 32;
+
+fn aead_key(bytes: &[u8]) -> Result<LessSafeKey> {
+    UnboundKey::new(&AES_256_GCM, bytes)
+        .map(LessSafeKey::new)
+        .map_err(|_| ChatError::configuration_error("Clé de chiffrement invalide (32 octets attendus)"))
+}
+
+fn decryption_error() -> ChatError {
+    ChatError::Internal { message: "Déchiffrement impossible : clé inconnue ou contenu altéré".to_string() }
+}
+
+fn rng_error() -> ChatError {
+    ChatError::Internal { message: "Générateur aléatoire indisponible".to_string() }
+}
+
+/// Chiffre `plaintext` ; retourne nonce || chiffré || tag
+fn seal_bytes(key: &LessSafeKey, rng: &SystemRandom, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce).map_err(|_| rng_error())?;
+
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+        .map_err(|_| ChatError::Internal { message: "Chiffrement impossible".to_string() })?;
+
+    let mut output = nonce.to_vec();
+    output.append(&mut sealed);
+    Ok(output)
+}
+
+fn open_bytes(key: &LessSafeKey, sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(decryption_error());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| decryption_error())?;
+
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = key.open_in_place(nonce, Aad::empty(), &mut buffer)
+        .map_err(|_| decryption_error())?;
+    Ok(plaintext.to_vec())
+}
+
+/// Le contenu stocké est-il scellé ?
+pub fn is_sealed(content: &str) -> bool {
+    content.starts_with(SEALED_PREFIX)
+}
+
+/// Clé de données d'un message, en clair (jamais persistée telle quelle)
+pub struct DataKey {
+    key: LessSafeKey,
+    rng: SystemRandom,
+    /// Clé maîtresse qui l'enveloppe
+    pub key_id: String,
+    /// Clé enveloppée, à stocker dans `messages.wrapped_key`
+    pub wrapped: Vec<u8>,
+}
+
+impl DataKey {
+    /// Scelle un contenu (`enc:v1:...`)
+    pub fn seal(&self, content: &str) -> Result<String> {
+        let sealed = seal_bytes(&self.key, &self.rng, content.as_bytes())?;
+        Ok(format!("{}{}", SEALED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    /// Ouvre un contenu scellé ; un contenu en clair est retourné tel quel
+    pub fn open(&self, content: &str) -> Result<String> {
+        let Some(encoded) = content.strip_prefix(SEALED_PREFIX) else {
+            return Ok(content.to_string());
+        };
+        let sealed = STANDARD.decode(encoded).map_err(|_| decryption_error())?;
+        String::from_utf8(open_bytes(&self.key, &sealed)?).map_err(|_| decryption_error())
+    }
+}
+
+/// Clés maîtresses du serveur
+pub struct MessageCipher {
+    active_key_id: String,
+    keys: HashMap<String, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+impl MessageCipher {
+    /// Clés de la configuration ; `None` si aucune clé active n'est configurée
+    pub fn from_config(config: &EncryptionAtRestConfig) -> Result<Option<Self>> {
+        let Some(active_key_id) = config.active_key_id.clone() else {
+            return Ok(None);
+        };
+
+        let mut keys = HashMap::new();
+        for (key_id, encoded) in &config.keys {
+            let bytes = STANDARD.decode(encoded.trim())
+                .map_err(|_| ChatError::configuration_error(&format!("Clé de chiffrement {} : base64 invalide", key_id)))?;
+            if bytes.len() != KEY_LEN {
+                return Err(ChatError::configuration_error(&format!("Clé de chiffrement {} : {} octets attendus", key_id, KEY_LEN)));
+            }
+            keys.insert(key_id.clone(), aead_key(&bytes)?);
+        }
+        if !keys.contains_key(&active_key_id) {
+            return Err(ChatError::configuration_error(&format!("Clé de chiffrement active inconnue: {}", active_key_id)));
+        }
+
+        Ok(Some(Self { active_key_id, keys, rng: SystemRandom::new() }))
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    fn master_key(&self, key_id: &str) -> Result<&LessSafeKey> {
+        self.keys.get(key_id)
+            .ok_or_else(|| ChatError::Internal { message: format!("Clé de chiffrement non configurée: {}", key_id) })
+    }
+
+    /// Nouvelle clé de données, enveloppée par la clé active
+    pub fn new_data_key(&self) -> Result<DataKey> {
+        let mut bytes = [0u8; KEY_LEN];
+        self.rng.fill(&mut bytes).map_err(|_| rng_error())?;
+
+        let wrapped = seal_bytes(self.master_key(&self.active_key_id)?, &self.rng, &bytes)?;
+        Ok(DataKey {
+            key: aead_key(&bytes)?,
+            rng: self.rng.clone(),
+            key_id: self.active_key_id.clone(),
+            wrapped,
+        })
+    }
+
+    /// Clé de données d'un message stocké
+    pub fn open_data_key(&self, key_id: &str, wrapped: &[u8]) -> Result<DataKey> {
+        let bytes = open_bytes(self.master_key(key_id)?, wrapped)?;
+        Ok(DataKey {
+            key: aead_key(&bytes)?,
+            rng: self.rng.clone(),
+            key_id: key_id.to_string(),
+            wrapped: wrapped.to_vec(),
+        })
+    }
+
+    /// Ouvre un contenu stocké ; en clair si le message n'est pas chiffré
+    pub fn open_content(&self, content: &str, key_id: Option<&str>, wrapped: Option<&[u8]>) -> Result<String> {
+        match (key_id, wrapped) {
+            (Some(key_id), Some(wrapped)) if is_sealed(content) => self.open_data_key(key_id, wrapped)?.open(content),
+            _ => Ok(content.to_string()),
+        }
+    }
+
+    /// Réenveloppe une clé de données avec la clé active (rotation)
+    pub fn rewrap(&self, key_id: &str, wrapped: &[u8]) -> Result<(String, Vec<u8>)> {
+        let bytes = open_bytes(self.master_key(key_id)?, wrapped)?;
+        let rewrapped = seal_bytes(self.master_key(&self.active_key_id)?, &self.rng, &bytes)?;
+        Ok((self.active_key_id.clone(), rewrapped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(active: &str, keys: &[(&str, u8)]) -> EncryptionAtRestConfig {
+        EncryptionAtRestConfig {
+            active_key_id: Some(active.to_string()),
+            keys: keys.iter()
+                .map(|(id, byte)| (id.to_string(), STANDARD.encode([*byte; KEY_LEN])))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_sealed_content_roundtrip() {
+        let cipher = MessageCipher::from_config(&config("k1", &[("k1", 7)])).unwrap().unwrap();
+        let data_key = cipher.new_data_key().unwrap();
+
+        let sealed = data_key.seal("Réunion à 14h").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("Réunion"));
+        assert_eq!(cipher.open_content(&sealed, Some("k1"), Some(&data_key.wrapped)).unwrap(), "Réunion à 14h");
+
+        // Contenu antérieur au chiffrement : lu tel quel
+        assert_eq!(cipher.open_content("en clair", None, None).unwrap(), "en clair");
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_messages_readable() {
+        let old = MessageCipher::from_config(&config("k1", &[("k1", 1)])).unwrap().unwrap();
+        let data_key = old.new_data_key().unwrap();
+        let sealed = data_key.seal("ancien").unwrap();
+
+        let rotated = MessageCipher::from_config(&config("k2", &[("k1", 1), ("k2", 2)])).unwrap().unwrap();
+        assert_eq!(rotated.open_content(&sealed, Some("k1"), Some(&data_key.wrapped)).unwrap(), "ancien");
+        assert_eq!(rotated.new_data_key().unwrap().key_id, "k2");
+
+        // Après réenveloppe, l'ancienne clé n'est plus nécessaire
+        let (key_id, wrapped) = rotated.rewrap("k1", &data_key.wrapped).unwrap();
+        let only_new = MessageCipher::from_config(&config("k2", &[("k2", 2)])).unwrap().unwrap();
+        assert_eq!(only_new.open_content(&sealed, Some(&key_id), Some(&wrapped)).unwrap(), "ancien");
+        assert!(only_new.open_content(&sealed, Some("k1"), Some(&data_key.wrapped)).is_err());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(MessageCipher::from_config(&EncryptionAtRestConfig::default()).unwrap().is_none());
+        assert!(MessageCipher::from_config(&config("absente", &[("k1", 1)])).is_err());
+
+        let mut short = config("k1", &[]);
+        short.keys.insert("k1".to_string(), STANDARD.encode([0u8; 16]));
+        assert!(MessageCipher::from_config(&short).is_err());
+    }
+}
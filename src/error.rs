@@ -85,14 +85,30 @@ pub enum ChatError {
         window: u64 
     },
     
+    /// Débit d'un salon dépassé, tous auteurs confondus
+    #[error("Limite de débit du salon {room_id} atteinte: {current}/{limit}, réessayer dans {retry_after}s")]
+    RoomRateLimitExceeded { room_id: String, current: u32, limit: u32, retry_after: u64 },
+    
     /// Quota utilisateur dépassé
     #[error("Quota {quota_type} dépassé: {used}/{limit}")]
     QuotaExceeded { quota_type: String, used: u64, limit: u64 },
     
+    /// Quota quotidien de messages atteint ; `resets_at` : timestamp Unix de la remise à zéro
+    #[error("Quota quotidien de messages atteint: {used}/{limit}, réinitialisé dans {retry_after}s")]
+    DailyQuotaExceeded { used: u64, limit: u64, resets_at: i64, retry_after: u64 },
+    
+    /// Envoi suspendu après des violations répétées du filtre de contenu
+    #[error("Envoi de messages suspendu après des violations répétées, réessayer dans {retry_after}s")]
+    UserMuted { retry_after: u64 },
+    
     /// Trop de connexions simultanées
     #[error("Trop de connexions simultanées: {current}/{max}")]
     TooManyConnections { current: u32, max: u32 },
     
+    /// Trop de nouvelles connexions depuis une IP, refusées au handshake
+    #[error("Trop de nouvelles connexions depuis {ip}: {limit} par {window}s, réessayer dans {retry_after}s")]
+    ConnectionRateExceeded { ip: String, limit: u32, window: u64, retry_after: u64 },
+    
     // ═══════════════════════════════════════════════════════════════════════
     // ERREURS RÉSEAU ET WEBSOCKET
     // ═══════════════════════════════════════════════════════════════════════
@@ -128,6 +144,10 @@ pub enum ChatError {
         source: sqlx::Error 
     },
     
+    /// Pool de connexions saturé : aucune connexion libre avant `acquire_timeout`
+    #[error("Pool de connexions saturé: {operation}")]
+    DatabasePoolExhausted { operation: String },
+    
     /// Ressource non trouvée
     #[error("{resource} non trouvé(e): {id}")]
     NotFound { resource: String, id: String },
@@ -168,6 +188,10 @@ pub enum ChatError {
     #[error("Edition impossible: {reason}")]
     EditForbidden { reason: String },
     
+    /// Délai de modification dépassé pour l'auteur
+    #[error("Délai dépassé pour {action} (fenêtre de {window_secs}s)")]
+    ModificationWindowExpired { action: String, window_secs: u64 },
+    
     // ═══════════════════════════════════════════════════════════════════════
     // ERREURS DE FICHIERS ET UPLOAD
     // ═══════════════════════════════════════════════════════════════════════
@@ -176,6 +200,18 @@ pub enum ChatError {
     #[error("Fichier trop volumineux: {size} bytes (max: {max_size})")]
     FileTooLarge { size: u64, max_size: u64 },
     
+    /// Trop de pièces jointes sur un message
+    #[error("Trop de pièces jointes: {count} (max: {max})")]
+    TooManyAttachments { count: usize, max: usize },
+    
+    /// Taille cumulée des pièces jointes d'un message dépassée
+    #[error("Pièces jointes trop volumineuses: {total_size} bytes au total (max: {max_total_size})")]
+    AttachmentsTooLarge { total_size: u64, max_total_size: u64 },
+    
+    /// Espace de stockage des pièces jointes de l'utilisateur épuisé
+    #[error("Quota de stockage dépassé: {used}/{quota} bytes utilisés, {requested} demandés")]
+    StorageQuotaExceeded { used: u64, requested: u64, quota: u64 },
+    
     /// Type de fichier non autorisé
     #[error("Type de fichier non autorisé: {mime_type}")]
     UnsupportedFileType { mime_type: String },
@@ -196,6 +232,10 @@ pub enum ChatError {
     #[error("Erreur configuration: {message}")]
     Configuration { message: String },
     
+    /// Configuration rejetée au chargement, avec tous ses problèmes
+    #[error("Configuration invalide ({} problème(s)): {}", .problems.len(), .problems.join("; "))]
+    InvalidConfiguration { problems: Vec<String> },
+    
     /// Service indisponible
     #[error("Service {service} indisponible: {reason}")]
     ServiceUnavailable { service: String, reason: String },
@@ -268,8 +308,13 @@ impl ChatError {
             | Self::OutOfRange { .. }
             | Self::MessageTooLong { .. }
             | Self::FileTooLarge { .. }
+            | Self::TooManyAttachments { .. }
+            | Self::AttachmentsTooLarge { .. }
             | Self::UnsupportedFileType { .. } => 400,
             
+            // 413 Payload Too Large
+            Self::StorageQuotaExceeded { .. } => 413,
+            
             // 401 Unauthorized  
             Self::InvalidToken { .. }
             | Self::InvalidCredentials
@@ -281,6 +326,8 @@ impl ChatError {
             | Self::AccountSuspended { .. }
             | Self::InsufficientPermissions { .. }
             | Self::EditForbidden { .. }
+            | Self::ModificationWindowExpired { .. }
+            | Self::UserMuted { .. }
             | Self::IpBlocked { .. } => 403,
             
             // 404 Not Found
@@ -301,19 +348,24 @@ impl ChatError {
             
             // 429 Too Many Requests
             Self::RateLimitExceeded { .. }
+            | Self::RoomRateLimitExceeded { .. }
+            | Self::ConnectionRateExceeded { .. }
             | Self::QuotaExceeded { .. }
+            | Self::DailyQuotaExceeded { .. }
             | Self::TooManyConnections { .. } => 429,
             
             // 500 Internal Server Error
             Self::Database { .. }
             | Self::Internal { .. }
             | Self::Configuration { .. }
+            | Self::InvalidConfiguration { .. }
             | Self::TransactionFailed { .. }
             | Self::UploadError { .. }
             | Self::Cache { .. } => 500,
             
             // 503 Service Unavailable
             Self::ServiceUnavailable { .. }
+            | Self::DatabasePoolExhausted { .. }
             | Self::ShutdownTimeout => 503,
             
             // 418 I'm a teapot (pour les tentatives d'injection)
@@ -340,6 +392,7 @@ impl ChatError {
         match self {
             // CRITICAL - Erreur système critique  
             Self::Database { .. }
+            | Self::DatabasePoolExhausted { .. }
             | Self::ServiceUnavailable { .. }
             | Self::ShutdownTimeout
             | Self::SuspiciousActivity { .. }
@@ -354,6 +407,8 @@ impl ChatError {
             | Self::OutOfRange { .. }
             | Self::MessageTooLong { .. }
             | Self::FileTooLarge { .. }
+            | Self::TooManyAttachments { .. }
+            | Self::AttachmentsTooLarge { .. }
             | Self::UnsupportedFileType { .. }
             | Self::TransactionFailed { .. }
             | Self::UploadError { .. }
@@ -366,13 +421,19 @@ impl ChatError {
             | Self::InsufficientPermissions { .. }
             | Self::MessageNotFound { .. }
             | Self::EditForbidden { .. }
+            | Self::ModificationWindowExpired { .. }
             | Self::Conflict { .. }
             | Self::ConnectionLimitReached
             | Self::SecurityValidationFailed { .. } => ErrorSeverity::Medium,
             
             // Gravité moyenne - Erreurs qui affectent l'utilisateur
             Self::RateLimitExceeded { .. }
+            | Self::RoomRateLimitExceeded { .. }
+            | Self::ConnectionRateExceeded { .. }
             | Self::QuotaExceeded { .. }
+            | Self::DailyQuotaExceeded { .. }
+            | Self::StorageQuotaExceeded { .. }
+            | Self::UserMuted { .. }
             | Self::TooManyConnections { .. }
             | Self::Unauthorized { .. }
             | Self::NotFound { .. } => ErrorSeverity::Low,
@@ -390,7 +451,20 @@ impl ChatError {
             | Self::ConnectionTimeout { .. }
             | Self::Cache { .. }
             | Self::Internal { .. }
-            | Self::Configuration { .. } => ErrorSeverity::Info,
+            | Self::Configuration { .. }
+            | Self::InvalidConfiguration { .. } => ErrorSeverity::Info,
+        }
+    }
+    
+    /// Délai (en secondes) avant de réessayer, pour les erreurs de limitation
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::RateLimitExceeded { window, .. } => Some(*window),
+            Self::RoomRateLimitExceeded { retry_after, .. } => Some(*retry_after),
+            Self::ConnectionRateExceeded { retry_after, .. } => Some(*retry_after),
+            Self::DailyQuotaExceeded { retry_after, .. } => Some(*retry_after),
+            Self::UserMuted { retry_after } => Some(*retry_after),
+            _ => None,
         }
     }
     
@@ -404,11 +478,16 @@ impl ChatError {
             Self::RateLimitExceeded { action, window, .. } => {
                 format!("Trop de requêtes pour {}, veuillez patienter {}s", action, window)
             },
+            Self::ConnectionRateExceeded { retry_after, .. } => {
+                format!("Trop de nouvelles connexions, veuillez patienter {}s", retry_after)
+            },
             
             // Messages génériques pour éviter la divulgation d'informations
             Self::Database { .. } => "Erreur temporaire, veuillez réessayer".to_string(),
+            Self::DatabasePoolExhausted { .. } => "Service surchargé, veuillez réessayer".to_string(),
             Self::Internal { .. } => "Erreur interne du serveur".to_string(),
-            Self::Configuration { .. } => "Service temporairement indisponible".to_string(),
+            Self::Configuration { .. }
+            | Self::InvalidConfiguration { .. } => "Service temporairement indisponible".to_string(),
             Self::InjectionAttempt => "Requête rejetée".to_string(),
             Self::SuspiciousActivity { .. } => "Activité inhabituelle détectée".to_string(),
             
@@ -476,10 +555,21 @@ impl ChatError {
     }
 
     /// Helper pour convertir sqlx::Error avec une meilleure gestion
+    ///
+    /// L'attente d'une connexion libre expirée devient `DatabasePoolExhausted`,
+    /// comptée pour `db_pool_exhausted_total`.
     pub fn from_sqlx_error(operation: &str, error: sqlx::Error) -> Self {
-        Self::Database {
-            operation: operation.to_string(),
-            source: error,
+        match error {
+            sqlx::Error::PoolTimedOut => {
+                crate::db_pool::record_pool_exhausted();
+                Self::DatabasePoolExhausted {
+                    operation: operation.to_string(),
+                }
+            }
+            error => Self::Database {
+                operation: operation.to_string(),
+                source: error,
+            },
         }
     }
     
@@ -538,7 +628,7 @@ impl fmt::Display for ErrorSeverity {
 /// Implémentations de conversion depuis des erreurs externes
 impl From<sqlx::Error> for ChatError {
     fn from(err: sqlx::Error) -> Self {
-        Self::database_error("query", err)
+        Self::from_sqlx_error("query", err)
     }
 }
 
@@ -608,6 +698,19 @@ mod tests {
         assert_eq!(db_error.public_message(), "Erreur temporaire, veuillez réessayer");
     }
     
+    #[test]
+    fn test_pool_timeout_maps_to_pool_exhausted() {
+        let before = crate::db_pool::pool_exhausted_total();
+        let error = ChatError::from_sqlx_error("get_room_members", sqlx::Error::PoolTimedOut);
+        assert!(matches!(&error, ChatError::DatabasePoolExhausted { operation } if operation == "get_room_members"));
+        assert!(crate::db_pool::pool_exhausted_total() > before);
+        assert_eq!(error.http_status(), 503);
+        assert_eq!(error.severity(), ErrorSeverity::High);
+        
+        let other = ChatError::from(sqlx::Error::RowNotFound);
+        assert!(matches!(other, ChatError::Database { .. }));
+    }
+    
     #[test]
     fn test_error_creation_helpers() {
         let error = ChatError::not_found("conversation", "room_123");
@@ -0,0 +1,275 @@
+//! Pont des événements vers NATS, pour les systèmes tiers
+//!
+//! Configuré (`[integrations.nats]`), le pont publie chaque événement diffusé
+//! aux clients sur un sujet propre à sa conversation, avec le même schéma que
+//! la trame WebSocket :
+//! - `{prefix}.room.{salon}` pour un salon (trame numérotée par `eventId`)
+//! - `{prefix}.dm.{conversation}` pour un message privé
+//!
+//! La diffusion aux clients ne l'attend jamais : les événements passent par une
+//! file bornée (`buffer_size`) vidée par une tâche de fond. Si NATS est
+//! injoignable, la tâche réessaie avec un délai croissant et, une fois la file
+//! pleine, les nouveaux événements sont abandonnés (`bridge_events_dropped_total`).
+//! Les événements à destinataires restreints ne sont pas publiés.
+
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use crate::config::NatsBridgeConfig;
+use crate::error::Result;
+
+/// Destination des événements publiés (NATS en production)
+pub trait BridgePublisher: Send + Sync + 'static {
+    fn publish<'a>(&'a self, subject: &'a str, payload: &'a [u8]) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Raison de l'abandon d'un événement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeDrop {
+    /// File pleine : NATS injoignable ou trop lent
+    BufferFull,
+    /// Tâche de publication arrêtée
+    Closed,
+}
+
+impl BridgeDrop {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BridgeDrop::BufferFull => "buffer_full",
+            BridgeDrop::Closed => "closed",
+        }
+    }
+}
+
+struct BridgeEvent {
+    subject: String,
+    payload: Vec<u8>,
+}
+
+/// File bornée vers le publieur, vidée en tâche de fond
+pub struct EventBridge {
+    subject_prefix: String,
+    sender: mpsc::Sender<BridgeEvent>,
+}
+
+impl EventBridge {
+    /// Lance la tâche de publication (à appeler dans un runtime Tokio)
+    pub fn spawn(publisher: Arc<dyn BridgePublisher>, config: &NatsBridgeConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.buffer_size.max(1));
+        tokio::spawn(run_publisher(publisher, receiver, config.reconnect_delay, config.max_reconnect_delay));
+        tracing::info!(prefix = %config.subject_prefix, buffer = %config.buffer_size, "🌉 Pont NATS démarré");
+
+        Self {
+            subject_prefix: config.subject_prefix.clone(),
+            sender,
+        }
+    }
+
+    pub fn room_subject(&self, room: impl Display) -> String {
+        format!("{}.room.{}", self.subject_prefix, room)
+    }
+
+    pub fn dm_subject(&self, conversation_id: i64) -> String {
+        format!("{}.dm.{}", self.subject_prefix, conversation_id)
+    }
+
+    /// Met l'événement en file sans attendre ; abandonné si la file est pleine
+    pub fn try_publish(&self, subject: String, frame: &Value) -> std::result::Result<(), BridgeDrop> {
+        let event = BridgeEvent { subject, payload: frame.to_string().into_bytes() };
+        self.sender.try_send(event).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => BridgeDrop::BufferFull,
+            mpsc::error::TrySendError::Closed(_) => BridgeDrop::Closed,
+        })
+    }
+}
+
+/// Publie les événements dans l'ordre ; un échec est réessayé jusqu'au succès
+async fn run_publisher(
+    publisher: Arc<dyn BridgePublisher>,
+    mut receiver: mpsc::Receiver<BridgeEvent>,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+) {
+    while let Some(event) = receiver.recv().await {
+        let mut delay = reconnect_delay;
+        while let Err(e) = publisher.publish(&event.subject, &event.payload).await {
+            tracing::warn!(subject = %event.subject, error = %e, retry_in_ms = %delay.as_millis(), "⚠️ Publication NATS échouée, nouvel essai");
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(max_reconnect_delay.max(reconnect_delay));
+        }
+    }
+    tracing::info!("🌉 Pont NATS arrêté");
+}
+
+/// Pont choisi par `[integrations.nats]` (`None` : non configuré)
+pub fn event_bridge_from_config(config: Option<&NatsBridgeConfig>) -> Option<EventBridge> {
+    let config = config?;
+    nats_publisher(config).map(|publisher| EventBridge::spawn(publisher, config))
+}
+
+#[cfg(feature = "nats-bridge")]
+fn nats_publisher(config: &NatsBridgeConfig) -> Option<Arc<dyn BridgePublisher>> {
+    Some(Arc::new(NatsPublisher::new(&config.url)))
+}
+
+#[cfg(not(feature = "nats-bridge"))]
+fn nats_publisher(_config: &NatsBridgeConfig) -> Option<Arc<dyn BridgePublisher>> {
+    tracing::warn!("⚠️ Feature nats-bridge absente, événements non publiés sur NATS");
+    None
+}
+
+/// Publieur en mémoire qui conserve chaque événement, pour les tests
+#[derive(Debug, Default)]
+pub struct RecordingPublisher {
+    published: std::sync::Mutex<Vec<(String, Value)>>,
+}
+
+impl RecordingPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Événements publiés : (sujet, trame)
+    pub fn published(&self) -> Vec<(String, Value)> {
+        self.published.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl BridgePublisher for RecordingPublisher {
+    fn publish<'a>(&'a self, subject: &'a str, payload: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let frame = serde_json::from_slice(payload).unwrap_or(Value::Null);
+            self.published.lock().unwrap_or_else(|e| e.into_inner()).push((subject.to_string(), frame));
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "nats-bridge")]
+pub use nats::NatsPublisher;
+
+#[cfg(feature = "nats-bridge")]
+mod nats {
+    use super::*;
+    use tokio::sync::Mutex;
+    use crate::error::ChatError;
+
+    /// Client NATS ouvert à la première publication, rouvert après un échec
+    pub struct NatsPublisher {
+        url: String,
+        client: Mutex<Option<async_nats::Client>>,
+    }
+
+    impl NatsPublisher {
+        pub fn new(url: &str) -> Self {
+            Self { url: url.to_string(), client: Mutex::new(None) }
+        }
+
+        async fn client(&self) -> Result<async_nats::Client> {
+            let mut client = self.client.lock().await;
+            if let Some(client) = client.as_ref() {
+                return Ok(client.clone());
+            }
+            let connected = async_nats::connect(self.url.as_str()).await
+                .map_err(|e| ChatError::NetworkError { message: format!("Connexion NATS impossible: {}", e) })?;
+            tracing::info!(url = %self.url, "🔌 Connecté à NATS");
+            *client = Some(connected.clone());
+            Ok(connected)
+        }
+    }
+
+    impl BridgePublisher for NatsPublisher {
+        fn publish<'a>(&'a self, subject: &'a str, payload: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                let client = self.client().await?;
+                let sent = match client.publish(subject.to_string(), payload.to_vec().into()).await {
+                    Ok(()) => client.flush().await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = sent {
+                    // Reconnexion complète au prochain essai
+                    *self.client.lock().await = None;
+                    return Err(ChatError::NetworkError { message: format!("Publication NATS impossible: {}", e) });
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::error::ChatError;
+
+    fn config(buffer_size: usize) -> NatsBridgeConfig {
+        NatsBridgeConfig {
+            buffer_size,
+            reconnect_delay: Duration::from_millis(5),
+            max_reconnect_delay: Duration::from_millis(20),
+            ..NatsBridgeConfig::default()
+        }
+    }
+
+    /// Échoue `failures` fois avant de transmettre au publieur en mémoire
+    struct FlakyPublisher {
+        failures: AtomicUsize,
+        inner: RecordingPublisher,
+    }
+
+    impl BridgePublisher for FlakyPublisher {
+        fn publish<'a>(&'a self, subject: &'a str, payload: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+            if self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                return Box::pin(async { Err(ChatError::NetworkError { message: "NATS injoignable".to_string() }) });
+            }
+            self.inner.publish(subject, payload)
+        }
+    }
+
+    async fn wait_for(publisher: &RecordingPublisher, count: usize) {
+        for _ in 0..100 {
+            if publisher.published().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_are_published_in_order_on_their_subject() {
+        let publisher = Arc::new(RecordingPublisher::new());
+        let bridge = EventBridge::spawn(publisher.clone(), &config(16));
+
+        bridge.try_publish(bridge.room_subject(3), &json!({ "type": "room_message", "eventId": 1 })).unwrap();
+        bridge.try_publish(bridge.dm_subject(9), &json!({ "type": "dm_message" })).unwrap();
+        wait_for(&publisher, 2).await;
+
+        let published = publisher.published();
+        assert_eq!(published[0].0, "chat.room.3");
+        assert_eq!(published[0].1["eventId"], 1);
+        assert_eq!(published[1].0, "chat.dm.9");
+    }
+
+    #[tokio::test]
+    async fn test_full_buffer_drops_while_nats_is_down() {
+        let publisher = Arc::new(FlakyPublisher { failures: AtomicUsize::new(3), inner: RecordingPublisher::new() });
+        let bridge = EventBridge::spawn(publisher.clone(), &config(2));
+
+        // La tâche de fond n'a pas encore tourné : seules 2 places
+        let results: Vec<_> = (0..5)
+            .map(|i| bridge.try_publish(bridge.room_subject(1), &json!({ "n": i })))
+            .collect();
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 2);
+        assert_eq!(results[4], Err(BridgeDrop::BufferFull));
+
+        // Après les échecs, les événements retenus partent dans l'ordre
+        wait_for(&publisher.inner, 2).await;
+        let published: Vec<Value> = publisher.inner.published().into_iter().map(|(_, frame)| frame["n"].clone()).collect();
+        assert_eq!(published, vec![json!(0), json!(1)]);
+    }
+}
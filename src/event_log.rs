@@ -0,0 +1,364 @@
+//! Journal des événements récents, pour la reprise après reconnexion
+//!
+//! Chaque événement diffusé dans un salon reçoit un `eventId` croissant propre
+//! au salon, sans trou : il sert à la fois de numéro de séquence (ordre) et de
+//! point de reprise. Un client qui se reconnecte présente le dernier `eventId`
+//! reçu par salon et obtient les événements manqués, sans recharger
+//! l'historique complet :
+//! - Rétention courte : `replay.max_events_per_room` derniers événements,
+//!   `replay.retention` au plus
+//! - Écart plus ancien que la rétention (ou journal réinitialisé) : la reprise
+//!   est marquée incomplète et le client recharge l'historique
+//! - Un événement à destinataires restreints n'est rejoué qu'à eux
+//!
+//! `MemoryEventLog` garde le journal dans le processus (un seul nœud) ;
+//! `RedisEventLog` (feature `redis-cache`) le partage entre nœuds.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use crate::config::{ReplayBackend, ReplayConfig, ServerConfig};
+use crate::error::Result;
+
+/// Événement retenu pour la reprise
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    /// Trame diffusée, sans `eventId`
+    pub payload: Value,
+    /// Destinataires d'un événement restreint (`None` = tous les membres)
+    pub recipients: Option<Vec<i64>>,
+}
+
+impl LoggedEvent {
+    pub fn visible_to(&self, user_id: i64) -> bool {
+        self.recipients.as_ref().map_or(true, |recipients| recipients.contains(&user_id))
+    }
+
+    /// Trame telle qu'envoyée au client
+    pub fn to_frame(&self) -> Value {
+        stamp_event(&self.payload, self.id)
+    }
+}
+
+/// Ajoute `eventId` à une trame `{"type", "data"}`
+pub fn stamp_event(payload: &Value, event_id: u64) -> Value {
+    let mut frame = payload.clone();
+    if let Some(object) = frame.as_object_mut() {
+        object.insert("eventId".to_string(), Value::from(event_id));
+    }
+    frame
+}
+
+/// Événements manqués depuis un `eventId`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replay {
+    pub events: Vec<LoggedEvent>,
+    /// `false` : des événements ont été perdus, recharger l'historique
+    pub complete: bool,
+    /// Dernier `eventId` attribué dans le salon
+    pub last_event_id: u64,
+}
+
+/// Tranche d'un journal postérieure à `after`
+///
+/// `retained` : événements encore retenus, `last_id` : dernier identifiant
+/// attribué. La reprise est complète si rien n'a été perdu entre `after` et
+/// le premier événement retenu.
+pub fn replay_window(mut retained: Vec<LoggedEvent>, after: u64, last_id: u64, oldest_at: DateTime<Utc>) -> Replay {
+    retained.retain(|event| event.id > after && event.at >= oldest_at);
+    retained.sort_by_key(|event| event.id);
+
+    let complete = if after > last_id {
+        false
+    } else if after == last_id {
+        true
+    } else {
+        retained.first().is_some_and(|first| first.id == after + 1)
+    };
+
+    Replay { events: retained, complete, last_event_id: last_id }
+}
+
+/// Journal des événements par salon
+pub trait EventLog: Send + Sync {
+    /// Enregistre un événement et retourne son `eventId`
+    fn append<'a>(&'a self, room_id: i64, payload: &'a Value, recipients: Option<&'a [i64]>) -> BoxFuture<'a, Result<u64>>;
+
+    /// Événements postérieurs à `after` (tous destinataires confondus)
+    fn since<'a>(&'a self, room_id: i64, after: u64) -> BoxFuture<'a, Result<Replay>>;
+}
+
+/// Journal choisi par la section `[replay]`
+///
+/// Redis inutilisable (URL invalide, feature absente) : repli sur la mémoire.
+pub fn event_log_from_config(config: &ServerConfig) -> Arc<dyn EventLog> {
+    match config.replay.backend {
+        #[cfg(feature = "redis-cache")]
+        ReplayBackend::Redis => match RedisEventLog::new(&config.cache, &config.replay) {
+            Ok(log) => return Arc::new(log),
+            Err(e) => tracing::warn!(error = %e, "⚠️ Journal de reprise Redis indisponible, repli en mémoire"),
+        },
+        #[cfg(not(feature = "redis-cache"))]
+        ReplayBackend::Redis => tracing::warn!("⚠️ Feature redis-cache absente, journal de reprise en mémoire"),
+        ReplayBackend::Memory => {}
+    }
+    Arc::new(MemoryEventLog::from_config(&config.replay))
+}
+
+/// Borne basse de rétention à l'instant présent
+fn retention_start(retention: Duration) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::from_std(retention).unwrap_or(chrono::Duration::zero())
+}
+
+// ================================================================
+// JOURNAL EN MÉMOIRE
+// ================================================================
+
+#[derive(Debug, Default)]
+struct MemoryStream {
+    last_id: u64,
+    events: VecDeque<LoggedEvent>,
+}
+
+/// Journal local au processus
+#[derive(Debug)]
+pub struct MemoryEventLog {
+    streams: Mutex<HashMap<i64, MemoryStream>>,
+    max_events: usize,
+    retention: Duration,
+}
+
+impl MemoryEventLog {
+    pub fn new(max_events: usize, retention: Duration) -> Self {
+        Self { streams: Mutex::new(HashMap::new()), max_events: max_events.max(1), retention }
+    }
+
+    pub fn from_config(config: &ReplayConfig) -> Self {
+        Self::new(config.max_events_per_room, config.retention)
+    }
+}
+
+impl EventLog for MemoryEventLog {
+    fn append<'a>(&'a self, room_id: i64, payload: &'a Value, recipients: Option<&'a [i64]>) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let oldest_at = retention_start(self.retention);
+            let mut streams = self.streams.lock().await;
+            let stream = streams.entry(room_id).or_default();
+
+            stream.last_id += 1;
+            stream.events.push_back(LoggedEvent {
+                id: stream.last_id,
+                at: Utc::now(),
+                payload: payload.clone(),
+                recipients: recipients.map(<[i64]>::to_vec),
+            });
+            while stream.events.len() > self.max_events
+                || stream.events.front().is_some_and(|event| event.at < oldest_at)
+            {
+                stream.events.pop_front();
+            }
+
+            Ok(stream.last_id)
+        })
+    }
+
+    fn since<'a>(&'a self, room_id: i64, after: u64) -> BoxFuture<'a, Result<Replay>> {
+        Box::pin(async move {
+            let streams = self.streams.lock().await;
+            let (retained, last_id) = streams.get(&room_id)
+                .map(|stream| (stream.events.iter().filter(|event| event.id > after).cloned().collect(), stream.last_id))
+                .unwrap_or_default();
+
+            Ok(replay_window(retained, after, last_id, retention_start(self.retention)))
+        })
+    }
+}
+
+// ================================================================
+// JOURNAL REDIS
+// ================================================================
+
+#[cfg(feature = "redis-cache")]
+pub use redis_log::RedisEventLog;
+
+#[cfg(feature = "redis-cache")]
+mod redis_log {
+    use super::*;
+    use redis::AsyncCommands;
+    use tokio::sync::OnceCell;
+    use crate::config::CacheConfig;
+    use crate::error::ChatError;
+
+    /// Journal partagé entre nœuds : séquence `INCR`, événements dans un
+    /// ensemble trié par `eventId`
+    pub struct RedisEventLog {
+        client: redis::Client,
+        connection: OnceCell<redis::aio::ConnectionManager>,
+        key_prefix: String,
+        max_events: usize,
+        retention: Duration,
+    }
+
+    fn redis_error(operation: &str, e: redis::RedisError) -> ChatError {
+        tracing::warn!(operation = %operation, error = %e, "⚠️ Erreur Redis du journal d'événements");
+        ChatError::Cache { operation: operation.to_string() }
+    }
+
+    impl RedisEventLog {
+        /// La connexion est ouverte au premier usage
+        pub fn new(cache: &CacheConfig, replay: &ReplayConfig) -> Result<Self> {
+            let client = redis::Client::open(cache.url.as_str())
+                .map_err(|e| ChatError::configuration_error(&format!("URL Redis invalide: {}", e)))?;
+            Ok(Self {
+                client,
+                connection: OnceCell::new(),
+                key_prefix: cache.key_prefix.clone(),
+                max_events: replay.max_events_per_room.max(1),
+                retention: replay.retention,
+            })
+        }
+
+        async fn connection(&self) -> Result<redis::aio::ConnectionManager> {
+            self.connection
+                .get_or_try_init(|| redis::aio::ConnectionManager::new(self.client.clone()))
+                .await
+                .cloned()
+                .map_err(|e| redis_error("connect", e))
+        }
+
+        fn keys(&self, room_id: i64) -> (String, String) {
+            (
+                format!("{}replay:{}:seq", self.key_prefix, room_id),
+                format!("{}replay:{}:events", self.key_prefix, room_id),
+            )
+        }
+    }
+
+    impl EventLog for RedisEventLog {
+        fn append<'a>(&'a self, room_id: i64, payload: &'a Value, recipients: Option<&'a [i64]>) -> BoxFuture<'a, Result<u64>> {
+            Box::pin(async move {
+                let mut conn = self.connection().await?;
+                let (seq_key, events_key) = self.keys(room_id);
+
+                let id: u64 = conn.incr(&seq_key, 1).await
+                    .map_err(|e| redis_error("replay_incr", e))?;
+                let event = LoggedEvent {
+                    id,
+                    at: Utc::now(),
+                    payload: payload.clone(),
+                    recipients: recipients.map(<[i64]>::to_vec),
+                };
+                let member = serde_json::to_string(&event)
+                    .map_err(ChatError::from_json_error)?;
+
+                redis::pipe()
+                    .atomic()
+                    .zadd(&events_key, member, id).ignore()
+                    .zremrangebyrank(&events_key, 0, -(self.max_events as isize) - 1).ignore()
+                    .expire(&events_key, self.retention.as_secs().max(1) as i64).ignore()
+                    .query_async::<_, ()>(&mut conn)
+                    .await
+                    .map_err(|e| redis_error("replay_append", e))?;
+
+                Ok(id)
+            })
+        }
+
+        fn since<'a>(&'a self, room_id: i64, after: u64) -> BoxFuture<'a, Result<Replay>> {
+            Box::pin(async move {
+                let mut conn = self.connection().await?;
+                let (seq_key, events_key) = self.keys(room_id);
+
+                let (last_id, members): (Option<u64>, Vec<String>) = redis::pipe()
+                    .get(&seq_key)
+                    .zrangebyscore(&events_key, format!("({}", after), "+inf")
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| redis_error("replay_since", e))?;
+
+                let retained = members.iter()
+                    .filter_map(|member| serde_json::from_str::<LoggedEvent>(member).ok())
+                    .collect();
+
+                Ok(replay_window(retained, after, last_id.unwrap_or(0), retention_start(self.retention)))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(n: u64) -> Value {
+        json!({ "type": "room_message", "data": { "n": n } })
+    }
+
+    #[tokio::test]
+    async fn test_ids_are_monotonic_per_room() {
+        let log = MemoryEventLog::new(10, Duration::from_secs(60));
+
+        assert_eq!(log.append(1, &event(1), None).await.unwrap(), 1);
+        assert_eq!(log.append(1, &event(2), None).await.unwrap(), 2);
+        assert_eq!(log.append(2, &event(1), None).await.unwrap(), 1);
+
+        let replay = log.since(1, 0).await.unwrap();
+        assert!(replay.complete);
+        assert_eq!(replay.last_event_id, 2);
+        assert_eq!(replay.events[1].to_frame()["eventId"], 2);
+        assert_eq!(replay.events[1].to_frame()["data"]["n"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_resume_returns_only_the_gap() {
+        let log = MemoryEventLog::new(10, Duration::from_secs(60));
+        for n in 1..=5 {
+            log.append(1, &event(n), None).await.unwrap();
+        }
+
+        let replay = log.since(1, 3).await.unwrap();
+        assert!(replay.complete);
+        assert_eq!(replay.events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4, 5]);
+
+        // Client à jour
+        let replay = log.since(1, 5).await.unwrap();
+        assert!(replay.complete && replay.events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gap_beyond_retention_is_incomplete() {
+        let log = MemoryEventLog::new(3, Duration::from_secs(60));
+        for n in 1..=6 {
+            log.append(1, &event(n), None).await.unwrap();
+        }
+
+        let replay = log.since(1, 1).await.unwrap();
+        assert!(!replay.complete);
+        assert_eq!(replay.events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4, 5, 6]);
+
+        // Journal réinitialisé (redémarrage) : identifiant inconnu
+        assert!(!log.since(1, 42).await.unwrap().complete);
+        assert!(!log.since(9, 3).await.unwrap().complete);
+    }
+
+    #[tokio::test]
+    async fn test_restricted_events_are_replayed_to_recipients_only() {
+        let log = MemoryEventLog::new(10, Duration::from_secs(60));
+        log.append(1, &event(1), Some(&[7, 8])).await.unwrap();
+        log.append(1, &event(2), None).await.unwrap();
+
+        let replay = log.since(1, 0).await.unwrap();
+        assert!(replay.events[0].visible_to(7));
+        assert!(!replay.events[0].visible_to(9));
+        assert!(replay.events[1].visible_to(9));
+    }
+}
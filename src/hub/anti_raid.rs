@@ -0,0 +1,377 @@
+//! Mode anti-raid
+//!
+//! Activé par un modérateur sur un salon, ou par un administrateur sur tous
+//! les salons, pour la durée d'un raid (`[anti_raid]`) :
+//! - Les messages des comptes trop récents (`min_account_age`) ou mal notés
+//!   (`min_reputation`, score de `reputation`) sont retenus pour examen ou
+//!   refusés (`action`)
+//! - Les jointures de chaque salon sont limitées à `joins_per_minute`
+//! - Les modérateurs du salon n'y sont jamais soumis
+//! - Levée automatique après `auto_disable_after` (tâche de maintenance)
+//! - Chaque activation et levée est annoncée par une trame `system_message`
+//!   et journalisée dans l'audit
+//!
+//! L'état est gardé en mémoire, comme le mode lent automatique.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use sqlx::{query, Row};
+use serde_json::{json, Value};
+use crate::config::{AntiRaidConfig, RaidAction};
+use crate::hub::common::{is_global_admin, ChatHub};
+use crate::hub::channels::{broadcast_to_room_members, is_moderator_role};
+use crate::hub::reputation::refresh_reputation;
+use crate::error::{ChatError, Result};
+
+/// Durée d'activation maximale qu'un modérateur peut demander
+pub const MAX_ANTI_RAID_DURATION: Duration = Duration::from_secs(86400);
+
+/// Fenêtre du décompte des jointures
+const JOIN_WINDOW: Duration = Duration::from_secs(60);
+
+// ================================================================
+// ÉTAT
+// ================================================================
+
+/// Portée d'une activation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RaidScope {
+    /// Tous les salons
+    Global,
+    Room(i64),
+}
+
+impl RaidScope {
+    pub fn room_id(&self) -> Option<i64> {
+        match self {
+            Self::Global => None,
+            Self::Room(room_id) => Some(*room_id),
+        }
+    }
+}
+
+/// Activations en cours (avec leur fin) et jointures récentes des salons protégés
+#[derive(Debug, Default)]
+pub struct AntiRaidTracker {
+    active: HashMap<RaidScope, Instant>,
+    joins: HashMap<i64, VecDeque<Instant>>,
+}
+
+impl AntiRaidTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Le salon est-il protégé (activation propre ou globale) ?
+    pub fn is_active(&self, room_id: i64, now: Instant) -> bool {
+        [RaidScope::Global, RaidScope::Room(room_id)].iter()
+            .any(|scope| self.active.get(scope).is_some_and(|until| *until > now))
+    }
+
+    /// Active (ou prolonge) le mode jusqu'à `until`
+    pub fn activate(&mut self, scope: RaidScope, until: Instant) {
+        self.active.insert(scope, until);
+    }
+
+    /// Lève le mode ; `false` s'il n'était pas actif
+    pub fn deactivate(&mut self, scope: RaidScope) -> bool {
+        if let Some(room_id) = scope.room_id() {
+            self.joins.remove(&room_id);
+        }
+        self.active.remove(&scope).is_some()
+    }
+
+    /// Lève les activations arrivées à échéance ; retourne leurs portées
+    pub fn expire(&mut self, now: Instant) -> Vec<RaidScope> {
+        let expired: Vec<RaidScope> = self.active.iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(scope, _)| *scope)
+            .collect();
+
+        for scope in &expired {
+            self.deactivate(*scope);
+        }
+        if self.active.is_empty() {
+            self.joins.clear();
+        }
+        expired
+    }
+
+    /// Compte une jointure dans un salon protégé, au plus `per_minute` par minute
+    pub fn admit_join(&mut self, room_id: i64, per_minute: u32, now: Instant) -> Result<()> {
+        if per_minute == 0 || !self.is_active(room_id, now) {
+            return Ok(());
+        }
+
+        let recent = self.joins.entry(room_id).or_default();
+        while recent.front().is_some_and(|joined| now.duration_since(*joined) >= JOIN_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() as u32 >= per_minute {
+            let oldest = recent.front().copied().unwrap_or(now);
+            return Err(ChatError::RateLimitExceeded {
+                action: "anti_raid_join".to_string(),
+                current: recent.len() as u32,
+                limit: per_minute,
+                window: (JOIN_WINDOW - now.duration_since(oldest)).as_secs().max(1),
+            });
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+}
+
+// ================================================================
+// COMPTES RESTREINTS
+// ================================================================
+
+/// Raison de la restriction d'un compte pendant le mode anti-raid, s'il est restreint
+pub fn restriction_reason(config: &AntiRaidConfig, account_age: Duration, reputation: i32) -> Option<String> {
+    if account_age < config.min_account_age {
+        return Some(format!(
+            "Mode anti-raid : compte créé il y a moins de {} h",
+            config.min_account_age.as_secs().div_ceil(3600)
+        ));
+    }
+    if reputation < config.min_reputation {
+        return Some(format!("Mode anti-raid : réputation {} inférieure à {}", reputation, config.min_reputation));
+    }
+    None
+}
+
+/// Vérifie l'auteur d'un message d'un salon protégé (hors modérateurs)
+///
+/// Retourne la raison de retenue (`action = "hold"`), une erreur
+/// (`action = "block"`), ou `None` si le mode est inactif ou le compte libre.
+pub async fn check_raid_message(hub: &ChatHub, room_id: i64, author_id: i64) -> Result<Option<String>> {
+    if !hub.anti_raid.lock().await.is_active(room_id, Instant::now()) {
+        return Ok(None);
+    }
+
+    let config = &hub.config.anti_raid;
+    let reputation = refresh_reputation(hub, author_id).await?;
+    let Some(reason) = restriction_reason(config, reputation.account_age, reputation.score) else {
+        return Ok(None);
+    };
+
+    tracing::warn!(room_id = %room_id, author_id = %author_id, action = ?config.action, reason = %reason, "🛡️ Message d'un compte restreint par le mode anti-raid");
+    hub.audit_sink.record(&hub.db, "anti_raid_message_restricted", Some(author_id), json!({
+        "room_id": room_id,
+        "action": config.action,
+        "reason": reason
+    })).await?;
+
+    match config.action {
+        RaidAction::Hold => Ok(Some(reason)),
+        RaidAction::Block => Err(ChatError::InsufficientPermissions {
+            action: "anti_raid".to_string(),
+            conversation_id: room_id.to_string(),
+        }),
+    }
+}
+
+/// Refuse le message d'un invité dans un salon protégé
+///
+/// Sans compte ni réputation, un invité est toujours restreint ; son message
+/// ne peut pas être retenu (`guest_messages` n'a pas d'examen).
+pub async fn check_raid_guest_message(hub: &ChatHub, room_id: i64, guest_id: i32) -> Result<()> {
+    if !hub.anti_raid.lock().await.is_active(room_id, Instant::now()) {
+        return Ok(());
+    }
+
+    tracing::warn!(room_id = %room_id, guest_id = %guest_id, "🛡️ Message d'invité refusé par le mode anti-raid");
+    Err(ChatError::InsufficientPermissions {
+        action: "anti_raid".to_string(),
+        conversation_id: room_id.to_string(),
+    })
+}
+
+/// Limite les jointures d'un salon protégé
+pub async fn check_raid_join(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<()> {
+    let admitted = hub.anti_raid.lock().await
+        .admit_join(room_id, hub.config.anti_raid.joins_per_minute, Instant::now());
+
+    if let Err(e) = &admitted {
+        tracing::warn!(room_id = %room_id, user_id = %user_id, error = %e, "🛡️ Jointure freinée par le mode anti-raid");
+    }
+    admitted
+}
+
+// ================================================================
+// ANNONCES
+// ================================================================
+
+/// Trame `system_message` d'activation ou de levée
+pub fn anti_raid_frame(scope: RaidScope, duration: Option<Duration>, automatic: bool) -> Value {
+    let content = match duration {
+        Some(duration) => format!(
+            "🛡️ Mode anti-raid activé pour {} min : messages des nouveaux comptes restreints, jointures limitées",
+            duration.as_secs().div_ceil(60)
+        ),
+        None => "✅ Mode anti-raid désactivé".to_string(),
+    };
+
+    json!({
+        "type": "system_message",
+        "data": {
+            "roomId": scope.room_id(),
+            "event": "anti_raid",
+            "enabled": duration.is_some(),
+            "durationSeconds": duration.map(|duration| duration.as_secs()),
+            "automatic": automatic,
+            "content": content
+        }
+    })
+}
+
+/// Annonce une activation ou une levée aux membres du salon, ou à tous les connectés
+pub async fn announce_anti_raid(hub: &ChatHub, scope: RaidScope, duration: Option<Duration>, automatic: bool) -> Result<()> {
+    let frame = anti_raid_frame(scope, duration, automatic);
+    match scope {
+        RaidScope::Room(room_id) => broadcast_to_room_members(hub, room_id, &frame).await,
+        RaidScope::Global => {
+            let payload = frame.to_string();
+            for client in hub.clients.values().await {
+                client.send_text(&payload);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Lève les activations arrivées à échéance (tâche de maintenance)
+pub async fn expire_anti_raid(hub: &ChatHub) -> usize {
+    let expired = hub.anti_raid.lock().await.expire(Instant::now());
+
+    for scope in &expired {
+        tracing::info!(room_id = ?scope.room_id(), "🛡️ Levée automatique du mode anti-raid");
+        if let Err(e) = hub.audit_sink.record(&hub.db, "anti_raid_changed", None, json!({
+            "room_id": scope.room_id(),
+            "enabled": false,
+            "automatic": true
+        })).await {
+            tracing::warn!(error = %e, "⚠️ Audit de la levée du mode anti-raid impossible");
+        }
+        if let Err(e) = announce_anti_raid(hub, *scope, None, true).await {
+            tracing::warn!(room_id = ?scope.room_id(), error = %e, "⚠️ Annonce de levée du mode anti-raid impossible");
+        }
+    }
+    expired.len()
+}
+
+// ================================================================
+// ACTIVATION
+// ================================================================
+
+/// Active ou lève le mode anti-raid
+///
+/// Un salon : modérateurs du salon ; global : administrateurs. `duration`
+/// remplace `anti_raid.auto_disable_after`.
+pub async fn set_anti_raid(hub: &ChatHub, scope: RaidScope, moderator_id: i64, enabled: bool, duration: Option<Duration>) -> Result<()> {
+    tracing::info!(room_id = ?scope.room_id(), moderator_id = %moderator_id, enabled = %enabled, "🛡️ Réglage du mode anti-raid");
+
+    let duration = duration.unwrap_or(hub.config.anti_raid.auto_disable_after);
+    if enabled && (duration.is_zero() || duration > MAX_ANTI_RAID_DURATION) {
+        return Err(ChatError::configuration_error("Durée du mode anti-raid invalide (1 s à 24 h)"));
+    }
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let authorized: bool = match scope {
+        RaidScope::Room(room_id) => query("
+            SELECT role FROM conversation_members
+            WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+        ")
+        .bind(room_id)
+        .bind(moderator_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+        .is_some_and(|row| is_moderator_role(row.get::<&str, _>("role"))),
+        RaidScope::Global => is_global_admin(&mut *tx, moderator_id).await?,
+    };
+
+    if !authorized {
+        return Err(ChatError::unauthorized("set_anti_raid"));
+    }
+
+    hub.audit_sink.record(&mut *tx, "anti_raid_changed", Some(moderator_id), json!({
+        "room_id": scope.room_id(),
+        "enabled": enabled,
+        "duration_seconds": enabled.then(|| duration.as_secs())
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    {
+        let mut tracker = hub.anti_raid.lock().await;
+        if enabled {
+            tracker.activate(scope, Instant::now() + duration);
+        } else if !tracker.deactivate(scope) {
+            return Ok(());
+        }
+    }
+
+    announce_anti_raid(hub, scope, enabled.then_some(duration), false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activation_expires_and_global_covers_rooms() {
+        let mut tracker = AntiRaidTracker::new();
+        let start = Instant::now();
+
+        tracker.activate(RaidScope::Room(1), start + Duration::from_secs(60));
+        assert!(tracker.is_active(1, start));
+        assert!(!tracker.is_active(2, start));
+
+        tracker.activate(RaidScope::Global, start + Duration::from_secs(120));
+        assert!(tracker.is_active(2, start));
+
+        assert!(tracker.expire(start + Duration::from_secs(59)).is_empty());
+        assert_eq!(tracker.expire(start + Duration::from_secs(60)), vec![RaidScope::Room(1)]);
+        assert!(tracker.is_active(1, start + Duration::from_secs(60)));
+        assert_eq!(tracker.expire(start + Duration::from_secs(120)), vec![RaidScope::Global]);
+        assert!(!tracker.is_active(1, start + Duration::from_secs(120)));
+        assert!(!tracker.deactivate(RaidScope::Global));
+    }
+
+    #[test]
+    fn test_joins_throttled_only_while_active() {
+        let mut tracker = AntiRaidTracker::new();
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            assert!(tracker.admit_join(1, 3, start).is_ok());
+        }
+
+        tracker.activate(RaidScope::Room(1), start + Duration::from_secs(600));
+        for _ in 0..3 {
+            tracker.admit_join(1, 3, start).unwrap();
+        }
+        let refused = tracker.admit_join(1, 3, start + Duration::from_secs(20));
+        assert!(matches!(refused, Err(ChatError::RateLimitExceeded { window: 40, .. })));
+        assert!(tracker.admit_join(2, 3, start).is_ok());
+        assert!(tracker.admit_join(1, 3, start + Duration::from_secs(60)).is_ok());
+    }
+
+    #[test]
+    fn test_new_and_low_reputation_accounts_restricted() {
+        let config = AntiRaidConfig::default();
+        let week = Duration::from_secs(7 * 86400);
+
+        assert!(restriction_reason(&config, Duration::from_secs(3600), 100).is_some());
+        assert!(restriction_reason(&config, week, 34).is_some());
+        assert!(restriction_reason(&config, week, 35).is_none());
+
+        // Compte sans historique libre, compte mis en sourdine restreint
+        let reputation = crate::config::ReputationConfig::default();
+        assert!(restriction_reason(&config, week, reputation.base).is_none());
+        assert!(restriction_reason(&config, week, reputation.base - reputation.mute_penalty).is_some());
+    }
+}
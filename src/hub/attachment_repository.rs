@@ -0,0 +1,170 @@
+//! Dépôt des pièces jointes
+//!
+//! Fichiers téléversés et occupation du stockage de chaque utilisateur,
+//! quotas compris (`attachments`) :
+//! - `PgAttachmentRepository` : PostgreSQL
+//! - `testing::InMemoryAttachmentRepository` (feature `testing`) : mémoire
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use sqlx::{query, PgPool, Row};
+use crate::config::LimitsConfig;
+use crate::error::{ChatError, Result};
+use crate::hub::attachments::{load_storage_usage, NewAttachment, StorageUsage};
+
+// ================================================================
+// TRAIT
+// ================================================================
+
+/// Accès aux fichiers téléversés
+pub trait AttachmentRepository: Send + Sync {
+    /// Taille stockée des fichiers de `file_ids` téléversés par `owner_id`
+    ///
+    /// Un fichier inconnu ou appartenant à un autre utilisateur est absent du résultat.
+    fn stored_file_sizes<'a>(&'a self, owner_id: i64, file_ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<(i64, u64)>>>;
+
+    /// Occupation du stockage de l'utilisateur
+    fn storage_usage<'a>(&'a self, user_id: i64, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<StorageUsage>>;
+
+    /// Réserve la place et enregistre le fichier : identifiant et date
+    ///
+    /// Au-delà du quota : `QuotaExceeded` (fichiers) ou `StorageQuotaExceeded`
+    /// (octets), rien n'est écrit.
+    fn store_attachment<'a>(&'a self, attachment: NewAttachment<'a>, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<(i64, DateTime<Utc>)>>;
+
+    /// Supprime un fichier de l'utilisateur et libère sa place : clé de
+    /// l'objet à retirer et occupation restante
+    fn delete_attachment<'a>(&'a self, user_id: i64, file_id: i64, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<(String, StorageUsage)>>;
+}
+
+// ================================================================
+// POSTGRESQL
+// ================================================================
+
+/// Dépôt PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgAttachmentRepository {
+    db: PgPool,
+}
+
+impl PgAttachmentRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+impl AttachmentRepository for PgAttachmentRepository {
+    fn stored_file_sizes<'a>(&'a self, owner_id: i64, file_ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<(i64, u64)>>> {
+        Box::pin(async move {
+            let rows = query("SELECT id, file_size FROM files WHERE id = ANY($1) AND uploaded_by = $2")
+                .bind(file_ids)
+                .bind(owner_id)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("stored_file_sizes", e))?;
+
+            Ok(rows.iter().map(|row| (row.get("id"), row.get::<i64, _>("file_size") as u64)).collect())
+        })
+    }
+
+    fn storage_usage<'a>(&'a self, user_id: i64, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<StorageUsage>> {
+        Box::pin(load_storage_usage(&self.db, user_id, limits))
+    }
+
+    fn store_attachment<'a>(&'a self, attachment: NewAttachment<'a>, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<(i64, DateTime<Utc>)>> {
+        Box::pin(async move {
+            let size = attachment.size_bytes as i64;
+
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Réservation conditionnelle : un téléversement concurrent ne fait pas dépasser le quota
+            let reserved = query("
+                INSERT INTO user_storage_usage (user_id, used_bytes, file_count)
+                VALUES ($1, $2, 1)
+                ON CONFLICT (user_id) DO UPDATE
+                SET used_bytes = user_storage_usage.used_bytes + EXCLUDED.used_bytes,
+                    file_count = user_storage_usage.file_count + 1,
+                    updated_at = NOW()
+                WHERE ($3 = 0 OR user_storage_usage.used_bytes + EXCLUDED.used_bytes <= $3)
+                  AND user_storage_usage.file_count < $4
+                RETURNING used_bytes
+            ")
+            .bind(attachment.user_id)
+            .bind(size)
+            .bind(limits.max_storage_per_user as i64)
+            .bind(limits.max_files_per_user as i32)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("reserve_storage", e))?;
+
+            if reserved.is_none() {
+                let usage = load_storage_usage(&mut *tx, attachment.user_id, limits).await?;
+                usage.check_upload(size as u64)?;
+                return Err(ChatError::StorageQuotaExceeded {
+                    used: usage.used_bytes,
+                    requested: size as u64,
+                    quota: usage.quota_bytes.unwrap_or_default(),
+                });
+            }
+
+            let row = query("
+                INSERT INTO files (uuid, uploaded_by, filename, original_filename, file_path, file_size, mime_type, checksum)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING id, created_at
+            ")
+            .bind(attachment.file_uuid)
+            .bind(attachment.user_id)
+            .bind(attachment.file_uuid.to_string())
+            .bind(attachment.original_filename)
+            .bind(attachment.object_key)
+            .bind(size)
+            .bind(attachment.mime_type)
+            .bind(&attachment.checksum)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("insert_attachment", e))?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok((row.get("id"), row.get("created_at")))
+        })
+    }
+
+    fn delete_attachment<'a>(&'a self, user_id: i64, file_id: i64, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<(String, StorageUsage)>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let row = query("DELETE FROM files WHERE id = $1 AND uploaded_by = $2 RETURNING file_path, file_size")
+                .bind(file_id)
+                .bind(user_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("delete_attachment", e))?
+                .ok_or_else(|| ChatError::not_found("pièce jointe", &file_id.to_string()))?;
+
+            let object_key: String = row.get("file_path");
+            let size: i64 = row.get("file_size");
+
+            query("
+                UPDATE user_storage_usage
+                SET used_bytes = GREATEST(used_bytes - $2, 0), file_count = GREATEST(file_count - 1, 0), updated_at = NOW()
+                WHERE user_id = $1
+            ")
+            .bind(user_id)
+            .bind(size)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("release_storage", e))?;
+
+            let usage = load_storage_usage(&mut *tx, user_id, limits).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok((object_key, usage))
+        })
+    }
+}
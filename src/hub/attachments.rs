@@ -0,0 +1,304 @@
+//! Pièces jointes téléversées et quota de stockage par utilisateur
+//!
+//! Chaque utilisateur dispose de `limits.max_storage_per_user` octets (0 =
+//! illimité) et de `limits.max_files_per_user` fichiers. L'occupation est
+//! tenue dans `user_storage_usage`, réservée dans la même transaction que
+//! l'insertion du fichier : deux téléversements simultanés ne peuvent pas
+//! dépasser le quota. La suppression d'une pièce jointe libère sa place.
+
+use ring::digest::{digest, SHA256};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::{query, PgExecutor, Row};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::config::LimitsConfig;
+use crate::hub::common::ChatHub;
+use crate::hub::feature_flags::FeatureFlag;
+use crate::error::{ChatError, Result};
+use crate::validation::{attachment_file_ids, AttachmentLimits};
+
+/// Longueur maximale du nom de fichier conservé
+pub const MAX_FILENAME_LENGTH: usize = 255;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Occupation du stockage d'un utilisateur
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageUsage {
+    pub used_bytes: u64,
+    pub file_count: u32,
+    /// `None` = illimité
+    pub quota_bytes: Option<u64>,
+    pub max_files: u32,
+}
+
+impl StorageUsage {
+    pub fn new(used_bytes: u64, file_count: u32, limits: &LimitsConfig) -> Self {
+        Self {
+            used_bytes,
+            file_count,
+            quota_bytes: Some(limits.max_storage_per_user).filter(|quota| *quota > 0),
+            max_files: limits.max_files_per_user,
+        }
+    }
+
+    /// Octets encore disponibles (`None` = illimité)
+    pub fn remaining_bytes(&self) -> Option<u64> {
+        self.quota_bytes.map(|quota| quota.saturating_sub(self.used_bytes))
+    }
+
+    /// Refuse un fichier de `size` octets qui dépasserait le quota
+    pub fn check_upload(&self, size: u64) -> Result<()> {
+        if self.file_count >= self.max_files {
+            return Err(ChatError::QuotaExceeded {
+                quota_type: "files".to_string(),
+                used: self.file_count as u64,
+                limit: self.max_files as u64,
+            });
+        }
+        if let Some(quota) = self.quota_bytes {
+            if self.used_bytes.saturating_add(size) > quota {
+                return Err(ChatError::StorageQuotaExceeded { used: self.used_bytes, requested: size, quota });
+            }
+        }
+        Ok(())
+    }
+
+    /// Occupation après l'ajout d'un fichier
+    pub fn with_upload(self, size: u64) -> Self {
+        Self { used_bytes: self.used_bytes + size, file_count: self.file_count + 1, ..self }
+    }
+
+    /// Occupation après la suppression d'un fichier
+    pub fn with_deletion(self, size: u64) -> Self {
+        Self {
+            used_bytes: self.used_bytes.saturating_sub(size),
+            file_count: self.file_count.saturating_sub(1),
+            ..self
+        }
+    }
+}
+
+/// Fichier à enregistrer, objet déjà écrit dans le stockage
+pub struct NewAttachment<'a> {
+    pub user_id: i64,
+    pub file_uuid: Uuid,
+    pub object_key: &'a str,
+    pub original_filename: &'a str,
+    pub mime_type: &'a str,
+    pub size_bytes: u64,
+    /// SHA-256 hexadécimal du contenu
+    pub checksum: String,
+}
+
+/// Pièce jointe stockée
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub filename: String,
+    pub url: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Nom de fichier affichable : sans chemin ni caractère de contrôle, borné
+pub fn sanitize_filename(filename: &str) -> Result<String> {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_FILENAME_LENGTH)
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        return Err(ChatError::InvalidFormat {
+            field: "filename".to_string(),
+            reason: "Nom de fichier vide".to_string(),
+        });
+    }
+    Ok(cleaned.to_string())
+}
+
+// ================================================================
+// OCCUPATION
+// ================================================================
+
+pub(crate) async fn load_storage_usage<'e>(executor: impl PgExecutor<'e>, user_id: i64, limits: &LimitsConfig) -> Result<StorageUsage> {
+    let row = query("SELECT used_bytes, file_count FROM user_storage_usage WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_storage_usage", e))?;
+
+    Ok(match row {
+        Some(row) => StorageUsage::new(row.get::<i64, _>("used_bytes") as u64, row.get::<i32, _>("file_count") as u32, limits),
+        None => StorageUsage::new(0, 0, limits),
+    })
+}
+
+/// Occupation du stockage d'un utilisateur
+pub async fn get_storage_usage(hub: &ChatHub, user_id: i64) -> Result<StorageUsage> {
+    hub.attachment_repository.storage_usage(user_id, &hub.config.limits).await
+}
+
+// ================================================================
+// TÉLÉVERSEMENT ET SUPPRESSION
+// ================================================================
+
+/// Téléverse une pièce jointe, dans la limite du quota de l'utilisateur
+///
+/// Au-delà du quota, l'erreur `StorageQuotaExceeded` porte l'occupation actuelle.
+pub async fn upload_attachment(
+    hub: &ChatHub,
+    user_id: i64,
+    filename: &str,
+    mime_type: &str,
+    bytes: &[u8]
+) -> Result<Attachment> {
+    tracing::info!(user_id = %user_id, size = %bytes.len(), "📎 Téléversement d'une pièce jointe");
+
+    hub.require_feature(FeatureFlag::FileUploads).await?;
+    let limits = &hub.config.limits;
+    let size = bytes.len() as u64;
+    if size == 0 {
+        return Err(ChatError::InvalidFormat { field: "file".to_string(), reason: "Fichier vide".to_string() });
+    }
+    if size > limits.max_file_size {
+        return Err(ChatError::FileTooLarge { size, max_size: limits.max_file_size });
+    }
+    let mime_type: mime::Mime = mime_type.parse()
+        .map_err(|_| ChatError::UnsupportedFileType { mime_type: mime_type.to_string() })?;
+    let original_filename = sanitize_filename(filename)?;
+
+    // Refus anticipé, avant d'écrire l'objet
+    get_storage_usage(hub, user_id).await?.check_upload(size)?;
+
+    let file_uuid = Uuid::new_v4();
+    let object_key = format!("attachments/{}/{}", user_id, file_uuid);
+    let url = hub.object_store.put(&object_key, bytes, mime_type.essence_str()).await?;
+
+    let attachment = NewAttachment {
+        user_id,
+        file_uuid,
+        object_key: &object_key,
+        original_filename: &original_filename,
+        mime_type: mime_type.essence_str(),
+        size_bytes: size,
+        checksum: hex::encode(digest(&SHA256, bytes)),
+    };
+    let stored = hub.attachment_repository.store_attachment(attachment, limits).await;
+    let (id, created_at) = match stored {
+        Ok(stored) => stored,
+        Err(e) => {
+            if let Err(cleanup) = hub.object_store.delete(&object_key).await {
+                tracing::warn!(key = %object_key, error = %cleanup, "⚠️ Pièce jointe orpheline");
+            }
+            return Err(e);
+        }
+    };
+
+    tracing::info!(user_id = %user_id, file_id = %id, size = %size, "✅ Pièce jointe téléversée");
+    Ok(Attachment {
+        id,
+        uuid: file_uuid,
+        filename: original_filename,
+        url,
+        mime_type: mime_type.essence_str().to_string(),
+        size_bytes: size,
+        created_at,
+    })
+}
+
+/// Supprime une pièce jointe de l'utilisateur et libère sa place
+pub async fn delete_attachment(hub: &ChatHub, user_id: i64, file_id: i64) -> Result<StorageUsage> {
+    tracing::info!(user_id = %user_id, file_id = %file_id, "🗑️ Suppression d'une pièce jointe");
+
+    let (object_key, usage) = hub.attachment_repository.delete_attachment(user_id, file_id, &hub.config.limits).await?;
+
+    // Objet retiré après la validation : au pire un fichier orphelin, jamais une ligne sans objet
+    if let Err(e) = hub.object_store.delete(&object_key).await {
+        tracing::warn!(key = %object_key, error = %e, "⚠️ Pièce jointe orpheline");
+    }
+
+    tracing::info!(user_id = %user_id, file_id = %file_id, used_bytes = %usage.used_bytes, "✅ Pièce jointe supprimée");
+    Ok(usage)
+}
+
+// ================================================================
+// PIÈCES JOINTES D'UN MESSAGE
+// ================================================================
+
+/// Vérifie les pièces jointes d'un message sur la taille des fichiers stockés
+///
+/// Chaque pièce de `metadata.attachments` désigne par `id` un fichier
+/// téléversé par l'auteur ; son `sizeBytes` est réécrit avec la taille
+/// stockée, celle annoncée par le client n'est jamais crue.
+pub async fn check_message_attachments(
+    hub: &ChatHub,
+    author_id: i64,
+    limits: AttachmentLimits,
+    metadata: Option<&mut Value>
+) -> Result<()> {
+    let file_ids = attachment_file_ids(metadata.as_deref())?;
+    let Some(metadata) = metadata.filter(|_| !file_ids.is_empty()) else {
+        return Ok(());
+    };
+    // Nombre refusé avant toute lecture des fichiers
+    limits.check_count(file_ids.len())?;
+
+    let stored = hub.attachment_repository.stored_file_sizes(author_id, &file_ids).await?;
+    let sizes = file_ids.iter()
+        .map(|file_id| stored.iter().find(|(id, _)| id == file_id).map(|(_, size)| *size).ok_or_else(|| ChatError::InvalidFormat {
+            field: "attachments.id".to_string(),
+            reason: format!("fichier {} inconnu", file_id),
+        }))
+        .collect::<Result<Vec<u64>>>()?;
+    limits.check(&sizes)?;
+
+    if let Some(attachments) = metadata.get_mut("attachments").and_then(Value::as_array_mut) {
+        for (attachment, size) in attachments.iter_mut().zip(sizes) {
+            attachment["sizeBytes"] = json!(size);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(quota: u64) -> LimitsConfig {
+        LimitsConfig { max_storage_per_user: quota, max_files_per_user: 10, ..LimitsConfig::default() }
+    }
+
+    #[test]
+    fn test_upload_at_quota_passes_over_quota_is_rejected() {
+        let usage = StorageUsage::new(900, 3, &limits(1000));
+        assert!(usage.check_upload(100).is_ok());
+
+        let full = usage.with_upload(100);
+        assert_eq!(full.remaining_bytes(), Some(0));
+        match full.check_upload(1) {
+            Err(ChatError::StorageQuotaExceeded { used, requested, quota }) => {
+                assert_eq!((used, requested, quota), (1000, 1, 1000));
+            }
+            other => panic!("quota attendu: {:?}", other),
+        }
+
+        // 0 = illimité
+        assert!(StorageUsage::new(u64::MAX / 2, 0, &limits(0)).check_upload(1 << 40).is_ok());
+    }
+
+    #[test]
+    fn test_filename_is_stripped_of_paths() {
+        assert_eq!(sanitize_filename("../../etc/rapport.pdf").unwrap(), "rapport.pdf");
+        assert_eq!(sanitize_filename("C:\\Users\\a\\photo\u{0}.png").unwrap(), "photo.png");
+        assert!(sanitize_filename("dossier/").is_err());
+    }
+}
@@ -9,7 +9,7 @@
 
 use sqlx::{query, query_as, FromRow, Row};
 use serde::{Serialize, Deserialize};
-use crate::hub::common::ChatHub;
+use crate::hub::common::{check_global_admin, ChatHub};
 use crate::validation::{validate_user_id, validate_limit};
 use crate::error::{ChatError, Result};
 use serde_json::{json, Value};
@@ -78,7 +78,7 @@ pub struct RoomAuditSummary {
 // ENREGISTREMENT DES LOGS D'AUDIT
 // ================================================================
 
-/// Enregistrer une action d'audit
+/// Enregistrer une action d'audit (via le puits, selon la configuration `[audit]`)
 pub async fn log_action(
     hub: &ChatHub,
     action: &str,
@@ -86,29 +86,16 @@ pub async fn log_action(
     user_id: Option<i64>,
     ip_address: Option<&str>,
     user_agent: Option<&str>
-) -> Result<i64> {
-    tracing::debug!(action = %action, user_id = ?user_id, "📝 Enregistrement d'action d'audit");
+) -> Result<Option<i64>> {
+    let audit_id = hub.audit_sink
+        .record_with_origin(&hub.db, action, user_id, ip_address, user_agent, details)
+        .await?;
     
-    let audit_id = query("
-        INSERT INTO audit_logs (action, details, user_id, ip_address, user_agent)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id
-    ")
-    .bind(action)
-    .bind(&details)
-    .bind(user_id)
-    .bind(ip_address)
-    .bind(user_agent)
-    .fetch_one(&hub.db)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("insert_audit_log", e))?
-    .get::<i64, _>("id");
-    
-    tracing::info!(action = %action, audit_id = %audit_id, "✅ Action d'audit enregistrée");
+    tracing::debug!(action = %action, audit_id = ?audit_id, "📝 Action d'audit traitée");
     Ok(audit_id)
 }
 
-/// Enregistrer un événement de sécurité
+/// Enregistrer un événement de sécurité (via le puits, selon la configuration `[audit]`)
 pub async fn log_security_event(
     hub: &ChatHub,
     event_type: &str,
@@ -117,31 +104,12 @@ pub async fn log_security_event(
     user_id: Option<i64>,
     ip_address: Option<&str>,
     metadata: Value
-) -> Result<i64> {
-    tracing::warn!(
-        event_type = %event_type, 
-        severity = %severity, 
-        user_id = ?user_id,
-        "🚨 Enregistrement d'événement de sécurité"
-    );
-    
-    let event_id = query("
-        INSERT INTO security_events (event_type, severity, description, user_id, ip_address, metadata)
-        VALUES ($1, $2, $3, $4, $5, $6)
-        RETURNING id
-    ")
-    .bind(event_type)
-    .bind(severity)
-    .bind(description)
-    .bind(user_id)
-    .bind(ip_address)
-    .bind(&metadata)
-    .fetch_one(&hub.db)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("insert_security_event", e))?
-    .get::<i64, _>("id");
+) -> Result<Option<i64>> {
+    let event_id = hub.audit_sink
+        .record_security_event(&hub.db, event_type, severity, description, user_id, ip_address, metadata)
+        .await?;
     
-    tracing::warn!(event_type = %event_type, event_id = %event_id, "🚨 Événement de sécurité enregistré");
+    tracing::debug!(event_type = %event_type, event_id = ?event_id, "🚨 Événement de sécurité traité");
     Ok(event_id)
 }
 
@@ -307,7 +275,7 @@ pub async fn get_room_audit_logs(
     tracing::info!(room_id = %room_id, user_id = %requesting_user_id, "📚 Récupération des logs d'audit du salon");
     
     validate_user_id(requesting_user_id as i32)?;
-    let validated_limit = validate_limit(limit)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
     
     // Vérifier que l'utilisateur a les permissions pour voir les logs
     check_audit_permissions(hub, room_id, requesting_user_id).await?;
@@ -357,7 +325,7 @@ pub async fn get_room_security_events(
     tracing::info!(room_id = %room_id, user_id = %requesting_user_id, "🚨 Récupération des événements de sécurité du salon");
     
     validate_user_id(requesting_user_id as i32)?;
-    let validated_limit = validate_limit(limit)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
     
     // Vérifier les permissions
     check_audit_permissions(hub, room_id, requesting_user_id).await?;
@@ -637,23 +605,7 @@ async fn check_audit_permissions(hub: &ChatHub, room_id: i64, user_id: i64) -> R
     
     match user_role.as_deref() {
         Some("owner") | Some("moderator") => Ok(()),
-        _ => {
-            // Vérifier si c'est un admin global
-            let is_admin: bool = query("
-                SELECT role = 'admin' OR role = 'super_admin' 
-                FROM users WHERE id = $1
-            ")
-            .bind(user_id)
-            .fetch_one(&hub.db)
-            .await
-            .map_err(|e| ChatError::from_sqlx_error("check_global_admin", e))?
-            .get(0);
-            
-            if is_admin {
-                Ok(())
-            } else {
-                Err(ChatError::unauthorized("access_audit_logs"))
-            }
-        }
+        // Sinon, seuls les administrateurs globaux y ont accès
+        _ => check_global_admin(hub, user_id, "access_audit_logs").await,
     }
 } 
\ No newline at end of file
@@ -0,0 +1,189 @@
+//! Puits d'audit
+//!
+//! Point d'entrée unique des enregistrements d'audit et de sécurité. La
+//! configuration `[audit]` décide, action par action, si l'enregistrement est
+//! conservé et avec quel niveau de détail, indépendamment de `RUST_LOG`.
+//!
+//! Le puits accepte tout exécuteur PostgreSQL : un enregistrement fait dans une
+//! transaction n'est conservé que si celle-ci est validée.
+
+use sqlx::{query, PgExecutor, Row};
+use crate::config::{AuditConfig, AuditDetail};
+use crate::error::{ChatError, Result};
+use serde_json::Value;
+
+/// Préfixe des actions désignant un événement de sécurité dans la configuration
+pub const SECURITY_ACTION_PREFIX: &str = "security_";
+
+/// Clé conservée au niveau `Minimal` (identifiants uniquement)
+fn is_identifier_key(key: &str) -> bool {
+    key == "id" || key == "action" || key.ends_with("_id") || key.ends_with("_ids")
+}
+
+/// Clé portant un contenu de message, retirée au niveau `Standard`
+fn is_content_key(key: &str) -> bool {
+    key.contains("content")
+}
+
+/// Réduit les détails au niveau demandé (`None` = pas d'enregistrement)
+pub fn apply_detail(detail: AuditDetail, details: Value) -> Option<Value> {
+    let keep: fn(&str) -> bool = match detail {
+        AuditDetail::Off => return None,
+        AuditDetail::Full => return Some(details),
+        AuditDetail::Minimal => is_identifier_key,
+        AuditDetail::Standard => |key| !is_content_key(key),
+    };
+
+    match details {
+        Value::Object(map) => Some(Value::Object(
+            map.into_iter().filter(|(key, _)| keep(key)).collect()
+        )),
+        other if detail == AuditDetail::Standard => Some(other),
+        _ => Some(Value::Object(Default::default())),
+    }
+}
+
+// ================================================================
+// PUITS D'AUDIT
+// ================================================================
+
+/// Enregistre les actions d'audit selon la configuration `[audit]`
+#[derive(Debug, Clone)]
+pub struct AuditSink {
+    config: AuditConfig,
+}
+
+impl AuditSink {
+    pub fn new(config: AuditConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn detail_for(&self, action: &str) -> AuditDetail {
+        self.config.detail_for(action)
+    }
+
+    /// Enregistre une action ; retourne `None` si l'action n'est pas auditée
+    pub async fn record<'e, E: PgExecutor<'e>>(
+        &self,
+        executor: E,
+        action: &str,
+        user_id: Option<i64>,
+        details: Value
+    ) -> Result<Option<i64>> {
+        self.record_with_origin(executor, action, user_id, None, None, details).await
+    }
+
+    /// Enregistre une action avec l'origine de la requête (IP, User-Agent)
+    pub async fn record_with_origin<'e, E: PgExecutor<'e>>(
+        &self,
+        executor: E,
+        action: &str,
+        user_id: Option<i64>,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+        details: Value
+    ) -> Result<Option<i64>> {
+        let Some(details) = apply_detail(self.detail_for(action), details) else {
+            return Ok(None);
+        };
+
+        let audit_id = query("
+            INSERT INTO audit_logs (action, details, user_id, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+        ")
+        .bind(action)
+        .bind(&details)
+        .bind(user_id)
+        .bind(ip_address)
+        .bind(user_agent)
+        .fetch_one(executor)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?
+        .get::<i64, _>("id");
+
+        Ok(Some(audit_id))
+    }
+
+    /// Enregistre un événement de sécurité (configuré via `security_<type>`)
+    pub async fn record_security_event<'e, E: PgExecutor<'e>>(
+        &self,
+        executor: E,
+        event_type: &str,
+        severity: &str,
+        description: &str,
+        user_id: Option<i64>,
+        ip_address: Option<&str>,
+        metadata: Value
+    ) -> Result<Option<i64>> {
+        let detail = self.detail_for(&format!("{}{}", SECURITY_ACTION_PREFIX, event_type));
+        let Some(metadata) = apply_detail(detail, metadata) else {
+            return Ok(None);
+        };
+
+        // La description est libre : seul le niveau complet la conserve
+        let description = if detail == AuditDetail::Full { description } else { "" };
+
+        let event_id = query("
+            INSERT INTO security_events (event_type, severity, description, user_id, ip_address, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+        ")
+        .bind(event_type)
+        .bind(severity)
+        .bind(description)
+        .bind(user_id)
+        .bind(ip_address)
+        .bind(&metadata)
+        .fetch_one(executor)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("insert_security_event", e))?
+        .get::<i64, _>("id");
+
+        Ok(Some(event_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn edit_details() -> Value {
+        json!({
+            "message_id": 42,
+            "room_id": 7,
+            "action": "edited",
+            "old_content": "avant",
+            "new_content": "après",
+            "reason": "typo"
+        })
+    }
+
+    #[test]
+    fn test_detail_levels() {
+        assert_eq!(apply_detail(AuditDetail::Off, edit_details()), None);
+        assert_eq!(apply_detail(AuditDetail::Full, edit_details()), Some(edit_details()));
+
+        assert_eq!(
+            apply_detail(AuditDetail::Standard, edit_details()),
+            Some(json!({ "message_id": 42, "room_id": 7, "action": "edited", "reason": "typo" }))
+        );
+        assert_eq!(
+            apply_detail(AuditDetail::Minimal, edit_details()),
+            Some(json!({ "message_id": 42, "room_id": 7, "action": "edited" }))
+        );
+    }
+
+    #[test]
+    fn test_sink_follows_config_not_log_level() {
+        let mut config = AuditConfig::default();
+        config.actions.insert("security_*".to_string(), AuditDetail::Minimal);
+        config.actions.insert("reaction_*".to_string(), AuditDetail::Off);
+        let sink = AuditSink::new(config);
+
+        assert_eq!(sink.detail_for("security_moderation_action"), AuditDetail::Minimal);
+        assert_eq!(sink.detail_for("reaction_added"), AuditDetail::Off);
+        assert_eq!(sink.detail_for("room_created"), AuditDetail::Full);
+    }
+}
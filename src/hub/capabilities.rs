@@ -0,0 +1,242 @@
+//! Module des capacités du serveur
+//!
+//! `get_capabilities` retourne les limites effectives (`[limits]`), les
+//! drapeaux de fonctionnalités et les versions de schéma servies, ainsi que
+//! les réglages propres à un salon quand `roomId` est fourni. La réponse porte
+//! une empreinte (`etag`) : un client qui renvoie l'empreinte de sa copie en
+//! cache reçoit `unchanged: true` sans le détail.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::{query, Row};
+use crate::config::LimitsConfig;
+use crate::hub::common::ChatHub;
+use crate::hub::feature_flags::FeatureFlags;
+use crate::hub::reaction_sets::ReactionSet;
+use crate::message_schema::{CURRENT_SCHEMA_VERSION, MIN_SCHEMA_VERSION};
+use crate::error::{ChatError, Result};
+
+/// Limites du serveur dont les clients ont besoin (durées en secondes)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerLimits {
+    pub max_message_length: usize,
+    pub max_long_message_length: usize,
+    pub max_mentions_per_message: usize,
+    pub max_file_size: u64,
+    pub max_files_per_user: u32,
+    pub max_storage_per_user: u64,
+    pub max_attachments_per_message: usize,
+    pub max_attachments_total_size: u64,
+    pub max_rooms_per_user: u32,
+    pub max_members_per_room: u32,
+    pub max_connections_per_user: u32,
+    pub max_messages_per_minute: u32,
+    pub room_messages_per_minute: u32,
+    pub message_edit_window_secs: u64,
+    pub message_delete_window_secs: u64,
+    pub max_pin_duration_secs: u64,
+    pub max_custom_emoji_size: u64,
+    pub max_custom_emojis_per_scope: u32,
+}
+
+impl ServerLimits {
+    pub fn from_config(limits: &LimitsConfig) -> Self {
+        Self {
+            max_message_length: limits.max_message_length,
+            max_long_message_length: limits.max_long_message_length,
+            max_mentions_per_message: limits.max_mentions_per_message,
+            max_file_size: limits.max_file_size,
+            max_files_per_user: limits.max_files_per_user,
+            max_storage_per_user: limits.max_storage_per_user,
+            max_attachments_per_message: limits.max_attachments_per_message,
+            max_attachments_total_size: limits.max_attachments_total_size,
+            max_rooms_per_user: limits.max_rooms_per_user,
+            max_members_per_room: limits.max_members_per_room,
+            max_connections_per_user: limits.max_connections_per_user,
+            max_messages_per_minute: limits.max_messages_per_minute,
+            room_messages_per_minute: limits.room_messages_per_minute,
+            message_edit_window_secs: limits.message_edit_window.as_secs(),
+            message_delete_window_secs: limits.message_delete_window.as_secs(),
+            max_pin_duration_secs: limits.max_pin_duration.as_secs(),
+            max_custom_emoji_size: limits.max_custom_emoji_size,
+            max_custom_emojis_per_scope: limits.max_custom_emojis_per_scope,
+        }
+    }
+}
+
+/// Réglages propres à un salon
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomCapabilities {
+    pub room_id: i64,
+    pub archived: bool,
+    pub post_policy: String,
+    pub reactions: ReactionSet,
+    /// Délai du mode lent imposé par les modérateurs
+    pub slow_mode_seconds: Option<i32>,
+    pub max_members: Option<i32>,
+    /// Messages antérieurs visibles à l'arrivée
+    pub joinable_history_limit: Option<i32>,
+    pub filter_mode: String,
+    /// Pièces jointes par message, surchargées par le salon
+    pub max_attachments: Option<i32>,
+    pub max_attachments_size: Option<i64>,
+    /// Langues filtrées en plus de celles du serveur
+    pub languages: Vec<String>,
+    /// Contenu chiffré au repos (recherche indisponible)
+    pub encrypt_at_rest: bool,
+}
+
+/// Réponse à `get_capabilities`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub etag: String,
+    pub schema_version: Value,
+    pub limits: ServerLimits,
+    pub features: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<RoomCapabilities>,
+}
+
+impl Capabilities {
+    pub fn new(limits: &LimitsConfig, features: &FeatureFlags, room: Option<RoomCapabilities>) -> Self {
+        let mut capabilities = Self {
+            etag: String::new(),
+            schema_version: json!({ "current": CURRENT_SCHEMA_VERSION, "min": MIN_SCHEMA_VERSION }),
+            limits: ServerLimits::from_config(limits),
+            features: features.to_json(),
+            room,
+        };
+        capabilities.etag = capabilities_etag(&json!(capabilities));
+        capabilities
+    }
+
+    /// Trame `capabilities` ; sans le détail si le client a déjà cette empreinte
+    pub fn to_frame(&self, known_etag: Option<&str>) -> Value {
+        if known_etag == Some(self.etag.as_str()) {
+            return json!({
+                "type": "capabilities",
+                "data": {
+                    "etag": self.etag,
+                    "roomId": self.room.as_ref().map(|room| room.room_id),
+                    "unchanged": true
+                }
+            });
+        }
+        let mut data = json!(self);
+        data["unchanged"] = json!(false);
+        json!({
+            "type": "capabilities",
+            "data": data
+        })
+    }
+}
+
+/// Empreinte du contenu des capacités (hors empreinte elle-même)
+pub fn capabilities_etag(capabilities: &Value) -> String {
+    let mut content = capabilities.clone();
+    if let Some(object) = content.as_object_mut() {
+        object.remove("etag");
+    }
+    let mut hasher = DefaultHasher::new();
+    content.to_string().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Réglages d'un salon public
+async fn load_room_capabilities(hub: &ChatHub, room_id: i64) -> Result<RoomCapabilities> {
+    let row = query("
+        SELECT is_archived, post_policy, allowed_reactions, slow_mode_seconds, max_members,
+               joinable_history_limit, filter_mode, languages, max_attachments, max_attachments_size,
+               encrypt_at_rest
+        FROM conversations
+        WHERE id = $1 AND type = 'public_room'
+    ")
+    .bind(room_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_room_capabilities", e))?
+    .ok_or_else(|| ChatError::not_found("salon", &room_id.to_string()))?;
+
+    Ok(RoomCapabilities {
+        room_id,
+        archived: row.get("is_archived"),
+        post_policy: row.get("post_policy"),
+        reactions: ReactionSet::from_db(row.get("allowed_reactions")),
+        slow_mode_seconds: row.get("slow_mode_seconds"),
+        max_members: row.get("max_members"),
+        joinable_history_limit: row.get("joinable_history_limit"),
+        filter_mode: row.get("filter_mode"),
+        max_attachments: row.get("max_attachments"),
+        max_attachments_size: row.get("max_attachments_size"),
+        languages: row.get("languages"),
+        encrypt_at_rest: row.get("encrypt_at_rest"),
+    })
+}
+
+/// Capacités effectives du serveur, et du salon `room_id` s'il est fourni
+pub async fn get_capabilities(hub: &ChatHub, room_id: Option<i64>) -> Result<Capabilities> {
+    let room = match room_id {
+        Some(room_id) => Some(load_room_capabilities(hub, room_id).await?),
+        None => None,
+    };
+    let features = hub.get_feature_flags().await;
+    Ok(Capabilities::new(&hub.config.limits, &features, room))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeaturesConfig;
+
+    fn capabilities(limits: &LimitsConfig, room: Option<RoomCapabilities>) -> Capabilities {
+        Capabilities::new(limits, &FeatureFlags::from_config(&FeaturesConfig::default()), room)
+    }
+
+    #[test]
+    fn test_etag_tracks_limits_and_room() {
+        let limits = LimitsConfig::default();
+        let base = capabilities(&limits, None);
+        assert_eq!(base.etag, capabilities(&limits, None).etag);
+
+        let mut shorter = LimitsConfig::default();
+        shorter.max_message_length = 500;
+        assert_ne!(base.etag, capabilities(&shorter, None).etag);
+
+        let room = RoomCapabilities {
+            room_id: 3,
+            archived: false,
+            post_policy: "everyone".to_string(),
+            reactions: ReactionSet::Curated(vec!["👍".to_string()]),
+            slow_mode_seconds: Some(30),
+            max_members: None,
+            joinable_history_limit: None,
+            filter_mode: "off".to_string(),
+            max_attachments: Some(20),
+            max_attachments_size: None,
+            languages: vec!["fr".to_string()],
+            encrypt_at_rest: false,
+        };
+        assert_ne!(base.etag, capabilities(&limits, Some(room)).etag);
+    }
+
+    #[test]
+    fn test_frame_is_short_when_etag_matches() {
+        let capabilities = capabilities(&LimitsConfig::default(), None);
+
+        let full = capabilities.to_frame(None);
+        assert_eq!(full["type"], "capabilities");
+        assert_eq!(full["data"]["unchanged"], false);
+        assert_eq!(full["data"]["limits"]["maxMessageLength"], LimitsConfig::default().max_message_length);
+        assert!(full["data"]["features"].is_object());
+
+        let cached = capabilities.to_frame(Some(&capabilities.etag));
+        assert_eq!(cached["data"]["unchanged"], true);
+        assert!(cached["data"].get("limits").is_none());
+        assert_eq!(capabilities.to_frame(Some("périmée"))["data"]["unchanged"], false);
+    }
+}
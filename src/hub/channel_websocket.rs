@@ -8,10 +8,23 @@
 //! - Notifications d'audit
 //! - Événements de modération
 
-use crate::hub::{ChatHub, room_enhanced, reactions, audit};
+use crate::hub::{ChatHub, channels, diagnostics, room_directory, reaction_sets, custom_emojis, feature_flags, templates, slow_mode, anti_raid, reputation, room_enhanced, reactions, audit, long_messages, reports, quotas, held_messages, presence_subscriptions, capabilities, missed_events, encrypted_rooms, attachments, violations, read_receipts, room_list, mutes, guests, profiles};
+use crate::client::{AckMode, Client};
+use crate::hub::inbound::{connection_user_id, read_frames, ConnectionInbox};
 use crate::error::{ChatError, Result};
+use crate::link_policy::RoomLinkPolicy;
+use crate::permissions::Role;
+use crate::presence::UserStatus;
+use crate::room_id::RoomId;
+use crate::message_schema::{message_frame, CURRENT_SCHEMA_VERSION};
+use crate::validation::{parse_client_json, UNSPECIFIED_LIMIT};
 use serde_json::{json, Value};
-use tokio_tungstenite::tungstenite::Message;
+use std::collections::HashMap;
+use base64::Engine;
+use futures_util::Stream;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
 use tracing::{info, warn, error};
 
 // ================================================================
@@ -22,36 +35,151 @@ pub enum RoomWebSocketMessage {
     // Messages de base
     JoinRoom { room_id: i64, user_id: i64 },
     LeaveRoom { room_id: i64, user_id: i64 },
-    SendMessage { room_id: i64, user_id: i64, username: String, content: String, parent_id: Option<i64> },
+    ListRooms { include_archived: bool, limit: i64, cursor: Option<i64> },
+    BrowseRooms { user_id: i64, public_only: bool, joined_only: bool, name_prefix: Option<String>, order: Option<String>, limit: i64, cursor: Option<String> },
+    SendMessage { room_id: i64, user_id: i64, username: String, content: String, parent_id: Option<i64>, visible_to: Option<Vec<i32>>, nonce: Option<String>, ack: Option<bool> },
+    SetAckMode { user_id: i64, mode: String },
+    EditMessage { room_id: i64, message_id: i64, user_id: i64, content: String },
+    DeleteMessage { room_id: i64, message_id: i64, user_id: i64 },
+    
+    // Modèles de réponse
+    ListTemplates { room_id: i64, user_id: i64 },
+    CreateTemplate { room_id: i64, user_id: i64, name: String, content: String, members_can_use: bool },
+    DeleteTemplate { room_id: i64, user_id: i64, name: String },
+    SendTemplate { room_id: i64, user_id: i64, username: String, name: String, values: HashMap<String, String> },
     
     // Historique et recherche
-    GetHistory { room_id: i64, user_id: i64, limit: i64, before_id: Option<i64> },
+    GetHistory { room_id: i64, user_id: i64, limit: i64, before_id: Option<i64>, include_pin_state: bool },
     GetPinnedMessages { room_id: i64, user_id: i64 },
+    OpenRoom { room_id: i64, user_id: i64, limit: i64 },
+    GetMessageBody { message_id: i64, user_id: i64 },
+    MarkRoomRead { room_id: i64, user_id: i64, up_to_message_id: i64 },
+    GetUserRooms { user_id: i64, limit: i64, cursor: Option<i64> },
+    SetRoomPinned { room_id: i64, user_id: i64, pinned: bool },
+    SetRoomMuted { room_id: i64, user_id: i64, muted: bool },
+    SetRoomGuestAccess { room_id: i64, user_id: i64, access: String },
     
     // Réactions
     AddReaction { message_id: i64, user_id: i64, emoji: String },
     RemoveReaction { message_id: i64, user_id: i64, emoji: String },
     GetReactions { message_id: i64, user_id: i64 },
+    GetReactionChanges { user_id: i64, message_ids: Vec<i64>, since: String },
     
     // Modération
-    PinMessage { room_id: i64, message_id: i64, user_id: i64 },
+    PinMessage { room_id: i64, message_id: i64, user_id: i64, duration_seconds: Option<u64> },
     UnpinMessage { room_id: i64, message_id: i64, user_id: i64 },
+    ReorderPins { room_id: i64, user_id: i64, message_ids: Vec<i64> },
+    ReportMessage { message_id: i64, user_id: i64, reason: String },
+    SetSlowMode { room_id: i64, user_id: i64, mode: slow_mode::SlowModeOverride },
+    SetAntiRaid { room_id: Option<i64>, user_id: i64, enabled: bool, duration_seconds: Option<u64> },
+    ForceDisconnect { user_id: i64, target_user_id: i32, message: Option<String> },
+    GetReputation { user_id: i64, target_user_id: i64 },
+    AdjustReputation { user_id: i64, target_user_id: i64, delta: i32, reason: String },
+    GetReactionSet { room_id: i64 },
+    
+    // Émojis personnalisés
+    GetEmojiCatalog { room_id: Option<i64>, known_version: Option<String> },
+    UploadCustomEmoji { user_id: i64, scope: custom_emojis::EmojiScope, shortcode: String, image: Vec<u8> },
+    DeleteCustomEmoji { user_id: i64, emoji_id: i64 },
+    UploadAttachment { user_id: i64, filename: String, mime_type: String, bytes: Vec<u8> },
+    DeleteAttachment { user_id: i64, file_id: i64 },
+    GetStorageUsage { user_id: i64 },
+    SetReactionSet { room_id: i64, user_id: i64, set: reaction_sets::ReactionSet },
+    SetRoomArchived { room_id: i64, user_id: i64, archived: bool },
+    SetHistoryLimit { room_id: i64, user_id: i64, history_limit: Option<i32> },
+    SetFilterMode { room_id: i64, user_id: i64, mode: String },
+    SetLinkPolicy { room_id: i64, user_id: i64, policy: Option<Value> },
+    SetRoomLanguages { room_id: i64, user_id: i64, languages: Vec<String> },
+    UpdateAllowlistedTerm { room_id: i64, user_id: i64, term: String, add: bool },
+    SetRoomEncryption { room_id: i64, user_id: i64, enabled: bool },
+    SendRoleNotice { room_id: i64, user_id: i64, role: String, content: String },
+    SetAttachmentLimits { room_id: i64, user_id: i64, max_count: Option<i32>, max_total_size: Option<i64> },
     
     // Administration
     GetRoomStats { room_id: i64, user_id: i64 },
-    GetMembers { room_id: i64, user_id: i64 },
+    GetMembers { room_id: i64, user_id: i64, limit: i64, cursor: Option<i64> },
     GetAuditLogs { room_id: i64, user_id: i64, limit: i64 },
+    GetModerationQueue { user_id: i64, limit: i64, cursor: Option<i64> },
+    GetUserViolations { user_id: i64, target_user_id: i64 },
+    ReviewHeldMessage { message_id: i64, user_id: i64, approve: bool, note: Option<String> },
+    GetFeatureFlags,
+    GetCapabilities { room_id: Option<i64>, etag: Option<String> },
+    SetFeatureFlag { user_id: i64, flag: String, enabled: Option<bool> },
+    
+    // Reprise après reconnexion : (salon, dernier eventId reçu)
+    Resume { user_id: i64, rooms: Vec<(i64, u64)> },
+    
+    // Rattrapage de tous les salons et messages privés depuis un instant (`since`) ou un curseur
+    GetMissedEvents { user_id: i64, since: Option<String>, cursor: Option<String>, limit: i64 },
+    
+    // Version du schéma des messages comprise par le client
+    NegotiateSchema { user_id: i64, schema_version: u16 },
+    
+    // Solde du quota quotidien de messages
+    GetQuota { user_id: i64 },
+    
+    // Présence (abonnements aux utilisateurs et salons suivis)
+    SubscribePresence { user_id: i64, users: Vec<i32>, rooms: Vec<RoomId> },
+    UnsubscribePresence { user_id: i64, users: Vec<i32>, rooms: Vec<RoomId> },
+    SetStatus { user_id: i64, status: Value, message: Option<String> },
+    
+    // Profils allégés des auteurs (nom d'affichage, avatar, badges, présence)
+    GetUserProfiles { user_id: i64, user_ids: Vec<i64> },
+    
+    // Diagnostic
+    PingDiag { user_id: i64, correlation_id: Option<String>, client_time: Option<i64> },
 }
 
 // ================================================================
 // GESTIONNAIRE PRINCIPAL
 // ================================================================
 
+/// Connexion salons : lit le socket de `client` jusqu'à sa fermeture
+///
+/// Les trames passent par la `ConnectionInbox` de la connexion (ordre,
+/// lots, débordement) ; la session est retirée du hub à la fin.
+pub async fn serve_room_connection<S>(hub: Arc<ChatHub>, client: Client, stream: S) -> usize
+where
+    S: Stream<Item = std::result::Result<Message, WsError>> + Unpin,
+{
+    info!(user_id = %client.user_id, "🔌 Connexion salons ouverte");
+    let processed = read_frames(ConnectionInbox::for_rooms(hub.clone(), client.clone()), stream).await;
+    hub.unregister(&client).await;
+    info!(user_id = %client.user_id, processed = %processed, "🔌 Connexion salons fermée");
+    processed
+}
+
+/// Traite une commande reçue sur la connexion de `caller`
+///
+/// Le routage invité dépend du jeton de la connexion (`Client::is_guest`),
+/// jamais de l'identifiant porté par la trame.
 pub async fn handle_room_websocket_message(
     hub: &ChatHub,
+    caller: &Client,
     message: RoomWebSocketMessage
 ) -> Result<Option<String>> {
     match message {
+        // Invités : présence en mémoire, publication selon `guest_access`
+        RoomWebSocketMessage::JoinRoom { room_id, user_id } if caller.is_guest() => {
+            handle_guest_join_room(hub, room_id, user_id as i32).await
+        }
+        
+        RoomWebSocketMessage::LeaveRoom { room_id, user_id } if caller.is_guest() => {
+            guests::leave_room_as_guest(hub, room_id, user_id as i32).await;
+            Ok(Some(json!({
+                "type": "room_left",
+                "data": {
+                    "roomId": room_id,
+                    "userId": user_id,
+                    "success": true
+                }
+            }).to_string()))
+        }
+        
+        RoomWebSocketMessage::SendMessage { room_id, user_id, content, .. } if caller.is_guest() => {
+            handle_guest_send_message(hub, room_id, user_id as i32, &content).await
+        }
+        
         // Messages de base
         RoomWebSocketMessage::JoinRoom { room_id, user_id } => {
             handle_join_room(hub, room_id, user_id).await
@@ -61,19 +189,67 @@ pub async fn handle_room_websocket_message(
             handle_leave_room(hub, room_id, user_id).await
         }
         
-        RoomWebSocketMessage::SendMessage { room_id, user_id, username, content, parent_id } => {
-            handle_send_message(hub, room_id, user_id, &username, &content, parent_id).await
+        RoomWebSocketMessage::ListRooms { include_archived, limit, cursor } => {
+            handle_list_rooms(hub, include_archived, limit, cursor).await
+        }
+        
+        RoomWebSocketMessage::BrowseRooms { user_id, public_only, joined_only, name_prefix, order, limit, cursor } => {
+            handle_browse_rooms(hub, user_id, public_only, joined_only, name_prefix, order.as_deref(), limit, cursor.as_deref()).await
+        }
+        
+        RoomWebSocketMessage::SendMessage { room_id, user_id, username, content, parent_id, visible_to, nonce, ack } => {
+            handle_send_message(hub, room_id, user_id, &username, &content, parent_id, visible_to, nonce, ack).await
+        }
+        
+        RoomWebSocketMessage::SetAckMode { user_id, mode } => {
+            handle_set_ack_mode(hub, user_id, &mode).await
+        }
+        
+        RoomWebSocketMessage::EditMessage { room_id, message_id, user_id, content } => {
+            handle_edit_message(hub, room_id, message_id, user_id, &content).await
+        }
+        
+        RoomWebSocketMessage::DeleteMessage { room_id, message_id, user_id } => {
+            handle_delete_message(hub, room_id, message_id, user_id).await
         }
         
         // Historique
-        RoomWebSocketMessage::GetHistory { room_id, user_id, limit, before_id } => {
-            handle_get_history(hub, room_id, user_id, limit, before_id).await
+        RoomWebSocketMessage::GetHistory { room_id, user_id, limit, before_id, include_pin_state } => {
+            handle_get_history(hub, room_id, user_id, limit, before_id, include_pin_state).await
         }
         
         RoomWebSocketMessage::GetPinnedMessages { room_id, user_id } => {
             handle_get_pinned_messages(hub, room_id, user_id).await
         }
         
+        RoomWebSocketMessage::OpenRoom { room_id, user_id, limit } => {
+            handle_open_room(hub, room_id, user_id, limit).await
+        }
+        
+        RoomWebSocketMessage::GetMessageBody { message_id, user_id } => {
+            handle_get_message_body(hub, message_id, user_id).await
+        }
+        
+        RoomWebSocketMessage::MarkRoomRead { room_id, user_id, up_to_message_id } => {
+            handle_mark_room_read(hub, room_id, user_id, up_to_message_id).await
+        }
+        
+        RoomWebSocketMessage::GetUserRooms { user_id, limit, cursor } => {
+            handle_get_user_rooms(hub, user_id, limit, cursor).await
+        }
+        
+        RoomWebSocketMessage::SetRoomPinned { room_id, user_id, pinned } => {
+            handle_set_room_pinned(hub, room_id, user_id, pinned).await
+        }
+        
+        RoomWebSocketMessage::SetRoomMuted { room_id, user_id, muted } => {
+            handle_set_room_muted(hub, room_id, user_id, muted).await
+        }
+        
+        RoomWebSocketMessage::SetRoomGuestAccess { room_id, user_id, access } => {
+            handle_set_room_guest_access(hub, room_id, user_id, &access).await
+        }
+        
         // Réactions
         RoomWebSocketMessage::AddReaction { message_id, user_id, emoji } => {
             handle_add_reaction(hub, message_id, user_id, &emoji).await
@@ -87,13 +263,132 @@ pub async fn handle_room_websocket_message(
             handle_get_reactions(hub, message_id, user_id).await
         }
         
+        RoomWebSocketMessage::GetReactionChanges { user_id, message_ids, since } => {
+            handle_get_reaction_changes(hub, user_id, &message_ids, &since).await
+        }
+        
+        // Modèles de réponse
+        RoomWebSocketMessage::ListTemplates { room_id, user_id } => {
+            handle_list_templates(hub, room_id, user_id).await
+        }
+        
+        RoomWebSocketMessage::CreateTemplate { room_id, user_id, name, content, members_can_use } => {
+            handle_create_template(hub, room_id, user_id, &name, &content, members_can_use).await
+        }
+        
+        RoomWebSocketMessage::DeleteTemplate { room_id, user_id, name } => {
+            handle_delete_template(hub, room_id, user_id, &name).await
+        }
+        
+        RoomWebSocketMessage::SendTemplate { room_id, user_id, username, name, values } => {
+            handle_send_template(hub, room_id, user_id, &username, &name, values).await
+        }
+        
         // Modération
-        RoomWebSocketMessage::PinMessage { room_id, message_id, user_id } => {
-            handle_pin_message(hub, room_id, message_id, user_id, true).await
+        RoomWebSocketMessage::PinMessage { room_id, message_id, user_id, duration_seconds } => {
+            handle_pin_message(hub, room_id, message_id, user_id, true, duration_seconds.map(Duration::from_secs)).await
         }
         
         RoomWebSocketMessage::UnpinMessage { room_id, message_id, user_id } => {
-            handle_pin_message(hub, room_id, message_id, user_id, false).await
+            handle_pin_message(hub, room_id, message_id, user_id, false, None).await
+        }
+        
+        RoomWebSocketMessage::ReorderPins { room_id, user_id, message_ids } => {
+            handle_reorder_pins(hub, room_id, user_id, &message_ids).await
+        }
+        
+        RoomWebSocketMessage::ReportMessage { message_id, user_id, reason } => {
+            handle_report_message(hub, message_id, user_id, &reason).await
+        }
+        
+        RoomWebSocketMessage::SetHistoryLimit { room_id, user_id, history_limit } => {
+            handle_set_history_limit(hub, room_id, user_id, history_limit).await
+        }
+        
+        RoomWebSocketMessage::SetFilterMode { room_id, user_id, mode } => {
+            handle_set_filter_mode(hub, room_id, user_id, &mode).await
+        }
+        
+        RoomWebSocketMessage::SetLinkPolicy { room_id, user_id, policy } => {
+            handle_set_link_policy(hub, room_id, user_id, policy).await
+        }
+        
+        RoomWebSocketMessage::SetRoomLanguages { room_id, user_id, languages } => {
+            handle_set_room_languages(hub, room_id, user_id, languages).await
+        }
+        
+        RoomWebSocketMessage::UpdateAllowlistedTerm { room_id, user_id, term, add } => {
+            handle_update_allowlisted_term(hub, room_id, user_id, &term, add).await
+        }
+        
+        RoomWebSocketMessage::SetRoomEncryption { room_id, user_id, enabled } => {
+            handle_set_room_encryption(hub, room_id, user_id, enabled).await
+        }
+        
+        RoomWebSocketMessage::SendRoleNotice { room_id, user_id, role, content } => {
+            handle_send_role_notice(hub, room_id, user_id, &role, &content).await
+        }
+        
+        RoomWebSocketMessage::SetAttachmentLimits { room_id, user_id, max_count, max_total_size } => {
+            handle_set_attachment_limits(hub, room_id, user_id, max_count, max_total_size).await
+        }
+        
+        RoomWebSocketMessage::GetEmojiCatalog { room_id, known_version } => {
+            handle_get_emoji_catalog(hub, room_id, known_version.as_deref()).await
+        }
+        
+        RoomWebSocketMessage::UploadCustomEmoji { user_id, scope, shortcode, image } => {
+            handle_upload_custom_emoji(hub, user_id, scope, &shortcode, &image).await
+        }
+        
+        RoomWebSocketMessage::DeleteCustomEmoji { user_id, emoji_id } => {
+            handle_delete_custom_emoji(hub, user_id, emoji_id).await
+        }
+        
+        RoomWebSocketMessage::UploadAttachment { user_id, filename, mime_type, bytes } => {
+            handle_upload_attachment(hub, user_id, &filename, &mime_type, &bytes).await
+        }
+        
+        RoomWebSocketMessage::DeleteAttachment { user_id, file_id } => {
+            handle_delete_attachment(hub, user_id, file_id).await
+        }
+        
+        RoomWebSocketMessage::GetStorageUsage { user_id } => {
+            handle_get_storage_usage(hub, user_id).await
+        }
+        
+        RoomWebSocketMessage::GetReactionSet { room_id } => {
+            handle_get_reaction_set(hub, room_id).await
+        }
+        
+        RoomWebSocketMessage::SetReactionSet { room_id, user_id, set } => {
+            handle_set_reaction_set(hub, room_id, user_id, set).await
+        }
+        
+        RoomWebSocketMessage::SetSlowMode { room_id, user_id, mode } => {
+            handle_set_slow_mode(hub, room_id, user_id, mode).await
+        }
+        
+        RoomWebSocketMessage::SetAntiRaid { room_id, user_id, enabled, duration_seconds } => {
+            handle_set_anti_raid(hub, room_id, user_id, enabled, duration_seconds).await
+        }
+        
+        RoomWebSocketMessage::ForceDisconnect { user_id, target_user_id, message } => {
+            handle_force_disconnect(hub, user_id, target_user_id, message.as_deref()).await
+        }
+        
+        RoomWebSocketMessage::GetReputation { user_id, target_user_id } => {
+            let result = reputation::get_user_reputation(hub, user_id, target_user_id).await;
+            reputation_response(result, "get_reputation")
+        }
+        
+        RoomWebSocketMessage::AdjustReputation { user_id, target_user_id, delta, reason } => {
+            let result = reputation::adjust_user_reputation(hub, user_id, target_user_id, delta, &reason).await;
+            reputation_response(result, "adjust_reputation")
+        }
+        
+        RoomWebSocketMessage::SetRoomArchived { room_id, user_id, archived } => {
+            handle_set_room_archived(hub, room_id, user_id, archived).await
         }
         
         // Administration
@@ -101,13 +396,85 @@ pub async fn handle_room_websocket_message(
             handle_get_room_stats(hub, room_id, user_id).await
         }
         
-        RoomWebSocketMessage::GetMembers { room_id, user_id } => {
-            handle_get_members(hub, room_id, user_id).await
+        RoomWebSocketMessage::GetMembers { room_id, user_id, limit, cursor } => {
+            handle_get_members(hub, room_id, user_id, limit, cursor).await
         }
         
         RoomWebSocketMessage::GetAuditLogs { room_id, user_id, limit } => {
             handle_get_audit_logs(hub, room_id, user_id, limit).await
         }
+        
+        RoomWebSocketMessage::GetModerationQueue { user_id, limit, cursor } => {
+            handle_get_moderation_queue(hub, user_id, limit, cursor).await
+        }
+        
+        RoomWebSocketMessage::GetUserViolations { user_id, target_user_id } => {
+            handle_get_user_violations(hub, user_id, target_user_id).await
+        }
+        
+        RoomWebSocketMessage::ReviewHeldMessage { message_id, user_id, approve, note } => {
+            handle_review_held_message(hub, message_id, user_id, approve, note.as_deref()).await
+        }
+        
+        RoomWebSocketMessage::GetFeatureFlags => {
+            Ok(Some(hub.get_feature_flags().await.to_frame()))
+        }
+        
+        RoomWebSocketMessage::GetCapabilities { room_id, etag } => {
+            handle_get_capabilities(hub, room_id, etag.as_deref()).await
+        }
+        
+        RoomWebSocketMessage::SetFeatureFlag { user_id, flag, enabled } => {
+            handle_set_feature_flag(hub, user_id, &flag, enabled).await
+        }
+        
+        RoomWebSocketMessage::Resume { user_id, rooms } => {
+            handle_resume(hub, user_id, &rooms).await
+        }
+        
+        RoomWebSocketMessage::GetMissedEvents { user_id, since, cursor, limit } => {
+            handle_get_missed_events(hub, user_id, since.as_deref(), cursor.as_deref(), limit).await
+        }
+        
+        RoomWebSocketMessage::GetQuota { user_id } => {
+            handle_get_quota(hub, user_id).await
+        }
+        
+        RoomWebSocketMessage::SubscribePresence { user_id, users, rooms } => {
+            handle_subscribe_presence(hub, user_id, &users, &rooms).await
+        }
+        
+        RoomWebSocketMessage::UnsubscribePresence { user_id, users, rooms } => {
+            presence_subscriptions::unsubscribe_presence(hub, user_id as i32, &users, &rooms).await;
+            Ok(Some(json!({
+                "type": "presence_unsubscribed",
+                "data": { "users": users, "rooms": rooms }
+            }).to_string()))
+        }
+        
+        RoomWebSocketMessage::SetStatus { user_id, status, message } => {
+            handle_set_status(hub, user_id, status, message).await
+        }
+        
+        RoomWebSocketMessage::GetUserProfiles { user_id, user_ids } => {
+            handle_get_user_profiles(hub, user_id, &user_ids).await
+        }
+        
+        RoomWebSocketMessage::NegotiateSchema { user_id, schema_version } => {
+            let version = hub.negotiate_schema(user_id as i32, schema_version).await;
+            Ok(Some(json!({
+                "type": "schema_negotiated",
+                "data": {
+                    "schemaVersion": version,
+                    "currentVersion": CURRENT_SCHEMA_VERSION
+                }
+            }).to_string()))
+        }
+        
+        // Diagnostic
+        RoomWebSocketMessage::PingDiag { user_id, correlation_id, client_time } => {
+            handle_ping_diag(hub, user_id, correlation_id.as_deref(), client_time).await
+        }
     }
 }
 
@@ -145,6 +512,55 @@ async fn handle_join_room(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<O
     }
 }
 
+async fn handle_guest_join_room(hub: &ChatHub, room_id: i64, guest_id: i32) -> Result<Option<String>> {
+    match guests::join_room_as_guest(hub, room_id, guest_id).await {
+        Ok(access) => Ok(Some(json!({
+            "type": "room_joined",
+            "data": {
+                "roomId": room_id,
+                "userId": guest_id,
+                "guest": true,
+                "guestAccess": access.as_str(),
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, guest_id = %guest_id, error = %e, "❌ Invité refusé dans le salon");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "join_room",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_guest_send_message(hub: &ChatHub, room_id: i64, guest_id: i32, content: &str) -> Result<Option<String>> {
+    match guests::send_guest_message(hub, room_id, guest_id, content).await {
+        Ok(message_id) => Ok(Some(json!({
+            "type": "message_sent",
+            "data": {
+                "messageId": message_id,
+                "roomId": room_id,
+                "guest": true,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, guest_id = %guest_id, error = %e, "❌ Message d'invité refusé");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "send_message",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
 async fn handle_leave_room(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
     info!(room_id = %room_id, user_id = %user_id, "🚪 Tentative de quitter le salon");
     
@@ -181,21 +597,29 @@ async fn handle_send_message(
     user_id: i64,
     username: &str,
     content: &str,
-    parent_id: Option<i64>
+    parent_id: Option<i64>,
+    visible_to: Option<Vec<i32>>,
+    nonce: Option<String>,
+    ack: Option<bool>
 ) -> Result<Option<String>> {
     info!(room_id = %room_id, user_id = %user_id, content_length = %content.len(), "📝 Envoi de message dans le salon");
     
-    match room_enhanced::send_room_message(hub, room_id, user_id, username, content, parent_id, None).await {
-        Ok(message_id) => {
-            info!(room_id = %room_id, message_id = %message_id, "✅ Message envoyé dans le salon");
-            Ok(Some(json!({
+    let metadata = nonce.as_ref().map(|nonce| json!({ "nonce": nonce }));
+    let ack_mode = hub.ack_mode_of(user_id as i32).await.for_command(ack);
+    
+    match room_enhanced::send_room_message(hub, room_id, user_id, username, content, parent_id, metadata, visible_to).await {
+        Ok(sent) => {
+            info!(room_id = %room_id, message_id = %sent.id, "✅ Message envoyé dans le salon");
+            Ok(ack_mode.ack(json!({
                 "type": "message_sent",
                 "data": {
-                    "messageId": message_id,
+                    "messageId": sent.id,
                     "roomId": room_id,
+                    "nonce": nonce,
+                    "timestamp": sent.created_at,
                     "success": true
                 }
-            }).to_string()))
+            })))
         }
         Err(e) => {
             warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec d'envoi de message");
@@ -203,40 +627,60 @@ async fn handle_send_message(
                 "type": "error",
                 "data": {
                     "action": "send_message",
-                    "error": e.to_string()
+                    "error": e.to_string(),
+                    "retryAfter": e.retry_after()
                 }
             }).to_string()))
         }
     }
 }
 
-async fn handle_get_history(
-    hub: &ChatHub,
-    room_id: i64,
-    user_id: i64,
-    limit: i64,
-    before_id: Option<i64>
-) -> Result<Option<String>> {
-    info!(room_id = %room_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique du salon");
+async fn handle_set_ack_mode(hub: &ChatHub, user_id: i64, mode: &str) -> Result<Option<String>> {
+    let result = match AckMode::from_name(mode) {
+        Some(mode) => hub.set_ack_mode(user_id as i32, mode).await.map(|_| mode),
+        None => Err(ChatError::InvalidFormat {
+            field: "mode".to_string(),
+            reason: "Valeurs acceptées : confirm, fire_and_forget".to_string(),
+        }),
+    };
     
-    match room_enhanced::fetch_room_history(hub, room_id, user_id, limit, before_id).await {
-        Ok(messages) => {
-            info!(room_id = %room_id, message_count = %messages.len(), "✅ Historique récupéré");
+    match result {
+        Ok(mode) => Ok(Some(json!({
+            "type": "ack_mode_updated",
+            "data": { "mode": mode.as_str() }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec du réglage de l'accusé des envois");
             Ok(Some(json!({
-                "type": "room_history",
+                "type": "error",
                 "data": {
-                    "roomId": room_id,
-                    "messages": messages,
-                    "hasMore": messages.len() as i64 == limit
+                    "action": "set_ack_mode",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_edit_message(hub: &ChatHub, room_id: i64, message_id: i64, user_id: i64, content: &str) -> Result<Option<String>> {
+    info!(room_id = %room_id, message_id = %message_id, user_id = %user_id, "✏️ Édition de message");
+    
+    // Fenêtre d'édition et verrous : voir `MessagePolicy`
+    match room_enhanced::edit_room_message(hub, room_id, message_id, user_id, content).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "message_edited",
+            "data": {
+                "messageId": message_id,
+                "roomId": room_id,
+                "success": true
+            }
+        }).to_string())),
         Err(e) => {
-            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération de l'historique");
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de l'édition du message");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "get_history",
+                    "action": "edit_message",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -244,26 +688,43 @@ async fn handle_get_history(
     }
 }
 
-async fn handle_get_pinned_messages(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
-    info!(room_id = %room_id, user_id = %user_id, "📌 Récupération des messages épinglés");
+async fn handle_delete_message(hub: &ChatHub, room_id: i64, message_id: i64, user_id: i64) -> Result<Option<String>> {
+    info!(room_id = %room_id, message_id = %message_id, user_id = %user_id, "🗑️ Suppression de message");
     
-    match room_enhanced::fetch_pinned_messages(hub, room_id, user_id).await {
-        Ok(messages) => {
-            info!(room_id = %room_id, pinned_count = %messages.len(), "✅ Messages épinglés récupérés");
+    match room_enhanced::delete_room_message(hub, room_id, message_id, user_id).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "message_deleted",
+            "data": {
+                "messageId": message_id,
+                "roomId": room_id,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de la suppression du message");
             Ok(Some(json!({
-                "type": "pinned_messages",
+                "type": "error",
                 "data": {
-                    "roomId": room_id,
-                    "messages": messages
+                    "action": "delete_message",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_get_quota(hub: &ChatHub, user_id: i64) -> Result<Option<String>> {
+    match quotas::get_message_quota(hub, user_id).await {
+        Ok(status) => Ok(Some(json!({
+            "type": "message_quota",
+            "data": status
+        }).to_string())),
         Err(e) => {
-            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des messages épinglés");
+            warn!(user_id = %user_id, error = %e, "❌ Échec de lecture du quota");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "get_pinned_messages",
+                    "action": "get_quota",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -271,28 +732,34 @@ async fn handle_get_pinned_messages(hub: &ChatHub, room_id: i64, user_id: i64) -
     }
 }
 
-async fn handle_add_reaction(hub: &ChatHub, message_id: i64, user_id: i64, emoji: &str) -> Result<Option<String>> {
-    info!(message_id = %message_id, user_id = %user_id, emoji = %emoji, "😊 Ajout de réaction");
-    
-    match reactions::add_reaction(hub, message_id, user_id, emoji).await {
-        Ok(()) => {
-            info!(message_id = %message_id, emoji = %emoji, "✅ Réaction ajoutée");
+async fn handle_subscribe_presence(hub: &ChatHub, user_id: i64, users: &[i32], rooms: &[RoomId]) -> Result<Option<String>> {
+    match presence_subscriptions::subscribe_presence(hub, user_id as i32, users, rooms).await {
+        Ok(snapshot) => Ok(Some(snapshot.to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec de l'abonnement à la présence");
             Ok(Some(json!({
-                "type": "reaction_added",
+                "type": "error",
                 "data": {
-                    "messageId": message_id,
-                    "userId": user_id,
-                    "emoji": emoji,
-                    "success": true
+                    "action": "subscribe_presence",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_get_user_profiles(hub: &ChatHub, user_id: i64, user_ids: &[i64]) -> Result<Option<String>> {
+    match profiles::get_user_profiles(hub, user_id, user_ids).await {
+        Ok(profiles) => Ok(Some(json!({
+            "type": "user_profiles",
+            "data": { "profiles": profiles }
+        }).to_string())),
         Err(e) => {
-            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec d'ajout de réaction");
+            warn!(user_id = %user_id, error = %e, "❌ Échec de la résolution des profils");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "add_reaction",
+                    "action": "get_user_profiles",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -300,28 +767,38 @@ async fn handle_add_reaction(hub: &ChatHub, message_id: i64, user_id: i64, emoji
     }
 }
 
-async fn handle_remove_reaction(hub: &ChatHub, message_id: i64, user_id: i64, emoji: &str) -> Result<Option<String>> {
-    info!(message_id = %message_id, user_id = %user_id, emoji = %emoji, "🗑️ Suppression de réaction");
-    
-    match reactions::remove_reaction(hub, message_id, user_id, emoji).await {
-        Ok(()) => {
-            info!(message_id = %message_id, emoji = %emoji, "✅ Réaction supprimée");
+async fn handle_set_status(hub: &ChatHub, user_id: i64, status: Value, message: Option<String>) -> Result<Option<String>> {
+    let result = match serde_json::from_value::<UserStatus>(status) {
+        Ok(status) => presence_subscriptions::set_presence_status(hub, user_id as i32, status.clone(), message).await.map(|()| status),
+        Err(e) => Err(ChatError::configuration_error(&format!("Statut inconnu: {}", e))),
+    };
+    match result {
+        Ok(status) => Ok(Some(json!({
+            "type": "status_updated",
+            "data": { "status": status }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec du changement de statut");
             Ok(Some(json!({
-                "type": "reaction_removed",
+                "type": "error",
                 "data": {
-                    "messageId": message_id,
-                    "userId": user_id,
-                    "emoji": emoji,
-                    "success": true
+                    "action": "set_status",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_mark_room_read(hub: &ChatHub, room_id: i64, user_id: i64, up_to_message_id: i64) -> Result<Option<String>> {
+    match read_receipts::mark_room_read(hub, user_id, room_id, up_to_message_id).await {
+        Ok(marker) => Ok(Some(marker.to_frame().to_string())),
         Err(e) => {
-            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de suppression de réaction");
+            warn!(user_id = %user_id, room_id = %room_id, error = %e, "❌ Échec de mise à jour du marqueur de lecture");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "remove_reaction",
+                    "action": "mark_room_read",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -329,23 +806,26 @@ async fn handle_remove_reaction(hub: &ChatHub, message_id: i64, user_id: i64, em
     }
 }
 
-async fn handle_get_reactions(hub: &ChatHub, message_id: i64, user_id: i64) -> Result<Option<String>> {
-    info!(message_id = %message_id, user_id = %user_id, "📊 Récupération des réactions");
-    
-    match reactions::get_message_reactions(hub, message_id, user_id).await {
-        Ok(message_reactions) => {
-            info!(message_id = %message_id, total_reactions = %message_reactions.total_reactions, "✅ Réactions récupérées");
+async fn handle_get_user_rooms(hub: &ChatHub, user_id: i64, limit: i64, cursor: Option<i64>) -> Result<Option<String>> {
+    match room_list::get_user_rooms(hub, user_id, limit, cursor).await {
+        Ok(page) => {
+            info!(user_id = %user_id, room_count = %page.len(), "✅ Salons rejoints listés");
             Ok(Some(json!({
-                "type": "message_reactions",
-                "data": message_reactions
+                "type": "user_rooms",
+                "data": {
+                    "rooms": page.items,
+                    "total": page.total,
+                    "hasMore": page.has_more,
+                    "nextCursor": page.next_cursor
+                }
             }).to_string()))
         }
         Err(e) => {
-            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de récupération des réactions");
+            warn!(user_id = %user_id, error = %e, "❌ Échec de liste des salons rejoints");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "get_reactions",
+                    "action": "get_user_rooms",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -353,29 +833,1122 @@ async fn handle_get_reactions(hub: &ChatHub, message_id: i64, user_id: i64) -> R
     }
 }
 
-async fn handle_pin_message(hub: &ChatHub, room_id: i64, message_id: i64, user_id: i64, pin: bool) -> Result<Option<String>> {
-    let action_text = if pin { "épinglage" } else { "désépinglage" };
-    info!(room_id = %room_id, message_id = %message_id, user_id = %user_id, pin = %pin, "📌 {} de message", action_text);
+async fn handle_set_room_pinned(hub: &ChatHub, room_id: i64, user_id: i64, pinned: bool) -> Result<Option<String>> {
+    match room_list::set_room_pinned(hub, room_id, user_id, pinned).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "room_pinned",
+            "data": {
+                "roomId": room_id,
+                "pinned": pinned
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec d'épinglage du salon dans la liste");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_room_pinned",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_room_guest_access(hub: &ChatHub, room_id: i64, user_id: i64, access: &str) -> Result<Option<String>> {
+    let result = match guests::GuestAccess::from_name(access) {
+        Some(access) => guests::set_room_guest_access(hub, room_id, user_id, access).await.map(|_| access),
+        None => Err(ChatError::InvalidFormat {
+            field: "access".to_string(),
+            reason: "Valeurs acceptées : closed, read_only, limited".to_string(),
+        }),
+    };
+    
+    match result {
+        Ok(access) => Ok(Some(json!({
+            "type": "room_guest_access",
+            "data": {
+                "roomId": room_id,
+                "access": access.as_str()
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du changement d'accès des invités");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_room_guest_access",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_room_muted(hub: &ChatHub, room_id: i64, user_id: i64, muted: bool) -> Result<Option<String>> {
+    match mutes::set_room_muted(hub, room_id, user_id, muted).await {
+        Ok(()) => Ok(Some(mutes::mute_state_frame("room", room_id, muted).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de la mise en sourdine du salon");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_room_muted",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_history(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    limit: i64,
+    before_id: Option<i64>,
+    include_pin_state: bool
+) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique du salon");
+    
+    let history = match before_id {
+        Some(before_id) => room_enhanced::fetch_room_history(hub, room_id, user_id, limit, Some(before_id)).await,
+        None => room_enhanced::get_latest_room_messages(hub, room_id, user_id, limit).await,
+    };
+    
+    match history {
+        Ok(page) => {
+            info!(room_id = %room_id, message_count = %page.len(), "✅ Historique récupéré");
+            let mut data = json!({
+                "roomId": room_id,
+                "hasMore": page.has_more,
+                "nextCursor": page.next_cursor
+            });
+            if include_pin_state {
+                data["pinnedMessageIds"] = json!(room_enhanced::pinned_ids(&page.items));
+            }
+            data["messages"] = json!(page.items);
+            Ok(Some(hub.render_frame_for(user_id, &message_frame("room_history", data)).await))
+        }
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération de l'historique");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_history",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_pinned_messages(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, "📌 Récupération des messages épinglés");
+    
+    match room_enhanced::fetch_pinned_messages(hub, room_id, user_id).await {
+        Ok(messages) => {
+            info!(room_id = %room_id, pinned_count = %messages.len(), "✅ Messages épinglés récupérés");
+            let frame = message_frame("pinned_messages", json!({
+                "roomId": room_id,
+                "messages": messages
+            }));
+            Ok(Some(hub.render_frame_for(user_id, &frame).await))
+        }
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des messages épinglés");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_pinned_messages",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_open_room(hub: &ChatHub, room_id: i64, user_id: i64, limit: i64) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, limit = %limit, "🚪 Ouverture du salon");
+    
+    match room_enhanced::open_room(hub, room_id, user_id, limit).await {
+        Ok(opening) => {
+            let frame = message_frame("room_opened", json!({
+                "roomId": room_id,
+                "pinned": opening.pinned,
+                "messages": opening.history.items,
+                "hasMore": opening.history.has_more,
+                "nextCursor": opening.history.next_cursor
+            }));
+            Ok(Some(hub.render_frame_for(user_id, &frame).await))
+        }
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de l'ouverture du salon");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "open_room",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_add_reaction(hub: &ChatHub, message_id: i64, user_id: i64, emoji: &str) -> Result<Option<String>> {
+    info!(message_id = %message_id, user_id = %user_id, emoji = %emoji, "😊 Ajout de réaction");
+    
+    match reactions::add_reaction(hub, message_id, user_id, emoji).await {
+        Ok(()) => {
+            info!(message_id = %message_id, emoji = %emoji, "✅ Réaction ajoutée");
+            Ok(Some(json!({
+                "type": "reaction_added",
+                "data": {
+                    "messageId": message_id,
+                    "userId": user_id,
+                    "emoji": emoji,
+                    "success": true
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec d'ajout de réaction");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "add_reaction",
+                    "error": e.to_string(),
+                    "retryAfter": e.retry_after()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_remove_reaction(hub: &ChatHub, message_id: i64, user_id: i64, emoji: &str) -> Result<Option<String>> {
+    info!(message_id = %message_id, user_id = %user_id, emoji = %emoji, "🗑️ Suppression de réaction");
     
-    match room_enhanced::pin_message(hub, room_id, message_id, user_id, pin).await {
+    match reactions::remove_reaction(hub, message_id, user_id, emoji).await {
         Ok(()) => {
+            info!(message_id = %message_id, emoji = %emoji, "✅ Réaction supprimée");
+            Ok(Some(json!({
+                "type": "reaction_removed",
+                "data": {
+                    "messageId": message_id,
+                    "userId": user_id,
+                    "emoji": emoji,
+                    "success": true
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de suppression de réaction");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "remove_reaction",
+                    "error": e.to_string(),
+                    "retryAfter": e.retry_after()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_reactions(hub: &ChatHub, message_id: i64, user_id: i64) -> Result<Option<String>> {
+    info!(message_id = %message_id, user_id = %user_id, "📊 Récupération des réactions");
+    
+    match reactions::get_message_reactions(hub, message_id, user_id).await {
+        Ok(message_reactions) => {
+            info!(message_id = %message_id, total_reactions = %message_reactions.total_reactions, "✅ Réactions récupérées");
+            Ok(Some(json!({
+                "type": "message_reactions",
+                "data": message_reactions
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de récupération des réactions");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_reactions",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_reaction_changes(hub: &ChatHub, user_id: i64, message_ids: &[i64], since: &str) -> Result<Option<String>> {
+    info!(user_id = %user_id, messages = %message_ids.len(), "🔁 Récupération des changements de réactions");
+    
+    let result = match chrono::DateTime::parse_from_rfc3339(since) {
+        Ok(since) => reactions::get_reaction_changes(hub, user_id, message_ids, since.with_timezone(&chrono::Utc)).await,
+        Err(_) => Err(ChatError::InvalidFormat { field: "since".to_string(), reason: "date RFC 3339 attendue".to_string() }),
+    };
+    
+    match result {
+        Ok(changes) => Ok(Some(json!({
+            "type": "reaction_changes",
+            "data": {
+                "since": since,
+                "changes": changes
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec de récupération des changements de réactions");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_reaction_changes",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_message_body(hub: &ChatHub, message_id: i64, user_id: i64) -> Result<Option<String>> {
+    info!(message_id = %message_id, user_id = %user_id, "📄 Récupération du corps complet d'un message");
+    
+    match long_messages::get_message_body(hub, message_id, user_id).await {
+        Ok(body) => Ok(Some(json!({
+            "type": "message_body",
+            "data": body
+        }).to_string())),
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de récupération du corps complet");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_message_body",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_pin_message(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    user_id: i64,
+    pin: bool,
+    pinned_for: Option<Duration>
+) -> Result<Option<String>> {
+    let action_text = if pin { "épinglage" } else { "désépinglage" };
+    info!(room_id = %room_id, message_id = %message_id, user_id = %user_id, pin = %pin, "📌 {} de message", action_text);
+    
+    match room_enhanced::pin_message(hub, room_id, message_id, user_id, pin, pinned_for).await {
+        Ok(pinned_until) => {
             info!(message_id = %message_id, pin = %pin, "✅ Statut d'épinglage mis à jour");
             Ok(Some(json!({
-                "type": if pin { "message_pinned" } else { "message_unpinned" },
+                "type": if pin { "message_pinned" } else { "message_unpinned" },
+                "data": {
+                    "messageId": message_id,
+                    "roomId": room_id,
+                    "isPinned": pin,
+                    "pinnedUntil": pinned_until,
+                    "success": true
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de {} de message", action_text);
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": if pin { "pin_message" } else { "unpin_message" },
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_reorder_pins(hub: &ChatHub, room_id: i64, user_id: i64, message_ids: &[i64]) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, "📌 Réordonnancement des épingles");
+    
+    match room_enhanced::reorder_pins(hub, room_id, message_ids, user_id).await {
+        Ok(ordered) => Ok(Some(json!({
+            "type": "pins_reordered",
+            "data": {
+                "roomId": room_id,
+                "messageIds": ordered,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réordonnancement des épingles");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "reorder_pins",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_room_stats(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, "📊 Récupération des statistiques du salon");
+    
+    match room_enhanced::get_room_stats(hub, room_id).await {
+        Ok(stats) => {
+            info!(room_id = %room_id, "✅ Statistiques récupérées");
+            Ok(Some(json!({
+                "type": "room_stats",
+                "data": stats
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des statistiques");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_room_stats",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_members(hub: &ChatHub, room_id: i64, user_id: i64, limit: i64, cursor: Option<i64>) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, "👥 Récupération de la liste des membres");
+    
+    match room_enhanced::list_room_members(hub, room_id, user_id, limit, cursor).await {
+        Ok(page) => {
+            info!(room_id = %room_id, member_count = %page.len(), "✅ Liste des membres récupérée");
+            Ok(Some(json!({
+                "type": "room_members",
+                "data": {
+                    "roomId": room_id,
+                    "members": page.items,
+                    "hasMore": page.has_more,
+                    "nextCursor": page.next_cursor
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des membres");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_members",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_audit_logs(hub: &ChatHub, room_id: i64, user_id: i64, limit: i64) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, limit = %limit, "📋 Récupération des logs d'audit");
+    
+    match audit::get_room_audit_logs(hub, room_id, user_id, limit, None).await {
+        Ok(logs) => {
+            info!(room_id = %room_id, log_count = %logs.len(), "✅ Logs d'audit récupérés");
+            Ok(Some(json!({
+                "type": "audit_logs",
+                "data": {
+                    "roomId": room_id,
+                    "logs": logs
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des logs d'audit");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_audit_logs",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_report_message(hub: &ChatHub, message_id: i64, user_id: i64, reason: &str) -> Result<Option<String>> {
+    info!(message_id = %message_id, user_id = %user_id, "🚩 Signalement de message");
+    
+    match reports::report_message(hub, message_id, user_id, reason).await {
+        Ok(outcome) => Ok(Some(json!({
+            "type": "message_reported",
+            "data": outcome
+        }).to_string())),
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec du signalement");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "report_message",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_slow_mode(hub: &ChatHub, room_id: i64, user_id: i64, mode: slow_mode::SlowModeOverride) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, mode = ?mode, "🐢 Réglage du mode lent");
+    
+    match slow_mode::set_room_slow_mode(hub, room_id, user_id, mode).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "slow_mode_updated",
+            "data": {
+                "roomId": room_id,
+                "slowModeSeconds": mode.to_db(),
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage du mode lent");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_slow_mode",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_anti_raid(hub: &ChatHub, room_id: Option<i64>, user_id: i64, enabled: bool, duration_seconds: Option<u64>) -> Result<Option<String>> {
+    info!(room_id = ?room_id, user_id = %user_id, enabled = %enabled, "🛡️ Réglage du mode anti-raid");
+    
+    let scope = room_id.map_or(anti_raid::RaidScope::Global, anti_raid::RaidScope::Room);
+    match anti_raid::set_anti_raid(hub, scope, user_id, enabled, duration_seconds.map(Duration::from_secs)).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "anti_raid_updated",
+            "data": {
+                "roomId": room_id,
+                "enabled": enabled,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = ?room_id, user_id = %user_id, error = %e, "❌ Échec du réglage du mode anti-raid");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_anti_raid",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_force_disconnect(hub: &ChatHub, user_id: i64, target_user_id: i32, message: Option<&str>) -> Result<Option<String>> {
+    info!(user_id = %user_id, target_user_id = %target_user_id, "🔌 Déconnexion imposée");
+    
+    match hub.force_disconnect(user_id, target_user_id, message).await {
+        Ok(closed) => Ok(Some(json!({
+            "type": "user_disconnected",
+            "data": {
+                "userId": target_user_id,
+                "closedConnections": closed,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, target_user_id = %target_user_id, error = %e, "❌ Échec de la déconnexion imposée");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "force_disconnect",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+fn reputation_response(result: Result<reputation::UserReputation>, action: &str) -> Result<Option<String>> {
+    match result {
+        Ok(user_reputation) => Ok(Some(json!({
+            "type": "user_reputation",
+            "data": user_reputation
+        }).to_string())),
+        Err(e) => {
+            warn!(action = %action, error = %e, "❌ Échec de l'accès à la réputation");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": action,
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_history_limit(hub: &ChatHub, room_id: i64, user_id: i64, history_limit: Option<i32>) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, history_limit = ?history_limit, "📜 Réglage de l'historique des nouveaux membres");
+    
+    match room_enhanced::set_joinable_history_limit(hub, room_id, user_id, history_limit).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "history_limit_updated",
+            "data": {
+                "roomId": room_id,
+                "joinableHistoryLimit": history_limit,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage de l'historique");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_history_limit",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_filter_mode(hub: &ChatHub, room_id: i64, user_id: i64, mode: &str) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, mode = %mode, "🧹 Réglage du filtrage du salon");
+    
+    let result = match held_messages::RoomFilterMode::parse(mode) {
+        Ok(mode) => room_enhanced::set_room_filter_mode(hub, room_id, user_id, mode).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Ok(Some(json!({
+            "type": "filter_mode_updated",
+            "data": {
+                "roomId": room_id,
+                "mode": mode,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage du filtrage");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_filter_mode",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_room_languages(hub: &ChatHub, room_id: i64, user_id: i64, languages: Vec<String>) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, languages = ?languages, "🌐 Réglage des langues du salon");
+    
+    match room_enhanced::set_room_languages(hub, room_id, user_id, languages.clone()).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "room_languages_updated",
+            "data": {
+                "roomId": room_id,
+                "languages": languages,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage des langues du salon");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_room_languages",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_update_allowlisted_term(hub: &ChatHub, room_id: i64, user_id: i64, term: &str, add: bool) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, term = %term, add = %add, "✅ Réglage des termes autorisés du salon");
+    
+    let (action, result) = if add {
+        ("add_allowlisted_term", room_enhanced::add_allowlisted_term(hub, room_id, user_id, term).await)
+    } else {
+        ("remove_allowlisted_term", room_enhanced::remove_allowlisted_term(hub, room_id, user_id, term).await)
+    };
+    
+    match result {
+        Ok(term) => Ok(Some(json!({
+            "type": "room_allowlist_updated",
+            "data": {
+                "roomId": room_id,
+                "term": term,
+                "allowed": add,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage des termes autorisés");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": action,
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_room_encryption(hub: &ChatHub, room_id: i64, user_id: i64, enabled: bool) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, enabled = %enabled, "🔐 Réglage du chiffrement au repos du salon");
+    
+    match encrypted_rooms::set_room_encryption(hub, room_id, user_id, enabled).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "room_encryption_updated",
+            "data": {
+                "roomId": room_id,
+                "encryptAtRest": enabled,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage du chiffrement au repos");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_room_encryption",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_send_role_notice(hub: &ChatHub, room_id: i64, user_id: i64, role: &str, content: &str) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, role = %role, "📣 Avis restreint à un rôle");
+    
+    let sent = match Role::from_string(role) {
+        Ok(role) => room_enhanced::send_role_notice(hub, room_id, user_id, &role, content).await,
+        Err(e) => Err(e),
+    };
+    
+    match sent {
+        Ok(delivered) => Ok(Some(json!({
+            "type": "role_notice_sent",
+            "data": {
+                "roomId": room_id,
+                "role": role,
+                "delivered": delivered,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de l'avis restreint");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "send_role_notice",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_attachment_limits(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    max_count: Option<i32>,
+    max_total_size: Option<i64>
+) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, max_count = ?max_count, max_total_size = ?max_total_size, "📎 Réglage des pièces jointes du salon");
+    
+    match room_enhanced::set_room_attachment_limits(hub, room_id, user_id, max_count, max_total_size).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "attachment_limits_updated",
+            "data": {
+                "roomId": room_id,
+                "maxAttachments": max_count,
+                "maxTotalSize": max_total_size,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage des pièces jointes du salon");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_attachment_limits",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_link_policy(hub: &ChatHub, room_id: i64, user_id: i64, policy: Option<Value>) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, custom = %policy.is_some(), "🔗 Réglage de la politique des liens");
+    
+    let result = match policy.map(serde_json::from_value::<RoomLinkPolicy>).transpose() {
+        Ok(policy) => room_enhanced::set_room_link_policy(hub, room_id, user_id, policy.clone()).await.map(|()| policy),
+        Err(e) => Err(ChatError::configuration_error(&format!("Politique des liens invalide: {}", e))),
+    };
+    match result {
+        Ok(policy) => Ok(Some(json!({
+            "type": "link_policy_updated",
+            "data": {
+                "roomId": room_id,
+                "policy": policy,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage de la politique des liens");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_link_policy",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_emoji_catalog(hub: &ChatHub, room_id: Option<i64>, known_version: Option<&str>) -> Result<Option<String>> {
+    match custom_emojis::emoji_catalog(hub, room_id).await {
+        // Le client a déjà cette version en cache
+        Ok(catalog) if known_version == Some(catalog.version.as_str()) => Ok(Some(json!({
+            "type": "emoji_catalog",
+            "data": {
+                "roomId": room_id,
+                "version": catalog.version,
+                "unchanged": true
+            }
+        }).to_string())),
+        Ok(catalog) => Ok(Some(json!({
+            "type": "emoji_catalog",
+            "data": {
+                "roomId": room_id,
+                "version": catalog.version,
+                "unchanged": false,
+                "emojis": catalog.emojis
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = ?room_id, error = %e, "❌ Échec de lecture du catalogue d'émojis");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_emoji_catalog",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_capabilities(hub: &ChatHub, room_id: Option<i64>, known_etag: Option<&str>) -> Result<Option<String>> {
+    match capabilities::get_capabilities(hub, room_id).await {
+        Ok(capabilities) => Ok(Some(capabilities.to_frame(known_etag).to_string())),
+        Err(e) => {
+            warn!(room_id = ?room_id, error = %e, "❌ Échec de lecture des capacités");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_capabilities",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_upload_custom_emoji(
+    hub: &ChatHub,
+    user_id: i64,
+    scope: custom_emojis::EmojiScope,
+    shortcode: &str,
+    image: &[u8]
+) -> Result<Option<String>> {
+    info!(user_id = %user_id, scope = ?scope, shortcode = %shortcode, "🖼️ Téléversement d'émoji");
+    
+    match custom_emojis::upload_custom_emoji(hub, user_id, scope, shortcode, image).await {
+        Ok(emoji) => Ok(Some(json!({
+            "type": "custom_emoji_uploaded",
+            "data": {
+                "emoji": emoji,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, shortcode = %shortcode, error = %e, "❌ Échec du téléversement d'émoji");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "upload_custom_emoji",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_delete_custom_emoji(hub: &ChatHub, user_id: i64, emoji_id: i64) -> Result<Option<String>> {
+    info!(user_id = %user_id, emoji_id = %emoji_id, "🗑️ Suppression d'émoji");
+    
+    match custom_emojis::delete_custom_emoji(hub, user_id, emoji_id).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "custom_emoji_deleted",
+            "data": {
+                "emojiId": emoji_id,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, emoji_id = %emoji_id, error = %e, "❌ Échec de suppression d'émoji");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "delete_custom_emoji",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_upload_attachment(hub: &ChatHub, user_id: i64, filename: &str, mime_type: &str, bytes: &[u8]) -> Result<Option<String>> {
+    info!(user_id = %user_id, size = %bytes.len(), "📎 Téléversement de pièce jointe");
+    
+    match attachments::upload_attachment(hub, user_id, filename, mime_type, bytes).await {
+        Ok(attachment) => Ok(Some(json!({
+            "type": "attachment_uploaded",
+            "data": {
+                "attachment": attachment,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec du téléversement de pièce jointe");
+            let mut data = json!({
+                "action": "upload_attachment",
+                "error": e.to_string()
+            });
+            // Quota dépassé : occupation actuelle pour le client
+            if let ChatError::StorageQuotaExceeded { used, quota, .. } = e {
+                data["usedBytes"] = json!(used);
+                data["quotaBytes"] = json!(quota);
+            }
+            Ok(Some(json!({
+                "type": "error",
+                "data": data
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_delete_attachment(hub: &ChatHub, user_id: i64, file_id: i64) -> Result<Option<String>> {
+    info!(user_id = %user_id, file_id = %file_id, "🗑️ Suppression de pièce jointe");
+    
+    match attachments::delete_attachment(hub, user_id, file_id).await {
+        Ok(usage) => Ok(Some(json!({
+            "type": "attachment_deleted",
+            "data": {
+                "fileId": file_id,
+                "usage": usage,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, file_id = %file_id, error = %e, "❌ Échec de suppression de pièce jointe");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "delete_attachment",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_storage_usage(hub: &ChatHub, user_id: i64) -> Result<Option<String>> {
+    match attachments::get_storage_usage(hub, user_id).await {
+        Ok(usage) => Ok(Some(json!({
+            "type": "storage_usage",
+            "data": usage
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec de lecture de l'occupation du stockage");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_storage_usage",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_get_reaction_set(hub: &ChatHub, room_id: i64) -> Result<Option<String>> {
+    match reaction_sets::get_room_reaction_set(hub, room_id).await {
+        Ok(set) => Ok(Some(json!({
+            "type": "reaction_set",
+            "data": {
+                "roomId": room_id,
+                "reactionSet": set
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, error = %e, "❌ Échec de lecture de la palette de réactions");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "get_reaction_set",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_reaction_set(hub: &ChatHub, room_id: i64, user_id: i64, set: reaction_sets::ReactionSet) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, set = ?set, "🎨 Réglage de la palette de réactions");
+    
+    match reaction_sets::set_room_reaction_set(hub, room_id, user_id, set).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "reaction_set_saved",
+            "data": {
+                "roomId": room_id,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec du réglage de la palette de réactions");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_reaction_set",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_room_archived(hub: &ChatHub, room_id: i64, user_id: i64, archived: bool) -> Result<Option<String>> {
+    info!(room_id = %room_id, user_id = %user_id, archived = %archived, "🗄️ Archivage du salon");
+    
+    match room_enhanced::set_room_archived(hub, room_id, user_id, archived).await {
+        Ok(()) => Ok(Some(json!({
+            "type": if archived { "room_archived" } else { "room_unarchived" },
+            "data": {
+                "roomId": room_id,
+                "archived": archived,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de l'archivage du salon");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": if archived { "archive_room" } else { "unarchive_room" },
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_list_rooms(hub: &ChatHub, include_archived: bool, limit: i64, cursor: Option<i64>) -> Result<Option<String>> {
+    match room_enhanced::list_public_rooms(hub, include_archived, limit, cursor).await {
+        Ok(page) => Ok(Some(json!({
+            "type": "rooms",
+            "data": {
+                "rooms": page.items,
+                "includeArchived": include_archived,
+                "hasMore": page.has_more,
+                "nextCursor": page.next_cursor
+            }
+        }).to_string())),
+        Err(e) => Ok(Some(json!({
+            "type": "error",
+            "data": {
+                "action": "list_rooms",
+                "error": e.to_string()
+            }
+        }).to_string()))
+    }
+}
+
+async fn handle_browse_rooms(
+    hub: &ChatHub,
+    user_id: i64,
+    public_only: bool,
+    joined_only: bool,
+    name_prefix: Option<String>,
+    order: Option<&str>,
+    limit: i64,
+    cursor: Option<&str>
+) -> Result<Option<String>> {
+    let result = async {
+        let order = match order {
+            Some(name) => room_directory::RoomOrder::from_name(name).ok_or_else(|| ChatError::InvalidFormat {
+                field: "order".to_string(),
+                reason: "Valeurs acceptées : member_count, recent_activity".to_string(),
+            })?,
+            None => room_directory::RoomOrder::default(),
+        };
+        let cursor = cursor.map(room_directory::RoomCursor::decode).transpose()?;
+        let filter = room_directory::RoomFilter { public_only, joined_only, name_prefix, order };
+
+        let page = room_directory::list_rooms(hub, user_id, filter, limit, cursor).await?;
+        let next_cursor = room_directory::RoomCursor::next_page(&page, order).map(|cursor| cursor.encode());
+        Ok::<_, ChatError>((page, next_cursor))
+    }.await;
+
+    match result {
+        Ok((page, next_cursor)) => Ok(Some(json!({
+            "type": "room_directory",
+            "data": {
+                "rooms": page.items,
+                "hasMore": page.has_more,
+                "nextCursor": next_cursor
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec de la liste des salons");
+            Ok(Some(json!({
+                "type": "error",
                 "data": {
-                    "messageId": message_id,
-                    "roomId": room_id,
-                    "isPinned": pin,
-                    "success": true
+                    "action": "browse_rooms",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_review_held_message(hub: &ChatHub, message_id: i64, user_id: i64, approve: bool, note: Option<&str>) -> Result<Option<String>> {
+    match held_messages::review_held_message(hub, message_id, user_id, approve, note).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "held_message_reviewed",
+            "data": {
+                "messageId": message_id,
+                "approved": approve,
+                "success": true
+            }
+        }).to_string())),
         Err(e) => {
-            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de {} de message", action_text);
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec de l'examen du message retenu");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": if pin { "pin_message" } else { "unpin_message" },
+                    "action": "review_held_message",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -383,23 +1956,45 @@ async fn handle_pin_message(hub: &ChatHub, room_id: i64, message_id: i64, user_i
     }
 }
 
-async fn handle_get_room_stats(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
-    info!(room_id = %room_id, user_id = %user_id, "📊 Récupération des statistiques du salon");
+async fn handle_get_moderation_queue(hub: &ChatHub, user_id: i64, limit: i64, cursor: Option<i64>) -> Result<Option<String>> {
+    info!(user_id = %user_id, limit = %limit, "🚩 Récupération de la file de modération");
     
-    match room_enhanced::get_room_stats(hub, room_id).await {
-        Ok(stats) => {
-            info!(room_id = %room_id, "✅ Statistiques récupérées");
+    match reports::get_moderation_queue(hub, user_id, limit, cursor).await {
+        Ok(page) => Ok(Some(json!({
+            "type": "moderation_queue",
+            "data": {
+                "messages": page.items,
+                "hasMore": page.has_more,
+                "nextCursor": page.next_cursor
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec de récupération de la file de modération");
             Ok(Some(json!({
-                "type": "room_stats",
-                "data": stats
+                "type": "error",
+                "data": {
+                    "action": "get_moderation_queue",
+                    "error": e.to_string()
+                }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_get_user_violations(hub: &ChatHub, user_id: i64, target_user_id: i64) -> Result<Option<String>> {
+    info!(user_id = %user_id, target_user_id = %target_user_id, "🚨 Consultation des violations d'un utilisateur");
+    
+    match violations::get_user_violations(hub, user_id as i32, target_user_id as i32).await {
+        Ok(record) => Ok(Some(json!({
+            "type": "user_violations",
+            "data": record
+        }).to_string())),
         Err(e) => {
-            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des statistiques");
+            warn!(user_id = %user_id, error = %e, "❌ Échec de consultation des violations");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "get_room_stats",
+                    "action": "get_user_violations",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -407,26 +2002,47 @@ async fn handle_get_room_stats(hub: &ChatHub, room_id: i64, user_id: i64) -> Res
     }
 }
 
-async fn handle_get_members(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
-    info!(room_id = %room_id, user_id = %user_id, "👥 Récupération de la liste des membres");
-    
-    match room_enhanced::list_room_members(hub, room_id, user_id).await {
-        Ok(members) => {
-            info!(room_id = %room_id, member_count = %members.len(), "✅ Liste des membres récupérée");
+async fn handle_list_templates(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
+    match templates::list_room_templates(hub, room_id, user_id).await {
+        Ok(templates) => Ok(Some(json!({
+            "type": "room_templates",
+            "data": {
+                "roomId": room_id,
+                "templates": templates
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des modèles");
             Ok(Some(json!({
-                "type": "room_members",
+                "type": "error",
                 "data": {
-                    "roomId": room_id,
-                    "members": members
+                    "action": "list_templates",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_create_template(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    name: &str,
+    content: &str,
+    members_can_use: bool
+) -> Result<Option<String>> {
+    match templates::create_room_template(hub, room_id, user_id, name, content, members_can_use).await {
+        Ok(template) => Ok(Some(json!({
+            "type": "template_created",
+            "data": template
+        }).to_string())),
         Err(e) => {
-            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des membres");
+            warn!(room_id = %room_id, user_id = %user_id, name = %name, error = %e, "❌ Échec de création du modèle");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "get_members",
+                    "action": "create_template",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -434,26 +2050,138 @@ async fn handle_get_members(hub: &ChatHub, room_id: i64, user_id: i64) -> Result
     }
 }
 
-async fn handle_get_audit_logs(hub: &ChatHub, room_id: i64, user_id: i64, limit: i64) -> Result<Option<String>> {
-    info!(room_id = %room_id, user_id = %user_id, limit = %limit, "📋 Récupération des logs d'audit");
+async fn handle_delete_template(hub: &ChatHub, room_id: i64, user_id: i64, name: &str) -> Result<Option<String>> {
+    match templates::delete_room_template(hub, room_id, user_id, name).await {
+        Ok(()) => Ok(Some(json!({
+            "type": "template_deleted",
+            "data": {
+                "roomId": room_id,
+                "name": name
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, name = %name, error = %e, "❌ Échec de suppression du modèle");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "delete_template",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_send_template(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    username: &str,
+    name: &str,
+    values: HashMap<String, String>
+) -> Result<Option<String>> {
+    match templates::send_room_template(hub, room_id, user_id, username, name, values).await {
+        Ok((message_id, verdict)) => Ok(Some(json!({
+            "type": "message_sent",
+            "data": {
+                "messageId": message_id,
+                "roomId": room_id,
+                "template": name,
+                "severity": verdict.severity,
+                "filterAction": verdict.action,
+                "reason": verdict.reason,
+                "success": true
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(room_id = %room_id, user_id = %user_id, name = %name, error = %e, "❌ Échec d'envoi du modèle");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "send_template",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_feature_flag(hub: &ChatHub, user_id: i64, flag: &str, enabled: Option<bool>) -> Result<Option<String>> {
+    info!(user_id = %user_id, flag = %flag, enabled = ?enabled, "🚩 Changement de drapeau de fonctionnalité");
     
-    match audit::get_room_audit_logs(hub, room_id, user_id, limit, None).await {
-        Ok(logs) => {
-            info!(room_id = %room_id, log_count = %logs.len(), "✅ Logs d'audit récupérés");
+    let result = match feature_flags::FeatureFlag::from_name(flag) {
+        Some(flag) => feature_flags::set_feature_flag(hub, user_id, flag, enabled).await,
+        None => Err(ChatError::configuration_error(&format!("Fonctionnalité inconnue: {}", flag))),
+    };
+    
+    match result {
+        Ok(flags) => Ok(Some(flags.to_frame())),
+        Err(e) => {
+            warn!(user_id = %user_id, flag = %flag, error = %e, "❌ Échec du changement de drapeau");
             Ok(Some(json!({
-                "type": "audit_logs",
+                "type": "error",
                 "data": {
-                    "roomId": room_id,
-                    "logs": logs
+                    "action": "set_feature_flag",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_resume(hub: &ChatHub, user_id: i64, rooms: &[(i64, u64)]) -> Result<Option<String>> {
+    info!(user_id = %user_id, room_count = %rooms.len(), "⏪ Reprise après reconnexion");
+    
+    let mut resumed = Vec::with_capacity(rooms.len());
+    for &(room_id, last_event_id) in rooms {
+        match channels::resume_room_events(hub, room_id, user_id, last_event_id).await {
+            Ok(room) => resumed.push(room),
+            Err(e) => {
+                warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Reprise impossible pour ce salon");
+                resumed.push(json!({
+                    "roomId": room_id,
+                    "error": e.to_string()
+                }));
+            }
+        }
+    }
+    
+    Ok(Some(json!({
+        "type": "resumed",
+        "data": { "rooms": resumed }
+    }).to_string()))
+}
+
+async fn handle_get_missed_events(
+    hub: &ChatHub,
+    user_id: i64,
+    since: Option<&str>,
+    cursor: Option<&str>,
+    limit: i64
+) -> Result<Option<String>> {
+    info!(user_id = %user_id, resumed = %cursor.is_some(), "⏪ Rattrapage des événements manqués");
+    
+    // Le curseur d'une page précédente l'emporte sur `since`
+    let start = match (cursor, since) {
+        (Some(cursor), _) => missed_events::MissedCursor::decode(cursor),
+        (None, Some(since)) => chrono::DateTime::parse_from_rfc3339(since)
+            .map(|since| missed_events::MissedCursor::since(since.with_timezone(&chrono::Utc)))
+            .map_err(|_| ChatError::InvalidFormat { field: "since".to_string(), reason: "date RFC 3339 attendue".to_string() }),
+        (None, None) => Err(ChatError::MissingParameter { param: "since".to_string() }),
+    };
+    
+    let result = match start {
+        Ok(start) => missed_events::get_missed_events(hub, user_id, &start, limit).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(missed) => Ok(Some(missed.to_frame().to_string())),
         Err(e) => {
-            warn!(room_id = %room_id, user_id = %user_id, error = %e, "❌ Échec de récupération des logs d'audit");
+            warn!(user_id = %user_id, error = %e, "❌ Échec du rattrapage des événements manqués");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
-                    "action": "get_audit_logs",
+                    "action": "get_missed_events",
                     "error": e.to_string()
                 }
             }).to_string()))
@@ -461,96 +2189,532 @@ async fn handle_get_audit_logs(hub: &ChatHub, room_id: i64, user_id: i64, limit:
     }
 }
 
+async fn handle_ping_diag(hub: &ChatHub, user_id: i64, correlation_id: Option<&str>, client_time: Option<i64>) -> Result<Option<String>> {
+    match diagnostics::ping_diag(hub, user_id, correlation_id, client_time).await {
+        Ok(reply) => Ok(Some(reply.to_string())),
+        Err(e) => Ok(Some(json!({
+            "type": "error",
+            "data": {
+                "action": "ping_diag",
+                "correlationId": correlation_id,
+                "error": e.to_string()
+            }
+        }).to_string())),
+    }
+}
+
 // ================================================================
 // UTILITAIRES DE PARSING
 // ================================================================
 
 /// Parser un message JSON WebSocket en RoomWebSocketMessage
-pub fn parse_websocket_message(message: &str) -> Result<RoomWebSocketMessage> {
-    let value: Value = serde_json::from_str(message)
-        .map_err(|e| ChatError::configuration_error(&format!("JSON invalide: {}", e)))?;
+///
+/// La trame agit au nom de `caller`, le client authentifié de la connexion
+/// (voir `connection_user_id`).
+pub fn parse_websocket_message(message: &str, caller: &Client) -> Result<RoomWebSocketMessage> {
+    let value: Value = parse_client_json(message)?;
     
     let msg_type = value.get("type")
         .and_then(|v| v.as_str())
         .ok_or_else(|| ChatError::configuration_error("Type de message manquant"))?;
     
+    // Diagnostic : `data` facultatif, champs acceptés à la racine du message
+    if msg_type == "ping_diag" {
+        let data = value.get("data").unwrap_or(&value);
+        return Ok(RoomWebSocketMessage::PingDiag {
+            // Limite de débit propre à la connexion, jamais partagée ni contournable
+            user_id: connection_user_id(data, caller)?,
+            correlation_id: diagnostics::correlation_id_from(data.get("correlationId")),
+            client_time: data.get("clientTime").and_then(|v| v.as_i64()),
+        });
+    }
+    
+    if msg_type == "get_feature_flags" {
+        return Ok(RoomWebSocketMessage::GetFeatureFlags);
+    }
+    
+    // Les données sont facultatives : sans salon, capacités du serveur seules
+    if msg_type == "get_capabilities" {
+        let data = value.get("data");
+        return Ok(RoomWebSocketMessage::GetCapabilities {
+            room_id: data.and_then(|d| d.get("roomId")).and_then(|v| v.as_i64()),
+            etag: data.and_then(|d| d.get("etag")).and_then(|v| v.as_str()).map(str::to_string),
+        });
+    }
+    
     let data = value.get("data")
         .ok_or_else(|| ChatError::configuration_error("Données du message manquantes"))?;
+    let user_id = connection_user_id(data, caller)?;
     
     match msg_type {
         "join_room" => Ok(RoomWebSocketMessage::JoinRoom {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
         }),
         
         "leave_room" => Ok(RoomWebSocketMessage::LeaveRoom {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
         }),
         
         "send_message" => Ok(RoomWebSocketMessage::SendMessage {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            username: data.get("username").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            user_id: user_id,
+            username: caller.username.clone(),
             content: data.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
             parent_id: data.get("parentId").and_then(|v| v.as_i64()),
+            visible_to: data.get("visibleTo").and_then(|v| v.as_array()).map(|ids| {
+                ids.iter().filter_map(|id| id.as_i64()).map(|id| id as i32).collect()
+            }),
+            nonce: data.get("nonce").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            ack: data.get("ack").and_then(|v| v.as_bool()),
+        }),
+        
+        "set_ack_mode" => Ok(RoomWebSocketMessage::SetAckMode {
+            user_id: user_id,
+            mode: data.get("mode").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        "edit_message" => Ok(RoomWebSocketMessage::EditMessage {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            content: data.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        "delete_message" => Ok(RoomWebSocketMessage::DeleteMessage {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
+        "list_templates" => Ok(RoomWebSocketMessage::ListTemplates {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
+        "create_template" => Ok(RoomWebSocketMessage::CreateTemplate {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            name: data.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            content: data.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            members_can_use: data.get("membersCanUse").and_then(|v| v.as_bool()).unwrap_or(true),
+        }),
+        
+        "delete_template" => Ok(RoomWebSocketMessage::DeleteTemplate {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            name: data.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        "send_template" => Ok(RoomWebSocketMessage::SendTemplate {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            username: caller.username.clone(),
+            name: data.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            values: data.get("values").and_then(|v| v.as_object()).map(|values| {
+                values.iter()
+                    .filter_map(|(key, value)| value.as_str().map(|value| (key.clone(), value.to_string())))
+                    .collect()
+            }).unwrap_or_default(),
+        }),
+        
+        "mark_room_read" => Ok(RoomWebSocketMessage::MarkRoomRead {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            up_to_message_id: data.get("upToMessageId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        "get_user_rooms" => Ok(RoomWebSocketMessage::GetUserRooms {
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            cursor: data.get("cursor").and_then(|v| v.as_i64()),
+        }),
+        
+        "set_room_pinned" => Ok(RoomWebSocketMessage::SetRoomPinned {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            pinned: data.get("pinned").and_then(|v| v.as_bool()).unwrap_or(true),
+        }),
+        
+        "set_room_guest_access" => Ok(RoomWebSocketMessage::SetRoomGuestAccess {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            access: data.get("access").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        "set_room_muted" => Ok(RoomWebSocketMessage::SetRoomMuted {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            muted: data.get("muted").and_then(|v| v.as_bool()).unwrap_or(true),
         }),
         
         "get_history" => Ok(RoomWebSocketMessage::GetHistory {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(50),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
             before_id: data.get("beforeId").and_then(|v| v.as_i64()),
+            include_pin_state: data.get("includePinState").and_then(|v| v.as_bool()).unwrap_or(false),
         }),
         
         "get_pinned_messages" => Ok(RoomWebSocketMessage::GetPinnedMessages {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
+        "open_room" => Ok(RoomWebSocketMessage::OpenRoom {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
         }),
         
         "add_reaction" => Ok(RoomWebSocketMessage::AddReaction {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
             emoji: data.get("emoji").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
         
         "remove_reaction" => Ok(RoomWebSocketMessage::RemoveReaction {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
             emoji: data.get("emoji").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
         
+        "get_message_body" => Ok(RoomWebSocketMessage::GetMessageBody {
+            message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
         "get_reactions" => Ok(RoomWebSocketMessage::GetReactions {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
+        "get_reaction_changes" => Ok(RoomWebSocketMessage::GetReactionChanges {
+            user_id: user_id,
+            message_ids: data.get("messageIds").and_then(|v| v.as_array()).map(|ids| {
+                ids.iter().filter_map(|id| id.as_i64()).collect()
+            }).unwrap_or_default(),
+            since: data.get("since").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
         
         "pin_message" => Ok(RoomWebSocketMessage::PinMessage {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            duration_seconds: data.get("durationSeconds").and_then(|v| v.as_u64()),
         }),
         
         "unpin_message" => Ok(RoomWebSocketMessage::UnpinMessage {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
+        "reorder_pins" => Ok(RoomWebSocketMessage::ReorderPins {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            message_ids: data.get("messageIds").and_then(|v| v.as_array()).map(|ids| {
+                ids.iter().filter_map(|id| id.as_i64()).collect()
+            }).unwrap_or_default(),
         }),
         
         "get_room_stats" => Ok(RoomWebSocketMessage::GetRoomStats {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
         }),
         
         "get_members" => Ok(RoomWebSocketMessage::GetMembers {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            cursor: data.get("cursor").and_then(|v| v.as_i64()),
+        }),
+        
+        "report_message" => Ok(RoomWebSocketMessage::ReportMessage {
+            message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            reason: data.get("reason").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        "set_slow_mode" => Ok(RoomWebSocketMessage::SetSlowMode {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            mode: match data.get("mode").and_then(|v| v.as_str()) {
+                None | Some("auto") => slow_mode::SlowModeOverride::Auto,
+                Some("off") => slow_mode::SlowModeOverride::Disabled,
+                Some("on") => slow_mode::SlowModeOverride::Forced(Duration::from_secs(
+                    data.get("intervalSeconds").and_then(|v| v.as_u64()).unwrap_or(0)
+                )),
+                Some(other) => return Err(ChatError::configuration_error(&format!("Mode lent inconnu: {}", other))),
+            },
+        }),
+        
+        // Sans `roomId` : tous les salons (administrateurs)
+        "set_anti_raid" => Ok(RoomWebSocketMessage::SetAntiRaid {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()),
+            user_id: user_id,
+            enabled: data.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true),
+            duration_seconds: data.get("durationSeconds").and_then(|v| v.as_u64()),
+        }),
+        
+        "force_disconnect" => Ok(RoomWebSocketMessage::ForceDisconnect {
+            user_id: user_id,
+            target_user_id: data.get("targetUserId").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+            message: data.get("message").and_then(|v| v.as_str()).map(String::from),
+        }),
+        
+        "get_reputation" => Ok(RoomWebSocketMessage::GetReputation {
+            user_id: user_id,
+            target_user_id: data.get("targetUserId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        "adjust_reputation" => Ok(RoomWebSocketMessage::AdjustReputation {
+            user_id: user_id,
+            target_user_id: data.get("targetUserId").and_then(|v| v.as_i64()).unwrap_or(0),
+            delta: data.get("delta").and_then(|v| v.as_i64()).map(|delta| delta.clamp(i32::MIN as i64, i32::MAX as i64) as i32).unwrap_or(0),
+            reason: data.get("reason").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        // `limit: null` rend l'historique complet aux nouveaux membres
+        "set_history_limit" => Ok(RoomWebSocketMessage::SetHistoryLimit {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            history_limit: data.get("limit").and_then(|v| v.as_i64()).map(|limit| i32::try_from(limit).unwrap_or(i32::MAX)),
+        }),
+        
+        // `mode` : "off" ou "flag"
+        "set_filter_mode" => Ok(RoomWebSocketMessage::SetFilterMode {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            mode: data.get("mode").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        // `languages` : codes des langues filtrées en plus de celles du serveur
+        "set_room_languages" => Ok(RoomWebSocketMessage::SetRoomLanguages {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            languages: data.get("languages").and_then(|v| v.as_array()).map(|languages| {
+                languages.iter().filter_map(|language| language.as_str()).map(str::to_string).collect()
+            }).unwrap_or_default(),
+        }),
+        
+        // `role` : rôle minimal des destinataires (`moderator`, `admin`...)
+        "send_role_notice" => Ok(RoomWebSocketMessage::SendRoleNotice {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            role: data.get("role").and_then(|v| v.as_str()).unwrap_or("moderator").to_string(),
+            content: data.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        // Termes du salon exemptés des mots interdits
+        "add_allowlisted_term" | "remove_allowlisted_term" => Ok(RoomWebSocketMessage::UpdateAllowlistedTerm {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            term: data.get("term").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            add: msg_type == "add_allowlisted_term",
+        }),
+        
+        // Chiffrement au repos des nouveaux messages (propriétaire ou admin)
+        "set_room_encryption" => Ok(RoomWebSocketMessage::SetRoomEncryption {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            enabled: data.get("enabled").and_then(|v| v.as_bool()).unwrap_or(false),
+        }),
+        
+        // `null` revient à la limite de la configuration
+        "set_attachment_limits" => Ok(RoomWebSocketMessage::SetAttachmentLimits {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            max_count: data.get("maxAttachments").and_then(|v| v.as_i64()).map(|count| i32::try_from(count).unwrap_or(i32::MAX)),
+            max_total_size: data.get("maxTotalSize").and_then(|v| v.as_i64()),
+        }),
+        
+        // `policy` : surcharges du salon (`null` revient à la politique du serveur)
+        "set_link_policy" => Ok(RoomWebSocketMessage::SetLinkPolicy {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            policy: data.get("policy").filter(|v| !v.is_null()).cloned(),
+        }),
+        
+        "approve_message" | "reject_message" => Ok(RoomWebSocketMessage::ReviewHeldMessage {
+            message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            approve: msg_type == "approve_message",
+            note: data.get("reason").and_then(|v| v.as_str()).map(|v| v.to_string()),
+        }),
+        
+        "get_emoji_catalog" => Ok(RoomWebSocketMessage::GetEmojiCatalog {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()),
+            known_version: data.get("version").and_then(|v| v.as_str()).map(|v| v.to_string()),
+        }),
+        
+        // Image encodée en base64 ; sans `roomId`, émoji serveur
+        "upload_custom_emoji" => Ok(RoomWebSocketMessage::UploadCustomEmoji {
+            user_id: user_id,
+            scope: match data.get("roomId").and_then(|v| v.as_i64()) {
+                Some(room_id) => custom_emojis::EmojiScope::Room(room_id),
+                None => custom_emojis::EmojiScope::Server,
+            },
+            shortcode: data.get("shortcode").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            image: base64::engine::general_purpose::STANDARD
+                .decode(data.get("image").and_then(|v| v.as_str()).unwrap_or(""))
+                .map_err(|_| ChatError::InvalidFormat {
+                    field: "image".to_string(),
+                    reason: "Image base64 invalide".to_string(),
+                })?,
+        }),
+        
+        "delete_custom_emoji" => Ok(RoomWebSocketMessage::DeleteCustomEmoji {
+            user_id: user_id,
+            emoji_id: data.get("emojiId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        // Contenu encodé en base64
+        "upload_attachment" => Ok(RoomWebSocketMessage::UploadAttachment {
+            user_id: user_id,
+            filename: data.get("filename").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            mime_type: data.get("mimeType").and_then(|v| v.as_str()).unwrap_or("application/octet-stream").to_string(),
+            bytes: base64::engine::general_purpose::STANDARD
+                .decode(data.get("content").and_then(|v| v.as_str()).unwrap_or(""))
+                .map_err(|_| ChatError::InvalidFormat {
+                    field: "content".to_string(),
+                    reason: "Contenu base64 invalide".to_string(),
+                })?,
+        }),
+        
+        "delete_attachment" => Ok(RoomWebSocketMessage::DeleteAttachment {
+            user_id: user_id,
+            file_id: data.get("fileId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        "get_storage_usage" => Ok(RoomWebSocketMessage::GetStorageUsage {
+            user_id: user_id,
+        }),
+        
+        "get_reaction_set" => Ok(RoomWebSocketMessage::GetReactionSet {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        // `emojis: null` rouvre le salon à toutes les réactions
+        "set_reaction_set" => Ok(RoomWebSocketMessage::SetReactionSet {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            set: match data.get("emojis").and_then(|v| v.as_array()) {
+                Some(emojis) => reaction_sets::ReactionSet::Curated(
+                    emojis.iter().filter_map(|v| v.as_str()).map(|emoji| emoji.to_string()).collect()
+                ),
+                None => reaction_sets::ReactionSet::Open,
+            },
+        }),
+        
+        "archive_room" | "unarchive_room" => Ok(RoomWebSocketMessage::SetRoomArchived {
+            room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            archived: msg_type == "archive_room",
+        }),
+        
+        "list_rooms" => Ok(RoomWebSocketMessage::ListRooms {
+            include_archived: data.get("includeArchived").and_then(|v| v.as_bool()).unwrap_or(false),
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            cursor: data.get("cursor").and_then(|v| v.as_i64()),
+        }),
+        
+        "browse_rooms" => Ok(RoomWebSocketMessage::BrowseRooms {
+            user_id: user_id,
+            public_only: data.get("publicOnly").and_then(|v| v.as_bool()).unwrap_or(false),
+            joined_only: data.get("joinedOnly").and_then(|v| v.as_bool()).unwrap_or(false),
+            name_prefix: data.get("prefix").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            order: data.get("order").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            cursor: data.get("cursor").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        }),
+        
+        "get_moderation_queue" => Ok(RoomWebSocketMessage::GetModerationQueue {
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            cursor: data.get("cursor").and_then(|v| v.as_i64()),
+        }),
+        
+        "get_user_violations" => Ok(RoomWebSocketMessage::GetUserViolations {
+            user_id: user_id,
+            target_user_id: data.get("targetUserId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        "set_feature_flag" => Ok(RoomWebSocketMessage::SetFeatureFlag {
+            user_id: user_id,
+            flag: data.get("flag").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            enabled: data.get("enabled").and_then(|v| v.as_bool()),
+        }),
+        
+        "negotiate_schema" => Ok(RoomWebSocketMessage::NegotiateSchema {
+            user_id: user_id,
+            schema_version: data.get("schemaVersion")
+                .and_then(|v| v.as_u64())
+                .map_or(CURRENT_SCHEMA_VERSION, |v| v.min(u16::MAX as u64) as u16),
+        }),
+        
+        "get_quota" => Ok(RoomWebSocketMessage::GetQuota {
+            user_id: user_id,
+        }),
+        
+        // `users` : identifiants suivis, `rooms` : noms des salons suivis
+        "subscribe_presence" | "unsubscribe_presence" => {
+            let user_id = user_id;
+            let users = data.get("users").and_then(|v| v.as_array()).map(|ids| {
+                ids.iter().filter_map(|id| id.as_i64()).map(|id| id as i32).collect()
+            }).unwrap_or_default();
+            let rooms = data.get("rooms").and_then(|v| v.as_array()).map(|rooms| {
+                rooms.iter().filter_map(|room| room.as_str()).map(RoomId::new).collect::<Result<Vec<_>>>()
+            }).transpose()?.unwrap_or_default();
+            if msg_type == "subscribe_presence" {
+                Ok(RoomWebSocketMessage::SubscribePresence { user_id, users, rooms })
+            } else {
+                Ok(RoomWebSocketMessage::UnsubscribePresence { user_id, users, rooms })
+            }
+        }
+        
+        // `status` : "Online", "Away", "Busy" ou "Invisible"
+        "set_status" => Ok(RoomWebSocketMessage::SetStatus {
+            user_id: user_id,
+            status: data.get("status").cloned().unwrap_or(Value::Null),
+            message: data.get("message").and_then(|v| v.as_str()).map(str::to_string),
+        }),
+        
+        // `userIds` : auteurs à résoudre (200 au plus)
+        "get_user_profiles" => Ok(RoomWebSocketMessage::GetUserProfiles {
+            user_id: user_id,
+            user_ids: data.get("userIds")
+                .and_then(|v| v.as_array())
+                .map(|ids| ids.iter().filter_map(|id| id.as_i64()).collect())
+                .unwrap_or_default(),
+        }),
+        
+        // `since` : date RFC 3339 de la coupure ; `cursor` : suite d'une page précédente
+        "get_missed_events" => Ok(RoomWebSocketMessage::GetMissedEvents {
+            user_id: user_id,
+            since: data.get("since").and_then(|v| v.as_str()).map(|v| v.to_string()),
+            cursor: data.get("cursor").and_then(|v| v.as_str()).map(|v| v.to_string()),
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+        }),
+        
+        "resume" => Ok(RoomWebSocketMessage::Resume {
+            user_id: user_id,
+            rooms: data.get("rooms")
+                .and_then(|v| v.as_array())
+                .map(|rooms| rooms.iter()
+                    .filter_map(|room| Some((
+                        room.get("roomId")?.as_i64()?,
+                        room.get("lastEventId").and_then(|v| v.as_u64()).unwrap_or(0),
+                    )))
+                    .collect())
+                .unwrap_or_default(),
         }),
         
         "get_audit_logs" => Ok(RoomWebSocketMessage::GetAuditLogs {
             room_id: data.get("roomId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(50),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
         }),
         
         _ => Err(ChatError::configuration_error(&format!("Type de message non supporté: {}", msg_type)))
@@ -5,20 +5,53 @@
 //! - Historique complet des messages
 //! - Système de mentions
 //! - Réactions aux messages
-//! - Messages épinglés
+//! - Messages épinglés (permanents ou temporaires)
 //! - Threads de discussion
 //! - Audit et logs de sécurité
 //! - Gestion des permissions
 //! - Modération intégrée
+//! - Archivage en lecture seule
 
 use sqlx::{query, query_as, FromRow, Row, Transaction, Postgres};
 use serde::{Serialize, Deserialize};
 use crate::hub::common::ChatHub;
-use crate::validation::{validate_room_name, validate_message_content, validate_limit, validate_user_id};
+use crate::hub::feature_flags::FeatureFlag;
+use crate::hub::slow_mode::announce_slow_mode;
+use crate::hub::anti_raid::{check_raid_join, check_raid_message};
+use crate::hub::reputation::{check_message_rate, record_message};
+use crate::hub::long_messages::PreparedContent;
+use crate::hub::encrypted_rooms::{room_data_key, message_data_key, seal_prepared, open_room_messages};
+use crate::encryption::{DataKey, MessageCipher};
+use crate::hub::dedup::{DedupKey, SentMessage};
+use crate::hub::quotas::{consume_message_quota, release_message_quota};
+use crate::hub::room_links::review_message_links;
+use crate::hub::visibility::{MessageVisibility, visibility_clause};
+use crate::hub::quotes::QuotedExcerpt;
+use crate::hub::message_policy::{MessagePolicy, ModificationContext, RoomMessagePolicy};
+use crate::hub::mentions::{parse_mentions, check_mention_count, notify_mention_recipients, mentions_payload, ParsedMention};
+use crate::hub::held_messages::{RoomFilterMode, APPROVAL_PENDING_REASON, held_clause, notify_message_held, screen_room_message};
+use crate::hub::violations::{check_standing, record_if_blocked};
+use crate::hub::guests::forward_to_guests;
+use crate::hub::room_repository::{ArchiveState, NewRoomMessage};
+use crate::hub::attachments::check_message_attachments;
+use crate::event_log::stamp_event;
+use crate::link_policy::RoomLinkPolicy;
+use crate::word_packs::is_known_locale;
+use crate::message_schema::{downgrade, MessagePayload, VersionedFrame};
+use crate::validation::{AttachmentLimits, validate_message_content, validate_limit, validate_user_id, normalize_username};
+use crate::pagination::Page;
+use crate::config::{LimitsConfig, ModerationConfig};
+use crate::permissions::{check_message_action, MessageAction, Role};
+use crate::room_id::RoomId;
 use crate::error::{ChatError, Result};
 use serde_json::{json, Value};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Fréquence du balayage des épingles expirées
+const PIN_EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
 
 // ================================================================
 // STRUCTURES DE DONNÉES
@@ -63,6 +96,10 @@ pub struct RoomMessage {
     pub is_edited: bool,
     pub edit_count: i32,
     pub is_pinned: bool,
+    /// Position dans la liste des épingles (`None` = ordre par date)
+    pub pin_order: Option<i32>,
+    /// Fin d'une épingle temporaire
+    pub pinned_until: Option<DateTime<Utc>>,
     pub metadata: Value,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -84,6 +121,62 @@ pub struct RoomStats {
     pub pinned_messages: i64,
 }
 
+/// Politique de publication d'un salon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomPostPolicy {
+    /// Tous les membres publient et réagissent
+    #[default]
+    Everyone,
+    /// Seuls les modérateurs publient et réagissent
+    ModeratorsOnly,
+    /// Seuls les modérateurs publient, tous les membres peuvent réagir
+    Announcement,
+}
+
+impl RoomPostPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Everyone => "everyone",
+            Self::ModeratorsOnly => "moderators_only",
+            Self::Announcement => "announcement",
+        }
+    }
+
+    /// Valeur inconnue en base : on retombe sur la politique par défaut
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "moderators_only" => Self::ModeratorsOnly,
+            "announcement" => Self::Announcement,
+            _ => Self::Everyone,
+        }
+    }
+
+    /// Un membre avec ce rôle peut-il publier un message ?
+    pub fn can_post(&self, member_role: &str) -> bool {
+        match self {
+            Self::Everyone => member_role != "read_only",
+            Self::ModeratorsOnly | Self::Announcement => is_moderator_role(member_role),
+        }
+    }
+
+    /// Un membre avec ce rôle peut-il réagir à un message ?
+    ///
+    /// Indépendant du droit de publication : seule l'appartenance au salon
+    /// compte, sauf en mode `ModeratorsOnly`.
+    pub fn can_react(&self, member_role: &str) -> bool {
+        match self {
+            Self::Everyone | Self::Announcement => true,
+            Self::ModeratorsOnly => is_moderator_role(member_role),
+        }
+    }
+}
+
+/// Rôles de membre disposant des droits de modération
+pub fn is_moderator_role(member_role: &str) -> bool {
+    matches!(member_role, "owner" | "admin" | "moderator")
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RoomPermissions {
     pub can_send_messages: bool,
@@ -108,7 +201,7 @@ pub async fn create_room(
 ) -> Result<Room> {
     tracing::info!(owner_id = %owner_id, name = %name, is_public = %is_public, "🏗️ Création d'un nouveau salon");
     
-    validate_room_name(name)?;
+    let name = RoomId::new(name)?;
     validate_user_id(owner_id as i32)?;
     
     let room_uuid = Uuid::new_v4();
@@ -123,7 +216,7 @@ pub async fn create_room(
         RETURNING id, uuid, name, description, owner_id, is_public, is_archived, max_members, created_at, updated_at
     ")
     .bind(room_uuid)
-    .bind(name)
+    .bind(&name)
     .bind(description)
     .bind(owner_id)
     .bind(is_public)
@@ -144,20 +237,12 @@ pub async fn create_room(
     .map_err(|e| ChatError::from_sqlx_error("add_owner_member", e))?;
     
     // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('room_created', $1, $2)
-    ")
-    .bind(json!({
+    hub.audit_sink.record(&mut *tx, "room_created", Some(owner_id), json!({
         "room_id": conversation.id,
         "room_name": name,
         "is_public": is_public,
         "max_members": max_members
-    }))
-    .bind(owner_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
+    })).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
@@ -171,85 +256,10 @@ pub async fn join_room(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<()>
     tracing::info!(user_id = %user_id, room_id = %room_id, "👥 Tentative de rejoindre le salon");
     
     validate_user_id(user_id as i32)?;
+    // Salon en mode anti-raid : jointures limitées
+    check_raid_join(hub, room_id, user_id).await?;
     
-    let mut tx = hub.db.begin().await
-        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
-    
-    // Vérifier que le salon existe et n'est pas archivé
-    let room: Room = query_as("
-        SELECT id, uuid, name, description, owner_id, is_public, is_archived, max_members, created_at, updated_at
-        FROM conversations 
-        WHERE id = $1 AND type = 'public_room' AND NOT is_archived
-    ")
-    .bind(room_id)
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|_| ChatError::not_found("salon", &room_id.to_string()))?;
-    
-    // Vérifier si l'utilisateur est déjà membre
-    let is_member: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM conversation_members 
-            WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
-        )
-    ")
-    .bind(room_id)
-    .bind(user_id)
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?
-    .get(0);
-    
-    if is_member {
-        return Err(ChatError::configuration_error("Utilisateur déjà membre du salon"));
-    }
-    
-    // Vérifier la limite de membres
-    if let Some(max_members) = room.max_members {
-        let current_count: i64 = query("
-            SELECT COUNT(*) FROM conversation_members 
-            WHERE conversation_id = $1 AND left_at IS NULL
-        ")
-        .bind(room_id)
-        .fetch_one(&mut *tx)
-        .await
-        .map_err(|e| ChatError::from_sqlx_error("count_members", e))?
-        .get(0);
-        
-        if current_count >= max_members as i64 {
-            return Err(ChatError::configuration_error("Salon plein"));
-        }
-    }
-    
-    // Ajouter le membre
-    query("
-        INSERT INTO conversation_members (conversation_id, user_id, role)
-        VALUES ($1, $2, 'member')
-        ON CONFLICT (conversation_id, user_id) 
-        DO UPDATE SET left_at = NULL, joined_at = NOW()
-    ")
-    .bind(room_id)
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("add_member", e))?;
-    
-    // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('room_joined', $1, $2)
-    ")
-    .bind(json!({
-        "room_id": room_id,
-        "room_name": room.name
-    }))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
-    
-    tx.commit().await
-        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    hub.room_repository.add_room_member(room_id, user_id, &hub.audit_sink).await?;
     
     tracing::info!(user_id = %user_id, room_id = %room_id, "✅ Utilisateur a rejoint le salon");
     Ok(())
@@ -280,15 +290,7 @@ pub async fn leave_room(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<()>
     }
     
     // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('room_left', $1, $2)
-    ")
-    .bind(json!({"room_id": room_id}))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
+    hub.audit_sink.record(&mut *tx, "room_left", Some(user_id), json!({"room_id": room_id})).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
@@ -297,110 +299,74 @@ pub async fn leave_room(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<()>
     Ok(())
 }
 
-// ================================================================
-// GESTION DES MESSAGES
-// ================================================================
-
-/// Envoyer un message dans un salon
-pub async fn send_room_message(
+/// Modifie la politique de publication d'un salon (propriétaire ou admin)
+pub async fn set_room_post_policy(
     hub: &ChatHub,
     room_id: i64,
-    author_id: i64,
-    username: &str,
-    content: &str,
-    parent_message_id: Option<i64>,
-    metadata: Option<Value>
-) -> Result<i64> {
-    tracing::info!(author_id = %author_id, room_id = %room_id, "📝 Envoi d'un message dans le salon");
-    
-    validate_user_id(author_id as i32)?;
-    validate_message_content(content, hub.config.limits.max_message_length)?;
-    
-    // Vérification du rate limiting
-    if !hub.check_rate_limit(author_id as i32).await {
-        return Err(ChatError::rate_limit_exceeded_simple("send_message"));
-    }
+    user_id: i64,
+    policy: RoomPostPolicy
+) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, policy = %policy.as_str(), "📢 Changement de politique de publication");
     
     let mut tx = hub.db.begin().await
         .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
     
-    // Vérifier que l'utilisateur est membre du salon
-    let is_member: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM conversation_members 
-            WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
-        )
+    let user_role: Option<String> = query("
+        SELECT role FROM conversation_members 
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
     ")
     .bind(room_id)
-    .bind(author_id)
-    .fetch_one(&mut *tx)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
     .await
-    .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?
-    .get(0);
+    .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+    .map(|row| row.get("role"));
     
-    if !is_member {
-        return Err(ChatError::unauthorized("send_room_message"));
+    match user_role.as_deref() {
+        Some("owner") | Some("admin") => {},
+        _ => return Err(ChatError::unauthorized("set_room_post_policy"))
     }
     
-    // Insérer le message
-    let message_uuid = Uuid::new_v4();
-    let message_metadata = metadata.unwrap_or_else(|| json!({}));
-    
-    let message = query("
-        INSERT INTO messages (uuid, author_id, conversation_id, content, parent_message_id, metadata, status)
-        VALUES ($1, $2, $3, $4, $5, $6, 'sent')
-        RETURNING id, created_at
+    let rows_affected = query("
+        UPDATE conversations 
+        SET post_policy = $1, updated_at = NOW()
+        WHERE id = $2
     ")
-    .bind(message_uuid)
-    .bind(author_id)
+    .bind(policy.as_str())
     .bind(room_id)
-    .bind(content)
-    .bind(parent_message_id)
-    .bind(&message_metadata)
-    .fetch_one(&mut *tx)
+    .execute(&mut *tx)
     .await
-    .map_err(|e| ChatError::from_sqlx_error("insert_message", e))?;
-    
-    let message_id: i64 = message.get("id");
-    let timestamp: DateTime<Utc> = message.get("created_at");
-    
-    // Si c'est une réponse, incrémenter le compteur de thread
-    if let Some(parent_id) = parent_message_id {
-        query("
-            UPDATE messages 
-            SET thread_count = thread_count + 1 
-            WHERE id = $1
-        ")
-        .bind(parent_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| ChatError::from_sqlx_error("update_thread_count", e))?;
+    .map_err(|e| ChatError::from_sqlx_error("update_post_policy", e))?
+    .rows_affected();
+    
+    if rows_affected == 0 {
+        return Err(ChatError::not_found("salon", &room_id.to_string()));
     }
     
-    // Traiter les mentions (@username)
-    process_mentions(&mut tx, message_id, content).await?;
+    hub.audit_sink.record(&mut *tx, "room_post_policy_changed", Some(user_id), json!({
+        "room_id": room_id,
+        "post_policy": policy.as_str()
+    })).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
     
-    // Incrémentation des statistiques
-    hub.increment_message_count().await;
-    
-    // Diffusion en temps réel
-    broadcast_room_message(hub, room_id, message_id, author_id, username, content, timestamp, parent_message_id).await?;
-    
-    tracing::info!(message_id = %message_id, room_id = %room_id, "✅ Message envoyé dans le salon");
-    Ok(message_id)
+    tracing::info!(room_id = %room_id, policy = %policy.as_str(), "✅ Politique de publication mise à jour");
+    Ok(())
 }
 
-/// Épingler/désépingler un message
-pub async fn pin_message(hub: &ChatHub, room_id: i64, message_id: i64, user_id: i64, pin: bool) -> Result<()> {
-    tracing::info!(user_id = %user_id, room_id = %room_id, message_id = %message_id, pin = %pin, "📌 Épinglage de message");
+/// Modifie les fenêtres d'édition/suppression d'un salon (propriétaire ou admin)
+pub async fn set_room_message_policy(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    overrides: RoomMessagePolicy
+) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, "⏱️ Changement des fenêtres d'édition du salon");
     
     let mut tx = hub.db.begin().await
         .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
     
-    // Vérifier les permissions (propriétaire ou modérateur)
     let user_role: Option<String> = query("
         SELECT role FROM conversation_members 
         WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
@@ -413,184 +379,1107 @@ pub async fn pin_message(hub: &ChatHub, room_id: i64, message_id: i64, user_id:
     .map(|row| row.get("role"));
     
     match user_role.as_deref() {
-        Some("owner") | Some("moderator") => {},
-        _ => return Err(ChatError::unauthorized("pin_message"))
+        Some("owner") | Some("admin") => {},
+        _ => return Err(ChatError::unauthorized("set_room_message_policy"))
     }
     
-    // Mettre à jour le statut d'épinglage
-    let rows_affected = query("
-        UPDATE messages 
-        SET is_pinned = $1, updated_at = NOW()
-        WHERE id = $2 AND conversation_id = $3
+    query("
+        UPDATE conversations 
+        SET edit_window_seconds = $1, delete_window_seconds = $2,
+            edit_lock_after_reactions = $3, edit_lock_after_replies = $4,
+            updated_at = NOW()
+        WHERE id = $5
     ")
-    .bind(pin)
-    .bind(message_id)
+    .bind(overrides.edit_window_seconds)
+    .bind(overrides.delete_window_seconds)
+    .bind(overrides.edit_lock_after_reactions)
+    .bind(overrides.edit_lock_after_replies)
     .bind(room_id)
     .execute(&mut *tx)
     .await
-    .map_err(|e| ChatError::from_sqlx_error("update_pin_status", e))?
-    .rows_affected();
-    
-    if rows_affected == 0 {
-        return Err(ChatError::not_found("message", &message_id.to_string()));
-    }
+    .map_err(|e| ChatError::from_sqlx_error("update_message_policy", e))?;
     
-    // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ($1, $2, $3)
-    ")
-    .bind(if pin { "message_pinned" } else { "message_unpinned" })
-    .bind(json!({
+    hub.audit_sink.record(&mut *tx, "room_message_policy_changed", Some(user_id), json!({
         "room_id": room_id,
-        "message_id": message_id
-    }))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
+        "policy": overrides
+    })).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
     
-    tracing::info!(message_id = %message_id, pin = %pin, "✅ Statut d'épinglage mis à jour");
+    tracing::info!(room_id = %room_id, "✅ Fenêtres d'édition mises à jour");
     Ok(())
 }
 
 // ================================================================
-// HISTORIQUE ET RECHERCHE
+// ARCHIVAGE
 // ================================================================
 
-/// Récupérer l'historique complet d'un salon
-pub async fn fetch_room_history(
+/// Trame `room_updated` diffusée aux membres quand l'état du salon change
+pub fn room_updated_frame(room_id: i64, archived: bool, actor_id: i64) -> Value {
+    json!({
+        "type": "room_updated",
+        "data": {
+            "roomId": room_id,
+            "isArchived": archived,
+            "updatedBy": actor_id
+        }
+    })
+}
+
+/// Vérifie qu'un changement d'archivage est permis : modérateur du salon, état différent
+pub(crate) fn check_archive_change(state: &ArchiveState, archived: bool) -> Result<()> {
+    let action = if archived { "archive_room" } else { "unarchive_room" };
+    if !state.member_role.as_deref().is_some_and(is_moderator_role) {
+        return Err(ChatError::unauthorized(action));
+    }
+    
+    if state.is_archived == archived {
+        return Err(ChatError::configuration_error(if archived { "Salon déjà archivé" } else { "Salon non archivé" }));
+    }
+    Ok(())
+}
+
+/// Archive un salon : historique consultable, envois et adhésions bloqués
+pub async fn archive_room(hub: &ChatHub, room_id: i64, actor_id: i64) -> Result<()> {
+    set_room_archived(hub, room_id, actor_id, true).await
+}
+
+/// Désarchive un salon : les envois et adhésions sont de nouveau acceptés
+pub async fn unarchive_room(hub: &ChatHub, room_id: i64, actor_id: i64) -> Result<()> {
+    set_room_archived(hub, room_id, actor_id, false).await
+}
+
+/// Archive ou désarchive un salon (modérateurs du salon)
+///
+/// Un salon archivé reste lisible par ses membres mais personne ne peut y
+/// publier ni le rejoindre ; il disparaît des listes de salons par défaut et ne
+/// compte plus dans la limite de salons par utilisateur.
+pub async fn set_room_archived(hub: &ChatHub, room_id: i64, user_id: i64, archived: bool) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, archived = %archived, "🗄️ Changement d'archivage du salon");
+    
+    hub.room_repository.set_archived(room_id, user_id, archived, &hub.audit_sink).await?;
+    
+    let content = if archived {
+        "🗄️ Salon archivé : l'historique reste consultable, les nouveaux messages sont désactivés"
+    } else {
+        "📂 Salon désarchivé : les messages sont de nouveau autorisés"
+    };
+    broadcast_to_room_members(hub, room_id, &json!({
+        "type": "system_message",
+        "data": {
+            "roomId": room_id,
+            "event": if archived { "room_archived" } else { "room_unarchived" },
+            "archived": archived,
+            "actorId": user_id,
+            "content": content
+        }
+    })).await?;
+    broadcast_to_room_members(hub, room_id, &room_updated_frame(room_id, archived, user_id)).await?;
+    
+    tracing::info!(room_id = %room_id, archived = %archived, "✅ Archivage du salon mis à jour");
+    Ok(())
+}
+
+/// Clause SQL des salons figurant dans une liste (alias `c`), archivés exclus sauf `include_archived`
+///
+/// Commune à `list_public_rooms` et à l'annuaire (`room_directory::list_rooms`).
+pub(crate) fn listed_room_clause(include_archived: bool) -> &'static str {
+    if include_archived {
+        "c.type = 'public_room'"
+    } else {
+        "c.type = 'public_room' AND NOT c.is_archived"
+    }
+}
+
+/// Liste les salons publics par nom, archivés exclus par défaut
+///
+/// `cursor` est le `next_cursor` de la page précédente (décalage : l'ordre
+/// alphabétique change quand un salon est renommé).
+pub async fn list_public_rooms(hub: &ChatHub, include_archived: bool, limit: i64, cursor: Option<i64>) -> Result<Page<Room>> {
+    let limit = validate_limit(limit, &hub.config.limits)?;
+    let offset = cursor.unwrap_or(0).max(0);
+    
+    let sql = format!("
+        SELECT c.id, c.uuid, c.name, c.description, c.owner_id, c.is_public, c.is_archived, c.max_members, c.created_at, c.updated_at
+        FROM conversations c
+        WHERE {} AND c.is_public
+        ORDER BY c.name, c.id
+        LIMIT $1 OFFSET $2
+    ", listed_room_clause(include_archived));
+    let rooms = query_as::<_, Room>(&sql)
+        .bind(limit + 1)
+        .bind(offset)
+        .fetch_all(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("list_public_rooms", e))?;
+    
+    Ok(Page::from_offset(rooms, limit, offset))
+}
+
+// ================================================================
+// GESTION DES MESSAGES
+// ================================================================
+
+/// Envoyer un message dans un salon
+pub async fn send_room_message(
     hub: &ChatHub,
     room_id: i64,
-    user_id: i64,
-    limit: i64,
-    before_message_id: Option<i64>
-) -> Result<Vec<RoomMessage>> {
-    tracing::info!(room_id = %room_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique du salon");
-    
-    validate_user_id(user_id as i32)?;
-    let validated_limit = validate_limit(limit)?;
+    author_id: i64,
+    username: &str,
+    content: &str,
+    parent_message_id: Option<i64>,
+    mut metadata: Option<Value>,
+    visible_to: Option<Vec<i32>>
+) -> Result<SentMessage> {
+    tracing::info!(author_id = %author_id, room_id = %room_id, restricted = %visible_to.is_some(), "📝 Envoi d'un message dans le salon");
     
-    // Vérifier que l'utilisateur est membre
-    let is_member: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM conversation_members 
-            WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
-        )
-    ")
-    .bind(room_id)
-    .bind(user_id)
-    .fetch_one(&hub.db)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?
-    .get(0);
+    if parent_message_id.is_some() {
+        hub.require_feature(FeatureFlag::Threads).await?;
+    }
+    validate_user_id(author_id as i32)?;
+    // Auteur suspendu ou banni après des violations répétées du filtre
+    let standing = check_standing(hub, author_id as i32).await?;
+    // Nom dénormalisé sur le message : jamais affiché tel que reçu
+    let username: &str = &normalize_username(username)?;
+    // Transformations configurées (émojis, liens, ...) sur le contenu validé, avant persistance
+    validate_message_content(content, hub.config.limits.max_long_message_length)?;
+    let transformed = hub.content_pipeline.apply(content)?;
+    let visibility = MessageVisibility::from_request(visible_to, author_id)?;
+    
+    // Un renvoi (même nonce) retourne le message déjà stocké sans consommer de quota
+    let dedup_key = DedupKey::from_metadata(author_id, room_id, &transformed.content, &mut metadata)?;
+    if let Some(key) = &dedup_key {
+        if let Some(existing) = hub.room_repository.find_duplicate(key, hub.config.limits.duplicate_window).await? {
+            return Ok(existing);
+        }
+    }
     
-    if !is_member {
-        return Err(ChatError::unauthorized("fetch_room_history"));
+    // Vérification du rate limiting (limite propre à l'auteur, relâchée s'il est de confiance, avant celle du salon)
+    if !check_message_rate(hub, author_id).await? {
+        return Err(ChatError::rate_limit_exceeded_simple("send_message"));
     }
     
-    let mut query_builder = format!("
-        SELECT 
-            m.id, m.uuid, m.author_id, u.username as author_username,
-            m.conversation_id, m.content, m.parent_message_id, m.thread_count,
-            m.status, m.is_edited, m.edit_count, m.is_pinned, m.metadata,
-            m.created_at, m.updated_at, m.edited_at,
-            COALESCE(
-                json_agg(
-                    json_build_object(
-                        'emoji', mr.emoji,
-                        'count', COUNT(mr.id)
-                    ) ORDER BY mr.emoji
-                ) FILTER (WHERE mr.id IS NOT NULL), 
-                '[]'::json
-            ) as reactions,
-            COUNT(mm.id) as mention_count
-        FROM messages m
-        JOIN users u ON u.id = m.author_id
-        LEFT JOIN message_reactions mr ON mr.message_id = m.id
-        LEFT JOIN message_mentions mm ON mm.message_id = m.id
-        WHERE m.conversation_id = $1
-    ");
+    // Vérifier que l'utilisateur est membre du salon et peut y publier
+    let Some(posting) = hub.room_repository.posting_context(room_id, author_id).await? else {
+        return Err(ChatError::unauthorized("send_room_message"));
+    };
     
-    let mut param_count = 1;
+    // Salon archivé : lecture seule pour tous, propriétaire compris
+    if posting.is_archived {
+        return Err(ChatError::ConversationArchived { id: room_id.to_string() });
+    }
     
-    if let Some(_before_id) = before_message_id {
-        param_count += 1;
-        query_builder.push_str(&format!(" AND m.id < ${}", param_count));
+    let member_role = posting.member_role.as_str();
+    if !posting.post_policy.can_post(member_role) {
+        return Err(ChatError::InsufficientPermissions {
+            action: "send_room_message".to_string(),
+            conversation_id: room_id.to_string(),
+        });
     }
     
-    query_builder.push_str("
-        GROUP BY m.id, u.username
-        ORDER BY m.created_at DESC
-    ");
+    // Politique des liens (suivi des liens raccourcis) une fois l'envoi admis
+    let links = review_message_links(hub, Some(room_id), &transformed.content).await?;
+    let content: &str = &links.content;
+    let prepared = PreparedContent::prepare(content, &hub.config.limits)?;
     
-    param_count += 1;
-    query_builder.push_str(&format!(" LIMIT ${}", param_count));
+    // Pièces jointes : fichiers stockés de l'auteur, limites de la configuration resserrées par le salon
+    let attachment_limits = AttachmentLimits::from_config(&hub.config.limits)
+        .with_room_overrides(posting.max_attachments, posting.max_attachments_size);
+    check_message_attachments(hub, author_id, attachment_limits, metadata.as_mut()).await?;
     
-    let mut query_obj = query_as::<_, EnhancedRoomMessage>(&query_builder)
-        .bind(room_id);
+    // Débit du salon (raids coordonnés), seulement pour les membres ; modérateurs exemptés
+    if !is_moderator_role(member_role) {
+        hub.check_room_limit(room_id).await?;
+    }
+    // Mode anti-raid : messages des comptes récents ou mal notés retenus ou refusés
+    let raid_hold = if is_moderator_role(member_role) {
+        None
+    } else {
+        check_raid_message(hub, room_id, author_id).await?
+    };
+    
+    // Salon en mode `flag` : un message signalé par le filtre est retenu pour examen
+    let verdict = screen_room_message(hub, posting.filter_mode, member_role, &posting.languages, &posting.filter_allowlist, content);
+    let hold_reason = record_if_blocked(hub, author_id as i32, Some(room_id), verdict).await?
+        // Auteur mis en examen : tous ses messages sont retenus
+        .or_else(|| standing.under_review.then(|| "Auteur en examen après des violations répétées".to_string()))
+        .or(raid_hold);
+    
+    // Mode lent : délai par membre (modérateurs exemptés), débit du salon
+    let slow_mode_triggered = hub.slow_mode.lock().await
+        .admit(room_id, author_id, is_moderator_role(member_role), posting.slow_mode, std::time::Instant::now())?;
+    if slow_mode_triggered {
+        tracing::warn!(room_id = %room_id, "🐢 Pic de trafic : mode lent automatique activé");
+        if let Err(e) = announce_slow_mode(hub, room_id, Some(hub.config.limits.slow_mode_interval), true).await {
+            tracing::warn!(room_id = %room_id, error = %e, "⚠️ Annonce du mode lent impossible");
+        }
+    }
     
-    if let Some(before_id) = before_message_id {
-        query_obj = query_obj.bind(before_id);
+    // Les destinataires d'un message restreint doivent être membres du salon
+    if let Some(targets) = visibility.targets() {
+        let member_count = hub.room_repository.count_members(room_id, &targets).await?;
+        if member_count != targets.len() as i64 {
+            return Err(ChatError::configuration_error("Destinataire hors du salon"));
+        }
     }
     
-    let messages = query_obj
-        .bind(validated_limit)
-        .fetch_all(&hub.db)
-        .await
-        .map_err(|e| ChatError::from_sqlx_error("fetch_room_history", e))?;
+    let mut message_metadata = metadata.unwrap_or_else(|| json!({}));
+    let quoted = message_metadata.get("quote").is_some();
+    prepared.annotate_metadata(&mut message_metadata);
+    transformed.metadata.annotate_metadata(&mut message_metadata);
+    links.annotate_metadata(&mut message_metadata);
+    
+    let mentions = parse_mentions(content);
+    check_mention_count(&mentions, hub.config.limits.max_mentions_per_message)?;
+    
+    // Salon chiffré : contenu scellé avant stockage, diffusé en clair
+    let data_key = room_data_key(hub.message_cipher.as_ref(), posting.encrypt_at_rest)?;
+    let sealed = seal_prepared(data_key.as_ref(), &prepared)?;
+    
+    // Quota compté une fois le message validé, juste avant son stockage
+    let quota = consume_message_quota(hub, author_id).await?;
+    
+    // Les messages simples (publics, sans fil, citation, corps long, mention ni chiffrement) passent par l'insertion groupée
+    let batchable = parent_message_id.is_none() && !quoted && !prepared.is_long()
+        && mentions.is_empty() && !visibility.is_restricted() && hold_reason.is_none() && data_key.is_none();
+    
+    let inserted = hub.room_repository.insert_message(NewRoomMessage {
+        room_id,
+        author_id,
+        member_role,
+        content: &sealed,
+        parent_message_id,
+        metadata: message_metadata,
+        visible_to: visibility.targets(),
+        dedup_key,
+        hold_reason: hold_reason.as_deref(),
+        data_key: data_key.as_ref(),
+        mentions: &mentions,
+        batchable,
+    }, hub.write_context()).await?;
+    
+    // Renvoi simultané départagé à l'insertion : déjà diffusé par le premier envoi
+    if inserted.duplicate {
+        if quota.is_some() {
+            release_message_quota(hub, author_id).await?;
+        }
+        return Ok(SentMessage { id: inserted.id, created_at: inserted.created_at });
+    }
     
-    tracing::info!(room_id = %room_id, message_count = %messages.len(), "✅ Historique du salon récupéré");
-    Ok(messages)
+    // Message retenu : mentions traitées et diffusion faite à l'approbation
+    if let Some(reason) = hold_reason {
+        notify_message_held(hub, room_id, inserted.id, author_id, &reason).await?;
+        return Ok(SentMessage { id: inserted.id, created_at: inserted.created_at });
+    }
+    
+    // Incrémentation des statistiques
+    hub.increment_message_count().await;
+    record_message(hub, author_id).await;
+    hub.metrics.message_sent("room", Some(&room_id.to_string())).await;
+    hub.metrics.message_size(prepared.stored.len(), "room").await;
+    
+    // Diffusion en temps réel
+    broadcast_room_message(hub, room_id, inserted.id, author_id, username, &prepared.stored, prepared.full_length(), inserted.created_at, parent_message_id, inserted.quote.as_ref(), &mentions, &visibility).await?;
+    
+    notify_mention_recipients(hub, room_id, inserted.id, author_id, &inserted.mentions, &visibility).await;
+    
+    tracing::info!(message_id = %inserted.id, room_id = %room_id, "✅ Message envoyé dans le salon");
+    Ok(SentMessage { id: inserted.id, created_at: inserted.created_at })
 }
 
-/// Récupérer les messages épinglés d'un salon
-pub async fn fetch_pinned_messages(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Vec<RoomMessage>> {
-    tracing::info!(room_id = %room_id, user_id = %user_id, "📌 Récupération des messages épinglés");
+/// Date de fin d'une épingle temporaire (`None` = permanente)
+pub fn pin_expiry(now: DateTime<Utc>, pinned_for: Option<Duration>, max_duration: Duration) -> Result<Option<DateTime<Utc>>> {
+    let Some(pinned_for) = pinned_for else {
+        return Ok(None);
+    };
+    
+    if pinned_for.is_zero() || pinned_for > max_duration {
+        return Err(ChatError::OutOfRange {
+            field: "pin_duration".to_string(),
+            value: pinned_for.as_secs() as i64,
+            min: 1,
+            max: max_duration.as_secs() as i64,
+        });
+    }
     
-    // Vérifier membership
-    let is_member: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM conversation_members 
-            WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
-        )
+    let pinned_for = chrono::Duration::from_std(pinned_for)
+        .map_err(|_| ChatError::configuration_error("Durée d'épinglage invalide"))?;
+    Ok(Some(now + pinned_for))
+}
+
+/// Épingler/désépingler un message
+///
+/// `pinned_for` rend l'épingle temporaire : elle est retirée automatiquement
+/// à l'échéance. Retourne la date de fin de l'épingle.
+pub async fn pin_message(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    user_id: i64,
+    pin: bool,
+    pinned_for: Option<Duration>
+) -> Result<Option<DateTime<Utc>>> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, message_id = %message_id, pin = %pin, "📌 Épinglage de message");
+    
+    hub.require_feature(FeatureFlag::PinnedMessages).await?;
+    let pinned_until = if pin {
+        pin_expiry(Utc::now(), pinned_for, hub.config.limits.max_pin_duration)?
+    } else {
+        None
+    };
+    
+    hub.room_repository.set_pinned(room_id, message_id, user_id, pin, pinned_until, &hub.audit_sink).await?;
+    
+    tracing::info!(message_id = %message_id, pin = %pin, "✅ Statut d'épinglage mis à jour");
+    Ok(pinned_until)
+}
+
+/// Retire les épingles arrivées à échéance et prévient les membres des salons
+///
+/// Chaque retrait est audité comme un désépinglage manuel (`message_unpinned`),
+/// sans modérateur. Retourne le nombre d'épingles retirées.
+pub async fn unpin_expired_messages(hub: &ChatHub) -> Result<usize> {
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    let expired: Vec<(i64, i64)> = query("
+        UPDATE messages
+        SET is_pinned = FALSE, pin_order = NULL, pinned_until = NULL, updated_at = NOW()
+        WHERE is_pinned = TRUE AND pinned_until IS NOT NULL AND pinned_until <= NOW()
+        RETURNING id, conversation_id
+    ")
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("unpin_expired_messages", e))?
+    .into_iter()
+    .map(|row| (row.get("id"), row.get("conversation_id")))
+    .collect();
+    
+    for (message_id, room_id) in &expired {
+        hub.audit_sink.record(&mut *tx, "message_unpinned", None, json!({
+            "room_id": room_id,
+            "message_id": message_id,
+            "automatic": true
+        })).await?;
+    }
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    for (message_id, room_id) in &expired {
+        broadcast_to_room_members(hub, *room_id, &json!({
+            "type": "message_unpinned",
+            "data": {
+                "messageId": message_id,
+                "roomId": room_id,
+                "isPinned": false,
+                "automatic": true
+            }
+        })).await?;
+    }
+    
+    if !expired.is_empty() {
+        tracing::info!(unpinned = %expired.len(), "📌 Épingles expirées retirées");
+    }
+    Ok(expired.len())
+}
+
+/// Tâche de balayage des épingles expirées
+pub fn spawn_pin_expiry_sweeper(hub: Arc<ChatHub>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut sweep = tokio::time::interval(PIN_EXPIRY_SWEEP_INTERVAL);
+        
+        loop {
+            sweep.tick().await;
+            if let Err(e) = unpin_expired_messages(&hub).await {
+                tracing::warn!(error = %e, "⚠️ Échec du retrait des épingles expirées");
+            }
+        }
+    })
+}
+
+/// Épingler, désépingler et réordonner : propriétaire ou modérateur du salon
+pub(crate) fn check_pin_rights(member_role: Option<&str>, action: &str) -> Result<()> {
+    match member_role {
+        Some("owner") | Some("moderator") => Ok(()),
+        _ => Err(ChatError::unauthorized(action)),
+    }
+}
+
+/// Positions d'affichage des épingles après réordonnancement
+///
+/// `current` est l'ordre affiché actuel ; les messages de `ordered` passent en
+/// tête dans l'ordre donné, les autres suivent dans leur ordre actuel.
+pub fn plan_pin_order(current: &[i64], ordered: &[i64]) -> Result<Vec<i64>> {
+    let mut planned: Vec<i64> = Vec::with_capacity(current.len());
+
+    for message_id in ordered {
+        if planned.contains(message_id) {
+            return Err(ChatError::configuration_error("Message épinglé listé plusieurs fois"));
+        }
+        if !current.contains(message_id) {
+            return Err(ChatError::not_found("pinned_message", &message_id.to_string()));
+        }
+        planned.push(*message_id);
+    }
+
+    planned.extend(current.iter().filter(|id| !ordered.contains(id)));
+    Ok(planned)
+}
+
+/// Réordonne les messages épinglés d'un salon (propriétaire ou modérateur)
+///
+/// Retourne l'ordre complet des épingles, diffusé aux membres (`pins_updated`).
+pub async fn reorder_pins(hub: &ChatHub, room_id: i64, ordered_message_ids: &[i64], actor_id: i64) -> Result<Vec<i64>> {
+    tracing::info!(user_id = %actor_id, room_id = %room_id, pin_count = %ordered_message_ids.len(), "📌 Réordonnancement des épingles");
+    
+    hub.require_feature(FeatureFlag::PinnedMessages).await?;
+    
+    let planned = hub.room_repository.reorder_pins(room_id, actor_id, ordered_message_ids, &hub.audit_sink).await?;
+    
+    broadcast_to_room_members(hub, room_id, &json!({
+        "type": "pins_updated",
+        "data": {
+            "roomId": room_id,
+            "messageIds": planned
+        }
+    })).await?;
+    
+    Ok(planned)
+}
+
+/// Éditer un message de salon (auteur, dans la fenêtre autorisée)
+pub async fn edit_room_message(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    user_id: i64,
+    new_content: &str
+) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, message_id = %message_id, "✏️ Édition de message de salon");
+    
+    validate_message_content(new_content, hub.config.limits.max_message_length)?;
+    // Mêmes transformations, politique des liens et filtre du salon qu'à l'envoi
+    let transformed = hub.content_pipeline.apply(new_content)?;
+    let links = review_message_links(hub, Some(room_id), &transformed.content).await?;
+    let new_content: &str = &links.content;
+    screen_room_edit(hub, room_id, user_id, new_content).await?;
+    let mentions = parse_mentions(new_content);
+    check_mention_count(&mentions, hub.config.limits.max_mentions_per_message)?;
+    
+    let edited = hub.room_repository.edit_message(room_id, message_id, user_id, new_content, &mentions, hub.write_context()).await?;
+    
+    let payload = json!({
+        "type": "room_message_edited",
+        "data": {
+            "messageId": message_id,
+            "roomId": room_id,
+            "editorId": user_id,
+            "newContent": new_content,
+            "mentions": mentions_payload(&mentions),
+            "timestamp": Utc::now()
+        }
+    });
+    let visibility = MessageVisibility::from_request(
+        edited.visible_to.map(|ids| ids.into_iter().map(|id| id as i32).collect()),
+        user_id
+    ).unwrap_or_default();
+    broadcast_to_message_audience(hub, room_id, user_id, &visibility, edited.is_held, &payload).await?;
+    
+    if let Some(added_mentions) = edited.added_mentions {
+        notify_mention_recipients(hub, room_id, message_id, user_id, &added_mentions, &visibility).await;
+    }
+    
+    tracing::info!(message_id = %message_id, "✅ Message de salon édité");
+    Ok(())
+}
+
+/// Passe le contenu édité au filtre du salon
+///
+/// Un message déjà diffusé ne peut plus être retenu : le contenu signalé est
+/// refusé. L'attente d'approbation du mode `approve` ne concerne que l'envoi.
+/// Hors salon (administrateur global), le filtre du salon ne s'applique pas.
+async fn screen_room_edit(hub: &ChatHub, room_id: i64, user_id: i64, content: &str) -> Result<()> {
+    let Some(posting) = hub.room_repository.posting_context(room_id, user_id).await? else {
+        return Ok(());
+    };
+    let verdict = screen_room_message(hub, posting.filter_mode, &posting.member_role, &posting.languages, &posting.filter_allowlist, content);
+    let flagged = record_if_blocked(hub, user_id as i32, Some(room_id), verdict).await?
+        .filter(|reason| reason != APPROVAL_PENDING_REASON);
+    match flagged {
+        Some(reason) => Err(ChatError::InappropriateContent { reason }),
+        None => Ok(()),
+    }
+}
+
+/// Supprimer un message de salon (auteur dans la fenêtre, modérateurs à tout moment)
+pub async fn delete_room_message(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    user_id: i64
+) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, message_id = %message_id, "🗑️ Suppression de message de salon");
+    
+    let deleted = hub.room_repository.delete_message(room_id, message_id, user_id, hub.write_context()).await?;
+    
+    let payload = json!({
+        "type": "room_message_deleted",
+        "data": {
+            "messageId": message_id,
+            "roomId": room_id,
+            "deletedBy": user_id
+        }
+    });
+    // Seuls ceux qui pouvaient voir le message apprennent sa suppression
+    let visibility = MessageVisibility::from_request(
+        deleted.visible_to.map(|ids| ids.into_iter().map(|id| id as i32).collect()),
+        deleted.author_id
+    ).unwrap_or_default();
+    broadcast_to_message_audience(hub, room_id, deleted.author_id, &visibility, deleted.is_held, &payload).await?;
+    
+    tracing::info!(message_id = %message_id, "✅ Message de salon supprimé");
+    Ok(())
+}
+
+// ================================================================
+// HISTORIQUE ET RECHERCHE
+// ================================================================
+
+/// Limite maximale de `joinable_history_limit`
+pub const MAX_JOINABLE_HISTORY_LIMIT: i32 = 1000;
+
+/// Appartenance active d'un utilisateur à un salon
+struct ActiveMembership {
+    role: String,
+    joined_at: DateTime<Utc>,
+}
+
+/// Vérifie que l'utilisateur est membre actif du salon
+async fn check_room_member(hub: &ChatHub, room_id: i64, user_id: i64, action: &str) -> Result<ActiveMembership> {
+    query("
+        SELECT role, joined_at FROM conversation_members 
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
     ")
     .bind(room_id)
     .bind(user_id)
-    .fetch_one(&hub.db)
+    .fetch_optional(&hub.db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?
-    .get(0);
+    .map(|row| ActiveMembership { role: row.get("role"), joined_at: row.get("joined_at") })
+    .ok_or_else(|| ChatError::unauthorized(action))
+}
+
+/// Dernier message masqué à un membre (`None` = historique complet)
+///
+/// Un salon avec `joinable_history_limit = N` ne montre à un membre que les N
+/// derniers messages antérieurs à son arrivée, puis tout ce qui suit. Les
+/// modérateurs lisent tout. `earlier_ids` : identifiants des messages
+/// antérieurs à l'arrivée, du plus récent au plus ancien (N + 1 suffisent).
+pub fn history_hidden_through(history_limit: Option<i32>, member_role: &str, earlier_ids: &[i64]) -> Option<i64> {
+    let limit = history_limit?;
+    if is_moderator_role(member_role) {
+        return None;
+    }
+    earlier_ids.get(limit.max(0) as usize).copied()
+}
+
+/// Borne d'historique d'un membre selon le réglage du salon
+async fn member_history_cutoff(hub: &ChatHub, room_id: i64, membership: &ActiveMembership) -> Result<Option<i64>> {
+    let history_limit: Option<i32> = query("SELECT joinable_history_limit FROM conversations WHERE id = $1")
+        .bind(room_id)
+        .fetch_optional(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_history_limit", e))?
+        .and_then(|row| row.get("joinable_history_limit"));
+    
+    let Some(limit) = history_limit else {
+        return Ok(None);
+    };
+    if is_moderator_role(&membership.role) {
+        return Ok(None);
+    }
     
-    if !is_member {
-        return Err(ChatError::unauthorized("fetch_pinned_messages"));
+    let earlier_ids: Vec<i64> = query("
+        SELECT id FROM messages
+        WHERE conversation_id = $1 AND created_at < $2
+        ORDER BY id DESC
+        LIMIT $3
+    ")
+    .bind(room_id)
+    .bind(membership.joined_at)
+    .bind(limit as i64 + 1)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_history_cutoff", e))?
+    .into_iter()
+    .map(|row| row.get("id"))
+    .collect();
+    
+    Ok(history_hidden_through(Some(limit), &membership.role, &earlier_ids))
+}
+
+/// Règle la profondeur d'historique visible des nouveaux membres (modérateurs)
+///
+/// `None` rend l'historique complet à tous les membres.
+pub async fn set_joinable_history_limit(hub: &ChatHub, room_id: i64, moderator_id: i64, history_limit: Option<i32>) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, history_limit = ?history_limit, "📜 Réglage de l'historique des nouveaux membres");
+    
+    if let Some(limit) = history_limit {
+        if !(0..=MAX_JOINABLE_HISTORY_LIMIT).contains(&limit) {
+            return Err(ChatError::OutOfRange {
+                field: "joinable_history_limit".to_string(),
+                value: limit as i64,
+                min: 0,
+                max: MAX_JOINABLE_HISTORY_LIMIT as i64,
+            });
+        }
+    }
+    
+    let membership = check_room_member(hub, room_id, moderator_id, "set_joinable_history_limit").await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized("set_joinable_history_limit"));
+    }
+    
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    query("UPDATE conversations SET joinable_history_limit = $1, updated_at = NOW() WHERE id = $2 AND type = 'public_room'")
+        .bind(history_limit)
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_history_limit", e))?;
+    
+    hub.audit_sink.record(&mut *tx, "room_history_limit_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "joinable_history_limit": history_limit
+    })).await?;
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    Ok(())
+}
+
+/// Choisit le traitement des messages du salon par le filtre de contenu (modérateurs)
+pub async fn set_room_filter_mode(hub: &ChatHub, room_id: i64, moderator_id: i64, mode: RoomFilterMode) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, mode = %mode.as_str(), "🧹 Réglage du filtrage du salon");
+    
+    let membership = check_room_member(hub, room_id, moderator_id, "set_room_filter_mode").await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized("set_room_filter_mode"));
+    }
+    
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    query("UPDATE conversations SET filter_mode = $1, updated_at = NOW() WHERE id = $2 AND type = 'public_room'")
+        .bind(mode.as_str())
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_filter_mode", e))?;
+    
+    hub.audit_sink.record(&mut *tx, "room_filter_mode_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "filter_mode": mode.as_str()
+    })).await?;
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    Ok(())
+}
+
+/// Langues filtrées dans le salon en plus de celles de la configuration (modérateurs)
+pub async fn set_room_languages(hub: &ChatHub, room_id: i64, moderator_id: i64, languages: Vec<String>) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, languages = ?languages, "🌐 Réglage des langues du salon");
+    
+    if let Some(locale) = languages.iter().find(|locale| !is_known_locale(&hub.config.security.content_filter, locale)) {
+        return Err(ChatError::configuration_error(&format!("Langue de filtrage inconnue: {}", locale)));
+    }
+    
+    let membership = check_room_member(hub, room_id, moderator_id, "set_room_languages").await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized("set_room_languages"));
+    }
+    
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    query("UPDATE conversations SET languages = $1, updated_at = NOW() WHERE id = $2 AND type = 'public_room'")
+        .bind(&languages)
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_room_languages", e))?;
+    
+    hub.audit_sink.record(&mut *tx, "room_languages_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "languages": languages
+    })).await?;
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    Ok(())
+}
+
+/// Longueur maximale d'un terme autorisé par le filtre
+const MAX_ALLOWLISTED_TERM_LENGTH: usize = 100;
+
+/// Autorise un terme du salon malgré les mots interdits qu'il contient (modérateurs)
+pub async fn add_allowlisted_term(hub: &ChatHub, room_id: i64, moderator_id: i64, term: &str) -> Result<String> {
+    update_filter_allowlist(hub, room_id, moderator_id, term, true).await
+}
+
+/// Retire un terme de la liste autorisée du salon (modérateurs)
+pub async fn remove_allowlisted_term(hub: &ChatHub, room_id: i64, moderator_id: i64, term: &str) -> Result<String> {
+    update_filter_allowlist(hub, room_id, moderator_id, term, false).await
+}
+
+/// Ajoute ou retire un terme ; retourne le terme normalisé (minuscules)
+async fn update_filter_allowlist(hub: &ChatHub, room_id: i64, moderator_id: i64, term: &str, add: bool) -> Result<String> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, term = %term, add = %add, "✅ Réglage des termes autorisés du salon");
+    
+    let term = term.trim().to_lowercase();
+    if term.is_empty() || term.chars().count() > MAX_ALLOWLISTED_TERM_LENGTH {
+        return Err(ChatError::InvalidFormat {
+            field: "term".to_string(),
+            reason: format!("1 à {} caractères", MAX_ALLOWLISTED_TERM_LENGTH),
+        });
+    }
+    
+    let action = if add { "add_allowlisted_term" } else { "remove_allowlisted_term" };
+    let membership = check_room_member(hub, room_id, moderator_id, action).await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized(action));
+    }
+    
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    let update = if add {
+        "UPDATE conversations SET filter_allowlist = array_append(filter_allowlist, $1), updated_at = NOW()
+         WHERE id = $2 AND type = 'public_room' AND NOT ($1 = ANY(filter_allowlist))"
+    } else {
+        "UPDATE conversations SET filter_allowlist = array_remove(filter_allowlist, $1), updated_at = NOW()
+         WHERE id = $2 AND type = 'public_room'"
+    };
+    query(update)
+        .bind(&term)
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_filter_allowlist", e))?;
+    
+    let event = if add { "room_allowlist_term_added" } else { "room_allowlist_term_removed" };
+    hub.audit_sink.record(&mut *tx, event, Some(moderator_id), json!({
+        "room_id": room_id,
+        "term": term
+    })).await?;
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    Ok(term)
+}
+
+/// Surcharge la politique des liens du salon (modérateurs) ; `None` revient à celle du serveur
+pub async fn set_room_link_policy(hub: &ChatHub, room_id: i64, moderator_id: i64, policy: Option<RoomLinkPolicy>) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, custom = %policy.is_some(), "🔗 Réglage de la politique des liens du salon");
+    
+    if let Some(policy) = &policy {
+        policy.validate()?;
+    }
+    
+    let membership = check_room_member(hub, room_id, moderator_id, "set_room_link_policy").await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized("set_room_link_policy"));
+    }
+    
+    let policy = policy.map(|policy| json!(policy));
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    query("UPDATE conversations SET link_policy = $1, updated_at = NOW() WHERE id = $2 AND type = 'public_room'")
+        .bind(&policy)
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_link_policy", e))?;
+    
+    hub.audit_sink.record(&mut *tx, "room_link_policy_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "link_policy": policy
+    })).await?;
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    Ok(())
+}
+
+/// Surcharge les limites de pièces jointes du salon (modérateurs) ; `None` revient à la configuration
+pub async fn set_room_attachment_limits(
+    hub: &ChatHub,
+    room_id: i64,
+    moderator_id: i64,
+    max_count: Option<i32>,
+    max_total_size: Option<i64>
+) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, max_count = ?max_count, max_total_size = ?max_total_size, "📎 Réglage des pièces jointes du salon");
+    
+    if max_count.is_some_and(|count| count < 0) {
+        return Err(ChatError::configuration_error("Le nombre de pièces jointes ne peut pas être négatif"));
+    }
+    // Une surcharge ne fait que resserrer les limites de la configuration
+    let limits = &hub.config.limits;
+    if max_count.is_some_and(|count| count as usize > limits.max_attachments_per_message) {
+        return Err(ChatError::configuration_error(&format!(
+            "Le nombre de pièces jointes ne peut pas dépasser la limite du serveur ({})",
+            limits.max_attachments_per_message
+        )));
+    }
+    if max_total_size.is_some_and(|size| size as u64 > limits.max_attachments_total_size) {
+        return Err(ChatError::configuration_error(&format!(
+            "La taille cumulée des pièces jointes ne peut pas dépasser la limite du serveur ({} octets)",
+            limits.max_attachments_total_size
+        )));
+    }
+    // Une pièce seule doit toujours pouvoir passer
+    if max_total_size.is_some_and(|size| size < limits.max_file_size as i64) {
+        return Err(ChatError::configuration_error("La taille cumulée des pièces jointes doit couvrir au moins un fichier"));
+    }
+    
+    let membership = check_room_member(hub, room_id, moderator_id, "set_room_attachment_limits").await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized("set_room_attachment_limits"));
+    }
+    
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+    
+    query("UPDATE conversations SET max_attachments = $1, max_attachments_size = $2, updated_at = NOW() WHERE id = $3 AND type = 'public_room'")
+        .bind(max_count)
+        .bind(max_total_size)
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_attachment_limits", e))?;
+    
+    hub.audit_sink.record(&mut *tx, "room_attachment_limits_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "max_attachments": max_count,
+        "max_attachments_size": max_total_size
+    })).await?;
+    
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    
+    Ok(())
+}
+
+/// Récupérer l'historique complet d'un salon
+///
+/// `next_cursor` de la page est le `before_message_id` de la page suivante.
+pub async fn fetch_room_history(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    limit: i64,
+    before_message_id: Option<i64>
+) -> Result<Page<RoomMessage>> {
+    tracing::info!(room_id = %room_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique du salon");
+    
+    hub.require_feature(FeatureFlag::MessageHistory).await?;
+    validate_user_id(user_id as i32)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
+    
+    let membership = check_room_member(hub, room_id, user_id, "fetch_room_history").await?;
+    let hidden_through = member_history_cutoff(hub, room_id, &membership).await?;
+    let messages = load_room_history(hub, room_id, user_id, validated_limit, before_message_id, hidden_through).await?;
+    
+    tracing::info!(room_id = %room_id, message_count = %messages.len(), "✅ Historique du salon récupéré");
+    Ok(messages)
+}
+
+/// Derniers messages d'un salon, du plus récent au plus ancien
+///
+/// Contrat de « retour au plus récent » : équivaut à `fetch_room_history` sans
+/// `before_id` et ne lit que la fin de l'index
+/// `(conversation_id, created_at DESC, id DESC)`, quelle que soit la taille du
+/// salon. Les pages plus anciennes se chargent ensuite avec `before_id`.
+pub async fn get_latest_room_messages(hub: &ChatHub, room_id: i64, user_id: i64, limit: i64) -> Result<Page<RoomMessage>> {
+    fetch_room_history(hub, room_id, user_id, limit, None).await
+}
+
+/// Requête d'une page d'historique, du plus récent au plus ancien
+///
+/// Les `limit` messages sont choisis avant l'agrégation des réactions et des
+/// mentions : sans `before_id`, PostgreSQL lit directement la fin de l'index
+/// `(conversation_id, created_at DESC, id DESC)` au lieu d'agréger tout le salon
+/// (mesures dans la migration 1031). Les réactions sont comptées par émoji
+/// dans une sous-requête par message de la page.
+/// Paramètres : `$1` salon, `$2` utilisateur, puis `before_id`,
+/// `hidden_through` (s'ils sont fournis) et la limite.
+fn room_history_query(with_before: bool, with_hidden_through: bool) -> String {
+    let mut filters = format!("m.conversation_id = $1 AND {} AND {}", visibility_clause(2), held_clause(2));
+    let mut param_count = 2;
+    
+    if with_before {
+        param_count += 1;
+        filters.push_str(&format!(" AND m.id < ${}", param_count));
+    }
+    
+    if with_hidden_through {
+        param_count += 1;
+        filters.push_str(&format!(" AND m.id > ${}", param_count));
+    }
+    
+    param_count += 1;
+    format!("
+        WITH page AS (
+            SELECT m.*
+            FROM messages m
+            WHERE {}
+            ORDER BY m.created_at DESC, m.id DESC
+            LIMIT ${}
+        )
+        SELECT 
+            m.id, m.uuid, m.author_id, u.username as author_username,
+            m.conversation_id, m.content, m.parent_message_id, m.thread_count,
+            m.status, m.is_edited, m.edit_count, m.is_pinned, m.pin_order, m.pinned_until, m.metadata,
+            m.created_at, m.updated_at, m.edited_at,
+            COALESCE((
+                SELECT json_agg(json_build_object('emoji', r.emoji, 'count', r.count) ORDER BY r.emoji)
+                FROM (
+                    SELECT mr.emoji, COUNT(*) as count
+                    FROM message_reactions mr
+                    WHERE mr.message_id = m.id
+                    GROUP BY mr.emoji
+                ) r
+            ), '[]'::json) as reactions,
+            (SELECT COUNT(*) FROM message_mentions mm WHERE mm.message_id = m.id) as mention_count
+        FROM page m
+        JOIN users u ON u.id = m.author_id
+        ORDER BY m.created_at DESC, m.id DESC
+    ", filters, param_count)
+}
+
+/// Page d'historique (appartenance déjà vérifiée)
+///
+/// `hidden_through` : voir `history_hidden_through`. Un message de plus que
+/// `limit` est lu pour savoir s'il reste une page plus ancienne.
+async fn load_room_history(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    limit: i64,
+    before_message_id: Option<i64>,
+    hidden_through: Option<i64>
+) -> Result<Page<RoomMessage>> {
+    let sql = room_history_query(before_message_id.is_some(), hidden_through.is_some());
+    let mut query_obj = query_as::<_, EnhancedRoomMessage>(&sql)
+        .bind(room_id)
+        .bind(user_id);
+    
+    if let Some(before_id) = before_message_id {
+        query_obj = query_obj.bind(before_id);
+    }
+    
+    if let Some(hidden_id) = hidden_through {
+        query_obj = query_obj.bind(hidden_id);
     }
     
-    let messages = query_as::<_, RoomMessage>("
+    let messages = query_obj
+        .bind(limit + 1)
+        .fetch_all(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("fetch_room_history", e))?;
+    let mut page = Page::from_overfetch(messages, limit, |m| m.id);
+    open_room_messages(hub, &mut page.items).await?;
+    Ok(page)
+}
+
+/// Récupérer les messages épinglés d'un salon
+pub async fn fetch_pinned_messages(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Vec<RoomMessage>> {
+    tracing::info!(room_id = %room_id, user_id = %user_id, "📌 Récupération des messages épinglés");
+    
+    check_room_member(hub, room_id, user_id, "fetch_pinned_messages").await?;
+    let messages = load_pinned_messages(hub, room_id, user_id).await?;
+    
+    tracing::info!(room_id = %room_id, pinned_count = %messages.len(), "✅ Messages épinglés récupérés");
+    Ok(messages)
+}
+
+/// Épingles visibles du salon (appartenance déjà vérifiée)
+///
+/// Un message supprimé perd son épingle ; les lignes antérieures à cette règle
+/// sont tout de même écartées.
+async fn load_pinned_messages(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Vec<RoomMessage>> {
+    let mut messages = query_as::<_, RoomMessage>(&format!("
         SELECT 
             m.id, m.uuid, m.author_id, u.username as author_username,
             m.conversation_id, m.content, m.parent_message_id, m.thread_count,
-            m.status, m.is_edited, m.edit_count, m.is_pinned, m.metadata,
+            m.status, m.is_edited, m.edit_count, m.is_pinned, m.pin_order, m.pinned_until, m.metadata,
             m.created_at, m.updated_at, m.edited_at,
             '[]'::json as reactions,
             0 as mention_count
         FROM messages m
         JOIN users u ON u.id = m.author_id
-        WHERE m.conversation_id = $1 AND m.is_pinned = TRUE
-        ORDER BY m.created_at DESC
-    ")
+        WHERE m.conversation_id = $1 AND m.is_pinned = TRUE AND m.status != 'deleted' AND {} AND {}
+        ORDER BY m.pin_order ASC NULLS LAST, m.created_at DESC
+    ", visibility_clause(2), held_clause(2)))
     .bind(room_id)
+    .bind(user_id)
     .fetch_all(&hub.db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("fetch_pinned_messages", e))?;
-    
-    tracing::info!(room_id = %room_id, pinned_count = %messages.len(), "✅ Messages épinglés récupérés");
+    open_room_messages(hub, &mut messages).await?;
     Ok(messages)
 }
 
+/// Ouverture d'un salon : épingles et première page d'historique
+#[derive(Debug, Serialize)]
+pub struct RoomOpening {
+    pub pinned: Vec<RoomMessage>,
+    pub history: Page<RoomMessage>,
+}
+
+/// Identifiants des messages épinglés d'une page d'historique
+pub fn pinned_ids(messages: &[RoomMessage]) -> Vec<i64> {
+    messages.iter().filter(|m| m.is_pinned).map(|m| m.id).collect()
+}
+
+/// Épingles et première page d'historique en un seul appel
+///
+/// Remplace la paire `fetch_pinned_messages` + `fetch_room_history` à
+/// l'ouverture d'un salon (une seule vérification d'appartenance). Les épingles
+/// sont vides si la fonctionnalité est coupée.
+pub async fn open_room(hub: &ChatHub, room_id: i64, user_id: i64, limit: i64) -> Result<RoomOpening> {
+    tracing::info!(room_id = %room_id, user_id = %user_id, limit = %limit, "🚪 Ouverture du salon");
+    
+    hub.require_feature(FeatureFlag::MessageHistory).await?;
+    validate_user_id(user_id as i32)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
+    
+    let membership = check_room_member(hub, room_id, user_id, "open_room").await?;
+    let hidden_through = member_history_cutoff(hub, room_id, &membership).await?;
+    
+    let pinned = if hub.get_feature_flags().await.is_enabled(FeatureFlag::PinnedMessages) {
+        load_pinned_messages(hub, room_id, user_id).await?
+    } else {
+        Vec::new()
+    };
+    let history = load_room_history(hub, room_id, user_id, validated_limit, None, hidden_through).await?;
+    
+    tracing::info!(room_id = %room_id, pinned_count = %pinned.len(), message_count = %history.len(), "✅ Salon ouvert");
+    Ok(RoomOpening { pinned, history })
+}
+
 // ================================================================
 // STATISTIQUES ET ADMINISTRATION
 // ================================================================
@@ -624,9 +1513,15 @@ pub async fn get_room_stats(hub: &ChatHub, room_id: i64) -> Result<RoomStats> {
     Ok(stats)
 }
 
-/// Lister les membres d'un salon
-pub async fn list_room_members(hub: &ChatHub, room_id: i64, requesting_user_id: i64) -> Result<Vec<RoomMember>> {
+/// Lister les membres d'un salon, par rôle puis par ancienneté
+///
+/// `cursor` est le `next_cursor` de la page précédente (décalage : l'ordre
+/// change quand un rôle est modifié).
+pub async fn list_room_members(hub: &ChatHub, room_id: i64, requesting_user_id: i64, limit: i64, cursor: Option<i64>) -> Result<Page<RoomMember>> {
     tracing::info!(room_id = %room_id, requesting_user = %requesting_user_id, "👥 Récupération de la liste des membres");
+
+    let limit = validate_limit(limit, &hub.config.limits)?;
+    let offset = cursor.unwrap_or(0).max(0);
     
     // Vérifier que l'utilisateur est membre
     let is_member: bool = query("
@@ -656,110 +1551,355 @@ pub async fn list_room_members(hub: &ChatHub, room_id: i64, requesting_user_id:
                 WHEN 'moderator' THEN 2 
                 ELSE 3 
             END,
-            joined_at ASC
+            joined_at ASC,
+            id ASC
+        LIMIT $2 OFFSET $3
     ")
     .bind(room_id)
+    .bind(limit + 1)
+    .bind(offset)
     .fetch_all(&hub.db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("list_room_members", e))?;
     
-    tracing::info!(room_id = %room_id, member_count = %members.len(), "✅ Liste des membres récupérée");
-    Ok(members)
+    let page = Page::from_offset(members, limit, offset);
+    tracing::info!(room_id = %room_id, member_count = %page.len(), "✅ Liste des membres récupérée");
+    Ok(page)
 }
 
 // ================================================================
 // FONCTIONS UTILITAIRES
 // ================================================================
 
-/// Traiter les mentions dans un message
-async fn process_mentions(tx: &mut Transaction<'_, Postgres>, message_id: i64, content: &str) -> Result<()> {
-    use regex::Regex;
+/// Message chargé pour une édition ou une suppression
+pub(crate) struct ModificationTarget {
+    pub(crate) author_id: i64,
+    pub(crate) ctx: ModificationContext,
+    policy: MessagePolicy,
+    pub(crate) content: String,
+    /// Rôle de l'utilisateur dans le salon (`None` : non membre)
+    pub(crate) member_role: Option<String>,
+    /// Rang effectif de l'utilisateur : rôle global ou rôle dans le salon
+    role: Role,
+    pub(crate) is_held: bool,
+    pub(crate) visible_to: Option<Vec<i64>>,
+    /// Salon chiffré au repos
+    pub(crate) encrypted: bool,
+    /// Clé de données du message, s'il est scellé
+    pub(crate) data_key: Option<DataKey>,
+}
+
+impl ModificationTarget {
+    /// Vérifie le droit d'éditer ou de supprimer le message (`check_room_modification`)
+    pub(crate) fn check(&self, moderation: &ModerationConfig, action: MessageAction, now: DateTime<Utc>) -> Result<()> {
+        check_room_modification(moderation, &self.role, self.member_role.as_deref(), &self.ctx, &self.policy, action, now)
+    }
+}
+
+/// Droit d'éditer ou de supprimer un message de salon
+///
+/// Le rang décide d'abord (`permissions::check_message_action`, portée
+/// `[moderation]`, modérateur du salon = salon assigné) ; l'auteur reste
+/// ensuite soumis aux fenêtres et verrous de `MessagePolicy`.
+/// `member_role` : rôle dans le salon (`None` : non membre).
+pub(crate) fn check_room_modification(
+    moderation: &ModerationConfig,
+    role: &Role,
+    member_role: Option<&str>,
+    ctx: &ModificationContext,
+    policy: &MessagePolicy,
+    action: MessageAction,
+    now: DateTime<Utc>
+) -> Result<()> {
+    // Un ancien membre n'agit plus sur ses messages
+    if ctx.is_author && member_role.is_none() {
+        return Err(ChatError::unauthorized("modify_room_message"));
+    }
+    check_message_action(moderation, role, action, ctx.is_author, ctx.is_moderator)?;
+    if !ctx.is_author {
+        return Ok(());
+    }
+    match action {
+        MessageAction::Edit => policy.check_edit(ctx, now),
+        MessageAction::Delete => policy.check_delete(ctx, now),
+    }
+}
+
+/// Charger l'état d'un message et la politique effective du salon
+pub(crate) async fn load_modification_context(
+    cipher: Option<&MessageCipher>,
+    limits: &LimitsConfig,
+    tx: &mut Transaction<'_, Postgres>,
+    room_id: i64,
+    message_id: i64,
+    user_id: i64
+) -> Result<ModificationTarget> {
+    let row = query("
+        SELECT 
+            m.author_id, m.content, m.created_at, m.thread_count, m.is_held, m.visible_to,
+            m.encryption_key_id, m.wrapped_key,
+            (SELECT COUNT(*) FROM message_reactions r WHERE r.message_id = m.id) as reaction_count,
+            (SELECT u.role::text FROM users u WHERE u.id = $3) as user_role,
+            cm.role,
+            c.edit_window_seconds, c.delete_window_seconds,
+            c.edit_lock_after_reactions, c.edit_lock_after_replies, c.encrypt_at_rest
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        LEFT JOIN conversation_members cm ON cm.conversation_id = c.id AND cm.user_id = $3 AND cm.left_at IS NULL
+        WHERE m.id = $1 AND m.conversation_id = $2 AND m.status != 'deleted'
+    ")
+    .bind(message_id)
+    .bind(room_id)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_modification_context", e))?
+    .ok_or_else(|| ChatError::not_found("message", &message_id.to_string()))?;
+    
+    let member_role: Option<String> = row.get("role");
+    let user_role: Option<String> = row.get("user_role");
+    let role = Role::effective(user_role.as_deref().unwrap_or("user"), member_role.as_deref().unwrap_or("member"));
+    
+    let overrides = RoomMessagePolicy {
+        edit_window_seconds: row.get("edit_window_seconds"),
+        delete_window_seconds: row.get("delete_window_seconds"),
+        edit_lock_after_reactions: row.get("edit_lock_after_reactions"),
+        edit_lock_after_replies: row.get("edit_lock_after_replies"),
+    };
+    
+    let ctx = ModificationContext {
+        is_author: row.get::<i64, _>("author_id") == user_id,
+        is_moderator: member_role.as_deref().is_some_and(is_moderator_role),
+        created_at: row.get("created_at"),
+        reaction_count: row.get("reaction_count"),
+        reply_count: row.get::<i32, _>("thread_count") as i64,
+    };
+    
+    let key_id: Option<String> = row.get("encryption_key_id");
+    let wrapped: Option<Vec<u8>> = row.get("wrapped_key");
+    let data_key = message_data_key(cipher, key_id.as_deref(), wrapped.as_deref())?;
+    let stored: String = row.get("content");
+    let content = match &data_key {
+        Some(data_key) => data_key.open(&stored)?,
+        None => stored,
+    };
+    
+    Ok(ModificationTarget {
+        author_id: row.get("author_id"),
+        ctx,
+        policy: MessagePolicy::from_limits(limits).with_room_overrides(&overrides),
+        content,
+        member_role,
+        role,
+        is_held: row.get("is_held"),
+        visible_to: row.get("visible_to"),
+        encrypted: row.get("encrypt_at_rest"),
+        data_key,
+    })
+}
+
+/// Inscrit un événement de salon au journal de reprise et retourne la trame
+/// numérotée (`eventId`) ; sans journal disponible, la trame part sans numéro.
+/// La trame est aussi publiée sur le pont NATS, s'il est configuré.
+async fn stamp_room_event(hub: &ChatHub, room_id: i64, payload: &Value, recipients: Option<&[i64]>) -> VersionedFrame {
+    let frame = match hub.event_log.append(room_id, payload, recipients).await {
+        Ok(event_id) => stamp_event(payload, event_id),
+        Err(e) => {
+            tracing::warn!(room_id = %room_id, error = %e, "⚠️ Événement non journalisé, diffusé sans eventId");
+            payload.clone()
+        }
+    };
+    // Les événements restreints ne quittent pas le serveur
+    if recipients.is_none() {
+        hub.bridge_room_event(room_id, &frame).await;
+    }
+    VersionedFrame::new(frame)
+}
+
+/// Événements d'un salon manqués depuis `last_event_id` (reprise après reconnexion)
+///
+/// Les événements restreints (messages à visibilité limitée) ne sont rejoués
+/// qu'à leurs destinataires. `complete: false` signale un trou : le client
+/// doit recharger l'historique.
+pub async fn resume_room_events(hub: &ChatHub, room_id: i64, user_id: i64, last_event_id: u64) -> Result<Value> {
+    check_room_member(hub, room_id, user_id, "resume").await?;
+
+    let version = hub.schema_version_of(user_id as i32).await;
+    let replay = hub.event_log.since(room_id, last_event_id).await?;
+    let events: Vec<Value> = replay.events.iter()
+        .filter(|event| event.visible_to(user_id))
+        .map(|event| downgrade(&event.to_frame(), version))
+        .collect();
+
+    tracing::debug!(room_id = %room_id, user_id = %user_id, replayed = %events.len(), complete = %replay.complete, "⏪ Reprise des événements du salon");
+
+    Ok(json!({
+        "roomId": room_id,
+        "events": events,
+        "complete": replay.complete,
+        "lastEventId": replay.last_event_id
+    }))
+}
+
+/// Envoyer un événement à tous les membres connectés d'un salon
+pub(crate) async fn broadcast_to_room_members(hub: &ChatHub, room_id: i64, payload: &Value) -> Result<()> {
+    let member_ids = hub.room_repository.member_ids(room_id).await?;
     
-    let mention_regex = Regex::new(r"@(\w+)").unwrap();
+    let frame = stamp_room_event(hub, room_id, payload, None).await;
     
-    for cap in mention_regex.captures_iter(content) {
-        let username = &cap[1];
-        
-        // Trouver l'ID de l'utilisateur mentionné
-        if let Ok(user_row) = query("SELECT id FROM users WHERE username = $1")
-            .bind(username)
-            .fetch_one(&mut **tx)
-            .await {
-            
-            let mentioned_user_id: i64 = user_row.get("id");
-            
-            // Ajouter la mention
-            query("
-                INSERT INTO message_mentions (message_id, mentioned_user_id)
-                VALUES ($1, $2)
-                ON CONFLICT (message_id, mentioned_user_id) DO NOTHING
-            ")
-            .bind(message_id)
-            .bind(mentioned_user_id)
-            .execute(&mut **tx)
-            .await
-            .map_err(|e| ChatError::from_sqlx_error("insert_mention", e))?;
-        }
+    for client in hub.clients.get_many(member_ids.into_iter().map(|user_id| user_id as i32)).await {
+        client.send_frame(&frame);
     }
     
     Ok(())
 }
 
-/// Diffuser un message en temps réel aux membres du salon
-async fn broadcast_room_message(
+/// Membres autorisés à voir un message : ciblés et auteur s'il est restreint, auteur seul s'il est retenu
+fn message_audience(member_ids: Vec<i64>, author_id: i64, visibility: &MessageVisibility, is_held: bool) -> Vec<i64> {
+    if is_held {
+        return member_ids.into_iter().filter(|&id| id == author_id).collect();
+    }
+    visibility.filter_recipients(member_ids, author_id)
+}
+
+/// Envoyer un événement portant sur un message à la seule audience de ce message
+///
+/// Même filtre que `broadcast_room_message` : un message restreint ou retenu
+/// ne fuit pas par ses événements (édition), journalisés comme restreints.
+pub(crate) async fn broadcast_to_message_audience(
     hub: &ChatHub,
     room_id: i64,
-    message_id: i64,
     author_id: i64,
-    username: &str,
-    content: &str,
-    timestamp: DateTime<Utc>,
-    parent_message_id: Option<i64>
+    visibility: &MessageVisibility,
+    is_held: bool,
+    payload: &Value
 ) -> Result<()> {
-    let clients = hub.clients.read().await;
+    let member_ids = hub.room_repository.member_ids(room_id).await?;
+    let audience = message_audience(member_ids, author_id, visibility, is_held);
+    let public = !visibility.is_restricted() && !is_held;
     
-    // Récupérer la liste des membres connectés
-    let member_ids: Vec<i64> = query("
-        SELECT user_id 
-        FROM conversation_members 
-        WHERE conversation_id = $1 AND left_at IS NULL
+    let frame = stamp_room_event(hub, room_id, payload, (!public).then_some(audience.as_slice())).await;
+    for client in hub.clients.get_many(audience.iter().map(|user_id| *user_id as i32)).await {
+        client.send_frame(&frame);
+    }
+    if public {
+        forward_to_guests(hub, room_id, &frame).await;
+    }
+    
+    Ok(())
+}
+
+/// Membres dont le rôle effectif atteint `role` : (membre, rôle global, rôle dans le salon)
+fn role_audience(members: Vec<(i64, String, String)>, role: &Role) -> Vec<i64> {
+    members.into_iter()
+        .filter(|(_, user_role, member_role)| Role::effective(user_role, member_role).is_at_least(role))
+        .map(|(user_id, _, _)| user_id)
+        .collect()
+}
+
+/// Envoyer un événement aux seuls membres connectés d'un salon ayant au moins `role`
+///
+/// Le rôle effectif est le plus élevé du rôle global (`users.role`) et du
+/// rôle dans le salon. L'événement est journalisé comme restreint : seule
+/// cette audience le retrouve à la reprise. Retourne le nombre de sessions
+/// atteintes.
+pub async fn broadcast_to_role(hub: &ChatHub, room_id: i64, role: &Role, payload: &Value) -> Result<usize> {
+    let members: Vec<(i64, String, String)> = query("
+        SELECT cm.user_id, u.role::text as user_role, cm.role as member_role
+        FROM conversation_members cm
+        JOIN users u ON u.id = cm.user_id
+        WHERE cm.conversation_id = $1 AND cm.left_at IS NULL
     ")
     .bind(room_id)
     .fetch_all(&hub.db)
     .await
-    .map_err(|e| ChatError::from_sqlx_error("get_room_members", e))?
+    .map_err(|e| ChatError::from_sqlx_error("get_room_members_roles", e))?
     .into_iter()
-    .map(|row| row.get::<i64, _>("user_id"))
+    .map(|row| (row.get("user_id"), row.get("user_role"), row.get("member_role")))
     .collect();
     
+    let audience = role_audience(members, role);
+    let frame = stamp_room_event(hub, room_id, payload, Some(&audience)).await;
+    
+    let delivered = hub.clients.get_many(audience.iter().map(|user_id| *user_id as i32)).await
+        .iter()
+        .filter(|client| client.send_frame(&frame))
+        .count();
+    
+    tracing::info!(room_id = %room_id, role = %role.as_str(), audience = %audience.len(), delivered = %delivered, "📣 Diffusion restreinte à un rôle");
+    Ok(delivered)
+}
+
+/// Avis d'un modérateur réservé aux membres ayant au moins `role` (trame `role_notice`)
+pub async fn send_role_notice(hub: &ChatHub, room_id: i64, sender_id: i64, role: &Role, content: &str) -> Result<usize> {
+    validate_message_content(content, hub.config.limits.max_message_length)?;
+    
+    let membership = check_room_member(hub, room_id, sender_id, "send_role_notice").await?;
+    if !is_moderator_role(&membership.role) {
+        return Err(ChatError::unauthorized("send_role_notice"));
+    }
+    
     let payload = json!({
-        "type": "room_message",
+        "type": "role_notice",
         "data": {
-            "id": message_id,
             "roomId": room_id,
-            "authorId": author_id,
-            "username": username,
+            "fromUserId": sender_id,
+            "role": role.as_str(),
             "content": content,
-            "timestamp": timestamp,
-            "parentMessageId": parent_message_id,
-            "isThread": parent_message_id.is_some()
+            "timestamp": Utc::now()
         }
     });
+    broadcast_to_role(hub, room_id, role, &payload).await
+}
+
+/// Diffuser un message en temps réel aux membres du salon
+pub(crate) async fn broadcast_room_message(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    author_id: i64,
+    username: &str,
+    content: &str,
+    full_length: Option<usize>,
+    timestamp: DateTime<Utc>,
+    parent_message_id: Option<i64>,
+    quote: Option<&QuotedExcerpt>,
+    mentions: &[ParsedMention],
+    visibility: &MessageVisibility
+) -> Result<()> {
+    // Récupérer la liste des membres connectés
+    let member_ids = hub.room_repository.member_ids(room_id).await?;
+    
+    // Message restreint : uniquement les destinataires ciblés et l'auteur
+    let member_ids = message_audience(member_ids, author_id, visibility, false);
     
+    let payload = MessagePayload::new(message_id, author_id, username, content, timestamp)
+        .in_room(room_id)
+        .with_parent(parent_message_id)
+        .with_full_length(full_length)
+        .with_quote(quote.map(|q| q.to_payload()))
+        .with_audience(mentions_payload(mentions), visibility.targets())
+        .to_frame("room_message");
+    
+    let recipients = visibility.is_restricted().then_some(member_ids.as_slice());
+    let frame = stamp_room_event(hub, room_id, &payload, recipients).await;
+    
+    let connected = hub.clients.get_many(member_ids.iter().map(|user_id| *user_id as i32)).await;
     let mut successful_sends = 0;
-    let mut failed_sends = 0;
-    
-    for user_id in member_ids {
-        if let Some(client) = clients.get(&(user_id as i32)) {
-            if client.send_text(&payload.to_string()) {
-                successful_sends += 1;
-            } else {
-                failed_sends += 1;
-            }
+    // Membres non connectés comptés comme échecs
+    let mut failed_sends = member_ids.len() - connected.len();
+    
+    for client in connected {
+        if client.send_frame(&frame) {
+            successful_sends += 1;
         } else {
             failed_sends += 1;
         }
     }
+    // Invités en lecture : uniquement les messages visibles de tous
+    if !visibility.is_restricted() {
+        successful_sends += forward_to_guests(hub, room_id, &frame).await;
+    }
     
     tracing::info!(
         room_id = %room_id, 
@@ -770,4 +1910,187 @@ async fn broadcast_room_message(
     );
     
     Ok(())
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_page_is_limited_before_aggregation() {
+        let latest = room_history_query(false, false);
+        let limit_at = latest.find("LIMIT $3").unwrap();
+        assert!(limit_at < latest.find("json_agg").unwrap());
+        assert!(latest.contains("FROM page m"));
+        
+        let older = room_history_query(true, true);
+        assert!(older.contains("m.id < $3 AND m.id > $4"));
+        assert!(older.contains("LIMIT $5"));
+    }
+
+    #[test]
+    fn test_role_audience_uses_effective_role() {
+        let members = vec![
+            (1, "user".to_string(), "member".to_string()),
+            (2, "user".to_string(), "moderator".to_string()),
+            (3, "admin".to_string(), "member".to_string()),
+        ];
+        assert_eq!(role_audience(members.clone(), &Role::Moderator), vec![2, 3]);
+        assert_eq!(role_audience(members.clone(), &Role::Admin), vec![3]);
+        assert_eq!(role_audience(members, &Role::User).len(), 3);
+    }
+
+    #[test]
+    fn test_announcement_member_can_react_but_not_post() {
+        let policy = RoomPostPolicy::Announcement;
+        assert!(!policy.can_post("member"));
+        assert!(policy.can_react("member"));
+        assert!(policy.can_post("moderator"));
+        assert!(policy.can_react("owner"));
+    }
+
+    #[test]
+    fn test_moderators_only_restricts_reactions() {
+        let policy = RoomPostPolicy::ModeratorsOnly;
+        assert!(!policy.can_post("member"));
+        assert!(!policy.can_react("member"));
+        assert!(policy.can_post("admin"));
+        assert!(policy.can_react("admin"));
+    }
+
+    #[test]
+    fn test_room_updated_frame() {
+        assert_eq!(
+            room_updated_frame(4, true, 7),
+            json!({ "type": "room_updated", "data": { "roomId": 4, "isArchived": true, "updatedBy": 7 } })
+        );
+    }
+
+    #[test]
+    fn test_archive_change_requires_room_moderator_and_new_state() {
+        let state = |is_archived, role: Option<&str>| ArchiveState { is_archived, member_role: role.map(str::to_string) };
+
+        for role in ["owner", "admin", "moderator"] {
+            assert!(check_archive_change(&state(false, Some(role)), true).is_ok());
+            assert!(check_archive_change(&state(true, Some(role)), false).is_ok());
+        }
+        for role in [None, Some("member")] {
+            assert!(matches!(check_archive_change(&state(false, role), true), Err(ChatError::Unauthorized { .. })));
+            assert!(matches!(check_archive_change(&state(true, role), false), Err(ChatError::Unauthorized { .. })));
+        }
+
+        // Droits vérifiés avant l'état : un non-modérateur n'apprend pas si le salon est archivé
+        assert!(matches!(check_archive_change(&state(true, Some("member")), true), Err(ChatError::Unauthorized { .. })));
+        assert!(check_archive_change(&state(true, Some("owner")), true).is_err());
+        assert!(check_archive_change(&state(false, Some("owner")), false).is_err());
+    }
+
+    #[test]
+    fn test_post_policy_db_roundtrip() {
+        for policy in [RoomPostPolicy::Everyone, RoomPostPolicy::ModeratorsOnly, RoomPostPolicy::Announcement] {
+            assert_eq!(RoomPostPolicy::from_db(policy.as_str()), policy);
+        }
+        assert_eq!(RoomPostPolicy::from_db("inconnu"), RoomPostPolicy::Everyone);
+    }
+
+    #[test]
+    fn test_new_member_history_is_capped() {
+        // Messages 10..=1 antérieurs à l'arrivée, du plus récent au plus ancien
+        let earlier: Vec<i64> = (1..=10).rev().collect();
+        
+        // Nouveau membre : seuls les 3 derniers (10, 9, 8) restent visibles
+        assert_eq!(history_hidden_through(Some(3), "member", &earlier), Some(7));
+        assert_eq!(history_hidden_through(Some(0), "member", &earlier), Some(10));
+        // Moins de messages antérieurs que la limite : tout est visible
+        assert_eq!(history_hidden_through(Some(20), "member", &earlier), None);
+    }
+
+    #[test]
+    fn test_moderators_and_unlimited_rooms_see_full_history() {
+        let earlier: Vec<i64> = (1..=10).rev().collect();
+        
+        assert_eq!(history_hidden_through(Some(3), "moderator", &earlier), None);
+        assert_eq!(history_hidden_through(Some(3), "owner", &earlier), None);
+        assert_eq!(history_hidden_through(None, "member", &earlier), None);
+    }
+
+    #[test]
+    fn test_pin_expiry_validation() {
+        let now = Utc::now();
+        let max = Duration::from_secs(7 * 24 * 3600);
+        
+        assert_eq!(pin_expiry(now, None, max).unwrap(), None);
+        assert_eq!(
+            pin_expiry(now, Some(Duration::from_secs(3600)), max).unwrap(),
+            Some(now + chrono::Duration::hours(1))
+        );
+        // Échéance immédiate ou au-delà du maximum
+        assert!(pin_expiry(now, Some(Duration::ZERO), max).is_err());
+        assert!(matches!(
+            pin_expiry(now, Some(max + Duration::from_secs(1)), max),
+            Err(ChatError::OutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pin_order_rejects_unknown_or_duplicate_ids() {
+        assert!(plan_pin_order(&[30, 20], &[20, 20]).is_err());
+        assert!(plan_pin_order(&[30, 20], &[99]).is_err());
+    }
+
+    fn target(role: Role, member_role: Option<&str>, is_author: bool, age_secs: i64) -> ModificationTarget {
+        ModificationTarget {
+            author_id: if is_author { 1 } else { 2 },
+            ctx: ModificationContext {
+                is_author,
+                is_moderator: member_role.is_some_and(is_moderator_role),
+                created_at: Utc::now() - chrono::Duration::seconds(age_secs),
+                reaction_count: 0,
+                reply_count: 0,
+            },
+            policy: MessagePolicy::from_limits(&crate::config::LimitsConfig::default()),
+            content: String::new(),
+            member_role: member_role.map(str::to_string),
+            role,
+            is_held: false,
+            visible_to: None,
+            encrypted: false,
+            data_key: None,
+        }
+    }
+
+    #[test]
+    fn test_room_modification_follows_moderation_tiers() {
+        let moderation = ModerationConfig::default();
+        let now = Utc::now();
+        let delete = |target: &ModificationTarget| target.check(&moderation, MessageAction::Delete, now).is_ok();
+
+        // Modérateur du salon : salon assigné ; modérateur global ailleurs : refusé
+        assert!(delete(&target(Role::Moderator, Some("moderator"), false, 10)));
+        assert!(!delete(&target(Role::Moderator, Some("member"), false, 10)));
+        // Modérateur senior : partout, même hors du salon
+        assert!(delete(&target(Role::SeniorModerator, None, false, 10)));
+        assert!(!delete(&target(Role::User, Some("member"), false, 10)));
+
+        // Auteur : soumis à la fenêtre, et seulement tant qu'il est membre
+        assert!(delete(&target(Role::User, Some("member"), true, 10)));
+        assert!(!delete(&target(Role::User, Some("member"), true, 10 * 86400)));
+        assert!(!delete(&target(Role::User, None, true, 10)));
+
+        // Édition des messages d'autrui : désactivée par défaut, administrateurs compris
+        assert!(target(Role::Admin, Some("owner"), false, 10).check(&moderation, MessageAction::Edit, now).is_err());
+    }
+
+    #[test]
+    fn test_edit_audience_matches_message_audience() {
+        let members = vec![1, 2, 3, 4];
+        let public = MessageVisibility::default();
+        assert_eq!(message_audience(members.clone(), 1, &public, false), vec![1, 2, 3, 4]);
+
+        // Message restreint : ciblés et auteur ; retenu : auteur seul
+        let whisper = MessageVisibility::from_request(Some(vec![3]), 1).unwrap();
+        assert_eq!(message_audience(members.clone(), 1, &whisper, false), vec![1, 3]);
+        assert_eq!(message_audience(members.clone(), 1, &public, true), vec![1]);
+        assert_eq!(message_audience(members, 1, &whisper, true), vec![1]);
+    }
+}
@@ -3,22 +3,74 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
-use sqlx::PgPool;
+use tokio::sync::{Mutex, RwLock};
+use sqlx::{PgExecutor, PgPool};
+use serde::Serialize;
 
-use crate::client::Client;
+use crate::client::{AckMode, Client, SendOutcome};
+use crate::close_codes::{CloseReason, RetryPolicy};
 use crate::rate_limiter::RateLimiter;
+use crate::room_id::RoomId;
+use crate::sharded_map::ShardedMap;
 use crate::config::ServerConfig;
 use crate::cache::CacheManager;
 use crate::monitoring::ChatMetrics;
 use crate::moderation::ModerationSystem;
-use crate::presence::PresenceManager;
+use crate::presence::{NotificationManager, PresenceManager};
+use crate::hub::presence_subscriptions::{publish_online, publish_offline};
+use crate::security::{AdvancedRateLimiter, IpMonitor, SecurityAction};
+use crate::error::{ChatError, Result};
+use crate::validation::normalize_username;
 use crate::reactions::ReactionManager;
+use crate::message_batcher::{BatchConfig, MessageBatcher, PgBatchSink};
+use crate::hub::onboarding::auto_join_default_rooms;
+use crate::hub::maintenance::{Departure, EmptyRoomTracker};
+use crate::hub::memberships::restore_room_memberships;
+use crate::hub::guests::{forget_guest, is_guest};
+use crate::hub::violations::check_not_banned;
+use crate::hub::audit_sink::AuditSink;
+use crate::hub::feature_flags::FeatureFlags;
+use crate::hub::slow_mode::{SlowModeSettings, SlowModeTracker};
+use crate::hub::anti_raid::AntiRaidTracker;
+use crate::hub::room_repository::{PgRoomRepository, RoomRepository, WriteContext};
+use crate::hub::dm_repository::{DmRepository, PgDmRepository};
+use crate::hub::reaction_repository::{PgReactionRepository, ReactionRepository};
+use crate::hub::attachment_repository::{AttachmentRepository, PgAttachmentRepository};
+use crate::hub::moderation_repository::{ModerationRepository, PgModerationRepository};
+use crate::content_pipeline::ContentPipeline;
+use crate::object_store::{LocalObjectStore, ObjectStore};
+use crate::event_log::{event_log_from_config, EventLog};
+use crate::message_quota::{quota_counter_from_config, QuotaCounter};
+use crate::profile_cache::{profile_cache_from_config, ProfileCache};
+use crate::link_policy::{link_expander_from_config, LinkExpander};
+use crate::event_bridge::{event_bridge_from_config, EventBridge};
+use crate::encryption::MessageCipher;
+use crate::message_schema::{downgrade, CURRENT_SCHEMA_VERSION};
 
 pub struct ChatHub {
-    pub clients: Arc<RwLock<HashMap<i32, Client>>>,
-    pub rooms: Arc<RwLock<HashMap<String, Vec<i32>>>>,
+    /// Connexion principale par utilisateur, fragmentée (`limits.state_shards`)
+    pub clients: ShardedMap<i32, Client>,
+    /// Toutes les connexions ouvertes par utilisateur (plusieurs appareils), fragmentées comme `clients`
+    pub sessions: ShardedMap<i32, Vec<Client>>,
+    /// Membres connectés par salon, fragmentés comme `clients`
+    pub rooms: ShardedMap<RoomId, Vec<i32>>,
+    /// Invités présents par salon (identifiant persisté), jamais membres persistés
+    pub guest_rooms: ShardedMap<i64, Vec<i32>>,
+    /// Salons vides de `rooms`, retirés après `limits.empty_room_grace`
+    pub empty_rooms: Mutex<EmptyRoomTracker>,
+    /// Utilisateurs déconnectés encore membres de leurs salons (`limits.reconnect_grace`)
+    pub departures: Mutex<HashMap<i32, Departure>>,
     pub db: PgPool,
+    /// Salons, adhésions et messages persistés (PostgreSQL, ou mémoire pour les tests)
+    pub room_repository: Arc<dyn RoomRepository>,
+    /// Conversations privées, contacts et clés E2EE
+    pub dm_repository: Arc<dyn DmRepository>,
+    /// Réactions aux messages
+    pub reaction_repository: Arc<dyn ReactionRepository>,
+    /// Fichiers téléversés et occupation du stockage
+    pub attachment_repository: Arc<dyn AttachmentRepository>,
+    /// Violations, sanctions, réputation et rôles de modération
+    pub moderation_repository: Arc<dyn ModerationRepository>,
     pub rate_limiter: RateLimiter,
     pub config: ServerConfig,
     pub stats: Arc<RwLock<HubStats>>,
@@ -27,6 +79,68 @@ pub struct ChatHub {
     pub cache: CacheManager,
     pub metrics: ChatMetrics,
     pub presence: PresenceManager,
+    /// Notifications push (mentions, DM reçus hors ligne), hors sourdine
+    pub notifications: NotificationManager,
+    pub action_limiter: Mutex<AdvancedRateLimiter>,
+    pub ip_monitor: Mutex<IpMonitor>,
+    /// Regroupement des insertions (si activé dans la configuration)
+    pub message_batcher: Option<MessageBatcher>,
+    /// Enregistrements d'audit filtrés par la configuration `[audit]`
+    pub audit_sink: AuditSink,
+    /// Fonctionnalités actives (configuration + surcharges administrateur)
+    pub feature_flags: RwLock<FeatureFlags>,
+    /// Débit des salons et mode lent automatique
+    pub slow_mode: Mutex<SlowModeTracker>,
+    /// Activations du mode anti-raid (`[anti_raid]`) et jointures des salons protégés
+    pub anti_raid: Mutex<AntiRaidTracker>,
+    /// Transformations du contenu avant persistance (`[content_pipeline]`)
+    pub content_pipeline: ContentPipeline,
+    /// Fichiers servis aux clients (images d'émojis personnalisés)
+    pub object_store: Arc<dyn ObjectStore>,
+    /// Événements récents des salons, rejoués à la reprise (`[replay]`)
+    pub event_log: Arc<dyn EventLog>,
+    /// Compteurs des quotas quotidiens de messages (`[quotas]`)
+    pub message_quota: Arc<dyn QuotaCounter>,
+    /// Profils allégés résolus par lots (`[profiles]`)
+    pub profile_cache: Arc<dyn ProfileCache>,
+    /// Suivi des liens raccourcis pour la politique des liens (`[link_policy]`)
+    pub link_expander: Arc<dyn LinkExpander>,
+    /// Publication des événements sur NATS (`[integrations.nats]`)
+    pub event_bridge: Option<EventBridge>,
+    /// Clés du chiffrement au repos (`[security.encryption_at_rest]`)
+    pub message_cipher: Option<MessageCipher>,
+}
+
+/// Dépôts installés sur le hub
+pub struct Repositories {
+    pub rooms: Arc<dyn RoomRepository>,
+    pub dms: Arc<dyn DmRepository>,
+    pub reactions: Arc<dyn ReactionRepository>,
+    pub attachments: Arc<dyn AttachmentRepository>,
+    pub moderation: Arc<dyn ModerationRepository>,
+}
+
+impl Repositories {
+    /// Dépôts PostgreSQL partageant le pool
+    pub fn postgres(db: &PgPool) -> Self {
+        Self {
+            rooms: Arc::new(PgRoomRepository::new(db.clone())),
+            dms: Arc::new(PgDmRepository::new(db.clone())),
+            reactions: Arc::new(PgReactionRepository::new(db.clone())),
+            attachments: Arc::new(PgAttachmentRepository::new(db.clone())),
+            moderation: Arc::new(PgModerationRepository::new(db.clone())),
+        }
+    }
+}
+
+/// Connexion active exposée dans les vues d'administration
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionSummary {
+    pub user_id: i32,
+    pub username: String,
+    pub connected_for_secs: u64,
+    pub user_agent: Option<String>,
+    pub client_version: Option<String>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -53,91 +167,402 @@ impl HubStats {
 
 impl ChatHub {
     pub fn new(db: PgPool, config: ServerConfig) -> Arc<Self> {
+        let metrics = ChatMetrics::from_config(&config.metrics);
+        Self::with_metrics(db, config, metrics)
+    }
+
+    /// Variante dont les métriques passent par un `ChatMetrics` fourni
+    pub fn with_metrics(db: PgPool, config: ServerConfig, metrics: ChatMetrics) -> Arc<Self> {
+        let event_bridge = event_bridge_from_config(config.integrations.nats.as_ref());
+        Self::with_event_bridge(db, config, metrics, event_bridge)
+    }
+
+    /// Variante dont les événements sont publiés par le pont fourni
+    pub fn with_event_bridge(db: PgPool, config: ServerConfig, metrics: ChatMetrics, event_bridge: Option<EventBridge>) -> Arc<Self> {
+        let repositories = Repositories::postgres(&db);
+        Self::with_repositories(db, config, metrics, event_bridge, repositories)
+    }
+
+    /// Variante dont les données persistées sont lues et écrites par les dépôts fournis
+    pub fn with_repositories(
+        db: PgPool,
+        config: ServerConfig,
+        metrics: ChatMetrics,
+        event_bridge: Option<EventBridge>,
+        repositories: Repositories,
+    ) -> Arc<Self> {
         tracing::info!("🏗️ Création d'un nouveau ChatHub avec systèmes avancés");
         
+        let message_batcher = config.database.insert_batching.then(|| MessageBatcher::spawn(
+            PgBatchSink::new(db.clone()),
+            BatchConfig {
+                max_batch_size: config.database.insert_batch_size,
+                max_delay: config.database.insert_batch_delay,
+            },
+        ));
+        let action_limiter = AdvancedRateLimiter::new()
+            .with_room_limit(config.limits.room_messages_per_minute)
+            .with_guest_limit(config.guests.messages_per_minute);
+        let ip_monitor = IpMonitor::new()
+            .with_connection_limit(config.limits.connections_per_ip, config.limits.connection_window);
+        
         Arc::new(Self {
-            clients: Arc::new(RwLock::new(HashMap::new())),
-            rooms: Arc::new(RwLock::new(HashMap::new())),
+            clients: ShardedMap::new(config.limits.state_shards),
+            sessions: ShardedMap::new(config.limits.state_shards),
+            rooms: ShardedMap::new(config.limits.state_shards),
+            guest_rooms: ShardedMap::new(config.limits.state_shards),
+            empty_rooms: Mutex::new(EmptyRoomTracker::new()),
+            departures: Mutex::new(HashMap::new()),
             rate_limiter: RateLimiter::new(config.limits.max_messages_per_minute),
+            audit_sink: AuditSink::new(config.audit.clone()),
+            feature_flags: RwLock::new(FeatureFlags::from_config(&config.features)),
+            slow_mode: Mutex::new(SlowModeTracker::new(SlowModeSettings::from_limits(&config.limits))),
+            anti_raid: Mutex::new(AntiRaidTracker::new()),
+            content_pipeline: ContentPipeline::from_config(&config.content_pipeline),
+            object_store: Arc::new(LocalObjectStore::from_config(&config.object_store)),
+            event_log: event_log_from_config(&config),
+            message_quota: quota_counter_from_config(&config),
+            profile_cache: profile_cache_from_config(&config),
+            link_expander: link_expander_from_config(&config),
+            message_cipher: MessageCipher::from_config(&config.security.encryption_at_rest).unwrap_or_else(|e| {
+                tracing::error!(error = %e, "❌ Clés de chiffrement invalides, chiffrement au repos indisponible");
+                None
+            }),
             config,
             db,
+            room_repository: repositories.rooms,
+            dm_repository: repositories.dms,
+            reaction_repository: repositories.reactions,
+            attachment_repository: repositories.attachments,
+            moderation_repository: repositories.moderation,
             stats: Arc::new(RwLock::new(HubStats::new())),
             
             // Initialisation des nouveaux systèmes
             cache: CacheManager::new(),
-            metrics: ChatMetrics::new(),
+            metrics,
             presence: PresenceManager::new(),
+            notifications: NotificationManager::new(),
+            action_limiter: Mutex::new(action_limiter),
+            ip_monitor: Mutex::new(ip_monitor),
+            message_batcher,
+            event_bridge,
         })
     }
 
-    pub async fn register(&self, user_id: i32, client: Client) {
-        tracing::debug!(user_id = %user_id, username = %client.username, "🔧 Début register");
+    /// Arrêt propre : ferme les connexions (`server_restart`) puis écrit les
+    /// messages encore en attente d'insertion
+    pub async fn shutdown(&self) {
+        tracing::info!("🛑 Arrêt du ChatHub");
+        
+        let user_ids = self.clients.keys().await;
+        for user_id in user_ids {
+            self.disconnect_user(user_id, CloseReason::ServerRestart, None).await;
+        }
+        
+        if let Some(batcher) = &self.message_batcher {
+            batcher.shutdown().await;
+        }
+    }
+
+    /// Enregistre une connexion ; refusée si le nom d'utilisateur est invalide,
+    /// ou si l'utilisateur est banni après des violations répétées
+    ///
+    /// Les noms réservés sont refusés à la création ou au changement d'un nom
+    /// (profil, invité), pas ici : un compte existant se connecte toujours.
+    pub async fn register(&self, user_id: i32, mut client: Client) -> Result<()> {
+        tracing::debug!(user_id = %user_id, username = %client.username.escape_debug(), "🔧 Début register");
         
-        let mut clients = self.clients.write().await;
-        let clients_before = clients.len();
+        let username = normalize_username(&client.username)?;
+        check_not_banned(self, user_id).await?;
+        client.username = username.clone();
+
+        // Reconnexion dans le délai de grâce : adhésions intactes, sans annonce
+        if self.departures.lock().await.remove(&user_id).is_some() {
+            tracing::info!(user_id = %user_id, "🔁 Reconnexion dans le délai de grâce");
+        }
+
+        // Poignée de main : fonctionnalités disponibles sur ce serveur
+        client.send_text(&self.feature_flags.read().await.to_frame());
+        self.add_session(user_id, client.clone()).await;
         
-        clients.insert(user_id, client);
+        self.clients.insert(user_id, client).await;
+        let clients_after = self.clients.len().await;
 
         // Mise à jour des statistiques
         let mut stats = self.stats.write().await;
         stats.total_connections += 1;
-        stats.active_connections = clients.len() as u64;
+        stats.active_connections = clients_after as u64;
         
         tracing::info!(
             user_id = %user_id, 
-            clients_before = %clients_before, 
-            clients_after = %clients.len(), 
+            clients_after = %clients_after, 
             total_connections = %stats.total_connections,
             "👤 Enregistrement du client"
         );
+        
+        drop(stats);
+        
+        // Salons rejoints lors des connexions précédentes (un invité n'en a pas)
+        if self.config.features.restore_rooms_on_connect && !is_guest(user_id) {
+            if let Err(e) = restore_room_memberships(self, user_id, &username).await {
+                tracing::warn!(user_id = %user_id, error = %e, "⚠️ Échec de la restauration des salons");
+            }
+        }
+        
+        // Salons par défaut (sans effet pour un utilisateur déjà accueilli)
+        if is_guest(user_id) {
+            tracing::debug!(user_id = %user_id, "👤 Invité : aucun salon par défaut");
+        } else if let Err(e) = auto_join_default_rooms(self, user_id as i64, &username).await {
+            tracing::warn!(user_id = %user_id, error = %e, "⚠️ Échec de l'adhésion aux salons par défaut");
+        }
+        
+        // Présence annoncée aux seuls abonnés, une fois les salons restaurés
+        publish_online(self, user_id, &username).await;
+
+        Ok(())
+    }
+
+    /// Désenregistre une connexion perdue (fermeture côté client ou réseau)
+    ///
+    /// Avec `limits.reconnect_grace`, l'utilisateur reste en ligne et membre de
+    /// ses salons jusqu'à la fin du délai (voir `maintenance::expire_departures`). Seule
+    /// la session `session` est retirée : tant qu'une autre reste ouverte,
+    /// l'utilisateur demeure connecté et aucune absence n'est annoncée.
+    pub async fn unregister(&self, session: &Client) {
+        let user_id = session.user_id;
+        let remaining = {
+            let mut sessions = self.sessions.write(&user_id).await;
+            let remaining = sessions.get_mut(&user_id).and_then(|user_sessions| {
+                user_sessions.retain(|other| !other.sender.same_channel(&session.sender) && !other.sender.is_closed());
+                user_sessions.last().cloned()
+            });
+            if remaining.is_none() {
+                sessions.remove(&user_id);
+            }
+            remaining
+        };
+
+        if let Some(current) = remaining {
+            // La connexion de référence ne doit pas désigner la session partie
+            let departed = self.clients.with(&user_id, |client| {
+                client.map_or(true, |client| client.sender.same_channel(&session.sender))
+            }).await;
+            if departed {
+                self.clients.insert(user_id, current).await;
+            }
+            tracing::debug!(user_id = %user_id, "🔌 Session fermée, d'autres restent ouvertes");
+            return;
+        }
+
+        self.release(user_id, true).await;
     }
 
-    pub async fn unregister(&self, user_id: i32) {
+    /// Retire les connexions de l'utilisateur ; `may_return` : reconnexion attendue
+    async fn release(&self, user_id: i32, may_return: bool) {
         tracing::debug!(user_id = %user_id, "🔧 Début unregister");
         
-        let mut clients = self.clients.write().await;
-        let clients_before = clients.len();
+        self.sessions.remove(&user_id).await;
         
-        if let Some(removed_client) = clients.remove(&user_id) {
+        let removed = self.clients.remove(&user_id).await;
+        let clients_after = self.clients.len().await;
+        
+        if let Some(removed_client) = &removed {
             // Mise à jour des statistiques
             let mut stats = self.stats.write().await;
-            stats.active_connections = clients.len() as u64;
+            stats.active_connections = clients_after as u64;
             
             tracing::info!(
                 user_id = %user_id, 
                 username = %removed_client.username, 
-                clients_before = %clients_before, 
-                clients_after = %clients.len(),
+                clients_after = %clients_after,
                 active_connections = %stats.active_connections,
                 connection_duration = ?removed_client.connection_duration(),
                 "🚪 Déconnexion du client"
             );
         } else {
-            tracing::warn!(user_id = %user_id, clients_count = %clients.len(), "⚠️ Tentative de déconnexion d'un client non enregistré");
+            tracing::warn!(user_id = %user_id, clients_count = %clients_after, "⚠️ Tentative de déconnexion d'un client non enregistré");
+        }
+        
+        // Adhésions conservées le temps d'une reconnexion
+        let grace = self.config.limits.reconnect_grace;
+        if let Some(client) = removed.filter(|_| may_return && !grace.is_zero()) {
+            self.departures.lock().await.insert(user_id, Departure::new(client.username, Instant::now() + grace));
+            tracing::info!(user_id = %user_id, grace_seconds = %grace.as_secs(), "⏳ Adhésions aux salons conservées en attente de reconnexion");
+            return;
         }
         
-        // Nettoyer les salons
-        let mut rooms = self.rooms.write().await;
-        let mut rooms_cleaned = 0;
+        self.departures.lock().await.remove(&user_id);
+        // Avant le nettoyage des salons : leurs abonnés doivent être prévenus
+        publish_offline(self, user_id).await;
+        self.leave_all_rooms(user_id).await;
+        if is_guest(user_id) {
+            forget_guest(self, user_id).await;
+        }
+    }
+
+    /// Retire l'utilisateur de tous les salons en mémoire
+    ///
+    /// Retourne les salons quittés avec leurs membres restants.
+    pub async fn leave_all_rooms(&self, user_id: i32) -> Vec<(RoomId, Vec<i32>)> {
+        let mut left = Vec::new();
         let mut total_removals = 0;
         
-        for (room_name, user_list) in rooms.iter_mut() {
+        self.rooms.for_each_mut(|room_name, user_list| {
             let before_len = user_list.len();
             user_list.retain(|&id| id != user_id);
             let after_len = user_list.len();
             
             if before_len != after_len {
                 total_removals += before_len - after_len;
-                rooms_cleaned += 1;
+                left.push((room_name.clone(), user_list.clone()));
                 tracing::debug!(user_id = %user_id, room = %room_name, members_before = %before_len, members_after = %after_len, "🧹 Utilisateur retiré du salon");
             }
-        }
+        }).await;
         
-        if rooms_cleaned > 0 {
-            tracing::info!(user_id = %user_id, rooms_cleaned = %rooms_cleaned, total_removals = %total_removals, "🧹 Nettoyage des salons terminé");
+        if !left.is_empty() {
+            tracing::info!(user_id = %user_id, rooms_cleaned = %left.len(), total_removals = %total_removals, "🧹 Nettoyage des salons terminé");
         } else {
             tracing::debug!(user_id = %user_id, "🧹 Aucun salon à nettoyer");
         }
+        left
+    }
+
+    /// Ajoute une session à l'utilisateur (les plus anciennes au-delà de la limite sont écartées)
+    async fn add_session(&self, user_id: i32, client: Client) {
+        let max_sessions = self.config.limits.max_connections_per_user.max(1) as usize;
+        self.sessions.update(user_id, |user_sessions| {
+            user_sessions.retain(|session| !session.sender.is_closed());
+            user_sessions.push(client);
+            if user_sessions.len() > max_sessions {
+                let excess = user_sessions.len() - max_sessions;
+                for evicted in user_sessions.drain(..excess) {
+                    evicted.close(CloseReason::SessionReplaced, None);
+                }
+            }
+        }).await;
+    }
+
+    /// Ferme toutes les connexions d'un utilisateur avec un code applicatif
+    /// puis le désenregistre ; retourne le nombre de connexions fermées
+    pub async fn disconnect_user(&self, user_id: i32, reason: CloseReason, message: Option<&str>) -> usize {
+        let sessions = self.sessions.get(&user_id).await.unwrap_or_default();
+        let mut closed = sessions.iter()
+            .filter(|session| session.close(reason, message))
+            .count();
+        
+        // Connexion enregistrée sans session (ne devrait pas arriver)
+        if sessions.is_empty() {
+            if let Some(client) = self.clients.get(&user_id).await {
+                closed += client.close(reason, message) as usize;
+            }
+        }
+        
+        // Exclusion (expulsion, bannissement) : retrait immédiat des salons
+        self.release(user_id, reason.retry() != RetryPolicy::Never).await;
+        closed
+    }
+
+    /// Envoie un événement à toutes les sessions ouvertes d'un utilisateur
+    ///
+    /// Les sessions fermées sont retirées au passage ; une session lente dont la
+    /// trame est abandonnée reste enregistrée. Retourne le nombre de sessions atteintes.
+    pub async fn send_to_user_sessions(&self, user_id: i32, text: &str) -> usize {
+        let mut sessions = self.sessions.write(&user_id).await;
+        let Some(user_sessions) = sessions.get_mut(&user_id) else {
+            return 0;
+        };
+        
+        let mut delivered = 0;
+        user_sessions.retain(|session| match session.deliver(text) {
+            SendOutcome::Sent => {
+                delivered += 1;
+                true
+            }
+            SendOutcome::Dropped => true,
+            SendOutcome::Closed => false,
+        });
+        if user_sessions.is_empty() {
+            sessions.remove(&user_id);
+        }
+        delivered
+    }
+
+    /// Version du schéma des messages négociée par un utilisateur (courante s'il n'est pas connecté)
+    pub async fn schema_version_of(&self, user_id: i32) -> u16 {
+        self.clients.with(&user_id, |client| client.map_or(CURRENT_SCHEMA_VERSION, Client::schema_version)).await
+    }
+
+    /// Négocie la version du schéma des messages pour toutes les sessions d'un utilisateur
+    ///
+    /// Retourne la version retenue (bornée aux versions servies).
+    pub async fn negotiate_schema(&self, user_id: i32, requested: u16) -> u16 {
+        let version = match self.clients.with(&user_id, |client| client.map(|client| client.set_schema_version(requested))).await {
+            Some(version) => version,
+            None => return CURRENT_SCHEMA_VERSION,
+        };
+        
+        self.sessions.with(&user_id, |sessions| {
+            for session in sessions.into_iter().flatten() {
+                session.set_schema_version(version);
+            }
+        }).await;
+        
+        tracing::info!(user_id = %user_id, requested = %requested, version = %version, "🧬 Schéma des messages négocié");
+        version
+    }
+
+    /// Accusé des envois par défaut d'un utilisateur (`Confirm` s'il n'est pas connecté)
+    pub async fn ack_mode_of(&self, user_id: i32) -> AckMode {
+        self.clients.with(&user_id, |client| client.map_or(AckMode::Confirm, Client::ack_mode)).await
+    }
+
+    /// Fixe l'accusé des envois par défaut pour toutes les sessions d'un utilisateur
+    pub async fn set_ack_mode(&self, user_id: i32, mode: AckMode) -> Result<()> {
+        match self.clients.get(&user_id).await {
+            Some(client) => client.set_ack_mode(mode),
+            None => return Err(ChatError::not_found("client", &user_id.to_string())),
+        }
+        
+        self.sessions.with(&user_id, |sessions| {
+            for session in sessions.into_iter().flatten() {
+                session.set_ack_mode(mode);
+            }
+        }).await;
+        
+        tracing::info!(user_id = %user_id, mode = %mode.as_str(), "📬 Accusé des envois configuré");
+        Ok(())
+    }
+
+    /// Rend une réponse de message à la version de schéma du demandeur
+    pub async fn render_frame_for(&self, user_id: i64, frame: &serde_json::Value) -> String {
+        let version = self.schema_version_of(user_id as i32).await;
+        downgrade(frame, version).to_string()
+    }
+
+    /// Liste les connexions actives avec leurs métadonnées (vue d'administration)
+    pub async fn list_connections(&self) -> Vec<ConnectionSummary> {
+        self.clients.filter_map(|_, client| Some(ConnectionSummary {
+                user_id: client.user_id,
+                username: client.username.clone(),
+                connected_for_secs: client.connection_duration().as_secs(),
+                user_agent: client.metadata.user_agent.clone(),
+                client_version: client.metadata.client_version.clone(),
+            })).await
+    }
+
+    /// Déconnexion imposée par un administrateur (`force_disconnect`) ; retourne
+    /// le nombre de connexions fermées
+    pub async fn force_disconnect(&self, admin_id: i64, user_id: i32, message: Option<&str>) -> Result<usize> {
+        check_global_admin(self, admin_id, "force_disconnect").await?;
+
+        self.audit_sink.record(&self.db, "connection_force_closed", Some(admin_id), serde_json::json!({
+            "user_id": user_id,
+            "message": message
+        })).await?;
+
+        let closed = self.disconnect_user(user_id, CloseReason::ForceDisconnect, message).await;
+        tracing::warn!(admin_id = %admin_id, user_id = %user_id, closed = %closed, "🔌 Déconnexion imposée par un administrateur");
+        Ok(closed)
     }
 
     /// Vérifie le rate limiting pour un utilisateur
@@ -145,6 +570,60 @@ impl ChatHub {
         self.rate_limiter.check_and_update(user_id).await
     }
 
+    /// Vérifie la limite spécifique à une action (réactions, création de salon, ...)
+    pub async fn check_action_limit(&self, user_id: i32, action: SecurityAction) -> Result<()> {
+        self.action_limiter.lock().await.check_limit(user_id, &action)
+    }
+
+    /// Réglages et services passés aux dépôts pour écrire un message de salon
+    pub fn write_context(&self) -> WriteContext<'_> {
+        WriteContext {
+            limits: &self.config.limits,
+            moderation: &self.config.moderation,
+            audit: &self.audit_sink,
+            cipher: self.message_cipher.as_ref(),
+            batcher: self.message_batcher.as_ref(),
+            action_limiter: &self.action_limiter,
+        }
+    }
+
+    /// Vérifie le débit d'un salon, tous auteurs confondus (`limits.room_messages_per_minute`)
+    pub async fn check_room_limit(&self, room_id: i64) -> Result<()> {
+        self.action_limiter.lock().await.check_room_limit(room_id)
+    }
+
+    /// Admet ou refuse une nouvelle connexion depuis `ip` (bannissement,
+    /// `limits.connections_per_ip`) ; à appeler au handshake, avant l'authentification
+    pub async fn admit_connection(&self, ip: &str) -> Result<()> {
+        self.ip_monitor.lock().await.check_connection(ip)
+    }
+
+    /// Publie un événement de salon sur le pont NATS, sans attendre
+    pub async fn bridge_room_event(&self, room: impl std::fmt::Display, frame: &serde_json::Value) {
+        if let Some(bridge) = &self.event_bridge {
+            self.bridge_event(bridge, bridge.room_subject(room), frame).await;
+        }
+    }
+
+    /// Publie un événement de conversation privée sur le pont NATS, sans attendre
+    pub async fn bridge_dm_event(&self, conversation_id: i64, frame: &serde_json::Value) {
+        if let Some(bridge) = &self.event_bridge {
+            self.bridge_event(bridge, bridge.dm_subject(conversation_id), frame).await;
+        }
+    }
+
+    async fn bridge_event(&self, bridge: &EventBridge, subject: String, frame: &serde_json::Value) {
+        if let Err(drop) = bridge.try_publish(subject, frame) {
+            tracing::warn!(reason = %drop.as_str(), "📉 Événement non publié sur NATS");
+            self.metrics.bridge_event_dropped(drop.as_str()).await;
+        }
+    }
+
+    /// Vérifie qu'une IP n'est pas bannie et enregistre son activité
+    pub async fn check_ip(&self, ip: &str, action: SecurityAction) -> Result<()> {
+        self.ip_monitor.lock().await.check_ip(ip, &action)
+    }
+
     /// Incrémente le compteur de messages
     pub async fn increment_message_count(&self) {
         let mut stats = self.stats.write().await;
@@ -156,33 +635,37 @@ impl ChatHub {
         self.stats.read().await.clone()
     }
 
-    /// Nettoie les connexions mortes (heartbeat timeout)
+    /// Nettoie les connexions mortes (heartbeat timeout), inactives
+    /// (`server.idle_timeout`) et celles dont le jeton a expiré
     pub async fn cleanup_dead_connections(&self) {
         let timeout = Duration::from_secs(self.config.server.heartbeat_interval.as_secs() as u64 * 3); // 3x heartbeat interval
-        let mut dead_clients = Vec::new();
+        let idle_timeout = self.config.server.idle_timeout;
+        let now = chrono::Utc::now().timestamp();
         
-        {
-            let clients = self.clients.read().await;
-            for (user_id, client) in clients.iter() {
-                if !client.is_alive(timeout) {
-                    dead_clients.push(*user_id);
-                }
+        let dead_clients = self.clients.filter_map(|user_id, client| {
+            if client.is_token_expired(now) {
+                Some((*user_id, CloseReason::AuthExpired))
+            } else if !client.is_alive(timeout) {
+                Some((*user_id, CloseReason::HeartbeatTimeout))
+            } else if !idle_timeout.is_zero() && client.is_idle(idle_timeout) {
+                Some((*user_id, CloseReason::IdleTimeout))
+            } else {
+                None
             }
-        }
+        }).await;
 
-        for user_id in dead_clients {
-            tracing::warn!(user_id = %user_id, timeout_seconds = %timeout.as_secs(), "💀 Connexion morte détectée, nettoyage");
-            self.unregister(user_id).await;
+        for (user_id, reason) in dead_clients {
+            tracing::warn!(user_id = %user_id, timeout_seconds = %timeout.as_secs(), reason = %reason, "💀 Connexion morte détectée, nettoyage");
+            self.disconnect_user(user_id, reason, None).await;
         }
     }
 
     /// Envoie un ping à tous les clients connectés
     pub async fn ping_all_clients(&self) {
-        let clients = self.clients.read().await;
         let mut successful_pings = 0;
         let mut failed_pings = 0;
 
-        for client in clients.values() {
+        for client in self.clients.values().await {
             if client.send_ping() {
                 successful_pings += 1;
             } else {
@@ -197,3 +680,25 @@ impl ChatHub {
         }
     }
 }
+
+// ================================================================
+// DROITS GLOBAUX
+// ================================================================
+
+/// L'utilisateur est-il administrateur global (rôle `admin` ou `owner`) ?
+pub(crate) async fn is_global_admin<'e>(executor: impl PgExecutor<'e>, user_id: i64) -> Result<bool> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1 AND role::text IN ('admin', 'owner'))")
+        .bind(user_id)
+        .fetch_one(executor)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("check_global_admin", e))
+}
+
+/// Réserve `action` aux administrateurs globaux
+pub(crate) async fn check_global_admin(hub: &ChatHub, user_id: i64, action: &str) -> Result<()> {
+    if hub.moderation_repository.is_global_admin(user_id).await? {
+        Ok(())
+    } else {
+        Err(ChatError::unauthorized(action))
+    }
+}
@@ -0,0 +1,491 @@
+//! Émojis personnalisés
+//!
+//! - Téléversés par les modérateurs : image PNG, GIF ou WebP (taille bornée par
+//!   `limits.max_custom_emoji_size`) et nom court unique (`:nom:`)
+//! - Portée serveur (administrateurs globaux) ou salon (modérateurs du salon) ;
+//!   un émoji de salon ne peut pas reprendre le nom d'un émoji serveur
+//! - Utilisables dans les messages (`:nom:` → métadonnée `customEmojis`) et en
+//!   réaction
+//! - Catalogue versionné : un client qui connaît déjà la version courante
+//!   n'a pas à le recharger
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sqlx::{query, PgExecutor, Row};
+use serde::Serialize;
+use serde_json::{json, Value};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::hub::common::{is_global_admin, ChatHub};
+use crate::hub::channels::is_moderator_role;
+use crate::hub::feature_flags::FeatureFlag;
+use crate::error::{ChatError, Result};
+
+/// Longueur minimale d'un nom court
+pub const MIN_SHORTCODE_LENGTH: usize = 2;
+
+/// Longueur maximale d'un nom court (`:nom:` tient dans les 20 caractères d'une réaction)
+pub const MAX_SHORTCODE_LENGTH: usize = 18;
+
+static SHORTCODE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r":([a-z0-9_-]{2,18}):").unwrap());
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Formats d'image acceptés, reconnus à leur signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmojiImageFormat {
+    Png,
+    Gif,
+    Webp,
+}
+
+impl EmojiImageFormat {
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(EmojiImageFormat::Png)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(EmojiImageFormat::Gif)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(EmojiImageFormat::Webp)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            EmojiImageFormat::Png => "image/png",
+            EmojiImageFormat::Gif => "image/gif",
+            EmojiImageFormat::Webp => "image/webp",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EmojiImageFormat::Png => "png",
+            EmojiImageFormat::Gif => "gif",
+            EmojiImageFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Portée d'un émoji
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "scope", content = "roomId", rename_all = "snake_case")]
+pub enum EmojiScope {
+    /// Disponible partout
+    Server,
+    /// Disponible dans un seul salon
+    Room(i64),
+}
+
+impl EmojiScope {
+    pub fn room_id(&self) -> Option<i64> {
+        match self {
+            EmojiScope::Server => None,
+            EmojiScope::Room(room_id) => Some(*room_id),
+        }
+    }
+
+    /// Clé de l'image dans le stockage objet
+    fn object_key(&self, shortcode: &str, format: EmojiImageFormat) -> String {
+        let prefix = match self {
+            EmojiScope::Server => "server".to_string(),
+            EmojiScope::Room(room_id) => format!("room-{}", room_id),
+        };
+        format!("emojis/{}/{}-{}.{}", prefix, shortcode, Uuid::new_v4(), format.extension())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CustomEmoji {
+    pub id: i64,
+    pub shortcode: String,
+    /// `None` = émoji serveur
+    pub room_id: Option<i64>,
+    pub image_url: String,
+    pub content_type: String,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Catalogue transmis aux clients
+#[derive(Debug, Clone, Serialize)]
+pub struct EmojiCatalog {
+    /// Change dès qu'un émoji est ajouté ou retiré
+    pub version: String,
+    pub emojis: Vec<CustomEmoji>,
+}
+
+impl EmojiCatalog {
+    pub fn new(emojis: Vec<CustomEmoji>) -> Self {
+        Self { version: catalog_version(&emojis), emojis }
+    }
+}
+
+// ================================================================
+// RÈGLES
+// ================================================================
+
+/// Nom court : lettres minuscules, chiffres, `_` et `-` (2 à 18 caractères)
+pub fn is_valid_shortcode(name: &str) -> bool {
+    (MIN_SHORTCODE_LENGTH..=MAX_SHORTCODE_LENGTH).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'))
+}
+
+/// Nom court d'un émoji personnalisé écrit `:nom:`
+pub fn shortcode_of(emoji: &str) -> Option<&str> {
+    emoji.strip_prefix(':')
+        .and_then(|rest| rest.strip_suffix(':'))
+        .filter(|name| is_valid_shortcode(name))
+}
+
+/// Noms courts cités dans un contenu, sans doublons, dans l'ordre d'apparition
+pub fn extract_shortcodes(content: &str) -> Vec<String> {
+    let mut shortcodes: Vec<String> = Vec::new();
+    for capture in SHORTCODE_PATTERN.captures_iter(content) {
+        let name = &capture[1];
+        if !shortcodes.iter().any(|known| known == name) {
+            shortcodes.push(name.to_string());
+        }
+    }
+    shortcodes
+}
+
+/// Version du catalogue : empreinte des émojis présents
+pub fn catalog_version(emojis: &[CustomEmoji]) -> String {
+    let mut entries: Vec<(i64, &str)> = emojis.iter().map(|emoji| (emoji.id, emoji.shortcode.as_str())).collect();
+    entries.sort_unstable();
+
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Vérifie un téléversement et retourne le format de l'image
+pub fn validate_emoji_upload(shortcode: &str, image: &[u8], max_size: u64) -> Result<EmojiImageFormat> {
+    if !is_valid_shortcode(shortcode) {
+        return Err(ChatError::InvalidFormat {
+            field: "shortcode".to_string(),
+            reason: "Nom court invalide (2 à 18 caractères : a-z, 0-9, _ et -)".to_string(),
+        });
+    }
+    if image.len() as u64 > max_size {
+        return Err(ChatError::FileTooLarge { size: image.len() as u64, max_size });
+    }
+    EmojiImageFormat::detect(image).ok_or_else(|| ChatError::UnsupportedFileType {
+        mime_type: "inconnu (PNG, GIF ou WebP attendu)".to_string(),
+    })
+}
+
+// ================================================================
+// GESTION
+// ================================================================
+
+/// Seuls les administrateurs globaux gèrent les émojis serveur, les modérateurs ceux de leur salon
+async fn check_emoji_manager(hub: &ChatHub, user_id: i64, scope: EmojiScope, action: &str) -> Result<()> {
+    let allowed = match scope {
+        EmojiScope::Server => is_global_admin(&hub.db, user_id).await?,
+        EmojiScope::Room(room_id) => {
+            let role: Option<String> = query("
+                SELECT role FROM conversation_members
+                WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+            ")
+            .bind(room_id)
+            .bind(user_id)
+            .fetch_optional(&hub.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+            .map(|row| row.get("role"));
+
+            role.as_deref().is_some_and(is_moderator_role)
+        }
+    };
+
+    if !allowed {
+        return Err(ChatError::unauthorized(action));
+    }
+    Ok(())
+}
+
+fn row_to_emoji(row: &sqlx::postgres::PgRow) -> CustomEmoji {
+    CustomEmoji {
+        id: row.get("id"),
+        shortcode: row.get("shortcode"),
+        room_id: row.get("room_id"),
+        image_url: row.get("image_url"),
+        content_type: row.get("content_type"),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+    }
+}
+
+/// Téléverse un émoji personnalisé
+pub async fn upload_custom_emoji(
+    hub: &ChatHub,
+    uploader_id: i64,
+    scope: EmojiScope,
+    shortcode: &str,
+    image: &[u8]
+) -> Result<CustomEmoji> {
+    tracing::info!(uploader_id = %uploader_id, scope = ?scope, shortcode = %shortcode, size = %image.len(), "🖼️ Téléversement d'un émoji personnalisé");
+
+    hub.require_feature(FeatureFlag::FileUploads).await?;
+    let format = validate_emoji_upload(shortcode, image, hub.config.limits.max_custom_emoji_size)?;
+    check_emoji_manager(hub, uploader_id, scope, "upload_custom_emoji").await?;
+
+    let existing = query("
+        SELECT
+            EXISTS(
+                SELECT 1 FROM custom_emojis
+                WHERE shortcode = $1 AND ($2::BIGINT IS NULL OR room_id IS NULL OR room_id = $2)
+            ) as taken,
+            (SELECT COUNT(*) FROM custom_emojis WHERE room_id IS NOT DISTINCT FROM $2) as scope_count
+    ")
+    .bind(shortcode)
+    .bind(scope.room_id())
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_emoji_shortcode", e))?;
+
+    // Un émoji serveur masquerait les émojis de salon du même nom, et inversement
+    if existing.get::<bool, _>("taken") {
+        return Err(ChatError::Conflict { reason: format!("L'émoji :{}: existe déjà", shortcode) });
+    }
+    let scope_count: i64 = existing.get("scope_count");
+    let max_per_scope = hub.config.limits.max_custom_emojis_per_scope as u64;
+    if scope_count as u64 >= max_per_scope {
+        return Err(ChatError::QuotaExceeded {
+            quota_type: "custom_emojis".to_string(),
+            used: scope_count as u64,
+            limit: max_per_scope,
+        });
+    }
+
+    let object_key = scope.object_key(shortcode, format);
+    let image_url = hub.object_store.put(&object_key, image, format.content_type()).await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let inserted = query("
+        INSERT INTO custom_emojis (shortcode, room_id, object_key, image_url, content_type, size_bytes, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, shortcode, room_id, image_url, content_type, created_by, created_at
+    ")
+    .bind(shortcode)
+    .bind(scope.room_id())
+    .bind(&object_key)
+    .bind(&image_url)
+    .bind(format.content_type())
+    .bind(image.len() as i32)
+    .bind(uploader_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let emoji = match inserted {
+        Ok(row) => row_to_emoji(&row),
+        Err(e) => {
+            // Course sur le même nom (index unique) : l'image orpheline est retirée
+            if let Err(cleanup) = hub.object_store.delete(&object_key).await {
+                tracing::warn!(key = %object_key, error = %cleanup, "⚠️ Image d'émoji orpheline");
+            }
+            return Err(ChatError::from_sqlx_error("insert_custom_emoji", e));
+        }
+    };
+
+    hub.audit_sink.record(&mut *tx, "custom_emoji_added", Some(uploader_id), json!({
+        "emoji_id": emoji.id,
+        "shortcode": shortcode,
+        "room_id": scope.room_id()
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    tracing::info!(emoji_id = %emoji.id, shortcode = %shortcode, "✅ Émoji personnalisé ajouté");
+    Ok(emoji)
+}
+
+/// Retire un émoji personnalisé (les réactions déjà posées sont conservées)
+pub async fn delete_custom_emoji(hub: &ChatHub, moderator_id: i64, emoji_id: i64) -> Result<()> {
+    tracing::info!(moderator_id = %moderator_id, emoji_id = %emoji_id, "🗑️ Suppression d'un émoji personnalisé");
+
+    let row = query("SELECT shortcode, room_id, object_key FROM custom_emojis WHERE id = $1")
+        .bind(emoji_id)
+        .fetch_optional(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_custom_emoji", e))?
+        .ok_or_else(|| ChatError::not_found("émoji personnalisé", &emoji_id.to_string()))?;
+
+    let room_id: Option<i64> = row.get("room_id");
+    let scope = room_id.map_or(EmojiScope::Server, EmojiScope::Room);
+    check_emoji_manager(hub, moderator_id, scope, "delete_custom_emoji").await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    query("DELETE FROM custom_emojis WHERE id = $1")
+        .bind(emoji_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("delete_custom_emoji", e))?;
+
+    hub.audit_sink.record(&mut *tx, "custom_emoji_removed", Some(moderator_id), json!({
+        "emoji_id": emoji_id,
+        "shortcode": row.get::<String, _>("shortcode"),
+        "room_id": room_id
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    let object_key: String = row.get("object_key");
+    if let Err(e) = hub.object_store.delete(&object_key).await {
+        tracing::warn!(key = %object_key, error = %e, "⚠️ Échec de suppression de l'image d'émoji");
+    }
+
+    Ok(())
+}
+
+// ================================================================
+// CATALOGUE ET USAGE
+// ================================================================
+
+/// Émojis serveur, plus ceux du salon si `room_id` est fourni
+pub async fn emoji_catalog(hub: &ChatHub, room_id: Option<i64>) -> Result<EmojiCatalog> {
+    let rows = query("
+        SELECT id, shortcode, room_id, image_url, content_type, created_by, created_at
+        FROM custom_emojis
+        WHERE room_id IS NULL OR room_id = $1
+        ORDER BY shortcode
+    ")
+    .bind(room_id)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("emoji_catalog", e))?;
+
+    Ok(EmojiCatalog::new(rows.iter().map(row_to_emoji).collect()))
+}
+
+/// URL des émojis cités dans un contenu, disponibles dans la portée donnée
+pub async fn resolve_custom_emojis<'e, E: PgExecutor<'e>>(
+    executor: E,
+    room_id: Option<i64>,
+    shortcodes: &[String]
+) -> Result<HashMap<String, String>> {
+    if shortcodes.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let rows = query("
+        SELECT shortcode, image_url FROM custom_emojis
+        WHERE shortcode = ANY($1) AND (room_id IS NULL OR room_id = $2)
+    ")
+    .bind(shortcodes)
+    .bind(room_id)
+    .fetch_all(executor)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("resolve_custom_emojis", e))?;
+
+    Ok(rows.into_iter().map(|row| (row.get("shortcode"), row.get("image_url"))).collect())
+}
+
+/// Joint aux métadonnées du message les émojis personnalisés qu'il cite (`customEmojis`)
+pub async fn annotate_custom_emojis<'e, E: PgExecutor<'e>>(
+    executor: E,
+    room_id: Option<i64>,
+    content: &str,
+    metadata: &mut Value
+) -> Result<()> {
+    let resolved = resolve_custom_emojis(executor, room_id, &extract_shortcodes(content)).await?;
+    if !resolved.is_empty() {
+        metadata["customEmojis"] = json!(resolved);
+    }
+    Ok(())
+}
+
+/// L'émoji `:nom:` est-il disponible pour réagir à ce message ?
+pub(crate) async fn custom_emoji_available(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    message_id: i64,
+    shortcode: &str
+) -> Result<bool> {
+    Ok(query("
+        SELECT EXISTS(
+            SELECT 1 FROM custom_emojis e, messages m
+            WHERE m.id = $1 AND e.shortcode = $2
+              AND (e.room_id IS NULL OR e.room_id = m.conversation_id)
+        )
+    ")
+    .bind(message_id)
+    .bind(shortcode)
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_custom_emoji", e))?
+    .get(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn emoji(id: i64, shortcode: &str) -> CustomEmoji {
+        CustomEmoji {
+            id,
+            shortcode: shortcode.to_string(),
+            room_id: None,
+            image_url: format!("/objects/emojis/server/{}.png", shortcode),
+            content_type: "image/png".to_string(),
+            created_by: Some(1),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_image_format_detection() {
+        assert_eq!(EmojiImageFormat::detect(PNG), Some(EmojiImageFormat::Png));
+        assert_eq!(EmojiImageFormat::detect(b"GIF89a...."), Some(EmojiImageFormat::Gif));
+        assert_eq!(EmojiImageFormat::detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(EmojiImageFormat::Webp));
+        assert_eq!(EmojiImageFormat::detect(b"<svg xmlns=..."), None);
+        assert_eq!(EmojiImageFormat::detect(b"RIFF"), None);
+    }
+
+    #[test]
+    fn test_upload_validation() {
+        assert_eq!(validate_emoji_upload("parrot", PNG, 1024).unwrap(), EmojiImageFormat::Png);
+
+        assert!(matches!(validate_emoji_upload("Parrot", PNG, 1024), Err(ChatError::InvalidFormat { .. })));
+        assert!(matches!(validate_emoji_upload("p", PNG, 1024), Err(ChatError::InvalidFormat { .. })));
+        assert!(matches!(validate_emoji_upload("parrot", PNG, 4), Err(ChatError::FileTooLarge { .. })));
+        assert!(matches!(validate_emoji_upload("parrot", b"<svg/>", 1024), Err(ChatError::UnsupportedFileType { .. })));
+    }
+
+    #[test]
+    fn test_shortcodes_in_content_and_reactions() {
+        assert_eq!(
+            extract_shortcodes("gg :party-parrot: :veza: :party-parrot: :Nope: :x: fin :"),
+            vec!["party-parrot".to_string(), "veza".to_string()]
+        );
+        assert_eq!(shortcode_of(":veza:"), Some("veza"));
+        assert_eq!(shortcode_of("👍"), None);
+        assert_eq!(shortcode_of(":a:"), None);
+    }
+
+    #[test]
+    fn test_catalog_version_tracks_content() {
+        let catalog = EmojiCatalog::new(vec![emoji(1, "parrot"), emoji(2, "veza")]);
+
+        // Indépendante de l'ordre, modifiée par un ajout ou un retrait
+        assert_eq!(catalog.version, catalog_version(&[emoji(2, "veza"), emoji(1, "parrot")]));
+        assert_ne!(catalog.version, catalog_version(&[emoji(1, "parrot")]));
+        assert_ne!(catalog.version, catalog_version(&[emoji(1, "parrot"), emoji(3, "veza")]));
+    }
+}
@@ -0,0 +1,239 @@
+//! Module de suppression des messages dupliqués
+//!
+//! Sur un réseau instable, le client renvoie un message déjà reçu par le
+//! serveur. Chaque envoi peut porter un `nonce` client (`metadata.nonce`) :
+//! un envoi identique (auteur, conversation, contenu, nonce) dans la fenêtre
+//! configurée renvoie le message déjà stocké au lieu d'en créer un nouveau.
+//! Le contenu est comparé par son empreinte (`messages.content_hash`) : dans
+//! un salon chiffré au repos, le contenu stocké est scellé.
+//!
+//! La clé repose sur le nonce : deux « ok » volontaires portent deux nonces
+//! différents et sont tous deux enregistrés. Sans nonce, aucune déduplication.
+//!
+//! Un nonce est unique par auteur et conversation (index de la migration 1044) :
+//! - Dans la fenêtre, un renvoi au même contenu retourne le message stocké ;
+//!   un autre contenu sous le même nonce est refusé (`Conflict`)
+//! - Hors fenêtre, ou message supprimé, le nonce est libéré et l'envoi crée un
+//!   nouveau message
+//! - Deux renvois simultanés se départagent à l'insertion (`ON CONFLICT`) :
+//!   le second reçoit la ligne du premier, vérifiée de la même façon
+
+use ring::digest::{digest, SHA256};
+use sqlx::{query, PgPool, Row};
+use chrono::{DateTime, Utc};
+use crate::error::{ChatError, Result};
+use serde_json::Value;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longueur maximale d'un nonce client
+pub const MAX_NONCE_LENGTH: usize = 64;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Message stocké par un envoi (nouveau, ou déjà stocké lors d'un renvoi)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SentMessage {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Clé de déduplication d'un envoi
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DedupKey {
+    pub author_id: i64,
+    pub conversation_id: i64,
+    /// Empreinte SHA-256 du contenu en clair
+    pub content_hash: Vec<u8>,
+    pub nonce: String,
+}
+
+impl DedupKey {
+    /// Extrait le nonce des métadonnées (retiré de celles-ci, stocké en colonne)
+    pub fn from_metadata(
+        author_id: i64,
+        conversation_id: i64,
+        content: &str,
+        metadata: &mut Option<Value>
+    ) -> Result<Option<Self>> {
+        let Some(raw) = metadata.as_mut()
+            .and_then(|m| m.as_object_mut())
+            .and_then(|m| m.remove("nonce")) else {
+            return Ok(None);
+        };
+
+        let nonce = raw.as_str()
+            .map(str::trim)
+            .filter(|n| !n.is_empty() && n.len() <= MAX_NONCE_LENGTH)
+            .ok_or_else(|| ChatError::configuration_error("Nonce client invalide"))?;
+
+        Ok(Some(Self {
+            author_id,
+            conversation_id,
+            content_hash: content_hash(content),
+            nonce: nonce.to_string(),
+        }))
+    }
+
+    /// Un renvoi doit porter le contenu de l'envoi stocké sous le même nonce
+    pub fn check_retry(&self, stored_hash: Option<&[u8]>) -> Result<()> {
+        if stored_hash == Some(self.content_hash.as_slice()) {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            author_id = %self.author_id,
+            conversation_id = %self.conversation_id,
+            "⚠️ Nonce client réutilisé pour un autre contenu"
+        );
+        Err(ChatError::Conflict { reason: "Nonce client déjà utilisé pour un autre message".to_string() })
+    }
+}
+
+/// Empreinte du contenu en clair, stockée avec le nonce
+pub fn content_hash(content: &str) -> Vec<u8> {
+    digest(&SHA256, content.as_bytes()).as_ref().to_vec()
+}
+
+// ================================================================
+// RECHERCHE DES DOUBLONS
+// ================================================================
+
+/// Retourne le message déjà stocké pour cette clé, s'il existe
+///
+/// Libère au passage le nonce d'un message hors fenêtre ou supprimé (fenêtre
+/// nulle : toujours libéré). Un autre contenu sous le nonce est refusé.
+pub async fn find_duplicate(db: &PgPool, key: &DedupKey, window: Duration) -> Result<Option<SentMessage>> {
+    // La requête principale lit l'état antérieur à la libération : même filtre, inversé
+    let existing = query("
+        WITH released AS (
+            UPDATE messages SET client_nonce = NULL
+            WHERE author_id = $1 AND conversation_id = $2 AND client_nonce = $3
+              AND (status = 'deleted' OR created_at <= NOW() - make_interval(secs => $4))
+        )
+        SELECT id, created_at, content_hash FROM messages
+        WHERE author_id = $1 AND conversation_id = $2 AND client_nonce = $3
+          AND status != 'deleted'
+          AND created_at > NOW() - make_interval(secs => $4)
+    ")
+    .bind(key.author_id)
+    .bind(key.conversation_id)
+    .bind(&key.nonce)
+    .bind(window.as_secs_f64())
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("find_duplicate_message", e))?;
+
+    let Some(row) = existing else {
+        return Ok(None);
+    };
+    key.check_retry(row.get::<Option<Vec<u8>>, _>("content_hash").as_deref())?;
+
+    let existing = SentMessage { id: row.get("id"), created_at: row.get("created_at") };
+    tracing::info!(
+        author_id = %key.author_id,
+        conversation_id = %key.conversation_id,
+        message_id = %existing.id,
+        "♻️ Renvoi dupliqué ignoré, message existant retourné"
+    );
+    Ok(Some(existing))
+}
+
+/// Interprète la ligne retournée par un `INSERT ... ON CONFLICT` sur le nonce
+///
+/// `stored_uuid` différent de celui de l'envoi : la ligne existait déjà (renvoi
+/// simultané) ; retourne alors `true` après vérification du contenu.
+pub fn is_concurrent_retry(key: Option<&DedupKey>, message_uuid: Uuid, stored_uuid: Uuid, stored_hash: Option<&[u8]>) -> Result<bool> {
+    let Some(key) = key.filter(|_| stored_uuid != message_uuid) else {
+        return Ok(false);
+    };
+    key.check_retry(stored_hash)?;
+
+    tracing::info!(
+        author_id = %key.author_id,
+        conversation_id = %key.conversation_id,
+        "♻️ Renvoi simultané, message existant retourné"
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nonce_extracted_and_removed_from_metadata() {
+        let mut metadata = Some(json!({ "nonce": "c-123", "client": "web" }));
+        let key = DedupKey::from_metadata(1, 10, "salut", &mut metadata).unwrap().unwrap();
+
+        assert_eq!(key.nonce, "c-123");
+        assert_eq!(metadata, Some(json!({ "client": "web" })));
+    }
+
+    #[test]
+    fn test_retry_matches_but_intentional_repeat_does_not() {
+        let mut retry_a = Some(json!({ "nonce": "n-1" }));
+        let mut retry_b = Some(json!({ "nonce": "n-1" }));
+        let mut second_ok = Some(json!({ "nonce": "n-2" }));
+
+        let first = DedupKey::from_metadata(1, 10, "ok", &mut retry_a).unwrap();
+        let retry = DedupKey::from_metadata(1, 10, "ok", &mut retry_b).unwrap();
+        let repeat = DedupKey::from_metadata(1, 10, "ok", &mut second_ok).unwrap();
+
+        assert_eq!(first, retry);
+        assert_ne!(first, repeat);
+    }
+
+    #[test]
+    fn test_content_compared_by_hash() {
+        let mut first = Some(json!({ "nonce": "n-1" }));
+        let mut edited = Some(json!({ "nonce": "n-1" }));
+        let key = DedupKey::from_metadata(1, 10, "ok", &mut first).unwrap().unwrap();
+        let other = DedupKey::from_metadata(1, 10, "ko", &mut edited).unwrap().unwrap();
+
+        // Empreinte du clair : identique quel que soit le scellement stocké
+        assert_eq!(key.content_hash, content_hash("ok"));
+        assert_eq!(key.content_hash.len(), 32);
+        assert_ne!(key.content_hash, other.content_hash);
+    }
+
+    #[test]
+    fn test_same_nonce_with_other_content_conflicts() {
+        let mut metadata = Some(json!({ "nonce": "n-1" }));
+        let key = DedupKey::from_metadata(1, 10, "ok", &mut metadata).unwrap().unwrap();
+
+        assert!(key.check_retry(Some(&content_hash("ok"))).is_ok());
+        assert!(matches!(key.check_retry(Some(&content_hash("ko"))), Err(ChatError::Conflict { .. })));
+        assert!(key.check_retry(None).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_retry_detected_by_returned_uuid() {
+        let mut metadata = Some(json!({ "nonce": "n-1" }));
+        let key = DedupKey::from_metadata(1, 10, "ok", &mut metadata).unwrap().unwrap();
+        let (mine, theirs) = (Uuid::new_v4(), Uuid::new_v4());
+        let hash = content_hash("ok");
+
+        // Ligne insérée par cet envoi
+        assert!(!is_concurrent_retry(Some(&key), mine, mine, Some(&hash)).unwrap());
+        assert!(!is_concurrent_retry(None, mine, mine, None).unwrap());
+        // Ligne d'un envoi simultané : même contenu retourné, autre contenu refusé
+        assert!(is_concurrent_retry(Some(&key), mine, theirs, Some(&hash)).unwrap());
+        assert!(is_concurrent_retry(Some(&key), mine, theirs, Some(&content_hash("ko"))).is_err());
+    }
+
+    #[test]
+    fn test_no_or_invalid_nonce() {
+        let mut none = None;
+        assert!(DedupKey::from_metadata(1, 10, "ok", &mut none).unwrap().is_none());
+
+        let mut too_long = Some(json!({ "nonce": "x".repeat(MAX_NONCE_LENGTH + 1) }));
+        assert!(DedupKey::from_metadata(1, 10, "ok", &mut too_long).is_err());
+
+        let mut not_a_string = Some(json!({ "nonce": 42 }));
+        assert!(DedupKey::from_metadata(1, 10, "ok", &mut not_a_string).is_err());
+    }
+}
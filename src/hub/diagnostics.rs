@@ -0,0 +1,83 @@
+//! Module de diagnostic de connexion
+//!
+//! La commande `ping_diag` est renvoyée immédiatement avec l'heure serveur et
+//! l'identifiant de corrélation du client, sans accès à la base : le client
+//! mesure sa latence indépendamment du trafic de chat.
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use crate::hub::common::ChatHub;
+use crate::security::SecurityAction;
+use crate::error::Result;
+
+/// Longueur maximale de l'identifiant de corrélation renvoyé
+pub const MAX_CORRELATION_ID_LENGTH: usize = 64;
+
+/// Identifiant de corrélation fourni par le client (chaîne ou nombre)
+pub fn correlation_id_from(value: Option<&Value>) -> Option<String> {
+    let id = match value? {
+        Value::String(id) => id.trim().to_string(),
+        Value::Number(id) => id.to_string(),
+        _ => return None,
+    };
+
+    (!id.is_empty()).then(|| id.chars().take(MAX_CORRELATION_ID_LENGTH).collect())
+}
+
+/// Réponse `pong_diag` (l'heure client est renvoyée telle quelle)
+pub fn build_ping_diag_reply(correlation_id: Option<&str>, client_time: Option<i64>, now: DateTime<Utc>) -> Value {
+    json!({
+        "type": "pong_diag",
+        "data": {
+            "correlationId": correlation_id,
+            "clientTime": client_time,
+            "serverTime": now,
+            "serverTimeMs": now.timestamp_millis()
+        }
+    })
+}
+
+/// Répond à un `ping_diag` après contrôle du rate limiting
+///
+/// `user_id` est l'utilisateur authentifié de la connexion : chaque
+/// utilisateur a son propre compteur.
+pub async fn ping_diag(
+    hub: &ChatHub,
+    user_id: i64,
+    correlation_id: Option<&str>,
+    client_time: Option<i64>
+) -> Result<Value> {
+    hub.check_action_limit(user_id as i32, SecurityAction::Diagnostic).await?;
+
+    tracing::trace!(user_id = %user_id, correlation_id = ?correlation_id, "🏓 Diagnostic de connexion");
+    Ok(build_ping_diag_reply(correlation_id, client_time, Utc::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_reply_echoes_correlation_and_server_time() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let reply = build_ping_diag_reply(Some("abc-1"), Some(1_714_564_799_950), now);
+
+        assert_eq!(reply["type"], "pong_diag");
+        assert_eq!(reply["data"]["correlationId"], "abc-1");
+        assert_eq!(reply["data"]["clientTime"], 1_714_564_799_950_i64);
+        assert_eq!(reply["data"]["serverTimeMs"], now.timestamp_millis());
+    }
+
+    #[test]
+    fn test_correlation_id_normalization() {
+        assert_eq!(correlation_id_from(Some(&json!(" r-7 "))), Some("r-7".to_string()));
+        assert_eq!(correlation_id_from(Some(&json!(42))), Some("42".to_string()));
+        assert_eq!(correlation_id_from(Some(&json!(""))), None);
+        assert_eq!(correlation_id_from(Some(&json!({"id": 1}))), None);
+        assert_eq!(correlation_id_from(None), None);
+
+        let long = "x".repeat(MAX_CORRELATION_ID_LENGTH + 10);
+        assert_eq!(correlation_id_from(Some(&json!(long))).unwrap().len(), MAX_CORRELATION_ID_LENGTH);
+    }
+}
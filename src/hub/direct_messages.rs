@@ -8,11 +8,29 @@
 //! - Audit et logs de sécurité
 //! - Historique paginé avancé
 //! - Modération (blocage, signalement)
+//! - Confidentialité : chaque utilisateur choisit qui peut lui écrire
 
-use sqlx::{query, query_as, FromRow, Row, Transaction, Postgres};
+use sqlx::{query, query_as, FromRow, PgExecutor, Row, Transaction, Postgres};
 use serde::{Serialize, Deserialize};
 use crate::hub::common::ChatHub;
-use crate::validation::{validate_message_content, validate_user_id, validate_limit};
+use crate::hub::feature_flags::FeatureFlag;
+use crate::hub::long_messages::{PreparedContent, clear_message_body};
+use crate::hub::dedup::{DedupKey, SentMessage};
+use crate::hub::quotas::{consume_message_quota, release_message_quota};
+use crate::hub::room_links::review_message_links;
+use crate::hub::quotes::QuotedExcerpt;
+use crate::hub::message_policy::{MessagePolicy, ModificationContext};
+use crate::hub::violations::check_standing;
+use crate::hub::mutes::notify_dm_recipient;
+use crate::hub::mentions::{parse_mentions, MentionKind};
+use crate::hub::guests::reject_guest;
+use crate::hub::reputation::{check_message_rate, record_message};
+use crate::hub::attachments::check_message_attachments;
+use crate::hub::dm_repository::NewDmMessage;
+use crate::message_schema::{MessagePayload, VersionedFrame};
+use crate::validation::{AttachmentLimits, validate_message_content, validate_user_id, validate_limit, normalize_username};
+use crate::config::BlockedDmHistory;
+use crate::pagination::Page;
 use crate::error::{ChatError, Result};
 use serde_json::{json, Value};
 use chrono::{DateTime, Utc};
@@ -22,7 +40,7 @@ use uuid::Uuid;
 // STRUCTURES DE DONNÉES
 // ================================================================
 
-#[derive(Debug, FromRow, Serialize, Deserialize)]
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct DmConversation {
     pub id: i64,
     pub uuid: Uuid,
@@ -77,10 +95,103 @@ pub struct DmParticipant {
     pub last_seen: Option<DateTime<Utc>>,
 }
 
+/// Relation entre l'initiateur et la cible d'une nouvelle conversation
+#[derive(Debug, Clone, Default)]
+pub struct StartEligibility {
+    pub target_exists: bool,
+    pub blocked_by_target: bool,
+    pub blocked_by_user: bool,
+    pub conversation_blocked: bool,
+}
+
+impl StartEligibility {
+    /// Vérifie qu'une conversation peut être ouverte avec la cible
+    pub fn check(&self, user_id: i64, target_id: i64) -> Result<()> {
+        if user_id == target_id {
+            return Err(ChatError::configuration_error("Impossible de créer une conversation avec soi-même"));
+        }
+        if !self.target_exists {
+            return Err(ChatError::not_found("utilisateur", &target_id.to_string()));
+        }
+        if self.blocked_by_target || self.blocked_by_user || self.conversation_blocked {
+            return Err(ChatError::unauthorized("start_conversation"));
+        }
+        Ok(())
+    }
+}
+
+/// Qui peut envoyer un message direct à l'utilisateur
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DmPrivacy {
+    /// Tout le monde
+    #[default]
+    Everyone,
+    /// Seulement les membres d'au moins un salon commun
+    SharedRooms,
+    /// Personne
+    Nobody,
+}
+
+impl DmPrivacy {
+    /// Nom utilisé en base et dans les trames client
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DmPrivacy::Everyone => "everyone",
+            DmPrivacy::SharedRooms => "shared_rooms",
+            DmPrivacy::Nobody => "nobody",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [DmPrivacy::Everyone, DmPrivacy::SharedRooms, DmPrivacy::Nobody]
+            .into_iter()
+            .find(|privacy| privacy.as_str() == name)
+    }
+
+    /// L'expéditeur satisfait-il la politique du destinataire ?
+    pub fn allows(&self, shares_room: bool) -> bool {
+        match self {
+            DmPrivacy::Everyone => true,
+            DmPrivacy::SharedRooms => shares_room,
+            DmPrivacy::Nobody => false,
+        }
+    }
+}
+
 // ================================================================
 // GESTION DES CONVERSATIONS DM
 // ================================================================
 
+/// Ouvrir une conversation avec un utilisateur avant le premier message
+///
+/// Vérifie que la cible existe et qu'aucun blocage n'est en place, puis
+/// renvoie la conversation (existante ou nouvelle, encore vide).
+pub async fn start_conversation(
+    hub: &ChatHub,
+    user_id: i64,
+    target_id: i64
+) -> Result<(DmConversation, DmParticipant)> {
+    tracing::info!(user_id = %user_id, target_id = %target_id, "👋 Ouverture d'une conversation DM");
+    
+    reject_guest(user_id, "start_conversation")?;
+    validate_user_id(user_id as i32)?;
+    validate_user_id(target_id as i32)?;
+    
+    let target = hub.dm_repository.dm_target(user_id, target_id).await?;
+    let eligibility = target.as_ref().map(|(_, eligibility)| eligibility.clone()).unwrap_or_default();
+    eligibility.check(user_id, target_id)?;
+    
+    let Some((participant, _)) = target else {
+        return Err(ChatError::not_found("utilisateur", &target_id.to_string()));
+    };
+    
+    let conversation = get_or_create_dm_conversation(hub, user_id, target_id).await?;
+    
+    tracing::info!(conversation_id = %conversation.id, "✅ Conversation DM prête");
+    Ok((conversation, participant))
+}
+
 /// Créer ou récupérer une conversation DM entre deux utilisateurs
 pub async fn get_or_create_dm_conversation(
     hub: &ChatHub,
@@ -89,6 +200,8 @@ pub async fn get_or_create_dm_conversation(
 ) -> Result<DmConversation> {
     tracing::info!(user1_id = %user1_id, user2_id = %user2_id, "💬 Création/récupération conversation DM");
     
+    reject_guest(user1_id, "create_dm_conversation")?;
+    reject_guest(user2_id, "create_dm_conversation")?;
     validate_user_id(user1_id as i32)?;
     validate_user_id(user2_id as i32)?;
     
@@ -96,130 +209,121 @@ pub async fn get_or_create_dm_conversation(
         return Err(ChatError::configuration_error("Impossible de créer une conversation avec soi-même"));
     }
     
-    let mut tx = hub.db.begin().await
-        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
-    
-    // Chercher une conversation existante (dans les deux sens)
-    let existing = query_as::<_, DmConversation>("
-        SELECT id, uuid, user1_id, user2_id, is_blocked, blocked_by, created_at, updated_at
-        FROM dm_conversations 
-        WHERE (user1_id = $1 AND user2_id = $2) OR (user1_id = $2 AND user2_id = $1)
-    ")
-    .bind(user1_id)
-    .bind(user2_id)
-    .fetch_optional(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("find_existing_dm", e))?;
-    
-    if let Some(conversation) = existing {
-        tx.commit().await
-            .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
-        return Ok(conversation);
-    }
-    
-    // Créer une nouvelle conversation DM
-    let dm_uuid = Uuid::new_v4();
-    let conversation = query_as::<_, DmConversation>("
-        INSERT INTO dm_conversations (uuid, user1_id, user2_id)
-        VALUES ($1, $2, $3)
-        RETURNING id, uuid, user1_id, user2_id, is_blocked, blocked_by, created_at, updated_at
-    ")
-    .bind(dm_uuid)
-    .bind(user1_id.min(user2_id)) // Ordre consistant
-    .bind(user1_id.max(user2_id))
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("create_dm_conversation", e))?;
-    
-    // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('dm_conversation_created', $1, $2)
-    ")
-    .bind(json!({
-        "conversation_id": conversation.id,
-        "user1_id": user1_id,
-        "user2_id": user2_id
-    }))
-    .bind(user1_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
-    
-    tx.commit().await
-        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    let conversation = hub.dm_repository.open_dm_conversation(user1_id, user2_id, &hub.audit_sink).await?;
     
     tracing::info!(conversation_id = %conversation.id, "✅ Conversation DM créée/récupérée");
     Ok(conversation)
 }
 
 /// Bloquer/débloquer une conversation DM
+///
+/// Avec `hide_reactions`, les réactions de l'autre participant sur les messages
+/// de `user_id` lui sont masquées tant que le blocage dure (les réactions sont
+/// conservées ; le déblocage les rend de nouveau visibles).
 pub async fn block_dm_conversation(
     hub: &ChatHub,
     conversation_id: i64,
     user_id: i64,
-    block: bool
+    block: bool,
+    hide_reactions: bool
 ) -> Result<()> {
-    tracing::info!(conversation_id = %conversation_id, user_id = %user_id, block = %block, "🚫 Blocage/déblocage DM");
+    tracing::info!(conversation_id = %conversation_id, user_id = %user_id, block = %block, hide_reactions = %hide_reactions, "🚫 Blocage/déblocage DM");
+    
+    hub.dm_repository
+        .set_dm_block(conversation_id, user_id, block, hide_reactions, hub.config.security.blocked_dm_history, &hub.audit_sink)
+        .await?;
     
+    tracing::info!(conversation_id = %conversation_id, block = %block, "✅ Statut de blocage mis à jour");
+    Ok(())
+}
+
+// ================================================================
+// CONFIDENTIALITÉ
+// ================================================================
+
+/// Politique de messages directs d'un utilisateur
+pub async fn get_dm_privacy(hub: &ChatHub, user_id: i64) -> Result<DmPrivacy> {
+    let policy: String = sqlx::query_scalar("SELECT dm_privacy FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("get_dm_privacy", e))?
+        .ok_or_else(|| ChatError::not_found("utilisateur", &user_id.to_string()))?;
+
+    Ok(DmPrivacy::from_name(&policy).unwrap_or_default())
+}
+
+/// Choisit qui peut envoyer des messages directs à l'utilisateur
+pub async fn set_dm_privacy(hub: &ChatHub, user_id: i64, privacy: DmPrivacy) -> Result<()> {
+    tracing::info!(user_id = %user_id, privacy = %privacy.as_str(), "🔒 Changement de confidentialité DM");
+
+    validate_user_id(user_id as i32)?;
+
     let mut tx = hub.db.begin().await
         .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
-    
-    // Vérifier que l'utilisateur fait partie de la conversation
-    let is_participant: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM dm_conversations 
-            WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
-        )
-    ")
-    .bind(conversation_id)
-    .bind(user_id)
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("check_participant", e))?
-    .get(0);
-    
-    if !is_participant {
-        return Err(ChatError::unauthorized("block_dm_conversation"));
+
+    let updated = query("UPDATE users SET dm_privacy = $1 WHERE id = $2")
+        .bind(privacy.as_str())
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("set_dm_privacy", e))?
+        .rows_affected();
+
+    if updated == 0 {
+        return Err(ChatError::not_found("utilisateur", &user_id.to_string()));
     }
-    
-    // Mettre à jour le statut de blocage
-    query("
-        UPDATE dm_conversations 
-        SET is_blocked = $1, blocked_by = $2, updated_at = NOW()
-        WHERE id = $3
-    ")
-    .bind(block)
-    .bind(if block { Some(user_id) } else { None })
-    .bind(conversation_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("update_block_status", e))?;
-    
-    // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ($1, $2, $3)
-    ")
-    .bind(if block { "dm_blocked" } else { "dm_unblocked" })
-    .bind(json!({"conversation_id": conversation_id}))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
-    
+
+    hub.audit_sink.record(&mut *tx, "dm_privacy_changed", Some(user_id), json!({
+        "privacy": privacy.as_str()
+    })).await?;
+
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
-    
-    tracing::info!(conversation_id = %conversation_id, block = %block, "✅ Statut de blocage mis à jour");
+
     Ok(())
 }
 
+/// Le destinataire accepte-t-il les messages directs de l'expéditeur ?
+///
+/// « Salon commun » : les deux utilisateurs sont membres actifs d'au moins un
+/// même salon.
+pub async fn dm_allowed<'e, E: PgExecutor<'e>>(executor: E, sender_id: i64, recipient_id: i64) -> Result<bool> {
+    let row = query("
+        SELECT u.dm_privacy,
+               EXISTS(
+                   SELECT 1 FROM conversation_members a
+                   JOIN conversation_members b ON b.conversation_id = a.conversation_id
+                   WHERE a.user_id = $1 AND b.user_id = $2
+                     AND a.left_at IS NULL AND b.left_at IS NULL
+               ) as shares_room
+        FROM users u
+        WHERE u.id = $2
+    ")
+    .bind(sender_id)
+    .bind(recipient_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_dm_privacy", e))?;
+
+    // Destinataire inconnu : laissé aux vérifications de la conversation
+    let Some(row) = row else {
+        return Ok(true);
+    };
+
+    let policy: String = row.get("dm_privacy");
+    let privacy = DmPrivacy::from_name(&policy).unwrap_or_default();
+    Ok(privacy.allows(row.get("shares_room")))
+}
+
 // ================================================================
 // GESTION DES MESSAGES ENRICHIS
 // ================================================================
 
 /// Envoyer un message DM enrichi
+///
+/// Retourne `None` si la politique de confidentialité du destinataire écarte
+/// le message : comme pour un blocage, rien n'est stocké ni signalé.
 pub async fn send_dm_message(
     hub: &ChatHub,
     conversation_id: i64,
@@ -227,111 +331,130 @@ pub async fn send_dm_message(
     username: &str,
     content: &str,
     parent_message_id: Option<i64>,
-    metadata: Option<Value>
-) -> Result<i64> {
+    mut metadata: Option<Value>
+) -> Result<Option<SentMessage>> {
     tracing::info!(author_id = %author_id, conversation_id = %conversation_id, "📝 Envoi d'un message DM enrichi");
     
+    reject_guest(author_id, "send_dm_message")?;
+    if parent_message_id.is_some() {
+        hub.require_feature(FeatureFlag::Threads).await?;
+    }
     validate_user_id(author_id as i32)?;
-    validate_message_content(content, hub.config.limits.max_message_length)?;
+    // Auteur suspendu ou banni après des violations répétées du filtre
+    check_standing(hub, author_id as i32).await?;
+    // Nom dénormalisé sur le message : jamais affiché tel que reçu
+    let username: &str = &normalize_username(username)?;
+    // Transformations configurées (émojis, liens, ...) sur le contenu validé, avant persistance
+    validate_message_content(content, hub.config.limits.max_long_message_length)?;
+    let transformed = hub.content_pipeline.apply(content)?;
+    check_message_attachments(hub, author_id, AttachmentLimits::from_config(&hub.config.limits), metadata.as_mut()).await?;
+    
+    // Un renvoi (même nonce) retourne le message déjà stocké sans consommer de quota
+    let dedup_key = DedupKey::from_metadata(author_id, conversation_id, &transformed.content, &mut metadata)?;
+    if let Some(key) = &dedup_key {
+        if let Some(existing) = hub.dm_repository.find_duplicate(key, hub.config.limits.duplicate_window).await? {
+            return Ok(Some(existing));
+        }
+    }
     
-    // Vérification du rate limiting
-    if !hub.check_rate_limit(author_id as i32).await {
+    // Vérification du rate limiting (relâché pour un compte de confiance)
+    if !check_message_rate(hub, author_id).await? {
         return Err(ChatError::rate_limit_exceeded_simple("send_dm_message"));
     }
     
-    let mut tx = hub.db.begin().await
-        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
-    
     // Vérifier que l'utilisateur fait partie de la conversation et qu'elle n'est pas bloquée
-    let conversation_info = query("
-        SELECT is_blocked, blocked_by, user1_id, user2_id
-        FROM dm_conversations 
-        WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
-    ")
-    .bind(conversation_id)
-    .bind(author_id)
-    .fetch_optional(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("check_dm_conversation", e))?;
-    
-    let (is_blocked, blocked_by, user1_id, user2_id) = match conversation_info {
-        Some(row) => (
-            row.get::<bool, _>("is_blocked"),
-            row.get::<Option<i64>, _>("blocked_by"),
-            row.get::<i64, _>("user1_id"),
-            row.get::<i64, _>("user2_id")
-        ),
-        None => return Err(ChatError::not_found("conversation", &conversation_id.to_string()))
-    };
+    let conversation = hub.dm_repository.dm_conversation(conversation_id, author_id).await?
+        .ok_or_else(|| ChatError::not_found("conversation", &conversation_id.to_string()))?;
     
-    if is_blocked {
+    if conversation.is_blocked {
         return Err(ChatError::configuration_error("Conversation bloquée"));
     }
     
-    // Insérer le message
-    let message_uuid = Uuid::new_v4();
-    let message_metadata = metadata.unwrap_or_else(|| json!({}));
+    // Politique des liens (suivi des liens raccourcis) une fois l'envoi admis
+    let links = review_message_links(hub, None, &transformed.content).await?;
+    let content: &str = &links.content;
+    let prepared = PreparedContent::prepare(content, &hub.config.limits)?;
     
-    let message = query("
-        INSERT INTO messages (uuid, author_id, conversation_id, content, parent_message_id, metadata, status)
-        VALUES ($1, $2, $3, $4, $5, $6, 'sent')
-        RETURNING id, created_at
-    ")
-    .bind(message_uuid)
-    .bind(author_id)
-    .bind(conversation_id)
-    .bind(content)
-    .bind(parent_message_id)
-    .bind(&message_metadata)
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("insert_dm_message", e))?;
-    
-    let message_id: i64 = message.get("id");
-    let timestamp: DateTime<Utc> = message.get("created_at");
+    // Quota compté une fois le message validé ; un message écarté par la
+    // confidentialité compte aussi, pour ne pas la révéler
+    let quota = consume_message_quota(hub, author_id).await?;
     
-    // Si c'est une réponse, incrémenter le compteur de thread
-    if let Some(parent_id) = parent_message_id {
-        query("
-            UPDATE messages 
-            SET thread_count = thread_count + 1 
-            WHERE id = $1
-        ")
-        .bind(parent_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| ChatError::from_sqlx_error("update_thread_count", e))?;
+    // Politique du destinataire : message écarté sans le révéler à l'expéditeur
+    let other_user_id = if author_id == conversation.user1_id { conversation.user2_id } else { conversation.user1_id };
+    if !hub.dm_repository.dm_allowed(author_id, other_user_id).await? {
+        tracing::warn!(author_id = %author_id, recipient_id = %other_user_id, "🔒 Message DM écarté par la confidentialité du destinataire");
+        return Ok(None);
     }
     
-    // Traiter les mentions (@username)
-    process_dm_mentions(&mut tx, message_id, content).await?;
-    
-    // Mettre à jour la conversation
-    query("
-        UPDATE dm_conversations 
-        SET updated_at = NOW() 
-        WHERE id = $1
-    ")
-    .bind(conversation_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("update_dm_conversation", e))?;
-    
-    tx.commit().await
-        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    let mut message_metadata = metadata.unwrap_or_else(|| json!({}));
+    prepared.annotate_metadata(&mut message_metadata);
+    transformed.metadata.annotate_metadata(&mut message_metadata);
+    links.annotate_metadata(&mut message_metadata);
+    
+    let inserted = hub.dm_repository.insert_dm_message(NewDmMessage {
+        conversation_id,
+        author_id,
+        content: &prepared,
+        source: content,
+        parent_message_id,
+        metadata: message_metadata,
+        dedup_key,
+    }, hub.message_cipher.as_ref()).await?;
+    let (message_id, timestamp) = (inserted.id, inserted.created_at);
+    
+    // Renvoi simultané : message déjà stocké et diffusé
+    if inserted.duplicate {
+        if quota.is_some() {
+            release_message_quota(hub, author_id).await?;
+        }
+        return Ok(Some(SentMessage { id: message_id, created_at: timestamp }));
+    }
     
     // Incrémentation des statistiques
     hub.increment_message_count().await;
+    record_message(hub, author_id).await;
+    hub.metrics.message_sent("direct", None).await;
+    hub.metrics.message_size(prepared.stored.len(), "direct").await;
     
     // Diffusion en temps réel
-    let other_user_id = if author_id == user1_id { user2_id } else { user1_id };
-    broadcast_dm_message(hub, conversation_id, message_id, author_id, other_user_id, username, content, timestamp, parent_message_id).await?;
+    broadcast_dm_message(hub, conversation_id, message_id, author_id, other_user_id, username, &prepared.stored, prepared.full_length(), timestamp, parent_message_id, inserted.quote.as_ref()).await?;
+    notify_dm_recipient(hub, conversation_id, other_user_id, username, content).await;
     
     tracing::info!(message_id = %message_id, conversation_id = %conversation_id, "✅ Message DM enrichi envoyé");
-    Ok(message_id)
+    Ok(Some(SentMessage { id: message_id, created_at: timestamp }))
+}
+
+/// Refuse une épingle au-delà de `limits.max_dm_pins` (épingle demandée comprise)
+pub fn check_dm_pin_limit(pinned: usize, max: usize) -> Result<()> {
+    if pinned > max {
+        return Err(ChatError::OutOfRange {
+            field: "dm_pins".to_string(),
+            value: pinned as i64,
+            min: 0,
+            max: max as i64,
+        });
+    }
+    Ok(())
+}
+
+/// Trame `dm_pins_updated`, diffusée aux deux participants
+pub fn dm_pins_frame(conversation_id: i64, message_id: i64, actor_id: i64, pin: bool, pinned_ids: &[i64]) -> Value {
+    json!({
+        "type": "dm_pins_updated",
+        "data": {
+            "conversationId": conversation_id,
+            "messageId": message_id,
+            "isPinned": pin,
+            "pinnedBy": actor_id,
+            "messageIds": pinned_ids
+        }
+    })
 }
 
 /// Épingler/désépingler un message DM
+///
+/// Réservé aux deux participants, dans la limite de `limits.max_dm_pins`.
+/// Les épingles restantes sont diffusées aux deux participants (`dm_pins_updated`).
 pub async fn pin_dm_message(
     hub: &ChatHub,
     conversation_id: i64,
@@ -341,32 +464,33 @@ pub async fn pin_dm_message(
 ) -> Result<()> {
     tracing::info!(user_id = %user_id, conversation_id = %conversation_id, message_id = %message_id, pin = %pin, "📌 Épinglage de message DM");
     
+    hub.require_feature(FeatureFlag::PinnedMessages).await?;
+    
     let mut tx = hub.db.begin().await
         .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
     
-    // Vérifier que l'utilisateur fait partie de la conversation
-    let is_participant: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM dm_conversations 
-            WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
-        )
+    // Vérifier que l'utilisateur fait partie de la conversation ; le verrou
+    // sérialise les épinglages concurrents pour le décompte de la limite
+    let participants = query("
+        SELECT user1_id, user2_id FROM dm_conversations 
+        WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
+        FOR UPDATE
     ")
     .bind(conversation_id)
     .bind(user_id)
-    .fetch_one(&mut *tx)
+    .fetch_optional(&mut *tx)
     .await
     .map_err(|e| ChatError::from_sqlx_error("check_participant", e))?
-    .get(0);
+    .ok_or_else(|| ChatError::unauthorized("pin_dm_message"))?;
     
-    if !is_participant {
-        return Err(ChatError::unauthorized("pin_dm_message"));
-    }
+    let user1_id: i64 = participants.get("user1_id");
+    let other_user_id: i64 = if user1_id == user_id { participants.get("user2_id") } else { user1_id };
     
     // Mettre à jour le statut d'épinglage
     let rows_affected = query("
         UPDATE messages 
         SET is_pinned = $1, updated_at = NOW()
-        WHERE id = $2 AND conversation_id = $3
+        WHERE id = $2 AND conversation_id = $3 AND ($1 = FALSE OR status != 'deleted')
     ")
     .bind(pin)
     .bind(message_id)
@@ -380,25 +504,40 @@ pub async fn pin_dm_message(
         return Err(ChatError::not_found("message", &message_id.to_string()));
     }
     
-    // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ($1, $2, $3)
+    let pinned_ids: Vec<i64> = query("
+        SELECT id FROM messages
+        WHERE conversation_id = $1 AND is_pinned = TRUE AND status != 'deleted'
+        ORDER BY created_at DESC
     ")
-    .bind(if pin { "dm_message_pinned" } else { "dm_message_unpinned" })
-    .bind(json!({
+    .bind(conversation_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("list_pinned_dm_messages", e))?
+    .into_iter()
+    .map(|row| row.get("id"))
+    .collect();
+    
+    // Au-delà de la limite, la transaction est abandonnée
+    if pin {
+        check_dm_pin_limit(pinned_ids.len(), hub.config.limits.max_dm_pins)?;
+    }
+    
+    // Log d'audit
+    hub.audit_sink.record(&mut *tx, if pin { "dm_message_pinned" } else { "dm_message_unpinned" }, Some(user_id), json!({
         "conversation_id": conversation_id,
         "message_id": message_id
-    }))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
+    })).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
     
-    tracing::info!(message_id = %message_id, pin = %pin, "✅ Statut d'épinglage DM mis à jour");
+    let payload = dm_pins_frame(conversation_id, message_id, user_id, pin, &pinned_ids);
+    hub.bridge_dm_event(conversation_id, &payload).await;
+    let delivered = hub.clients.get_many([user_id as i32, other_user_id as i32]).await.iter()
+        .filter(|client| client.send_text(&payload.to_string()))
+        .count();
+    
+    tracing::info!(message_id = %message_id, pin = %pin, delivered = %delivered, "✅ Statut d'épinglage DM mis à jour");
     Ok(())
 }
 
@@ -419,30 +558,40 @@ pub async fn edit_dm_message(
     
     // Récupérer le message et vérifier les permissions
     let message_info = query("
-        SELECT m.content, m.author_id, m.conversation_id, dc.user1_id, dc.user2_id
+        SELECT m.content, m.author_id, m.conversation_id, m.created_at, m.thread_count, m.is_e2ee,
+               (SELECT COUNT(*) FROM message_reactions r WHERE r.message_id = m.id) as reaction_count,
+               dc.user1_id, dc.user2_id
         FROM messages m
         JOIN dm_conversations dc ON dc.id = m.conversation_id
-        WHERE m.id = $1
+        WHERE m.id = $1 AND m.status != 'deleted'
     ")
     .bind(message_id)
     .fetch_optional(&mut *tx)
     .await
     .map_err(|e| ChatError::from_sqlx_error("get_message_info", e))?;
     
-    let (old_content, author_id, conversation_id, user1_id, user2_id) = match message_info {
-        Some(row) => (
-            row.get::<String, _>("content"),
-            row.get::<i64, _>("author_id"),
-            row.get::<i64, _>("conversation_id"),
-            row.get::<i64, _>("user1_id"),
-            row.get::<i64, _>("user2_id")
-        ),
-        None => return Err(ChatError::not_found("message", &message_id.to_string()))
+    let Some(row) = message_info else {
+        return Err(ChatError::not_found("message", &message_id.to_string()));
     };
     
-    // Seul l'auteur peut éditer son message
-    if author_id != user_id {
-        return Err(ChatError::unauthorized("edit_dm_message"));
+    let old_content: String = row.get("content");
+    let conversation_id: i64 = row.get("conversation_id");
+    let user1_id: i64 = row.get("user1_id");
+    let user2_id: i64 = row.get("user2_id");
+    
+    // Seul l'auteur peut éditer son message, dans la fenêtre configurée
+    let ctx = ModificationContext {
+        is_author: row.get::<i64, _>("author_id") == user_id,
+        is_moderator: false,
+        created_at: row.get("created_at"),
+        reaction_count: row.get("reaction_count"),
+        reply_count: row.get::<i32, _>("thread_count") as i64,
+    };
+    MessagePolicy::from_limits(&hub.config.limits).check_edit(&ctx, Utc::now())?;
+
+    // Un message chiffré de bout en bout ne peut être remplacé par du clair
+    if row.get::<bool, _>("is_e2ee") {
+        return Err(ChatError::feature_not_available("edit_dm_message", "Message chiffré de bout en bout"));
     }
     
     // Mettre à jour le message
@@ -456,23 +605,16 @@ pub async fn edit_dm_message(
     .execute(&mut *tx)
     .await
     .map_err(|e| ChatError::from_sqlx_error("update_message", e))?;
+    clear_message_body(&mut tx, message_id).await?;
     
     // Log d'audit avec ancien et nouveau contenu
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('dm_message_edited', $1, $2)
-    ")
-    .bind(json!({
+    hub.audit_sink.record(&mut *tx, "dm_message_edited", Some(user_id), json!({
         "message_id": message_id,
         "conversation_id": conversation_id,
         "old_content": old_content,
         "new_content": new_content,
         "edit_reason": edit_reason
-    }))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
+    })).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
@@ -485,24 +627,18 @@ pub async fn edit_dm_message(
     Ok(())
 }
 
-// ================================================================
-// HISTORIQUE ET RECHERCHE
-// ================================================================
-
-/// Récupérer l'historique d'une conversation DM
-pub async fn fetch_history(
+/// Marquer comme lus les messages reçus d'une conversation DM
+///
+/// Sans `up_to_message_id`, toute la conversation est marquée (lecture groupée).
+/// Toutes les sessions ouvertes du lecteur reçoivent l'événement `read_state`.
+pub async fn mark_dm_read(
     hub: &ChatHub,
     conversation_id: i64,
     user_id: i64,
-    limit: i64,
-    before_message_id: Option<i64>
-) -> Result<Vec<DmMessage>> {
-    tracing::info!(conversation_id = %conversation_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique DM enrichi");
-    
-    validate_user_id(user_id as i32)?;
-    let validated_limit = validate_limit(limit)?;
+    up_to_message_id: Option<i64>
+) -> Result<i64> {
+    tracing::debug!(user_id = %user_id, conversation_id = %conversation_id, up_to = ?up_to_message_id, "👁️ Lecture de conversation DM");
     
-    // Vérifier que l'utilisateur fait partie de la conversation
     let is_participant: bool = query("
         SELECT EXISTS(
             SELECT 1 FROM dm_conversations 
@@ -517,62 +653,87 @@ pub async fn fetch_history(
     .get(0);
     
     if !is_participant {
-        return Err(ChatError::unauthorized("fetch_dm_history"));
+        return Err(ChatError::unauthorized("mark_dm_read"));
     }
     
-    let mut query_builder = format!("
+    let row = query("
+        WITH updated AS (
+            UPDATE messages 
+            SET status = 'read', updated_at = NOW()
+            WHERE conversation_id = $1 AND author_id != $2
+              AND status NOT IN ('read', 'deleted')
+              AND ($3::bigint IS NULL OR id <= $3)
+            RETURNING id
+        )
         SELECT 
-            m.id, m.uuid, m.author_id, u.username as author_username,
-            m.conversation_id, m.content, m.parent_message_id, m.thread_count,
-            m.status, m.is_edited, m.edit_count, m.is_pinned, m.metadata,
-            m.created_at, m.updated_at, m.edited_at,
-            COALESCE(
-                json_agg(
-                    json_build_object(
-                        'emoji', mr.emoji,
-                        'count', COUNT(mr.id)
-                    ) ORDER BY mr.emoji
-                ) FILTER (WHERE mr.id IS NOT NULL), 
-                '[]'::json
-            ) as reactions,
-            COUNT(mm.id) as mention_count
-        FROM messages m
-        JOIN users u ON u.id = m.author_id
-        LEFT JOIN message_reactions mr ON mr.message_id = m.id
-        LEFT JOIN message_mentions mm ON mm.message_id = m.id
-        WHERE m.conversation_id = $1
-    ");
+            (SELECT COUNT(*) FROM updated) as updated_count,
+            COALESCE($3, (SELECT MAX(id) FROM messages WHERE conversation_id = $1 AND status != 'deleted')) as last_read_message_id
+    ")
+    .bind(conversation_id)
+    .bind(user_id)
+    .bind(up_to_message_id)
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("mark_dm_read", e))?;
     
-    let mut param_count = 1;
+    let updated_count: i64 = row.get("updated_count");
+    let last_read_message_id: Option<i64> = row.get("last_read_message_id");
     
-    if let Some(_before_id) = before_message_id {
-        param_count += 1;
-        query_builder.push_str(&format!(" AND m.id < ${}", param_count));
+    if let Some(last_read_message_id) = last_read_message_id {
+        broadcast_read_state(hub, user_id, conversation_id, last_read_message_id).await;
     }
     
-    query_builder.push_str("
-        GROUP BY m.id, u.username
-        ORDER BY m.created_at DESC
-    ");
+    Ok(updated_count)
+}
+
+// ================================================================
+// HISTORIQUE ET RECHERCHE
+// ================================================================
+
+/// Récupérer l'historique d'une conversation DM
+///
+/// `next_cursor` de la page est le `before_message_id` de la page suivante.
+pub async fn fetch_history(
+    hub: &ChatHub,
+    conversation_id: i64,
+    user_id: i64,
+    limit: i64,
+    before_message_id: Option<i64>
+) -> Result<Page<DmMessage>> {
+    tracing::info!(conversation_id = %conversation_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique DM enrichi");
     
-    param_count += 1;
-    query_builder.push_str(&format!(" LIMIT ${}", param_count));
+    hub.require_feature(FeatureFlag::MessageHistory).await?;
+    validate_user_id(user_id as i32)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
     
-    let mut query_obj = query_as::<_, EnhancedDmMessage>(&query_builder)
-        .bind(conversation_id);
+    // Vérifier que l'utilisateur fait partie de la conversation
+    let conversation = hub.dm_repository.dm_conversation(conversation_id, user_id).await?
+        .ok_or_else(|| ChatError::unauthorized("fetch_dm_history"))?;
     
-    if let Some(before_id) = before_message_id {
-        query_obj = query_obj.bind(before_id);
+    let blocked_by = if conversation.is_blocked { conversation.blocked_by } else { None };
+    if !dm_history_visible(hub.config.security.blocked_dm_history, blocked_by, user_id) {
+        return Ok(Page::empty());
     }
     
-    let messages = query_obj
-        .bind(validated_limit)
-        .fetch_all(&hub.db)
-        .await
-        .map_err(|e| ChatError::from_sqlx_error("fetch_dm_history", e))?;
+    // Un message de plus que la limite signale une page plus ancienne
+    let messages = hub.dm_repository.dm_history_page(conversation_id, before_message_id, validated_limit + 1).await?;
+    let page = Page::from_overfetch(messages, validated_limit, |m| m.id);
     
-    tracing::info!(conversation_id = %conversation_id, message_count = %messages.len(), "✅ Historique DM enrichi récupéré");
-    Ok(messages)
+    tracing::info!(conversation_id = %conversation_id, message_count = %page.len(), "✅ Historique DM enrichi récupéré");
+    Ok(page)
+}
+
+/// L'historique d'une conversation est-il visible pour `viewer_id` ?
+///
+/// `blocked_by` : auteur du blocage en cours, le cas échéant. Seul celui qui
+/// bloque peut perdre l'historique ; l'utilisateur bloqué obtient toujours la
+/// même réponse, qu'il soit bloqué ou non. En mode `delete`, les messages ont
+/// déjà disparu au blocage.
+pub fn dm_history_visible(mode: BlockedDmHistory, blocked_by: Option<i64>, viewer_id: i64) -> bool {
+    match (mode, blocked_by) {
+        (BlockedDmHistory::HideForBlocker, Some(blocker_id)) => blocker_id != viewer_id,
+        _ => true,
+    }
 }
 
 /// Récupérer les messages épinglés d'une conversation DM
@@ -584,21 +745,20 @@ pub async fn fetch_pinned_messages(
     tracing::info!(conversation_id = %conversation_id, user_id = %user_id, "📌 Récupération des messages DM épinglés");
     
     // Vérifier que l'utilisateur fait partie de la conversation
-    let is_participant: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM dm_conversations 
-            WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
-        )
+    let conversation = query("
+        SELECT is_blocked, blocked_by FROM dm_conversations 
+        WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
     ")
     .bind(conversation_id)
     .bind(user_id)
-    .fetch_one(&hub.db)
+    .fetch_optional(&hub.db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("check_participant", e))?
-    .get(0);
+    .ok_or_else(|| ChatError::unauthorized("fetch_pinned_dm_messages"))?;
     
-    if !is_participant {
-        return Err(ChatError::unauthorized("fetch_pinned_dm_messages"));
+    let blocked_by: Option<i64> = if conversation.get("is_blocked") { conversation.get("blocked_by") } else { None };
+    if !dm_history_visible(hub.config.security.blocked_dm_history, blocked_by, user_id) {
+        return Ok(Vec::new());
     }
     
     let messages = query_as::<_, DmMessage>("
@@ -611,7 +771,7 @@ pub async fn fetch_pinned_messages(
             0 as mention_count
         FROM messages m
         JOIN users u ON u.id = m.author_id
-        WHERE m.conversation_id = $1 AND m.is_pinned = TRUE
+        WHERE m.conversation_id = $1 AND m.is_pinned = TRUE AND m.status != 'deleted'
         ORDER BY m.created_at DESC
     ")
     .bind(conversation_id)
@@ -623,6 +783,32 @@ pub async fn fetch_pinned_messages(
     Ok(messages)
 }
 
+/// Messages épinglés de la conversation DM entre deux utilisateurs
+///
+/// `user1_id` est le demandeur : seuls les deux participants lisent les
+/// épingles. Sans conversation entre eux, la liste est vide.
+pub async fn get_pinned_dm_messages(hub: &ChatHub, user1_id: i64, user2_id: i64) -> Result<Vec<DmMessage>> {
+    reject_guest(user1_id, "get_pinned_dm_messages")?;
+    validate_user_id(user1_id as i32)?;
+    validate_user_id(user2_id as i32)?;
+    
+    let conversation_id: Option<i64> = query("
+        SELECT id FROM dm_conversations
+        WHERE (user1_id = $1 AND user2_id = $2) OR (user1_id = $2 AND user2_id = $1)
+    ")
+    .bind(user1_id)
+    .bind(user2_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("find_existing_dm", e))?
+    .map(|row| row.get("id"));
+    
+    match conversation_id {
+        Some(conversation_id) => fetch_pinned_messages(hub, conversation_id, user1_id).await,
+        None => Ok(Vec::new()),
+    }
+}
+
 // ================================================================
 // STATISTIQUES ET ADMINISTRATION
 // ================================================================
@@ -678,15 +864,20 @@ pub async fn get_dm_stats(
 }
 
 /// Lister les conversations DM d'un utilisateur
+///
+/// Triées par activité récente, donc paginées par décalage : `cursor` est le
+/// `next_cursor` de la page précédente. Le total est toujours renseigné.
 pub async fn list_user_dm_conversations(
     hub: &ChatHub,
     user_id: i64,
-    limit: i64
-) -> Result<Vec<(DmConversation, DmParticipant)>> {
+    limit: i64,
+    cursor: Option<i64>
+) -> Result<Page<(DmConversation, DmParticipant)>> {
     tracing::info!(user_id = %user_id, limit = %limit, "💬 Liste des conversations DM");
     
     validate_user_id(user_id as i32)?;
-    let validated_limit = validate_limit(limit)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
+    let offset = cursor.unwrap_or(0).max(0);
     
     let conversations = query("
         SELECT 
@@ -702,15 +893,24 @@ pub async fn list_user_dm_conversations(
             END
         )
         WHERE dc.user1_id = $1 OR dc.user2_id = $1
-        ORDER BY dc.updated_at DESC
-        LIMIT $2
+        ORDER BY dc.updated_at DESC, dc.id DESC
+        LIMIT $2 OFFSET $3
     ")
     .bind(user_id)
-    .bind(validated_limit)
+    .bind(validated_limit + 1)
+    .bind(offset)
     .fetch_all(&hub.db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("list_dm_conversations", e))?;
     
+    let total: i64 = sqlx::query_scalar("
+        SELECT COUNT(*) FROM dm_conversations WHERE user1_id = $1 OR user2_id = $1
+    ")
+    .bind(user_id)
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("count_dm_conversations", e))?;
+    
     let mut result = Vec::new();
     
     for row in conversations {
@@ -736,7 +936,7 @@ pub async fn list_user_dm_conversations(
     }
     
     tracing::info!(user_id = %user_id, conversation_count = %result.len(), "✅ Conversations DM listées");
-    Ok(result)
+    Ok(Page::from_offset(result, validated_limit, offset).with_total(total))
 }
 
 // ================================================================
@@ -744,13 +944,10 @@ pub async fn list_user_dm_conversations(
 // ================================================================
 
 /// Traiter les mentions dans un message DM
-async fn process_dm_mentions(tx: &mut Transaction<'_, Postgres>, message_id: i64, content: &str) -> Result<()> {
-    use regex::Regex;
-    
-    let mention_regex = Regex::new(r"@(\w+)").unwrap();
-    
-    for cap in mention_regex.captures_iter(content) {
-        let username = &cap[1];
+pub(crate) async fn process_dm_mentions(tx: &mut Transaction<'_, Postgres>, message_id: i64, content: &str) -> Result<()> {
+    // Même analyse que dans les salons ; seules les mentions d'utilisateur ont un sens en DM
+    for mention in parse_mentions(content).into_iter().filter(|m| m.kind == MentionKind::User) {
+        let username = mention.target.as_str();
         
         // Trouver l'ID de l'utilisateur mentionné
         if let Ok(user_row) = query("SELECT id FROM users WHERE username = $1")
@@ -786,33 +983,26 @@ async fn broadcast_dm_message(
     other_user_id: i64,
     username: &str,
     content: &str,
+    full_length: Option<usize>,
     timestamp: DateTime<Utc>,
-    parent_message_id: Option<i64>
+    parent_message_id: Option<i64>,
+    quote: Option<&QuotedExcerpt>
 ) -> Result<()> {
-    let clients = hub.clients.read().await;
-    
-    let payload = json!({
-        "type": "dm_message",
-        "data": {
-            "id": message_id,
-            "conversationId": conversation_id,
-            "authorId": author_id,
-            "username": username,
-            "content": content,
-            "timestamp": timestamp,
-            "parentMessageId": parent_message_id,
-            "isThread": parent_message_id.is_some()
-        }
-    });
+    let payload = MessagePayload::new(message_id, author_id, username, content, timestamp)
+        .in_conversation(conversation_id)
+        .with_parent(parent_message_id)
+        .with_full_length(full_length)
+        .with_quote(quote.map(|q| q.to_payload()))
+        .to_frame("dm_message");
+    hub.bridge_dm_event(conversation_id, &payload).await;
+    let frame = VersionedFrame::new(payload);
     
     let mut successful_sends = 0;
     
     // Envoyer à l'auteur et au destinataire
-    for user_id in [author_id, other_user_id] {
-        if let Some(client) = clients.get(&(user_id as i32)) {
-            if client.send_text(&payload.to_string()) {
-                successful_sends += 1;
-            }
+    for client in hub.clients.get_many([author_id as i32, other_user_id as i32]).await {
+        if client.send_frame(&frame) {
+            successful_sends += 1;
         }
     }
     
@@ -826,6 +1016,28 @@ async fn broadcast_dm_message(
     Ok(())
 }
 
+/// Synchroniser l'état de lecture sur toutes les sessions du lecteur
+pub async fn broadcast_read_state(hub: &ChatHub, user_id: i64, conversation_id: i64, last_read_message_id: i64) -> usize {
+    let payload = json!({
+        "type": "read_state",
+        "data": {
+            "conversationId": conversation_id,
+            "lastReadMessageId": last_read_message_id
+        }
+    });
+    
+    let delivered = hub.send_to_user_sessions(user_id as i32, &payload.to_string()).await;
+    
+    tracing::debug!(
+        user_id = %user_id,
+        conversation_id = %conversation_id,
+        sessions = %delivered,
+        "📡 État de lecture synchronisé"
+    );
+    
+    delivered
+}
+
 /// Diffuser une édition de message DM
 async fn broadcast_dm_message_edit(
     hub: &ChatHub,
@@ -835,8 +1047,6 @@ async fn broadcast_dm_message_edit(
     other_user_id: i64,
     new_content: &str
 ) -> Result<()> {
-    let clients = hub.clients.read().await;
-    
     let payload = json!({
         "type": "dm_message_edited",
         "data": {
@@ -847,15 +1057,14 @@ async fn broadcast_dm_message_edit(
             "timestamp": Utc::now()
         }
     });
+    hub.bridge_dm_event(conversation_id, &payload).await;
     
     let mut successful_sends = 0;
     
     // Envoyer à l'éditeur et à l'autre utilisateur
-    for user_id in [editor_id, other_user_id] {
-        if let Some(client) = clients.get(&(user_id as i32)) {
-            if client.send_text(&payload.to_string()) {
-                successful_sends += 1;
-            }
+    for client in hub.clients.get_many([editor_id as i32, other_user_id as i32]).await {
+        if client.send_text(&payload.to_string()) {
+            successful_sends += 1;
         }
     }
     
@@ -867,4 +1076,60 @@ async fn broadcast_dm_message_edit(
     );
     
     Ok(())
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dm_pin_limit_counts_requested_pin() {
+        assert!(check_dm_pin_limit(5, 5).is_ok());
+        assert!(matches!(check_dm_pin_limit(6, 5), Err(ChatError::OutOfRange { max: 5, .. })));
+    }
+
+    #[test]
+    fn test_dm_pins_frame_shape() {
+        let frame = dm_pins_frame(8, 42, 1, true, &[42, 17]);
+        assert_eq!(frame["type"], "dm_pins_updated");
+        assert_eq!(frame["data"]["conversationId"], 8);
+        assert_eq!(frame["data"]["pinnedBy"], 1);
+        assert_eq!(frame["data"]["messageIds"], json!([42, 17]));
+    }
+
+    #[test]
+    fn test_start_conversation_with_nonexistent_user() {
+        let eligibility = StartEligibility::default();
+        assert!(matches!(eligibility.check(1, 999), Err(ChatError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_dm_privacy_names_round_trip() {
+        for privacy in [DmPrivacy::Everyone, DmPrivacy::SharedRooms, DmPrivacy::Nobody] {
+            assert_eq!(DmPrivacy::from_name(privacy.as_str()), Some(privacy));
+        }
+        assert_eq!(DmPrivacy::from_name("friends"), None);
+    }
+
+    const ALICE: i64 = 1;
+    const BOB: i64 = 2;
+
+    #[test]
+    fn test_blocked_history_keep_mode() {
+        // Alice bloque Bob : rien ne change, pour l'un comme pour l'autre
+        assert!(dm_history_visible(BlockedDmHistory::Keep, Some(ALICE), ALICE));
+        assert!(dm_history_visible(BlockedDmHistory::Keep, Some(ALICE), BOB));
+        assert_eq!(BlockedDmHistory::default(), BlockedDmHistory::Keep);
+    }
+
+    #[test]
+    fn test_blocked_user_cannot_infer_block_from_history() {
+        for mode in [BlockedDmHistory::Keep, BlockedDmHistory::HideForBlocker] {
+            assert_eq!(
+                dm_history_visible(mode, Some(ALICE), BOB),
+                dm_history_visible(mode, None, BOB),
+                "{:?}", mode
+            );
+        }
+    }
+}
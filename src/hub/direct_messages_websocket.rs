@@ -8,9 +8,17 @@
 //! - Édition de messages
 //! - Historique paginé
 
-use crate::hub::{ChatHub, dm_enhanced, reactions, audit};
+use crate::client::Client;
+use crate::hub::dedup::SentMessage;
+use crate::hub::inbound::{connection_user_id, read_frames, ConnectionInbox};
+use crate::hub::{ChatHub, diagnostics, dm_enhanced, reactions, audit, reports, e2ee, mutes};
 use crate::error::{ChatError, Result};
+use crate::message_schema::message_frame;
+use crate::validation::{parse_client_json, UNSPECIFIED_LIMIT};
+use futures_util::Stream;
 use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
 use tracing::{info, warn, error};
 
 // ================================================================
@@ -20,16 +28,25 @@ use tracing::{info, warn, error};
 pub enum DmWebSocketMessage {
     // Gestion des conversations
     CreateConversation { user1_id: i64, user2_id: i64 },
-    BlockConversation { conversation_id: i64, user_id: i64, block: bool },
-    ListConversations { user_id: i64, limit: i64 },
+    BlockConversation { conversation_id: i64, user_id: i64, block: bool, hide_reactions: bool },
+    ListConversations { user_id: i64, limit: i64, cursor: Option<i64> },
+    SetDmPrivacy { user_id: i64, privacy: String },
+    SetDmMuted { conversation_id: i64, user_id: i64, muted: bool },
     
     // Messages
-    SendMessage { conversation_id: i64, user_id: i64, username: String, content: String, parent_id: Option<i64> },
+    SendMessage { conversation_id: i64, user_id: i64, username: String, content: String, parent_id: Option<i64>, nonce: Option<String>, ack: Option<bool> },
     EditMessage { message_id: i64, user_id: i64, new_content: String, edit_reason: Option<String> },
+    MarkRead { conversation_id: i64, user_id: i64, up_to_message_id: Option<i64> },
+    
+    // Chiffrement de bout en bout (contenu opaque pour le serveur)
+    PublishKeyBundle { user_id: i64, bundle: Value },
+    FetchKeyBundle { user_id: i64, target_user_id: i64 },
+    SendEncryptedMessage { conversation_id: i64, user_id: i64, username: String, ciphertext: String, header: Value, ack: Option<bool> },
     
     // Historique et recherche
     GetHistory { conversation_id: i64, user_id: i64, limit: i64, before_id: Option<i64> },
     GetPinnedMessages { conversation_id: i64, user_id: i64 },
+    GetPinnedMessagesWith { user_id: i64, other_user_id: i64 },
     
     // Réactions (utilise le même système que les salons)
     AddReaction { message_id: i64, user_id: i64, emoji: String },
@@ -40,15 +57,36 @@ pub enum DmWebSocketMessage {
     PinMessage { conversation_id: i64, message_id: i64, user_id: i64 },
     UnpinMessage { conversation_id: i64, message_id: i64, user_id: i64 },
     
+    // Signalement
+    ReportMessage { message_id: i64, user_id: i64, reason: String },
+    
     // Administration
     GetDmStats { conversation_id: i64, user_id: i64 },
     GetAuditLogs { conversation_id: i64, user_id: i64, limit: i64 },
+    
+    // Diagnostic
+    PingDiag { user_id: i64, correlation_id: Option<String>, client_time: Option<i64> },
 }
 
 // ================================================================
 // GESTIONNAIRE PRINCIPAL
 // ================================================================
 
+/// Connexion messages directs : lit le socket de `client` jusqu'à sa fermeture
+///
+/// Même file par connexion que les salons (`ConnectionInbox`) ; la session
+/// est retirée du hub à la fin.
+pub async fn serve_dm_connection<S>(hub: Arc<ChatHub>, client: Client, stream: S) -> usize
+where
+    S: Stream<Item = std::result::Result<Message, WsError>> + Unpin,
+{
+    info!(user_id = %client.user_id, "🔌 Connexion DM ouverte");
+    let processed = read_frames(ConnectionInbox::for_dms(hub.clone(), client.clone()), stream).await;
+    hub.unregister(&client).await;
+    info!(user_id = %client.user_id, processed = %processed, "🔌 Connexion DM fermée");
+    processed
+}
+
 pub async fn handle_dm_websocket_message(
     hub: &ChatHub,
     message: DmWebSocketMessage
@@ -59,23 +97,49 @@ pub async fn handle_dm_websocket_message(
             handle_create_conversation(hub, user1_id, user2_id).await
         }
         
-        DmWebSocketMessage::BlockConversation { conversation_id, user_id, block } => {
-            handle_block_conversation(hub, conversation_id, user_id, block).await
+        DmWebSocketMessage::BlockConversation { conversation_id, user_id, block, hide_reactions } => {
+            handle_block_conversation(hub, conversation_id, user_id, block, hide_reactions).await
+        }
+        
+        DmWebSocketMessage::ListConversations { user_id, limit, cursor } => {
+            handle_list_conversations(hub, user_id, limit, cursor).await
         }
         
-        DmWebSocketMessage::ListConversations { user_id, limit } => {
-            handle_list_conversations(hub, user_id, limit).await
+        DmWebSocketMessage::SetDmPrivacy { user_id, privacy } => {
+            handle_set_dm_privacy(hub, user_id, &privacy).await
+        }
+        
+        DmWebSocketMessage::SetDmMuted { conversation_id, user_id, muted } => {
+            handle_set_dm_muted(hub, conversation_id, user_id, muted).await
         }
         
         // Messages
-        DmWebSocketMessage::SendMessage { conversation_id, user_id, username, content, parent_id } => {
-            handle_send_dm_message(hub, conversation_id, user_id, &username, &content, parent_id).await
+        DmWebSocketMessage::SendMessage { conversation_id, user_id, username, content, parent_id, nonce, ack } => {
+            handle_send_dm_message(hub, conversation_id, user_id, &username, &content, parent_id, nonce, ack).await
         }
         
         DmWebSocketMessage::EditMessage { message_id, user_id, new_content, edit_reason } => {
             handle_edit_dm_message(hub, message_id, user_id, &new_content, edit_reason.as_deref()).await
         }
         
+        DmWebSocketMessage::MarkRead { conversation_id, user_id, up_to_message_id } => {
+            handle_mark_dm_read(hub, conversation_id, user_id, up_to_message_id).await
+        }
+        
+        // Chiffrement de bout en bout
+        DmWebSocketMessage::PublishKeyBundle { user_id, bundle } => {
+            handle_publish_key_bundle(hub, user_id, bundle).await
+        }
+        
+        DmWebSocketMessage::FetchKeyBundle { user_id, target_user_id } => {
+            handle_fetch_key_bundle(hub, user_id, target_user_id).await
+        }
+        
+        DmWebSocketMessage::SendEncryptedMessage { conversation_id, user_id, username, ciphertext, header, ack } => {
+            let envelope = e2ee::EncryptedEnvelope { ciphertext, header };
+            handle_send_encrypted_dm(hub, conversation_id, user_id, &username, &envelope, ack).await
+        }
+        
         // Historique
         DmWebSocketMessage::GetHistory { conversation_id, user_id, limit, before_id } => {
             handle_get_dm_history(hub, conversation_id, user_id, limit, before_id).await
@@ -85,6 +149,10 @@ pub async fn handle_dm_websocket_message(
             handle_get_pinned_dm_messages(hub, conversation_id, user_id).await
         }
         
+        DmWebSocketMessage::GetPinnedMessagesWith { user_id, other_user_id } => {
+            handle_get_pinned_dm_messages_with(hub, user_id, other_user_id).await
+        }
+        
         // Réactions (réutilise le système des salons)
         DmWebSocketMessage::AddReaction { message_id, user_id, emoji } => {
             handle_add_dm_reaction(hub, message_id, user_id, &emoji).await
@@ -107,6 +175,11 @@ pub async fn handle_dm_websocket_message(
             handle_pin_dm_message(hub, conversation_id, message_id, user_id, false).await
         }
         
+        // Signalement
+        DmWebSocketMessage::ReportMessage { message_id, user_id, reason } => {
+            handle_report_dm_message(hub, message_id, user_id, &reason).await
+        }
+        
         // Administration
         DmWebSocketMessage::GetDmStats { conversation_id, user_id } => {
             handle_get_dm_stats(hub, conversation_id, user_id).await
@@ -115,6 +188,11 @@ pub async fn handle_dm_websocket_message(
         DmWebSocketMessage::GetAuditLogs { conversation_id, user_id, limit } => {
             handle_get_dm_audit_logs(hub, conversation_id, user_id, limit).await
         }
+        
+        // Diagnostic
+        DmWebSocketMessage::PingDiag { user_id, correlation_id, client_time } => {
+            handle_ping_diag(hub, user_id, correlation_id.as_deref(), client_time).await
+        }
     }
 }
 
@@ -149,11 +227,11 @@ async fn handle_create_conversation(hub: &ChatHub, user1_id: i64, user2_id: i64)
     }
 }
 
-async fn handle_block_conversation(hub: &ChatHub, conversation_id: i64, user_id: i64, block: bool) -> Result<Option<String>> {
+async fn handle_block_conversation(hub: &ChatHub, conversation_id: i64, user_id: i64, block: bool, hide_reactions: bool) -> Result<Option<String>> {
     let action_text = if block { "blocage" } else { "déblocage" };
     info!(conversation_id = %conversation_id, user_id = %user_id, block = %block, "🚫 {} de conversation DM", action_text);
     
-    match dm_enhanced::block_dm_conversation(hub, conversation_id, user_id, block).await {
+    match dm_enhanced::block_dm_conversation(hub, conversation_id, user_id, block, hide_reactions).await {
         Ok(()) => {
             info!(conversation_id = %conversation_id, block = %block, "✅ Statut de blocage mis à jour");
             Ok(Some(json!({
@@ -161,6 +239,7 @@ async fn handle_block_conversation(hub: &ChatHub, conversation_id: i64, user_id:
                 "data": {
                     "conversationId": conversation_id,
                     "isBlocked": block,
+                    "hideReactions": block && hide_reactions,
                     "success": true
                 }
             }).to_string()))
@@ -178,17 +257,70 @@ async fn handle_block_conversation(hub: &ChatHub, conversation_id: i64, user_id:
     }
 }
 
-async fn handle_list_conversations(hub: &ChatHub, user_id: i64, limit: i64) -> Result<Option<String>> {
+async fn handle_set_dm_privacy(hub: &ChatHub, user_id: i64, privacy: &str) -> Result<Option<String>> {
+    info!(user_id = %user_id, privacy = %privacy, "🔒 Changement de confidentialité DM");
+    
+    let result = match dm_enhanced::DmPrivacy::from_name(privacy) {
+        Some(privacy) => dm_enhanced::set_dm_privacy(hub, user_id, privacy).await.map(|_| privacy),
+        None => Err(ChatError::InvalidFormat {
+            field: "privacy".to_string(),
+            reason: "Valeurs acceptées : everyone, shared_rooms, nobody".to_string(),
+        }),
+    };
+    
+    match result {
+        Ok(privacy) => {
+            Ok(Some(json!({
+                "type": "dm_privacy_updated",
+                "data": {
+                    "userId": user_id,
+                    "privacy": privacy.as_str(),
+                    "success": true
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec du changement de confidentialité DM");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_dm_privacy",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_set_dm_muted(hub: &ChatHub, conversation_id: i64, user_id: i64, muted: bool) -> Result<Option<String>> {
+    match mutes::set_dm_muted(hub, conversation_id, user_id, muted).await {
+        Ok(()) => Ok(Some(mutes::mute_state_frame("dm", conversation_id, muted).to_string())),
+        Err(e) => {
+            warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec de la mise en sourdine de la conversation DM");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "set_dm_muted",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_list_conversations(hub: &ChatHub, user_id: i64, limit: i64, cursor: Option<i64>) -> Result<Option<String>> {
     info!(user_id = %user_id, limit = %limit, "📋 Liste des conversations DM");
     
-    match dm_enhanced::list_user_dm_conversations(hub, user_id, limit).await {
-        Ok(conversations) => {
-            info!(user_id = %user_id, conversation_count = %conversations.len(), "✅ Conversations DM listées");
+    match dm_enhanced::list_user_dm_conversations(hub, user_id, limit, cursor).await {
+        Ok(page) => {
+            info!(user_id = %user_id, conversation_count = %page.len(), "✅ Conversations DM listées");
             Ok(Some(json!({
                 "type": "dm_conversations_list",
                 "data": {
-                    "conversations": conversations,
-                    "total": conversations.len()
+                    "conversations": page.items,
+                    "total": page.total,
+                    "hasMore": page.has_more,
+                    "nextCursor": page.next_cursor
                 }
             }).to_string()))
         }
@@ -205,27 +337,45 @@ async fn handle_list_conversations(hub: &ChatHub, user_id: i64, limit: i64) -> R
     }
 }
 
+/// Accusé `dm_message_sent` complété de l'issue de l'envoi
+///
+/// Un message écarté par la confidentialité du destinataire est accepté mais
+/// signalé non remis (`delivered: false`), sans identifiant ni horodatage.
+fn dm_sent_ack(mut data: Value, sent: Option<SentMessage>) -> Value {
+    match sent {
+        Some(sent) => {
+            data["messageId"] = json!(sent.id);
+            data["timestamp"] = json!(sent.created_at);
+            data["delivered"] = json!(true);
+        }
+        None => data["delivered"] = json!(false),
+    }
+    data["success"] = json!(true);
+    json!({ "type": "dm_message_sent", "data": data })
+}
+
 async fn handle_send_dm_message(
     hub: &ChatHub,
     conversation_id: i64,
     user_id: i64,
     username: &str,
     content: &str,
-    parent_id: Option<i64>
+    parent_id: Option<i64>,
+    nonce: Option<String>,
+    ack: Option<bool>
 ) -> Result<Option<String>> {
     info!(conversation_id = %conversation_id, user_id = %user_id, content_length = %content.len(), "📝 Envoi de message DM enrichi");
     
-    match dm_enhanced::send_dm_message(hub, conversation_id, user_id, username, content, parent_id, None).await {
-        Ok(message_id) => {
-            info!(conversation_id = %conversation_id, message_id = %message_id, "✅ Message DM enrichi envoyé");
-            Ok(Some(json!({
-                "type": "dm_message_sent",
-                "data": {
-                    "messageId": message_id,
-                    "conversationId": conversation_id,
-                    "success": true
-                }
-            }).to_string()))
+    let metadata = nonce.as_ref().map(|nonce| json!({ "nonce": nonce }));
+    let ack_mode = hub.ack_mode_of(user_id as i32).await.for_command(ack);
+    
+    match dm_enhanced::send_dm_message(hub, conversation_id, user_id, username, content, parent_id, metadata).await {
+        Ok(sent) => {
+            info!(conversation_id = %conversation_id, message_id = ?sent.map(|sent| sent.id), "✅ Message DM enrichi envoyé");
+            Ok(ack_mode.ack(dm_sent_ack(json!({
+                "conversationId": conversation_id,
+                "nonce": nonce
+            }), sent)))
         }
         Err(e) => {
             warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec d'envoi de message DM");
@@ -233,7 +383,8 @@ async fn handle_send_dm_message(
                 "type": "error",
                 "data": {
                     "action": "send_dm_message",
-                    "error": e.to_string()
+                    "error": e.to_string(),
+                    "retryAfter": e.retry_after()
                 }
             }).to_string()))
         }
@@ -275,6 +426,115 @@ async fn handle_edit_dm_message(
     }
 }
 
+async fn handle_mark_dm_read(hub: &ChatHub, conversation_id: i64, user_id: i64, up_to_message_id: Option<i64>) -> Result<Option<String>> {
+    match dm_enhanced::mark_dm_read(hub, conversation_id, user_id, up_to_message_id).await {
+        Ok(updated_count) => Ok(Some(json!({
+            "type": "dm_marked_read",
+            "data": {
+                "conversationId": conversation_id,
+                "updatedCount": updated_count
+            }
+        }).to_string())),
+        Err(e) => {
+            warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec du marquage comme lu");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "mark_dm_read",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_publish_key_bundle(hub: &ChatHub, user_id: i64, bundle: Value) -> Result<Option<String>> {
+    info!(user_id = %user_id, "🔑 Publication de clés de chiffrement");
+    
+    let result = match serde_json::from_value::<e2ee::KeyBundleUpload>(bundle) {
+        Ok(upload) => e2ee::publish_key_bundle(hub, user_id, &upload).await,
+        Err(e) => Err(ChatError::InvalidFormat { field: "bundle".to_string(), reason: e.to_string() }),
+    };
+    
+    match result {
+        Ok(remaining) => {
+            Ok(Some(json!({
+                "type": "key_bundle_published",
+                "data": {
+                    "userId": user_id,
+                    "oneTimePrekeys": remaining,
+                    "success": true
+                }
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(user_id = %user_id, error = %e, "❌ Échec de publication des clés");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "publish_key_bundle",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_fetch_key_bundle(hub: &ChatHub, user_id: i64, target_user_id: i64) -> Result<Option<String>> {
+    info!(user_id = %user_id, target_user_id = %target_user_id, "🔑 Récupération de clés de chiffrement");
+    
+    match e2ee::fetch_key_bundle(hub, user_id, target_user_id).await {
+        Ok(bundle) => {
+            Ok(Some(json!({
+                "type": "key_bundle",
+                "data": bundle
+            }).to_string()))
+        }
+        Err(e) => {
+            warn!(user_id = %user_id, target_user_id = %target_user_id, error = %e, "❌ Échec de récupération des clés");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "fetch_key_bundle",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_send_encrypted_dm(
+    hub: &ChatHub,
+    conversation_id: i64,
+    user_id: i64,
+    username: &str,
+    envelope: &e2ee::EncryptedEnvelope,
+    ack: Option<bool>
+) -> Result<Option<String>> {
+    let ack_mode = hub.ack_mode_of(user_id as i32).await.for_command(ack);
+    
+    match e2ee::send_encrypted_dm(hub, conversation_id, user_id, username, envelope).await {
+        // Même accusé qu'un message en clair, y compris lorsqu'il est écarté
+        Ok(sent) => {
+            Ok(ack_mode.ack(dm_sent_ack(json!({
+                "conversationId": conversation_id,
+                "encrypted": true
+            }), sent)))
+        }
+        Err(e) => {
+            warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec d'envoi de message DM chiffré");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "send_encrypted_dm",
+                    "error": e.to_string(),
+                    "retryAfter": e.retry_after()
+                }
+            }).to_string()))
+        }
+    }
+}
+
 async fn handle_get_dm_history(
     hub: &ChatHub,
     conversation_id: i64,
@@ -285,16 +545,15 @@ async fn handle_get_dm_history(
     info!(conversation_id = %conversation_id, user_id = %user_id, limit = %limit, "📚 Récupération de l'historique DM enrichi");
     
     match dm_enhanced::fetch_dm_history(hub, conversation_id, user_id, limit, before_id).await {
-        Ok(messages) => {
-            info!(conversation_id = %conversation_id, message_count = %messages.len(), "✅ Historique DM enrichi récupéré");
-            Ok(Some(json!({
-                "type": "dm_history",
-                "data": {
-                    "conversationId": conversation_id,
-                    "messages": messages,
-                    "hasMore": messages.len() as i64 == limit
-                }
-            }).to_string()))
+        Ok(page) => {
+            info!(conversation_id = %conversation_id, message_count = %page.len(), "✅ Historique DM enrichi récupéré");
+            let frame = message_frame("dm_history", json!({
+                "conversationId": conversation_id,
+                "messages": page.items,
+                "hasMore": page.has_more,
+                "nextCursor": page.next_cursor
+            }));
+            Ok(Some(hub.render_frame_for(user_id, &frame).await))
         }
         Err(e) => {
             warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec de récupération de l'historique DM");
@@ -315,16 +574,38 @@ async fn handle_get_pinned_dm_messages(hub: &ChatHub, conversation_id: i64, user
     match dm_enhanced::fetch_pinned_dm_messages(hub, conversation_id, user_id).await {
         Ok(messages) => {
             info!(conversation_id = %conversation_id, pinned_count = %messages.len(), "✅ Messages DM épinglés récupérés");
+            let frame = message_frame("dm_pinned_messages", json!({
+                "conversationId": conversation_id,
+                "messages": messages
+            }));
+            Ok(Some(hub.render_frame_for(user_id, &frame).await))
+        }
+        Err(e) => {
+            warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec de récupération des messages DM épinglés");
             Ok(Some(json!({
-                "type": "dm_pinned_messages",
+                "type": "error",
                 "data": {
-                    "conversationId": conversation_id,
-                    "messages": messages
+                    "action": "get_pinned_dm_messages",
+                    "error": e.to_string()
                 }
             }).to_string()))
         }
+    }
+}
+
+async fn handle_get_pinned_dm_messages_with(hub: &ChatHub, user_id: i64, other_user_id: i64) -> Result<Option<String>> {
+    info!(user_id = %user_id, other_user_id = %other_user_id, "📌 Récupération des messages DM épinglés");
+    
+    match dm_enhanced::get_pinned_dm_messages(hub, user_id, other_user_id).await {
+        Ok(messages) => {
+            let frame = message_frame("dm_pinned_messages", json!({
+                "otherUserId": other_user_id,
+                "messages": messages
+            }));
+            Ok(Some(hub.render_frame_for(user_id, &frame).await))
+        }
         Err(e) => {
-            warn!(conversation_id = %conversation_id, user_id = %user_id, error = %e, "❌ Échec de récupération des messages DM épinglés");
+            warn!(user_id = %user_id, other_user_id = %other_user_id, error = %e, "❌ Échec de récupération des messages DM épinglés");
             Ok(Some(json!({
                 "type": "error",
                 "data": {
@@ -501,106 +782,242 @@ async fn handle_get_dm_audit_logs(hub: &ChatHub, conversation_id: i64, user_id:
     }
 }
 
+async fn handle_report_dm_message(hub: &ChatHub, message_id: i64, user_id: i64, reason: &str) -> Result<Option<String>> {
+    info!(message_id = %message_id, user_id = %user_id, "🚩 Signalement de message");
+    
+    match reports::report_message(hub, message_id, user_id, reason).await {
+        Ok(outcome) => Ok(Some(json!({
+            "type": "message_reported",
+            "data": outcome
+        }).to_string())),
+        Err(e) => {
+            warn!(message_id = %message_id, user_id = %user_id, error = %e, "❌ Échec du signalement");
+            Ok(Some(json!({
+                "type": "error",
+                "data": {
+                    "action": "report_dm_message",
+                    "error": e.to_string()
+                }
+            }).to_string()))
+        }
+    }
+}
+
+async fn handle_ping_diag(hub: &ChatHub, user_id: i64, correlation_id: Option<&str>, client_time: Option<i64>) -> Result<Option<String>> {
+    match diagnostics::ping_diag(hub, user_id, correlation_id, client_time).await {
+        Ok(reply) => Ok(Some(reply.to_string())),
+        Err(e) => Ok(Some(json!({
+            "type": "error",
+            "data": {
+                "action": "ping_diag",
+                "correlationId": correlation_id,
+                "error": e.to_string()
+            }
+        }).to_string())),
+    }
+}
+
 // ================================================================
 // UTILITAIRES DE PARSING
 // ================================================================
 
 /// Parser un message JSON WebSocket en DmWebSocketMessage
-pub fn parse_dm_websocket_message(message: &str) -> Result<DmWebSocketMessage> {
-    let value: Value = serde_json::from_str(message)
-        .map_err(|e| ChatError::configuration_error(&format!("JSON invalide: {}", e)))?;
+///
+/// La trame agit au nom de `caller`, le client authentifié de la connexion
+/// (voir `connection_user_id`).
+pub fn parse_dm_websocket_message(message: &str, caller: &Client) -> Result<DmWebSocketMessage> {
+    let value: Value = parse_client_json(message)?;
     
     let msg_type = value.get("type")
         .and_then(|v| v.as_str())
         .ok_or_else(|| ChatError::configuration_error("Type de message manquant"))?;
     
+    // Diagnostic : `data` facultatif, champs acceptés à la racine du message
+    if msg_type == "ping_diag" {
+        let data = value.get("data").unwrap_or(&value);
+        return Ok(DmWebSocketMessage::PingDiag {
+            // Limite de débit propre à la connexion, jamais partagée ni contournable
+            user_id: connection_user_id(data, caller)?,
+            correlation_id: diagnostics::correlation_id_from(data.get("correlationId")),
+            client_time: data.get("clientTime").and_then(|v| v.as_i64()),
+        });
+    }
+    
     let data = value.get("data")
         .ok_or_else(|| ChatError::configuration_error("Données du message manquantes"))?;
+    let user_id = connection_user_id(data, caller)?;
     
     match msg_type {
-        "create_dm_conversation" => Ok(DmWebSocketMessage::CreateConversation {
-            user1_id: data.get("user1Id").and_then(|v| v.as_i64()).unwrap_or(0),
-            user2_id: data.get("user2Id").and_then(|v| v.as_i64()).unwrap_or(0),
-        }),
+        "create_dm_conversation" => {
+            // La connexion est toujours l'un des deux participants
+            let user1_id = data.get("user1Id").and_then(|v| v.as_i64());
+            let user2_id = data.get("user2Id").and_then(|v| v.as_i64()).unwrap_or(0);
+            let other_user_id = match user1_id {
+                None => user2_id,
+                Some(id) if id == user_id => user2_id,
+                Some(id) if user2_id == user_id => id,
+                Some(_) => return Err(ChatError::unauthorized("user_id_mismatch")),
+            };
+            Ok(DmWebSocketMessage::CreateConversation { user1_id: user_id, user2_id: other_user_id })
+        }
         
         "block_dm_conversation" => Ok(DmWebSocketMessage::BlockConversation {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
             block: data.get("block").and_then(|v| v.as_bool()).unwrap_or(true),
+            hide_reactions: data.get("hideReactions").and_then(|v| v.as_bool()).unwrap_or(false),
         }),
         
         "list_dm_conversations" => Ok(DmWebSocketMessage::ListConversations {
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(50),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            cursor: data.get("cursor").and_then(|v| v.as_i64()),
+        }),
+        
+        "set_dm_privacy" => Ok(DmWebSocketMessage::SetDmPrivacy {
+            user_id: user_id,
+            privacy: data.get("privacy").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }),
+        
+        "set_dm_muted" => Ok(DmWebSocketMessage::SetDmMuted {
+            conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            muted: data.get("muted").and_then(|v| v.as_bool()).unwrap_or(true),
         }),
         
         "send_dm_message" => Ok(DmWebSocketMessage::SendMessage {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            username: data.get("username").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            user_id: user_id,
+            username: caller.username.clone(),
             content: data.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
             parent_id: data.get("parentId").and_then(|v| v.as_i64()),
+            nonce: data.get("nonce").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            ack: data.get("ack").and_then(|v| v.as_bool()),
         }),
         
         "edit_dm_message" => Ok(DmWebSocketMessage::EditMessage {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
             new_content: data.get("newContent").and_then(|v| v.as_str()).unwrap_or("").to_string(),
             edit_reason: data.get("editReason").and_then(|v| v.as_str()).map(|s| s.to_string()),
         }),
         
-        "get_dm_history" => Ok(DmWebSocketMessage::GetHistory {
+        "publish_key_bundle" => Ok(DmWebSocketMessage::PublishKeyBundle {
+            user_id: user_id,
+            bundle: data.get("bundle").cloned().unwrap_or(Value::Null),
+        }),
+        
+        "fetch_key_bundle" => Ok(DmWebSocketMessage::FetchKeyBundle {
+            user_id: user_id,
+            target_user_id: data.get("targetUserId").and_then(|v| v.as_i64()).unwrap_or(0),
+        }),
+        
+        "send_encrypted_dm" => Ok(DmWebSocketMessage::SendEncryptedMessage {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(50),
-            before_id: data.get("beforeId").and_then(|v| v.as_i64()),
+            user_id: user_id,
+            username: caller.username.clone(),
+            ciphertext: data.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+            header: data.get("header").cloned().unwrap_or(Value::Null),
+            ack: data.get("ack").and_then(|v| v.as_bool()),
         }),
         
-        "get_pinned_dm_messages" => Ok(DmWebSocketMessage::GetPinnedMessages {
+        "get_dm_history" => Ok(DmWebSocketMessage::GetHistory {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
+            before_id: data.get("beforeId").and_then(|v| v.as_i64()),
         }),
         
+        // `otherUserId` : épingles de la conversation avec cet utilisateur
+        "get_pinned_dm_messages" => match data.get("otherUserId").and_then(|v| v.as_i64()) {
+            Some(other_user_id) => Ok(DmWebSocketMessage::GetPinnedMessagesWith {
+                user_id: user_id,
+                other_user_id,
+            }),
+            None => Ok(DmWebSocketMessage::GetPinnedMessages {
+                conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
+                user_id: user_id,
+            }),
+        },
+        
         "add_dm_reaction" => Ok(DmWebSocketMessage::AddReaction {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
             emoji: data.get("emoji").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
         
         "remove_dm_reaction" => Ok(DmWebSocketMessage::RemoveReaction {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
             emoji: data.get("emoji").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
         
+        "mark_dm_read" => Ok(DmWebSocketMessage::MarkRead {
+            conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            up_to_message_id: data.get("messageId").and_then(|v| v.as_i64()),
+        }),
+        
         "get_dm_reactions" => Ok(DmWebSocketMessage::GetReactions {
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
         }),
         
         "pin_dm_message" => Ok(DmWebSocketMessage::PinMessage {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
         }),
         
         "unpin_dm_message" => Ok(DmWebSocketMessage::UnpinMessage {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
             message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+        }),
+        
+        "report_dm_message" => Ok(DmWebSocketMessage::ReportMessage {
+            message_id: data.get("messageId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
+            reason: data.get("reason").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         }),
         
         "get_dm_stats" => Ok(DmWebSocketMessage::GetDmStats {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
+            user_id: user_id,
         }),
         
         "get_dm_audit_logs" => Ok(DmWebSocketMessage::GetAuditLogs {
             conversation_id: data.get("conversationId").and_then(|v| v.as_i64()).unwrap_or(0),
-            user_id: data.get("userId").and_then(|v| v.as_i64()).unwrap_or(0),
-            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(50),
+            user_id: user_id,
+            limit: data.get("limit").and_then(|v| v.as_i64()).unwrap_or(UNSPECIFIED_LIMIT),
         }),
         
         _ => Err(ChatError::configuration_error(&format!("Type de message DM non supporté: {}", msg_type)))
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_dm_sent_ack_reports_delivery() {
+        let created_at = Utc::now();
+        let ack = dm_sent_ack(json!({ "conversationId": 7 }), Some(SentMessage { id: 42, created_at }));
+        assert_eq!(ack["type"], "dm_message_sent");
+        assert_eq!(ack["data"]["messageId"], 42);
+        assert_eq!(ack["data"]["timestamp"], json!(created_at));
+        assert_eq!(ack["data"]["delivered"], true);
+        assert_eq!(ack["data"]["conversationId"], 7);
+    }
+
+    #[test]
+    fn test_dm_sent_ack_withheld_message_is_not_delivered() {
+        let ack = dm_sent_ack(json!({ "conversationId": 7, "encrypted": true }), None);
+        assert_eq!(ack["data"]["delivered"], false);
+        assert_eq!(ack["data"]["success"], true);
+        assert!(ack["data"].get("messageId").is_none());
+        assert!(ack["data"].get("timestamp").is_none());
+        assert_eq!(ack["data"]["encrypted"], true);
+    }
+}
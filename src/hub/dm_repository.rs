@@ -0,0 +1,511 @@
+//! Dépôt des conversations privées
+//!
+//! Conversations DM, leurs messages et leurs blocages, ainsi que ce qui lie
+//! deux utilisateurs hors des salons (confidentialité des DM, présence
+//! suivie, clés E2EE) :
+//! - `PgDmRepository` : PostgreSQL
+//! - `testing::InMemoryDmRepository` (feature `testing`) : mémoire
+
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use sqlx::{query, query_as, PgPool, Row};
+use uuid::Uuid;
+use crate::config::BlockedDmHistory;
+use crate::encryption::MessageCipher;
+use crate::error::{ChatError, Result};
+use crate::hub::audit_sink::AuditSink;
+use crate::hub::custom_emojis::annotate_custom_emojis;
+use crate::hub::dedup::{self, DedupKey, SentMessage};
+use crate::hub::direct_messages::{dm_allowed, process_dm_mentions, DmConversation, DmMessage, DmParticipant, StartEligibility};
+use crate::hub::e2ee::{KeyBundle, OneTimePrekey};
+use crate::hub::long_messages::{store_message_body, PreparedContent};
+use crate::hub::presence_subscriptions::PresenceRelation;
+use crate::hub::quotes::{attach_quote, QuotedExcerpt};
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Message direct prêt à être stocké
+pub struct NewDmMessage<'a> {
+    pub conversation_id: i64,
+    pub author_id: i64,
+    pub content: &'a PreparedContent,
+    /// Contenu complet, source des mentions et des émojis serveur
+    pub source: &'a str,
+    pub parent_message_id: Option<i64>,
+    pub metadata: Value,
+    pub dedup_key: Option<DedupKey>,
+}
+
+/// Message direct stocké
+#[derive(Debug, Clone)]
+pub struct InsertedDmMessage {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// Extrait cité du parent, validé
+    pub quote: Option<QuotedExcerpt>,
+    /// Renvoi simultané : message déjà stocké sous ce nonce, rien n'a été écrit
+    pub duplicate: bool,
+}
+
+// ================================================================
+// TRAIT
+// ================================================================
+
+/// Accès aux données des conversations privées
+pub trait DmRepository: Send + Sync {
+    /// Lien de `viewer_id` à `user_id` (contact, salon commun, blocage), pour suivre sa présence
+    fn presence_relation<'a>(&'a self, viewer_id: i64, user_id: i64) -> BoxFuture<'a, Result<PresenceRelation>>;
+
+    /// Clés publiées par `target_id` et stock restant de clés à usage unique
+    ///
+    /// Pour un correspondant, une clé à usage unique est retirée du stock et
+    /// remise ; ses propres clés sont remises sans en consommer. `None` : rien
+    /// de publié, ou confidentialité du destinataire refusant le demandeur.
+    fn claim_key_bundle<'a>(&'a self, requester_id: i64, target_id: i64) -> BoxFuture<'a, Result<Option<(KeyBundle, i64)>>>;
+
+    /// Profil de la cible d'une nouvelle conversation DM et blocages entre les deux
+    ///
+    /// `None` : utilisateur inconnu ou banni.
+    fn dm_target<'a>(&'a self, user_id: i64, target_id: i64) -> BoxFuture<'a, Result<Option<(DmParticipant, StartEligibility)>>>;
+
+    /// Conversation DM entre les deux utilisateurs, créée et auditée si elle n'existe pas
+    fn open_dm_conversation<'a>(&'a self, user1_id: i64, user2_id: i64, audit: &'a AuditSink) -> BoxFuture<'a, Result<DmConversation>>;
+
+    /// Conversation DM dont `user_id` est l'un des participants
+    fn dm_conversation<'a>(&'a self, conversation_id: i64, user_id: i64) -> BoxFuture<'a, Result<Option<DmConversation>>>;
+
+    /// Politique de confidentialité du destinataire satisfaite par l'expéditeur
+    /// (`direct_messages::dm_allowed`)
+    fn dm_allowed<'a>(&'a self, sender_id: i64, recipient_id: i64) -> BoxFuture<'a, Result<bool>>;
+
+    /// Stocke un message direct, après les vérifications de la conversation
+    ///
+    /// Nonce déjà stocké (renvoi simultané) : retourne le message existant,
+    /// marqué `duplicate`.
+    fn insert_dm_message<'a>(&'a self, message: NewDmMessage<'a>, cipher: Option<&'a MessageCipher>) -> BoxFuture<'a, Result<InsertedDmMessage>>;
+
+    /// Messages de la conversation DM, du plus récent au plus ancien, avant
+    /// `before_message_id`, au plus `limit`
+    fn dm_history_page<'a>(&'a self, conversation_id: i64, before_message_id: Option<i64>, limit: i64) -> BoxFuture<'a, Result<Vec<DmMessage>>>;
+
+    /// Bloque ou débloque la conversation DM au nom de `user_id` (participant)
+    ///
+    /// `hide_reactions` n'est retenu qu'au blocage ; `history` règle le sort
+    /// des messages existants (`security.blocked_dm_history`).
+    fn set_dm_block<'a>(
+        &'a self,
+        conversation_id: i64,
+        user_id: i64,
+        block: bool,
+        hide_reactions: bool,
+        history: BlockedDmHistory,
+        audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Message direct déjà stocké pour cette clé dans la fenêtre, s'il existe
+    /// (`dedup::find_duplicate`)
+    fn find_duplicate<'a>(&'a self, key: &'a DedupKey, window: Duration) -> BoxFuture<'a, Result<Option<SentMessage>>>;
+}
+
+// ================================================================
+// POSTGRESQL
+// ================================================================
+
+/// Dépôt PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgDmRepository {
+    db: PgPool,
+}
+
+impl PgDmRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+impl DmRepository for PgDmRepository {
+    fn presence_relation<'a>(&'a self, viewer_id: i64, user_id: i64) -> BoxFuture<'a, Result<PresenceRelation>> {
+        Box::pin(async move {
+            let row = query("
+                SELECT
+                    EXISTS(
+                        SELECT 1 FROM dm_conversations
+                        WHERE user1_id = LEAST($1, $2) AND user2_id = GREATEST($1, $2)
+                    ) as contact,
+                    EXISTS(
+                        SELECT 1 FROM conversation_members a
+                        JOIN conversation_members b ON b.conversation_id = a.conversation_id
+                        WHERE a.user_id = $1 AND b.user_id = $2
+                          AND a.left_at IS NULL AND b.left_at IS NULL
+                    ) as shares_room,
+                    EXISTS(
+                        SELECT 1 FROM user_blocks
+                        WHERE (blocker_id = $1 AND blocked_id = $2) OR (blocker_id = $2 AND blocked_id = $1)
+                    ) OR EXISTS(
+                        SELECT 1 FROM dm_conversations
+                        WHERE user1_id = LEAST($1, $2) AND user2_id = GREATEST($1, $2) AND is_blocked = TRUE
+                    ) as blocked
+            ")
+            .bind(viewer_id)
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("presence_relation", e))?;
+
+            Ok(PresenceRelation {
+                contact: row.get("contact"),
+                shares_room: row.get("shares_room"),
+                blocked: row.get("blocked"),
+            })
+        })
+    }
+
+    fn claim_key_bundle<'a>(&'a self, requester_id: i64, target_id: i64) -> BoxFuture<'a, Result<Option<(KeyBundle, i64)>>> {
+        Box::pin(async move {
+            let own = requester_id == target_id;
+            if !own && !dm_allowed(&self.db, requester_id, target_id).await? {
+                return Ok(None);
+            }
+
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let Some(bundle) = query("
+                SELECT identity_key, signed_prekey_id, signed_prekey, signed_prekey_signature
+                FROM e2ee_key_bundles
+                WHERE user_id = $1
+            ")
+            .bind(target_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("load_key_bundle", e))? else {
+                return Ok(None);
+            };
+
+            // Une clé à usage unique n'est remise qu'une fois, même à deux demandeurs simultanés
+            let one_time_prekey = if own {
+                None
+            } else {
+                query("
+                    DELETE FROM e2ee_one_time_prekeys
+                    WHERE (user_id, key_id) = (
+                        SELECT user_id, key_id FROM e2ee_one_time_prekeys
+                        WHERE user_id = $1
+                        ORDER BY key_id
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    RETURNING key_id, public_key
+                ")
+                .bind(target_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("claim_one_time_prekey", e))?
+                .map(|row| OneTimePrekey { key_id: row.get("key_id"), public_key: row.get("public_key") })
+            };
+
+            let remaining: i64 = query("SELECT COUNT(*) FROM e2ee_one_time_prekeys WHERE user_id = $1")
+                .bind(target_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("count_one_time_prekeys", e))?
+                .get(0);
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(Some((KeyBundle {
+                user_id: target_id,
+                identity_key: bundle.get("identity_key"),
+                signed_prekey_id: bundle.get("signed_prekey_id"),
+                signed_prekey: bundle.get("signed_prekey"),
+                signed_prekey_signature: bundle.get("signed_prekey_signature"),
+                one_time_prekey,
+            }, remaining)))
+        })
+    }
+
+    fn dm_target<'a>(&'a self, user_id: i64, target_id: i64) -> BoxFuture<'a, Result<Option<(DmParticipant, StartEligibility)>>> {
+        Box::pin(async move {
+            let target = query("
+                SELECT
+                    u.id, u.username, u.is_online, u.last_activity as last_seen,
+                    EXISTS(SELECT 1 FROM user_blocks WHERE blocker_id = u.id AND blocked_id = $1) as blocked_by_target,
+                    EXISTS(SELECT 1 FROM user_blocks WHERE blocker_id = $1 AND blocked_id = u.id) as blocked_by_user,
+                    EXISTS(
+                        SELECT 1 FROM dm_conversations
+                        WHERE user1_id = LEAST($1, u.id) AND user2_id = GREATEST($1, u.id) AND is_blocked = TRUE
+                    ) as conversation_blocked
+                FROM users u
+                WHERE u.id = $2 AND u.role != 'banned'
+            ")
+            .bind(user_id)
+            .bind(target_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_dm_target", e))?;
+
+            Ok(target.map(|row| (
+                DmParticipant {
+                    user_id: row.get("id"),
+                    username: row.get("username"),
+                    is_online: row.get("is_online"),
+                    last_seen: row.get("last_seen"),
+                },
+                StartEligibility {
+                    target_exists: true,
+                    blocked_by_target: row.get("blocked_by_target"),
+                    blocked_by_user: row.get("blocked_by_user"),
+                    conversation_blocked: row.get("conversation_blocked"),
+                },
+            )))
+        })
+    }
+
+    fn open_dm_conversation<'a>(&'a self, user1_id: i64, user2_id: i64, audit: &'a AuditSink) -> BoxFuture<'a, Result<DmConversation>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Chercher une conversation existante (dans les deux sens)
+            let existing = query_as::<_, DmConversation>("
+                SELECT id, uuid, user1_id, user2_id, is_blocked, blocked_by, created_at, updated_at
+                FROM dm_conversations
+                WHERE (user1_id = $1 AND user2_id = $2) OR (user1_id = $2 AND user2_id = $1)
+            ")
+            .bind(user1_id)
+            .bind(user2_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("find_existing_dm", e))?;
+
+            if let Some(conversation) = existing {
+                tx.commit().await
+                    .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+                return Ok(conversation);
+            }
+
+            // Créer une nouvelle conversation DM
+            let conversation = query_as::<_, DmConversation>("
+                INSERT INTO dm_conversations (uuid, user1_id, user2_id)
+                VALUES ($1, $2, $3)
+                RETURNING id, uuid, user1_id, user2_id, is_blocked, blocked_by, created_at, updated_at
+            ")
+            .bind(Uuid::new_v4())
+            .bind(user1_id.min(user2_id)) // Ordre consistant
+            .bind(user1_id.max(user2_id))
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("create_dm_conversation", e))?;
+
+            audit.record(&mut *tx, "dm_conversation_created", Some(user1_id), json!({
+                "conversation_id": conversation.id,
+                "user1_id": user1_id,
+                "user2_id": user2_id
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+            Ok(conversation)
+        })
+    }
+
+    fn dm_conversation<'a>(&'a self, conversation_id: i64, user_id: i64) -> BoxFuture<'a, Result<Option<DmConversation>>> {
+        Box::pin(async move {
+            query_as::<_, DmConversation>("
+                SELECT id, uuid, user1_id, user2_id, is_blocked, blocked_by, created_at, updated_at
+                FROM dm_conversations
+                WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
+            ")
+            .bind(conversation_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_dm_conversation", e))
+        })
+    }
+
+    fn dm_allowed<'a>(&'a self, sender_id: i64, recipient_id: i64) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(dm_allowed(&self.db, sender_id, recipient_id))
+    }
+
+    fn insert_dm_message<'a>(&'a self, message: NewDmMessage<'a>, cipher: Option<&'a MessageCipher>) -> BoxFuture<'a, Result<InsertedDmMessage>> {
+        Box::pin(async move {
+            let NewDmMessage { conversation_id, author_id, content, source, parent_message_id, mut metadata, dedup_key } = message;
+
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Valider l'extrait cité du parent (conservé tel quel dans les métadonnées)
+            let quote = attach_quote(cipher, &mut tx, conversation_id, author_id, parent_message_id, &mut metadata).await?;
+            // Messages directs : émojis serveur uniquement
+            annotate_custom_emojis(&mut *tx, None, source, &mut metadata).await?;
+
+            let message_uuid = Uuid::new_v4();
+            let row = query("
+                INSERT INTO messages (uuid, author_id, conversation_id, content, parent_message_id, metadata, status, client_nonce, content_hash)
+                VALUES ($1, $2, $3, $4, $5, $6, 'sent', $7, $8)
+                ON CONFLICT (author_id, conversation_id, client_nonce) WHERE client_nonce IS NOT NULL
+                    DO UPDATE SET client_nonce = EXCLUDED.client_nonce
+                RETURNING id, uuid, created_at, content_hash
+            ")
+            .bind(message_uuid)
+            .bind(author_id)
+            .bind(conversation_id)
+            .bind(&content.stored)
+            .bind(parent_message_id)
+            .bind(&metadata)
+            .bind(dedup_key.as_ref().map(|key| &key.nonce))
+            .bind(dedup_key.as_ref().map(|key| &key.content_hash))
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("insert_dm_message", e))?;
+
+            let message_id: i64 = row.get("id");
+            let created_at: DateTime<Utc> = row.get("created_at");
+
+            // Renvoi simultané : message déjà stocké et diffusé, transaction abandonnée
+            let stored_hash: Option<Vec<u8>> = row.get("content_hash");
+            if dedup::is_concurrent_retry(dedup_key.as_ref(), message_uuid, row.get("uuid"), stored_hash.as_deref())? {
+                return Ok(InsertedDmMessage { id: message_id, created_at, quote: None, duplicate: true });
+            }
+
+            // Corps complet des messages longs
+            store_message_body(&mut tx, message_id, content).await?;
+
+            // Si c'est une réponse, incrémenter le compteur de thread
+            if let Some(parent_id) = parent_message_id {
+                query("UPDATE messages SET thread_count = thread_count + 1 WHERE id = $1")
+                    .bind(parent_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| ChatError::from_sqlx_error("update_thread_count", e))?;
+            }
+
+            process_dm_mentions(&mut tx, message_id, source).await?;
+
+            query("UPDATE dm_conversations SET updated_at = NOW() WHERE id = $1")
+                .bind(conversation_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("update_dm_conversation", e))?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(InsertedDmMessage { id: message_id, created_at, quote, duplicate: false })
+        })
+    }
+
+    fn dm_history_page<'a>(&'a self, conversation_id: i64, before_message_id: Option<i64>, limit: i64) -> BoxFuture<'a, Result<Vec<DmMessage>>> {
+        Box::pin(async move {
+            query_as::<_, DmMessage>("
+                SELECT 
+                    m.id, m.uuid, m.author_id, u.username as author_username,
+                    m.conversation_id, m.content, m.parent_message_id, m.thread_count,
+                    m.status, m.is_edited, m.edit_count, m.is_pinned, m.metadata,
+                    m.created_at, m.updated_at, m.edited_at,
+                    COALESCE((
+                        SELECT json_agg(json_build_object('emoji', r.emoji, 'count', r.count) ORDER BY r.emoji)
+                        FROM (
+                            SELECT mr.emoji, COUNT(*) as count
+                            FROM message_reactions mr
+                            WHERE mr.message_id = m.id
+                            GROUP BY mr.emoji
+                        ) r
+                    ), '[]'::json) as reactions,
+                    (SELECT COUNT(*) FROM message_mentions mm WHERE mm.message_id = m.id)::int as mention_count
+                FROM messages m
+                JOIN users u ON u.id = m.author_id
+                WHERE m.conversation_id = $1 AND ($2::bigint IS NULL OR m.id < $2)
+                ORDER BY m.created_at DESC
+                LIMIT $3
+            ")
+            .bind(conversation_id)
+            .bind(before_message_id)
+            .bind(limit)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("fetch_dm_history", e))
+        })
+    }
+
+    fn set_dm_block<'a>(
+        &'a self,
+        conversation_id: i64,
+        user_id: i64,
+        block: bool,
+        hide_reactions: bool,
+        history: BlockedDmHistory,
+        audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Vérifier que l'utilisateur fait partie de la conversation
+            let is_participant: bool = query("
+                SELECT EXISTS(
+                    SELECT 1 FROM dm_conversations 
+                    WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
+                )
+            ")
+            .bind(conversation_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_participant", e))?
+            .get(0);
+
+            if !is_participant {
+                return Err(ChatError::unauthorized("block_dm_conversation"));
+            }
+
+            // Mettre à jour le statut de blocage
+            query("
+                UPDATE dm_conversations 
+                SET is_blocked = $1, blocked_by = $2, hide_reactions = $3, updated_at = NOW()
+                WHERE id = $4
+            ")
+            .bind(block)
+            .bind(if block { Some(user_id) } else { None })
+            .bind(block && hide_reactions)
+            .bind(conversation_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("update_block_status", e))?;
+
+            // Mode `delete` : l'historique disparaît au blocage (irréversible)
+            let deleted_messages = if block && history == BlockedDmHistory::Delete {
+                query("DELETE FROM messages WHERE conversation_id = $1")
+                    .bind(conversation_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| ChatError::from_sqlx_error("delete_blocked_history", e))?
+                    .rows_affected()
+            } else {
+                0
+            };
+
+            // Log d'audit
+            audit.record(&mut *tx, if block { "dm_blocked" } else { "dm_unblocked" }, Some(user_id), json!({
+                "conversation_id": conversation_id,
+                "hide_reactions": block && hide_reactions,
+                "deleted_messages": deleted_messages
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(())
+        })
+    }
+
+    fn find_duplicate<'a>(&'a self, key: &'a DedupKey, window: Duration) -> BoxFuture<'a, Result<Option<SentMessage>>> {
+        Box::pin(dedup::find_duplicate(&self.db, key, window))
+    }
+}
@@ -0,0 +1,418 @@
+//! Messages directs chiffrés de bout en bout (relais opaque)
+//!
+//! Le serveur stocke et relaie le chiffré sans jamais pouvoir le lire :
+//! - `send_encrypted_dm` : `content` est un bloc opaque (base64) accompagné
+//!   d'un en-tête d'échange de clés (`header`) relayé tel quel au destinataire
+//! - Ni transformations, ni filtre de contenu, ni mentions, ni recherche ;
+//!   le signalement et la modération sont désactivés par construction
+//! - Poignée de main type X3DH : chaque utilisateur publie ses clés publiques
+//!   (`publish_key_bundle`) ; `fetch_key_bundle` les remet à un correspondant
+//!   avec une clé à usage unique, retirée du stock. Les signatures ne sont pas
+//!   vérifiées par le serveur : c'est au client de le faire.
+//!
+//! Un message chiffré porte `messages.is_e2ee` ; son en-tête est conservé dans
+//! `metadata.e2ee` et accompagne le contenu dans l'historique et le rattrapage.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{query, Row};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::config::LimitsConfig;
+use crate::hub::common::ChatHub;
+use crate::hub::dedup::SentMessage;
+use crate::hub::direct_messages::dm_allowed;
+use crate::hub::guests::reject_guest;
+use crate::hub::quotas::consume_message_quota;
+use crate::hub::reputation::{check_message_rate, record_message};
+use crate::hub::violations::check_standing;
+use crate::message_schema::{message_frame, VersionedFrame};
+use crate::security::SecurityAction;
+use crate::validation::{validate_user_id, normalize_username};
+use crate::error::{ChatError, Result};
+
+/// Taille maximale d'une clé publique ou d'une signature (octets décodés)
+pub const MAX_KEY_LENGTH: usize = 256;
+
+/// Taille maximale de l'en-tête d'échange de clés (JSON sérialisé)
+pub const MAX_HEADER_SIZE: usize = 4096;
+
+/// Clés à usage unique acceptées par publication
+pub const MAX_PREKEYS_PER_UPLOAD: usize = 100;
+
+/// Clés à usage unique conservées par utilisateur
+pub const MAX_STORED_PREKEYS: i64 = 500;
+
+/// Sous ce stock, le propriétaire est prévenu (`e2ee_prekeys_low`)
+pub const LOW_PREKEY_THRESHOLD: i64 = 10;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Clé publique à usage unique
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OneTimePrekey {
+    pub key_id: i64,
+    pub public_key: String,
+}
+
+/// Clés publiées par un utilisateur
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBundleUpload {
+    pub identity_key: String,
+    pub signed_prekey_id: i64,
+    pub signed_prekey: String,
+    pub signed_prekey_signature: String,
+    #[serde(default)]
+    pub one_time_prekeys: Vec<OneTimePrekey>,
+}
+
+/// Clés remises à un correspondant
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBundle {
+    pub user_id: i64,
+    pub identity_key: String,
+    pub signed_prekey_id: i64,
+    pub signed_prekey: String,
+    pub signed_prekey_signature: String,
+    /// Clé à usage unique réservée à cette poignée de main (`None` : stock épuisé)
+    pub one_time_prekey: Option<OneTimePrekey>,
+}
+
+/// Message chiffré tel que reçu du client
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncryptedEnvelope {
+    /// Chiffré en base64, jamais interprété
+    pub ciphertext: String,
+    /// Métadonnées d'échange de clés, relayées telles quelles
+    pub header: Value,
+}
+
+fn validate_key(field: &str, encoded: &str) -> Result<()> {
+    let invalid = |reason: &str| ChatError::InvalidFormat { field: field.to_string(), reason: reason.to_string() };
+    let bytes = STANDARD.decode(encoded).map_err(|_| invalid("Base64 invalide"))?;
+    if bytes.is_empty() || bytes.len() > MAX_KEY_LENGTH {
+        return Err(invalid(&format!("Entre 1 et {} octets attendus", MAX_KEY_LENGTH)));
+    }
+    Ok(())
+}
+
+impl KeyBundleUpload {
+    /// Forme des clés uniquement : le serveur ne vérifie aucune signature
+    pub fn validate(&self) -> Result<()> {
+        validate_key("identityKey", &self.identity_key)?;
+        validate_key("signedPrekey", &self.signed_prekey)?;
+        validate_key("signedPrekeySignature", &self.signed_prekey_signature)?;
+
+        if self.one_time_prekeys.len() > MAX_PREKEYS_PER_UPLOAD {
+            return Err(ChatError::OutOfRange {
+                field: "oneTimePrekeys".to_string(),
+                value: self.one_time_prekeys.len() as i64,
+                min: 0,
+                max: MAX_PREKEYS_PER_UPLOAD as i64,
+            });
+        }
+        for prekey in &self.one_time_prekeys {
+            validate_key("oneTimePrekeys.publicKey", &prekey.public_key)?;
+        }
+        Ok(())
+    }
+}
+
+impl EncryptedEnvelope {
+    /// Forme du bloc et taille de l'en-tête ; le contenu lui-même reste opaque
+    pub fn validate(&self, limits: &LimitsConfig) -> Result<()> {
+        let invalid = |field: &str, reason: &str| ChatError::InvalidFormat { field: field.to_string(), reason: reason.to_string() };
+
+        if self.ciphertext.is_empty() {
+            return Err(invalid("content", "Chiffré vide"));
+        }
+        if self.ciphertext.len() > limits.max_long_message_length {
+            return Err(ChatError::MessageTooLong { actual: self.ciphertext.len(), max: limits.max_long_message_length });
+        }
+        STANDARD.decode(&self.ciphertext).map_err(|_| invalid("content", "Chiffré base64 invalide"))?;
+
+        if !self.header.is_object() {
+            return Err(invalid("header", "Objet attendu"));
+        }
+        if self.header.to_string().len() > MAX_HEADER_SIZE {
+            return Err(invalid("header", &format!("En-tête limité à {} octets", MAX_HEADER_SIZE)));
+        }
+        Ok(())
+    }
+}
+
+// ================================================================
+// CLÉS PUBLIQUES
+// ================================================================
+
+/// Publie (ou remplace) les clés de l'utilisateur et complète son stock de
+/// clés à usage unique ; retourne le stock disponible
+pub async fn publish_key_bundle(hub: &ChatHub, user_id: i64, upload: &KeyBundleUpload) -> Result<i64> {
+    tracing::info!(user_id = %user_id, prekeys = %upload.one_time_prekeys.len(), "🔑 Publication des clés de chiffrement");
+
+    validate_user_id(user_id as i32)?;
+    upload.validate()?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    query("
+        INSERT INTO e2ee_key_bundles (user_id, identity_key, signed_prekey_id, signed_prekey, signed_prekey_signature)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id) DO UPDATE
+        SET identity_key = EXCLUDED.identity_key,
+            signed_prekey_id = EXCLUDED.signed_prekey_id,
+            signed_prekey = EXCLUDED.signed_prekey,
+            signed_prekey_signature = EXCLUDED.signed_prekey_signature,
+            updated_at = NOW()
+    ")
+    .bind(user_id)
+    .bind(&upload.identity_key)
+    .bind(upload.signed_prekey_id)
+    .bind(&upload.signed_prekey)
+    .bind(&upload.signed_prekey_signature)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("upsert_key_bundle", e))?;
+
+    let key_ids: Vec<i64> = upload.one_time_prekeys.iter().map(|prekey| prekey.key_id).collect();
+    let public_keys: Vec<&str> = upload.one_time_prekeys.iter().map(|prekey| prekey.public_key.as_str()).collect();
+    query("
+        INSERT INTO e2ee_one_time_prekeys (user_id, key_id, public_key)
+        SELECT $1, key_id, public_key FROM UNNEST($2::BIGINT[], $3::TEXT[]) AS t(key_id, public_key)
+        ON CONFLICT (user_id, key_id) DO NOTHING
+    ")
+    .bind(user_id)
+    .bind(&key_ids)
+    .bind(&public_keys)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_one_time_prekeys", e))?;
+
+    let stored: i64 = query("SELECT COUNT(*) FROM e2ee_one_time_prekeys WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("count_one_time_prekeys", e))?
+        .get(0);
+
+    if stored > MAX_STORED_PREKEYS {
+        return Err(ChatError::QuotaExceeded {
+            quota_type: "e2ee_prekeys".to_string(),
+            used: stored as u64,
+            limit: MAX_STORED_PREKEYS as u64,
+        });
+    }
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    tracing::info!(user_id = %user_id, stored = %stored, "✅ Clés de chiffrement publiées");
+    Ok(stored)
+}
+
+/// Clés de `target_id` pour ouvrir une session chiffrée ; consomme une clé à usage unique
+///
+/// Un utilisateur dont la confidentialité écarte le demandeur est traité
+/// comme s'il n'avait rien publié. Ses propres clés se relisent sans
+/// consommer de clé à usage unique. Le débit est limité par demandeur et,
+/// tous demandeurs confondus, par destinataire (son stock ne s'épuise pas en rafale).
+pub async fn fetch_key_bundle(hub: &ChatHub, requester_id: i64, target_id: i64) -> Result<KeyBundle> {
+    tracing::debug!(requester_id = %requester_id, target_id = %target_id, "🔑 Récupération des clés d'un correspondant");
+
+    hub.check_action_limit(requester_id as i32, SecurityAction::FetchKeyBundle).await?;
+    let own = requester_id == target_id;
+    if !own {
+        hub.check_action_limit(target_id as i32, SecurityAction::ClaimPrekey).await?;
+    }
+
+    let (bundle, remaining) = hub.dm_repository.claim_key_bundle(requester_id, target_id).await?
+        .ok_or_else(|| ChatError::not_found("key_bundle", &target_id.to_string()))?;
+
+    if !own && remaining < LOW_PREKEY_THRESHOLD {
+        hub.send_to_user_sessions(target_id as i32, &json!({
+            "type": "e2ee_prekeys_low",
+            "data": { "remaining": remaining }
+        }).to_string()).await;
+    }
+
+    Ok(bundle)
+}
+
+// ================================================================
+// MESSAGES CHIFFRÉS
+// ================================================================
+
+/// Trame `dm_encrypted_message` : chiffré et en-tête relayés sans modification
+pub fn encrypted_dm_frame(
+    message_id: i64,
+    conversation_id: i64,
+    author_id: i64,
+    username: &str,
+    envelope: &EncryptedEnvelope,
+    timestamp: DateTime<Utc>
+) -> Value {
+    message_frame("dm_encrypted_message", json!({
+        "id": message_id,
+        "conversationId": conversation_id,
+        "authorId": author_id,
+        "username": username,
+        "content": envelope.ciphertext,
+        "header": envelope.header,
+        "timestamp": timestamp
+    }))
+}
+
+/// Envoie un message direct chiffré de bout en bout
+///
+/// Mêmes règles d'accès qu'un message en clair (sanctions, participation,
+/// blocage, confidentialité, débit, quota) ; retourne `None` si la confidentialité du
+/// destinataire l'écarte. Le contenu n'est ni transformé, ni filtré, ni indexé.
+pub async fn send_encrypted_dm(
+    hub: &ChatHub,
+    conversation_id: i64,
+    author_id: i64,
+    username: &str,
+    envelope: &EncryptedEnvelope
+) -> Result<Option<SentMessage>> {
+    tracing::info!(author_id = %author_id, conversation_id = %conversation_id, size = %envelope.ciphertext.len(), "🔏 Envoi d'un message DM chiffré");
+
+    reject_guest(author_id, "send_encrypted_dm")?;
+    validate_user_id(author_id as i32)?;
+    // Auteur suspendu ou banni après des violations répétées (contenu chiffré : jamais retenu)
+    check_standing(hub, author_id as i32).await?;
+    let username: &str = &normalize_username(username)?;
+    envelope.validate(&hub.config.limits)?;
+
+    if !check_message_rate(hub, author_id).await? {
+        return Err(ChatError::rate_limit_exceeded_simple("send_encrypted_dm"));
+    }
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let conversation = query("
+        SELECT is_blocked, user1_id, user2_id
+        FROM dm_conversations
+        WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
+    ")
+    .bind(conversation_id)
+    .bind(author_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_dm_conversation", e))?
+    .ok_or_else(|| ChatError::not_found("conversation", &conversation_id.to_string()))?;
+
+    if conversation.get::<bool, _>("is_blocked") {
+        return Err(ChatError::configuration_error("Conversation bloquée"));
+    }
+
+    // Quota compté une fois l'envoi validé, message écarté par la confidentialité compris
+    consume_message_quota(hub, author_id).await?;
+
+    let user1_id: i64 = conversation.get("user1_id");
+    let other_user_id = if author_id == user1_id { conversation.get("user2_id") } else { user1_id };
+    if !dm_allowed(&mut *tx, author_id, other_user_id).await? {
+        tracing::warn!(author_id = %author_id, recipient_id = %other_user_id, "🔒 Message DM chiffré écarté par la confidentialité du destinataire");
+        return Ok(None);
+    }
+
+    let message = query("
+        INSERT INTO messages (uuid, author_id, conversation_id, content, metadata, status, is_e2ee)
+        VALUES ($1, $2, $3, $4, $5, 'sent', TRUE)
+        RETURNING id, created_at
+    ")
+    .bind(Uuid::new_v4())
+    .bind(author_id)
+    .bind(conversation_id)
+    .bind(&envelope.ciphertext)
+    .bind(json!({ "e2ee": envelope.header }))
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_encrypted_dm", e))?;
+
+    let message_id: i64 = message.get("id");
+    let timestamp: DateTime<Utc> = message.get("created_at");
+
+    query("UPDATE dm_conversations SET updated_at = NOW() WHERE id = $1")
+        .bind(conversation_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_dm_conversation", e))?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    hub.increment_message_count().await;
+    record_message(hub, author_id).await;
+    hub.metrics.message_sent("direct", None).await;
+    hub.metrics.message_size(envelope.ciphertext.len(), "direct").await;
+
+    let payload = encrypted_dm_frame(message_id, conversation_id, author_id, username, envelope, timestamp);
+    hub.bridge_dm_event(conversation_id, &payload).await;
+    let frame = VersionedFrame::new(payload);
+    for client in hub.clients.get_many([author_id as i32, other_user_id as i32]).await {
+        client.send_frame(&frame);
+    }
+
+    tracing::info!(message_id = %message_id, conversation_id = %conversation_id, "✅ Message DM chiffré envoyé");
+    Ok(Some(SentMessage { id: message_id, created_at: timestamp }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> String {
+        STANDARD.encode([byte; 32])
+    }
+
+    fn upload(prekeys: usize) -> KeyBundleUpload {
+        KeyBundleUpload {
+            identity_key: key(1),
+            signed_prekey_id: 1,
+            signed_prekey: key(2),
+            signed_prekey_signature: STANDARD.encode([3u8; 64]),
+            one_time_prekeys: (0..prekeys).map(|i| OneTimePrekey { key_id: i as i64, public_key: key(4) }).collect(),
+        }
+    }
+
+    #[test]
+    fn test_key_bundle_shape_is_checked() {
+        assert!(upload(10).validate().is_ok());
+        assert!(upload(MAX_PREKEYS_PER_UPLOAD + 1).validate().is_err());
+
+        let mut bad = upload(0);
+        bad.identity_key = "pas du base64 !".to_string();
+        assert!(bad.validate().is_err());
+        bad.identity_key = STANDARD.encode([0u8; MAX_KEY_LENGTH + 1]);
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_envelope_is_relayed_untouched() {
+        let limits = LimitsConfig::default();
+        let envelope = EncryptedEnvelope {
+            ciphertext: STANDARD.encode(b"\x00\xffopaque"),
+            header: json!({ "ephemeralKey": key(5), "oneTimePrekeyId": 3 }),
+        };
+        envelope.validate(&limits).unwrap();
+
+        let frame = encrypted_dm_frame(10, 4, 7, "alice", &envelope, Utc::now());
+        assert_eq!(frame["type"], "dm_encrypted_message");
+        assert_eq!(frame["data"]["content"], envelope.ciphertext);
+        assert_eq!(frame["data"]["header"], envelope.header);
+
+        let not_base64 = EncryptedEnvelope { ciphertext: "bonjour en clair".to_string(), ..envelope.clone() };
+        assert!(not_base64.validate(&limits).is_err());
+        let bare_header = EncryptedEnvelope { header: json!("clé"), ..envelope };
+        assert!(bare_header.validate(&limits).is_err());
+    }
+}
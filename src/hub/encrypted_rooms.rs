@@ -0,0 +1,254 @@
+//! Module des salons chiffrés au repos
+//!
+//! Un propriétaire ou administrateur marque un salon sensible
+//! (`conversations.encrypt_at_rest`) : le contenu de ses nouveaux messages est
+//! scellé avant stockage (voir `crate::encryption`) et déchiffré à la lecture,
+//! sans changement pour les clients. Les messages antérieurs restent en clair
+//! et la diffusion en temps réel porte toujours le contenu en clair.
+//!
+//! Contreparties d'un salon chiffré :
+//! - La recherche plein texte l'ignore
+//! - Ses messages n'empruntent pas l'insertion groupée
+//! - Le journal d'audit des éditions n'y conserve pas le contenu
+//! - Les métadonnées (extraits cités notamment) restent en clair
+
+use std::collections::HashMap;
+use serde_json::json;
+use sqlx::postgres::PgRow;
+use sqlx::{query, Row};
+use crate::encryption::{is_sealed, DataKey, MessageCipher};
+use crate::hub::channels::RoomMessage;
+use crate::hub::common::ChatHub;
+use crate::hub::long_messages::PreparedContent;
+use crate::error::{ChatError, Result};
+
+/// Messages réenveloppés par lot lors d'une rotation de clé
+pub const REWRAP_BATCH_SIZE: i64 = 500;
+
+fn message_cipher(hub: &ChatHub) -> Result<&MessageCipher> {
+    active_cipher(hub.message_cipher.as_ref())
+}
+
+/// Clés configurées, ou refus si le chiffrement au repos n'est pas configuré
+fn active_cipher(cipher: Option<&MessageCipher>) -> Result<&MessageCipher> {
+    cipher.ok_or_else(|| ChatError::feature_not_available(
+        "encryption_at_rest",
+        "Aucune clé de chiffrement configurée ([security.encryption_at_rest])"
+    ))
+}
+
+// ================================================================
+// ÉCRITURE
+// ================================================================
+
+/// Nouvelle clé de données pour un message d'un salon chiffré (`None` sinon)
+pub(crate) fn room_data_key(cipher: Option<&MessageCipher>, encrypted: bool) -> Result<Option<DataKey>> {
+    if !encrypted {
+        return Ok(None);
+    }
+    active_cipher(cipher)?.new_data_key().map(Some)
+}
+
+/// Clé de données d'un message stocké (`None` s'il est en clair)
+pub(crate) fn message_data_key(cipher: Option<&MessageCipher>, key_id: Option<&str>, wrapped: Option<&[u8]>) -> Result<Option<DataKey>> {
+    match (key_id, wrapped) {
+        (Some(key_id), Some(wrapped)) => active_cipher(cipher)?.open_data_key(key_id, wrapped).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Contenu tel qu'il sera stocké : scellé (aperçu et corps complet) si une clé est fournie
+pub(crate) fn seal_prepared(data_key: Option<&DataKey>, prepared: &PreparedContent) -> Result<PreparedContent> {
+    let Some(data_key) = data_key else {
+        return Ok(prepared.clone());
+    };
+    Ok(PreparedContent {
+        stored: data_key.seal(&prepared.stored)?,
+        body: prepared.body.as_deref().map(|body| data_key.seal(body)).transpose()?,
+    })
+}
+
+// ================================================================
+// LECTURE
+// ================================================================
+
+/// Contenu en clair d'une ligne portant `encryption_key_id` et `wrapped_key`
+pub(crate) fn open_row_content(cipher: Option<&MessageCipher>, row: &PgRow, column: &str) -> Result<String> {
+    let content: String = row.get(column);
+    if !is_sealed(&content) {
+        return Ok(content);
+    }
+    let key_id: Option<String> = row.get("encryption_key_id");
+    let wrapped: Option<Vec<u8>> = row.get("wrapped_key");
+    active_cipher(cipher)?.open_content(&content, key_id.as_deref(), wrapped.as_deref())
+}
+
+/// Déchiffre le contenu des messages scellés d'une page d'historique
+pub(crate) async fn open_room_messages(hub: &ChatHub, messages: &mut [RoomMessage]) -> Result<()> {
+    let sealed_ids: Vec<i64> = messages.iter()
+        .filter(|message| is_sealed(&message.content))
+        .map(|message| message.id)
+        .collect();
+    if sealed_ids.is_empty() {
+        return Ok(());
+    }
+
+    let keys: HashMap<i64, (String, Vec<u8>)> = query("
+        SELECT id, encryption_key_id, wrapped_key FROM messages
+        WHERE id = ANY($1) AND encryption_key_id IS NOT NULL AND wrapped_key IS NOT NULL
+    ")
+    .bind(&sealed_ids)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_message_keys", e))?
+    .into_iter()
+    .map(|row| (row.get("id"), (row.get("encryption_key_id"), row.get("wrapped_key"))))
+    .collect();
+
+    let cipher = message_cipher(hub)?;
+    for message in messages.iter_mut().filter(|message| is_sealed(&message.content)) {
+        let (key_id, wrapped) = keys.get(&message.id)
+            .map(|(key_id, wrapped)| (Some(key_id.as_str()), Some(wrapped.as_slice())))
+            .unwrap_or((None, None));
+        message.content = cipher.open_content(&message.content, key_id, wrapped)?;
+    }
+    Ok(())
+}
+
+// ================================================================
+// RÉGLAGE ET ROTATION
+// ================================================================
+
+/// Active ou désactive le chiffrement au repos d'un salon (propriétaire ou admin)
+///
+/// Seuls les messages envoyés ensuite sont concernés : les messages existants
+/// gardent leur forme (en clair ou scellée) et restent lisibles.
+pub async fn set_room_encryption(hub: &ChatHub, room_id: i64, user_id: i64, enabled: bool) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, enabled = %enabled, "🔐 Changement du chiffrement au repos");
+
+    if enabled {
+        message_cipher(hub)?;
+    }
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let user_role: Option<String> = query("
+        SELECT role FROM conversation_members
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+    .map(|row| row.get("role"));
+
+    match user_role.as_deref() {
+        Some("owner") | Some("admin") => {},
+        _ => return Err(ChatError::unauthorized("set_room_encryption"))
+    }
+
+    let rows_affected = query("
+        UPDATE conversations
+        SET encrypt_at_rest = $1, updated_at = NOW()
+        WHERE id = $2 AND type = 'public_room'
+    ")
+    .bind(enabled)
+    .bind(room_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("update_room_encryption", e))?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ChatError::not_found("salon", &room_id.to_string()));
+    }
+
+    hub.audit_sink.record(&mut *tx, "room_encryption_changed", Some(user_id), json!({
+        "room_id": room_id,
+        "encrypt_at_rest": enabled
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    tracing::info!(room_id = %room_id, enabled = %enabled, "✅ Chiffrement au repos mis à jour");
+    Ok(())
+}
+
+/// Réenveloppe avec la clé active jusqu'à `batch_size` messages scellés par
+/// une ancienne clé ; retourne le nombre de messages traités
+///
+/// Le contenu n'est pas rechiffré : seule la clé de données change
+/// d'enveloppe. À répéter jusqu'à 0 avant de retirer l'ancienne clé de la
+/// configuration.
+pub async fn rewrap_message_keys(hub: &ChatHub, batch_size: i64) -> Result<u64> {
+    let cipher = message_cipher(hub)?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let rows = query("
+        SELECT id, encryption_key_id, wrapped_key FROM messages
+        WHERE encryption_key_id IS NOT NULL AND encryption_key_id != $1
+        ORDER BY id
+        LIMIT $2
+        FOR UPDATE SKIP LOCKED
+    ")
+    .bind(cipher.active_key_id())
+    .bind(batch_size.clamp(1, REWRAP_BATCH_SIZE))
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_rewrap_batch", e))?;
+
+    for row in &rows {
+        let key_id: String = row.get("encryption_key_id");
+        let wrapped: Vec<u8> = row.get("wrapped_key");
+        let (new_key_id, rewrapped) = cipher.rewrap(&key_id, &wrapped)?;
+
+        query("UPDATE messages SET encryption_key_id = $1, wrapped_key = $2 WHERE id = $3")
+            .bind(new_key_id)
+            .bind(rewrapped)
+            .bind(row.get::<i64, _>("id"))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("rewrap_message_key", e))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    if !rows.is_empty() {
+        tracing::info!(count = %rows.len(), active_key_id = %cipher.active_key_id(), "🔑 Clés de messages réenveloppées");
+    }
+    Ok(rows.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use crate::config::{EncryptionAtRestConfig, LimitsConfig};
+    use crate::encryption::KEY_LEN;
+
+    #[test]
+    fn test_long_message_preview_and_body_are_both_sealed() {
+        let config = EncryptionAtRestConfig {
+            active_key_id: Some("k1".to_string()),
+            keys: [("k1".to_string(), STANDARD.encode([3u8; KEY_LEN]))].into_iter().collect(),
+        };
+        let cipher = MessageCipher::from_config(&config).unwrap().unwrap();
+        let data_key = cipher.new_data_key().unwrap();
+
+        let limits = LimitsConfig::default();
+        let prepared = PreparedContent::prepare(&"x".repeat(limits.max_message_length + 1), &limits).unwrap();
+        let sealed = seal_prepared(Some(&data_key), &prepared).unwrap();
+
+        assert!(is_sealed(&sealed.stored));
+        assert_eq!(data_key.open(&sealed.stored).unwrap(), prepared.stored);
+        assert_eq!(data_key.open(sealed.body.as_deref().unwrap()).unwrap(), prepared.body.clone().unwrap());
+        assert_eq!(seal_prepared(None, &prepared).unwrap(), prepared);
+    }
+}
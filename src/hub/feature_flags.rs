@@ -0,0 +1,283 @@
+//! Module des drapeaux de fonctionnalités
+//!
+//! Chaque fonctionnalité optionnelle (réactions, fils, épinglage, ...) peut être
+//! coupée sans redéploiement :
+//! - Valeurs de base : section `[features]` de la configuration
+//! - Surcharges posées à chaud par un administrateur global, persistées en base
+//!   et rechargées au démarrage
+//! - L'état effectif est envoyé au client à la connexion (trame `feature_flags`)
+//!   puis à chaque changement
+//! - Les handlers refusent une fonctionnalité coupée avec `FeatureNotAvailable`
+
+use std::collections::BTreeMap;
+use sqlx::{query, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use crate::config::FeaturesConfig;
+use crate::hub::common::{check_global_admin, ChatHub};
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// DRAPEAUX
+// ================================================================
+
+/// Fonctionnalité pouvant être activée ou coupée
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureFlag {
+    FileUploads,
+    Reactions,
+    Mentions,
+    PinnedMessages,
+    Threads,
+    Webhooks,
+    PushNotifications,
+    MessageHistory,
+}
+
+impl FeatureFlag {
+    pub const ALL: [FeatureFlag; 8] = [
+        FeatureFlag::FileUploads,
+        FeatureFlag::Reactions,
+        FeatureFlag::Mentions,
+        FeatureFlag::PinnedMessages,
+        FeatureFlag::Threads,
+        FeatureFlag::Webhooks,
+        FeatureFlag::PushNotifications,
+        FeatureFlag::MessageHistory,
+    ];
+
+    /// Nom utilisé en base et dans les trames client
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeatureFlag::FileUploads => "file_uploads",
+            FeatureFlag::Reactions => "reactions",
+            FeatureFlag::Mentions => "mentions",
+            FeatureFlag::PinnedMessages => "pinned_messages",
+            FeatureFlag::Threads => "threads",
+            FeatureFlag::Webhooks => "webhooks",
+            FeatureFlag::PushNotifications => "push_notifications",
+            FeatureFlag::MessageHistory => "message_history",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|flag| flag.as_str() == name)
+    }
+
+    /// Valeur de base lue dans la section `[features]`
+    fn configured(&self, config: &FeaturesConfig) -> bool {
+        match self {
+            FeatureFlag::FileUploads => config.file_uploads,
+            FeatureFlag::Reactions => config.message_reactions,
+            FeatureFlag::Mentions => config.user_mentions,
+            FeatureFlag::PinnedMessages => config.pinned_messages,
+            FeatureFlag::Threads => config.message_threads,
+            FeatureFlag::Webhooks => config.webhooks,
+            FeatureFlag::PushNotifications => config.push_notifications,
+            FeatureFlag::MessageHistory => config.message_history,
+        }
+    }
+}
+
+/// État effectif des drapeaux : configuration + surcharges administrateur
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureFlags {
+    configured: BTreeMap<FeatureFlag, bool>,
+    overrides: BTreeMap<FeatureFlag, bool>,
+}
+
+impl FeatureFlags {
+    pub fn from_config(config: &FeaturesConfig) -> Self {
+        Self {
+            configured: FeatureFlag::ALL.into_iter()
+                .map(|flag| (flag, flag.configured(config)))
+                .collect(),
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self, flag: FeatureFlag) -> bool {
+        self.overrides.get(&flag)
+            .or_else(|| self.configured.get(&flag))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Pose (`Some`) ou retire (`None`, retour à la configuration) une surcharge
+    pub fn set_override(&mut self, flag: FeatureFlag, enabled: Option<bool>) {
+        match enabled {
+            Some(enabled) => { self.overrides.insert(flag, enabled); }
+            None => { self.overrides.remove(&flag); }
+        }
+    }
+
+    /// Refuse une fonctionnalité coupée
+    pub fn require(&self, flag: FeatureFlag) -> Result<()> {
+        if self.is_enabled(flag) {
+            Ok(())
+        } else {
+            Err(ChatError::feature_not_available(flag.as_str(), "Fonctionnalité désactivée sur ce serveur"))
+        }
+    }
+
+    /// Nom → état effectif, pour les trames client
+    pub fn to_json(&self) -> Value {
+        Value::Object(FeatureFlag::ALL.into_iter()
+            .map(|flag| (flag.as_str().to_string(), Value::Bool(self.is_enabled(flag))))
+            .collect::<Map<String, Value>>())
+    }
+
+    /// Trame envoyée à la connexion et à chaque changement
+    pub fn to_frame(&self) -> String {
+        json!({
+            "type": "feature_flags",
+            "data": self.to_json()
+        }).to_string()
+    }
+}
+
+// ================================================================
+// ACCÈS DEPUIS LE HUB
+// ================================================================
+
+impl ChatHub {
+    /// État effectif des drapeaux
+    pub async fn get_feature_flags(&self) -> FeatureFlags {
+        self.feature_flags.read().await.clone()
+    }
+
+    /// Refuse l'opération si la fonctionnalité est coupée
+    pub async fn require_feature(&self, flag: FeatureFlag) -> Result<()> {
+        self.feature_flags.read().await.require(flag)
+    }
+}
+
+// ================================================================
+// SURCHARGES PERSISTÉES
+// ================================================================
+
+/// Recharge les surcharges persistées (au démarrage)
+pub async fn load_feature_flags(hub: &ChatHub) -> Result<usize> {
+    let rows = query("SELECT name, enabled FROM feature_flag_overrides")
+        .fetch_all(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_feature_flags", e))?;
+
+    let mut flags = hub.feature_flags.write().await;
+    let mut loaded = 0;
+    for row in rows {
+        let name: String = row.get("name");
+        match FeatureFlag::from_name(&name) {
+            Some(flag) => {
+                flags.set_override(flag, Some(row.get("enabled")));
+                loaded += 1;
+            }
+            None => tracing::warn!(name = %name, "⚠️ Drapeau de fonctionnalité inconnu ignoré"),
+        }
+    }
+
+    tracing::info!(overrides = %loaded, "🚩 Drapeaux de fonctionnalités chargés");
+    Ok(loaded)
+}
+
+/// Active, coupe ou rend à la configuration (`None`) une fonctionnalité
+///
+/// Réservé aux administrateurs globaux ; les clients connectés reçoivent le
+/// nouvel état.
+pub async fn set_feature_flag(
+    hub: &ChatHub,
+    admin_id: i64,
+    flag: FeatureFlag,
+    enabled: Option<bool>
+) -> Result<FeatureFlags> {
+    tracing::info!(admin_id = %admin_id, flag = %flag.as_str(), enabled = ?enabled, "🚩 Changement de drapeau de fonctionnalité");
+
+    check_global_admin(hub, admin_id, "set_feature_flag").await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    match enabled {
+        Some(enabled) => {
+            query("
+                INSERT INTO feature_flag_overrides (name, enabled, updated_by)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (name) DO UPDATE
+                SET enabled = EXCLUDED.enabled, updated_by = EXCLUDED.updated_by, updated_at = NOW()
+            ")
+            .bind(flag.as_str())
+            .bind(enabled)
+            .bind(admin_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("upsert_feature_flag", e))?;
+        }
+        None => {
+            query("DELETE FROM feature_flag_overrides WHERE name = $1")
+                .bind(flag.as_str())
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("delete_feature_flag", e))?;
+        }
+    }
+
+    hub.audit_sink.record(&mut *tx, "feature_flag_changed", Some(admin_id), json!({
+        "flag": flag.as_str(),
+        "enabled": enabled
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    let flags = {
+        let mut flags = hub.feature_flags.write().await;
+        flags.set_override(flag, enabled);
+        flags.clone()
+    };
+
+    let frame = flags.to_frame();
+    for client in hub.clients.values().await {
+        client.send_text(&frame);
+    }
+
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_values_are_the_baseline() {
+        let config = FeaturesConfig { message_reactions: false, ..Default::default() };
+        let flags = FeatureFlags::from_config(&config);
+
+        assert!(!flags.is_enabled(FeatureFlag::Reactions));
+        assert!(flags.is_enabled(FeatureFlag::Threads));
+        assert!(!flags.is_enabled(FeatureFlag::Webhooks));
+        assert_eq!(flags.to_json()["reactions"], json!(false));
+    }
+
+    #[test]
+    fn test_override_and_reset() {
+        let mut flags = FeatureFlags::from_config(&FeaturesConfig::default());
+
+        flags.set_override(FeatureFlag::Reactions, Some(false));
+        assert!(matches!(
+            flags.require(FeatureFlag::Reactions),
+            Err(ChatError::FeatureNotAvailable { ref feature, .. }) if feature == "reactions"
+        ));
+
+        flags.set_override(FeatureFlag::Reactions, None);
+        assert!(flags.require(FeatureFlag::Reactions).is_ok());
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for flag in FeatureFlag::ALL {
+            assert_eq!(FeatureFlag::from_name(flag.as_str()), Some(flag));
+        }
+        assert_eq!(FeatureFlag::from_name("telepathy"), None);
+    }
+}
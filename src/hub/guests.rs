@@ -0,0 +1,312 @@
+//! Connexions invitées
+//!
+//! Un invité (jeton `issue_guest_claims`, identifiant négatif) n'a pas de
+//! compte : il n'est jamais membre persisté d'un salon et n'entre que dans les
+//! salons publics qui l'acceptent (`conversations.guest_access`) :
+//! - `closed` (défaut) : salon fermé aux invités
+//! - `read_only` : l'invité reçoit les messages sans pouvoir publier
+//! - `limited` : publication limitée à `guests.messages_per_minute`
+//!
+//! Ni DM, ni commandes réservées aux comptes (identifiant refusé par
+//! `validate_user_id`). Les messages d'invités portent `guest: true` et ne sont
+//! conservés (`guest_messages`) qu'avec `guests.persist_messages`.
+//!
+//! Un message d'invité passe les contrôles d'un message de membre : politique
+//! des liens, débit du salon, mode anti-raid, filtre de contenu et mode lent.
+//! Faute d'examen possible, un message que le filtre retiendrait est refusé,
+//! et les salons pré-modérés n'acceptent pas de messages d'invités.
+
+use std::time::Instant;
+use sqlx::query;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use chrono::Utc;
+use crate::auth::GUEST_ROLE;
+use crate::hub::common::ChatHub;
+use crate::hub::anti_raid::check_raid_guest_message;
+use crate::hub::held_messages::{screen_room_message, RoomFilterMode};
+use crate::hub::room_links::review_message_links;
+use crate::hub::slow_mode::announce_slow_mode;
+use crate::config::GuestConfig;
+use crate::message_schema::{MessagePayload, VersionedFrame};
+use crate::security::SecurityAction;
+use crate::validation::validate_message_content;
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// POLITIQUE D'ACCÈS
+// ================================================================
+
+/// Accès des invités à un salon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuestAccess {
+    /// Salon fermé aux invités
+    #[default]
+    Closed,
+    /// Lecture seule
+    ReadOnly,
+    /// Lecture et publication limitée
+    Limited,
+}
+
+impl GuestAccess {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::ReadOnly => "read_only",
+            Self::Limited => "limited",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "closed" => Some(Self::Closed),
+            "read_only" => Some(Self::ReadOnly),
+            "limited" => Some(Self::Limited),
+            _ => None,
+        }
+    }
+
+    /// Valeur inconnue en base : salon fermé
+    pub fn from_db(value: &str) -> Self {
+        Self::from_name(value).unwrap_or_default()
+    }
+}
+
+/// Identifiant d'une connexion invitée (toujours négatif)
+pub fn is_guest(user_id: i32) -> bool {
+    user_id < 0
+}
+
+/// Refuse l'action à un invité (DM, conversations privées, ...)
+pub fn reject_guest(user_id: i64, action: &str) -> Result<()> {
+    if user_id < 0 {
+        tracing::warn!(user_id = %user_id, action = %action, "🚫 Action refusée à un invité");
+        return Err(ChatError::unauthorized(action));
+    }
+    Ok(())
+}
+
+/// Un invité peut-il entrer dans un salon avec cet accès ?
+pub fn check_guest_join(config: &GuestConfig, access: GuestAccess) -> Result<()> {
+    if !config.enabled {
+        return Err(ChatError::unauthorized("guest_access_disabled"));
+    }
+    if access == GuestAccess::Closed {
+        return Err(ChatError::unauthorized("guest_join_room"));
+    }
+    Ok(())
+}
+
+/// Un invité peut-il publier dans un salon avec cet accès ? Consomme son débit.
+pub async fn check_guest_send(hub: &ChatHub, guest_id: i32, access: GuestAccess) -> Result<()> {
+    check_guest_join(&hub.config.guests, access)?;
+    if access != GuestAccess::Limited {
+        return Err(ChatError::unauthorized("guest_send_message"));
+    }
+    hub.check_action_limit(guest_id, SecurityAction::GuestMessage).await
+}
+
+/// Marque une trame de message comme écrite par un invité
+pub fn label_guest_frame(frame: &mut Value, persisted: bool) {
+    frame["data"]["guest"] = json!(true);
+    frame["data"]["persisted"] = json!(persisted);
+}
+
+// ================================================================
+// SALONS
+// ================================================================
+
+/// Accès des invités au salon ; fermé s'il n'est pas public ou est archivé
+pub async fn room_guest_access(hub: &ChatHub, room_id: i64) -> Result<GuestAccess> {
+    hub.room_repository.guest_access(room_id).await
+}
+
+/// Modifie l'accès des invités au salon (propriétaire ou admin du salon)
+pub async fn set_room_guest_access(hub: &ChatHub, room_id: i64, user_id: i64, access: GuestAccess) -> Result<()> {
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let updated = query("
+        UPDATE conversations SET guest_access = $1, updated_at = NOW()
+        WHERE id = $2 AND EXISTS(
+            SELECT 1 FROM conversation_members
+            WHERE conversation_id = $2 AND user_id = $3 AND left_at IS NULL AND role IN ('owner', 'admin')
+        )
+    ")
+    .bind(access.as_str())
+    .bind(room_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("set_room_guest_access", e))?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(ChatError::unauthorized("set_room_guest_access"));
+    }
+
+    hub.audit_sink.record(&mut *tx, "room_guest_access_changed", Some(user_id), json!({
+        "room_id": room_id,
+        "guest_access": access.as_str()
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    tracing::info!(room_id = %room_id, access = %access.as_str(), "✅ Accès des invités au salon mis à jour");
+    Ok(())
+}
+
+/// Fait entrer un invité dans un salon (en mémoire uniquement)
+pub async fn join_room_as_guest(hub: &ChatHub, room_id: i64, guest_id: i32) -> Result<GuestAccess> {
+    if !is_guest(guest_id) {
+        return Err(ChatError::unauthorized("guest_join_room"));
+    }
+    let access = room_guest_access(hub, room_id).await?;
+    check_guest_join(&hub.config.guests, access)?;
+
+    hub.guest_rooms.update(room_id, |guests| {
+        if !guests.contains(&guest_id) {
+            guests.push(guest_id);
+        }
+    }).await;
+
+    tracing::info!(guest_id = %guest_id, room_id = %room_id, access = %access.as_str(), "👤 Invité entré dans le salon");
+    Ok(access)
+}
+
+/// Fait sortir un invité d'un salon
+pub async fn leave_room_as_guest(hub: &ChatHub, room_id: i64, guest_id: i32) {
+    hub.guest_rooms.with_mut(&room_id, |guests| {
+        if let Some(guests) = guests {
+            guests.retain(|id| *id != guest_id);
+        }
+    }).await;
+    hub.guest_rooms.remove_if(&room_id, Vec::is_empty).await;
+}
+
+/// Retire l'invité de tous les salons (déconnexion)
+pub async fn forget_guest(hub: &ChatHub, guest_id: i32) {
+    let rooms = hub.guest_rooms.filter_map(|room_id, guests| guests.contains(&guest_id).then_some(*room_id)).await;
+    for room_id in rooms {
+        leave_room_as_guest(hub, room_id, guest_id).await;
+    }
+}
+
+/// Transmet une trame de salon aux invités présents
+pub async fn forward_to_guests(hub: &ChatHub, room_id: i64, frame: &VersionedFrame) -> usize {
+    let guests = hub.guest_rooms.get(&room_id).await.unwrap_or_default();
+    hub.clients.get_many(guests).await.iter()
+        .filter(|client| client.send_frame(frame))
+        .count()
+}
+
+// ================================================================
+// MESSAGES D'INVITÉS
+// ================================================================
+
+/// Publie le message d'un invité entré dans le salon
+///
+/// Retourne l'identifiant du message conservé, `None` sans `guests.persist_messages`.
+pub async fn send_guest_message(hub: &ChatHub, room_id: i64, guest_id: i32, content: &str) -> Result<Option<i64>> {
+    let present = hub.guest_rooms.with(&room_id, |guests| guests.is_some_and(|guests| guests.contains(&guest_id))).await;
+    if !present {
+        return Err(ChatError::unauthorized("guest_send_message"));
+    }
+    let username = hub.clients.with(&guest_id, |client| client.map(|client| client.username.clone())).await
+        .ok_or_else(|| ChatError::not_found("client", &guest_id.to_string()))?;
+
+    let access = room_guest_access(hub, room_id).await?;
+    check_guest_send(hub, guest_id, access).await?;
+    validate_message_content(content, hub.config.limits.max_message_length)?;
+
+    let Some(posting) = hub.room_repository.guest_posting_context(room_id).await? else {
+        return Err(ChatError::unauthorized("guest_send_message"));
+    };
+    // Pré-modération : aucun examen possible pour un message d'invité
+    if posting.filter_mode == RoomFilterMode::Approve {
+        return Err(ChatError::InsufficientPermissions {
+            action: "guest_send_message".to_string(),
+            conversation_id: room_id.to_string(),
+        });
+    }
+
+    hub.check_room_limit(room_id).await?;
+    check_raid_guest_message(hub, room_id, guest_id).await?;
+    let links = review_message_links(hub, Some(room_id), content).await?;
+    let content: &str = &links.content;
+
+    // Message que le filtre retiendrait pour examen : refusé
+    let verdict = screen_room_message(hub, posting.filter_mode, GUEST_ROLE, &posting.languages, &posting.filter_allowlist, content)?;
+    if let Some(reason) = verdict {
+        tracing::warn!(guest_id = %guest_id, room_id = %room_id, reason = %reason, "🚫 Message d'invité refusé par le filtre");
+        return Err(ChatError::inappropriate_content_simple(&reason));
+    }
+
+    let slow_mode_triggered = hub.slow_mode.lock().await
+        .admit(room_id, guest_id as i64, false, posting.slow_mode, Instant::now())?;
+    if slow_mode_triggered {
+        tracing::warn!(room_id = %room_id, "🐢 Pic de trafic : mode lent automatique activé");
+        if let Err(e) = announce_slow_mode(hub, room_id, Some(hub.config.limits.slow_mode_interval), true).await {
+            tracing::warn!(room_id = %room_id, error = %e, "⚠️ Annonce du mode lent impossible");
+        }
+    }
+
+    let (message_id, timestamp) = if hub.config.guests.persist_messages {
+        let stored = hub.room_repository.insert_guest_message(room_id, guest_id, &username, content).await?;
+        (Some(stored.id), stored.created_at)
+    } else {
+        (None, Utc::now())
+    };
+
+    let mut payload = MessagePayload::new(message_id.unwrap_or(0), guest_id as i64, &username, content, timestamp)
+        .in_room(room_id)
+        .to_frame("room_message");
+    label_guest_frame(&mut payload, message_id.is_some());
+    let frame = VersionedFrame::new(payload);
+
+    let member_ids = hub.room_repository.member_ids(room_id).await?;
+    for client in hub.clients.get_many(member_ids.into_iter().map(|user_id| user_id as i32)).await {
+        client.send_frame(&frame);
+    }
+    forward_to_guests(hub, room_id, &frame).await;
+    hub.metrics.message_sent("guest", None).await;
+
+    tracing::info!(guest_id = %guest_id, room_id = %room_id, persisted = %message_id.is_some(), "💬 Message d'invité diffusé");
+    Ok(message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_access_names_round_trip() {
+        for access in [GuestAccess::Closed, GuestAccess::ReadOnly, GuestAccess::Limited] {
+            assert_eq!(GuestAccess::from_name(access.as_str()), Some(access));
+        }
+        assert_eq!(GuestAccess::from_db("inconnu"), GuestAccess::Closed);
+    }
+
+    #[test]
+    fn test_guest_join_requires_config_and_open_room() {
+        let mut config = GuestConfig::default();
+        assert!(check_guest_join(&config, GuestAccess::ReadOnly).is_err());
+
+        config.enabled = true;
+        assert!(check_guest_join(&config, GuestAccess::Closed).is_err());
+        assert!(check_guest_join(&config, GuestAccess::ReadOnly).is_ok());
+        assert!(reject_guest(-4, "send_dm_message").is_err());
+        assert!(reject_guest(4, "send_dm_message").is_ok());
+    }
+
+    #[test]
+    fn test_guest_frames_are_labeled() {
+        let mut frame = json!({"type": "room_message", "data": {"id": 0}});
+        label_guest_frame(&mut frame, false);
+        assert_eq!(frame["data"]["guest"], true);
+        assert_eq!(frame["data"]["persisted"], false);
+    }
+}
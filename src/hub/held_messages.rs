@@ -0,0 +1,278 @@
+//! Module des messages retenus pour examen
+//!
+//! Un salon en mode `flag` (`conversations.filter_mode`) passe chaque message
+//! au filtre de contenu ; au lieu d'être masqué ou signalé après coup, un
+//! message qui déclenche une règle est retenu :
+//! - Il est stocké mais reste invisible des autres membres (`messages.is_held`)
+//! - L'auteur reçoit `message_held` avec la raison, les modérateurs
+//!   `message_flagged` ; le message rejoint la file de modération
+//! - Un modérateur l'approuve (diffusion au salon, `message_approved` à
+//!   l'auteur) ou le rejette (suppression, `message_rejected`)
+//! - Les règles de gravité « reject » refusent toujours le message à l'envoi
+//!
+//! En mode `approve` (pré-modération), tout message d'un membre sans rôle de
+//! modération est retenu de la même façon, en attente d'approbation, même
+//! quand le filtre n'y trouve rien.
+
+use serde::{Deserialize, Serialize};
+use crate::hub::common::ChatHub;
+use crate::hub::channels::{broadcast_room_message, is_moderator_role};
+use crate::hub::mentions::{parse_mentions, notify_mention_recipients};
+use crate::hub::quotes::QuotedExcerpt;
+use crate::hub::reports::send_to_moderators;
+use crate::hub::visibility::MessageVisibility;
+use crate::permissions::Role;
+use crate::security::{ContentFilter, FilterVerdict};
+use crate::error::{ChatError, Result};
+use serde_json::{json, Value};
+
+// ================================================================
+// MODE DU SALON
+// ================================================================
+
+/// Traitement des messages d'un salon par le filtre de contenu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomFilterMode {
+    /// Filtre non appliqué à l'envoi
+    #[default]
+    Off,
+    /// Messages signalés par le filtre retenus jusqu'à examen
+    Flag,
+    /// Pré-modération : messages des membres non modérateurs retenus jusqu'à approbation
+    Approve,
+}
+
+/// Raison de la retenue d'un message propre dans un salon pré-modéré
+pub const APPROVAL_PENDING_REASON: &str = "En attente d'approbation par un modérateur";
+
+impl RoomFilterMode {
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "flag" => RoomFilterMode::Flag,
+            "approve" => RoomFilterMode::Approve,
+            _ => RoomFilterMode::Off,
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "off" => Ok(RoomFilterMode::Off),
+            "flag" => Ok(RoomFilterMode::Flag),
+            "approve" => Ok(RoomFilterMode::Approve),
+            _ => Err(ChatError::configuration_error(&format!("Mode de filtrage inconnu: {}", value))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoomFilterMode::Off => "off",
+            RoomFilterMode::Flag => "flag",
+            RoomFilterMode::Approve => "approve",
+        }
+    }
+}
+
+/// Clause SQL masquant les messages retenus aux autres que leur auteur (alias `m`)
+pub fn held_clause(user_param: usize) -> String {
+    format!("(NOT m.is_held OR m.author_id = ${})", user_param)
+}
+
+/// Raison de la retenue d'après le verdict du filtre (`None` : message publié)
+pub fn hold_reason(verdict: &FilterVerdict) -> Option<String> {
+    if verdict.is_clean() {
+        return None;
+    }
+    Some(verdict.reason.clone()
+        .or_else(|| verdict.rules.first().map(|rule| rule.reason().to_string()))
+        .unwrap_or_else(|| "Contenu signalé par le filtre".to_string()))
+}
+
+/// Passe le message au filtre d'un salon en mode `flag` ou `approve`
+///
+/// `languages` : langues du salon, filtrées en plus de celles de la
+/// configuration ; `allowlist` : termes du salon exemptés des mots interdits.
+/// Retourne la raison de la retenue ; une règle « reject » refuse le message.
+/// En mode `approve`, les rôles de modération ne sont retenus que sur signalement.
+pub fn screen_room_message(
+    hub: &ChatHub,
+    mode: RoomFilterMode,
+    member_role: &str,
+    languages: &[String],
+    allowlist: &[String],
+    content: &str
+) -> Result<Option<String>> {
+    if mode == RoomFilterMode::Off {
+        return Ok(None);
+    }
+    let verdict = ContentFilter::with_locales(&hub.config.security.content_filter, languages)?
+        .for_role(&Role::from_room_role(member_role))
+        .with_allowlist(allowlist)
+        .check_content(content)?;
+    let reason = hold_reason(&verdict);
+    if reason.is_none() && mode == RoomFilterMode::Approve && !is_moderator_role(member_role) {
+        return Ok(Some(APPROVAL_PENDING_REASON.to_string()));
+    }
+    Ok(reason)
+}
+
+// ================================================================
+// TRAMES
+// ================================================================
+
+/// Trame reçue par l'auteur d'un message retenu
+pub fn held_frame(message_id: i64, room_id: i64, reason: &str) -> Value {
+    json!({
+        "type": "message_held",
+        "data": {
+            "id": message_id,
+            "roomId": room_id,
+            "reason": reason
+        }
+    })
+}
+
+/// Trame reçue par l'auteur après examen (`message_approved` / `message_rejected`)
+pub fn review_frame(message_id: i64, room_id: i64, approved: bool, reason: Option<&str>) -> Value {
+    let kind = if approved { "message_approved" } else { "message_rejected" };
+    let mut frame = json!({
+        "type": kind,
+        "data": {
+            "id": message_id,
+            "roomId": room_id
+        }
+    });
+    if let Some(reason) = reason {
+        frame["data"]["reason"] = json!(reason);
+    }
+    frame
+}
+
+/// Prévient l'auteur et les modérateurs qu'un message vient d'être retenu
+pub async fn notify_message_held(hub: &ChatHub, room_id: i64, message_id: i64, author_id: i64, reason: &str) -> Result<()> {
+    tracing::warn!(room_id = %room_id, message_id = %message_id, reason = %reason, "⏸️ Message retenu pour examen");
+
+    hub.send_to_user_sessions(author_id as i32, &held_frame(message_id, room_id, reason).to_string()).await;
+
+    send_to_moderators(hub, room_id, &json!({
+        "type": "message_flagged",
+        "data": {
+            "messageId": message_id,
+            "conversationId": room_id,
+            "reportCount": 0,
+            "reasons": [reason],
+            "held": true
+        }
+    }).to_string()).await
+}
+
+// ================================================================
+// EXAMEN
+// ================================================================
+
+/// Seuls les modérateurs du salon et l'équipe de modération globale examinent
+pub(crate) fn check_review_rights(can_review: bool) -> Result<()> {
+    if !can_review {
+        return Err(ChatError::unauthorized("review_held_message"));
+    }
+    Ok(())
+}
+
+/// Approuve (diffusion au salon) ou rejette (suppression) un message retenu
+///
+/// `note` : motif transmis à l'auteur en cas de rejet.
+pub async fn review_held_message(
+    hub: &ChatHub,
+    message_id: i64,
+    moderator_id: i64,
+    approve: bool,
+    note: Option<&str>
+) -> Result<()> {
+    tracing::info!(message_id = %message_id, moderator_id = %moderator_id, approve = %approve, "⚖️ Examen d'un message retenu");
+
+    let message = hub.room_repository.review_held_message(message_id, moderator_id, approve, note, hub.write_context()).await?;
+
+    if let Some(resolved_mentions) = &message.mentions {
+        let mentions = parse_mentions(&message.content);
+        let quote = message.metadata.get("quote").and_then(|q| serde_json::from_value::<QuotedExcerpt>(q.clone()).ok());
+        let full_length = message.metadata.pointer("/longBody/fullLength").and_then(Value::as_u64).map(|n| n as usize);
+        let visibility = MessageVisibility::from_request(
+            message.visible_to.clone().map(|ids| ids.into_iter().map(|id| id as i32).collect()),
+            message.author_id
+        ).unwrap_or_default();
+
+        broadcast_room_message(hub, message.room_id, message_id, message.author_id, &message.username, &message.content, full_length,
+            message.created_at, message.parent_message_id, quote.as_ref(), &mentions, &visibility).await?;
+        notify_mention_recipients(hub, message.room_id, message_id, message.author_id, resolved_mentions, &visibility).await;
+    }
+
+    hub.send_to_user_sessions(message.author_id as i32, &review_frame(message_id, message.room_id, approve, note).to_string()).await;
+
+    tracing::info!(message_id = %message_id, room_id = %message.room_id, approve = %approve, "✅ Message retenu examiné");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FilterAction, FilterSeverity};
+    use crate::security::FilterRule;
+
+    fn verdict(rules: Vec<FilterRule>, reason: Option<&str>) -> FilterVerdict {
+        FilterVerdict {
+            content: "x".to_string(),
+            severity: (!rules.is_empty()).then_some(FilterSeverity::Low),
+            action: (!rules.is_empty()).then_some(FilterAction::Warn),
+            reason: reason.map(str::to_string),
+            rules,
+            exempted_rules: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_flagged_content_is_held_with_reason() {
+        assert_eq!(hold_reason(&verdict(Vec::new(), None)), None);
+        assert_eq!(
+            hold_reason(&verdict(vec![FilterRule::Spam], Some("Spam détecté"))).as_deref(),
+            Some("Spam détecté")
+        );
+        assert!(hold_reason(&verdict(vec![FilterRule::Spam], None)).is_some());
+    }
+
+    #[test]
+    fn test_held_frames() {
+        assert_eq!(
+            held_frame(12, 3, "Spam détecté"),
+            json!({ "type": "message_held", "data": { "id": 12, "roomId": 3, "reason": "Spam détecté" } })
+        );
+        assert_eq!(review_frame(12, 3, true, None)["type"], "message_approved");
+        let rejected = review_frame(12, 3, false, Some("Hors sujet"));
+        assert_eq!(rejected["type"], "message_rejected");
+        assert_eq!(rejected["data"]["reason"], "Hors sujet");
+    }
+
+    #[test]
+    fn test_approve_mode_round_trips() {
+        assert_eq!(RoomFilterMode::parse("approve").unwrap(), RoomFilterMode::Approve);
+        assert_eq!(RoomFilterMode::from_db(RoomFilterMode::Approve.as_str()), RoomFilterMode::Approve);
+    }
+
+    #[test]
+    fn test_room_filter_mode() {
+        assert_eq!(RoomFilterMode::from_db("flag"), RoomFilterMode::Flag);
+        assert_eq!(RoomFilterMode::from_db("inconnu"), RoomFilterMode::Off);
+        assert!(RoomFilterMode::parse("reject").is_err());
+        assert_eq!(held_clause(2), "(NOT m.is_held OR m.author_id = $2)");
+    }
+
+    #[test]
+    fn test_held_message_hidden_from_every_other_reader() {
+        // Corps complet, réaction, citation, recherche et non-lus : seul l'auteur voit un message retenu
+        assert!(crate::hub::long_messages::message_body_query().contains(&held_clause(2)));
+        assert!(crate::hub::reactions::message_access_query().contains(&held_clause(2)));
+        assert!(crate::hub::quotes::quoted_parent_query().contains(&held_clause(3)));
+        assert!(crate::message_store::search_query(true).contains(&held_clause(1)));
+        assert!(crate::hub::read_receipts::mark_read_query().contains(&held_clause(2)));
+        assert!(crate::hub::highlights::highlight_candidate_query().contains("NOT m.is_held"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Module de mise en avant des messages par réactions (starboard)
+//!
+//! Fonctionnalités :
+//! - Règles par salon : emoji + seuil de réactions
+//! - Événement `message_highlighted` lorsque le seuil est franchi
+//! - Republication optionnelle dans un salon vitrine
+//! - Déclenchement unique par message (table `message_highlights`)
+
+use sqlx::{query, query_as, FromRow, Row};
+use serde::{Serialize, Deserialize};
+use crate::hub::common::ChatHub;
+use crate::hub::channels::broadcast_to_room_members;
+use crate::hub::encrypted_rooms::open_row_content;
+use crate::hub::reactions::validate_emoji;
+use crate::error::{ChatError, Result};
+use crate::message_schema::message_frame;
+use serde_json::json;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct HighlightRule {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub emoji: String,
+    pub threshold: i32,
+    pub showcase_room_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Salon vitrine vu par l'auteur de la règle
+#[derive(Debug, Clone)]
+struct ShowcaseRoom {
+    /// Salon public ou privé (pas une conversation directe)
+    is_room: bool,
+    is_public: bool,
+    /// Rôle de l'auteur de la règle dans la vitrine
+    role: Option<String>,
+}
+
+// ================================================================
+// CONFIGURATION DES RÈGLES
+// ================================================================
+
+/// Vérifie la vitrine d'une règle
+///
+/// La vitrine est un salon dont l'auteur de la règle est propriétaire ou
+/// administrateur ; les messages d'un salon privé n'y sont pas republiés si
+/// elle est publique.
+fn check_showcase(source_is_public: bool, showcase: &ShowcaseRoom) -> Result<()> {
+    if !showcase.is_room {
+        return Err(ChatError::configuration_error("La vitrine doit être un salon"));
+    }
+
+    match showcase.role.as_deref() {
+        Some("owner") | Some("admin") => {},
+        _ => return Err(ChatError::unauthorized("set_highlight_rule")),
+    }
+
+    if !source_is_public && showcase.is_public {
+        return Err(ChatError::configuration_error("Un salon privé ne peut pas être mis en avant dans un salon public"));
+    }
+
+    Ok(())
+}
+
+/// Crée ou remplace la règle de mise en avant d'un emoji (propriétaire, admin ou modérateur)
+pub async fn set_highlight_rule(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    emoji: &str,
+    threshold: i32,
+    showcase_room_id: Option<i64>
+) -> Result<HighlightRule> {
+    tracing::info!(room_id = %room_id, emoji = %emoji, threshold = %threshold, "⭐ Configuration d'une règle de mise en avant");
+
+    validate_emoji(emoji)?;
+    if threshold <= 0 {
+        return Err(ChatError::OutOfRange {
+            field: "threshold".to_string(),
+            value: threshold as i64,
+            min: 1,
+            max: i32::MAX as i64,
+        });
+    }
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let source = query("
+        SELECT cm.role, c.is_public
+        FROM conversation_members cm
+        JOIN conversations c ON c.id = cm.conversation_id
+        WHERE cm.conversation_id = $1 AND cm.user_id = $2 AND cm.left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+    .ok_or_else(|| ChatError::unauthorized("set_highlight_rule"))?;
+
+    match source.get::<String, _>("role").as_str() {
+        "owner" | "admin" | "moderator" => {},
+        _ => return Err(ChatError::unauthorized("set_highlight_rule"))
+    }
+
+    if let Some(showcase_id) = showcase_room_id {
+        let showcase = query("
+            SELECT c.type::text IN ('public_room', 'private_room') as is_room, c.is_public, cm.role
+            FROM conversations c
+            LEFT JOIN conversation_members cm
+              ON cm.conversation_id = c.id AND cm.user_id = $2 AND cm.left_at IS NULL
+            WHERE c.id = $1
+        ")
+        .bind(showcase_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("check_showcase_room", e))?
+        .ok_or_else(|| ChatError::not_found("salon", &showcase_id.to_string()))?;
+
+        check_showcase(source.get("is_public"), &ShowcaseRoom {
+            is_room: showcase.get("is_room"),
+            is_public: showcase.get("is_public"),
+            role: showcase.get("role"),
+        })?;
+    }
+
+    let rule = query_as::<_, HighlightRule>("
+        INSERT INTO room_highlight_rules (conversation_id, emoji, threshold, showcase_room_id, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (conversation_id, emoji) DO UPDATE
+        SET threshold = EXCLUDED.threshold, showcase_room_id = EXCLUDED.showcase_room_id
+        RETURNING id, conversation_id, emoji, threshold, showcase_room_id, created_at
+    ")
+    .bind(room_id)
+    .bind(emoji)
+    .bind(threshold)
+    .bind(showcase_room_id)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("upsert_highlight_rule", e))?;
+
+    hub.audit_sink.record(&mut *tx, "highlight_rule_set", Some(user_id), json!({
+        "room_id": room_id,
+        "emoji": emoji,
+        "threshold": threshold,
+        "showcase_room_id": showcase_room_id
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    Ok(rule)
+}
+
+/// Liste les règles de mise en avant d'un salon
+pub async fn list_highlight_rules(hub: &ChatHub, room_id: i64) -> Result<Vec<HighlightRule>> {
+    query_as::<_, HighlightRule>("
+        SELECT id, conversation_id, emoji, threshold, showcase_room_id, created_at
+        FROM room_highlight_rules
+        WHERE conversation_id = $1
+        ORDER BY emoji
+    ")
+    .bind(room_id)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("list_highlight_rules", e))
+}
+
+// ================================================================
+// ÉVALUATION
+// ================================================================
+
+/// Message candidat à la mise en avant, avec la règle de l'emoji
+///
+/// Un message restreint ou retenu n'est jamais mis en avant : l'événement et
+/// la republication sont visibles de tout le salon.
+pub(crate) fn highlight_candidate_query() -> String {
+    "
+        SELECT r.id as rule_id, r.threshold, r.showcase_room_id,
+               m.conversation_id, m.author_id, m.content, m.encryption_key_id, m.wrapped_key,
+               (SELECT COUNT(*) FROM message_reactions mr WHERE mr.message_id = m.id AND mr.emoji = $2) as reaction_count
+        FROM messages m
+        JOIN room_highlight_rules r ON r.conversation_id = m.conversation_id AND r.emoji = $2
+        WHERE m.id = $1 AND m.status != 'deleted'
+          AND m.visible_to IS NULL AND NOT m.is_held
+          AND NOT EXISTS (SELECT 1 FROM message_highlights h WHERE h.message_id = m.id)
+    ".to_string()
+}
+
+/// Évalue les règles après l'ajout d'une réaction
+///
+/// Le marquage dans `message_highlights` garantit un déclenchement unique,
+/// même si la réaction est retirée puis remise.
+pub(crate) async fn evaluate_highlight(hub: &ChatHub, message_id: i64, emoji: &str) -> Result<()> {
+    let candidate = query(&highlight_candidate_query())
+    .bind(message_id)
+    .bind(emoji)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("evaluate_highlight", e))?;
+
+    let Some(row) = candidate else {
+        return Ok(());
+    };
+
+    let reaction_count: i64 = row.get("reaction_count");
+    let threshold: i32 = row.get("threshold");
+    if reaction_count < threshold as i64 {
+        return Ok(());
+    }
+
+    let rule_id: i64 = row.get("rule_id");
+    let room_id: i64 = row.get("conversation_id");
+    let author_id: i64 = row.get("author_id");
+    let content = open_row_content(hub.message_cipher.as_ref(), &row, "content")?;
+    let showcase_room_id: Option<i64> = row.get("showcase_room_id");
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    // Verrou d'unicité : seul le premier franchissement passe
+    let inserted = query("
+        INSERT INTO message_highlights (message_id, rule_id)
+        VALUES ($1, $2)
+        ON CONFLICT (message_id) DO NOTHING
+    ")
+    .bind(message_id)
+    .bind(rule_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_highlight", e))?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Ok(());
+    }
+
+    let showcase_message_id = match showcase_room_id {
+        Some(showcase_id) => {
+            // Contenu repris tel que stocké : un message chiffré le reste dans la vitrine
+            let reposted: i64 = query("
+                INSERT INTO messages (uuid, author_id, conversation_id, content, metadata, status, encryption_key_id, wrapped_key)
+                VALUES ($1, $2, $3, $4, $5, 'sent', $6, $7)
+                RETURNING id
+            ")
+            .bind(Uuid::new_v4())
+            .bind(author_id)
+            .bind(showcase_id)
+            .bind(row.get::<String, _>("content"))
+            .bind(json!({
+                "highlight": {
+                    "sourceMessageId": message_id,
+                    "sourceRoomId": room_id,
+                    "emoji": emoji,
+                    "reactionCount": reaction_count
+                }
+            }))
+            .bind(row.get::<Option<String>, _>("encryption_key_id"))
+            .bind(row.get::<Option<Vec<u8>>, _>("wrapped_key"))
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("repost_highlight", e))?
+            .get("id");
+
+            query("UPDATE message_highlights SET showcase_message_id = $1 WHERE message_id = $2")
+                .bind(reposted)
+                .bind(message_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("update_highlight", e))?;
+
+            Some(reposted)
+        }
+        None => None,
+    };
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    let payload = json!({
+        "type": "message_highlighted",
+        "data": {
+            "messageId": message_id,
+            "roomId": room_id,
+            "emoji": emoji,
+            "reactionCount": reaction_count,
+            "showcaseRoomId": showcase_room_id,
+            "showcaseMessageId": showcase_message_id
+        }
+    });
+    broadcast_to_room_members(hub, room_id, &payload).await?;
+
+    if let (Some(showcase_id), Some(showcase_message_id)) = (showcase_room_id, showcase_message_id) {
+        let repost = message_frame("room_message", json!({
+            "id": showcase_message_id,
+            "roomId": showcase_id,
+            "authorId": author_id,
+            "content": content,
+            "timestamp": Utc::now(),
+            "highlightOf": message_id
+        }));
+        broadcast_to_room_members(hub, showcase_id, &repost).await?;
+    }
+
+    tracing::info!(message_id = %message_id, room_id = %room_id, emoji = %emoji, "⭐ Message mis en avant");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn showcase(is_room: bool, is_public: bool, role: Option<&str>) -> ShowcaseRoom {
+        ShowcaseRoom { is_room, is_public, role: role.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_showcase_requires_owner_or_admin_room() {
+        assert!(check_showcase(true, &showcase(true, true, Some("owner"))).is_ok());
+        assert!(check_showcase(true, &showcase(true, true, Some("admin"))).is_ok());
+
+        // Modérateur, simple membre ou non-membre de la vitrine : refusé
+        for role in [Some("moderator"), Some("member"), None] {
+            let error = check_showcase(true, &showcase(true, true, role)).unwrap_err();
+            assert!(matches!(error, ChatError::Unauthorized { .. }), "{:?}", role);
+        }
+
+        // Une conversation directe n'est pas une vitrine
+        assert!(check_showcase(true, &showcase(false, false, Some("owner"))).is_err());
+    }
+
+    #[test]
+    fn test_private_room_not_showcased_publicly() {
+        assert!(check_showcase(false, &showcase(true, true, Some("owner"))).is_err());
+        assert!(check_showcase(false, &showcase(true, false, Some("owner"))).is_ok());
+        assert!(check_showcase(true, &showcase(true, false, Some("admin"))).is_ok());
+    }
+}
@@ -0,0 +1,557 @@
+//! File des trames entrantes d'une connexion
+//!
+//! La lecture du socket et le traitement des commandes sont découplés : le
+//! lecteur (`read_frames`) pousse chaque trame dans la `ConnectionInbox`, un
+//! worker unique par connexion la traite.
+//!
+//! Garanties :
+//! - Les trames d'une connexion sont traitées une à une, dans l'ordre de
+//!   réception ; un message A reçu avant B est persisté (identifiant, ordre
+//!   d'historique) avant B, quelle que soit la durée de traitement de A
+//! - Les réponses sont renvoyées au client dans ce même ordre
+//! - `close()` traite les trames déjà reçues avant de rendre la main
+//! - Au-delà de `MAX_PENDING_FRAMES` trames en attente, la connexion est
+//!   fermée avec le code `rate_limited` (4004)
+//!
+//! Aucune garantie n'est donnée entre deux connexions distinctes, même pour
+//! un même utilisateur.
+//!
+//! Une trame `{"type":"batch","commands":[...]}` regroupe plusieurs commandes
+//! (liaisons à forte latence) : elles sont exécutées dans l'ordre et la réponse
+//! `batch_result` donne le résultat de chacune, succès ou erreur, sans qu'un
+//! échec n'interrompe les suivantes.
+
+use crate::client::Client;
+use crate::close_codes::CloseReason;
+use crate::error::{ChatError, Result};
+use crate::hub::common::ChatHub;
+use crate::hub::channel_websocket::{handle_room_websocket_message, parse_websocket_message};
+use crate::hub::direct_messages_websocket::{handle_dm_websocket_message, parse_dm_websocket_message};
+use crate::validation::parse_client_json;
+use futures_util::future::BoxFuture;
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+// ================================================================
+// TRAITEMENT DES TRAMES
+// ================================================================
+
+/// Traitement d'une trame ; retourne la réponse éventuelle pour le client
+pub trait FrameHandler: Send + Sync + 'static {
+    fn handle<'a>(&'a self, frame: &'a str) -> BoxFuture<'a, Option<String>>;
+}
+
+/// Trame d'erreur renvoyée au client
+fn error_frame(action: &str, error: &ChatError) -> String {
+    json!({
+        "type": "error",
+        "data": {
+            "action": action,
+            "error": error.to_string()
+        }
+    }).to_string()
+}
+
+/// Identifiant utilisateur d'une trame : celui de la connexion authentifiée
+///
+/// Sans `userId`, la trame agit au nom de la connexion ; un `userId` différent
+/// est refusé : une connexion n'agit jamais au nom d'un autre utilisateur.
+pub fn connection_user_id(data: &Value, client: &Client) -> Result<i64> {
+    let user_id = client.user_id as i64;
+    match data.get("userId") {
+        None | Some(Value::Null) => Ok(user_id),
+        Some(claimed) if claimed.as_i64() == Some(user_id) => Ok(user_id),
+        Some(claimed) => {
+            tracing::warn!(user_id = %user_id, claimed = %claimed, "🚫 Trame au nom d'un autre utilisateur refusée");
+            Err(ChatError::unauthorized("user_id_mismatch"))
+        }
+    }
+}
+
+/// Commandes de salon (production), au nom du client de la connexion
+pub struct RoomFrameHandler {
+    hub: Arc<ChatHub>,
+    client: Client,
+}
+
+impl RoomFrameHandler {
+    pub fn new(hub: Arc<ChatHub>, client: Client) -> Self {
+        Self { hub, client }
+    }
+}
+
+impl FrameHandler for RoomFrameHandler {
+    fn handle<'a>(&'a self, frame: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let message = match parse_websocket_message(frame, &self.client) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!(error = %e, "❌ Trame entrante invalide");
+                    return Some(error_frame("parse", &e));
+                }
+            };
+
+            match handle_room_websocket_message(&self.hub, &self.client, message).await {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::error!(error = %e, "❌ Échec du traitement de la trame");
+                    Some(error_frame("dispatch", &e))
+                }
+            }
+        })
+    }
+}
+
+/// Commandes de messages directs (production), au nom du client de la connexion
+pub struct DmFrameHandler {
+    hub: Arc<ChatHub>,
+    client: Client,
+}
+
+impl DmFrameHandler {
+    pub fn new(hub: Arc<ChatHub>, client: Client) -> Self {
+        Self { hub, client }
+    }
+}
+
+impl FrameHandler for DmFrameHandler {
+    fn handle<'a>(&'a self, frame: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let message = match parse_dm_websocket_message(frame, &self.client) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!(error = %e, "❌ Trame DM entrante invalide");
+                    return Some(error_frame("parse", &e));
+                }
+            };
+
+            match handle_dm_websocket_message(&self.hub, message).await {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::error!(error = %e, "❌ Échec du traitement de la trame DM");
+                    Some(error_frame("dispatch", &e))
+                }
+            }
+        })
+    }
+}
+
+// ================================================================
+// LOTS DE COMMANDES
+// ================================================================
+
+/// Nombre maximal de commandes dans une trame `batch`
+pub const MAX_BATCH_SIZE: usize = 20;
+
+/// Résultat d'une commande d'un lot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchItemResult {
+    /// Position de la commande dans le lot
+    pub index: usize,
+    pub ok: bool,
+    /// Trame de réponse de la commande (absente si elle n'en produit pas)
+    pub response: Option<Value>,
+    /// Erreur (`action`, `error`) si la commande a échoué
+    pub error: Option<Value>,
+}
+
+impl BatchItemResult {
+    /// Une réponse de type `error` marque la commande en échec
+    fn from_response(index: usize, response: Option<String>) -> Self {
+        let response = response.map(|text| serde_json::from_str(&text).unwrap_or(Value::String(text)));
+        match response {
+            Some(frame) if frame.get("type").and_then(Value::as_str) == Some("error") => Self {
+                index,
+                ok: false,
+                response: None,
+                error: frame.get("data").cloned(),
+            },
+            response => Self { index, ok: true, response, error: None },
+        }
+    }
+
+    fn failed(index: usize, action: &str, error: &ChatError) -> Self {
+        Self {
+            index,
+            ok: false,
+            response: None,
+            error: Some(json!({ "action": action, "error": error.to_string() })),
+        }
+    }
+}
+
+/// Commandes d'une trame `batch` ; `None` si la trame n'est pas un lot
+pub fn parse_batch(frame: &str) -> Option<Result<Vec<Value>>> {
+    // Évite une seconde analyse JSON pour les trames ordinaires
+    if !frame.contains("batch") {
+        return None;
+    }
+    let value = match parse_client_json(frame) {
+        Ok(value) => value,
+        Err(_) => return None,
+    };
+    if value.get("type").and_then(Value::as_str) != Some("batch") {
+        return None;
+    }
+
+    let commands = match value.get("commands").and_then(Value::as_array) {
+        Some(commands) => commands.clone(),
+        None => return Some(Err(ChatError::MissingParameter { param: "commands".to_string() })),
+    };
+    if commands.len() > MAX_BATCH_SIZE {
+        return Some(Err(ChatError::OutOfRange {
+            field: "commands".to_string(),
+            value: commands.len() as i64,
+            min: 0,
+            max: MAX_BATCH_SIZE as i64,
+        }));
+    }
+    Some(Ok(commands))
+}
+
+/// Ajoute la prise en charge des trames `batch` à un traitement existant
+pub struct BatchingHandler<H> {
+    inner: H,
+}
+
+impl<H: FrameHandler> BatchingHandler<H> {
+    pub fn new(inner: H) -> Self {
+        Self { inner }
+    }
+}
+
+impl<H: FrameHandler> FrameHandler for BatchingHandler<H> {
+    fn handle<'a>(&'a self, frame: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let commands = match parse_batch(frame) {
+                None => return self.inner.handle(frame).await,
+                Some(Err(e)) => {
+                    tracing::warn!(error = %e, "❌ Lot de commandes refusé");
+                    return Some(error_frame("batch", &e));
+                }
+                Some(Ok(commands)) => commands,
+            };
+
+            let mut results = Vec::with_capacity(commands.len());
+            for (index, command) in commands.iter().enumerate() {
+                if command.get("type").and_then(Value::as_str) == Some("batch") {
+                    results.push(BatchItemResult::failed(index, "batch", &ChatError::InvalidFormat {
+                        field: "type".to_string(),
+                        reason: "Lot imbriqué refusé".to_string(),
+                    }));
+                    continue;
+                }
+                let response = self.inner.handle(&command.to_string()).await;
+                results.push(BatchItemResult::from_response(index, response));
+            }
+
+            tracing::debug!(commands = %results.len(), failed = %results.iter().filter(|r| !r.ok).count(), "📦 Lot de commandes traité");
+            Some(json!({
+                "type": "batch_result",
+                "data": { "results": results }
+            }).to_string())
+        })
+    }
+}
+
+// ================================================================
+// FILE PAR CONNEXION
+// ================================================================
+
+/// Trames en attente de traitement au-delà desquelles la connexion est fermée
+pub const MAX_PENDING_FRAMES: usize = 256;
+
+/// Délai suggéré au client avant de se reconnecter après un débordement
+pub const FLOOD_RETRY_AFTER_SECS: u64 = 5;
+
+pub struct ConnectionInbox {
+    sender: mpsc::UnboundedSender<String>,
+    worker: JoinHandle<usize>,
+    pending: Arc<AtomicUsize>,
+    client: Client,
+}
+
+impl ConnectionInbox {
+    /// Démarre le worker de la connexion ; les réponses partent vers `client`
+    pub fn spawn<H: FrameHandler>(handler: H, client: Client) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        let worker_client = client.clone();
+        let worker_pending = pending.clone();
+        let worker = tokio::spawn(async move {
+            let mut processed = 0;
+            // Une trame à la fois : la suivante attend la fin de la précédente
+            while let Some(frame) = receiver.recv().await {
+                if let Some(response) = handler.handle(&frame).await {
+                    worker_client.send_text(&response);
+                }
+                worker_pending.fetch_sub(1, Ordering::SeqCst);
+                processed += 1;
+            }
+            tracing::debug!(user_id = %worker_client.user_id, processed = %processed, "📭 File entrante fermée");
+            processed
+        });
+
+        Self { sender, worker, pending, client }
+    }
+
+    /// File de commandes de salon (lots compris) pour un client connecté
+    pub fn for_rooms(hub: Arc<ChatHub>, client: Client) -> Self {
+        Self::spawn(BatchingHandler::new(RoomFrameHandler::new(hub, client.clone())), client)
+    }
+
+    /// File de commandes de messages directs (lots compris) pour un client connecté
+    pub fn for_dms(hub: Arc<ChatHub>, client: Client) -> Self {
+        Self::spawn(BatchingHandler::new(DmFrameHandler::new(hub, client.clone())), client)
+    }
+
+    /// Ajoute une trame reçue en fin de file
+    ///
+    /// Un client qui envoie plus vite que le serveur ne traite voit sa
+    /// connexion fermée (`rate_limited`) ; la trame est refusée.
+    pub fn push(&self, frame: String) -> Result<()> {
+        self.client.mark_activity();
+        let pending = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        if pending > MAX_PENDING_FRAMES {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            tracing::warn!(user_id = %self.client.user_id, pending = %pending, "🌊 File entrante saturée, fermeture de la connexion");
+            self.client.close(CloseReason::RateLimited { retry_after_secs: FLOOD_RETRY_AFTER_SECS }, None);
+            return Err(ChatError::RateLimitExceeded {
+                action: "inbound_frames".to_string(),
+                current: pending as u32,
+                limit: MAX_PENDING_FRAMES as u32,
+                window: FLOOD_RETRY_AFTER_SECS,
+            });
+        }
+
+        self.sender.send(frame).map_err(|_| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            ChatError::Internal {
+                message: "File entrante de la connexion fermée".to_string(),
+            }
+        })
+    }
+
+    /// Traite les trames restantes puis arrête le worker ; retourne le nombre de trames traitées
+    pub async fn close(self) -> usize {
+        drop(self.sender);
+        self.worker.await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "❌ Worker de file entrante interrompu");
+            0
+        })
+    }
+}
+
+// ================================================================
+// LECTURE DU SOCKET
+// ================================================================
+
+/// Boucle de lecture d'une connexion : chaque trame texte part dans `inbox`
+///
+/// Un `Pong` rafraîchit le heartbeat du client. La boucle s'arrête à la
+/// trame `Close`, sur erreur de lecture ou quand la file refuse une trame
+/// (débordement) ; les trames déjà reçues sont traitées avant le retour.
+/// Retourne le nombre de trames traitées.
+pub async fn read_frames<S>(inbox: ConnectionInbox, mut stream: S) -> usize
+where
+    S: Stream<Item = std::result::Result<Message, WsError>> + Unpin,
+{
+    while let Some(message) = stream.next().await {
+        match message {
+            Ok(Message::Text(text)) => {
+                if inbox.push(text).is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Pong(_)) => inbox.client.update_heartbeat(),
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!(user_id = %inbox.client.user_id, error = %e, "❌ Erreur de lecture du socket");
+                break;
+            }
+        }
+    }
+    inbox.close().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::time::Duration;
+
+    /// Attribue un numéro de séquence à chaque trame, après un délai variable
+    #[derive(Clone, Default)]
+    struct SequencingHandler {
+        next_seq: Arc<AtomicI64>,
+        persisted: Arc<std::sync::Mutex<Vec<(String, i64)>>>,
+    }
+
+    impl FrameHandler for SequencingHandler {
+        fn handle<'a>(&'a self, frame: &'a str) -> BoxFuture<'a, Option<String>> {
+            Box::pin(async move {
+                // Le premier message est le plus lent à traiter
+                if frame == "A" {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                }
+                let seq = self.next_seq.fetch_add(1, Ordering::SeqCst) + 1;
+                self.persisted.lock().unwrap().push((frame.to_string(), seq));
+                Some(format!("{}:{}", frame, seq))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_back_to_back_messages_keep_send_order() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let handler = SequencingHandler::default();
+        let inbox = ConnectionInbox::spawn(handler.clone(), Client::new(1, "alice".to_string(), tx));
+
+        inbox.push("A".to_string()).unwrap();
+        inbox.push("B".to_string()).unwrap();
+        assert_eq!(inbox.close().await, 2);
+
+        assert_eq!(
+            handler.persisted.lock().unwrap().as_slice(),
+            &[("A".to_string(), 1), ("B".to_string(), 2)]
+        );
+
+        let mut replies = Vec::new();
+        while let Ok(Message::Text(text)) = rx.try_recv() {
+            replies.push(text);
+        }
+        assert_eq!(replies, vec!["A:1".to_string(), "B:2".to_string()]);
+    }
+
+    /// Répond `<type>_ok`, ou une trame d'erreur pour `leave_room`
+    struct EchoHandler;
+
+    impl FrameHandler for EchoHandler {
+        fn handle<'a>(&'a self, frame: &'a str) -> BoxFuture<'a, Option<String>> {
+            Box::pin(async move {
+                let value: Value = serde_json::from_str(frame).unwrap();
+                let msg_type = value["type"].as_str().unwrap_or("").to_string();
+                match msg_type.as_str() {
+                    "leave_room" => Some(error_frame("leave_room", &ChatError::unauthorized("leave_room"))),
+                    "typing" => None,
+                    _ => Some(json!({ "type": format!("{}_ok", msg_type) }).to_string()),
+                }
+            })
+        }
+    }
+
+    async fn batch_results(frame: Value) -> Value {
+        let response = BatchingHandler::new(EchoHandler).handle(&frame.to_string()).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mixed_batch_reports_each_command() {
+        let response = batch_results(json!({
+            "type": "batch",
+            "commands": [
+                { "type": "mark_read", "data": {} },
+                { "type": "leave_room", "data": {} },
+                { "type": "typing", "data": {} },
+                { "type": "batch", "commands": [] },
+                { "type": "get_history", "data": {} }
+            ]
+        })).await;
+
+        assert_eq!(response["type"], "batch_result");
+        let results = response["data"]["results"].as_array().unwrap();
+        let outcomes: Vec<(u64, bool)> = results.iter()
+            .map(|r| (r["index"].as_u64().unwrap(), r["ok"].as_bool().unwrap()))
+            .collect();
+        assert_eq!(outcomes, vec![(0, true), (1, false), (2, true), (3, false), (4, true)]);
+
+        assert_eq!(results[0]["response"]["type"], "mark_read_ok");
+        assert_eq!(results[1]["error"]["action"], "leave_room");
+        assert!(results[2]["response"].is_null());
+        assert_eq!(results[4]["response"]["type"], "get_history_ok");
+    }
+
+    #[tokio::test]
+    async fn test_batch_size_is_bounded() {
+        let commands: Vec<Value> = (0..=MAX_BATCH_SIZE).map(|_| json!({ "type": "mark_read", "data": {} })).collect();
+        let response = batch_results(json!({ "type": "batch", "commands": commands })).await;
+
+        assert_eq!(response["type"], "error");
+        assert_eq!(response["data"]["action"], "batch");
+
+        // Trame ordinaire : transmise telle quelle
+        let plain = BatchingHandler::new(EchoHandler).handle(r#"{"type":"join_room","data":{}}"#).await;
+        assert_eq!(plain.as_deref(), Some(r#"{"type":"join_room_ok"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_push_after_worker_stopped_is_refused() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut inbox = ConnectionInbox::spawn(SequencingHandler::default(), Client::new(1, "alice".to_string(), tx));
+
+        inbox.worker.abort();
+        assert!((&mut inbox.worker).await.is_err());
+
+        assert!(inbox.push("A".to_string()).is_err());
+    }
+
+    /// Ne termine jamais une trame : la file s'accumule
+    struct StalledHandler;
+
+    impl FrameHandler for StalledHandler {
+        fn handle<'a>(&'a self, _frame: &'a str) -> BoxFuture<'a, Option<String>> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flooding_client_is_closed_as_rate_limited() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let inbox = ConnectionInbox::spawn(StalledHandler, Client::new(1, "alice".to_string(), tx));
+
+        for i in 0..MAX_PENDING_FRAMES {
+            inbox.push(i.to_string()).unwrap();
+        }
+        assert!(matches!(inbox.push("de trop".to_string()), Err(ChatError::RateLimitExceeded { .. })));
+
+        match rx.try_recv() {
+            Ok(Message::Close(Some(frame))) => {
+                assert_eq!(u16::from(frame.code), 4004);
+                let reason: Value = serde_json::from_str(&frame.reason).unwrap();
+                assert_eq!(reason["reason"], "rate_limited");
+                assert_eq!(reason["retryAfter"], FLOOD_RETRY_AFTER_SECS);
+            }
+            other => panic!("trame de fermeture attendue, reçu {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_loop_stops_at_close_frame() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let handler = SequencingHandler::default();
+        let inbox = ConnectionInbox::spawn(handler.clone(), Client::new(1, "alice".to_string(), tx));
+
+        let frames = futures_util::stream::iter(vec![
+            Ok(Message::Text("A".to_string())),
+            Ok(Message::Pong(Vec::new())),
+            Ok(Message::Text("B".to_string())),
+            Ok(Message::Close(None)),
+            Ok(Message::Text("après fermeture".to_string())),
+        ]);
+        assert_eq!(read_frames(inbox, frames).await, 2);
+
+        let mut replies = Vec::new();
+        while let Ok(Message::Text(text)) = rx.try_recv() {
+            replies.push(text);
+        }
+        assert_eq!(replies, vec!["A:1".to_string(), "B:2".to_string()]);
+    }
+}
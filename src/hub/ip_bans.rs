@@ -0,0 +1,323 @@
+//! Module de persistance et de partage des IP bannies
+//!
+//! Fonctionnalités :
+//! - Stockage en base (raison, auteur, expiration) dans `banned_ips`
+//! - Chargement au démarrage dans l'`IpMonitor` du hub
+//! - Propagation entre instances via PostgreSQL LISTEN/NOTIFY
+//! - Blocages temporaires levés automatiquement à expiration
+//! - API d'administration : lister, bannir, débannir
+
+use sqlx::{query, query_as, FromRow};
+use sqlx::postgres::PgListener;
+use serde::{Serialize, Deserialize};
+use crate::hub::common::{check_global_admin, ChatHub};
+use crate::security::IpMonitor;
+use crate::error::{ChatError, Result};
+use serde_json::json;
+use chrono::{DateTime, Utc};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Fréquence de purge des blocages expirés
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct BannedIp {
+    pub ip_address: String,
+    pub reason: String,
+    pub banned_by: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Événement diffusé aux autres instances
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum IpBanEvent {
+    Added {
+        ip: String,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    Removed {
+        ip: String,
+    },
+}
+
+impl IpBanEvent {
+    /// Applique l'événement à la liste noire locale
+    pub fn apply(&self, monitor: &mut IpMonitor) {
+        match self {
+            Self::Added { ip, expires_at } => {
+                monitor.blacklist_ip_until(ip, expires_at.map(SystemTime::from));
+            }
+            Self::Removed { ip } => {
+                monitor.unblacklist_ip(ip);
+            }
+        }
+    }
+}
+
+// ================================================================
+// ADMINISTRATION
+// ================================================================
+
+/// Bannit une IP (administrateur global), éventuellement pour une durée limitée
+pub async fn ban_ip(
+    hub: &ChatHub,
+    admin_id: i64,
+    ip: &str,
+    reason: &str,
+    duration: Option<Duration>
+) -> Result<BannedIp> {
+    tracing::info!(admin_id = %admin_id, ip = %ip, "🚫 Bannissement d'une IP");
+
+    let ip = normalize_ip(ip)?;
+    check_global_admin(hub, admin_id, "ban_ip").await?;
+
+    let expires_at = duration
+        .map(|d| chrono::Duration::from_std(d)
+            .map(|d| Utc::now() + d)
+            .map_err(|_| ChatError::configuration_error("Durée de bannissement invalide")))
+        .transpose()?;
+
+    let event = IpBanEvent::Added { ip: ip.clone(), expires_at };
+
+    let banned = if hub.config.security.persist_ip_bans {
+        let mut tx = hub.db.begin().await
+            .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+        let banned = query_as::<_, BannedIp>("
+            INSERT INTO banned_ips (ip_address, reason, banned_by, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (ip_address) DO UPDATE
+            SET reason = EXCLUDED.reason, banned_by = EXCLUDED.banned_by,
+                expires_at = EXCLUDED.expires_at, created_at = NOW()
+            RETURNING ip_address, reason, banned_by, expires_at, created_at
+        ")
+        .bind(&ip)
+        .bind(reason)
+        .bind(admin_id)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("insert_banned_ip", e))?;
+
+        hub.audit_sink.record(&mut *tx, "ip_banned", Some(admin_id), json!({
+            "ip": ip,
+            "reason": reason,
+            "expires_at": expires_at
+        })).await?;
+
+        publish_event(&mut tx, &hub.config.security.ip_ban_channel, &event).await?;
+
+        tx.commit().await
+            .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+        banned
+    } else {
+        BannedIp {
+            ip_address: ip.clone(),
+            reason: reason.to_string(),
+            banned_by: Some(admin_id),
+            expires_at,
+            created_at: Utc::now(),
+        }
+    };
+
+    // Application locale immédiate (la notification reçue en retour est idempotente)
+    event.apply(&mut *hub.ip_monitor.lock().await);
+
+    Ok(banned)
+}
+
+/// Lève le bannissement d'une IP (administrateur global)
+pub async fn unban_ip(hub: &ChatHub, admin_id: i64, ip: &str) -> Result<()> {
+    tracing::info!(admin_id = %admin_id, ip = %ip, "✅ Levée du bannissement d'une IP");
+
+    let ip = normalize_ip(ip)?;
+    check_global_admin(hub, admin_id, "unban_ip").await?;
+
+    let event = IpBanEvent::Removed { ip: ip.clone() };
+
+    if hub.config.security.persist_ip_bans {
+        let mut tx = hub.db.begin().await
+            .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+        let deleted = query("DELETE FROM banned_ips WHERE ip_address = $1")
+            .bind(&ip)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("delete_banned_ip", e))?
+            .rows_affected();
+
+        if deleted == 0 {
+            return Err(ChatError::not_found("banned_ip", &ip));
+        }
+
+        hub.audit_sink.record(&mut *tx, "ip_unbanned", Some(admin_id), json!({ "ip": ip })).await?;
+
+        publish_event(&mut tx, &hub.config.security.ip_ban_channel, &event).await?;
+
+        tx.commit().await
+            .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+    }
+
+    event.apply(&mut *hub.ip_monitor.lock().await);
+    Ok(())
+}
+
+/// Liste les bannissements actifs (administrateur global)
+pub async fn list_ip_bans(hub: &ChatHub, admin_id: i64) -> Result<Vec<BannedIp>> {
+    check_global_admin(hub, admin_id, "list_ip_bans").await?;
+
+    if !hub.config.security.persist_ip_bans {
+        return Err(ChatError::configuration_error("Persistance des IP bannies désactivée"));
+    }
+
+    fetch_active_bans(hub).await
+}
+
+// ================================================================
+// SYNCHRONISATION
+// ================================================================
+
+/// Charge les bannissements actifs dans la liste noire locale (au démarrage)
+pub async fn load_ip_bans(hub: &ChatHub) -> Result<usize> {
+    if !hub.config.security.persist_ip_bans {
+        return Ok(0);
+    }
+
+    let bans = fetch_active_bans(hub).await?;
+
+    let mut monitor = hub.ip_monitor.lock().await;
+    for ban in &bans {
+        monitor.blacklist_ip_until(&ban.ip_address, ban.expires_at.map(SystemTime::from));
+    }
+
+    tracing::info!(count = %bans.len(), "📥 IP bannies chargées");
+    Ok(bans.len())
+}
+
+/// Démarre l'écoute des bannissements émis par les autres instances
+///
+/// La même tâche purge périodiquement les blocages temporaires expirés.
+pub async fn spawn_ip_ban_sync(hub: Arc<ChatHub>) -> Result<Option<tokio::task::JoinHandle<()>>> {
+    if !hub.config.security.persist_ip_bans {
+        return Ok(None);
+    }
+
+    let mut listener = PgListener::connect_with(&hub.db).await
+        .map_err(|e| ChatError::from_sqlx_error("ip_ban_listener_connect", e))?;
+    listener.listen(&hub.config.security.ip_ban_channel).await
+        .map_err(|e| ChatError::from_sqlx_error("ip_ban_listener_listen", e))?;
+
+    let handle = tokio::spawn(async move {
+        let mut sweep = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+
+        loop {
+            tokio::select! {
+                notification = listener.recv() => match notification {
+                    Ok(notification) => match serde_json::from_str::<IpBanEvent>(notification.payload()) {
+                        Ok(event) => event.apply(&mut *hub.ip_monitor.lock().await),
+                        Err(e) => tracing::warn!(error = %e, "⚠️ Notification de bannissement illisible"),
+                    },
+                    // PgListener se reconnecte seul au prochain recv()
+                    Err(e) => tracing::warn!(error = %e, "⚠️ Erreur d'écoute des bannissements"),
+                },
+                _ = sweep.tick() => {
+                    if let Err(e) = purge_expired_ip_bans(&hub).await {
+                        tracing::warn!(error = %e, "⚠️ Échec de la purge des IP bannies");
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(Some(handle))
+}
+
+/// Supprime les blocages temporaires expirés (mémoire et base)
+pub async fn purge_expired_ip_bans(hub: &ChatHub) -> Result<u64> {
+    hub.ip_monitor.lock().await.purge_expired();
+
+    let purged = query("DELETE FROM banned_ips WHERE expires_at IS NOT NULL AND expires_at <= NOW()")
+        .execute(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("purge_banned_ips", e))?
+        .rows_affected();
+
+    if purged > 0 {
+        tracing::info!(count = %purged, "⏰ Bannissements expirés supprimés");
+    }
+    Ok(purged)
+}
+
+// ================================================================
+// FONCTIONS UTILITAIRES
+// ================================================================
+
+async fn fetch_active_bans(hub: &ChatHub) -> Result<Vec<BannedIp>> {
+    query_as::<_, BannedIp>("
+        SELECT ip_address, reason, banned_by, expires_at, created_at
+        FROM banned_ips
+        WHERE expires_at IS NULL OR expires_at > NOW()
+        ORDER BY created_at DESC
+    ")
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("list_banned_ips", e))
+}
+
+async fn publish_event(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    channel: &str,
+    event: &IpBanEvent
+) -> Result<()> {
+    let payload = serde_json::to_string(event).map_err(ChatError::from_json_error)?;
+
+    // La notification n'est délivrée qu'au commit de la transaction
+    query("SELECT pg_notify($1, $2)")
+        .bind(channel)
+        .bind(payload)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("notify_ip_ban", e))?;
+
+    Ok(())
+}
+
+fn normalize_ip(ip: &str) -> Result<String> {
+    ip.trim()
+        .parse::<IpAddr>()
+        .map(|addr| addr.to_string())
+        .map_err(|_| ChatError::configuration_error("Adresse IP invalide"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::SecurityAction;
+
+    #[test]
+    fn test_ban_event_roundtrip_and_apply() {
+        let expires_at = Utc::now() + chrono::Duration::minutes(5);
+        let added = IpBanEvent::Added { ip: "198.51.100.4".to_string(), expires_at: Some(expires_at) };
+
+        let payload = serde_json::to_string(&added).unwrap();
+        assert!(payload.contains("\"action\":\"added\""));
+        let received: IpBanEvent = serde_json::from_str(&payload).unwrap();
+        assert_eq!(received, added);
+
+        let mut monitor = IpMonitor::new();
+        received.apply(&mut monitor);
+        assert!(monitor.check_ip("198.51.100.4", &SecurityAction::SendMessage).is_err());
+
+        IpBanEvent::Removed { ip: "198.51.100.4".to_string() }.apply(&mut monitor);
+        assert!(monitor.check_ip("198.51.100.4", &SecurityAction::SendMessage).is_ok());
+    }
+}
@@ -0,0 +1,211 @@
+//! Module de gestion des messages longs
+//!
+//! Un contenu dépassant `max_message_length` (mais pas `max_long_message_length`)
+//! est accepté comme « message long » :
+//! - La ligne `messages` ne contient qu'un aperçu, seul diffusé en temps réel
+//! - Le contenu complet est stocké dans `message_bodies`
+//! - Les clients récupèrent le corps complet à la demande via `get_message_body`
+
+use sqlx::{query, Row, Transaction, Postgres};
+use serde::Serialize;
+use crate::hub::common::ChatHub;
+use crate::hub::encrypted_rooms::open_row_content;
+use crate::hub::held_messages::held_clause;
+use crate::hub::visibility::visibility_clause;
+use crate::config::LimitsConfig;
+use crate::validation::validate_message_content;
+use crate::error::{ChatError, Result};
+use serde_json::{json, Value};
+
+/// Longueur de l'aperçu d'un message long (en caractères)
+pub const LONG_MESSAGE_PREVIEW_LENGTH: usize = 500;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Contenu prêt à être stocké
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedContent {
+    /// Contenu de la ligne `messages` (aperçu pour un message long)
+    pub stored: String,
+    /// Corps complet à stocker dans `message_bodies`
+    pub body: Option<String>,
+}
+
+impl PreparedContent {
+    /// Valide le contenu et le découpe en aperçu + corps s'il est trop long
+    pub fn prepare(content: &str, limits: &LimitsConfig) -> Result<Self> {
+        if content.len() <= limits.max_message_length {
+            validate_message_content(content, limits.max_message_length)?;
+            return Ok(Self { stored: content.to_string(), body: None });
+        }
+
+        validate_message_content(content, limits.max_long_message_length)?;
+
+        Ok(Self {
+            stored: build_preview(content),
+            body: Some(content.to_string()),
+        })
+    }
+
+    pub fn is_long(&self) -> bool {
+        self.body.is_some()
+    }
+
+    /// Longueur complète (en octets) pour les clients, `None` si message court
+    pub fn full_length(&self) -> Option<usize> {
+        self.body.as_ref().map(|body| body.len())
+    }
+
+    /// Métadonnées indiquant aux clients qu'un corps complet existe
+    pub fn annotate_metadata(&self, metadata: &mut Value) {
+        if let Some(full_length) = self.full_length() {
+            metadata["longBody"] = json!({ "fullLength": full_length });
+        }
+    }
+}
+
+/// Corps complet d'un message
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageBody {
+    pub message_id: i64,
+    pub content: String,
+    pub is_long: bool,
+}
+
+/// Construit l'aperçu d'un contenu (coupé sur une frontière de caractère)
+pub fn build_preview(content: &str) -> String {
+    match content.char_indices().nth(LONG_MESSAGE_PREVIEW_LENGTH) {
+        Some((cut, _)) => format!("{}…", &content[..cut]),
+        None => content.to_string(),
+    }
+}
+
+// ================================================================
+// STOCKAGE ET LECTURE
+// ================================================================
+
+/// Enregistre le corps complet d'un message long
+pub(crate) async fn store_message_body(
+    tx: &mut Transaction<'_, Postgres>,
+    message_id: i64,
+    prepared: &PreparedContent
+) -> Result<()> {
+    let Some(body) = &prepared.body else {
+        return Ok(());
+    };
+
+    query("
+        INSERT INTO message_bodies (message_id, content)
+        VALUES ($1, $2)
+    ")
+    .bind(message_id)
+    .bind(body)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_message_body", e))?;
+
+    Ok(())
+}
+
+/// Retire le corps complet d'un message édité (une édition est toujours courte)
+///
+/// Sans cela, `get_message_body` servirait l'ancien contenu et `longBody`
+/// annoncerait encore sa longueur.
+pub(crate) async fn clear_message_body(
+    tx: &mut Transaction<'_, Postgres>,
+    message_id: i64
+) -> Result<()> {
+    let removed = query("DELETE FROM message_bodies WHERE message_id = $1")
+        .bind(message_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("delete_message_body", e))?
+        .rows_affected();
+
+    if removed > 0 {
+        query("UPDATE messages SET metadata = metadata - 'longBody' WHERE id = $1")
+            .bind(message_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("clear_long_body_metadata", e))?;
+    }
+    Ok(())
+}
+
+/// Récupère le corps complet d'un message (membre du salon ou participant du DM)
+pub async fn get_message_body(hub: &ChatHub, message_id: i64, user_id: i64) -> Result<MessageBody> {
+    tracing::debug!(message_id = %message_id, user_id = %user_id, "📄 Récupération du corps complet");
+
+    let row = query(&message_body_query())
+    .bind(message_id)
+    .bind(user_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_message_body", e))?
+    .ok_or_else(|| ChatError::not_found("message", &message_id.to_string()))?;
+
+    let is_long = row.get::<Option<String>, _>("full_content").is_some();
+    let content = open_row_content(hub.message_cipher.as_ref(), &row, if is_long { "full_content" } else { "content" })?;
+
+    Ok(MessageBody {
+        message_id,
+        is_long,
+        content,
+    })
+}
+
+/// Corps d'un message visible de `$2` : ni restreint hors de sa portée, ni retenu
+pub(crate) fn message_body_query() -> String {
+    format!("
+        SELECT m.content, mb.content as full_content, m.encryption_key_id, m.wrapped_key
+        FROM messages m
+        LEFT JOIN message_bodies mb ON mb.message_id = m.id
+        WHERE m.id = $1 AND m.status != 'deleted'
+          AND {} AND {}
+          AND (
+            EXISTS (
+                SELECT 1 FROM conversation_members cm
+                WHERE cm.conversation_id = m.conversation_id AND cm.user_id = $2 AND cm.left_at IS NULL
+            )
+            OR EXISTS (
+                SELECT 1 FROM dm_conversations dc
+                WHERE dc.id = m.conversation_id AND (dc.user1_id = $2 OR dc.user2_id = $2)
+            )
+          )
+    ", visibility_clause(2), held_clause(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_message_stores_preview_and_full_body() {
+        let limits = LimitsConfig::default();
+        let content = "é".repeat(limits.max_message_length);
+
+        let prepared = PreparedContent::prepare(&content, &limits).unwrap();
+        assert!(prepared.is_long());
+        assert_eq!(prepared.stored.chars().count(), LONG_MESSAGE_PREVIEW_LENGTH + 1);
+        assert!(content.starts_with(prepared.stored.trim_end_matches('…')));
+        assert_eq!(prepared.body.as_deref(), Some(content.as_str()));
+
+        let mut metadata = json!({});
+        prepared.annotate_metadata(&mut metadata);
+        assert_eq!(metadata["longBody"]["fullLength"], content.len());
+    }
+
+    #[test]
+    fn test_short_and_oversized_messages() {
+        let limits = LimitsConfig::default();
+
+        let prepared = PreparedContent::prepare("bonjour", &limits).unwrap();
+        assert_eq!(prepared.stored, "bonjour");
+        assert!(!prepared.is_long());
+
+        let oversized = "a".repeat(limits.max_long_message_length + 1);
+        assert!(PreparedContent::prepare(&oversized, &limits).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Tâche de maintenance du hub
+//!
+//! Exécutée à chaque intervalle de heartbeat :
+//! - Fermeture des connexions mortes ou au jeton expiré
+//! - Fin des délais de reconnexion (`limits.reconnect_grace`) : l'utilisateur
+//!   qui n'est pas revenu passe hors ligne, quitte ses salons et les membres
+//!   restants reçoivent `member_left`. Revenu à temps, il retrouve ses salons
+//!   sans aucune annonce.
+//! - Retrait de `hub.rooms` des salons restés vides pendant
+//!   `limits.empty_room_grace` (les salons éphémères ne s'accumulent plus en
+//!   mémoire). Seule l'entrée en mémoire disparaît : le salon persisté est
+//!   intact et une prochaine jointure recrée l'entrée. Un membre qui revient
+//!   avant la fin du délai retrouve la même entrée.
+//! - Levée du mode anti-raid arrivé à échéance (`anti_raid.auto_disable_after`)
+//! - Export de l'attente des verrous de `hub.clients`, `hub.sessions` et `hub.rooms`
+//!   (`hub_lock_wait_seconds`, `hub_lock_wait_max_seconds`, `hub_lock_contended`)
+//!   sur l'intervalle écoulé
+//! - Export de l'occupation du pool PostgreSQL (`db_pool_connections_in_use`,
+//!   `db_pool_connections_idle`, `db_pool_acquire_wait_seconds`) et des
+//!   attentes de connexion expirées depuis le passage précédent (`db_pool_exhausted_total`)
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::hub::common::ChatHub;
+use crate::hub::anti_raid::expire_anti_raid;
+use crate::hub::presence_subscriptions::publish_offline;
+use crate::db_pool::{probe_acquire, PoolStats};
+use crate::error::ChatError;
+use crate::room_id::RoomId;
+use serde_json::json;
+
+// ================================================================
+// DÉLAI DE RECONNEXION
+// ================================================================
+
+/// Utilisateur déconnecté dont les adhésions sont conservées
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Departure {
+    pub username: String,
+    /// Fin du délai de reconnexion
+    pub deadline: Instant,
+}
+
+impl Departure {
+    pub fn new(username: String, deadline: Instant) -> Self {
+        Self { username, deadline }
+    }
+}
+
+/// Retire les départs dont le délai est écoulé (les reconnectés en sont déjà sortis)
+pub fn take_expired_departures(departures: &mut HashMap<i32, Departure>, now: Instant) -> Vec<(i32, Departure)> {
+    let expired: Vec<i32> = departures.iter()
+        .filter(|(_, departure)| departure.deadline <= now)
+        .map(|(user_id, _)| *user_id)
+        .collect();
+
+    expired.into_iter()
+        .filter_map(|user_id| departures.remove(&user_id).map(|departure| (user_id, departure)))
+        .collect()
+}
+
+/// Fait quitter leurs salons aux utilisateurs non revenus dans le délai
+///
+/// Le verrou des départs est tenu jusqu'au bout : une reconnexion concurrente
+/// attend la fin du traitement, et un utilisateur dont une session est déjà
+/// ouverte garde ses salons.
+pub async fn expire_departures(hub: &ChatHub) -> usize {
+    let mut departures = hub.departures.lock().await;
+    let mut expired = Vec::new();
+    for (user_id, departure) in take_expired_departures(&mut departures, Instant::now()) {
+        if !hub.sessions.contains_key(&user_id).await {
+            expired.push((user_id, departure));
+        }
+    }
+
+    for (user_id, departure) in &expired {
+        // Avant le nettoyage des salons : leurs abonnés doivent être prévenus
+        publish_offline(hub, *user_id).await;
+        let left = hub.leave_all_rooms(*user_id).await;
+        for (room, remaining) in left {
+            let payload = json!({
+                "type": "member_left",
+                "data": {
+                    "roomName": room,
+                    "userId": user_id,
+                    "username": departure.username,
+                    "reason": "disconnected"
+                }
+            }).to_string();
+
+            for client in hub.clients.get_many(remaining).await {
+                client.send_text(&payload);
+            }
+        }
+        tracing::info!(user_id = %user_id, "⌛ Délai de reconnexion écoulé, salons quittés");
+    }
+    expired.len()
+}
+
+// ================================================================
+// SALONS VIDES
+// ================================================================
+
+/// Salons vides en mémoire, avec l'instant où ils ont été vus vides pour la première fois
+#[derive(Debug, Default)]
+pub struct EmptyRoomTracker {
+    emptied_since: HashMap<RoomId, Instant>,
+}
+
+impl EmptyRoomTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parmi les salons vus vides à ce passage, ceux qui le sont depuis au moins `grace`
+    ///
+    /// Les salons absents de `empty` (repeuplés ou retirés) sont oubliés ; les
+    /// salons retournés aussi, l'appelant devant les retirer.
+    pub fn expired(&mut self, empty: &[RoomId], now: Instant, grace: Duration) -> Vec<RoomId> {
+        self.emptied_since.retain(|room, _| empty.contains(room));
+
+        let mut expired = Vec::new();
+        for room in empty {
+            let since = *self.emptied_since.entry(room.clone()).or_insert(now);
+            if now.duration_since(since) >= grace {
+                expired.push(room.clone());
+            }
+        }
+
+        for room in &expired {
+            self.emptied_since.remove(room);
+        }
+        expired
+    }
+
+    /// Nombre de salons vides en attente de retrait
+    pub fn pending(&self) -> usize {
+        self.emptied_since.len()
+    }
+}
+
+/// Retire les salons restés vides au-delà du délai configuré
+pub async fn cleanup_empty_rooms(hub: &ChatHub) -> usize {
+    let grace = hub.config.limits.empty_room_grace;
+    let empty = hub.rooms.filter_map(|room, members| members.is_empty().then(|| room.clone())).await;
+    let expired = hub.empty_rooms.lock().await.expired(&empty, Instant::now(), grace);
+
+    // Un salon rejoint depuis le parcours est conservé
+    let mut removed = Vec::new();
+    for room in expired {
+        if hub.rooms.remove_if(&room, Vec::is_empty).await.is_some() {
+            removed.push(room);
+        }
+    }
+
+    if !removed.is_empty() {
+        tracing::info!(removed = %removed.len(), grace_seconds = %grace.as_secs(), "🧹 Salons vides retirés de la mémoire");
+    }
+    removed.len()
+}
+
+// ================================================================
+// ATTENTE DES VERROUS
+// ================================================================
+
+/// Attente d'un verrou au-delà de laquelle la contention est signalée
+const LOCK_WAIT_WARNING: Duration = Duration::from_millis(100);
+
+/// Exporte l'attente des verrous des tables du hub depuis le dernier passage
+pub async fn report_lock_waits(hub: &ChatHub) {
+    let clients = hub.clients.lock_waits().take();
+    let sessions = hub.sessions.lock_waits().take();
+    let rooms = hub.rooms.lock_waits().take();
+    hub.metrics.lock_wait("clients", &clients).await;
+    hub.metrics.lock_wait("sessions", &sessions).await;
+    hub.metrics.lock_wait("rooms", &rooms).await;
+
+    let slowest = clients.max_wait.max(sessions.max_wait).max(rooms.max_wait);
+    if slowest >= LOCK_WAIT_WARNING {
+        tracing::warn!(
+            clients_max_ms = %clients.max_wait.as_millis(),
+            sessions_max_ms = %sessions.max_wait.as_millis(),
+            rooms_max_ms = %rooms.max_wait.as_millis(),
+            "🐢 Contention élevée sur les verrous du hub"
+        );
+    }
+}
+
+// ================================================================
+// POOL DE CONNEXIONS
+// ================================================================
+
+/// Exporte l'occupation du pool et l'attente d'une connexion libre
+pub async fn report_pool_stats(hub: &ChatHub) {
+    let stats = PoolStats::of(&hub.db);
+    let wait = probe_acquire(&hub.db).await;
+    hub.metrics.db_pool(&stats, wait.as_ref().ok().copied()).await;
+
+    match wait {
+        Ok(wait) if stats.is_saturated() => {
+            tracing::warn!(in_use = %stats.in_use, max = %stats.max, wait_ms = %wait.as_millis(), "🐢 Pool de connexions saturé");
+        }
+        Ok(_) => {}
+        Err(ChatError::DatabasePoolExhausted { .. }) => {
+            tracing::warn!(in_use = %stats.in_use, max = %stats.max, "🚨 Aucune connexion libre avant acquire_timeout");
+        }
+        Err(e) => tracing::warn!(error = %e, "⚠️ Connexion à la base de données impossible"),
+    }
+}
+
+// ================================================================
+// TÂCHE PÉRIODIQUE
+// ================================================================
+
+/// Lance la maintenance périodique du hub (intervalle de heartbeat)
+pub fn spawn_maintenance_task(hub: Arc<ChatHub>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(hub.config.server.heartbeat_interval);
+
+        loop {
+            ticker.tick().await;
+            hub.cleanup_dead_connections().await;
+            expire_departures(&hub).await;
+            cleanup_empty_rooms(&hub).await;
+            expire_anti_raid(&hub).await;
+            report_lock_waits(&hub).await;
+            report_pool_stats(&hub).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(name: &str) -> RoomId {
+        RoomId::new(name).unwrap()
+    }
+
+    #[test]
+    fn test_departures_expire_at_deadline() {
+        let start = Instant::now();
+        let mut departures = HashMap::from([
+            (1, Departure::new("alice".to_string(), start + Duration::from_secs(30))),
+            (2, Departure::new("bob".to_string(), start + Duration::from_secs(60))),
+        ]);
+
+        assert!(take_expired_departures(&mut departures, start + Duration::from_secs(29)).is_empty());
+
+        let expired = take_expired_departures(&mut departures, start + Duration::from_secs(30));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, 1);
+        assert_eq!(expired[0].1.username, "alice");
+        assert!(departures.contains_key(&2));
+    }
+
+    #[test]
+    fn test_room_emptied_again_restarts_grace_period() {
+        let grace = Duration::from_secs(300);
+        let start = Instant::now();
+        let mut tracker = EmptyRoomTracker::new();
+        let empty = [room("ephemere")];
+
+        assert!(tracker.expired(&empty, start, grace).is_empty());
+        assert!(tracker.expired(&empty, start + Duration::from_secs(299), grace).is_empty());
+
+        // Repeuplé à un passage : oublié, le délai repart du passage suivant
+        assert!(tracker.expired(&[], start + Duration::from_secs(350), grace).is_empty());
+        assert_eq!(tracker.pending(), 0);
+        assert!(tracker.expired(&empty, start + Duration::from_secs(400), grace).is_empty());
+        assert!(tracker.expired(&empty, start + Duration::from_secs(699), grace).is_empty());
+        assert_eq!(tracker.expired(&empty, start + Duration::from_secs(700), grace), vec![room("ephemere")]);
+        assert_eq!(tracker.pending(), 0);
+    }
+}
@@ -0,0 +1,104 @@
+//! Restauration des salons à la reconnexion
+//!
+//! `hub.rooms` ne vit qu'en mémoire : une déconnexion en retire l'utilisateur.
+//! À l'enregistrement, ses adhésions persistées (`conversation_members` actifs,
+//! salons non archivés) sont relues et l'utilisateur est replacé dans chacun
+//! de ces salons :
+//! - Il reçoit une trame `rooms_restored` listant les salons retrouvés
+//! - Les autres membres présents reçoivent `member_joined` (`restored: true`)
+//! - Les entrées ajoutées en mémoire sans adhésion (consultation passagère)
+//!   ne sont pas restaurées
+
+use serde::Serialize;
+use crate::hub::common::ChatHub;
+use crate::error::Result;
+use crate::room_id::RoomId;
+use serde_json::json;
+
+/// Adhésion persistée d'un utilisateur
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistedMembership {
+    pub room_id: i64,
+    pub room_name: RoomId,
+}
+
+// ================================================================
+// LECTURE
+// ================================================================
+
+/// Salons dont l'utilisateur est membre actif
+pub async fn load_persisted_memberships(hub: &ChatHub, user_id: i64) -> Result<Vec<PersistedMembership>> {
+    hub.room_repository.persisted_memberships(user_id).await
+}
+
+// ================================================================
+// RESTAURATION
+// ================================================================
+
+/// Replace l'utilisateur dans les salons donnés et prévient les présents
+///
+/// Retourne les salons effectivement restaurés (ceux où il était déjà
+/// présent en mémoire sont ignorés).
+pub async fn restore_memberships(
+    hub: &ChatHub,
+    user_id: i32,
+    username: &str,
+    memberships: &[PersistedMembership]
+) -> Vec<PersistedMembership> {
+    let mut restored = Vec::with_capacity(memberships.len());
+    let mut notifications = Vec::new();
+
+    for membership in memberships {
+        let present = hub.rooms.update(membership.room_name.clone(), |members| {
+            if members.contains(&user_id) {
+                return None;
+            }
+            let present = members.clone();
+            members.push(user_id);
+            Some(present)
+        }).await;
+
+        if let Some(present) = present {
+            notifications.push((membership, present));
+            restored.push(membership.clone());
+        }
+    }
+
+    if restored.is_empty() {
+        return restored;
+    }
+
+    if let Some(client) = hub.clients.get(&user_id).await {
+        client.send_text(&json!({
+            "type": "rooms_restored",
+            "data": { "rooms": restored }
+        }).to_string());
+    }
+
+    for (membership, present) in notifications {
+        let payload = json!({
+            "type": "member_joined",
+            "data": {
+                "roomId": membership.room_id,
+                "roomName": membership.room_name,
+                "userId": user_id,
+                "username": username,
+                "restored": true
+            }
+        }).to_string();
+
+        for client in hub.clients.get_many(present).await {
+            client.send_text(&payload);
+        }
+    }
+
+    tracing::info!(user_id = %user_id, restored_count = %restored.len(), "🔁 Salons restaurés à la reconnexion");
+    restored
+}
+
+/// Relit les adhésions de l'utilisateur et le replace dans ses salons
+pub async fn restore_room_memberships(hub: &ChatHub, user_id: i32, username: &str) -> Result<Vec<PersistedMembership>> {
+    let memberships = load_persisted_memberships(hub, user_id as i64).await?;
+    Ok(restore_memberships(hub, user_id, username, &memberships).await)
+}
@@ -0,0 +1,580 @@
+//! Module d'analyse et de résolution des mentions
+//!
+//! Mentions supportées :
+//! - `@username` : un utilisateur
+//! - `@everyone` : tous les membres actifs du salon
+//! - `@here` : les membres actuellement connectés
+//! - `@owners`, `@admins`, `@moderators` : les membres d'un rôle
+//!
+//! Les mentions de masse sont réservées aux rôles de modération et limitées
+//! en fréquence. Les destinataires individuels (utilisateur, rôle) sont stockés
+//! avec le type de mention, sans doublon et dans la limite de
+//! `limits.max_mentions_per_message` ; `@everyone` et `@here` ne sont qu'un
+//! indicateur sur le message (aucune ligne par membre).
+
+use sqlx::{query, Row, Transaction, Postgres};
+use serde::{Serialize, Deserialize};
+use regex::Regex;
+use once_cell::sync::Lazy;
+use crate::hub::common::ChatHub;
+use crate::hub::channels::is_moderator_role;
+use crate::hub::visibility::MessageVisibility;
+use crate::hub::mutes::{muted_room_members, retain_unmuted};
+use crate::hub::room_repository::WriteContext;
+use crate::error::{ChatError, Result};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Limite par défaut des mentions individuelles d'un message
+pub const DEFAULT_MAX_MENTIONS_PER_MESSAGE: usize = 50;
+
+/// `@` en début de texte ou après un caractère hors mot, suivi d'un nom au
+/// jeu de caractères de `validation::normalize_username` : `ops@here.io` ou
+/// `foo@everyone` ne sont pas des mentions
+static MENTION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|[^\w.@-])@([A-Za-z0-9][A-Za-z0-9_.-]*)").unwrap());
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionKind {
+    User,
+    Everyone,
+    Here,
+    Role,
+}
+
+impl MentionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Everyone => "everyone",
+            Self::Here => "here",
+            Self::Role => "role",
+        }
+    }
+
+    /// Mention visant potentiellement plusieurs membres
+    pub fn is_mass(&self) -> bool {
+        !matches!(self, Self::User)
+    }
+}
+
+/// Mention extraite du contenu d'un message
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ParsedMention {
+    pub kind: MentionKind,
+    /// Nom d'utilisateur ou rôle ciblé (vide pour everyone/here)
+    pub target: String,
+}
+
+/// Destinataire effectif d'une mention
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionRecipient {
+    pub user_id: i64,
+    pub kind: MentionKind,
+}
+
+/// Mentions résolues d'un message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedMentions {
+    /// Destinataires individuels, stockés dans `message_mentions`
+    pub recipients: Vec<MentionRecipient>,
+    /// `@everyone` : indicateur sur le message
+    pub everyone: bool,
+    /// `@here` : indicateur sur le message
+    pub here: bool,
+}
+
+impl ResolvedMentions {
+    /// Type de la mention de masse à notifier (`@everyone` l'emporte)
+    pub fn mass_kind(&self) -> Option<MentionKind> {
+        if self.everyone {
+            Some(MentionKind::Everyone)
+        } else if self.here {
+            Some(MentionKind::Here)
+        } else {
+            None
+        }
+    }
+}
+
+// ================================================================
+// ANALYSE
+// ================================================================
+
+/// Extrait les mentions d'un contenu (sans doublons, dans l'ordre d'apparition)
+pub fn parse_mentions(content: &str) -> Vec<ParsedMention> {
+    let mut seen = HashSet::new();
+    let mut mentions = Vec::new();
+
+    for cap in MENTION_REGEX.captures_iter(content) {
+        // Ponctuation finale de la phrase (« merci @alice. »)
+        let token = cap[1].trim_end_matches(['.', '-']);
+        let mention = match token {
+            "everyone" => ParsedMention { kind: MentionKind::Everyone, target: String::new() },
+            "here" => ParsedMention { kind: MentionKind::Here, target: String::new() },
+            "owners" => ParsedMention { kind: MentionKind::Role, target: "owner".to_string() },
+            "admins" => ParsedMention { kind: MentionKind::Role, target: "admin".to_string() },
+            "moderators" => ParsedMention { kind: MentionKind::Role, target: "moderator".to_string() },
+            username => ParsedMention { kind: MentionKind::User, target: username.to_string() },
+        };
+
+        if seen.insert(mention.clone()) {
+            mentions.push(mention);
+        }
+    }
+
+    mentions
+}
+
+/// Vérifie qu'un membre peut utiliser les mentions de masse présentes
+pub fn check_mass_mention_permission(mentions: &[ParsedMention], member_role: &str, room_id: i64) -> Result<()> {
+    if let Some(mention) = mentions.iter().find(|m| m.kind.is_mass()) {
+        if !is_moderator_role(member_role) {
+            return Err(ChatError::InsufficientPermissions {
+                action: format!("mention_{}", mention.kind.as_str()),
+                conversation_id: room_id.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Refuse un message citant trop de cibles individuelles
+///
+/// `@everyone` et `@here` ne comptent pas : ils ne produisent aucune ligne.
+pub fn check_mention_count(mentions: &[ParsedMention], max_mentions: usize) -> Result<()> {
+    let count = mentions.iter()
+        .filter(|m| !matches!(m.kind, MentionKind::Everyone | MentionKind::Here))
+        .count();
+
+    if count > max_mentions {
+        return Err(ChatError::OutOfRange {
+            field: "mentions".to_string(),
+            value: count as i64,
+            min: 0,
+            max: max_mentions as i64,
+        });
+    }
+    Ok(())
+}
+
+/// Déduplique une liste d'identifiants mentionnés (ordre conservé) et la plafonne
+pub fn dedup_mention_ids<T: Copy + Eq + Hash>(ids: impl IntoIterator<Item = T>, max_mentions: usize) -> Result<Vec<T>> {
+    let mut seen = HashSet::new();
+    let ids: Vec<T> = ids.into_iter().filter(|id| seen.insert(*id)).collect();
+
+    if ids.len() > max_mentions {
+        return Err(ChatError::OutOfRange {
+            field: "mentions".to_string(),
+            value: ids.len() as i64,
+            min: 0,
+            max: max_mentions as i64,
+        });
+    }
+    Ok(ids)
+}
+
+/// Destinataires individuels : sans doublon ni auteur, tronqués à `max_mentions`
+///
+/// La mention la plus précise l'emporte (ordre d'apparition). Seule l'expansion
+/// d'un rôle peut dépasser la limite : les membres au-delà ne sont pas notifiés.
+pub fn collect_recipients(resolved: Vec<(i64, MentionKind)>, author_id: i64, max_mentions: usize) -> Vec<MentionRecipient> {
+    let mut seen = HashSet::new();
+    resolved.into_iter()
+        .filter(|(user_id, _)| *user_id != author_id && seen.insert(*user_id))
+        .take(max_mentions)
+        .map(|(user_id, kind)| MentionRecipient { user_id, kind })
+        .collect()
+}
+
+/// Écart entre les mentions stockées d'un message et celles de son nouveau contenu
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MentionDiff {
+    /// Nouveaux destinataires, à enregistrer et notifier
+    pub added: Vec<MentionRecipient>,
+    /// Destinataires qui ne sont plus mentionnés, à retirer
+    pub removed: Vec<i64>,
+}
+
+/// Mentions de masse du nouveau contenu absentes de l'ancien
+pub fn added_mass_mentions(old_content: &str, mentions: &[ParsedMention]) -> Vec<ParsedMention> {
+    let previous = parse_mentions(old_content);
+    mentions.iter()
+        .filter(|m| m.kind.is_mass() && !previous.contains(m))
+        .cloned()
+        .collect()
+}
+
+/// Compare les destinataires stockés (`existing`) à ceux du contenu édité
+///
+/// Un destinataire présent des deux côtés n'apparaît pas : il n'est ni
+/// réenregistré ni notifié une seconde fois.
+pub fn diff_mentions(existing: &[i64], updated: &[MentionRecipient]) -> MentionDiff {
+    let kept: HashSet<i64> = updated.iter().map(|r| r.user_id).collect();
+    let known: HashSet<i64> = existing.iter().copied().collect();
+    MentionDiff {
+        added: updated.iter().filter(|r| !known.contains(&r.user_id)).cloned().collect(),
+        removed: existing.iter().copied().filter(|user_id| !kept.contains(user_id)).collect(),
+    }
+}
+
+// ================================================================
+// RÉSOLUTION ET STOCKAGE
+// ================================================================
+
+/// Résout les mentions en destinataires, sans contrôle ni écriture
+async fn resolve_room_mentions(
+    max_mentions: usize,
+    tx: &mut Transaction<'_, Postgres>,
+    room_id: i64,
+    message_id: i64,
+    author_id: i64,
+    mentions: &[ParsedMention]
+) -> Result<ResolvedMentions> {
+    let mut resolved: Vec<(i64, MentionKind)> = Vec::new();
+
+    for mention in mentions {
+        let lookup = match mention.kind {
+            MentionKind::User => query("
+                SELECT u.id FROM users u
+                JOIN conversation_members cm ON cm.user_id = u.id AND cm.conversation_id = $2 AND cm.left_at IS NULL
+                WHERE u.username = $1
+            ")
+            .bind(&mention.target)
+            .bind(room_id),
+            // Une ligne de plus que la limite suffit à savoir qu'elle est dépassée
+            MentionKind::Role => query("
+                SELECT user_id as id FROM conversation_members
+                WHERE conversation_id = $2 AND role = $1 AND left_at IS NULL
+                ORDER BY joined_at
+                LIMIT $3
+            ")
+            .bind(&mention.target)
+            .bind(room_id)
+            .bind(max_mentions as i64 + 1),
+            MentionKind::Everyone | MentionKind::Here => continue,
+        };
+
+        let user_ids: Vec<i64> = lookup
+            .fetch_all(&mut **tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("resolve_mentions", e))?
+            .into_iter()
+            .map(|row| row.get::<i64, _>("id"))
+            .collect();
+
+        resolved.extend(user_ids.into_iter().map(|user_id| (user_id, mention.kind.clone())));
+    }
+
+    let resolved_count = resolved.len();
+    let result = ResolvedMentions {
+        recipients: collect_recipients(resolved, author_id, max_mentions),
+        everyone: mentions.iter().any(|m| m.kind == MentionKind::Everyone),
+        here: mentions.iter().any(|m| m.kind == MentionKind::Here),
+    };
+
+    if result.recipients.len() == max_mentions && resolved_count > max_mentions {
+        tracing::warn!(message_id = %message_id, max_mentions = %max_mentions, "⚠️ Mentions tronquées à la limite");
+    }
+
+    Ok(result)
+}
+
+/// Enregistre des destinataires individuels dans `message_mentions`
+async fn insert_mention_rows(tx: &mut Transaction<'_, Postgres>, message_id: i64, recipients: &[MentionRecipient]) -> Result<()> {
+    if recipients.is_empty() {
+        return Ok(());
+    }
+
+    let user_ids: Vec<i64> = recipients.iter().map(|r| r.user_id).collect();
+    let kinds: Vec<&str> = recipients.iter().map(|r| r.kind.as_str()).collect();
+
+    query("
+        INSERT INTO message_mentions (message_id, mentioned_user_id, mention_kind)
+        SELECT $1, user_id, kind FROM UNNEST($2::bigint[], $3::text[]) AS data(user_id, kind)
+        ON CONFLICT (message_id, mentioned_user_id) DO NOTHING
+    ")
+    .bind(message_id)
+    .bind(&user_ids)
+    .bind(&kinds)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_mentions", e))?;
+
+    Ok(())
+}
+
+/// Résout et enregistre les mentions d'un message de salon
+///
+/// Retourne les destinataires individuels (hors auteur) et les indicateurs de
+/// masse pour les notifications.
+pub(crate) async fn process_room_mentions(
+    ctx: WriteContext<'_>,
+    tx: &mut Transaction<'_, Postgres>,
+    room_id: i64,
+    message_id: i64,
+    author_id: i64,
+    author_role: &str,
+    mentions: &[ParsedMention]
+) -> Result<ResolvedMentions> {
+    if mentions.is_empty() {
+        return Ok(ResolvedMentions::default());
+    }
+
+    check_mass_mention_permission(mentions, author_role, room_id)?;
+
+    if mentions.iter().any(|m| m.kind.is_mass()) {
+        ctx.check_mass_mention_limit(author_id).await?;
+    }
+
+    let result = resolve_room_mentions(ctx.limits.max_mentions_per_message, tx, room_id, message_id, author_id, mentions).await?;
+    insert_mention_rows(tx, message_id, &result.recipients).await?;
+
+    if result.everyone || result.here {
+        query("UPDATE messages SET mentions_everyone = $2, mentions_here = $3 WHERE id = $1")
+            .bind(message_id)
+            .bind(result.everyone)
+            .bind(result.here)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("flag_mass_mention", e))?;
+    }
+
+    Ok(result)
+}
+
+/// Met à jour les mentions d'un message de salon après édition
+///
+/// Les mentions du nouveau contenu sont comparées à celles stockées : les
+/// destinataires retirés perdent leur ligne, les nouveaux sont enregistrés.
+/// Seules les mentions de masse ajoutées par l'édition sont soumises aux
+/// contrôles de rôle et de fréquence. Retourne ce qu'il reste à notifier :
+/// les nouveaux destinataires et les mentions de masse ajoutées.
+pub(crate) async fn update_room_mentions(
+    ctx: WriteContext<'_>,
+    tx: &mut Transaction<'_, Postgres>,
+    room_id: i64,
+    message_id: i64,
+    author_id: i64,
+    author_role: &str,
+    old_content: &str,
+    mentions: &[ParsedMention]
+) -> Result<ResolvedMentions> {
+    let added_mass = added_mass_mentions(old_content, mentions);
+    if !added_mass.is_empty() {
+        check_mass_mention_permission(&added_mass, author_role, room_id)?;
+        ctx.check_mass_mention_limit(author_id).await?;
+    }
+
+    let resolved = resolve_room_mentions(ctx.limits.max_mentions_per_message, tx, room_id, message_id, author_id, mentions).await?;
+
+    let existing: Vec<i64> = query("SELECT mentioned_user_id FROM message_mentions WHERE message_id = $1")
+        .bind(message_id)
+        .fetch_all(&mut **tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_mentions", e))?
+        .into_iter()
+        .map(|row| row.get::<i64, _>("mentioned_user_id"))
+        .collect();
+
+    let diff = diff_mentions(&existing, &resolved.recipients);
+
+    if !diff.removed.is_empty() {
+        query("DELETE FROM message_mentions WHERE message_id = $1 AND mentioned_user_id = ANY($2)")
+            .bind(message_id)
+            .bind(&diff.removed)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("delete_mentions", e))?;
+    }
+    insert_mention_rows(tx, message_id, &diff.added).await?;
+
+    query("UPDATE messages SET mentions_everyone = $2, mentions_here = $3 WHERE id = $1")
+        .bind(message_id)
+        .bind(resolved.everyone)
+        .bind(resolved.here)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("flag_mass_mention", e))?;
+
+    tracing::debug!(message_id = %message_id, added = %diff.added.len(), removed = %diff.removed.len(), "🏷️ Mentions mises à jour après édition");
+
+    Ok(ResolvedMentions {
+        recipients: diff.added,
+        everyone: added_mass.iter().any(|m| m.kind == MentionKind::Everyone),
+        here: added_mass.iter().any(|m| m.kind == MentionKind::Here),
+    })
+}
+
+/// Notifie les destinataires connectés d'une mention
+///
+/// Pour `@everyone`/`@here`, seuls les membres connectés sont recherchés :
+/// le coût suit le nombre de connexions, pas la taille du salon. Les membres
+/// qui ont mis le salon en sourdine ne sont pas notifiés (le message leur
+/// parvient quand même en direct).
+pub(crate) async fn notify_mention_recipients(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    author_id: i64,
+    mentions: &ResolvedMentions,
+    visibility: &MessageVisibility
+) {
+    // Un message restreint ne notifie que les membres qui peuvent le voir
+    let mut targets: Vec<(i64, MentionKind)> = mentions.recipients.iter()
+        .filter(|r| visibility.can_see(r.user_id, author_id))
+        .map(|r| (r.user_id, r.kind.clone()))
+        .collect();
+
+    if let Some(kind) = mentions.mass_kind() {
+        let connected: Vec<i64> = hub.clients.keys().await.into_iter().map(i64::from).collect();
+
+        match query("
+            SELECT user_id FROM conversation_members
+            WHERE conversation_id = $1 AND left_at IS NULL AND user_id = ANY($2)
+        ")
+        .bind(room_id)
+        .bind(&connected)
+        .fetch_all(&hub.db)
+        .await
+        {
+            Ok(rows) => {
+                let mut notified: HashSet<i64> = targets.iter().map(|(user_id, _)| *user_id).collect();
+                for user_id in rows.into_iter().map(|row| row.get::<i64, _>("user_id")) {
+                    if user_id != author_id && visibility.can_see(user_id, author_id) && notified.insert(user_id) {
+                        targets.push((user_id, kind.clone()));
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(room_id = %room_id, error = %e, "⚠️ Membres connectés introuvables pour la mention de masse"),
+        }
+    }
+
+    let candidates: Vec<i64> = targets.iter().map(|(user_id, _)| *user_id).collect();
+    match muted_room_members(hub, room_id, &candidates).await {
+        Ok(muted) => retain_unmuted(&mut targets, &muted),
+        Err(e) => tracing::warn!(room_id = %room_id, error = %e, "⚠️ Sourdines du salon introuvables"),
+    }
+
+    for (user_id, kind) in targets {
+        if let Some(client) = hub.clients.get(&(user_id as i32)).await {
+            let payload = json!({
+                "type": "mention",
+                "data": {
+                    "messageId": message_id,
+                    "roomId": room_id,
+                    "authorId": author_id,
+                    "kind": kind.as_str()
+                }
+            });
+            client.send_text(&payload.to_string());
+        }
+    }
+}
+
+/// Représentation des mentions pour les clients
+pub fn mentions_payload(mentions: &[ParsedMention]) -> Value {
+    json!(mentions.iter().map(|m| json!({
+        "kind": m.kind.as_str(),
+        "target": m.target
+    })).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mention_kinds() {
+        let mentions = parse_mentions("@everyone @here @moderators salut @alice et encore @alice");
+        let kinds: Vec<_> = mentions.iter().map(|m| m.kind.clone()).collect();
+        assert_eq!(kinds, vec![MentionKind::Everyone, MentionKind::Here, MentionKind::Role, MentionKind::User]);
+        assert_eq!(mentions[2].target, "moderator");
+        assert_eq!(mentions[3].target, "alice");
+    }
+
+    #[test]
+    fn test_email_addresses_are_not_mentions() {
+        assert!(parse_mentions("écrivez à ops@here.io ou x@admins.org").is_empty());
+        assert!(parse_mentions("foo@everyone bar.@here a-@moderators").is_empty());
+
+        // Les adresses ne déclenchent pas le contrôle des mentions de masse
+        let mentions = parse_mentions("contact : support@everyone.example");
+        assert!(check_mass_mention_permission(&mentions, "member", 1).is_ok());
+    }
+
+    #[test]
+    fn test_mention_boundaries_and_username_charset() {
+        let targets = |content: &str| parse_mentions(content).into_iter().map(|m| m.target).collect::<Vec<_>>();
+        assert_eq!(targets("@jean.dupont,@marie-claire (@bob_2)"), vec!["jean.dupont", "marie-claire", "bob_2"]);
+        assert_eq!(targets("merci @alice."), vec!["alice"]);
+        assert_eq!(targets("@alice@bob"), vec!["alice"]);
+
+        let mentions = parse_mentions("ligne\n@everyone");
+        assert_eq!(mentions[0].kind, MentionKind::Everyone);
+    }
+
+    #[test]
+    fn test_mass_mention_requires_moderation_role() {
+        let mentions = parse_mentions("@everyone réunion");
+        assert!(matches!(
+            check_mass_mention_permission(&mentions, "member", 1),
+            Err(ChatError::InsufficientPermissions { .. })
+        ));
+        assert!(check_mass_mention_permission(&mentions, "moderator", 1).is_ok());
+
+        // Les mentions d'utilisateur restent ouvertes à tous
+        let mentions = parse_mentions("@bob");
+        assert!(check_mass_mention_permission(&mentions, "member", 1).is_ok());
+    }
+
+    #[test]
+    fn test_mention_ids_deduplicated_and_capped() {
+        assert_eq!(dedup_mention_ids(vec![3, 1, 3, 2, 1], 10).unwrap(), vec![3, 1, 2]);
+        // La limite porte sur les identifiants distincts
+        assert!(dedup_mention_ids(vec![7; 100], 1).is_ok());
+        assert!(matches!(
+            dedup_mention_ids(1..=51, 50),
+            Err(ChatError::OutOfRange { value: 51, max: 50, .. })
+        ));
+    }
+
+    #[test]
+    fn test_explicit_mention_cap() {
+        let content: String = (0..4).map(|i| format!("@user{} ", i)).collect();
+        assert!(check_mention_count(&parse_mentions(&content), 4).is_ok());
+        assert!(check_mention_count(&parse_mentions(&content), 3).is_err());
+    }
+
+    #[test]
+    fn test_everyone_is_a_flag_not_a_recipient_list() {
+        let mentions = parse_mentions("@everyone @here @alice");
+        // Les mentions de masse ne comptent pas dans la limite
+        assert!(check_mention_count(&mentions, 1).is_ok());
+
+        let resolved = ResolvedMentions { recipients: Vec::new(), everyone: true, here: true };
+        assert_eq!(resolved.mass_kind(), Some(MentionKind::Everyone));
+        assert_eq!(ResolvedMentions::default().mass_kind(), None);
+    }
+
+    #[test]
+    fn test_recipients_skip_author_duplicates_and_overflow() {
+        let resolved = vec![
+            (2, MentionKind::User),
+            (1, MentionKind::Role),
+            (2, MentionKind::Role),
+            (3, MentionKind::Role),
+            (4, MentionKind::Role),
+        ];
+        let recipients = collect_recipients(resolved, 1, 2);
+        assert_eq!(recipients, vec![
+            MentionRecipient { user_id: 2, kind: MentionKind::User },
+            MentionRecipient { user_id: 3, kind: MentionKind::Role },
+        ]);
+    }
+}
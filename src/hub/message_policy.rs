@@ -0,0 +1,185 @@
+//! Module des politiques d'édition et de suppression des messages
+//!
+//! Règles appliquées :
+//! - L'auteur peut éditer/supprimer pendant une fenêtre configurable
+//! - Les modérateurs suppriment à tout moment et éditent leurs messages sans délai
+//! - Verrouillage optionnel de l'édition après N réactions ou N réponses
+//! - Surcharges par salon (colonnes `conversations.*_window_seconds`)
+
+use sqlx::FromRow;
+use serde::{Serialize, Deserialize};
+use crate::config::LimitsConfig;
+use crate::error::{ChatError, Result};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Surcharges par salon (`None` = configuration globale)
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct RoomMessagePolicy {
+    pub edit_window_seconds: Option<i32>,
+    pub delete_window_seconds: Option<i32>,
+    pub edit_lock_after_reactions: Option<i32>,
+    pub edit_lock_after_replies: Option<i32>,
+}
+
+/// Politique effective pour un message donné
+#[derive(Debug, Clone)]
+pub struct MessagePolicy {
+    pub edit_window: Duration,
+    pub delete_window: Duration,
+    pub lock_after_reactions: Option<u32>,
+    pub lock_after_replies: Option<u32>,
+}
+
+/// État du message et de l'acteur au moment de la modification
+#[derive(Debug, Clone)]
+pub struct ModificationContext {
+    pub is_author: bool,
+    pub is_moderator: bool,
+    pub created_at: DateTime<Utc>,
+    pub reaction_count: i64,
+    pub reply_count: i64,
+}
+
+impl MessagePolicy {
+    pub fn from_limits(limits: &LimitsConfig) -> Self {
+        Self {
+            edit_window: limits.message_edit_window,
+            delete_window: limits.message_delete_window,
+            lock_after_reactions: limits.edit_lock_after_reactions,
+            lock_after_replies: limits.edit_lock_after_replies,
+        }
+    }
+
+    /// Applique les surcharges d'un salon
+    pub fn with_room_overrides(mut self, overrides: &RoomMessagePolicy) -> Self {
+        if let Some(secs) = overrides.edit_window_seconds {
+            self.edit_window = Duration::from_secs(secs.max(0) as u64);
+        }
+        if let Some(secs) = overrides.delete_window_seconds {
+            self.delete_window = Duration::from_secs(secs.max(0) as u64);
+        }
+        if let Some(count) = overrides.edit_lock_after_reactions {
+            self.lock_after_reactions = Some(count.max(1) as u32);
+        }
+        if let Some(count) = overrides.edit_lock_after_replies {
+            self.lock_after_replies = Some(count.max(1) as u32);
+        }
+        self
+    }
+
+    /// Vérifie qu'une édition est autorisée
+    pub fn check_edit(&self, ctx: &ModificationContext, now: DateTime<Utc>) -> Result<()> {
+        if !ctx.is_author {
+            return Err(ChatError::unauthorized("edit_message"));
+        }
+
+        // Les modérateurs éditent leurs propres messages sans contrainte
+        if ctx.is_moderator {
+            return Ok(());
+        }
+
+        if let Some(max) = self.lock_after_reactions {
+            if ctx.reaction_count >= max as i64 {
+                return Err(ChatError::EditForbidden {
+                    reason: format!("message verrouillé après {} réactions", max),
+                });
+            }
+        }
+
+        if let Some(max) = self.lock_after_replies {
+            if ctx.reply_count >= max as i64 {
+                return Err(ChatError::EditForbidden {
+                    reason: format!("message verrouillé après {} réponses", max),
+                });
+            }
+        }
+
+        Self::check_window("edit_message", self.edit_window, ctx.created_at, now)
+    }
+
+    /// Vérifie qu'une suppression est autorisée
+    pub fn check_delete(&self, ctx: &ModificationContext, now: DateTime<Utc>) -> Result<()> {
+        if ctx.is_moderator {
+            return Ok(());
+        }
+
+        if !ctx.is_author {
+            return Err(ChatError::unauthorized("delete_message"));
+        }
+
+        Self::check_window("delete_message", self.delete_window, ctx.created_at, now)
+    }
+
+    fn check_window(action: &str, window: Duration, created_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<()> {
+        let elapsed = (now - created_at).to_std().unwrap_or(Duration::ZERO);
+        if elapsed > window {
+            return Err(ChatError::ModificationWindowExpired {
+                action: action.to_string(),
+                window_secs: window.as_secs(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(is_author: bool, is_moderator: bool, age_secs: i64) -> (ModificationContext, DateTime<Utc>) {
+        let now = Utc::now();
+        let ctx = ModificationContext {
+            is_author,
+            is_moderator,
+            created_at: now - chrono::Duration::seconds(age_secs),
+            reaction_count: 0,
+            reply_count: 0,
+        };
+        (ctx, now)
+    }
+
+    #[test]
+    fn test_author_window_expired_vs_unauthorized() {
+        let policy = MessagePolicy::from_limits(&LimitsConfig::default());
+
+        let (ctx, now) = context(true, false, 60);
+        assert!(policy.check_edit(&ctx, now).is_ok());
+
+        let (ctx, now) = context(true, false, 3 * 3600);
+        assert!(matches!(policy.check_edit(&ctx, now), Err(ChatError::ModificationWindowExpired { .. })));
+        assert!(matches!(policy.check_delete(&ctx, now), Err(ChatError::ModificationWindowExpired { .. })));
+
+        let (ctx, now) = context(false, false, 60);
+        assert!(matches!(policy.check_edit(&ctx, now), Err(ChatError::Unauthorized { .. })));
+        assert!(matches!(policy.check_delete(&ctx, now), Err(ChatError::Unauthorized { .. })));
+    }
+
+    #[test]
+    fn test_moderator_deletes_anytime() {
+        let policy = MessagePolicy::from_limits(&LimitsConfig::default());
+        let (ctx, now) = context(false, true, 30 * 24 * 3600);
+        assert!(policy.check_delete(&ctx, now).is_ok());
+    }
+
+    #[test]
+    fn test_room_overrides_and_reaction_lock() {
+        let overrides = RoomMessagePolicy {
+            edit_window_seconds: Some(10),
+            edit_lock_after_reactions: Some(3),
+            ..Default::default()
+        };
+        let policy = MessagePolicy::from_limits(&LimitsConfig::default()).with_room_overrides(&overrides);
+
+        let (ctx, now) = context(true, false, 60);
+        assert!(matches!(policy.check_edit(&ctx, now), Err(ChatError::ModificationWindowExpired { .. })));
+
+        let (mut ctx, now) = context(true, false, 1);
+        ctx.reaction_count = 3;
+        assert!(matches!(policy.check_edit(&ctx, now), Err(ChatError::EditForbidden { .. })));
+    }
+}
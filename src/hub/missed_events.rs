@@ -0,0 +1,469 @@
+//! Module de rattrapage des événements manqués
+//!
+//! Un client qui revient après une coupure demande, en une seule requête,
+//! tout ce qui a changé depuis un instant dans ses salons et conversations
+//! privées (`get_missed_events`), au lieu d'interroger chaque salon :
+//! - Messages : nouveau, édité ou supprimé, un seul événement par message
+//!   reflétant son dernier état (un message créé puis supprimé pendant la
+//!   coupure n'apparaît pas)
+//! - Réactions ajoutées (un retrait efface la ligne : il n'est pas rejoué)
+//! - Arrivées et départs de membres
+//!
+//! Les événements sont triés par date puis par source et identifiant ; la
+//! réponse est plafonnée et porte un curseur de continuation. Contrairement à
+//! `resume` (journal court en mémoire), le rattrapage lit le dépôt des salons et couvre
+//! donc des coupures longues.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::hub::common::ChatHub;
+use crate::validation::validate_limit;
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Source d'un changement, dans l'ordre de tri à date égale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSource {
+    Message,
+    Reaction,
+    Membership,
+}
+
+impl ChangeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeSource::Message => "message",
+            ChangeSource::Reaction => "reaction",
+            ChangeSource::Membership => "membership",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "message" => Some(ChangeSource::Message),
+            "reaction" => Some(ChangeSource::Reaction),
+            "membership" => Some(ChangeSource::Membership),
+            _ => None,
+        }
+    }
+}
+
+/// Nature d'un événement rejoué
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HubEventKind {
+    MessageCreated,
+    MessageEdited,
+    MessageDeleted,
+    ReactionAdded,
+    MemberJoined,
+    MemberLeft,
+}
+
+/// Événement manqué, prêt à être rejoué par le client
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HubEvent {
+    pub kind: HubEventKind,
+    pub conversation_id: i64,
+    pub at: DateTime<Utc>,
+    #[serde(skip)]
+    pub source: ChangeSource,
+    /// Identifiant de la ligne source (message, réaction, adhésion)
+    #[serde(skip)]
+    pub source_id: i64,
+    pub data: Value,
+}
+
+/// Clé de tri et de pagination : date, source, identifiant de la ligne source
+pub type ChangeKey = (DateTime<Utc>, ChangeSource, i64);
+
+impl HubEvent {
+    fn key(&self) -> ChangeKey {
+        (self.at, self.source, self.source_id)
+    }
+}
+
+/// Point de reprise du rattrapage
+///
+/// `since` : instant de la coupure, conservé de page en page pour décider du
+/// sort des messages ; `after` : dernier événement déjà transmis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissedCursor {
+    pub since: DateTime<Utc>,
+    pub after: Option<ChangeKey>,
+}
+
+impl MissedCursor {
+    /// Première page : tout ce qui a changé après `since`
+    pub fn since(since: DateTime<Utc>) -> Self {
+        Self { since, after: None }
+    }
+
+    /// Borne basse `(date, identifiant)` d'une source, à comparer strictement
+    ///
+    /// À la date du dernier événement transmis, les sources triées avant lui
+    /// sont épuisées, celles triées après sont reprises depuis le début.
+    pub fn lower_bound(&self, source: ChangeSource) -> (DateTime<Utc>, i64) {
+        match self.after {
+            None => (self.since, i64::MAX),
+            Some((at, last_source, _)) if source < last_source => (at, i64::MAX),
+            Some((at, last_source, id)) if source == last_source => (at, id),
+            Some((at, _, _)) => (at, 0),
+        }
+    }
+
+    fn contains(&self, event: &HubEvent) -> bool {
+        let (at, id) = self.lower_bound(event.source);
+        (event.at, event.source_id) > (at, id)
+    }
+
+    /// Forme opaque transmise au client (`<since>:<date>:<source>:<id>`, dates en ns)
+    pub fn encode(&self) -> String {
+        match self.after {
+            None => nanos(self.since).to_string(),
+            Some((at, source, id)) => format!("{}:{}:{}:{}", nanos(self.since), nanos(at), source.as_str(), id),
+        }
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let invalid = || ChatError::InvalidFormat {
+            field: "cursor".to_string(),
+            reason: "Curseur de rattrapage invalide".to_string(),
+        };
+        let date = |value: &str| value.parse::<i64>().ok().map(|nanos| Utc.timestamp_nanos(nanos));
+
+        let parts: Vec<&str> = cursor.split(':').collect();
+        match parts.as_slice() {
+            [since] => Ok(Self::since(date(since).ok_or_else(invalid)?)),
+            [since, at, source, id] => Ok(Self {
+                since: date(since).ok_or_else(invalid)?,
+                after: Some((
+                    date(at).ok_or_else(invalid)?,
+                    ChangeSource::parse(source).ok_or_else(invalid)?,
+                    id.parse().map_err(|_| invalid())?,
+                )),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn nanos(at: DateTime<Utc>) -> i64 {
+    at.timestamp_nanos_opt().unwrap_or(i64::MAX)
+}
+
+/// Page d'événements manqués
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissedEvents {
+    pub events: Vec<HubEvent>,
+    /// Curseur de la page suivante
+    pub cursor: MissedCursor,
+    /// D'autres événements attendent au-delà du plafond
+    pub has_more: bool,
+}
+
+impl MissedEvents {
+    pub fn to_frame(&self) -> Value {
+        json!({
+            "type": "missed_events",
+            "data": {
+                "events": self.events,
+                "cursor": self.cursor.encode(),
+                "hasMore": self.has_more
+            }
+        })
+    }
+}
+
+// ================================================================
+// CHANGEMENTS SOURCES
+// ================================================================
+
+/// État courant d'un message modifié depuis la coupure
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageChange {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub author_id: i64,
+    pub username: String,
+    pub content: String,
+    pub parent_message_id: Option<i64>,
+    /// En-tête d'échange de clés d'un message chiffré de bout en bout
+    pub e2ee_header: Option<Value>,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Date de suppression (`None` : message en ligne)
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl MessageChange {
+    /// Date du dernier changement (création, édition ou suppression)
+    pub fn key(&self) -> ChangeKey {
+        let at = [Some(self.created_at), self.edited_at, self.deleted_at].into_iter().flatten().max().unwrap_or(self.created_at);
+        (at, ChangeSource::Message, self.id)
+    }
+
+    /// Un seul événement par message, d'après son dernier état
+    pub fn into_event(self, since: DateTime<Utc>) -> Option<HubEvent> {
+        let (at, _, _) = self.key();
+        let created_during_gap = self.created_at > since;
+        let (kind, data) = match self.deleted_at {
+            // Jamais vu par le client : rien à retirer
+            Some(_) if created_during_gap => return None,
+            Some(_) => (HubEventKind::MessageDeleted, json!({ "messageId": self.id })),
+            None => {
+                let kind = if created_during_gap { HubEventKind::MessageCreated } else { HubEventKind::MessageEdited };
+                let mut data = json!({
+                    "messageId": self.id,
+                    "authorId": self.author_id,
+                    "username": self.username,
+                    "content": self.content,
+                    "parentMessageId": self.parent_message_id,
+                    "createdAt": self.created_at,
+                    "editedAt": self.edited_at
+                });
+                if let Some(header) = self.e2ee_header {
+                    data["header"] = header;
+                }
+                (kind, data)
+            }
+        };
+        if at <= since {
+            return None;
+        }
+        Some(HubEvent { kind, conversation_id: self.conversation_id, at, source: ChangeSource::Message, source_id: self.id, data })
+    }
+}
+
+/// Réaction posée depuis la coupure
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReactionChange {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub message_id: i64,
+    pub user_id: i64,
+    pub emoji: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ReactionChange {
+    pub fn key(&self) -> ChangeKey {
+        (self.created_at, ChangeSource::Reaction, self.id)
+    }
+
+    pub fn into_event(self) -> HubEvent {
+        HubEvent {
+            kind: HubEventKind::ReactionAdded,
+            conversation_id: self.conversation_id,
+            at: self.created_at,
+            source: ChangeSource::Reaction,
+            source_id: self.id,
+            data: json!({ "messageId": self.message_id, "userId": self.user_id, "emoji": self.emoji }),
+        }
+    }
+}
+
+/// Adhésion modifiée depuis la coupure
+#[derive(Debug, Clone, PartialEq)]
+pub struct MembershipChange {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub user_id: i64,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+}
+
+impl MembershipChange {
+    pub fn key(&self) -> ChangeKey {
+        (self.left_at.map_or(self.joined_at, |left_at| left_at.max(self.joined_at)), ChangeSource::Membership, self.id)
+    }
+
+    /// Arrivée ou départ ; un passage complet pendant la coupure n'apparaît pas
+    pub fn into_event(self, since: DateTime<Utc>) -> Option<HubEvent> {
+        let (at, _, _) = self.key();
+        let kind = match self.left_at {
+            Some(_) if self.joined_at > since => return None,
+            Some(_) => HubEventKind::MemberLeft,
+            None => HubEventKind::MemberJoined,
+        };
+        if at <= since {
+            return None;
+        }
+        Some(HubEvent {
+            kind,
+            conversation_id: self.conversation_id,
+            at,
+            source: ChangeSource::Membership,
+            source_id: self.id,
+            data: json!({ "userId": self.user_id }),
+        })
+    }
+}
+
+/// Trie, déduplique et plafonne les événements postérieurs au curseur
+///
+/// `frontier` : clé de la dernière ligne lue d'une source dont la lecture a
+/// été tronquée ; au-delà, des changements de cette source manquent encore et
+/// la page s'arrête là. Les lignes écartées (message créé puis supprimé...)
+/// avancent le curseur comme les autres.
+pub fn paginate_missed_events(mut events: Vec<HubEvent>, cursor: &MissedCursor, limit: usize, frontier: Option<ChangeKey>) -> MissedEvents {
+    events.retain(|event| cursor.contains(event) && frontier.map_or(true, |frontier| event.key() <= frontier));
+    events.sort_by_key(HubEvent::key);
+    events.dedup_by_key(|event| (event.source, event.source_id));
+
+    let truncated = events.len() > limit;
+    events.truncate(limit);
+
+    let after = if truncated {
+        events.last().map(HubEvent::key)
+    } else {
+        frontier.or_else(|| events.last().map(HubEvent::key))
+    };
+    MissedEvents {
+        events,
+        cursor: MissedCursor { since: cursor.since, after: after.or(cursor.after) },
+        has_more: truncated || frontier.is_some(),
+    }
+}
+
+// ================================================================
+// LECTURE
+// ================================================================
+
+/// Abaisse la frontière si la lecture d'une source a atteint `limit + 1` lignes
+fn lower_frontier(frontier: Option<ChangeKey>, keys: &[ChangeKey], limit: i64) -> Option<ChangeKey> {
+    if (keys.len() as i64) <= limit {
+        return frontier;
+    }
+    frontier.into_iter().chain(keys.iter().max().copied()).min()
+}
+
+/// Événements manqués par `user_id` depuis le curseur, dans tous ses salons et messages privés
+///
+/// Chaque source est lue au plus `limit + 1` lignes après sa borne : la page
+/// fusionnée est donc exacte, et `has_more` signale la suite.
+pub async fn get_missed_events(hub: &ChatHub, user_id: i64, since: &MissedCursor, limit: i64) -> Result<MissedEvents> {
+    let limit = validate_limit(limit, &hub.config.limits)?;
+    tracing::debug!(user_id = %user_id, cursor = %since.encode(), limit = %limit, "⏪ Rattrapage des événements manqués");
+
+    let repository = &hub.room_repository;
+    let mut events = Vec::new();
+    let mut frontier = None;
+
+    let messages = repository.missed_messages(user_id, since.lower_bound(ChangeSource::Message), limit + 1, hub.message_cipher.as_ref()).await?;
+    frontier = lower_frontier(frontier, &messages.iter().map(MessageChange::key).collect::<Vec<_>>(), limit);
+    events.extend(messages.into_iter().filter_map(|change| change.into_event(since.since)));
+
+    let reactions = repository.missed_reactions(user_id, since.lower_bound(ChangeSource::Reaction), limit + 1).await?;
+    frontier = lower_frontier(frontier, &reactions.iter().map(ReactionChange::key).collect::<Vec<_>>(), limit);
+    events.extend(reactions.into_iter().map(ReactionChange::into_event));
+
+    let memberships = repository.missed_memberships(user_id, since.lower_bound(ChangeSource::Membership), limit + 1).await?;
+    frontier = lower_frontier(frontier, &memberships.iter().map(MembershipChange::key).collect::<Vec<_>>(), limit);
+    events.extend(memberships.into_iter().filter_map(|change| change.into_event(since.since)));
+
+    let missed = paginate_missed_events(events, since, limit as usize, frontier);
+    tracing::debug!(user_id = %user_id, replayed = %missed.events.len(), has_more = %missed.has_more, "✅ Événements manqués rattrapés");
+    Ok(missed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn message(id: i64, created: i64, edited: Option<i64>, deleted: Option<i64>) -> MessageChange {
+        MessageChange {
+            id,
+            conversation_id: 1,
+            author_id: 7,
+            username: "bob".to_string(),
+            content: format!("message {}", id),
+            parent_message_id: None,
+            e2ee_header: None,
+            created_at: at(created),
+            edited_at: edited.map(at),
+            deleted_at: deleted.map(at),
+        }
+    }
+
+    #[test]
+    fn test_one_event_per_message_from_its_latest_state() {
+        let since = at(100);
+        let kind = |change: MessageChange| change.into_event(since).map(|event| (event.kind, event.at));
+
+        assert_eq!(kind(message(1, 110, None, None)), Some((HubEventKind::MessageCreated, at(110))));
+        // Créé puis édité pendant la coupure : nouveau message, contenu courant
+        assert_eq!(kind(message(2, 110, Some(120), None)), Some((HubEventKind::MessageCreated, at(120))));
+        assert_eq!(kind(message(3, 50, Some(120), None)), Some((HubEventKind::MessageEdited, at(120))));
+        assert_eq!(kind(message(4, 50, Some(110), Some(130))), Some((HubEventKind::MessageDeleted, at(130))));
+        // Créé puis supprimé pendant la coupure : jamais vu, rien à rejouer
+        assert_eq!(kind(message(5, 110, None, Some(130))), None);
+        assert_eq!(kind(message(6, 50, Some(90), None)), None);
+
+        let left = MembershipChange { id: 1, conversation_id: 1, user_id: 9, joined_at: at(50), left_at: Some(at(110)) };
+        assert_eq!(left.into_event(since).map(|event| event.kind), Some(HubEventKind::MemberLeft));
+        let visit = MembershipChange { id: 2, conversation_id: 1, user_id: 9, joined_at: at(105), left_at: Some(at(110)) };
+        assert_eq!(visit.into_event(since), None);
+    }
+
+    #[test]
+    fn test_pages_are_ordered_and_resume_after_cursor() {
+        let since = at(100);
+        let reaction = ReactionChange { id: 3, conversation_id: 2, message_id: 1, user_id: 9, emoji: "👍".to_string(), created_at: at(110) };
+        let events = vec![
+            message(2, 120, None, None).into_event(since).unwrap(),
+            reaction.into_event(),
+            message(1, 110, None, None).into_event(since).unwrap(),
+            // Doublon d'une même ligne source
+            message(1, 110, None, None).into_event(since).unwrap(),
+        ];
+
+        let first = paginate_missed_events(events.clone(), &MissedCursor::since(since), 2, None);
+        // À date égale, le message précède la réaction
+        assert_eq!(first.events.iter().map(|e| e.kind).collect::<Vec<_>>(), vec![HubEventKind::MessageCreated, HubEventKind::ReactionAdded]);
+        assert!(first.has_more);
+
+        let cursor = MissedCursor::decode(&first.cursor.encode()).unwrap();
+        assert_eq!(cursor, first.cursor);
+        let second = paginate_missed_events(events.clone(), &cursor, 2, None);
+        assert_eq!(second.events.len(), 1);
+        assert_eq!(second.events[0].data["messageId"], 2);
+        assert!(!second.has_more);
+
+        let last = paginate_missed_events(events, &second.cursor, 2, None);
+        assert!(last.events.is_empty());
+        assert_eq!(last.cursor, second.cursor);
+    }
+
+    #[test]
+    fn test_truncated_source_stops_the_page_at_its_frontier() {
+        let since = at(100);
+        let events = vec![
+            message(1, 110, None, None).into_event(since).unwrap(),
+            message(2, 130, None, None).into_event(since).unwrap(),
+        ];
+        // Les réactions ont été lues jusqu'à 120 seulement
+        let frontier = (at(120), ChangeSource::Reaction, 8);
+        let page = paginate_missed_events(events, &MissedCursor::since(since), 10, Some(frontier));
+        assert_eq!(page.events.len(), 1);
+        assert!(page.has_more);
+        assert_eq!(page.cursor.after, Some(frontier));
+
+        assert_eq!(lower_frontier(None, &[frontier], 1), None);
+        let later = (at(120) + Duration::seconds(1), ChangeSource::Message, 1);
+        assert_eq!(lower_frontier(Some(later), &[frontier, frontier], 1), Some(frontier));
+        assert!(MissedCursor::decode("12:a:message:3").is_err());
+        assert!(MissedCursor::decode("12:13:poll:3").is_err());
+    }
+}
@@ -24,6 +24,135 @@ pub mod reactions;
 /// Système d'audit et de logs de sécurité
 pub mod audit;
 
+/// Puits d'audit filtré par la configuration `[audit]`
+pub mod audit_sink;
+
+/// Gestion des profils utilisateur
+pub mod profiles;
+
+/// Calcul des messages non lus
+pub mod unread;
+
+/// Liste des salons rejoints (aperçu, activité, non lus)
+pub mod room_list;
+
+/// Sourdine des salons et DM (notifications seulement)
+pub mod mutes;
+
+/// Connexions invitées (salons publics, accès restreint)
+pub mod guests;
+
+/// Citations d'extraits dans les réponses
+pub mod quotes;
+
+/// Politiques d'édition et de suppression des messages
+pub mod message_policy;
+
+/// Mise en avant des messages par réactions
+pub mod highlights;
+
+/// Analyse des mentions (@user, @everyone, @here, @role)
+pub mod mentions;
+
+/// Persistance et partage des IP bannies
+pub mod ip_bans;
+
+/// Messages longs (aperçu + corps complet à la demande)
+pub mod long_messages;
+
+/// Marqueurs de lecture et liste « vu par » des salons
+pub mod read_receipts;
+
+/// Messages visibles par une partie des membres (chuchotements)
+pub mod visibility;
+
+/// Déduplication des renvois par nonce client
+pub mod dedup;
+
+/// Adhésion automatique aux salons par défaut
+pub mod onboarding;
+
+/// Signalement des messages et file de modération
+pub mod reports;
+
+/// Diagnostic de connexion (ping_diag)
+pub mod diagnostics;
+
+/// Drapeaux de fonctionnalités (configuration + surcharges à chaud)
+pub mod feature_flags;
+
+/// Modèles de réponse des salons (support)
+pub mod templates;
+
+/// Mode lent des salons (automatique sur pic de trafic, réglable par les modérateurs)
+pub mod slow_mode;
+
+/// Mode anti-raid (comptes récents restreints, jointures limitées)
+pub mod anti_raid;
+
+/// Réputation des comptes (score de confiance configurable)
+pub mod reputation;
+
+/// Annuaire des salons (métadonnées, filtres, pagination)
+pub mod room_directory;
+
+/// Palettes de réactions des salons
+pub mod reaction_sets;
+
+/// Émojis personnalisés (téléversement, catalogue, usage)
+pub mod custom_emojis;
+
+/// Restauration des salons persistés à la reconnexion
+pub mod memberships;
+
+/// Quotas quotidiens de messages par utilisateur
+pub mod quotas;
+
+/// Messages retenus par le filtre en attente d'examen
+pub mod held_messages;
+
+/// Politique des liens sortants (listes de domaines par salon)
+pub mod room_links;
+
+/// Abonnements à la présence (utilisateurs et salons suivis)
+pub mod presence_subscriptions;
+
+/// Capacités du serveur (limites effectives, fonctionnalités, réglages d'un salon)
+pub mod capabilities;
+
+/// Rattrapage des événements manqués pendant une coupure (tous salons et messages privés)
+pub mod missed_events;
+
+/// Chiffrement au repos des messages des salons sensibles
+pub mod encrypted_rooms;
+
+/// Pièces jointes téléversées et quota de stockage par utilisateur
+pub mod attachments;
+
+/// Messages directs chiffrés de bout en bout et échange de clés publiques
+pub mod e2ee;
+
+/// Escalade des sanctions après des refus répétés du filtre de contenu
+pub mod violations;
+
+/// Maintenance périodique (connexions mortes, délais de reconnexion, salons vides)
+pub mod maintenance;
+
+/// Accès aux salons et à leurs messages derrière un trait (PostgreSQL par défaut)
+pub mod room_repository;
+
+/// Accès aux conversations privées derrière un trait
+pub mod dm_repository;
+
+/// Accès aux réactions des messages derrière un trait
+pub mod reaction_repository;
+
+/// Accès aux pièces jointes et au stockage derrière un trait
+pub mod attachment_repository;
+
+/// Accès aux données de modération (violations, sanctions, réputation) derrière un trait
+pub mod moderation_repository;
+
 // ================================================================
 // MODULES WEBSOCKET
 // ================================================================
@@ -34,42 +163,54 @@ pub mod channel_websocket;
 /// WebSocket pour les messages directs
 pub mod direct_messages_websocket;
 
+/// File ordonnée des trames entrantes d'une connexion
+pub mod inbound;
+
 // ================================================================
 // RÉEXPORTS PRINCIPAUX
 // ================================================================
 
 // Types et fonctions du hub principal
-pub use common::{ChatHub, HubStats};
+pub use common::{ChatHub, HubStats, ConnectionSummary, Repositories};
 
 // Types et fonctions pour les salons de chat
 pub use channels::{
     Room, RoomMember, EnhancedRoomMessage as RoomMessage, 
     RoomStats, RoomPermissions,
     create_room, join_room, leave_room,
-    send_room_message, pin_message as pin_room_message,
-    fetch_room_history, fetch_pinned_messages as fetch_pinned_room_messages,
+    send_room_message, pin_message as pin_room_message, reorder_pins,
+    unpin_expired_messages, spawn_pin_expiry_sweeper,
+    fetch_room_history, get_latest_room_messages, fetch_pinned_messages as fetch_pinned_room_messages,
+    RoomOpening, open_room, set_joinable_history_limit, set_room_filter_mode, set_room_link_policy, set_room_languages,
+    set_room_attachment_limits, add_allowlisted_term, remove_allowlisted_term,
+    broadcast_to_role, send_role_notice,
+    archive_room, unarchive_room, room_updated_frame,
     get_room_stats, list_room_members
 };
 
 // Types et fonctions pour les messages directs
 pub use direct_messages::{
-    DmConversation, DmMessage, DmStats, DmParticipant,
+    DmConversation, DmMessage, DmStats, DmParticipant, DmPrivacy,
     get_or_create_conversation as get_or_create_dm_conversation,
     block_conversation as block_dm_conversation,
     send_message as send_dm_message, 
     pin_message as pin_dm_message, 
     edit_message as edit_dm_message,
+    mark_dm_read, broadcast_read_state,
     fetch_history as fetch_dm_history,
     fetch_pinned_messages as fetch_pinned_dm_messages,
+    get_pinned_dm_messages,
     get_stats as get_dm_stats, 
-    list_user_conversations as list_user_dm_conversations
+    list_user_conversations as list_user_dm_conversations,
+    start_conversation as start_dm_conversation,
+    get_dm_privacy, set_dm_privacy, dm_allowed
 };
 
 // Système de réactions
 pub use reactions::{
-    MessageReaction, ReactionSummary, MessageReactions,
+    MessageReaction, ReactionSummary, MessageReactions, ReactionAction, ReactionDelta,
     add_reaction, remove_reaction, toggle_reaction,
-    get_message_reactions, get_user_reactions, get_popular_emojis
+    get_message_reactions, get_reaction_changes, get_user_reactions, get_popular_emojis
 };
 
 // Système d'audit
@@ -82,11 +223,139 @@ pub use audit::{
     detect_suspicious_patterns
 };
 
+// Puits d'audit
+pub use audit_sink::AuditSink;
+
+// Profils utilisateur
+pub use profiles::{
+    UserProfileDetails, ProfileUpdate,
+    get_user_profile, get_user_profiles, update_user_profile, get_user_timezone
+};
+
+// Citations
+pub use quotes::QuotedExcerpt;
+
+// Politiques d'édition/suppression
+pub use message_policy::{MessagePolicy, RoomMessagePolicy, ModificationContext};
+
+// Mise en avant par réactions
+pub use highlights::{HighlightRule, set_highlight_rule, list_highlight_rules};
+
+// Mentions
+pub use mentions::{MentionKind, ParsedMention, ResolvedMentions, parse_mentions, check_mention_count};
+
+// Messages longs
+pub use long_messages::{MessageBody, PreparedContent, get_message_body};
+
+// IP bannies
+pub use ip_bans::{
+    BannedIp, IpBanEvent,
+    ban_ip, unban_ip, list_ip_bans, load_ip_bans, spawn_ip_ban_sync
+};
+
+// Accusés de lecture
+pub use read_receipts::{
+    SeenByMode, SeenBy, SeenByMember,
+    RoomReadMarker, mark_room_read, get_message_seen_by, set_seen_by_mode
+};
+
+// Visibilité restreinte
+pub use visibility::MessageVisibility;
+
+// Maintenance du hub
+pub use maintenance::{Departure, EmptyRoomTracker, cleanup_empty_rooms, expire_departures, report_lock_waits, spawn_maintenance_task};
+
+// Accueil des nouveaux utilisateurs
+pub use onboarding::{DefaultRoom, auto_join_default_rooms};
+
+// Restauration des salons
+pub use memberships::{PersistedMembership, restore_memberships, restore_room_memberships};
+
+// Signalements
+pub use reports::{
+    ReportOutcome, MessageReport, FlaggedMessage,
+    report_message, get_moderation_queue
+};
+
+// Messages retenus
+pub use held_messages::{RoomFilterMode, review_held_message};
+
+// Drapeaux de fonctionnalités
+pub use feature_flags::{FeatureFlag, FeatureFlags, load_feature_flags, set_feature_flag};
+
+// Modèles de réponse
+pub use templates::{
+    RoomTemplate, render_template,
+    list_room_templates, create_room_template, delete_room_template, send_room_template
+};
+
+// Mode lent
+pub use slow_mode::{
+    SlowModeOverride, SlowModeSettings, SlowModeTracker,
+    set_room_slow_mode, spawn_slow_mode_monitor
+};
+
+// Mode anti-raid
+pub use anti_raid::{AntiRaidTracker, RaidScope, set_anti_raid};
+
+// Réputation des comptes
+pub use reputation::{UserReputation, get_user_reputation, adjust_user_reputation};
+
+// Annuaire des salons
+pub use room_directory::{RoomFilter, RoomOrder, RoomInfo, RoomCursor, list_rooms};
+
+// Palettes de réactions
+pub use reaction_sets::{ReactionSet, get_room_reaction_set, set_room_reaction_set};
+
+// Émojis personnalisés
+pub use custom_emojis::{
+    CustomEmoji, EmojiCatalog, EmojiScope,
+    upload_custom_emoji, delete_custom_emoji, emoji_catalog
+};
+
+// Rattrapage après coupure
+pub use missed_events::{HubEvent, HubEventKind, MissedCursor, MissedEvents, get_missed_events};
+
+// Salons chiffrés au repos
+pub use encrypted_rooms::{set_room_encryption, rewrap_message_keys, REWRAP_BATCH_SIZE};
+
+// Pièces jointes
+pub use attachments::{Attachment, StorageUsage, upload_attachment, delete_attachment, get_storage_usage};
+
+// Messages directs chiffrés de bout en bout
+pub use e2ee::{EncryptedEnvelope, KeyBundle, KeyBundleUpload, OneTimePrekey, publish_key_bundle, fetch_key_bundle, send_encrypted_dm};
+
+// Violations du filtre de contenu
+pub use violations::{EscalationAction, UserViolations, record_violation, get_user_violations};
+
+// Messages non lus
+pub use unread::{RoomUnread, DmUnread, UnreadSummary, get_unread_summary};
+
+// Salons rejoints
+pub use room_list::{LastMessagePreview, UserRoom, get_user_rooms, set_room_pinned};
+
+// Sourdine côté utilisateur
+pub use mutes::{set_room_muted, set_dm_muted, muted_room_members, is_dm_muted};
+
+// Invités
+pub use guests::{GuestAccess, join_room_as_guest, send_guest_message, set_room_guest_access};
+
 // Handlers WebSocket
 pub use channel_websocket::{
-    RoomWebSocketMessage, handle_room_websocket_message, parse_websocket_message as parse_room_websocket_message
+    RoomWebSocketMessage, handle_room_websocket_message, parse_websocket_message as parse_room_websocket_message, serve_room_connection
 };
 
+pub use inbound::{ConnectionInbox, FrameHandler, RoomFrameHandler, DmFrameHandler, BatchingHandler, read_frames, BatchItemResult, MAX_BATCH_SIZE, MAX_PENDING_FRAMES};
+
 pub use direct_messages_websocket::{
-    DmWebSocketMessage, handle_dm_websocket_message, parse_dm_websocket_message
+    DmWebSocketMessage, handle_dm_websocket_message, parse_dm_websocket_message, serve_dm_connection
+};
+
+// Dépôts
+pub use room_repository::{
+    ArchiveState, InsertedRoomMessage, NewRoomMessage, PgRoomRepository, PostingContext, ReviewedMessage, RoomRepository, WriteContext
 };
+pub use dm_repository::{DmRepository, PgDmRepository};
+pub use reaction_repository::{PgReactionRepository, ReactionRepository};
+pub use attachment_repository::{AttachmentRepository, PgAttachmentRepository};
+pub use moderation_repository::{ModerationRepository, PgModerationRepository};
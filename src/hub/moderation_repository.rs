@@ -0,0 +1,198 @@
+//! Dépôt de la modération
+//!
+//! Rôles de modération, réputation, violations du filtre et sanctions
+//! automatiques qui en découlent :
+//! - `PgModerationRepository` : PostgreSQL
+//! - `testing::InMemoryModerationRepository` (feature `testing`) : mémoire
+
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use serde_json::json;
+use sqlx::{query, PgPool, Row};
+use crate::config::ViolationEscalationConfig;
+use crate::error::{ChatError, Result};
+use crate::hub::audit_sink::AuditSink;
+use crate::hub::common::is_global_admin;
+use crate::hub::violations::{next_violation, RecordedViolation, SanctionDeadlines};
+
+// ================================================================
+// TRAIT
+// ================================================================
+
+/// Accès aux données de modération
+pub trait ModerationRepository: Send + Sync {
+    /// Modérateurs du salon et équipe de modération globale
+    fn moderator_ids<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Vec<i64>>>;
+
+    /// Score de réputation de l'utilisateur, s'il en a un
+    fn reputation_score<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<Option<i32>>>;
+
+    /// Compte un message de l'utilisateur et retourne son total
+    fn count_author_message<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<i64>>;
+
+    /// Compte une violation du filtre et enregistre la sanction du palier atteint
+    ///
+    /// `conversation_id` et `reason` sont conservés dans l'audit de la sanction ;
+    /// paliers de `[security.violation_escalation]`.
+    fn record_violation<'a>(
+        &'a self,
+        user_id: i64,
+        conversation_id: Option<i64>,
+        reason: &'a str,
+        escalation: &'a ViolationEscalationConfig,
+        audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<RecordedViolation>>;
+
+    /// Échéances des sanctions automatiques de l'utilisateur
+    fn sanction_deadlines<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<SanctionDeadlines>>;
+
+    /// Rôle global `admin` ou `owner`
+    fn is_global_admin<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<bool>>;
+}
+
+// ================================================================
+// POSTGRESQL
+// ================================================================
+
+/// Dépôt PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgModerationRepository {
+    db: PgPool,
+}
+
+impl PgModerationRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+impl ModerationRepository for PgModerationRepository {
+    fn moderator_ids<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Vec<i64>>> {
+        Box::pin(async move {
+            let moderator_ids = query("
+                SELECT user_id FROM conversation_members
+                WHERE conversation_id = $1 AND left_at IS NULL
+                  AND role IN ('owner', 'admin', 'moderator')
+                UNION
+                SELECT id FROM users WHERE role::text IN ('moderator', 'admin', 'owner')
+            ")
+            .bind(room_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("list_moderators", e))?
+            .into_iter()
+            .map(|row| row.get::<i64, _>("user_id"))
+            .collect();
+            Ok(moderator_ids)
+        })
+    }
+
+    fn reputation_score<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<Option<i32>>> {
+        Box::pin(async move {
+            let score = query("SELECT score FROM user_reputation WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("get_reputation_score", e))?
+                .and_then(|row| row.get("score"));
+            Ok(score)
+        })
+    }
+
+    fn count_author_message<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<i64>> {
+        Box::pin(async move {
+            let count = query("
+                INSERT INTO user_reputation (user_id, message_count) VALUES ($1, 1)
+                ON CONFLICT (user_id) DO UPDATE SET message_count = user_reputation.message_count + 1
+                RETURNING message_count
+            ")
+            .bind(user_id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("record_message_reputation", e))?
+            .get::<i64, _>("message_count");
+            Ok(count)
+        })
+    }
+
+    fn record_violation<'a>(
+        &'a self,
+        user_id: i64,
+        conversation_id: Option<i64>,
+        reason: &'a str,
+        escalation: &'a ViolationEscalationConfig,
+        audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<RecordedViolation>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let now = Utc::now();
+            let previous = query("SELECT count, last_violation_at FROM user_violations WHERE user_id = $1 FOR UPDATE")
+                .bind(user_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("fetch_user_violations", e))?
+                .map(|row| (row.get::<i32, _>("count") as u32, row.get("last_violation_at")));
+            let violation = next_violation(escalation, previous, now);
+
+            query("
+                INSERT INTO user_violations (user_id, count, last_violation_at, last_action, muted_until, review_until, banned_until)
+                VALUES ($1, $2, $3, $4,
+                        CASE WHEN $4 = 'mute' THEN $5 END,
+                        CASE WHEN $4 = 'flag_for_review' THEN $5 END,
+                        CASE WHEN $4 = 'temp_ban' THEN $5 END)
+                ON CONFLICT (user_id) DO UPDATE SET
+                    count = EXCLUDED.count,
+                    last_violation_at = EXCLUDED.last_violation_at,
+                    last_action = COALESCE(EXCLUDED.last_action, user_violations.last_action),
+                    muted_until = COALESCE(EXCLUDED.muted_until, user_violations.muted_until),
+                    review_until = COALESCE(EXCLUDED.review_until, user_violations.review_until),
+                    banned_until = COALESCE(EXCLUDED.banned_until, user_violations.banned_until)
+            ")
+            .bind(user_id)
+            .bind(violation.count as i32)
+            .bind(now)
+            .bind(violation.action.map(|action| action.as_str()))
+            .bind(violation.until)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("record_violation", e))?;
+
+            // Sanction automatique : aucun modérateur, l'utilisateur visé est le sujet de l'audit
+            if let Some(action) = violation.action {
+                audit.record(&mut *tx, &format!("violation_{}", action.as_str()), Some(user_id), json!({
+                    "conversation_id": conversation_id,
+                    "violations": violation.count,
+                    "until": violation.until,
+                    "reason": reason
+                })).await?;
+            }
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+            Ok(violation)
+        })
+    }
+
+    fn sanction_deadlines<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<SanctionDeadlines>> {
+        Box::pin(async move {
+            let deadlines = query("SELECT muted_until, review_until, banned_until FROM user_violations WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("check_standing", e))?
+                .map(|row| SanctionDeadlines {
+                    muted_until: row.get("muted_until"),
+                    review_until: row.get("review_until"),
+                    banned_until: row.get("banned_until"),
+                })
+                .unwrap_or_default();
+            Ok(deadlines)
+        })
+    }
+
+    fn is_global_admin<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(is_global_admin(&self.db, user_id))
+    }
+}
@@ -0,0 +1,165 @@
+//! Sourdine des salons et des conversations DM
+//!
+//! Sourdine côté utilisateur : il reste membre et reçoit toujours les messages
+//! en direct, mais plus les notifications :
+//! - Salon (`conversation_members.is_muted`) : pas de trame `mention`, hors du
+//!   total des non lus
+//! - DM (`dm_mutes`) : pas de notification push pour un message reçu hors
+//!   ligne, hors du total des non lus
+//!
+//! À ne pas confondre avec la sourdine de modération (`user_violations.muted_until`),
+//! qui empêche de publier.
+
+use std::collections::HashSet;
+use sqlx::{query, Row};
+use serde_json::{json, Value};
+use crate::hub::common::ChatHub;
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// ÉTAT DE SOURDINE
+// ================================================================
+
+/// Trame `mute_state`, réponse aux actions `set_room_muted` et `set_dm_muted`
+pub fn mute_state_frame(target: &str, id: i64, muted: bool) -> Value {
+    json!({
+        "type": "mute_state",
+        "data": {
+            "target": target,
+            "id": id,
+            "muted": muted
+        }
+    })
+}
+
+/// Met en sourdine (ou non) un salon pour un de ses membres
+pub async fn set_room_muted(hub: &ChatHub, room_id: i64, user_id: i64, muted: bool) -> Result<()> {
+    let updated = query("
+        UPDATE conversation_members SET is_muted = $3
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(user_id)
+    .bind(muted)
+    .execute(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("set_room_muted", e))?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(ChatError::unauthorized("set_room_muted"));
+    }
+
+    tracing::info!(room_id = %room_id, user_id = %user_id, muted = %muted, "🔕 Sourdine du salon mise à jour");
+    Ok(())
+}
+
+/// Met en sourdine (ou non) une conversation DM pour un de ses participants
+pub async fn set_dm_muted(hub: &ChatHub, conversation_id: i64, user_id: i64, muted: bool) -> Result<()> {
+    let is_participant: bool = query("
+        SELECT EXISTS(
+            SELECT 1 FROM dm_conversations
+            WHERE id = $1 AND (user1_id = $2 OR user2_id = $2)
+        )
+    ")
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_participant", e))?
+    .get(0);
+
+    if !is_participant {
+        return Err(ChatError::unauthorized("set_dm_muted"));
+    }
+
+    let statement = if muted {
+        "INSERT INTO dm_mutes (conversation_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"
+    } else {
+        "DELETE FROM dm_mutes WHERE conversation_id = $1 AND user_id = $2"
+    };
+    query(statement)
+        .bind(conversation_id)
+        .bind(user_id)
+        .execute(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("set_dm_muted", e))?;
+
+    tracing::info!(conversation_id = %conversation_id, user_id = %user_id, muted = %muted, "🔕 Sourdine de la conversation DM mise à jour");
+    Ok(())
+}
+
+// ================================================================
+// FILTRAGE DES NOTIFICATIONS
+// ================================================================
+
+/// Membres du salon, parmi `user_ids`, qui l'ont mis en sourdine
+pub async fn muted_room_members(hub: &ChatHub, room_id: i64, user_ids: &[i64]) -> Result<HashSet<i64>> {
+    if user_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    hub.room_repository.muted_members(room_id, user_ids).await
+}
+
+/// La conversation DM est-elle en sourdine pour ce participant ?
+pub async fn is_dm_muted(hub: &ChatHub, conversation_id: i64, user_id: i64) -> Result<bool> {
+    let muted: bool = query("
+        SELECT EXISTS(SELECT 1 FROM dm_mutes WHERE conversation_id = $1 AND user_id = $2)
+    ")
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("is_dm_muted", e))?
+    .get(0);
+
+    Ok(muted)
+}
+
+/// Retire les destinataires en sourdine d'une liste de notifications
+pub fn retain_unmuted<T>(targets: &mut Vec<(i64, T)>, muted: &HashSet<i64>) {
+    targets.retain(|(user_id, _)| !muted.contains(user_id));
+}
+
+/// Notification push d'un message DM au destinataire hors ligne, sauf sourdine
+///
+/// Connecté, le destinataire a déjà reçu le message en direct.
+pub async fn notify_dm_recipient(hub: &ChatHub, conversation_id: i64, recipient_id: i64, from_username: &str, content: &str) {
+    if hub.clients.contains_key(&(recipient_id as i32)).await {
+        return;
+    }
+
+    match is_dm_muted(hub, conversation_id, recipient_id).await {
+        Ok(true) => {
+            tracing::debug!(conversation_id = %conversation_id, recipient_id = %recipient_id, "🔕 Notification DM supprimée (sourdine)");
+        }
+        Ok(false) => {
+            if let Err(e) = hub.notifications.notify_new_dm(recipient_id as i32, from_username, content).await {
+                tracing::warn!(recipient_id = %recipient_id, error = %e, "⚠️ Échec de la notification DM");
+            }
+        }
+        Err(e) => tracing::warn!(conversation_id = %conversation_id, error = %e, "⚠️ État de sourdine DM introuvable"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_muted_recipients_are_not_notified() {
+        let mut targets = vec![(1, "direct"), (2, "everyone"), (3, "here")];
+        retain_unmuted(&mut targets, &HashSet::from([2, 9]));
+        assert_eq!(targets, vec![(1, "direct"), (3, "here")]);
+    }
+
+    #[test]
+    fn test_mute_state_frame_shape() {
+        let frame = mute_state_frame("dm", 12, true);
+        assert_eq!(frame["type"], "mute_state");
+        assert_eq!(frame["data"]["target"], "dm");
+        assert_eq!(frame["data"]["id"], 12);
+        assert_eq!(frame["data"]["muted"], true);
+    }
+}
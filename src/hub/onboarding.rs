@@ -0,0 +1,129 @@
+//! Module d'accueil des nouveaux utilisateurs
+//!
+//! À la première connexion, l'utilisateur rejoint automatiquement les salons
+//! listés dans `features.default_rooms` (ex. `general`, `announcements`) :
+//! - Un utilisateur ayant déjà un historique dans un salon n'y est jamais
+//!   réinscrit (il a pu le quitter volontairement)
+//! - La limite de salons par utilisateur est respectée
+//! - Idempotent : une reconnexion ne produit aucune nouvelle adhésion
+
+use crate::hub::common::ChatHub;
+use crate::hub::channels::{join_room, broadcast_to_room_members};
+use crate::error::Result;
+use crate::room_id::RoomId;
+use serde_json::json;
+use std::collections::HashSet;
+
+// ================================================================
+// PLANIFICATION
+// ================================================================
+
+/// Salon par défaut résolu dans le dépôt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultRoom {
+    pub id: i64,
+    pub name: RoomId,
+}
+
+/// Sélectionne les salons par défaut à rejoindre
+///
+/// `known_rooms` contient tous les salons où l'utilisateur a (ou a eu) une adhésion.
+pub fn plan_default_joins(
+    default_rooms: &[DefaultRoom],
+    known_rooms: &HashSet<i64>,
+    active_room_count: i64,
+    max_rooms_per_user: u32
+) -> Vec<DefaultRoom> {
+    let available = (max_rooms_per_user as i64 - active_room_count).max(0) as usize;
+
+    default_rooms.iter()
+        .filter(|room| !known_rooms.contains(&room.id))
+        .take(available)
+        .cloned()
+        .collect()
+}
+
+// ================================================================
+// ADHÉSION AUTOMATIQUE
+// ================================================================
+
+/// Inscrit l'utilisateur aux salons par défaut qu'il n'a jamais rejoints
+///
+/// Retourne les salons effectivement rejoints.
+pub async fn auto_join_default_rooms(hub: &ChatHub, user_id: i64, username: &str) -> Result<Vec<DefaultRoom>> {
+    let room_names = &hub.config.features.default_rooms;
+    if room_names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Ordre de la configuration conservé (priorité en cas de limite atteinte)
+    let mut default_rooms = hub.room_repository.default_rooms(room_names).await?;
+    default_rooms.sort_by_key(|room| room_names.iter().position(|name| *name == room.name));
+
+    let (known_rooms, active_room_count) = hub.room_repository.known_rooms(user_id).await?;
+
+    let planned = plan_default_joins(&default_rooms, &known_rooms, active_room_count, hub.config.limits.max_rooms_per_user);
+    let mut joined = Vec::with_capacity(planned.len());
+
+    for room in planned {
+        if let Err(e) = join_room(hub, room.id, user_id).await {
+            tracing::warn!(user_id = %user_id, room_id = %room.id, error = %e, "⚠️ Adhésion automatique impossible");
+            continue;
+        }
+
+        hub.rooms.update(room.name.clone(), |members| {
+            if !members.contains(&(user_id as i32)) {
+                members.push(user_id as i32);
+            }
+        }).await;
+
+        // Accusé d'adhésion pour l'utilisateur
+        if let Some(client) = hub.clients.get(&(user_id as i32)).await {
+            client.send_text(&json!({
+                "type": "room_joined",
+                "data": {
+                    "roomId": room.id,
+                    "roomName": room.name,
+                    "automatic": true
+                }
+            }).to_string());
+        }
+
+        broadcast_to_room_members(hub, room.id, &json!({
+            "type": "member_joined",
+            "data": {
+                "roomId": room.id,
+                "userId": user_id,
+                "username": username
+            }
+        })).await?;
+
+        joined.push(room);
+    }
+
+    if !joined.is_empty() {
+        tracing::info!(user_id = %user_id, joined_count = %joined.len(), "🏠 Salons par défaut rejoints");
+    }
+    Ok(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Vec<DefaultRoom> {
+        vec![
+            DefaultRoom { id: 1, name: RoomId::new("general").unwrap() },
+            DefaultRoom { id: 2, name: RoomId::new("announcements").unwrap() },
+        ]
+    }
+
+    #[test]
+    fn test_room_cap_respected() {
+        let planned = plan_default_joins(&defaults(), &HashSet::new(), 99, 100);
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].name.as_str(), "general");
+
+        assert!(plan_default_joins(&defaults(), &HashSet::new(), 100, 100).is_empty());
+    }
+}
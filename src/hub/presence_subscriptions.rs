@@ -0,0 +1,154 @@
+//! Module des abonnements à la présence
+//!
+//! Un client ne reçoit les changements de présence (`presence_update`) que des
+//! utilisateurs et des salons auxquels il s'est abonné (`subscribe_presence`) ;
+//! l'abonnement renvoie un instantané (`presence_snapshot`) de la présence
+//! courante. Les abonnements d'un client disparaissent à sa déconnexion.
+//!
+//! Seuls se suivent un contact (conversation DM) ou un membre d'un salon
+//! commun, sans blocage entre eux. Le salon courant d'un utilisateur n'est
+//! montré qu'aux membres de ce salon.
+
+use std::collections::BTreeSet;
+use serde_json::{json, Value};
+use crate::hub::common::ChatHub;
+use crate::presence::{UserPresence, UserStatus};
+use crate::error::{ChatError, Result};
+use crate::room_id::RoomId;
+
+/// Salons en mémoire dont l'utilisateur est membre
+async fn user_rooms(hub: &ChatHub, user_id: i32) -> Vec<RoomId> {
+    hub.rooms.filter_map(|room, members| members.contains(&user_id).then(|| room.clone())).await
+}
+
+/// Lien entre un abonné et l'utilisateur dont il veut suivre la présence
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PresenceRelation {
+    /// Conversation DM entre les deux utilisateurs
+    pub contact: bool,
+    /// Adhésion active des deux à un même salon
+    pub shares_room: bool,
+    /// Blocage dans un sens ou dans l'autre
+    pub blocked: bool,
+}
+
+impl PresenceRelation {
+    pub fn may_watch(&self) -> bool {
+        (self.contact || self.shares_room) && !self.blocked
+    }
+}
+
+/// Retire de la vue publique le salon courant si `viewer` n'en est pas membre
+async fn hide_foreign_room(hub: &ChatHub, view: &mut Value, viewer: i32) {
+    let Some(room) = view["current_room"].as_str().and_then(|room| RoomId::new(room).ok()) else {
+        return;
+    };
+    if !hub.rooms.get(&room).await.is_some_and(|members| members.contains(&viewer)) {
+        view["current_room"] = Value::Null;
+    }
+}
+
+/// Envoie un changement de présence aux seuls abonnés concernés
+async fn publish_presence(hub: &ChatHub, presence: &UserPresence, event: &str) {
+    let rooms = user_rooms(hub, presence.user_id).await;
+    let subscribers = hub.presence.subscribers_for(presence.user_id, &rooms).await;
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let event = hub.presence.create_presence_event(presence, event);
+    for subscriber in &subscribers {
+        let mut frame = event.clone();
+        hide_foreign_room(hub, &mut frame["data"], *subscriber).await;
+        hub.send_to_user_sessions(*subscriber, &frame.to_string()).await;
+    }
+    tracing::debug!(user_id = %presence.user_id, event = %event, subscribers = %subscribers.len(), "📡 Présence publiée aux abonnés");
+}
+
+// ================================================================
+// ABONNEMENTS
+// ================================================================
+
+/// Abonne un client à la présence d'utilisateurs et de salons dont il est membre
+///
+/// Chaque utilisateur suivi doit être un contact ou partager un salon avec
+/// l'abonné, sans blocage entre eux. Retourne la trame `presence_snapshot` :
+/// présence courante des utilisateurs suivis et des membres des salons suivis.
+pub async fn subscribe_presence(hub: &ChatHub, subscriber: i32, users: &[i32], rooms: &[RoomId]) -> Result<Value> {
+    tracing::info!(subscriber = %subscriber, users = %users.len(), rooms = %rooms.len(), "👀 Abonnement à la présence");
+
+    for &user_id in users.iter().filter(|&&user_id| user_id != subscriber) {
+        let relation = hub.dm_repository.presence_relation(subscriber as i64, user_id as i64).await?;
+        if !relation.may_watch() {
+            tracing::warn!(subscriber = %subscriber, user_id = %user_id, "🚫 Présence d'un utilisateur sans lien refusée");
+            return Err(ChatError::unauthorized("subscribe_presence"));
+        }
+    }
+
+    let mut watched: BTreeSet<i32> = users.iter().copied().collect();
+    for room in rooms {
+        let members = hub.rooms.get(room).await
+            .filter(|members| members.contains(&subscriber))
+            .ok_or_else(|| ChatError::unauthorized("subscribe_presence"))?;
+        watched.extend(members);
+    }
+    watched.remove(&subscriber);
+
+    hub.presence.subscribe(subscriber, users, rooms).await?;
+
+    let watched: Vec<i32> = watched.into_iter().collect();
+    let mut snapshot = hub.presence.snapshot(&watched).await;
+    for view in &mut snapshot {
+        hide_foreign_room(hub, view, subscriber).await;
+    }
+    Ok(json!({
+        "type": "presence_snapshot",
+        "data": {
+            "users": snapshot,
+            "rooms": rooms
+        }
+    }))
+}
+
+/// Retire des abonnements ; les autres sont conservés
+pub async fn unsubscribe_presence(hub: &ChatHub, subscriber: i32, users: &[i32], rooms: &[RoomId]) {
+    hub.presence.unsubscribe(subscriber, users, rooms).await;
+}
+
+// ================================================================
+// PUBLICATION
+// ================================================================
+
+/// Marque l'utilisateur en ligne à sa première session et prévient ses abonnés
+pub async fn publish_online(hub: &ChatHub, user_id: i32, username: &str) {
+    if hub.presence.get_user_presence(user_id).await.is_some() {
+        return;
+    }
+    hub.presence.user_online(user_id, username.to_string()).await;
+    if let Some(presence) = hub.presence.get_user_presence(user_id).await {
+        publish_presence(hub, &presence, "online").await;
+    }
+}
+
+/// Marque l'utilisateur hors ligne, prévient ses abonnés et oublie ses abonnements
+pub async fn publish_offline(hub: &ChatHub, user_id: i32) {
+    hub.presence.remove_subscriber(user_id).await;
+    let Some(mut presence) = hub.presence.get_user_presence(user_id).await else {
+        return;
+    };
+    hub.presence.user_offline(user_id).await;
+    presence.status = UserStatus::Offline;
+    publish_presence(hub, &presence, "offline").await;
+}
+
+/// Change le statut de l'utilisateur et prévient ses abonnés
+pub async fn set_presence_status(hub: &ChatHub, user_id: i32, status: UserStatus, message: Option<String>) -> Result<()> {
+    if status == UserStatus::Offline {
+        return Err(ChatError::configuration_error("Le statut hors ligne suit la déconnexion (utiliser Invisible)"));
+    }
+    hub.presence.set_user_status(user_id, status, message).await?;
+    let presence = hub.presence.get_user_presence(user_id).await
+        .ok_or_else(|| ChatError::not_found("client", &user_id.to_string()))?;
+    publish_presence(hub, &presence, "status_changed").await;
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Module de gestion des profils utilisateur
+//!
+//! Fonctionnalités :
+//! - Lecture et mise à jour du profil (nom d'affichage, bio, avatar, fuseau)
+//! - Modération du nom d'affichage et de la bio via le filtre de contenu
+//! - Résolution groupée des profils allégés (`get_user_profiles`), mis en cache
+//! - Invalidation du cache des profils
+//! - Diffusion `profile_update` aux salons de l'utilisateur
+
+use sqlx::{query_as, FromRow};
+use serde::{Serialize, Deserialize};
+use crate::hub::common::ChatHub;
+use crate::services::{UserProfile, UserService};
+use crate::security::ContentFilter;
+use crate::validation::{check_reserved_username, validate_user_id, validate_display_name, validate_bio, validate_avatar_url, validate_timezone};
+use crate::error::{ChatError, Result};
+use crate::utils::resolve_timezone;
+use serde_json::json;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct UserProfileDetails {
+    pub user_id: i64,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// URL publique de l'avatar, déposé au préalable dans le stockage objet
+    pub avatar_url: Option<String>,
+    pub timezone: String,
+    pub profile_updated_at: Option<DateTime<Utc>>,
+}
+
+/// Champs modifiables du profil (`None` = inchangé)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProfileUpdate {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub timezone: Option<String>,
+}
+
+impl ProfileUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.display_name.is_none()
+            && self.bio.is_none()
+            && self.avatar_url.is_none()
+            && self.timezone.is_none()
+    }
+}
+
+// ================================================================
+// LECTURE ET MISE À JOUR
+// ================================================================
+
+/// Récupère le profil complet d'un utilisateur
+pub async fn get_user_profile(hub: &ChatHub, user_id: i64) -> Result<UserProfileDetails> {
+    validate_user_id(user_id as i32)?;
+
+    query_as::<_, UserProfileDetails>("
+        SELECT id as user_id, username, display_name, bio, avatar_url, timezone, profile_updated_at
+        FROM users
+        WHERE id = $1
+    ")
+    .bind(user_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_user_profile", e))?
+    .ok_or_else(|| ChatError::not_found("utilisateur", &user_id.to_string()))
+}
+
+/// Met à jour le profil d'un utilisateur puis notifie ses salons
+pub async fn update_user_profile(
+    hub: &ChatHub,
+    user_id: i64,
+    update: ProfileUpdate
+) -> Result<UserProfileDetails> {
+    tracing::info!(user_id = %user_id, "🪪 Mise à jour du profil");
+
+    validate_user_id(user_id as i32)?;
+
+    if update.is_empty() {
+        return get_user_profile(hub, user_id).await;
+    }
+
+    // Validation et modération des champs textuels
+    let mut filter = ContentFilter::with_config(&hub.config.security.content_filter)?;
+
+    let display_name = match update.display_name.as_deref() {
+        Some(name) => {
+            validate_display_name(name)?;
+            check_reserved_username(name.trim(), &hub.config.security.reserved_usernames)?;
+            Some(filter.validate_content(name.trim())?)
+        }
+        None => None,
+    };
+
+    let bio = match update.bio.as_deref() {
+        Some(bio) if bio.trim().is_empty() => Some(String::new()),
+        Some(bio) => {
+            validate_bio(bio)?;
+            Some(filter.validate_content(bio)?)
+        }
+        None => None,
+    };
+
+    if let Some(avatar_url) = update.avatar_url.as_deref() {
+        validate_avatar_url(avatar_url, &hub.config.object_store.public_base_url)?;
+    }
+
+    if let Some(timezone) = update.timezone.as_deref() {
+        validate_timezone(timezone)?;
+    }
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let profile = query_as::<_, UserProfileDetails>("
+        UPDATE users SET
+            display_name = COALESCE($2, display_name),
+            bio = CASE WHEN $3::text IS NULL THEN bio ELSE NULLIF($3, '') END,
+            avatar_url = COALESCE($4, avatar_url),
+            timezone = COALESCE($5, timezone),
+            profile_updated_at = NOW()
+        WHERE id = $1
+        RETURNING id as user_id, username, display_name, bio, avatar_url, timezone, profile_updated_at
+    ")
+    .bind(user_id)
+    .bind(&display_name)
+    .bind(&bio)
+    .bind(&update.avatar_url)
+    .bind(&update.timezone)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("update_user_profile", e))?
+    .ok_or_else(|| ChatError::not_found("utilisateur", &user_id.to_string()))?;
+
+    // Log d'audit
+    hub.audit_sink.record(&mut *tx, "profile_updated", Some(user_id), json!({
+        "display_name_changed": display_name.is_some(),
+        "bio_changed": bio.is_some(),
+        "avatar_changed": update.avatar_url.is_some(),
+        "timezone_changed": update.timezone.is_some()
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    if let Err(e) = hub.profile_cache.invalidate(user_id).await {
+        tracing::warn!(user_id = %user_id, error = %e, "⚠️ Profil en cache non invalidé");
+    }
+
+    broadcast_profile_update(hub, &profile).await?;
+
+    tracing::info!(user_id = %user_id, "✅ Profil mis à jour");
+    Ok(profile)
+}
+
+/// Profils allégés d'une liste d'utilisateurs, pour enrichir les messages reçus
+///
+/// Réservé aux utilisateurs authentifiés ; la présence respecte le réglage de
+/// confidentialité de chaque utilisateur.
+pub async fn get_user_profiles(hub: &ChatHub, user_id: i64, user_ids: &[i64]) -> Result<Vec<UserProfile>> {
+    validate_user_id(user_id as i32)?;
+
+    UserService::new(Arc::new(hub.db.clone()))
+        .get_user_profiles(user_ids, hub.profile_cache.as_ref(), &hub.presence)
+        .await
+}
+
+/// Fuseau horaire de l'utilisateur pour le formatage côté serveur (UTC par défaut)
+pub async fn get_user_timezone(hub: &ChatHub, user_id: i64) -> Result<chrono_tz::Tz> {
+    let timezone: Option<String> = sqlx::query_scalar("SELECT timezone FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("get_user_timezone", e))?;
+
+    Ok(resolve_timezone(timezone.as_deref()))
+}
+
+// ================================================================
+// FONCTIONS UTILITAIRES
+// ================================================================
+
+/// Diffuse un `profile_update` allégé aux membres des salons de l'utilisateur
+async fn broadcast_profile_update(hub: &ChatHub, profile: &UserProfileDetails) -> Result<()> {
+    let recipients: Vec<i64> = sqlx::query_scalar("
+        SELECT DISTINCT other.user_id
+        FROM conversation_members me
+        JOIN conversation_members other ON other.conversation_id = me.conversation_id AND other.left_at IS NULL
+        WHERE me.user_id = $1 AND me.left_at IS NULL
+    ")
+    .bind(profile.user_id)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_profile_recipients", e))?;
+
+    let payload = json!({
+        "type": "profile_update",
+        "data": {
+            "userId": profile.user_id,
+            "username": profile.username,
+            "displayName": profile.display_name,
+            "avatarUrl": profile.avatar_url
+        }
+    });
+
+    let mut successful_sends = 0;
+
+    for client in hub.clients.get_many(recipients.into_iter().map(|user_id| user_id as i32)).await {
+        if client.send_text(&payload.to_string()) {
+            successful_sends += 1;
+        }
+    }
+
+    tracing::debug!(user_id = %profile.user_id, successful_sends = %successful_sends, "📡 Mise à jour de profil diffusée");
+    Ok(())
+}
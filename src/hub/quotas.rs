@@ -0,0 +1,69 @@
+//! Quotas quotidiens de messages des utilisateurs
+//!
+//! Le plafond dépend du compte (`users.role`, `users.is_bot`) ; le décompte
+//! passe par `hub.message_quota` (voir `crate::message_quota`). Quand le solde
+//! passe sous `quotas.warning_ratio`, l'utilisateur reçoit une trame
+//! `message_quota` à chaque envoi pour que son client le prévienne.
+
+use sqlx::{query, Row};
+use crate::hub::common::ChatHub;
+use crate::error::{ChatError, Result};
+use crate::message_quota::{QuotaClass, QuotaDay, QuotaStatus};
+use serde_json::json;
+
+/// Catégorie de quota d'un utilisateur
+pub async fn load_quota_class(hub: &ChatHub, user_id: i64) -> Result<QuotaClass> {
+    let row = query("SELECT role::text AS role, is_bot FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_quota_class", e))?
+        .ok_or_else(|| ChatError::not_found("user", &user_id.to_string()))?;
+
+    Ok(QuotaClass::from_account(row.get("role"), row.get("is_bot")))
+}
+
+/// Solde du jour, sans rien consommer
+pub async fn get_message_quota(hub: &ChatHub, user_id: i64) -> Result<QuotaStatus> {
+    let class = load_quota_class(hub, user_id).await?;
+    let day = QuotaDay::today(&hub.config);
+    let used = hub.message_quota.current(user_id, &day).await?;
+    Ok(QuotaStatus::new(class, used, class.limit(&hub.config.quotas), &day, hub.config.quotas.warning_ratio))
+}
+
+/// Compte un message de l'utilisateur, ou le refuse si son quota est épuisé
+///
+/// Quotas désactivés ou catégorie illimitée : rien n'est compté, `None`.
+pub async fn consume_message_quota(hub: &ChatHub, user_id: i64) -> Result<Option<QuotaStatus>> {
+    if !hub.config.quotas.enabled {
+        return Ok(None);
+    }
+    let class = load_quota_class(hub, user_id).await?;
+    let Some(limit) = class.limit(&hub.config.quotas) else {
+        return Ok(None);
+    };
+
+    let day = QuotaDay::today(&hub.config);
+    let used = hub.message_quota.increment(user_id, &day).await?;
+    if used > limit {
+        hub.message_quota.decrement(user_id, &day).await?;
+        let status = QuotaStatus::new(class, limit, Some(limit), &day, hub.config.quotas.warning_ratio);
+        tracing::warn!(user_id = %user_id, limit = %limit, resets_at = %day.resets_at, "📉 Quota quotidien de messages atteint");
+        return Err(status.exceeded_error());
+    }
+
+    let status = QuotaStatus::new(class, used, Some(limit), &day, hub.config.quotas.warning_ratio);
+    if status.warning {
+        hub.send_to_user_sessions(user_id as i32, &json!({
+            "type": "message_quota",
+            "data": status
+        }).to_string()).await;
+    }
+    Ok(Some(status))
+}
+
+/// Rend un message compté par `consume_message_quota` (envoi resté sans effet,
+/// comme un renvoi simultané)
+pub async fn release_message_quota(hub: &ChatHub, user_id: i64) -> Result<()> {
+    hub.message_quota.decrement(user_id, &QuotaDay::today(&hub.config)).await
+}
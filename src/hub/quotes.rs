@@ -0,0 +1,165 @@
+//! Module de gestion des citations dans les réponses
+//!
+//! Une réponse peut citer un extrait précis de son message parent
+//! ("répondre à la sélection"). L'extrait est validé contre le contenu du
+//! parent au moment de l'envoi puis conservé tel quel dans les métadonnées
+//! de la réponse : il survit aux éditions et suppressions du parent.
+
+use sqlx::{query, Transaction, Postgres};
+use serde::{Serialize, Deserialize};
+use crate::encryption::MessageCipher;
+use crate::hub::encrypted_rooms::open_row_content;
+use crate::hub::held_messages::held_clause;
+use crate::hub::visibility::visibility_clause;
+use crate::error::{ChatError, Result};
+use serde_json::{json, Value};
+
+/// Longueur maximale d'un extrait cité (en caractères)
+pub const MAX_QUOTE_LENGTH: usize = 500;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Extrait cité du message parent
+///
+/// `quote_offset` et `quote_length` sont exprimés en caractères Unicode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotedExcerpt {
+    pub quoted_text: String,
+    pub quote_offset: usize,
+    pub quote_length: usize,
+}
+
+impl QuotedExcerpt {
+    /// Vérifie que l'extrait correspond exactement au contenu du parent
+    pub fn validate_against(&self, parent_content: &str) -> Result<()> {
+        if self.quote_length == 0 || self.quote_length > MAX_QUOTE_LENGTH {
+            return Err(ChatError::configuration_error("Longueur de citation invalide"));
+        }
+
+        if self.quoted_text.chars().count() != self.quote_length {
+            return Err(ChatError::configuration_error("La longueur ne correspond pas au texte cité"));
+        }
+
+        if !self.is_current(parent_content) {
+            return Err(ChatError::configuration_error("La citation ne correspond pas au message parent"));
+        }
+
+        Ok(())
+    }
+
+    /// Indique si l'extrait correspond toujours au contenu (éventuellement édité) du parent
+    pub fn is_current(&self, parent_content: &str) -> bool {
+        let excerpt: String = parent_content.chars()
+            .skip(self.quote_offset)
+            .take(self.quote_length)
+            .collect();
+
+        excerpt.chars().count() == self.quote_length && excerpt == self.quoted_text
+    }
+
+    /// Représentation envoyée aux clients
+    pub fn to_payload(&self) -> Value {
+        json!({
+            "quotedText": self.quoted_text,
+            "quoteOffset": self.quote_offset,
+            "quoteLength": self.quote_length
+        })
+    }
+}
+
+// ================================================================
+// VALIDATION À L'ENVOI
+// ================================================================
+
+/// Valide la citation éventuelle présente dans `metadata.quote`
+///
+/// La citation exige un message parent appartenant à la même conversation et
+/// visible de l'auteur de la réponse (`author_id`).
+/// Les métadonnées sont réécrites avec la forme normalisée de l'extrait.
+pub(crate) async fn attach_quote(
+    cipher: Option<&MessageCipher>,
+    tx: &mut Transaction<'_, Postgres>,
+    conversation_id: i64,
+    author_id: i64,
+    parent_message_id: Option<i64>,
+    metadata: &mut Value
+) -> Result<Option<QuotedExcerpt>> {
+    let Some(raw_quote) = metadata.get("quote").cloned() else {
+        return Ok(None);
+    };
+
+    let quote: QuotedExcerpt = serde_json::from_value(raw_quote)
+        .map_err(|_| ChatError::configuration_error("Citation malformée"))?;
+
+    let Some(parent_id) = parent_message_id else {
+        return Err(ChatError::configuration_error("Une citation nécessite un message parent"));
+    };
+
+    let parent = query(&quoted_parent_query())
+    .bind(parent_id)
+    .bind(conversation_id)
+    .bind(author_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("fetch_quoted_parent", e))?
+    .ok_or_else(|| ChatError::not_found("message", &parent_id.to_string()))?;
+    let parent_content = open_row_content(cipher, &parent, "content")?;
+
+    quote.validate_against(&parent_content)?;
+
+    metadata["quote"] = serde_json::to_value(&quote)
+        .map_err(ChatError::from_json_error)?;
+
+    Ok(Some(quote))
+}
+
+/// Parent cité : même conversation, non supprimé et visible de l'auteur (`$3`)
+///
+/// Un message restreint hors de portée ou retenu est traité comme introuvable.
+pub(crate) fn quoted_parent_query() -> String {
+    format!("
+        SELECT m.content, m.encryption_key_id, m.wrapped_key FROM messages m
+        WHERE m.id = $1 AND m.conversation_id = $2 AND m.status != 'deleted'
+          AND {} AND {}
+    ", visibility_clause(3), held_clause(3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excerpt(text: &str, offset: usize) -> QuotedExcerpt {
+        QuotedExcerpt {
+            quoted_text: text.to_string(),
+            quote_offset: offset,
+            quote_length: text.chars().count(),
+        }
+    }
+
+    #[test]
+    fn test_quote_validated_against_parent() {
+        let parent = "Réunion demain à 10h dans la salle B";
+        assert!(excerpt("demain à 10h", 8).validate_against(parent).is_ok());
+        assert!(excerpt("demain à 11h", 8).validate_against(parent).is_err());
+        assert!(excerpt("salle B", 200).validate_against(parent).is_err());
+        assert!(excerpt("", 0).validate_against(parent).is_err());
+    }
+
+    #[test]
+    fn test_quote_snapshot_survives_parent_edit() {
+        let original = "Le déploiement est prévu vendredi";
+        let quote = excerpt("prévu vendredi", 19);
+        assert!(quote.validate_against(original).is_ok());
+
+        let stored = serde_json::to_value(&quote).unwrap();
+
+        // Le parent est édité : l'extrait n'est plus à jour mais reste intact
+        let edited = "Le déploiement est reporté à lundi";
+        let restored: QuotedExcerpt = serde_json::from_value(stored).unwrap();
+        assert!(!restored.is_current(edited));
+        assert_eq!(restored, quote);
+        assert_eq!(restored.to_payload()["quotedText"], "prévu vendredi");
+    }
+}
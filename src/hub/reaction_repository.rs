@@ -0,0 +1,201 @@
+//! Dépôt des réactions aux messages
+//!
+//! Lecture et ajout des réactions d'un message, selon son accès et la
+//! politique du salon :
+//! - `PgReactionRepository` : PostgreSQL
+//! - `testing::InMemoryReactionRepository` (feature `testing`) : mémoire
+
+use futures_util::future::BoxFuture;
+use serde_json::json;
+use sqlx::{query, PgPool, Row};
+use crate::error::{ChatError, Result};
+use crate::hub::audit_sink::AuditSink;
+use crate::hub::custom_emojis::{custom_emoji_available, shortcode_of};
+use crate::hub::reaction_sets::reaction_set_for_message;
+use crate::hub::reactions::{
+    check_message_access, check_reaction_policy, get_message_access_users, message_access_query, AddedReaction, ReactionUser, ReactionView,
+};
+
+// ================================================================
+// TRAIT
+// ================================================================
+
+/// Accès aux réactions des messages
+pub trait ReactionRepository: Send + Sync {
+    /// Auteur et réactions d'un message accessible au lecteur, avec les
+    /// utilisateurs qu'il a bloqués en masquant leurs réactions
+    ///
+    /// `None` : message inconnu ou inaccessible (`reactions::message_access_query`).
+    fn message_reactions<'a>(&'a self, message_id: i64, viewer_id: i64) -> BoxFuture<'a, Result<Option<ReactionView>>>;
+
+    /// Enregistre la réaction d'un utilisateur ayant accès au message
+    ///
+    /// Refus : message inaccessible ou politique du salon (`Unauthorized`),
+    /// emoji hors palette, limite de réactions atteinte ou réaction déjà présente.
+    fn insert_reaction<'a>(&'a self, message_id: i64, user_id: i64, emoji: &'a str, audit: &'a AuditSink) -> BoxFuture<'a, Result<AddedReaction>>;
+}
+
+// ================================================================
+// POSTGRESQL
+// ================================================================
+
+/// Dépôt PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgReactionRepository {
+    db: PgPool,
+}
+
+impl PgReactionRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+impl ReactionRepository for PgReactionRepository {
+    fn message_reactions<'a>(&'a self, message_id: i64, viewer_id: i64) -> BoxFuture<'a, Result<Option<ReactionView>>> {
+        Box::pin(async move {
+            let has_access: bool = query(&message_access_query())
+                .bind(message_id)
+                .bind(viewer_id)
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("check_message_access", e))?
+                .get(0);
+            if !has_access {
+                return Ok(None);
+            }
+
+            let hiding_row = query("
+                SELECT m.author_id,
+                       COALESCE(ARRAY(
+                           SELECT CASE WHEN dc.user1_id = $2 THEN dc.user2_id ELSE dc.user1_id END
+                           FROM dm_conversations dc
+                           WHERE dc.is_blocked = TRUE AND dc.hide_reactions = TRUE AND dc.blocked_by = $2
+                       ), '{}') as blocked_ids
+                FROM messages m
+                WHERE m.id = $1
+            ")
+            .bind(message_id)
+            .bind(viewer_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("get_hidden_reactors", e))?;
+            let Some(hiding_row) = hiding_row else {
+                return Ok(None);
+            };
+
+            // Toutes les réactions du message, regroupées par emoji côté serveur
+            let rows = query("
+                SELECT mr.emoji, mr.user_id, u.username, mr.created_at
+                FROM message_reactions mr
+                JOIN users u ON u.id = mr.user_id
+                WHERE mr.message_id = $1
+                ORDER BY mr.created_at ASC
+            ")
+            .bind(message_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("get_reaction_users", e))?;
+
+            Ok(Some(ReactionView {
+                author_id: hiding_row.get("author_id"),
+                hidden_by_viewer: hiding_row.get("blocked_ids"),
+                reactions: rows.into_iter().map(|row| (
+                    row.get("emoji"),
+                    ReactionUser {
+                        user_id: row.get("user_id"),
+                        username: row.get("username"),
+                        created_at: row.get("created_at"),
+                    },
+                )).collect(),
+            }))
+        })
+    }
+
+    fn insert_reaction<'a>(&'a self, message_id: i64, user_id: i64, emoji: &'a str, audit: &'a AuditSink) -> BoxFuture<'a, Result<AddedReaction>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Vérifier que le message existe et que l'utilisateur a accès
+            if !check_message_access(&mut tx, message_id, user_id).await? {
+                return Err(ChatError::unauthorized("add_reaction"));
+            }
+
+            // Les réactions dépendent de l'appartenance au salon, pas du droit de publication
+            if !check_reaction_policy(&mut tx, message_id, user_id).await? {
+                return Err(ChatError::unauthorized("add_reaction"));
+            }
+
+            // Palette du salon (ensemble ouvert par défaut)
+            reaction_set_for_message(&mut tx, message_id).await?.check(emoji)?;
+
+            // Émoji personnalisé : disponible pour le serveur ou pour le salon du message
+            if let Some(shortcode) = shortcode_of(emoji) {
+                if !custom_emoji_available(&mut tx, message_id, shortcode).await? {
+                    return Err(ChatError::not_found("émoji personnalisé", emoji));
+                }
+            }
+
+            // Vérifier la limite de réactions par utilisateur par message (max 10)
+            let user_reaction_count: i64 = query("
+                SELECT COUNT(*)
+                FROM message_reactions
+                WHERE message_id = $1 AND user_id = $2
+            ")
+            .bind(message_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("count_user_reactions", e))?
+            .get(0);
+
+            if user_reaction_count >= 10 {
+                return Err(ChatError::configuration_error("Limite de réactions par message atteinte"));
+            }
+
+            // Ajouter la réaction (ou ne rien faire si elle existe déjà)
+            let rows_affected = query("
+                INSERT INTO message_reactions (message_id, user_id, emoji)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (message_id, user_id, emoji) DO NOTHING
+            ")
+            .bind(message_id)
+            .bind(user_id)
+            .bind(emoji)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("insert_reaction", e))?
+            .rows_affected();
+
+            if rows_affected == 0 {
+                return Err(ChatError::configuration_error("Réaction déjà présente"));
+            }
+
+            let highlight_rule: bool = query("
+                SELECT EXISTS(
+                    SELECT 1 FROM messages m
+                    JOIN room_highlight_rules r ON r.conversation_id = m.conversation_id AND r.emoji = $2
+                    WHERE m.id = $1
+                )
+            ")
+            .bind(message_id)
+            .bind(emoji)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_highlight_rule", e))?
+            .get(0);
+
+            audit.record(&mut *tx, "reaction_added", Some(user_id), json!({
+                "message_id": message_id,
+                "emoji": emoji
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            let audience = get_message_access_users(&self.db, message_id).await?;
+            Ok(AddedReaction { audience, highlight_rule })
+        })
+    }
+}
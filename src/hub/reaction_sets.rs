@@ -0,0 +1,252 @@
+//! Palettes de réactions des salons
+//!
+//! - Par défaut, un salon accepte toute réaction valide (ensemble ouvert)
+//! - Les modérateurs peuvent restreindre les réactions à une palette choisie
+//! - Une palette mélange émojis Unicode et émojis personnalisés `:nom:`
+//! - `add_reaction` refuse une réaction hors palette ; les réactions déjà
+//!   posées avant la restriction sont conservées
+
+use sqlx::{query, Row};
+use serde::Serialize;
+use crate::hub::common::ChatHub;
+use crate::hub::channels::{broadcast_to_room_members, is_moderator_role};
+use crate::hub::custom_emojis::shortcode_of;
+use crate::hub::reactions::validate_emoji;
+use crate::error::{ChatError, Result};
+use serde_json::json;
+
+/// Nombre maximal de réactions dans une palette
+pub const MAX_REACTION_SET_SIZE: usize = 50;
+
+// ================================================================
+// PALETTE
+// ================================================================
+
+/// Réactions acceptées dans un salon
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+#[serde(tag = "mode", content = "emojis", rename_all = "snake_case")]
+pub enum ReactionSet {
+    /// Toute réaction valide (comportement historique)
+    #[default]
+    Open,
+    /// Seules les réactions listées, dans l'ordre d'affichage
+    Curated(Vec<String>),
+}
+
+impl ReactionSet {
+    /// Colonne `allowed_reactions` (NULL = ensemble ouvert)
+    pub fn from_db(allowed: Option<Vec<String>>) -> Self {
+        match allowed {
+            Some(emojis) => ReactionSet::Curated(emojis),
+            None => ReactionSet::Open,
+        }
+    }
+
+    pub fn to_db(&self) -> Option<&[String]> {
+        match self {
+            ReactionSet::Open => None,
+            ReactionSet::Curated(emojis) => Some(emojis),
+        }
+    }
+
+    pub fn allows(&self, emoji: &str) -> bool {
+        match self {
+            ReactionSet::Open => true,
+            ReactionSet::Curated(emojis) => emojis.iter().any(|allowed| allowed == emoji),
+        }
+    }
+
+    /// Palette restreinte validée : espaces retirés, doublons écartés, ordre conservé
+    pub fn curated(emojis: &[String]) -> Result<Self> {
+        let mut palette: Vec<String> = Vec::with_capacity(emojis.len());
+
+        for emoji in emojis.iter().map(|emoji| emoji.trim()) {
+            if emoji.starts_with(':') {
+                validate_custom_emoji(emoji)?;
+            } else {
+                validate_emoji(emoji)?;
+            }
+            if !palette.iter().any(|known| known == emoji) {
+                palette.push(emoji.to_string());
+            }
+        }
+
+        if palette.is_empty() {
+            return Err(ChatError::configuration_error("Palette de réactions vide"));
+        }
+        if palette.len() > MAX_REACTION_SET_SIZE {
+            return Err(ChatError::OutOfRange {
+                field: "emojis".to_string(),
+                value: palette.len() as i64,
+                min: 1,
+                max: MAX_REACTION_SET_SIZE as i64,
+            });
+        }
+
+        Ok(ReactionSet::Curated(palette))
+    }
+
+    /// Vérifie une réaction avant son ajout
+    pub fn check(&self, emoji: &str) -> Result<()> {
+        match self {
+            ReactionSet::Curated(emojis) if !self.allows(emoji) => Err(ChatError::InvalidFormat {
+                field: "emoji".to_string(),
+                reason: format!("Réaction non autorisée dans ce salon (palette : {})", emojis.join(" ")),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Émoji personnalisé `:nom:` (voir `custom_emojis::is_valid_shortcode`)
+pub fn is_custom_emoji(emoji: &str) -> bool {
+    shortcode_of(emoji).is_some()
+}
+
+fn validate_custom_emoji(emoji: &str) -> Result<()> {
+    if !is_custom_emoji(emoji) {
+        return Err(ChatError::InvalidFormat {
+            field: "emoji".to_string(),
+            reason: format!("Émoji personnalisé invalide: {} (attendu :nom:, 18 caractères max)", emoji),
+        });
+    }
+    Ok(())
+}
+
+// ================================================================
+// ACCÈS
+// ================================================================
+
+/// Palette du salon contenant un message
+pub(crate) async fn reaction_set_for_message(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    message_id: i64
+) -> Result<ReactionSet> {
+    let allowed: Option<Vec<String>> = query("
+        SELECT c.allowed_reactions
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        WHERE m.id = $1
+    ")
+    .bind(message_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_reaction_set", e))?
+    .and_then(|row| row.get("allowed_reactions"));
+
+    Ok(ReactionSet::from_db(allowed))
+}
+
+/// Palette d'un salon
+pub async fn get_room_reaction_set(hub: &ChatHub, room_id: i64) -> Result<ReactionSet> {
+    let allowed: Option<Vec<String>> = query("
+        SELECT allowed_reactions FROM conversations WHERE id = $1 AND type = 'public_room'
+    ")
+    .bind(room_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_room_reaction_set", e))?
+    .ok_or_else(|| ChatError::not_found("salon", &room_id.to_string()))?
+    .get("allowed_reactions");
+
+    Ok(ReactionSet::from_db(allowed))
+}
+
+/// Restreint ou rouvre les réactions d'un salon (modérateurs)
+pub async fn set_room_reaction_set(hub: &ChatHub, room_id: i64, moderator_id: i64, set: ReactionSet) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, set = ?set, "🎨 Réglage de la palette de réactions");
+
+    let set = match set {
+        ReactionSet::Open => ReactionSet::Open,
+        ReactionSet::Curated(emojis) => ReactionSet::curated(&emojis)?,
+    };
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let role: Option<String> = query("
+        SELECT role FROM conversation_members
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(moderator_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+    .map(|row| row.get("role"));
+
+    if !role.as_deref().is_some_and(is_moderator_role) {
+        return Err(ChatError::unauthorized("set_room_reaction_set"));
+    }
+
+    query("UPDATE conversations SET allowed_reactions = $1, updated_at = NOW() WHERE id = $2")
+        .bind(set.to_db())
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_reaction_set", e))?;
+
+    hub.audit_sink.record(&mut *tx, "room_reaction_set_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "allowed_reactions": set.to_db()
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    broadcast_to_room_members(hub, room_id, &json!({
+        "type": "reaction_set_updated",
+        "data": {
+            "roomId": room_id,
+            "reactionSet": set
+        }
+    })).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette(emojis: &[&str]) -> Vec<String> {
+        emojis.iter().map(|emoji| emoji.to_string()).collect()
+    }
+
+    #[test]
+    fn test_open_set_allows_everything() {
+        let set = ReactionSet::from_db(None);
+        assert_eq!(set, ReactionSet::Open);
+        assert!(set.allows("🦄"));
+        assert!(set.check(":party_parrot:").is_ok());
+    }
+
+    #[test]
+    fn test_curated_set_rejects_outside_reactions() {
+        let set = ReactionSet::curated(&palette(&["👍", "🎉", ":veza:"])).unwrap();
+
+        assert!(set.check("👍").is_ok());
+        assert!(set.check(":veza:").is_ok());
+        assert!(matches!(set.check("😂"), Err(ChatError::InvalidFormat { .. })));
+    }
+
+    #[test]
+    fn test_curated_set_normalization() {
+        let set = ReactionSet::curated(&palette(&[" 👍", "🎉", "👍 "])).unwrap();
+        assert_eq!(set, ReactionSet::Curated(palette(&["👍", "🎉"])));
+        assert_eq!(set.to_db(), Some(palette(&["👍", "🎉"]).as_slice()));
+
+        assert!(ReactionSet::curated(&[]).is_err());
+        assert!(ReactionSet::curated(&palette(&["  "])).is_err());
+        let too_many: Vec<String> = (0..=MAX_REACTION_SET_SIZE).map(|i| format!(":e{}:", i)).collect();
+        assert!(matches!(ReactionSet::curated(&too_many), Err(ChatError::OutOfRange { .. })));
+    }
+
+    #[test]
+    fn test_custom_emoji_names() {
+        assert!(is_custom_emoji(":party-parrot:"));
+        assert!(is_custom_emoji(":veza_2:"));
+        assert!(!is_custom_emoji("::"));
+        assert!(!is_custom_emoji(":Veza:"));
+        assert!(!is_custom_emoji(":nom trop long pour un emoji:"));
+        assert!(ReactionSet::curated(&palette(&[":a b:"])).is_err());
+    }
+}
@@ -7,13 +7,21 @@
 //! - Limitations et validation
 //! - Support pour DM et salons
 
-use sqlx::{query, query_as, FromRow, Row};
+use sqlx::{query, query_as, FromRow, PgPool, Row};
 use serde::{Serialize, Deserialize};
 use crate::hub::common::ChatHub;
-use crate::validation::validate_user_id;
+use crate::hub::channels::RoomPostPolicy;
+use crate::security::SecurityAction;
+use crate::hub::highlights::evaluate_highlight;
+use crate::hub::held_messages::held_clause;
+use crate::hub::visibility::visibility_clause;
+use crate::hub::feature_flags::FeatureFlag;
+use crate::validation::{validate_limit, validate_user_id};
 use crate::error::{ChatError, Result};
+use crate::pagination::Page;
 use serde_json::json;
 use chrono::{DateTime, Utc};
+use std::collections::HashSet;
 
 // ================================================================
 // STRUCTURES DE DONNÉES
@@ -49,6 +57,38 @@ pub struct MessageReactions {
     pub reactions: Vec<ReactionSummary>,
 }
 
+/// Sens d'un changement de réaction (un retrait suit toujours un ajout)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactionAction {
+    Added,
+    Removed,
+}
+
+/// Dernier changement d'une réaction (message, utilisateur, emoji) depuis une date
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionDelta {
+    pub message_id: i64,
+    pub user_id: i64,
+    pub emoji: String,
+    pub action: ReactionAction,
+    pub at: DateTime<Utc>,
+}
+
+/// Réactions d'un message lues pour un lecteur, avant masquage
+#[derive(Debug)]
+pub struct ReactionView {
+    pub author_id: i64,
+    /// Utilisateurs bloqués par le lecteur avec masquage des réactions
+    pub hidden_by_viewer: Vec<i64>,
+    /// Réactions (emoji, réacteur) dans l'ordre chronologique
+    pub reactions: Vec<(String, ReactionUser)>,
+}
+
+/// Messages acceptés par appel à `get_reaction_changes`
+pub const MAX_REACTION_DELTA_MESSAGES: usize = 200;
+
 // ================================================================
 // GESTION DES RÉACTIONS
 // ================================================================
@@ -62,73 +102,25 @@ pub async fn add_reaction(
 ) -> Result<()> {
     tracing::info!(user_id = %user_id, message_id = %message_id, emoji = %emoji, "😊 Ajout d'une réaction");
     
+    hub.require_feature(FeatureFlag::Reactions).await?;
+    
     validate_user_id(user_id as i32)?;
     validate_emoji(emoji)?;
+    hub.check_action_limit(user_id as i32, SecurityAction::AddReaction).await?;
     
-    let mut tx = hub.db.begin().await
-        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
-    
-    // Vérifier que le message existe et que l'utilisateur a accès
-    let message_access = check_message_access(&mut tx, message_id, user_id).await?;
-    if !message_access {
-        return Err(ChatError::unauthorized("add_reaction"));
-    }
-    
-    // Vérifier la limite de réactions par utilisateur par message (max 10)
-    let user_reaction_count: i64 = query("
-        SELECT COUNT(*) 
-        FROM message_reactions 
-        WHERE message_id = $1 AND user_id = $2
-    ")
-    .bind(message_id)
-    .bind(user_id)
-    .fetch_one(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("count_user_reactions", e))?
-    .get(0);
+    // Accès, politique de réaction du salon, palette et limite par utilisateur
+    let added = hub.reaction_repository.insert_reaction(message_id, user_id, emoji, &hub.audit_sink).await?;
     
-    if user_reaction_count >= 10 {
-        return Err(ChatError::configuration_error("Limite de réactions par message atteinte"));
-    }
-    
-    // Ajouter la réaction (ou ne rien faire si elle existe déjà)
-    let rows_affected = query("
-        INSERT INTO message_reactions (message_id, user_id, emoji)
-        VALUES ($1, $2, $3)
-        ON CONFLICT (message_id, user_id, emoji) DO NOTHING
-    ")
-    .bind(message_id)
-    .bind(user_id)
-    .bind(emoji)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("insert_reaction", e))?
-    .rows_affected();
+    // Notifier en temps réel
+    send_reaction_update(hub, added.audience, message_id, "added", user_id, emoji).await;
     
-    if rows_affected == 0 {
-        return Err(ChatError::configuration_error("Réaction déjà présente"));
+    // Mise en avant éventuelle (ne doit pas faire échouer la réaction)
+    if added.highlight_rule {
+        if let Err(e) = evaluate_highlight(hub, message_id, emoji).await {
+            tracing::warn!(message_id = %message_id, error = %e, "⚠️ Échec de l'évaluation de mise en avant");
+        }
     }
     
-    // Log d'audit
-    query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('reaction_added', $1, $2)
-    ")
-    .bind(json!({
-        "message_id": message_id,
-        "emoji": emoji
-    }))
-    .bind(user_id)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
-    
-    tx.commit().await
-        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
-    
-    // Notifier en temps réel
-    broadcast_reaction_update(hub, message_id, "added", user_id, emoji).await?;
-    
     tracing::info!(user_id = %user_id, message_id = %message_id, emoji = %emoji, "✅ Réaction ajoutée");
     Ok(())
 }
@@ -142,8 +134,12 @@ pub async fn remove_reaction(
 ) -> Result<()> {
     tracing::info!(user_id = %user_id, message_id = %message_id, emoji = %emoji, "🗑️ Suppression d'une réaction");
     
+    hub.require_feature(FeatureFlag::Reactions).await?;
+    
     validate_user_id(user_id as i32)?;
     validate_emoji(emoji)?;
+    // Même budget que l'ajout : empêche le va-et-vient ajout/retrait
+    hub.check_action_limit(user_id as i32, SecurityAction::AddReaction).await?;
     
     let mut tx = hub.db.begin().await
         .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
@@ -171,19 +167,23 @@ pub async fn remove_reaction(
         return Err(ChatError::not_found("réaction", &format!("{}:{}", message_id, emoji)));
     }
     
-    // Log d'audit
+    // Trace du retrait pour les clients qui appliquent des deltas
     query("
-        INSERT INTO audit_logs (action, details, user_id)
-        VALUES ('reaction_removed', $1, $2)
+        INSERT INTO message_reaction_removals (message_id, user_id, emoji)
+        VALUES ($1, $2, $3)
     ")
-    .bind(json!({
-        "message_id": message_id,
-        "emoji": emoji
-    }))
+    .bind(message_id)
     .bind(user_id)
+    .bind(emoji)
     .execute(&mut *tx)
     .await
-    .map_err(|e| ChatError::from_sqlx_error("audit_log", e))?;
+    .map_err(|e| ChatError::from_sqlx_error("record_reaction_removal", e))?;
+    
+    // Log d'audit
+    hub.audit_sink.record(&mut *tx, "reaction_removed", Some(user_id), json!({
+        "message_id": message_id,
+        "emoji": emoji
+    })).await?;
     
     tx.commit().await
         .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
@@ -204,6 +204,8 @@ pub async fn toggle_reaction(
 ) -> Result<bool> {
     tracing::info!(user_id = %user_id, message_id = %message_id, emoji = %emoji, "🔄 Basculement de réaction");
     
+    hub.require_feature(FeatureFlag::Reactions).await?;
+    
     validate_user_id(user_id as i32)?;
     validate_emoji(emoji)?;
     
@@ -245,56 +247,14 @@ pub async fn get_message_reactions(
     
     validate_user_id(requesting_user_id as i32)?;
     
-    // Vérifier l'accès au message
-    let mut tx = hub.db.begin().await
-        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
-    
-    let message_access = check_message_access(&mut tx, message_id, requesting_user_id).await?;
-    if !message_access {
-        return Err(ChatError::unauthorized("get_message_reactions"));
-    }
+    // Accès au message, blocages du lecteur et réactions brutes
+    let view = hub.reaction_repository.message_reactions(message_id, requesting_user_id).await?
+        .ok_or_else(|| ChatError::unauthorized("get_message_reactions"))?;
     
-    tx.commit().await
-        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
-    
-    // Récupérer les réactions groupées par emoji
-    let reactions = query_as::<_, (String, i64)>("
-        SELECT emoji, COUNT(*) as count
-        FROM message_reactions 
-        WHERE message_id = $1
-        GROUP BY emoji
-        ORDER BY count DESC, emoji
-    ")
-    .bind(message_id)
-    .fetch_all(&hub.db)
-    .await
-    .map_err(|e| ChatError::from_sqlx_error("get_reaction_counts", e))?;
-    
-    let total_reactions: i64 = reactions.iter().map(|(_, count)| count).sum();
-    
-    // Pour chaque emoji, récupérer les détails des utilisateurs
-    let mut reaction_summaries = Vec::new();
-    
-    for (emoji, count) in reactions {
-        let users = query_as::<_, ReactionUser>("
-            SELECT mr.user_id, u.username, mr.created_at
-            FROM message_reactions mr
-            JOIN users u ON u.id = mr.user_id
-            WHERE mr.message_id = $1 AND mr.emoji = $2
-            ORDER BY mr.created_at ASC
-        ")
-        .bind(message_id)
-        .bind(&emoji)
-        .fetch_all(&hub.db)
-        .await
-        .map_err(|e| ChatError::from_sqlx_error("get_reaction_users", e))?;
-        
-        reaction_summaries.push(ReactionSummary {
-            emoji,
-            count,
-            users,
-        });
-    }
+    // Réactions masquées par un blocage du lecteur (seulement sur ses propres messages)
+    let hidden = hidden_reactors(requesting_user_id, view.author_id, &view.hidden_by_viewer);
+    let reaction_summaries = summarize_reactions(view.reactions, &hidden);
+    let total_reactions: i64 = reaction_summaries.iter().map(|summary| summary.count).sum();
     
     let message_reactions = MessageReactions {
         message_id,
@@ -306,31 +266,199 @@ pub async fn get_message_reactions(
     Ok(message_reactions)
 }
 
-/// Obtenir les réactions d'un utilisateur
+/// Réacteurs masqués pour `viewer_id` sur un message de `author_id`
+///
+/// `blocked_by_viewer` : utilisateurs bloqués par le lecteur avec masquage des
+/// réactions. Le masquage ne porte que sur les messages du lecteur ; les autres
+/// lecteurs, dont l'utilisateur bloqué, voient toutes les réactions.
+pub fn hidden_reactors(viewer_id: i64, author_id: i64, blocked_by_viewer: &[i64]) -> HashSet<i64> {
+    if viewer_id != author_id {
+        return HashSet::new();
+    }
+    blocked_by_viewer.iter().copied().collect()
+}
+
+/// Regroupe des réactions (dans l'ordre chronologique) par emoji, sans les réacteurs masqués
+///
+/// Tri : emoji les plus utilisés d'abord, puis ordre alphabétique.
+pub fn summarize_reactions(
+    reactions: impl IntoIterator<Item = (String, ReactionUser)>,
+    hidden: &HashSet<i64>
+) -> Vec<ReactionSummary> {
+    let mut summaries: Vec<ReactionSummary> = Vec::new();
+    
+    for (emoji, user) in reactions {
+        if hidden.contains(&user.user_id) {
+            continue;
+        }
+        match summaries.iter_mut().find(|summary| summary.emoji == emoji) {
+            Some(summary) => {
+                summary.count += 1;
+                summary.users.push(user);
+            }
+            None => summaries.push(ReactionSummary { emoji, count: 1, users: vec![user] }),
+        }
+    }
+    
+    summaries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
+    summaries
+}
+
+/// Obtenir les réactions d'un utilisateur, des plus récentes aux plus anciennes
+///
+/// `before_id` : `next_cursor` de la page précédente.
 pub async fn get_user_reactions(
     hub: &ChatHub,
     user_id: i64,
-    limit: i64
-) -> Result<Vec<MessageReaction>> {
+    limit: i64,
+    before_id: Option<i64>
+) -> Result<Page<MessageReaction>> {
     tracing::info!(user_id = %user_id, limit = %limit, "👤 Récupération des réactions de l'utilisateur");
     
     validate_user_id(user_id as i32)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
     
     let reactions = query_as::<_, MessageReaction>("
         SELECT id, message_id, user_id, emoji, created_at
         FROM message_reactions
-        WHERE user_id = $1
-        ORDER BY created_at DESC
+        WHERE user_id = $1 AND ($3::bigint IS NULL OR id < $3)
+        ORDER BY id DESC
         LIMIT $2
     ")
     .bind(user_id)
-    .bind(limit)
+    .bind(validated_limit + 1)
+    .bind(before_id)
     .fetch_all(&hub.db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("get_user_reactions", e))?;
+    let page = Page::from_overfetch(reactions, validated_limit, |reaction| reaction.id);
+    
+    tracing::info!(user_id = %user_id, reaction_count = %page.len(), "✅ Réactions de l'utilisateur récupérées");
+    Ok(page)
+}
+
+/// Réactions ajoutées ou retirées depuis `since` sur les messages donnés
+///
+/// Un seul changement par réaction, son dernier état : un ajout suivi d'un
+/// retrait dans la fenêtre donne un retrait. Les messages inaccessibles sont
+/// ignorés et les réacteurs masqués par un blocage restent masqués.
+pub async fn get_reaction_changes(
+    hub: &ChatHub,
+    requesting_user_id: i64,
+    message_ids: &[i64],
+    since: DateTime<Utc>
+) -> Result<Vec<ReactionDelta>> {
+    tracing::debug!(user_id = %requesting_user_id, messages = %message_ids.len(), since = %since, "🔁 Récupération des changements de réactions");
+    
+    validate_user_id(requesting_user_id as i32)?;
+    if message_ids.len() > MAX_REACTION_DELTA_MESSAGES {
+        return Err(ChatError::OutOfRange {
+            field: "messageIds".to_string(),
+            value: message_ids.len() as i64,
+            min: 0,
+            max: MAX_REACTION_DELTA_MESSAGES as i64,
+        });
+    }
+    if message_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    // Messages accessibles (même règle que `check_message_access`) et leur auteur
+    let authors: Vec<(i64, i64)> = query(&format!("
+        SELECT m.id, m.author_id
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        LEFT JOIN conversation_members cm ON cm.conversation_id = c.id AND cm.user_id = $2 AND cm.left_at IS NULL
+        WHERE m.id = ANY($1) AND {}
+    ", message_access_filter(2)))
+    .bind(message_ids)
+    .bind(requesting_user_id)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_reaction_delta_access", e))?
+    .into_iter()
+    .map(|row| (row.get("id"), row.get("author_id")))
+    .collect();
+    if authors.is_empty() {
+        return Ok(Vec::new());
+    }
+    let accessible: Vec<i64> = authors.iter().map(|(id, _)| *id).collect();
+    
+    let blocked_ids: Vec<i64> = query("
+        SELECT COALESCE(ARRAY(
+            SELECT CASE WHEN dc.user1_id = $1 THEN dc.user2_id ELSE dc.user1_id END
+            FROM dm_conversations dc
+            WHERE dc.is_blocked = TRUE AND dc.hide_reactions = TRUE AND dc.blocked_by = $1
+        ), '{}')
+    ")
+    .bind(requesting_user_id)
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_hidden_reactors", e))?
+    .get(0);
+    
+    let rows = query("
+        SELECT message_id, user_id, emoji, created_at as at, FALSE as removed
+        FROM message_reactions
+        WHERE message_id = ANY($1) AND created_at > $2
+        UNION ALL
+        SELECT message_id, user_id, emoji, removed_at as at, TRUE as removed
+        FROM message_reaction_removals
+        WHERE message_id = ANY($1) AND removed_at > $2
+    ")
+    .bind(&accessible)
+    .bind(since)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_reaction_changes", e))?;
+    
+    let changes = rows.into_iter()
+        .map(|row| ReactionDelta {
+            message_id: row.get("message_id"),
+            user_id: row.get("user_id"),
+            emoji: row.get("emoji"),
+            action: if row.get("removed") { ReactionAction::Removed } else { ReactionAction::Added },
+            at: row.get("at"),
+        })
+        .filter(|change| {
+            let author_id = authors.iter().find(|(id, _)| *id == change.message_id).map(|(_, author)| *author).unwrap_or(0);
+            !hidden_reactors(requesting_user_id, author_id, &blocked_ids).contains(&change.user_id)
+        });
     
-    tracing::info!(user_id = %user_id, reaction_count = %reactions.len(), "✅ Réactions de l'utilisateur récupérées");
-    Ok(reactions)
+    Ok(latest_reaction_changes(changes, since))
+}
+
+/// Ne garde que le dernier changement de chaque réaction, postérieur à `since`
+///
+/// Résultat trié chronologiquement : l'appliquer dans l'ordre reproduit l'état courant.
+pub fn latest_reaction_changes(
+    changes: impl IntoIterator<Item = ReactionDelta>,
+    since: DateTime<Utc>
+) -> Vec<ReactionDelta> {
+    let mut latest: Vec<ReactionDelta> = Vec::new();
+    
+    for change in changes.into_iter().filter(|change| change.at > since) {
+        match latest.iter_mut().find(|kept| {
+            kept.message_id == change.message_id && kept.user_id == change.user_id && kept.emoji == change.emoji
+        }) {
+            // À égalité, le retrait l'emporte : il ne peut suivre que l'ajout
+            Some(kept) if (change.at, change.action) > (kept.at, kept.action) => *kept = change,
+            Some(_) => {}
+            None => latest.push(change),
+        }
+    }
+    
+    latest.sort_by_key(|change| change.at);
+    latest
+}
+
+/// Réaction enregistrée par `RoomRepository::insert_reaction`
+#[derive(Debug)]
+pub struct AddedReaction {
+    /// Utilisateurs ayant accès au message, destinataires de la mise à jour
+    pub audience: Vec<i64>,
+    /// Une règle de mise en avant du salon porte sur cet emoji
+    pub highlight_rule: bool,
 }
 
 /// Obtenir les emojis les plus utilisés
@@ -367,8 +495,20 @@ async fn broadcast_reaction_update(
     emoji: &str
 ) -> Result<()> {
     // Récupérer les utilisateurs qui ont accès au message
-    let users_with_access = get_message_access_users(hub, message_id).await?;
-    
+    let users_with_access = get_message_access_users(&hub.db, message_id).await?;
+    send_reaction_update(hub, users_with_access, message_id, action, user_id, emoji).await;
+    Ok(())
+}
+
+/// Envoie la mise à jour aux utilisateurs connectés parmi `users_with_access`
+async fn send_reaction_update(
+    hub: &ChatHub,
+    users_with_access: Vec<i64>,
+    message_id: i64,
+    action: &str,
+    user_id: i64,
+    emoji: &str
+) {
     let payload = json!({
         "type": "reaction_update",
         "data": {
@@ -380,14 +520,11 @@ async fn broadcast_reaction_update(
         }
     });
     
-    let clients = hub.clients.read().await;
     let mut successful_sends = 0;
     
-    for access_user_id in users_with_access {
-        if let Some(client) = clients.get(&(access_user_id as i32)) {
-            if client.send_text(&payload.to_string()) {
-                successful_sends += 1;
-            }
+    for client in hub.clients.get_many(users_with_access.into_iter().map(|access_user_id| access_user_id as i32)).await {
+        if client.send_text(&payload.to_string()) {
+            successful_sends += 1;
         }
     }
     
@@ -397,8 +534,6 @@ async fn broadcast_reaction_update(
         successful_sends = %successful_sends,
         "📡 Mise à jour de réaction diffusée"
     );
-    
-    Ok(())
 }
 
 // ================================================================
@@ -406,7 +541,7 @@ async fn broadcast_reaction_update(
 // ================================================================
 
 /// Valider un emoji (caractères autorisés et longueur)
-fn validate_emoji(emoji: &str) -> Result<()> {
+pub(crate) fn validate_emoji(emoji: &str) -> Result<()> {
     if emoji.is_empty() || emoji.len() > 20 {
         return Err(ChatError::configuration_error("Emoji invalide"));
     }
@@ -430,26 +565,39 @@ fn validate_emoji(emoji: &str) -> Result<()> {
     Ok(())
 }
 
+/// Accès d'un utilisateur `$user` à un message (alias `m`, `c`, `cm`)
+///
+/// Conversation publique ou dont il est membre, et message qu'il peut voir :
+/// un message restreint n'est accessible qu'à ses destinataires et à son auteur,
+/// un message retenu qu'à son auteur.
+fn message_access_filter(user_param: usize) -> String {
+    format!(
+        "(c.is_public = TRUE OR cm.user_id IS NOT NULL OR m.author_id = ${}) AND {} AND {}",
+        user_param,
+        visibility_clause(user_param),
+        held_clause(user_param)
+    )
+}
+
+pub(crate) fn message_access_query() -> String {
+    format!("
+        SELECT EXISTS(
+            SELECT 1 FROM messages m
+            JOIN conversations c ON c.id = m.conversation_id
+            LEFT JOIN conversation_members cm ON cm.conversation_id = c.id AND cm.user_id = $2 AND cm.left_at IS NULL
+            WHERE m.id = $1 AND {}
+        )
+    ", message_access_filter(2))
+}
+
 /// Vérifier si un utilisateur a accès à un message
-async fn check_message_access(
+pub(crate) async fn check_message_access(
     tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
     message_id: i64,
     user_id: i64
 ) -> Result<bool> {
     // Vérifier si c'est un message dans une conversation où l'utilisateur est membre
-    let has_access: bool = query("
-        SELECT EXISTS(
-            SELECT 1 FROM messages m
-            JOIN conversations c ON c.id = m.conversation_id
-            LEFT JOIN conversation_members cm ON cm.conversation_id = c.id AND cm.user_id = $2 AND cm.left_at IS NULL
-            WHERE m.id = $1 
-            AND (
-                c.is_public = TRUE OR
-                cm.user_id IS NOT NULL OR
-                m.author_id = $2
-            )
-        )
-    ")
+    let has_access: bool = query(&message_access_query())
     .bind(message_id)
     .bind(user_id)
     .fetch_one(&mut **tx)
@@ -460,8 +608,44 @@ async fn check_message_access(
     Ok(has_access)
 }
 
+/// Vérifie la politique de réaction du salon contenant le message
+///
+/// Dans un salon restreint (annonces ou modérateurs uniquement), il faut être
+/// membre actif ; le rôle n'intervient qu'en mode `ModeratorsOnly`.
+pub(crate) async fn check_reaction_policy(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    message_id: i64,
+    user_id: i64
+) -> Result<bool> {
+    let row = query("
+        SELECT c.post_policy, cm.role
+        FROM messages m
+        JOIN conversations c ON c.id = m.conversation_id
+        LEFT JOIN conversation_members cm ON cm.conversation_id = c.id AND cm.user_id = $2 AND cm.left_at IS NULL
+        WHERE m.id = $1
+    ")
+    .bind(message_id)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_reaction_policy", e))?;
+    
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    
+    let policy = RoomPostPolicy::from_db(row.get("post_policy"));
+    let member_role: Option<String> = row.get("role");
+    
+    Ok(match (policy, member_role) {
+        (RoomPostPolicy::Everyone, _) => true,
+        (_, Some(role)) => policy.can_react(&role),
+        (_, None) => false,
+    })
+}
+
 /// Obtenir la liste des utilisateurs qui ont accès à un message
-async fn get_message_access_users(hub: &ChatHub, message_id: i64) -> Result<Vec<i64>> {
+pub(crate) async fn get_message_access_users(db: &PgPool, message_id: i64) -> Result<Vec<i64>> {
     let users = query("
         SELECT DISTINCT cm.user_id
         FROM messages m
@@ -476,7 +660,7 @@ async fn get_message_access_users(hub: &ChatHub, message_id: i64) -> Result<Vec<
         WHERE m.id = $1
     ")
     .bind(message_id)
-    .fetch_all(&hub.db)
+    .fetch_all(db)
     .await
     .map_err(|e| ChatError::from_sqlx_error("get_message_access_users", e))?
     .into_iter()
@@ -484,4 +668,89 @@ async fn get_message_access_users(hub: &ChatHub, message_id: i64) -> Result<Vec<
     .collect();
     
     Ok(users)
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: i64 = 1;
+    const BOB: i64 = 2;
+    const CAROL: i64 = 3;
+
+    fn reactions() -> Vec<(String, ReactionUser)> {
+        [(BOB, "👍"), (CAROL, "👍"), (BOB, "🎉"), (CAROL, "❤️")]
+            .into_iter()
+            .map(|(user_id, emoji)| (emoji.to_string(), ReactionUser {
+                user_id,
+                username: format!("user{}", user_id),
+                created_at: Utc::now(),
+            }))
+            .collect()
+    }
+
+    fn counts(summaries: &[ReactionSummary]) -> Vec<(&str, i64)> {
+        summaries.iter().map(|summary| (summary.emoji.as_str(), summary.count)).collect()
+    }
+
+    #[test]
+    fn test_blocker_no_longer_sees_blocked_user_reactions() {
+        // Alice a bloqué Bob avec masquage ; le message est d'Alice
+        let alice_view = summarize_reactions(reactions(), &hidden_reactors(ALICE, ALICE, &[BOB]));
+        assert_eq!(counts(&alice_view), vec![("❤️", 1), ("👍", 1)]);
+        assert!(alice_view.iter().all(|summary| summary.users.iter().all(|user| user.user_id != BOB)));
+
+        // Bob voit toujours sa réaction
+        let bob_view = summarize_reactions(reactions(), &hidden_reactors(BOB, ALICE, &[]));
+        assert_eq!(counts(&bob_view), vec![("👍", 2), ("❤️", 1), ("🎉", 1)]);
+    }
+
+    fn change(user_id: i64, emoji: &str, action: ReactionAction, minute: i64) -> ReactionDelta {
+        ReactionDelta {
+            message_id: 10,
+            user_id,
+            emoji: emoji.to_string(),
+            action,
+            at: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_delta_reflects_additions_and_removals_in_window() {
+        let since = DateTime::from_timestamp(10 * 60, 0).unwrap();
+        let log = vec![
+            // Avant la fenêtre : déjà connu du client
+            change(BOB, "👍", ReactionAction::Added, 5),
+            // Carol ajoute puis retire, Bob retire son pouce, Alice ajoute
+            change(CAROL, "🎉", ReactionAction::Added, 11),
+            change(BOB, "👍", ReactionAction::Removed, 12),
+            change(CAROL, "🎉", ReactionAction::Removed, 13),
+            change(ALICE, "❤️", ReactionAction::Added, 14),
+        ];
+
+        let delta = latest_reaction_changes(log, since);
+        assert_eq!(delta, vec![
+            change(BOB, "👍", ReactionAction::Removed, 12),
+            change(CAROL, "🎉", ReactionAction::Removed, 13),
+            change(ALICE, "❤️", ReactionAction::Added, 14),
+        ]);
+    }
+
+    #[test]
+    fn test_readded_reaction_ends_as_added() {
+        let since = DateTime::from_timestamp(0, 0).unwrap();
+        let log = vec![
+            change(BOB, "👍", ReactionAction::Added, 3),
+            change(BOB, "👍", ReactionAction::Removed, 1),
+            change(BOB, "👍", ReactionAction::Added, 2),
+        ];
+        assert_eq!(latest_reaction_changes(log, since), vec![change(BOB, "👍", ReactionAction::Added, 3)]);
+
+        // Ajout et retrait dans la même seconde : le retrait l'emporte
+        let same_time = vec![
+            change(BOB, "👍", ReactionAction::Removed, 4),
+            change(BOB, "👍", ReactionAction::Added, 4),
+        ];
+        assert_eq!(latest_reaction_changes(same_time, since)[0].action, ReactionAction::Removed);
+    }
+}
@@ -0,0 +1,291 @@
+//! Module des accusés de lecture des salons
+//!
+//! Fonctionnalités :
+//! - Marqueur de lecture par membre (`conversation_members.last_read_message_id`),
+//!   monotone : seul un marqueur en avance est écrit puis diffusé
+//! - Liste « vu par » d'un message, paginée par identifiant de membre
+//! - Réglage par salon : identités visibles ou simple compteur (par défaut)
+
+use sqlx::{query, FromRow, Row};
+use serde::{Serialize, Deserialize};
+use crate::hub::common::ChatHub;
+use crate::hub::held_messages::held_clause;
+use crate::validation::validate_limit;
+use crate::error::{ChatError, Result};
+use serde_json::{json, Value};
+
+/// Taille de page maximale de la liste « vu par »
+pub const MAX_SEEN_BY_PAGE: i64 = 100;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Visibilité de la liste « vu par » d'un salon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeenByMode {
+    /// Seul le nombre de lecteurs est exposé
+    #[default]
+    CountOnly,
+    /// Les identités des lecteurs sont exposées
+    Members,
+}
+
+impl SeenByMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::CountOnly => "count_only",
+            Self::Members => "members",
+        }
+    }
+
+    /// Valeur inconnue en base : on retombe sur le mode le plus discret
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "members" => Self::Members,
+            _ => Self::CountOnly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct SeenByMember {
+    pub user_id: i64,
+    pub username: String,
+    pub last_read_message_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeenBy {
+    pub message_id: i64,
+    pub seen_count: i64,
+    /// `None` lorsque le salon masque les identités
+    pub members: Option<Vec<SeenByMember>>,
+    /// Curseur (`after_user_id`) de la page suivante
+    pub next_cursor: Option<i64>,
+}
+
+impl SeenBy {
+    /// Assemble la réponse selon le mode du salon
+    ///
+    /// `page` contient au plus `limit + 1` lignes : la ligne excédentaire signale
+    /// l'existence d'une page suivante.
+    pub fn build(message_id: i64, mode: SeenByMode, seen_count: i64, mut page: Vec<SeenByMember>, limit: i64) -> Self {
+        if mode == SeenByMode::CountOnly {
+            return Self { message_id, seen_count, members: None, next_cursor: None };
+        }
+
+        let has_more = page.len() as i64 > limit;
+        page.truncate(limit as usize);
+        let next_cursor = if has_more { page.last().map(|m| m.user_id) } else { None };
+
+        Self { message_id, seen_count, members: Some(page), next_cursor }
+    }
+}
+
+// ================================================================
+// MARQUEURS DE LECTURE
+// ================================================================
+
+/// Marqueur de lecture d'un membre après `mark_room_read`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomReadMarker {
+    pub room_id: i64,
+    pub last_read_message_id: i64,
+    pub unread_count: i64,
+    /// Le marqueur a avancé (sinon rien n'a été écrit ni diffusé)
+    #[serde(skip)]
+    pub advanced: bool,
+    /// Délai conseillé avant le prochain marqueur de ce salon
+    pub debounce_ms: u64,
+}
+
+impl RoomReadMarker {
+    pub fn to_frame(&self) -> Value {
+        json!({
+            "type": "room_read",
+            "data": self
+        })
+    }
+}
+
+/// Avance le marqueur de lecture d'un membre jusqu'à `up_to_message_id`
+///
+/// Une seule requête : l'écriture n'a lieu que si le marqueur avance (jamais de
+/// retour en arrière), et le nombre de non lus est recalculé au passage. Un
+/// marqueur inchangé n'est pas diffusé aux autres sessions ; la réponse indique
+/// au client le délai à respecter entre deux marqueurs pendant le défilement.
+pub async fn mark_room_read(hub: &ChatHub, user_id: i64, room_id: i64, up_to_message_id: i64) -> Result<RoomReadMarker> {
+    tracing::debug!(user_id = %user_id, room_id = %room_id, up_to = %up_to_message_id, "👁️ Mise à jour du marqueur de lecture");
+
+    let row = query(&mark_read_query())
+    .bind(room_id)
+    .bind(user_id)
+    .bind(up_to_message_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("mark_room_read", e))?
+    .ok_or_else(|| ChatError::unauthorized("mark_room_read"))?;
+
+    if !row.get::<bool, _>("message_exists") {
+        return Err(ChatError::not_found("message", &up_to_message_id.to_string()));
+    }
+
+    let marker = RoomReadMarker {
+        room_id,
+        last_read_message_id: row.get("last_read_message_id"),
+        unread_count: row.get("unread_count"),
+        advanced: row.get("advanced"),
+        debounce_ms: hub.config.limits.read_marker_debounce.as_millis() as u64,
+    };
+
+    // Autres sessions du membre : seulement si le marqueur a avancé
+    if marker.advanced {
+        hub.send_to_user_sessions(user_id as i32, &marker.to_frame().to_string()).await;
+    }
+
+    Ok(marker)
+}
+
+/// Avance le marqueur de `$2` dans le salon `$1` jusqu'à `$3`
+///
+/// Le compte des non-lus exclut les messages restreints hors de sa portée et
+/// les messages retenus, qu'il ne voit pas.
+pub(crate) fn mark_read_query() -> String {
+    format!("
+        WITH target AS (
+            SELECT id FROM messages WHERE id = $3 AND conversation_id = $1
+        ),
+        updated AS (
+            UPDATE conversation_members
+            SET last_read_message_id = $3
+            WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+              AND COALESCE(last_read_message_id, 0) < $3
+              AND EXISTS (SELECT 1 FROM target)
+            RETURNING last_read_message_id
+        ),
+        marker AS (
+            SELECT COALESCE((SELECT last_read_message_id FROM updated), cm.last_read_message_id, 0) AS last_read_message_id
+            FROM conversation_members cm
+            WHERE cm.conversation_id = $1 AND cm.user_id = $2 AND cm.left_at IS NULL
+        )
+        SELECT
+            marker.last_read_message_id,
+            EXISTS (SELECT 1 FROM updated) AS advanced,
+            EXISTS (SELECT 1 FROM target) AS message_exists,
+            (
+                SELECT COUNT(*) FROM messages m
+                WHERE m.conversation_id = $1
+                  AND m.id > marker.last_read_message_id
+                  AND m.author_id != $2
+                  AND m.status != 'deleted'
+                  AND (m.visible_to IS NULL OR $2 = ANY(m.visible_to))
+                  AND {held}
+            ) AS unread_count
+        FROM marker
+    ", held = held_clause(2))
+}
+
+// ================================================================
+// LISTE « VU PAR »
+// ================================================================
+
+/// Membres dont le marqueur de lecture atteint ou dépasse le message
+pub async fn get_message_seen_by(
+    hub: &ChatHub,
+    room_id: i64,
+    message_id: i64,
+    requester_id: i64,
+    limit: i64,
+    after_user_id: Option<i64>
+) -> Result<SeenBy> {
+    tracing::debug!(room_id = %room_id, message_id = %message_id, requester_id = %requester_id, "👁️ Récupération de la liste vu par");
+
+    let limit = validate_limit(limit, &hub.config.limits)?.min(MAX_SEEN_BY_PAGE);
+
+    let (mode, author_id) = hub.room_repository.seen_by_context(room_id, message_id, requester_id).await?
+        .ok_or_else(|| ChatError::not_found("message", &message_id.to_string()))?;
+
+    let seen_count = hub.room_repository.count_seen_by(room_id, message_id, author_id).await?;
+
+    let page = if mode == SeenByMode::Members {
+        hub.room_repository.list_seen_by(room_id, message_id, author_id, after_user_id.unwrap_or(0), limit + 1).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(SeenBy::build(message_id, mode, seen_count, page, limit))
+}
+
+/// Modifie la visibilité de la liste « vu par » d'un salon (propriétaire ou admin)
+pub async fn set_seen_by_mode(hub: &ChatHub, room_id: i64, user_id: i64, mode: SeenByMode) -> Result<()> {
+    tracing::info!(user_id = %user_id, room_id = %room_id, mode = %mode.as_str(), "👁️ Changement de la visibilité des lectures");
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let user_role: Option<String> = query("
+        SELECT role FROM conversation_members
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+    .map(|row| row.get("role"));
+
+    match user_role.as_deref() {
+        Some("owner") | Some("admin") => {},
+        _ => return Err(ChatError::unauthorized("set_seen_by_mode"))
+    }
+
+    query("UPDATE conversations SET seen_by_mode = $1, updated_at = NOW() WHERE id = $2")
+        .bind(mode.as_str())
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_seen_by_mode", e))?;
+
+    hub.audit_sink.record(&mut *tx, "room_seen_by_mode_changed", Some(user_id), json!({
+        "room_id": room_id,
+        "mode": mode.as_str()
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_marker_frame_omits_internal_flag() {
+        let marker = RoomReadMarker { room_id: 3, last_read_message_id: 120, unread_count: 4, advanced: true, debounce_ms: 2000 };
+        let frame = marker.to_frame();
+        assert_eq!(frame["type"], "room_read");
+        assert_eq!(frame["data"]["lastReadMessageId"], 120);
+        assert_eq!(frame["data"]["unreadCount"], 4);
+        assert_eq!(frame["data"]["debounceMs"], 2000);
+        assert!(frame["data"].get("advanced").is_none());
+    }
+
+    #[test]
+    fn test_hidden_identities_return_count_only() {
+        let seen = SeenBy::build(42, SeenByMode::CountOnly, 7, Vec::new(), 20);
+        assert_eq!(seen.seen_count, 7);
+        assert!(seen.members.is_none());
+        assert!(seen.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_unknown_mode_defaults_to_count_only() {
+        assert_eq!(SeenByMode::from_db("members"), SeenByMode::Members);
+        assert_eq!(SeenByMode::from_db("everyone"), SeenByMode::CountOnly);
+    }
+}
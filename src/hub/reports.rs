@@ -0,0 +1,355 @@
+//! Module de signalement des messages
+//!
+//! Les utilisateurs signalent un message avec une raison :
+//! - Un seul signalement par utilisateur et par message
+//! - Signalements limités en fréquence (`SecurityAction::ReportMessage`)
+//! - Au-delà de `limits.report_flag_threshold` signaleurs distincts, le message
+//!   est marqué et apparaît dans la file de modération avec les raisons
+//! - Les modérateurs connectés sont prévenus en temps réel au marquage
+//! - La raison passe le filtre de contenu ; citer le message signalé
+//!   (`> ...`) reste possible, les règles que la citation déclenche sont
+//!   relevées avec le signalement (`exempted_rules`)
+//! - Les messages retenus par le filtre (`held_messages`) rejoignent la même
+//!   file, sans signalement
+
+use sqlx::{query, Row};
+use serde::Serialize;
+use crate::hub::common::ChatHub;
+use crate::hub::encrypted_rooms::open_row_content;
+use crate::security::{ContentFilter, FilterRule, SecurityAction};
+use crate::validation::{validate_limit, validate_unicode_text};
+use crate::error::{ChatError, Result};
+use crate::pagination::Page;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Longueur maximale de la raison d'un signalement (en caractères)
+pub const MAX_REPORT_REASON_LENGTH: usize = 500;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Résultat d'un signalement
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportOutcome {
+    pub report_id: i64,
+    pub message_id: i64,
+    pub report_count: i64,
+    /// Le message vient d'être placé en file de modération
+    pub flagged: bool,
+    /// Règles déclenchées par la citation du message signalé
+    pub exempted_rules: Vec<FilterRule>,
+}
+
+/// Signalement tel qu'affiché aux modérateurs
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageReport {
+    pub reporter_id: i64,
+    pub reporter_username: String,
+    pub reason: String,
+    /// Règles déclenchées par la citation du message signalé
+    pub exempted_rules: Vec<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Message marqué dans la file de modération
+#[derive(Debug, Clone, Serialize)]
+pub struct FlaggedMessage {
+    pub message_id: i64,
+    pub conversation_id: i64,
+    pub author_id: i64,
+    pub author_username: String,
+    pub content: String,
+    pub flagged_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Message retenu par le filtre (salon en mode `flag`), invisible jusqu'à examen
+    pub held: bool,
+    pub held_reason: Option<String>,
+    pub reports: Vec<MessageReport>,
+}
+
+/// Nettoie et valide la raison d'un signalement
+pub fn normalize_report_reason(reason: &str) -> Result<String> {
+    let reason = reason.trim();
+
+    if reason.is_empty() {
+        return Err(ChatError::configuration_error("La raison du signalement est requise"));
+    }
+
+    if reason.chars().count() > MAX_REPORT_REASON_LENGTH {
+        return Err(ChatError::configuration_error("Raison du signalement trop longue (max 500 caractères)"));
+    }
+
+    validate_unicode_text("reason", reason)?;
+    Ok(reason.to_string())
+}
+
+/// Le message doit-il être placé en file de modération ?
+pub fn should_flag(already_flagged: bool, report_count: i64, threshold: u32) -> bool {
+    !already_flagged && report_count >= threshold as i64
+}
+
+// ================================================================
+// SIGNALEMENT
+// ================================================================
+
+/// Signale un message visible par l'utilisateur
+pub async fn report_message(hub: &ChatHub, message_id: i64, reporter_id: i64, reason: &str) -> Result<ReportOutcome> {
+    tracing::info!(message_id = %message_id, reporter_id = %reporter_id, "🚩 Signalement de message");
+
+    let reason = normalize_report_reason(reason)?;
+    hub.check_action_limit(reporter_id as i32, SecurityAction::ReportMessage).await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    // Verrou sur le message : les comptages concurrents restent cohérents
+    let message = query("
+        SELECT m.author_id, m.conversation_id, m.is_flagged, m.is_e2ee, m.content, m.encryption_key_id, m.wrapped_key
+        FROM messages m
+        WHERE m.id = $1 AND m.status != 'deleted'
+          AND (m.visible_to IS NULL OR m.author_id = $2 OR $2 = ANY(m.visible_to))
+          AND (
+            EXISTS (
+                SELECT 1 FROM conversation_members cm
+                WHERE cm.conversation_id = m.conversation_id AND cm.user_id = $2 AND cm.left_at IS NULL
+            )
+            OR EXISTS (
+                SELECT 1 FROM dm_conversations dc
+                WHERE dc.id = m.conversation_id AND (dc.user1_id = $2 OR dc.user2_id = $2)
+            )
+          )
+        FOR UPDATE OF m
+    ")
+    .bind(message_id)
+    .bind(reporter_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_reported_message", e))?
+    .ok_or_else(|| ChatError::not_found("message", &message_id.to_string()))?;
+
+    let author_id: i64 = message.get("author_id");
+    let conversation_id: i64 = message.get("conversation_id");
+
+    if author_id == reporter_id {
+        return Err(ChatError::configuration_error("Impossible de signaler son propre message"));
+    }
+
+    // Contenu chiffré de bout en bout : illisible pour la modération
+    if message.get::<bool, _>("is_e2ee") {
+        return Err(ChatError::feature_not_available("report_message", "Message chiffré de bout en bout"));
+    }
+
+    // La raison est conservée telle quelle (lue par les seuls modérateurs) :
+    // le filtre ne sert qu'à refuser, sauf pour la citation du message signalé
+    let exempted_rules = if hub.config.security.content_filtering {
+        let reported = open_row_content(hub.message_cipher.as_ref(), &message, "content")?;
+        ContentFilter::with_config(&hub.config.security.content_filter)?
+            .check_content_with_exemptions(&reason, &[&reported])?
+            .exempted_rules
+    } else {
+        Vec::new()
+    };
+    let exempted_names: Vec<&str> = exempted_rules.iter().map(FilterRule::as_str).collect();
+
+    let report_id: i64 = query("
+        INSERT INTO message_reports (message_id, reporter_id, reason, exempted_rules)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (message_id, reporter_id) DO NOTHING
+        RETURNING id
+    ")
+    .bind(message_id)
+    .bind(reporter_id)
+    .bind(&reason)
+    .bind(&exempted_names)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_message_report", e))?
+    .map(|row| row.get("id"))
+    .ok_or_else(|| ChatError::configuration_error("Message déjà signalé"))?;
+
+    if !exempted_rules.is_empty() {
+        hub.audit_sink.record(&mut *tx, "moderation_quoted_content_exempted", Some(reporter_id), json!({
+            "message_id": message_id,
+            "report_id": report_id,
+            "rules": exempted_names
+        })).await?;
+    }
+
+    let report_count: i64 = query("SELECT COUNT(*) FROM message_reports WHERE message_id = $1")
+        .bind(message_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("count_message_reports", e))?
+        .get(0);
+
+    let flagged = should_flag(message.get("is_flagged"), report_count, hub.config.limits.report_flag_threshold);
+    if flagged {
+        query("UPDATE messages SET is_flagged = TRUE, flagged_at = NOW() WHERE id = $1")
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("flag_message", e))?;
+
+        hub.audit_sink.record(&mut *tx, "moderation_message_flagged", None, json!({
+            "message_id": message_id,
+            "conversation_id": conversation_id,
+            "author_id": author_id,
+            "report_count": report_count
+        })).await?;
+    }
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    if flagged {
+        tracing::warn!(message_id = %message_id, report_count = %report_count, "🚨 Message placé en file de modération");
+        notify_moderators(hub, conversation_id, message_id, report_count).await?;
+    }
+
+    Ok(ReportOutcome { report_id, message_id, report_count, flagged, exempted_rules })
+}
+
+/// Prévient les modérateurs connectés (salon et équipe globale)
+async fn notify_moderators(hub: &ChatHub, conversation_id: i64, message_id: i64, report_count: i64) -> Result<()> {
+    let reasons: Vec<String> = query("
+        SELECT reason FROM message_reports
+        WHERE message_id = $1
+        ORDER BY created_at
+    ")
+    .bind(message_id)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("list_report_reasons", e))?
+    .into_iter()
+    .map(|row| row.get("reason"))
+    .collect();
+
+    let text = json!({
+        "type": "message_flagged",
+        "data": {
+            "messageId": message_id,
+            "conversationId": conversation_id,
+            "reportCount": report_count,
+            "reasons": reasons
+        }
+    }).to_string();
+
+    send_to_moderators(hub, conversation_id, &text).await
+}
+
+/// Envoie une trame aux modérateurs connectés d'une conversation (salon et équipe globale)
+pub(crate) async fn send_to_moderators(hub: &ChatHub, conversation_id: i64, text: &str) -> Result<()> {
+    let moderator_ids = hub.moderation_repository.moderator_ids(conversation_id).await?;
+
+    for client in hub.clients.get_many(moderator_ids.into_iter().map(|user_id| user_id as i32)).await {
+        client.send_text(text);
+    }
+
+    Ok(())
+}
+
+// ================================================================
+// FILE DE MODÉRATION
+// ================================================================
+
+/// Messages marqués que le modérateur peut traiter, du plus ancien au plus récent
+///
+/// L'équipe globale voit toute la file, un modérateur de salon ne voit que ses salons.
+/// `cursor` est le `next_cursor` de la page précédente.
+pub async fn get_moderation_queue(hub: &ChatHub, moderator_id: i64, limit: i64, cursor: Option<i64>) -> Result<Page<FlaggedMessage>> {
+    tracing::debug!(moderator_id = %moderator_id, "🚩 Récupération de la file de modération");
+
+    let limit = validate_limit(limit, &hub.config.limits)?;
+    let offset = cursor.unwrap_or(0).max(0);
+
+    let rows = query("
+        SELECT m.id, m.conversation_id, m.author_id, u.username as author_username,
+               m.content, m.encryption_key_id, m.wrapped_key, m.flagged_at, m.is_held, m.held_reason
+        FROM messages m
+        JOIN users u ON u.id = m.author_id
+        WHERE m.is_flagged AND m.status != 'deleted'
+          AND (
+            EXISTS (SELECT 1 FROM users s WHERE s.id = $1 AND s.role::text IN ('moderator', 'admin', 'owner'))
+            OR EXISTS (
+                SELECT 1 FROM conversation_members cm
+                WHERE cm.conversation_id = m.conversation_id AND cm.user_id = $1 AND cm.left_at IS NULL
+                  AND cm.role IN ('owner', 'admin', 'moderator')
+            )
+          )
+        ORDER BY m.flagged_at, m.id
+        LIMIT $2 OFFSET $3
+    ")
+    .bind(moderator_id)
+    .bind(limit + 1)
+    .bind(offset)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_moderation_queue", e))?;
+
+    let message_ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
+
+    let mut reports_by_message: HashMap<i64, Vec<MessageReport>> = HashMap::new();
+    for row in query("
+        SELECT r.message_id, r.reporter_id, u.username, r.reason, r.exempted_rules, r.created_at
+        FROM message_reports r
+        JOIN users u ON u.id = r.reporter_id
+        WHERE r.message_id = ANY($1)
+        ORDER BY r.created_at
+    ")
+    .bind(&message_ids)
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("list_message_reports", e))?
+    {
+        reports_by_message.entry(row.get("message_id")).or_default().push(MessageReport {
+            reporter_id: row.get("reporter_id"),
+            reporter_username: row.get("username"),
+            reason: row.get("reason"),
+            exempted_rules: row.get("exempted_rules"),
+            created_at: row.get("created_at"),
+        });
+    }
+
+    let messages = rows.into_iter()
+        .map(|row| {
+            let message_id: i64 = row.get("id");
+            Ok(FlaggedMessage {
+                message_id,
+                conversation_id: row.get("conversation_id"),
+                author_id: row.get("author_id"),
+                author_username: row.get("author_username"),
+                content: open_row_content(hub.message_cipher.as_ref(), &row, "content")?,
+                flagged_at: row.get("flagged_at"),
+                held: row.get("is_held"),
+                held_reason: row.get("held_reason"),
+                reports: reports_by_message.remove(&message_id).unwrap_or_default(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Page::from_offset(messages, limit, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_once_threshold_reached() {
+        assert!(!should_flag(false, 2, 3));
+        assert!(should_flag(false, 3, 3));
+        // Déjà en file : pas de nouvelle notification
+        assert!(!should_flag(true, 4, 3));
+        // Seuil abaissé après coup : le prochain signalement marque le message
+        assert!(should_flag(false, 5, 3));
+    }
+
+    #[test]
+    fn test_report_reason_validation() {
+        assert_eq!(normalize_report_reason("  spam  ").unwrap(), "spam");
+        assert!(normalize_report_reason("   ").is_err());
+        assert!(normalize_report_reason(&"x".repeat(MAX_REPORT_REASON_LENGTH + 1)).is_err());
+        assert!(normalize_report_reason("a\0b").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Réputation des comptes
+//!
+//! Score de confiance (0–100) calculé selon la formule `[reputation]` :
+//! ancienneté du compte, e-mail vérifié, double authentification, messages
+//! publiés, sanctions reçues et ajustement manuel des administrateurs.
+//! - Compteurs (`user_reputation`) incrémentés à chaque message et sanction ;
+//!   le score stocké est recalculé quand un compteur le fait changer
+//! - Comptes de confiance (`trusted_threshold`) : débit de messages multiplié
+//!   par `trusted_rate_multiplier`
+//! - Le mode anti-raid restreint les comptes sous `anti_raid.min_reputation`
+//! - Consultation et ajustement par les administrateurs, ajustements audités
+
+use std::time::Duration;
+use sqlx::{query, Row};
+use serde::Serialize;
+use serde_json::json;
+use chrono::{DateTime, Utc};
+use crate::config::ReputationConfig;
+use crate::hub::common::{check_global_admin, ChatHub};
+use crate::moderation::SanctionType;
+use crate::error::{ChatError, Result};
+
+/// Ajustement manuel maximal en une fois
+pub const MAX_REPUTATION_ADJUSTMENT: i32 = 100;
+
+// ================================================================
+// FORMULE
+// ================================================================
+
+/// Éléments du score d'un compte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReputationFactors {
+    pub account_age: Duration,
+    pub email_verified: bool,
+    pub two_factor: bool,
+    pub message_count: i64,
+    pub warnings: i32,
+    pub mutes: i32,
+    pub bans: i32,
+    pub adjustment: i32,
+}
+
+/// Score de réputation borné à 0–100
+pub fn compute_score(config: &ReputationConfig, factors: &ReputationFactors) -> i32 {
+    let weeks = (factors.account_age.as_secs() / (7 * 86400)).min(i32::MAX as u64) as i32;
+    let age_points = weeks.saturating_mul(config.points_per_account_week).min(config.max_account_age_points);
+    let message_points = (factors.message_count / config.messages_per_point.max(1) as i64)
+        .min(config.max_message_points as i64) as i32;
+    let penalties = factors.warnings * config.warning_penalty
+        + factors.mutes * config.mute_penalty
+        + factors.bans * config.ban_penalty;
+
+    let score = config.base
+        + age_points
+        + if factors.email_verified { config.verified_email_points } else { 0 }
+        + if factors.two_factor { config.two_factor_points } else { 0 }
+        + message_points
+        - penalties
+        + factors.adjustment;
+    score.clamp(0, 100)
+}
+
+pub fn is_trusted(config: &ReputationConfig, score: i32) -> bool {
+    score >= config.trusted_threshold
+}
+
+/// Réputation d'un compte, telle que consultée par les administrateurs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserReputation {
+    pub user_id: i64,
+    pub score: i32,
+    pub trusted: bool,
+    #[serde(skip)]
+    pub account_age: Duration,
+    pub account_age_days: u64,
+    pub email_verified: bool,
+    pub two_factor: bool,
+    pub message_count: i64,
+    pub warnings: i32,
+    pub mutes: i32,
+    pub bans: i32,
+    pub adjustment: i32,
+}
+
+impl UserReputation {
+    pub fn from_factors(config: &ReputationConfig, user_id: i64, factors: &ReputationFactors) -> Self {
+        let score = compute_score(config, factors);
+        Self {
+            user_id,
+            score,
+            trusted: is_trusted(config, score),
+            account_age: factors.account_age,
+            account_age_days: factors.account_age.as_secs() / 86400,
+            email_verified: factors.email_verified,
+            two_factor: factors.two_factor,
+            message_count: factors.message_count,
+            warnings: factors.warnings,
+            mutes: factors.mutes,
+            bans: factors.bans,
+            adjustment: factors.adjustment,
+        }
+    }
+}
+
+// ================================================================
+// CALCUL ET MISE À JOUR
+// ================================================================
+
+async fn load_factors(hub: &ChatHub, user_id: i64) -> Result<ReputationFactors> {
+    let row = query("
+        SELECT u.created_at, COALESCE(u.is_verified, FALSE) AS email_verified,
+               COALESCE(u.two_factor_enabled, FALSE) AS two_factor,
+               COALESCE(r.message_count, 0) AS message_count, COALESCE(r.warnings, 0) AS warnings,
+               COALESCE(r.mutes, 0) AS mutes, COALESCE(r.bans, 0) AS bans,
+               COALESCE(r.adjustment, 0) AS adjustment
+        FROM users u
+        LEFT JOIN user_reputation r ON r.user_id = u.id
+        WHERE u.id = $1
+    ")
+    .bind(user_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_reputation", e))?
+    .ok_or_else(|| ChatError::not_found("utilisateur", &user_id.to_string()))?;
+
+    let created_at: DateTime<Utc> = row.get("created_at");
+    Ok(ReputationFactors {
+        account_age: (Utc::now() - created_at).to_std().unwrap_or(Duration::ZERO),
+        email_verified: row.get("email_verified"),
+        two_factor: row.get("two_factor"),
+        message_count: row.get("message_count"),
+        warnings: row.get("warnings"),
+        mutes: row.get("mutes"),
+        bans: row.get("bans"),
+        adjustment: row.get("adjustment"),
+    })
+}
+
+/// Recalcule et enregistre le score du compte (ancienneté à jour)
+pub async fn refresh_reputation(hub: &ChatHub, user_id: i64) -> Result<UserReputation> {
+    let factors = load_factors(hub, user_id).await?;
+    let reputation = UserReputation::from_factors(&hub.config.reputation, user_id, &factors);
+
+    query("
+        INSERT INTO user_reputation (user_id, score) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET score = EXCLUDED.score, updated_at = NOW()
+    ")
+    .bind(user_id)
+    .bind(reputation.score)
+    .execute(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("store_reputation", e))?;
+
+    Ok(reputation)
+}
+
+/// Compte un message publié ; le score n'est recalculé qu'au gain d'un point
+pub async fn record_message(hub: &ChatHub, user_id: i64) {
+    let counted = hub.moderation_repository.count_author_message(user_id).await;
+
+    let refreshed = match counted {
+        Ok(count) if count % hub.config.reputation.messages_per_point.max(1) as i64 == 0 => {
+            refresh_reputation(hub, user_id).await.map(|_| ())
+        }
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = refreshed {
+        tracing::warn!(user_id = %user_id, error = %e, "⚠️ Réputation non mise à jour après un message");
+    }
+}
+
+/// Compte une sanction reçue et recalcule le score (les exclusions ne comptent pas)
+pub async fn record_sanction(hub: &ChatHub, user_id: i64, sanction_type: &SanctionType) -> Result<()> {
+    let column = match sanction_type {
+        SanctionType::Warning => "warnings",
+        SanctionType::Mute => "mutes",
+        SanctionType::TempBan | SanctionType::PermaBan => "bans",
+        SanctionType::Kick => return Ok(()),
+    };
+
+    query(&format!("
+        INSERT INTO user_reputation (user_id, {column}) VALUES ($1, 1)
+        ON CONFLICT (user_id) DO UPDATE SET {column} = user_reputation.{column} + 1, updated_at = NOW()
+    ", column = column))
+    .bind(user_id)
+    .execute(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("record_sanction_reputation", e))?;
+
+    let reputation = refresh_reputation(hub, user_id).await?;
+    tracing::info!(user_id = %user_id, sanction_type = ?sanction_type, score = %reputation.score, "📉 Réputation mise à jour après une sanction");
+    Ok(())
+}
+
+/// Débit de messages de l'utilisateur, relâché pour un compte de confiance (score stocké)
+pub async fn check_message_rate(hub: &ChatHub, user_id: i64) -> Result<bool> {
+    let score = hub.moderation_repository.reputation_score(user_id).await?;
+
+    let config = &hub.config.reputation;
+    let multiplier = if score.is_some_and(|score| is_trusted(config, score)) {
+        config.trusted_rate_multiplier
+    } else {
+        1
+    };
+    Ok(hub.rate_limiter.check_and_update_scaled(user_id as i32, multiplier).await)
+}
+
+// ================================================================
+// ADMINISTRATION
+// ================================================================
+
+/// Réputation détaillée d'un compte (administrateurs)
+pub async fn get_user_reputation(hub: &ChatHub, admin_id: i64, user_id: i64) -> Result<UserReputation> {
+    check_global_admin(hub, admin_id, "get_user_reputation").await?;
+    refresh_reputation(hub, user_id).await
+}
+
+/// Ajuste manuellement la réputation d'un compte (administrateurs, audité)
+pub async fn adjust_user_reputation(hub: &ChatHub, admin_id: i64, user_id: i64, delta: i32, reason: &str) -> Result<UserReputation> {
+    tracing::info!(admin_id = %admin_id, user_id = %user_id, delta = %delta, "⚖️ Ajustement de réputation");
+
+    if delta == 0 || delta.abs() > MAX_REPUTATION_ADJUSTMENT {
+        return Err(ChatError::configuration_error("Ajustement de réputation invalide (1 à 100 points)"));
+    }
+    if reason.trim().is_empty() {
+        return Err(ChatError::configuration_error("Motif de l'ajustement requis"));
+    }
+    check_global_admin(hub, admin_id, "adjust_user_reputation").await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let adjustment: i32 = query("
+        INSERT INTO user_reputation (user_id, adjustment) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET adjustment = user_reputation.adjustment + EXCLUDED.adjustment, updated_at = NOW()
+        RETURNING adjustment
+    ")
+    .bind(user_id)
+    .bind(delta)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("adjust_reputation", e))?
+    .get("adjustment");
+
+    hub.audit_sink.record(&mut *tx, "reputation_adjusted", Some(admin_id), json!({
+        "user_id": user_id,
+        "delta": delta,
+        "adjustment": adjustment,
+        "reason": reason
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    refresh_reputation(hub, user_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEEK: Duration = Duration::from_secs(7 * 86400);
+
+    #[test]
+    fn test_new_account_starts_at_base() {
+        let config = ReputationConfig::default();
+        assert_eq!(compute_score(&config, &ReputationFactors::default()), 40);
+
+        let verified = ReputationFactors { email_verified: true, two_factor: true, ..Default::default() };
+        assert_eq!(compute_score(&config, &verified), 60);
+    }
+
+    #[test]
+    fn test_age_and_messages_are_capped_and_score_clamped() {
+        let config = ReputationConfig::default();
+        let veteran = ReputationFactors {
+            account_age: WEEK * 520,
+            email_verified: true,
+            two_factor: true,
+            message_count: 1_000_000,
+            ..Default::default()
+        };
+        assert_eq!(compute_score(&config, &veteran), 100);
+        assert!(is_trusted(&config, compute_score(&config, &veteran)));
+
+        let month = ReputationFactors { account_age: WEEK * 4, message_count: 120, ..Default::default() };
+        assert_eq!(compute_score(&config, &month), 40 + 8 + 2);
+    }
+
+    #[test]
+    fn test_sanctions_and_adjustment() {
+        let config = ReputationConfig::default();
+        let banned = ReputationFactors { account_age: WEEK * 20, warnings: 1, bans: 1, ..Default::default() };
+        assert_eq!(compute_score(&config, &banned), 0);
+
+        let forgiven = ReputationFactors { mutes: 1, adjustment: 25, ..Default::default() };
+        assert_eq!(compute_score(&config, &forgiven), 40 - 15 + 25);
+
+        let mut custom = ReputationConfig::default();
+        custom.mute_penalty = 0;
+        assert_eq!(compute_score(&custom, &forgiven), 65);
+    }
+}
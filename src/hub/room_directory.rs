@@ -0,0 +1,266 @@
+//! Annuaire des salons
+//!
+//! Liste les salons disponibles avec leurs métadonnées :
+//! - Nom, sujet, nombre de membres, visibilité, appartenance du demandeur
+//! - Filtres : publics seulement, rejoints seulement, préfixe de nom
+//! - Tri par nombre de membres ou par activité récente, pagination par curseur
+//! - Un salon privé n'apparaît qu'à ses membres et aux administrateurs globaux
+//! - Les salons archivés sont exclus (`channels::listed_room_clause`)
+//!
+//! La lecture passe par `RoomRepository::list_directory`.
+
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use crate::hub::common::ChatHub;
+use crate::pagination::Page;
+use crate::validation::validate_limit;
+use crate::error::{ChatError, Result};
+
+/// Longueur maximale du préfixe de recherche
+pub const MAX_ROOM_PREFIX_LENGTH: usize = 100;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Ordre de la liste
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomOrder {
+    /// Salons les plus peuplés en premier
+    #[default]
+    MemberCount,
+    /// Dernier message (ou création) le plus récent en premier
+    RecentActivity,
+}
+
+impl RoomOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "member_count" => Some(RoomOrder::MemberCount),
+            "recent_activity" => Some(RoomOrder::RecentActivity),
+            _ => None,
+        }
+    }
+
+    /// Clé de tri SQL (décroissante), calculée sur les colonnes de l'annuaire
+    pub(crate) fn sort_expression(&self) -> &'static str {
+        match self {
+            RoomOrder::MemberCount => "member_count",
+            RoomOrder::RecentActivity => "(EXTRACT(EPOCH FROM last_activity_at) * 1000)::BIGINT",
+        }
+    }
+
+    /// Clé de tri d'un salon déjà chargé
+    pub(crate) fn sort_key(&self, room: &RoomInfo) -> i64 {
+        match self {
+            RoomOrder::MemberCount => room.member_count,
+            RoomOrder::RecentActivity => room.last_activity_at.timestamp_millis(),
+        }
+    }
+}
+
+/// Filtres de la liste
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomFilter {
+    pub public_only: bool,
+    pub joined_only: bool,
+    /// Préfixe de nom, insensible à la casse
+    pub name_prefix: Option<String>,
+    pub order: RoomOrder,
+}
+
+impl RoomFilter {
+    /// Le salon peut-il figurer dans la liste du demandeur ?
+    pub fn admits(&self, room: &RoomInfo, requester_is_admin: bool) -> bool {
+        if !room.is_public && !room.is_member && !requester_is_admin {
+            return false;
+        }
+        if self.public_only && !room.is_public {
+            return false;
+        }
+        if self.joined_only && !room.is_member {
+            return false;
+        }
+        match &self.name_prefix {
+            Some(prefix) => room.name.to_lowercase().starts_with(&prefix.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+/// Salon tel qu'affiché dans l'annuaire
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomInfo {
+    pub id: i64,
+    pub name: String,
+    pub topic: Option<String>,
+    pub member_count: i64,
+    pub is_public: bool,
+    /// Le demandeur est membre actif du salon
+    pub is_member: bool,
+    pub last_activity_at: DateTime<Utc>,
+}
+
+/// Position dans la liste : clé de tri et identifiant du dernier salon reçu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomCursor {
+    pub sort_key: i64,
+    pub room_id: i64,
+}
+
+impl RoomCursor {
+    /// Curseur désignant la suite de la liste après `room`
+    pub fn after(room: &RoomInfo, order: RoomOrder) -> Self {
+        Self { sort_key: order.sort_key(room), room_id: room.id }
+    }
+
+    /// Curseur de la page suivante d'une liste triée par `order` (`None` en fin de liste)
+    ///
+    /// La position est composée (clé de tri, id) : elle ne tient pas dans
+    /// `Page::next_cursor`, laissé vide par `list_rooms`.
+    pub fn next_page(page: &Page<RoomInfo>, order: RoomOrder) -> Option<Self> {
+        if !page.has_more {
+            return None;
+        }
+        page.items.last().map(|room| Self::after(room, order))
+    }
+
+    /// Forme opaque transmise au client (`<clé>:<id>`)
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.sort_key, self.room_id)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let invalid = || ChatError::InvalidFormat {
+            field: "cursor".to_string(),
+            reason: "Curseur de pagination invalide".to_string(),
+        };
+
+        let (sort_key, room_id) = cursor.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            sort_key: sort_key.parse().map_err(|_| invalid())?,
+            room_id: room_id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Échappe un préfixe pour un motif `LIKE` (`%`, `_` et `\` pris littéralement)
+pub fn like_prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+// ================================================================
+// LISTE DES SALONS
+// ================================================================
+
+/// Liste les salons visibles par le demandeur
+///
+/// `cursor` reprend la liste après le dernier salon d'une page précédente
+/// (voir `RoomCursor::next_page`, dont le client reçoit la forme encodée) ;
+/// `next_cursor` de la page est toujours vide.
+pub async fn list_rooms(
+    hub: &ChatHub,
+    requester_id: i64,
+    filter: RoomFilter,
+    limit: i64,
+    cursor: Option<RoomCursor>
+) -> Result<Page<RoomInfo>> {
+    tracing::debug!(requester_id = %requester_id, filter = ?filter, limit = %limit, "📋 Liste des salons");
+
+    let limit = validate_limit(limit, &hub.config.limits)?;
+
+    let name_prefix = match filter.name_prefix.as_deref().map(str::trim) {
+        Some(prefix) if prefix.chars().count() > MAX_ROOM_PREFIX_LENGTH => {
+            return Err(ChatError::configuration_error("Préfixe de recherche trop long (max 100 caractères)"));
+        }
+        Some(prefix) if !prefix.is_empty() => Some(prefix.to_string()),
+        _ => None,
+    };
+    let filter = RoomFilter { name_prefix, ..filter };
+
+    // Un salon de plus que la limite signale une page suivante
+    let rooms = hub.room_repository.list_directory(requester_id, &filter, limit + 1, cursor).await?;
+    let mut page = Page::from_overfetch(rooms, limit, |room| room.id);
+    page.next_cursor = None;
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(id: i64, name: &str, is_public: bool, is_member: bool) -> RoomInfo {
+        RoomInfo {
+            id,
+            name: name.to_string(),
+            topic: None,
+            member_count: 3,
+            is_public,
+            is_member,
+            last_activity_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_public_only_excludes_private_room() {
+        let filter = RoomFilter { public_only: true, ..Default::default() };
+
+        assert!(filter.admits(&room(1, "general", true, false), false));
+        // Privé, même rejoint ou vu par un administrateur
+        assert!(!filter.admits(&room(2, "staff", false, true), false));
+        assert!(!filter.admits(&room(2, "staff", false, true), true));
+    }
+
+    #[test]
+    fn test_private_rooms_hidden_from_non_members() {
+        let filter = RoomFilter::default();
+        let private = room(2, "staff", false, false);
+
+        assert!(!filter.admits(&private, false));
+        assert!(filter.admits(&private, true));
+        assert!(filter.admits(&room(2, "staff", false, true), false));
+    }
+
+    #[test]
+    fn test_joined_only() {
+        let filter = RoomFilter { joined_only: true, ..Default::default() };
+
+        assert!(filter.admits(&room(1, "general", true, true), false));
+        assert!(!filter.admits(&room(3, "random", true, false), false));
+    }
+
+    #[test]
+    fn test_name_prefix_filter() {
+        let filter = RoomFilter { name_prefix: Some("Dev".to_string()), ..Default::default() };
+
+        assert!(filter.admits(&room(1, "dev-backend", true, false), false));
+        assert!(filter.admits(&room(2, "DevOps", true, false), false));
+        assert!(!filter.admits(&room(3, "frontend-dev", true, false), false));
+    }
+
+    #[test]
+    fn test_like_prefix_pattern_escapes_wildcards() {
+        assert_eq!(like_prefix_pattern("dev"), "dev%");
+        assert_eq!(like_prefix_pattern("100%_sure\\"), "100\\%\\_sure\\\\%");
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let info = RoomInfo { member_count: 42, ..room(7, "general", true, false) };
+        let cursor = RoomCursor::after(&info, RoomOrder::MemberCount);
+
+        assert_eq!(cursor.encode(), "42:7");
+        assert_eq!(RoomCursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(RoomCursor::decode("42").is_err());
+        assert!(RoomCursor::decode("a:7").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Application de la politique des liens aux messages
+//!
+//! Les salons peuvent surcharger la politique du serveur
+//! (`conversations.link_policy`) ; les messages directs suivent celle du
+//! serveur. Voir `crate::link_policy`.
+
+use sqlx::{query, Row};
+use crate::hub::common::ChatHub;
+use crate::content_pipeline::UrlAutoLink;
+use crate::error::{ChatError, Result};
+use crate::link_policy::{expand_shortened_links, LinkPolicy, LinkReview, RoomLinkPolicy};
+use serde_json::Value;
+
+/// Surcharges de la politique des liens d'un salon
+pub async fn load_room_link_policy(hub: &ChatHub, room_id: i64) -> Result<RoomLinkPolicy> {
+    let policy: Option<Value> = query("SELECT link_policy FROM conversations WHERE id = $1")
+        .bind(room_id)
+        .fetch_optional(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_room_link_policy", e))?
+        .and_then(|row| row.get("link_policy"));
+
+    Ok(policy
+        .and_then(|policy| serde_json::from_value(policy).ok())
+        .unwrap_or_default())
+}
+
+/// Confronte les liens d'un message à la politique du salon (`None` : message direct)
+///
+/// Retourne le contenu éventuellement masqué et les liens signalés ; un lien
+/// refusé refuse le message.
+pub async fn review_message_links(hub: &ChatHub, room_id: Option<i64>, content: &str) -> Result<LinkReview> {
+    if UrlAutoLink::extract(content).is_empty() {
+        return Ok(LinkReview { content: content.to_string(), warnings: Vec::new() });
+    }
+
+    let mut policy = LinkPolicy::from_config(&hub.config.link_policy);
+    if let Some(room_id) = room_id {
+        policy = policy.with_room(&load_room_link_policy(hub, room_id).await?);
+    }
+
+    let expanded = expand_shortened_links(&policy, hub.link_expander.as_ref(), content).await;
+    let review = policy.review(content, &expanded).map_err(|e| {
+        tracing::warn!(room_id = ?room_id, error = %e, "🔗 Message refusé par la politique des liens");
+        e
+    })?;
+
+    if !review.warnings.is_empty() {
+        tracing::info!(room_id = ?room_id, flagged_links = %review.warnings.len(), "🔗 Liens signalés par la politique des liens");
+    }
+    Ok(review)
+}
@@ -0,0 +1,204 @@
+//! Liste des salons rejoints par un utilisateur
+//!
+//! Équivalent pour les salons de la liste des conversations DM, en une seule
+//! requête :
+//! - Aperçu d'une ligne du dernier message visible par l'utilisateur
+//!   (`limits.list_preview_length` ; chuchotements et messages retenus
+//!   d'autrui exclus, contenu chiffré au repos déchiffré)
+//! - Dernière activité : dernier message, ou adhésion pour un salon silencieux
+//! - Non lus comptés comme `get_unread_summary`, état muet et épinglé
+//! - Tri par activité récente, pagination par décalage (l'ordre change à chaque message)
+
+use sqlx::{query, Row};
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use crate::hub::common::ChatHub;
+use crate::hub::encrypted_rooms::open_row_content;
+use crate::hub::held_messages::held_clause;
+use crate::hub::visibility::visibility_clause;
+use crate::pagination::Page;
+use crate::utils::list_preview;
+use crate::validation::{validate_limit, validate_user_id};
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Dernier message d'un salon, tronqué pour la liste
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastMessagePreview {
+    pub message_id: i64,
+    pub author_id: i64,
+    pub author_username: String,
+    pub preview: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Salon rejoint, tel qu'affiché dans la liste de l'utilisateur
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRoom {
+    pub room_id: i64,
+    pub name: Option<String>,
+    pub last_message: Option<LastMessagePreview>,
+    pub last_activity_at: DateTime<Utc>,
+    pub unread_count: i64,
+    pub is_muted: bool,
+    pub is_pinned: bool,
+}
+
+// ================================================================
+// LISTE DES SALONS
+// ================================================================
+
+/// Requête de la liste : `$1` utilisateur, `$2` taille de page (+1), `$3` décalage
+fn user_rooms_query() -> String {
+    let visible = format!("{} AND {} AND m.status != 'deleted'", visibility_clause(1), held_clause(1));
+    format!("
+        SELECT
+            c.id AS room_id, c.name,
+            COALESCE(cm.is_muted, FALSE) AS is_muted,
+            COALESCE(cm.is_pinned, FALSE) AS is_pinned,
+            last.id AS last_message_id, last.author_id AS last_author_id,
+            last.author_username AS last_author_username, last.content AS last_content,
+            last.encryption_key_id, last.wrapped_key, last.created_at AS last_message_at,
+            GREATEST(last.created_at, cm.joined_at) AS last_activity_at,
+            unread.count AS unread_count,
+            COUNT(*) OVER () AS total
+        FROM conversation_members cm
+        JOIN conversations c ON c.id = cm.conversation_id AND c.type <> 'direct_message'
+        LEFT JOIN LATERAL (
+            SELECT m.id, m.author_id, u.username AS author_username, m.content,
+                   m.encryption_key_id, m.wrapped_key, m.created_at
+            FROM messages m
+            JOIN users u ON u.id = m.author_id
+            WHERE m.conversation_id = cm.conversation_id AND {visible}
+            ORDER BY m.created_at DESC, m.id DESC
+            LIMIT 1
+        ) last ON TRUE
+        CROSS JOIN LATERAL (
+            SELECT COUNT(*) AS count
+            FROM messages m
+            WHERE m.conversation_id = cm.conversation_id
+              AND m.id > COALESCE(cm.last_read_message_id, 0)
+              AND m.author_id != cm.user_id
+              AND {visible}
+        ) unread
+        WHERE cm.user_id = $1 AND cm.left_at IS NULL
+        ORDER BY last_activity_at DESC, c.id DESC
+        LIMIT $2 OFFSET $3
+    ", visible = visible)
+}
+
+/// Salons rejoints par l'utilisateur, du plus récemment actif au plus ancien
+///
+/// `cursor` est le `next_cursor` de la page précédente. Le total est toujours
+/// renseigné (calculé par la même requête).
+pub async fn get_user_rooms(hub: &ChatHub, user_id: i64, limit: i64, cursor: Option<i64>) -> Result<Page<UserRoom>> {
+    tracing::debug!(user_id = %user_id, limit = %limit, "📋 Liste des salons rejoints");
+
+    validate_user_id(user_id as i32)?;
+    let validated_limit = validate_limit(limit, &hub.config.limits)?;
+    let offset = cursor.unwrap_or(0).max(0);
+
+    let rows = query(&user_rooms_query())
+        .bind(user_id)
+        .bind(validated_limit + 1)
+        .bind(offset)
+        .fetch_all(&hub.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("get_user_rooms", e))?;
+
+    let total = rows.first().map_or(0, |row| row.get::<i64, _>("total"));
+    let mut rooms = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let last_message = match row.get::<Option<i64>, _>("last_message_id") {
+            Some(message_id) => Some(LastMessagePreview {
+                message_id,
+                author_id: row.get("last_author_id"),
+                author_username: row.get("last_author_username"),
+                preview: list_preview(&open_row_content(hub.message_cipher.as_ref(), row, "last_content")?, hub.config.limits.list_preview_length),
+                created_at: row.get("last_message_at"),
+            }),
+            None => None,
+        };
+
+        rooms.push(UserRoom {
+            room_id: row.get("room_id"),
+            name: row.get("name"),
+            last_message,
+            last_activity_at: row.get("last_activity_at"),
+            unread_count: row.get("unread_count"),
+            is_muted: row.get("is_muted"),
+            is_pinned: row.get("is_pinned"),
+        });
+    }
+
+    tracing::debug!(user_id = %user_id, room_count = %rooms.len(), total = %total, "✅ Salons rejoints listés");
+    Ok(Page::from_offset(rooms, validated_limit, offset).with_total(total))
+}
+
+/// Épingle ou désépingle un salon dans la liste de l'utilisateur (membre uniquement)
+pub async fn set_room_pinned(hub: &ChatHub, room_id: i64, user_id: i64, pinned: bool) -> Result<()> {
+    let updated = query("
+        UPDATE conversation_members SET is_pinned = $3
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(user_id)
+    .bind(pinned)
+    .execute(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("set_room_pinned", e))?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(ChatError::unauthorized("set_room_pinned"));
+    }
+
+    tracing::debug!(room_id = %room_id, user_id = %user_id, pinned = %pinned, "📌 Épinglage du salon dans la liste mis à jour");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_is_one_query_ordered_by_recency() {
+        let sql = user_rooms_query();
+        assert!(sql.contains("ORDER BY last_activity_at DESC, c.id DESC"));
+        assert!(sql.contains("LIMIT $2 OFFSET $3"));
+        assert!(sql.contains("COUNT(*) OVER ()"));
+        // Aperçu et non lus filtrés comme l'historique du demandeur
+        assert_eq!(sql.matches("m.visible_to IS NULL").count(), 2);
+        assert_eq!(sql.matches("NOT m.is_held").count(), 2);
+    }
+
+    #[test]
+    fn test_serialized_room_is_camel_case() {
+        let at = Utc::now();
+        let room = UserRoom {
+            room_id: 4,
+            name: Some("general".to_string()),
+            last_message: Some(LastMessagePreview {
+                message_id: 90,
+                author_id: 2,
+                author_username: "bob".to_string(),
+                preview: "salut".to_string(),
+                created_at: at,
+            }),
+            last_activity_at: at,
+            unread_count: 3,
+            is_muted: false,
+            is_pinned: true,
+        };
+        let value = serde_json::to_value(&room).unwrap();
+        assert_eq!(value["roomId"], 4);
+        assert_eq!(value["lastMessage"]["authorUsername"], "bob");
+        assert_eq!(value["unreadCount"], 3);
+        assert_eq!(value["isPinned"], true);
+    }
+}
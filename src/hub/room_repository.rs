@@ -0,0 +1,1368 @@
+//! Dépôt des salons et de leurs messages
+//!
+//! Les flux salon du hub (envoi, archivage, examen des messages retenus,
+//! rattrapage, restauration des adhésions, messages d'invités) lisent et
+//! écrivent par `RoomRepository` ; droits, filtres et diffusion restent dans
+//! les fonctions du hub, communes à toutes les implémentations :
+//! - `PgRoomRepository` : PostgreSQL
+//! - `testing::InMemoryRoomRepository` (feature `testing`) : mémoire, pour
+//!   exercer ces mêmes fonctions sans base
+//!
+//! Les conversations privées, réactions, pièces jointes et la modération ont
+//! leurs propres dépôts (`dm_repository`, `reaction_repository`,
+//! `attachment_repository`, `moderation_repository`). Aucun ne reçoit le
+//! hub : réglages, audit et clés leur sont passés en paramètres.
+
+use std::collections::HashSet;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use sqlx::{query, query_as, PgPool, Row};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use crate::auth::GUEST_ROLE;
+use crate::config::{LimitsConfig, ModerationConfig};
+use crate::encryption::{DataKey, MessageCipher};
+use crate::error::{ChatError, Result};
+use crate::hub::audit_sink::AuditSink;
+use crate::hub::channels::{
+    check_archive_change, check_pin_rights, listed_room_clause, load_modification_context, plan_pin_order, Room, RoomPostPolicy,
+};
+use crate::hub::common::is_global_admin;
+use crate::hub::custom_emojis::annotate_custom_emojis;
+use crate::hub::dedup::{self, DedupKey, SentMessage};
+use crate::hub::encrypted_rooms::{open_row_content, room_data_key};
+use crate::hub::guests::GuestAccess;
+use crate::hub::held_messages::{check_review_rights, held_clause, RoomFilterMode};
+use crate::hub::long_messages::{clear_message_body, store_message_body, PreparedContent};
+use crate::hub::memberships::PersistedMembership;
+use crate::hub::mentions::{parse_mentions, process_room_mentions, update_room_mentions, ParsedMention, ResolvedMentions};
+use crate::hub::missed_events::{MembershipChange, MessageChange, ReactionChange};
+use crate::hub::onboarding::DefaultRoom;
+use crate::hub::quotes::{attach_quote, QuotedExcerpt};
+use crate::hub::read_receipts::{SeenByMember, SeenByMode};
+use crate::hub::room_directory::{like_prefix_pattern, RoomCursor, RoomFilter, RoomInfo};
+use crate::hub::unread::{DmUnread, RoomUnread};
+use crate::hub::slow_mode::SlowModeOverride;
+use crate::hub::visibility::visibility_clause;
+use crate::message_batcher::{MessageBatcher, PendingMessage};
+use crate::permissions::MessageAction;
+use crate::room_id::RoomId;
+use crate::security::{AdvancedRateLimiter, SecurityAction};
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Adhésion de l'auteur et réglages du salon, lus avant un envoi
+#[derive(Debug, Clone)]
+pub struct PostingContext {
+    pub member_role: String,
+    pub post_policy: RoomPostPolicy,
+    pub slow_mode: SlowModeOverride,
+    pub is_archived: bool,
+    pub filter_mode: RoomFilterMode,
+    pub languages: Vec<String>,
+    pub filter_allowlist: Vec<String>,
+    pub max_attachments: Option<i32>,
+    pub max_attachments_size: Option<i64>,
+    pub encrypt_at_rest: bool,
+}
+
+/// Message de salon prêt à être stocké (contenu déjà scellé dans un salon chiffré)
+pub struct NewRoomMessage<'a> {
+    pub room_id: i64,
+    pub author_id: i64,
+    pub member_role: &'a str,
+    pub content: &'a PreparedContent,
+    pub parent_message_id: Option<i64>,
+    pub metadata: Value,
+    pub visible_to: Option<Vec<i64>>,
+    pub dedup_key: Option<DedupKey>,
+    /// Motif de retenue : message stocké hors diffusion jusqu'à son examen
+    pub hold_reason: Option<&'a str>,
+    pub data_key: Option<&'a DataKey>,
+    pub mentions: &'a [ParsedMention],
+    /// Message simple, éligible à l'insertion groupée (`database.insert_batching`)
+    pub batchable: bool,
+}
+
+/// Message de salon stocké
+#[derive(Debug, Clone)]
+pub struct InsertedRoomMessage {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// Extrait cité du parent, validé
+    pub quote: Option<QuotedExcerpt>,
+    /// Mentions résolues (aucune pour un message retenu)
+    pub mentions: ResolvedMentions,
+    /// Renvoi simultané : message déjà stocké sous ce nonce, rien n'a été écrit
+    pub duplicate: bool,
+}
+
+impl InsertedRoomMessage {
+    fn duplicate(id: i64, created_at: DateTime<Utc>) -> Self {
+        Self { id, created_at, quote: None, mentions: ResolvedMentions::default(), duplicate: true }
+    }
+}
+
+/// Message de salon après son édition
+#[derive(Debug, Clone)]
+pub struct EditedMessage {
+    pub visible_to: Option<Vec<i64>>,
+    pub is_held: bool,
+    /// Nouveaux destinataires et mentions de masse ajoutées (`None` : message retenu)
+    pub added_mentions: Option<ResolvedMentions>,
+}
+
+/// Message supprimé : son audience, pour l'événement de suppression
+#[derive(Debug, Clone)]
+pub struct DeletedMessage {
+    pub author_id: i64,
+    pub visible_to: Option<Vec<i64>>,
+    pub is_held: bool,
+}
+
+/// État d'un salon lu avant son archivage ou son désarchivage
+#[derive(Debug, Clone)]
+pub struct ArchiveState {
+    pub is_archived: bool,
+    /// Rôle de l'acteur dans le salon (`None` : non membre)
+    pub member_role: Option<String>,
+}
+
+/// Message retenu après son examen
+#[derive(Debug, Clone)]
+pub struct ReviewedMessage {
+    pub room_id: i64,
+    pub author_id: i64,
+    pub username: String,
+    pub content: String,
+    pub parent_message_id: Option<i64>,
+    pub visible_to: Option<Vec<i64>>,
+    pub metadata: Value,
+    pub created_at: DateTime<Utc>,
+    /// Mentions résolues à l'approbation (`None` : message rejeté)
+    pub mentions: Option<ResolvedMentions>,
+}
+
+/// Borne d'une source du rattrapage : (date, identifiant) exclus
+pub type ChangeBound = (DateTime<Utc>, i64);
+
+/// Réglages et services du hub utilisés pour écrire un message de salon
+/// (`ChatHub::write_context`)
+#[derive(Clone, Copy)]
+pub struct WriteContext<'a> {
+    pub limits: &'a LimitsConfig,
+    pub moderation: &'a ModerationConfig,
+    pub audit: &'a AuditSink,
+    /// Clés du chiffrement au repos, si configurées
+    pub cipher: Option<&'a MessageCipher>,
+    /// Regroupement des insertions (`database.insert_batching`)
+    pub batcher: Option<&'a MessageBatcher>,
+    /// Débit des mentions de masse (`SecurityAction::MassMention`)
+    pub action_limiter: &'a Mutex<AdvancedRateLimiter>,
+}
+
+impl WriteContext<'_> {
+    /// Débit des mentions de masse de l'auteur
+    pub async fn check_mass_mention_limit(&self, author_id: i64) -> Result<()> {
+        self.action_limiter.lock().await.check_limit(author_id as i32, &SecurityAction::MassMention)
+    }
+}
+
+// ================================================================
+// TRAIT
+// ================================================================
+
+/// Accès aux données des salons
+pub trait RoomRepository: Send + Sync {
+    /// Adhésion active de l'utilisateur et réglages du salon (`None` : non membre)
+    fn posting_context<'a>(&'a self, room_id: i64, user_id: i64) -> BoxFuture<'a, Result<Option<PostingContext>>>;
+
+    /// Nombre des utilisateurs donnés qui sont membres actifs du salon
+    fn count_members<'a>(&'a self, room_id: i64, user_ids: &'a [i64]) -> BoxFuture<'a, Result<i64>>;
+
+    /// Membres actifs du salon
+    fn member_ids<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Vec<i64>>>;
+
+    /// Salons publics non archivés dont l'utilisateur est membre actif, par date d'adhésion
+    fn persisted_memberships<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<Vec<PersistedMembership>>>;
+
+    /// Accès des invités ; fermé si le salon n'est pas public ou est archivé
+    fn guest_access<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<GuestAccess>>;
+
+    /// Réglages d'un salon public non archivé, lus avant la publication d'un
+    /// invité (rôle `guest`)
+    fn guest_posting_context<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Option<PostingContext>>>;
+
+    /// Non-lus de l'utilisateur par salon et par DM (conversations sans non-lu omises)
+    ///
+    /// Un message restreint hors de sa portée, retenu ou supprimé n'est pas compté.
+    fn unread_counts<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<(Vec<RoomUnread>, Vec<DmUnread>)>>;
+
+    /// Visibilité « vu par » du salon et auteur du message, si `requester_id` en est membre
+    ///
+    /// `None` : message inconnu ou supprimé, ou demandeur hors du salon.
+    fn seen_by_context<'a>(&'a self, room_id: i64, message_id: i64, requester_id: i64) -> BoxFuture<'a, Result<Option<(SeenByMode, i64)>>>;
+
+    /// Membres actifs, hors auteur, dont le marqueur atteint `message_id`
+    fn count_seen_by<'a>(&'a self, room_id: i64, message_id: i64, author_id: i64) -> BoxFuture<'a, Result<i64>>;
+
+    /// Les mêmes membres, par identifiant croissant après `after_user_id`, au plus `limit`
+    fn list_seen_by<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        author_id: i64,
+        after_user_id: i64,
+        limit: i64
+    ) -> BoxFuture<'a, Result<Vec<SeenByMember>>>;
+
+    /// Salons de l'annuaire visibles par `requester_id`, dans l'ordre du filtre
+    ///
+    /// Un salon privé n'y figure que pour ses membres et les administrateurs
+    /// globaux (`RoomFilter::admits`) ; `cursor` reprend après un salon déjà reçu.
+    fn list_directory<'a>(
+        &'a self,
+        requester_id: i64,
+        filter: &'a RoomFilter,
+        limit: i64,
+        cursor: Option<RoomCursor>
+    ) -> BoxFuture<'a, Result<Vec<RoomInfo>>>;
+
+    /// Message déjà stocké pour cette clé dans la fenêtre, s'il existe
+    ///
+    /// Libère le nonce d'un message hors fenêtre ou supprimé ; un autre
+    /// contenu sous le nonce est refusé (`dedup::DedupKey::check_retry`).
+    fn find_duplicate<'a>(&'a self, key: &'a DedupKey, window: Duration) -> BoxFuture<'a, Result<Option<SentMessage>>>;
+
+    /// Stocke un message ; ses mentions sont résolues s'il n'est pas retenu
+    ///
+    /// Nonce déjà stocké (renvoi simultané) : retourne le message existant,
+    /// marqué `duplicate`.
+    fn insert_message<'a>(&'a self, message: NewRoomMessage<'a>, ctx: WriteContext<'a>) -> BoxFuture<'a, Result<InsertedRoomMessage>>;
+
+    /// Stocke le message d'un invité (`guests.persist_messages`)
+    fn insert_guest_message<'a>(&'a self, room_id: i64, guest_id: i32, guest_name: &'a str, content: &'a str) -> BoxFuture<'a, Result<SentMessage>>;
+
+    /// Salons publics non archivés portant l'un des noms donnés, dans un ordre quelconque
+    fn default_rooms<'a>(&'a self, names: &'a [RoomId]) -> BoxFuture<'a, Result<Vec<DefaultRoom>>>;
+
+    /// Membres du salon, parmi `user_ids`, qui l'ont mis en sourdine
+    fn muted_members<'a>(&'a self, room_id: i64, user_ids: &'a [i64]) -> BoxFuture<'a, Result<HashSet<i64>>>;
+
+    /// Salons où l'utilisateur a (ou a eu) une adhésion, et nombre d'adhésions
+    /// actives hors salons archivés
+    fn known_rooms<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<(HashSet<i64>, i64)>>;
+
+    /// Inscrit l'utilisateur au salon (rôle `member`)
+    ///
+    /// Salon inconnu, archivé ou plein, ou utilisateur déjà membre : refusé.
+    fn add_room_member<'a>(&'a self, room_id: i64, user_id: i64, audit: &'a AuditSink) -> BoxFuture<'a, Result<()>>;
+
+    /// Remplace le contenu d'un message du salon, après
+    /// `channels::check_room_modification`, et met ses mentions à jour
+    /// (`mentions::update_room_mentions`) s'il n'est pas retenu
+    fn edit_message<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        user_id: i64,
+        new_content: &'a str,
+        mentions: &'a [ParsedMention],
+        ctx: WriteContext<'a>
+    ) -> BoxFuture<'a, Result<EditedMessage>>;
+
+    /// Supprime un message du salon (épingle retirée), après
+    /// `channels::check_room_modification` ; retourne son auteur et sa visibilité
+    fn delete_message<'a>(&'a self, room_id: i64, message_id: i64, user_id: i64, ctx: WriteContext<'a>) -> BoxFuture<'a, Result<DeletedMessage>>;
+
+    /// Épingle ou désépingle un message du salon, après `channels::check_pin_rights`
+    ///
+    /// L'épingle perd sa position d'affichage ; `pinned_until` la rend temporaire.
+    fn set_pinned<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        user_id: i64,
+        pin: bool,
+        pinned_until: Option<DateTime<Utc>>,
+        audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Réordonne les épingles du salon (`channels::plan_pin_order`), après
+    /// `channels::check_pin_rights` ; retourne l'ordre complet
+    fn reorder_pins<'a>(&'a self, room_id: i64, actor_id: i64, ordered_message_ids: &'a [i64], audit: &'a AuditSink) -> BoxFuture<'a, Result<Vec<i64>>>;
+
+    /// Archive ou désarchive le salon, après `channels::check_archive_change`
+    fn set_archived<'a>(&'a self, room_id: i64, user_id: i64, archived: bool, audit: &'a AuditSink) -> BoxFuture<'a, Result<()>>;
+
+    /// Publie ou retire un message retenu, après `held_messages::check_review_rights`
+    fn review_held_message<'a>(
+        &'a self,
+        message_id: i64,
+        moderator_id: i64,
+        approve: bool,
+        note: Option<&'a str>,
+        ctx: WriteContext<'a>
+    ) -> BoxFuture<'a, Result<ReviewedMessage>>;
+
+    /// Messages créés, édités ou supprimés après la borne, au plus `limit` lignes
+    fn missed_messages<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64, cipher: Option<&'a MessageCipher>) -> BoxFuture<'a, Result<Vec<MessageChange>>>;
+
+    /// Réactions posées après la borne, au plus `limit` lignes
+    fn missed_reactions<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64) -> BoxFuture<'a, Result<Vec<ReactionChange>>>;
+
+    /// Arrivées et départs de membres après la borne, au plus `limit` lignes
+    fn missed_memberships<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64) -> BoxFuture<'a, Result<Vec<MembershipChange>>>;
+}
+
+// ================================================================
+// POSTGRESQL
+// ================================================================
+
+/// Conversations actives de l'utilisateur (salons et messages privés), paramètre `$1`
+const USER_CONVERSATIONS: &str = "
+    SELECT conversation_id FROM conversation_members WHERE user_id = $1 AND left_at IS NULL
+";
+
+/// Dépôt PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgRoomRepository {
+    db: PgPool,
+}
+
+impl PgRoomRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+impl RoomRepository for PgRoomRepository {
+    fn posting_context<'a>(&'a self, room_id: i64, user_id: i64) -> BoxFuture<'a, Result<Option<PostingContext>>> {
+        Box::pin(async move {
+            let row = query("
+                SELECT cm.role, c.post_policy, c.slow_mode_seconds, c.is_archived, c.filter_mode, c.languages,
+                       c.filter_allowlist, c.max_attachments, c.max_attachments_size, c.encrypt_at_rest
+                FROM conversation_members cm
+                JOIN conversations c ON c.id = cm.conversation_id
+                WHERE cm.conversation_id = $1 AND cm.user_id = $2 AND cm.left_at IS NULL
+            ")
+            .bind(room_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?;
+
+            Ok(row.map(|row| PostingContext {
+                member_role: row.get("role"),
+                post_policy: RoomPostPolicy::from_db(row.get("post_policy")),
+                slow_mode: SlowModeOverride::from_db(row.get("slow_mode_seconds")),
+                is_archived: row.get("is_archived"),
+                filter_mode: RoomFilterMode::from_db(row.get("filter_mode")),
+                languages: row.get("languages"),
+                filter_allowlist: row.get("filter_allowlist"),
+                max_attachments: row.get("max_attachments"),
+                max_attachments_size: row.get("max_attachments_size"),
+                encrypt_at_rest: row.get("encrypt_at_rest"),
+            }))
+        })
+    }
+
+    fn count_members<'a>(&'a self, room_id: i64, user_ids: &'a [i64]) -> BoxFuture<'a, Result<i64>> {
+        Box::pin(async move {
+            let count = query("
+                SELECT COUNT(*) FROM conversation_members
+                WHERE conversation_id = $1 AND user_id = ANY($2) AND left_at IS NULL
+            ")
+            .bind(room_id)
+            .bind(user_ids)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_visible_to", e))?
+            .get(0);
+            Ok(count)
+        })
+    }
+
+    fn member_ids<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Vec<i64>>> {
+        Box::pin(async move {
+            let member_ids = query("
+                SELECT user_id
+                FROM conversation_members
+                WHERE conversation_id = $1 AND left_at IS NULL
+            ")
+            .bind(room_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("get_room_members", e))?
+            .into_iter()
+            .map(|row| row.get::<i64, _>("user_id"))
+            .collect();
+            Ok(member_ids)
+        })
+    }
+
+    fn persisted_memberships<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<Vec<PersistedMembership>>> {
+        Box::pin(async move {
+            let memberships = query("
+                SELECT c.id, c.name
+                FROM conversation_members cm
+                JOIN conversations c ON c.id = cm.conversation_id
+                WHERE cm.user_id = $1 AND cm.left_at IS NULL
+                  AND c.type = 'public_room' AND NOT c.is_archived
+                ORDER BY cm.joined_at ASC
+            ")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("load_persisted_memberships", e))?
+            .into_iter()
+            .filter_map(|row| {
+                let room_id: i64 = row.get("id");
+                match row.try_get("name") {
+                    Ok(room_name) => Some(PersistedMembership { room_id, room_name }),
+                    Err(e) => {
+                        tracing::warn!(room_id = %room_id, error = %e, "⚠️ Nom de salon persisté invalide, adhésion ignorée");
+                        None
+                    }
+                }
+            })
+            .collect();
+            Ok(memberships)
+        })
+    }
+
+    fn guest_access<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<GuestAccess>> {
+        Box::pin(async move {
+            let access = query("
+                SELECT guest_access FROM conversations
+                WHERE id = $1 AND type = 'public_room' AND is_public AND NOT is_archived
+            ")
+            .bind(room_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("room_guest_access", e))?
+            .map_or(GuestAccess::Closed, |row| GuestAccess::from_db(row.get("guest_access")));
+            Ok(access)
+        })
+    }
+
+    fn guest_posting_context<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Option<PostingContext>>> {
+        Box::pin(async move {
+            let row = query("
+                SELECT post_policy, slow_mode_seconds, is_archived, filter_mode, languages,
+                       filter_allowlist, max_attachments, max_attachments_size, encrypt_at_rest
+                FROM conversations
+                WHERE id = $1 AND type = 'public_room' AND is_public AND NOT is_archived
+            ")
+            .bind(room_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("guest_posting_context", e))?;
+
+            Ok(row.map(|row| PostingContext {
+                member_role: GUEST_ROLE.to_string(),
+                post_policy: RoomPostPolicy::from_db(row.get("post_policy")),
+                slow_mode: SlowModeOverride::from_db(row.get("slow_mode_seconds")),
+                is_archived: row.get("is_archived"),
+                filter_mode: RoomFilterMode::from_db(row.get("filter_mode")),
+                languages: row.get("languages"),
+                filter_allowlist: row.get("filter_allowlist"),
+                max_attachments: row.get("max_attachments"),
+                max_attachments_size: row.get("max_attachments_size"),
+                encrypt_at_rest: row.get("encrypt_at_rest"),
+            }))
+        })
+    }
+
+    fn unread_counts<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<(Vec<RoomUnread>, Vec<DmUnread>)>> {
+        Box::pin(async move {
+            let rooms = query_as::<_, RoomUnread>("
+                SELECT
+                    cm.conversation_id as room_id,
+                    COUNT(m.id) as unread_count,
+                    COALESCE(cm.is_muted, FALSE) as is_muted
+                FROM conversation_members cm
+                JOIN messages m ON m.conversation_id = cm.conversation_id
+                    AND m.id > COALESCE(cm.last_read_message_id, 0)
+                    AND m.author_id != cm.user_id
+                    AND m.status != 'deleted'
+                    AND NOT m.is_held
+                    AND (m.visible_to IS NULL OR cm.user_id = ANY(m.visible_to))
+                WHERE cm.user_id = $1 AND cm.left_at IS NULL
+                GROUP BY cm.conversation_id, cm.is_muted
+            ")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("get_room_unread_counts", e))?;
+
+            let direct_messages = query_as::<_, DmUnread>("
+                SELECT
+                    dc.id as conversation_id,
+                    CASE WHEN dc.user1_id = $1 THEN dc.user2_id ELSE dc.user1_id END as other_user_id,
+                    COUNT(m.id) as unread_count,
+                    (dm.user_id IS NOT NULL) as is_muted
+                FROM dm_conversations dc
+                JOIN messages m ON m.conversation_id = dc.id
+                    AND m.author_id != $1
+                    AND m.status NOT IN ('read', 'deleted')
+                LEFT JOIN dm_mutes dm ON dm.conversation_id = dc.id AND dm.user_id = $1
+                WHERE dc.user1_id = $1 OR dc.user2_id = $1
+                GROUP BY dc.id, dc.user1_id, dc.user2_id, dm.user_id
+            ")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("get_dm_unread_counts", e))?;
+
+            Ok((rooms, direct_messages))
+        })
+    }
+
+    fn seen_by_context<'a>(&'a self, room_id: i64, message_id: i64, requester_id: i64) -> BoxFuture<'a, Result<Option<(SeenByMode, i64)>>> {
+        Box::pin(async move {
+            let context = query("
+                SELECT c.seen_by_mode, m.author_id
+                FROM conversations c
+                JOIN conversation_members cm ON cm.conversation_id = c.id AND cm.user_id = $3 AND cm.left_at IS NULL
+                JOIN messages m ON m.conversation_id = c.id AND m.id = $2 AND m.status != 'deleted'
+                WHERE c.id = $1
+            ")
+            .bind(room_id)
+            .bind(message_id)
+            .bind(requester_id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("seen_by_context", e))?;
+
+            Ok(context.map(|row| (SeenByMode::from_db(row.get("seen_by_mode")), row.get("author_id"))))
+        })
+    }
+
+    fn count_seen_by<'a>(&'a self, room_id: i64, message_id: i64, author_id: i64) -> BoxFuture<'a, Result<i64>> {
+        Box::pin(async move {
+            let seen_count: i64 = query("
+                SELECT COUNT(*) FROM conversation_members
+                WHERE conversation_id = $1 AND left_at IS NULL
+                  AND user_id != $3 AND last_read_message_id >= $2
+            ")
+            .bind(room_id)
+            .bind(message_id)
+            .bind(author_id)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("count_seen_by", e))?
+            .get(0);
+            Ok(seen_count)
+        })
+    }
+
+    fn list_seen_by<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        author_id: i64,
+        after_user_id: i64,
+        limit: i64
+    ) -> BoxFuture<'a, Result<Vec<SeenByMember>>> {
+        Box::pin(async move {
+            query_as::<_, SeenByMember>("
+                SELECT cm.user_id, u.username, cm.last_read_message_id
+                FROM conversation_members cm
+                JOIN users u ON u.id = cm.user_id
+                WHERE cm.conversation_id = $1 AND cm.left_at IS NULL
+                  AND cm.user_id != $3 AND cm.last_read_message_id >= $2
+                  AND cm.user_id > $4
+                ORDER BY cm.user_id
+                LIMIT $5
+            ")
+            .bind(room_id)
+            .bind(message_id)
+            .bind(author_id)
+            .bind(after_user_id)
+            .bind(limit)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("list_seen_by", e))
+        })
+    }
+
+    fn list_directory<'a>(
+        &'a self,
+        requester_id: i64,
+        filter: &'a RoomFilter,
+        limit: i64,
+        cursor: Option<RoomCursor>
+    ) -> BoxFuture<'a, Result<Vec<RoomInfo>>> {
+        Box::pin(async move {
+            let requester_is_admin = is_global_admin(&self.db, requester_id).await?;
+
+            let sql = format!("
+                SELECT id, name, topic, member_count, is_public, is_member, last_activity_at
+                FROM (
+                    SELECT c.id, c.name, c.description as topic, c.is_public,
+                           (SELECT COUNT(*) FROM conversation_members m
+                            WHERE m.conversation_id = c.id AND m.left_at IS NULL) as member_count,
+                           EXISTS(
+                               SELECT 1 FROM conversation_members m
+                               WHERE m.conversation_id = c.id AND m.user_id = $1 AND m.left_at IS NULL
+                           ) as is_member,
+                           COALESCE(
+                               (SELECT MAX(msg.created_at) FROM messages msg
+                                WHERE msg.conversation_id = c.id AND msg.status != 'deleted'),
+                               c.created_at
+                           ) as last_activity_at
+                    FROM conversations c
+                    WHERE {listed}
+                ) rooms
+                WHERE (is_public OR is_member OR $2)
+                  AND (NOT $3 OR is_public)
+                  AND (NOT $4 OR is_member)
+                  AND ($5::TEXT IS NULL OR name ILIKE $5)
+                  AND ($6::BIGINT IS NULL OR ({sort}, id) < ($6, $7))
+                ORDER BY {sort} DESC, id DESC
+                LIMIT $8
+            ", listed = listed_room_clause(false), sort = filter.order.sort_expression());
+
+            let rows = query(&sql)
+                .bind(requester_id)
+                .bind(requester_is_admin)
+                .bind(filter.public_only)
+                .bind(filter.joined_only)
+                .bind(filter.name_prefix.as_deref().map(like_prefix_pattern))
+                .bind(cursor.map(|c| c.sort_key))
+                .bind(cursor.map(|c| c.room_id))
+                .bind(limit)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("list_rooms", e))?;
+
+            let mut rooms: Vec<RoomInfo> = rows.into_iter()
+                .map(|row| RoomInfo {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    topic: row.get("topic"),
+                    member_count: row.get("member_count"),
+                    is_public: row.get("is_public"),
+                    is_member: row.get("is_member"),
+                    last_activity_at: row.get("last_activity_at"),
+                })
+                .collect();
+
+            // Garde-fou : un salon privé ne sort jamais de l'annuaire sans droit de le voir
+            rooms.retain(|room| filter.admits(room, requester_is_admin));
+            Ok(rooms)
+        })
+    }
+
+    fn find_duplicate<'a>(&'a self, key: &'a DedupKey, window: Duration) -> BoxFuture<'a, Result<Option<SentMessage>>> {
+        Box::pin(dedup::find_duplicate(&self.db, key, window))
+    }
+
+    fn insert_message<'a>(&'a self, message: NewRoomMessage<'a>, ctx: WriteContext<'a>) -> BoxFuture<'a, Result<InsertedRoomMessage>> {
+        Box::pin(async move {
+            let NewRoomMessage {
+                room_id, author_id, member_role, content, parent_message_id, mut metadata,
+                visible_to, dedup_key, hold_reason, data_key, mentions, batchable,
+            } = message;
+            let message_uuid = Uuid::new_v4();
+
+            if let Some(batcher) = ctx.batcher.filter(|_| batchable) {
+                annotate_custom_emojis(&self.db, Some(room_id), &content.stored, &mut metadata).await?;
+                let inserted = batcher.submit(PendingMessage {
+                    uuid: message_uuid,
+                    conversation_id: room_id,
+                    author_id,
+                    content: content.stored.clone(),
+                    metadata,
+                    content_hash: dedup_key.as_ref().map(|key| key.content_hash.clone()),
+                    client_nonce: dedup_key.as_ref().map(|key| key.nonce.clone()),
+                }).await?;
+                if dedup::is_concurrent_retry(dedup_key.as_ref(), message_uuid, inserted.uuid, inserted.content_hash.as_deref())? {
+                    return Ok(InsertedRoomMessage::duplicate(inserted.id, inserted.created_at));
+                }
+                return Ok(InsertedRoomMessage {
+                    id: inserted.id,
+                    created_at: inserted.created_at,
+                    quote: None,
+                    mentions: ResolvedMentions::default(),
+                    duplicate: false,
+                });
+            }
+
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Valider l'extrait cité du parent (conservé tel quel dans les métadonnées)
+            let quote = attach_quote(ctx.cipher, &mut tx, room_id, author_id, parent_message_id, &mut metadata).await?;
+            annotate_custom_emojis(&mut *tx, Some(room_id), &content.stored, &mut metadata).await?;
+
+            let row = query("
+                INSERT INTO messages (uuid, author_id, conversation_id, content, parent_message_id, metadata, status, visible_to, client_nonce,
+                                      is_held, held_reason, is_flagged, flagged_at, encryption_key_id, wrapped_key, content_hash)
+                VALUES ($1, $2, $3, $4, $5, $6, 'sent', $7, $8, $9 IS NOT NULL, $9, $9 IS NOT NULL, CASE WHEN $9 IS NOT NULL THEN NOW() END, $10, $11, $12)
+                ON CONFLICT (author_id, conversation_id, client_nonce) WHERE client_nonce IS NOT NULL
+                    DO UPDATE SET client_nonce = EXCLUDED.client_nonce
+                RETURNING id, uuid, created_at, content_hash
+            ")
+            .bind(message_uuid)
+            .bind(author_id)
+            .bind(room_id)
+            .bind(&content.stored)
+            .bind(parent_message_id)
+            .bind(&metadata)
+            .bind(visible_to)
+            .bind(dedup_key.as_ref().map(|key| &key.nonce))
+            .bind(hold_reason)
+            .bind(data_key.map(|key| &key.key_id))
+            .bind(data_key.map(|key| &key.wrapped))
+            .bind(dedup_key.as_ref().map(|key| &key.content_hash))
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("insert_message", e))?;
+
+            let message_id: i64 = row.get("id");
+            let stored_hash: Option<Vec<u8>> = row.get("content_hash");
+            if dedup::is_concurrent_retry(dedup_key.as_ref(), message_uuid, row.get("uuid"), stored_hash.as_deref())? {
+                // Rien d'écrit par cet envoi : la transaction est abandonnée
+                return Ok(InsertedRoomMessage::duplicate(message_id, row.get("created_at")));
+            }
+
+            // Corps complet des messages longs
+            store_message_body(&mut tx, message_id, content).await?;
+
+            // Si c'est une réponse, incrémenter le compteur de thread
+            if let Some(parent_id) = parent_message_id {
+                query("
+                    UPDATE messages
+                    SET thread_count = thread_count + 1
+                    WHERE id = $1
+                ")
+                .bind(parent_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("update_thread_count", e))?;
+            }
+
+            // Message retenu : mentions traitées à l'approbation
+            let resolved = if hold_reason.is_some() {
+                ResolvedMentions::default()
+            } else {
+                process_room_mentions(ctx, &mut tx, room_id, message_id, author_id, member_role, mentions).await?
+            };
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(InsertedRoomMessage { id: message_id, created_at: row.get("created_at"), quote, mentions: resolved, duplicate: false })
+        })
+    }
+
+    fn insert_guest_message<'a>(&'a self, room_id: i64, guest_id: i32, guest_name: &'a str, content: &'a str) -> BoxFuture<'a, Result<SentMessage>> {
+        Box::pin(async move {
+            let row = query("
+                INSERT INTO guest_messages (conversation_id, guest_id, guest_name, content)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, created_at
+            ")
+            .bind(room_id)
+            .bind(guest_id)
+            .bind(guest_name)
+            .bind(content)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("insert_guest_message", e))?;
+            Ok(SentMessage { id: row.get("id"), created_at: row.get("created_at") })
+        })
+    }
+
+    fn default_rooms<'a>(&'a self, names: &'a [RoomId]) -> BoxFuture<'a, Result<Vec<DefaultRoom>>> {
+        Box::pin(async move {
+            let rows = query("
+                SELECT id, name FROM conversations
+                WHERE lower(name) = ANY($1) AND type = 'public_room' AND NOT is_archived
+            ")
+            .bind(names.iter().map(RoomId::as_str).collect::<Vec<_>>())
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("resolve_default_rooms", e))?;
+
+            Ok(rows.into_iter()
+                .filter_map(|row| Some(DefaultRoom { id: row.get("id"), name: row.try_get("name").ok()? }))
+                .collect())
+        })
+    }
+
+    fn muted_members<'a>(&'a self, room_id: i64, user_ids: &'a [i64]) -> BoxFuture<'a, Result<HashSet<i64>>> {
+        Box::pin(async move {
+            let muted = query("
+                SELECT user_id FROM conversation_members
+                WHERE conversation_id = $1 AND user_id = ANY($2) AND is_muted AND left_at IS NULL
+            ")
+            .bind(room_id)
+            .bind(user_ids)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("muted_room_members", e))?
+            .into_iter()
+            .map(|row| row.get::<i64, _>("user_id"))
+            .collect();
+            Ok(muted)
+        })
+    }
+
+    fn known_rooms<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<(HashSet<i64>, i64)>> {
+        Box::pin(async move {
+            let memberships = query("
+                SELECT cm.conversation_id, cm.left_at IS NULL AND NOT c.is_archived as is_active
+                FROM conversation_members cm
+                JOIN conversations c ON c.id = cm.conversation_id
+                WHERE cm.user_id = $1
+            ")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("list_user_memberships", e))?;
+
+            let known_rooms = memberships.iter()
+                .map(|row| row.get::<i64, _>("conversation_id"))
+                .collect();
+            let active_room_count = memberships.iter()
+                .filter(|row| row.get::<bool, _>("is_active"))
+                .count() as i64;
+            Ok((known_rooms, active_room_count))
+        })
+    }
+
+    fn add_room_member<'a>(&'a self, room_id: i64, user_id: i64, audit: &'a AuditSink) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            // Vérifier que le salon existe et n'est pas archivé
+            let room: Room = query_as("
+                SELECT id, uuid, name, description, owner_id, is_public, is_archived, max_members, created_at, updated_at
+                FROM conversations
+                WHERE id = $1 AND type = 'public_room'
+            ")
+            .bind(room_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|_| ChatError::not_found("salon", &room_id.to_string()))?;
+
+            if room.is_archived {
+                return Err(ChatError::ConversationArchived { id: room_id.to_string() });
+            }
+
+            // Vérifier si l'utilisateur est déjà membre
+            let is_member: bool = query("
+                SELECT EXISTS(
+                    SELECT 1 FROM conversation_members
+                    WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+                )
+            ")
+            .bind(room_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?
+            .get(0);
+
+            if is_member {
+                return Err(ChatError::configuration_error("Utilisateur déjà membre du salon"));
+            }
+
+            // Vérifier la limite de membres
+            if let Some(max_members) = room.max_members {
+                let current_count: i64 = query("
+                    SELECT COUNT(*) FROM conversation_members
+                    WHERE conversation_id = $1 AND left_at IS NULL
+                ")
+                .bind(room_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("count_members", e))?
+                .get(0);
+
+                if current_count >= max_members as i64 {
+                    return Err(ChatError::configuration_error("Salon plein"));
+                }
+            }
+
+            query("
+                INSERT INTO conversation_members (conversation_id, user_id, role)
+                VALUES ($1, $2, 'member')
+                ON CONFLICT (conversation_id, user_id)
+                DO UPDATE SET left_at = NULL, joined_at = NOW()
+            ")
+            .bind(room_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("add_member", e))?;
+
+            audit.record(&mut *tx, "room_joined", Some(user_id), json!({
+                "room_id": room_id,
+                "room_name": room.name
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))
+        })
+    }
+
+    fn edit_message<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        user_id: i64,
+        new_content: &'a str,
+        mentions: &'a [ParsedMention],
+        ctx: WriteContext<'a>
+    ) -> BoxFuture<'a, Result<EditedMessage>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let target = load_modification_context(ctx.cipher, ctx.limits, &mut tx, room_id, message_id, user_id).await?;
+            target.check(ctx.moderation, MessageAction::Edit, Utc::now())?;
+            let old_content = &target.content;
+
+            // Salon chiffré : nouveau contenu scellé par la clé du message (ou une nouvelle)
+            let data_key = match target.data_key {
+                Some(data_key) => Some(data_key),
+                None => room_data_key(ctx.cipher, target.encrypted)?,
+            };
+            let stored_content = match &data_key {
+                Some(data_key) => data_key.seal(new_content)?,
+                None => new_content.to_string(),
+            };
+
+            query("
+                UPDATE messages 
+                SET content = $1, is_edited = true, edit_count = edit_count + 1, edited_at = NOW(), updated_at = NOW(),
+                    encryption_key_id = COALESCE($3, encryption_key_id), wrapped_key = COALESCE($4, wrapped_key)
+                WHERE id = $2
+            ")
+            .bind(&stored_content)
+            .bind(message_id)
+            .bind(data_key.as_ref().map(|key| &key.key_id))
+            .bind(data_key.as_ref().map(|key| &key.wrapped))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("update_message", e))?;
+            clear_message_body(&mut tx, message_id).await?;
+
+            // Les mentions d'un message retenu ne sont traitées qu'à l'approbation
+            let added_mentions = if target.is_held {
+                None
+            } else {
+                Some(update_room_mentions(ctx, &mut tx, room_id, message_id, user_id, target.member_role.as_deref().unwrap_or("member"), old_content, mentions).await?)
+            };
+
+            // Pas de contenu en clair au journal pour un message chiffré
+            let audit_details = if data_key.is_some() {
+                json!({ "room_id": room_id, "message_id": message_id, "encrypted": true })
+            } else {
+                json!({
+                    "room_id": room_id,
+                    "message_id": message_id,
+                    "old_content": old_content,
+                    "new_content": new_content
+                })
+            };
+            ctx.audit.record(&mut *tx, "room_message_edited", Some(user_id), audit_details).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(EditedMessage { visible_to: target.visible_to, is_held: target.is_held, added_mentions })
+        })
+    }
+
+    fn delete_message<'a>(&'a self, room_id: i64, message_id: i64, user_id: i64, ctx: WriteContext<'a>) -> BoxFuture<'a, Result<DeletedMessage>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let target = load_modification_context(ctx.cipher, ctx.limits, &mut tx, room_id, message_id, user_id).await?;
+            target.check(ctx.moderation, MessageAction::Delete, Utc::now())?;
+
+            query("
+                UPDATE messages 
+                SET status = 'deleted', is_pinned = FALSE, pin_order = NULL, pinned_until = NULL, updated_at = NOW()
+                WHERE id = $1
+            ")
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("delete_message", e))?;
+
+            ctx.audit.record(&mut *tx, "room_message_deleted", Some(user_id), json!({
+                "room_id": room_id,
+                "message_id": message_id,
+                "by_moderator": !target.ctx.is_author
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(DeletedMessage { author_id: target.author_id, visible_to: target.visible_to, is_held: target.is_held })
+        })
+    }
+
+    fn set_pinned<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        user_id: i64,
+        pin: bool,
+        pinned_until: Option<DateTime<Utc>>,
+        audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let member_role: Option<String> = query("
+                SELECT role FROM conversation_members
+                WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+            ")
+            .bind(room_id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+            .map(|row| row.get("role"));
+            check_pin_rights(member_role.as_deref(), "pin_message")?;
+
+            let rows_affected = query("
+                UPDATE messages
+                SET is_pinned = $1, pin_order = NULL, pinned_until = $4, updated_at = NOW()
+                WHERE id = $2 AND conversation_id = $3 AND ($1 = FALSE OR status != 'deleted')
+            ")
+            .bind(pin)
+            .bind(message_id)
+            .bind(room_id)
+            .bind(pinned_until)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("update_pin_status", e))?
+            .rows_affected();
+
+            if rows_affected == 0 {
+                return Err(ChatError::not_found("message", &message_id.to_string()));
+            }
+
+            audit.record(&mut *tx, if pin { "message_pinned" } else { "message_unpinned" }, Some(user_id), json!({
+                "room_id": room_id,
+                "message_id": message_id,
+                "pinned_until": pinned_until
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))
+        })
+    }
+
+    fn reorder_pins<'a>(&'a self, room_id: i64, actor_id: i64, ordered_message_ids: &'a [i64], audit: &'a AuditSink) -> BoxFuture<'a, Result<Vec<i64>>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let member_role: Option<String> = query("
+                SELECT role FROM conversation_members
+                WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+            ")
+            .bind(room_id)
+            .bind(actor_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+            .map(|row| row.get("role"));
+            check_pin_rights(member_role.as_deref(), "reorder_pins")?;
+
+            // Verrou sur les épingles : un épinglage concurrent attend la fin du réordonnancement
+            let current: Vec<i64> = query("
+                SELECT id FROM messages
+                WHERE conversation_id = $1 AND is_pinned = TRUE
+                ORDER BY pin_order ASC NULLS LAST, created_at DESC
+                FOR UPDATE
+            ")
+            .bind(room_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("list_pinned_messages", e))?
+            .into_iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+            let planned = plan_pin_order(&current, ordered_message_ids)?;
+            let positions: Vec<i32> = (0..planned.len() as i32).collect();
+
+            query("
+                UPDATE messages m
+                SET pin_order = data.position
+                FROM UNNEST($1::bigint[], $2::int[]) AS data(id, position)
+                WHERE m.id = data.id
+            ")
+            .bind(&planned)
+            .bind(&positions)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("update_pin_order", e))?;
+
+            audit.record(&mut *tx, "pins_reordered", Some(actor_id), json!({
+                "room_id": room_id,
+                "message_ids": planned
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+            Ok(planned)
+        })
+    }
+
+    fn set_archived<'a>(&'a self, room_id: i64, user_id: i64, archived: bool, audit: &'a AuditSink) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let room = query("
+                SELECT c.name, c.is_archived, cm.role
+                FROM conversations c
+                LEFT JOIN conversation_members cm
+                  ON cm.conversation_id = c.id AND cm.user_id = $2 AND cm.left_at IS NULL
+                WHERE c.id = $1 AND c.type = 'public_room'
+                FOR UPDATE OF c
+            ")
+            .bind(room_id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("load_room_archive_state", e))?
+            .ok_or_else(|| ChatError::not_found("salon", &room_id.to_string()))?;
+
+            check_archive_change(&ArchiveState {
+                is_archived: room.get("is_archived"),
+                member_role: room.get("role"),
+            }, archived)?;
+
+            query("
+                UPDATE conversations
+                SET is_archived = $1, updated_at = NOW()
+                WHERE id = $2
+            ")
+            .bind(archived)
+            .bind(room_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("update_room_archived", e))?;
+
+            let room_name: String = room.get("name");
+            audit.record(&mut *tx, if archived { "room_archived" } else { "room_unarchived" }, Some(user_id), json!({
+                "room_id": room_id,
+                "room_name": room_name
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))
+        })
+    }
+
+    fn review_held_message<'a>(
+        &'a self,
+        message_id: i64,
+        moderator_id: i64,
+        approve: bool,
+        note: Option<&'a str>,
+        ctx: WriteContext<'a>
+    ) -> BoxFuture<'a, Result<ReviewedMessage>> {
+        Box::pin(async move {
+            let mut tx = self.db.begin().await
+                .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+            let message = query("
+                SELECT m.conversation_id, m.author_id, u.username, m.content, m.encryption_key_id, m.wrapped_key,
+                       m.parent_message_id, m.visible_to, m.metadata, m.created_at, m.held_reason,
+                       COALESCE(author.role, 'member') as author_role,
+                       (
+                         EXISTS (SELECT 1 FROM users s WHERE s.id = $2 AND s.role::text IN ('moderator', 'admin', 'owner'))
+                         OR EXISTS (
+                             SELECT 1 FROM conversation_members cm
+                             WHERE cm.conversation_id = m.conversation_id AND cm.user_id = $2 AND cm.left_at IS NULL
+                               AND cm.role IN ('owner', 'admin', 'moderator')
+                         )
+                       ) as can_review
+                FROM messages m
+                JOIN users u ON u.id = m.author_id
+                LEFT JOIN conversation_members author
+                    ON author.conversation_id = m.conversation_id AND author.user_id = m.author_id AND author.left_at IS NULL
+                WHERE m.id = $1 AND m.is_held AND m.status != 'deleted'
+                FOR UPDATE OF m
+            ")
+            .bind(message_id)
+            .bind(moderator_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("load_held_message", e))?
+            .ok_or_else(|| ChatError::not_found("held_message", &message_id.to_string()))?;
+
+            check_review_rights(message.get("can_review"))?;
+
+            let room_id: i64 = message.get("conversation_id");
+            let author_id: i64 = message.get("author_id");
+            let content = open_row_content(ctx.cipher, &message, "content")?;
+
+            let update = if approve {
+                "UPDATE messages SET is_held = FALSE, held_reason = NULL, is_flagged = FALSE, flagged_at = NULL WHERE id = $1"
+            } else {
+                "UPDATE messages SET is_held = FALSE, is_flagged = FALSE, status = 'deleted' WHERE id = $1"
+            };
+            query(update)
+                .bind(message_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("review_held_message", e))?;
+
+            // Les mentions d'un message retenu ne sont traitées qu'à l'approbation
+            let mentions = if approve {
+                let author_role: String = message.get("author_role");
+                let parsed = parse_mentions(&content);
+                Some(process_room_mentions(ctx, &mut tx, room_id, message_id, author_id, &author_role, &parsed).await?)
+            } else {
+                None
+            };
+
+            let action = if approve { "held_message_approved" } else { "held_message_rejected" };
+            ctx.audit.record(&mut *tx, action, Some(moderator_id), json!({
+                "message_id": message_id,
+                "room_id": room_id,
+                "author_id": author_id,
+                "held_reason": message.get::<Option<String>, _>("held_reason"),
+                "note": note
+            })).await?;
+
+            tx.commit().await
+                .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+            Ok(ReviewedMessage {
+                room_id,
+                author_id,
+                username: message.get("username"),
+                content,
+                parent_message_id: message.get("parent_message_id"),
+                visible_to: message.get("visible_to"),
+                metadata: message.get("metadata"),
+                created_at: message.get("created_at"),
+                mentions,
+            })
+        })
+    }
+
+    fn missed_messages<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64, cipher: Option<&'a MessageCipher>) -> BoxFuture<'a, Result<Vec<MessageChange>>> {
+        Box::pin(async move {
+            let sql = format!("
+                SELECT * FROM (
+                    SELECT m.id, m.conversation_id, m.author_id, u.username, m.content, m.parent_message_id,
+                           m.encryption_key_id, m.wrapped_key, m.created_at, m.edited_at,
+                           CASE WHEN m.is_e2ee THEN m.metadata->'e2ee' END as e2ee_header,
+                           CASE WHEN m.status = 'deleted' THEN m.updated_at END as deleted_at,
+                           GREATEST(m.created_at, COALESCE(m.edited_at, m.created_at),
+                                    CASE WHEN m.status = 'deleted' THEN m.updated_at ELSE m.created_at END) as changed_at
+                    FROM messages m
+                    JOIN users u ON u.id = m.author_id
+                    WHERE m.conversation_id IN ({conversations}) AND {visible} AND {held}
+                ) changes
+                WHERE (changed_at, id) > ($2, $3)
+                ORDER BY changed_at, id
+                LIMIT $4
+            ", conversations = USER_CONVERSATIONS, visible = visibility_clause(1), held = held_clause(1));
+            let rows = query(&sql)
+                .bind(user_id)
+                .bind(after.0)
+                .bind(after.1)
+                .bind(limit)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("missed_messages", e))?;
+            rows.into_iter().map(|row| Ok(MessageChange {
+                id: row.get("id"),
+                conversation_id: row.get("conversation_id"),
+                author_id: row.get("author_id"),
+                username: row.get("username"),
+                content: open_row_content(cipher, &row, "content")?,
+                parent_message_id: row.get("parent_message_id"),
+                e2ee_header: row.get("e2ee_header"),
+                created_at: row.get("created_at"),
+                edited_at: row.get("edited_at"),
+                deleted_at: row.get("deleted_at"),
+            })).collect()
+        })
+    }
+
+    fn missed_reactions<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64) -> BoxFuture<'a, Result<Vec<ReactionChange>>> {
+        Box::pin(async move {
+            let sql = format!("
+                SELECT r.id, m.conversation_id, r.message_id, r.user_id, r.emoji, r.created_at
+                FROM message_reactions r
+                JOIN messages m ON m.id = r.message_id
+                WHERE m.conversation_id IN ({conversations}) AND m.status != 'deleted' AND {visible} AND {held}
+                  AND (r.created_at, r.id) > ($2, $3)
+                ORDER BY r.created_at, r.id
+                LIMIT $4
+            ", conversations = USER_CONVERSATIONS, visible = visibility_clause(1), held = held_clause(1));
+            let rows = query(&sql)
+                .bind(user_id)
+                .bind(after.0)
+                .bind(after.1)
+                .bind(limit)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("missed_reactions", e))?;
+            Ok(rows.into_iter().map(|row| ReactionChange {
+                id: row.get("id"),
+                conversation_id: row.get("conversation_id"),
+                message_id: row.get("message_id"),
+                user_id: row.get("user_id"),
+                emoji: row.get("emoji"),
+                created_at: row.get("created_at"),
+            }).collect())
+        })
+    }
+
+    fn missed_memberships<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64) -> BoxFuture<'a, Result<Vec<MembershipChange>>> {
+        Box::pin(async move {
+            let sql = format!("
+                SELECT * FROM (
+                    SELECT cm.id, cm.conversation_id, cm.user_id, cm.joined_at, cm.left_at,
+                           GREATEST(cm.joined_at, COALESCE(cm.left_at, cm.joined_at)) as changed_at
+                    FROM conversation_members cm
+                    WHERE cm.conversation_id IN ({conversations})
+                ) changes
+                WHERE (changed_at, id) > ($2, $3)
+                ORDER BY changed_at, id
+                LIMIT $4
+            ", conversations = USER_CONVERSATIONS);
+            let rows = query(&sql)
+                .bind(user_id)
+                .bind(after.0)
+                .bind(after.1)
+                .bind(limit)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ChatError::from_sqlx_error("missed_memberships", e))?;
+            Ok(rows.into_iter().map(|row| MembershipChange {
+                id: row.get("id"),
+                conversation_id: row.get("conversation_id"),
+                user_id: row.get("user_id"),
+                joined_at: row.get("joined_at"),
+                left_at: row.get("left_at"),
+            }).collect())
+        })
+    }
+}
@@ -0,0 +1,385 @@
+//! Module du mode lent des salons
+//!
+//! Protège les salons très actifs (raids, pics de trafic) :
+//! - Débit de chaque salon mesuré sur une fenêtre glissante
+//! - Au-delà de `limits.slow_mode_trigger_rate`, le mode lent automatique impose
+//!   `limits.slow_mode_interval` entre deux messages d'un même membre
+//! - Levée après `limits.slow_mode_cooldown`, une fois le débit retombé sous
+//!   `limits.slow_mode_recovery_rate`
+//! - Les modérateurs peuvent forcer un délai, couper le mode lent ou revenir au
+//!   mode automatique ; eux-mêmes n'y sont jamais soumis
+//! - Chaque changement est annoncé aux membres par une trame `system_message`
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sqlx::{query, Row};
+use crate::config::LimitsConfig;
+use crate::hub::common::ChatHub;
+use crate::hub::channels::{broadcast_to_room_members, is_moderator_role};
+use crate::error::{ChatError, Result};
+use serde_json::json;
+
+/// Délai maximal qu'un modérateur peut imposer
+pub const MAX_SLOW_MODE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Fréquence de vérification des salons à libérer
+const RECOVERY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// ================================================================
+// RÉGLAGES
+// ================================================================
+
+/// Choix des modérateurs pour un salon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlowModeOverride {
+    /// Mode lent déclenché et levé selon le débit du salon
+    #[default]
+    Auto,
+    /// Délai imposé en permanence
+    Forced(Duration),
+    /// Jamais de mode lent
+    Disabled,
+}
+
+impl SlowModeOverride {
+    /// Colonne `conversations.slow_mode_seconds`
+    pub fn from_db(seconds: Option<i32>) -> Self {
+        match seconds {
+            None => Self::Auto,
+            Some(seconds) if seconds <= 0 => Self::Disabled,
+            Some(seconds) => Self::Forced(Duration::from_secs(seconds as u64)),
+        }
+    }
+
+    pub fn to_db(&self) -> Option<i32> {
+        match self {
+            Self::Auto => None,
+            Self::Disabled => Some(0),
+            Self::Forced(interval) => Some(interval.as_secs() as i32),
+        }
+    }
+}
+
+/// Seuils du mode lent automatique
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowModeSettings {
+    pub trigger_rate: u32,
+    pub window: Duration,
+    pub interval: Duration,
+    pub recovery_rate: u32,
+    pub cooldown: Duration,
+}
+
+impl SlowModeSettings {
+    pub fn from_limits(limits: &LimitsConfig) -> Self {
+        Self {
+            trigger_rate: limits.slow_mode_trigger_rate,
+            window: limits.slow_mode_window,
+            interval: limits.slow_mode_interval,
+            recovery_rate: limits.slow_mode_recovery_rate,
+            cooldown: limits.slow_mode_cooldown,
+        }
+    }
+
+    pub fn auto_enabled(&self) -> bool {
+        self.trigger_rate > 0
+    }
+}
+
+// ================================================================
+// SUIVI DU DÉBIT
+// ================================================================
+
+/// Trafic récent d'un salon
+#[derive(Debug, Default)]
+struct RoomTraffic {
+    recent: VecDeque<Instant>,
+    auto_since: Option<Instant>,
+    last_post: HashMap<i64, Instant>,
+}
+
+impl RoomTraffic {
+    fn prune(&mut self, now: Instant, window: Duration) {
+        while self.recent.front().is_some_and(|sent| now.duration_since(*sent) >= window) {
+            self.recent.pop_front();
+        }
+    }
+}
+
+/// Débit des salons et état du mode lent automatique
+#[derive(Debug)]
+pub struct SlowModeTracker {
+    settings: SlowModeSettings,
+    rooms: HashMap<i64, RoomTraffic>,
+}
+
+impl SlowModeTracker {
+    pub fn new(settings: SlowModeSettings) -> Self {
+        Self { settings, rooms: HashMap::new() }
+    }
+
+    pub fn is_auto_active(&self, room_id: i64) -> bool {
+        self.rooms.get(&room_id).is_some_and(|room| room.auto_since.is_some())
+    }
+
+    /// Délai effectif entre deux messages d'un membre (`None` = pas de mode lent)
+    pub fn interval(&self, room_id: i64, mode: SlowModeOverride) -> Option<Duration> {
+        match mode {
+            SlowModeOverride::Disabled => None,
+            SlowModeOverride::Forced(interval) => Some(interval),
+            SlowModeOverride::Auto => self.is_auto_active(room_id).then_some(self.settings.interval),
+        }
+    }
+
+    /// Vérifie le délai du membre puis compte le message
+    ///
+    /// Retourne `true` si ce message déclenche le mode lent automatique.
+    pub fn admit(&mut self, room_id: i64, user_id: i64, exempt: bool, mode: SlowModeOverride, now: Instant) -> Result<bool> {
+        let settings = self.settings;
+        let interval = self.interval(room_id, mode);
+        let room = self.rooms.entry(room_id).or_default();
+
+        if let (Some(interval), false) = (interval, exempt) {
+            if let Some(elapsed) = room.last_post.get(&user_id).map(|last| now.duration_since(*last)) {
+                if elapsed < interval {
+                    return Err(ChatError::RateLimitExceeded {
+                        action: "slow_mode".to_string(),
+                        current: 1,
+                        limit: 1,
+                        window: (interval - elapsed).as_secs().max(1),
+                    });
+                }
+            }
+        }
+
+        room.last_post.insert(user_id, now);
+        room.prune(now, settings.window);
+        room.recent.push_back(now);
+
+        let triggered = mode == SlowModeOverride::Auto
+            && settings.auto_enabled()
+            && room.auto_since.is_none()
+            && room.recent.len() as u32 >= settings.trigger_rate;
+        if triggered {
+            room.auto_since = Some(now);
+        }
+        Ok(triggered)
+    }
+
+    /// Lève le mode lent des salons revenus au calme ; retourne ces salons
+    ///
+    /// Oublie aussi les salons inactifs (au-delà du délai maximal imposable).
+    pub fn lift_recovered(&mut self, now: Instant) -> Vec<i64> {
+        let settings = self.settings;
+        let mut lifted = Vec::new();
+
+        self.rooms.retain(|room_id, room| {
+            room.prune(now, settings.window);
+            room.last_post.retain(|_, last| now.duration_since(*last) < MAX_SLOW_MODE_INTERVAL);
+
+            if let Some(since) = room.auto_since {
+                if now.duration_since(since) >= settings.cooldown && (room.recent.len() as u32) < settings.recovery_rate {
+                    room.auto_since = None;
+                    lifted.push(*room_id);
+                }
+            }
+
+            room.auto_since.is_some() || !room.recent.is_empty() || !room.last_post.is_empty()
+        });
+
+        lifted
+    }
+
+    /// Un choix de modérateur remplace l'état automatique
+    pub fn reset_auto(&mut self, room_id: i64) {
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.auto_since = None;
+        }
+    }
+}
+
+// ================================================================
+// ANNONCES
+// ================================================================
+
+/// Annonce un changement de mode lent aux membres du salon
+pub async fn announce_slow_mode(hub: &ChatHub, room_id: i64, interval: Option<Duration>, automatic: bool) -> Result<()> {
+    let content = match interval {
+        Some(interval) => format!("🐢 Mode lent activé : un message toutes les {} s", interval.as_secs()),
+        None => "✅ Mode lent désactivé".to_string(),
+    };
+
+    broadcast_to_room_members(hub, room_id, &json!({
+        "type": "system_message",
+        "data": {
+            "roomId": room_id,
+            "event": "slow_mode",
+            "enabled": interval.is_some(),
+            "intervalSeconds": interval.map(|interval| interval.as_secs()),
+            "automatic": automatic,
+            "content": content
+        }
+    })).await
+}
+
+/// Surveille les salons en mode lent automatique et lève celui-ci au retour au calme
+pub fn spawn_slow_mode_monitor(hub: Arc<ChatHub>) -> Option<tokio::task::JoinHandle<()>> {
+    if !SlowModeSettings::from_limits(&hub.config.limits).auto_enabled() {
+        return None;
+    }
+
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RECOVERY_CHECK_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+            let lifted = hub.slow_mode.lock().await.lift_recovered(Instant::now());
+
+            for room_id in lifted {
+                tracing::info!(room_id = %room_id, "🐢 Levée du mode lent automatique");
+                if let Err(e) = announce_slow_mode(&hub, room_id, None, true).await {
+                    tracing::warn!(room_id = %room_id, error = %e, "⚠️ Annonce de levée du mode lent impossible");
+                }
+            }
+        }
+    }))
+}
+
+// ================================================================
+// CHOIX DES MODÉRATEURS
+// ================================================================
+
+/// Force, coupe ou rend automatique le mode lent d'un salon (modérateurs)
+pub async fn set_room_slow_mode(hub: &ChatHub, room_id: i64, moderator_id: i64, mode: SlowModeOverride) -> Result<()> {
+    tracing::info!(room_id = %room_id, moderator_id = %moderator_id, mode = ?mode, "🐢 Réglage du mode lent");
+
+    if let SlowModeOverride::Forced(interval) = mode {
+        if interval.is_zero() || interval > MAX_SLOW_MODE_INTERVAL {
+            return Err(ChatError::configuration_error("Délai du mode lent invalide (1 à 3600 s)"));
+        }
+    }
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let role: Option<String> = query("
+        SELECT role FROM conversation_members
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(moderator_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_role", e))?
+    .map(|row| row.get("role"));
+
+    if !role.as_deref().is_some_and(is_moderator_role) {
+        return Err(ChatError::unauthorized("set_room_slow_mode"));
+    }
+
+    query("UPDATE conversations SET slow_mode_seconds = $1, updated_at = NOW() WHERE id = $2")
+        .bind(mode.to_db())
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("update_slow_mode", e))?;
+
+    hub.audit_sink.record(&mut *tx, "room_slow_mode_changed", Some(moderator_id), json!({
+        "room_id": room_id,
+        "slow_mode_seconds": mode.to_db()
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    let interval = {
+        let mut tracker = hub.slow_mode.lock().await;
+        tracker.reset_auto(room_id);
+        tracker.interval(room_id, mode)
+    };
+
+    announce_slow_mode(hub, room_id, interval, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> SlowModeSettings {
+        SlowModeSettings {
+            trigger_rate: 5,
+            window: Duration::from_secs(10),
+            interval: Duration::from_secs(3),
+            recovery_rate: 2,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_spike_triggers_slow_mode_then_recovers() {
+        let mut tracker = SlowModeTracker::new(settings());
+        let start = Instant::now();
+
+        // Raid : cinq membres différents en une seconde
+        let triggers: Vec<bool> = (1..=5)
+            .map(|user| tracker.admit(1, user, false, SlowModeOverride::Auto, start).unwrap())
+            .collect();
+        assert_eq!(triggers, vec![false, false, false, false, true]);
+        assert_eq!(tracker.interval(1, SlowModeOverride::Auto), Some(Duration::from_secs(3)));
+
+        // Le même membre doit attendre, un modérateur non
+        let wait = tracker.admit(1, 1, false, SlowModeOverride::Auto, start + Duration::from_secs(1));
+        assert!(matches!(wait, Err(ChatError::RateLimitExceeded { window: 2, .. })));
+        assert!(tracker.admit(1, 9, true, SlowModeOverride::Auto, start + Duration::from_secs(1)).is_ok());
+
+        // Pas de levée avant la fin de la période minimale
+        assert!(tracker.lift_recovered(start + Duration::from_secs(20)).is_empty());
+        assert_eq!(tracker.lift_recovered(start + Duration::from_secs(31)), vec![1]);
+        assert_eq!(tracker.interval(1, SlowModeOverride::Auto), None);
+    }
+
+    #[test]
+    fn test_sustained_traffic_keeps_slow_mode() {
+        let mut tracker = SlowModeTracker::new(settings());
+        let start = Instant::now();
+        for user in 1..=5 {
+            tracker.admit(1, user, false, SlowModeOverride::Auto, start).unwrap();
+        }
+
+        // Toujours trois messages dans la fenêtre : au-dessus du seuil de levée
+        let later = start + Duration::from_secs(35);
+        for user in 10..13 {
+            tracker.admit(1, user, false, SlowModeOverride::Auto, later).unwrap();
+        }
+        assert!(tracker.lift_recovered(later).is_empty());
+        assert!(tracker.is_auto_active(1));
+    }
+
+    #[test]
+    fn test_moderator_overrides() {
+        let mut tracker = SlowModeTracker::new(settings());
+        let start = Instant::now();
+
+        // Coupé : pas de déclenchement malgré le pic
+        for user in 1..=10 {
+            assert!(!tracker.admit(1, user, false, SlowModeOverride::Disabled, start).unwrap());
+        }
+
+        // Forcé : délai imposé même sans trafic
+        let forced = SlowModeOverride::Forced(Duration::from_secs(60));
+        tracker.admit(2, 1, false, forced, start).unwrap();
+        assert!(tracker.admit(2, 1, false, forced, start + Duration::from_secs(30)).is_err());
+
+        // Le salon inactif reste suivi tant que le délai peut s'appliquer
+        tracker.lift_recovered(start + Duration::from_secs(30));
+        assert!(tracker.admit(2, 1, false, forced, start + Duration::from_secs(40)).is_err());
+    }
+
+    #[test]
+    fn test_override_db_roundtrip() {
+        for mode in [SlowModeOverride::Auto, SlowModeOverride::Disabled, SlowModeOverride::Forced(Duration::from_secs(15))] {
+            assert_eq!(SlowModeOverride::from_db(mode.to_db()), mode);
+        }
+    }
+}
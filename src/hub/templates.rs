@@ -0,0 +1,435 @@
+//! Module des modèles de réponse des salons
+//!
+//! Réponses préenregistrées pour les équipes de support :
+//! - Modèles nommés par salon, avec variables `{{nom}}`
+//! - Création et suppression réservées aux modérateurs du salon
+//! - Utilisables par tous les membres si le modèle l'autorise
+//! - Rendu côté serveur (`{{username}}`, `{{room}}`, `{{date}}` fournis par le
+//!   serveur, les autres par le client) puis passage par le filtre de contenu
+//!   avant l'envoi comme message ordinaire
+
+use std::collections::HashMap;
+use sqlx::{query, Row};
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use crate::hub::common::ChatHub;
+use crate::hub::channels::{is_moderator_role, send_room_message};
+use crate::hub::violations::record_if_blocked;
+use crate::security::{ContentFilter, FilterVerdict};
+use crate::permissions::Role;
+use crate::validation::{validate_message_content, validate_unicode_text};
+use crate::error::{ChatError, Result};
+use serde_json::json;
+
+/// Longueur maximale du nom d'un modèle ou d'une variable
+pub const MAX_TEMPLATE_NAME_LENGTH: usize = 50;
+
+/// Longueur maximale d'une valeur fournie par le client (en caractères)
+pub const MAX_TEMPLATE_VALUE_LENGTH: usize = 200;
+
+/// Nombre maximal de modèles par salon
+pub const MAX_TEMPLATES_PER_ROOM: i64 = 100;
+
+/// Variables renseignées par le serveur (prioritaires sur celles du client)
+pub const BUILTIN_VARIABLES: [&str; 3] = ["username", "room", "date"];
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Modèle de réponse d'un salon
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomTemplate {
+    pub id: i64,
+    pub room_id: i64,
+    pub name: String,
+    pub content: String,
+    /// Variables à fournir par le client (hors variables du serveur)
+    pub variables: Vec<String>,
+    pub members_can_use: bool,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ================================================================
+// RENDU
+// ================================================================
+
+/// Nom de modèle ou de variable : minuscules, chiffres, `_` et `-`
+pub fn validate_template_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_TEMPLATE_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ChatError::InvalidFormat {
+            field: "name".to_string(),
+            reason: "minuscules, chiffres, '_' ou '-' (50 caractères max)".to_string(),
+        })
+    }
+}
+
+/// Découpe le modèle en texte littéral et noms de variables
+///
+/// Un `{{` sans `}}` fermant reste du texte.
+fn split_placeholders(content: &str) -> Vec<(&str, Option<&str>)> {
+    let mut parts = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        parts.push((&rest[..start], Some(rest[start + 2..start + 2 + len].trim())));
+        rest = &rest[start + 4 + len..];
+    }
+
+    parts.push((rest, None));
+    parts
+}
+
+/// Variables du modèle à fournir par le client, dans l'ordre d'apparition
+pub fn template_variables(content: &str) -> Vec<String> {
+    let mut variables: Vec<String> = Vec::new();
+    for (_, variable) in split_placeholders(content) {
+        if let Some(variable) = variable {
+            if !BUILTIN_VARIABLES.contains(&variable) && !variables.iter().any(|v| v == variable) {
+                variables.push(variable.to_string());
+            }
+        }
+    }
+    variables
+}
+
+/// Remplace les variables ; les valeurs insérées ne sont pas réinterprétées
+pub fn render_template(content: &str, values: &HashMap<String, String>) -> Result<String> {
+    let mut rendered = String::with_capacity(content.len());
+
+    for (text, variable) in split_placeholders(content) {
+        rendered.push_str(text);
+        if let Some(variable) = variable {
+            let value = values.get(variable).ok_or_else(|| ChatError::configuration_error(
+                &format!("Variable de modèle manquante: {}", variable)
+            ))?;
+            rendered.push_str(value);
+        }
+    }
+
+    Ok(rendered)
+}
+
+/// Valeurs du client complétées par les variables du serveur
+fn template_values(client_values: HashMap<String, String>, username: &str, room_name: &str) -> Result<HashMap<String, String>> {
+    let mut values = HashMap::with_capacity(client_values.len() + BUILTIN_VARIABLES.len());
+
+    for (key, value) in client_values {
+        if value.chars().count() > MAX_TEMPLATE_VALUE_LENGTH {
+            return Err(ChatError::configuration_error(&format!("Valeur trop longue pour {} (max 200 caractères)", key)));
+        }
+        validate_unicode_text(&key, &value)?;
+        values.insert(key, value);
+    }
+
+    values.insert("username".to_string(), username.to_string());
+    values.insert("room".to_string(), room_name.to_string());
+    values.insert("date".to_string(), Utc::now().format("%Y-%m-%d").to_string());
+    Ok(values)
+}
+
+// ================================================================
+// GESTION DES MODÈLES
+// ================================================================
+
+/// Rôle actif du membre dans le salon (`None` si non membre)
+async fn member_role(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Option<String>> {
+    Ok(query("
+        SELECT role FROM conversation_members
+        WHERE conversation_id = $1 AND user_id = $2 AND left_at IS NULL
+    ")
+    .bind(room_id)
+    .bind(user_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_membership", e))?
+    .map(|row| row.get("role")))
+}
+
+async fn require_template_manager(hub: &ChatHub, room_id: i64, user_id: i64, action: &str) -> Result<()> {
+    match member_role(hub, room_id, user_id).await? {
+        Some(role) if is_moderator_role(&role) => Ok(()),
+        Some(_) => Err(ChatError::InsufficientPermissions {
+            action: action.to_string(),
+            conversation_id: room_id.to_string(),
+        }),
+        None => Err(ChatError::unauthorized(action)),
+    }
+}
+
+/// Modèles visibles par le membre (les modérateurs voient aussi les modèles réservés)
+pub async fn list_room_templates(hub: &ChatHub, room_id: i64, user_id: i64) -> Result<Vec<RoomTemplate>> {
+    tracing::debug!(room_id = %room_id, user_id = %user_id, "📋 Récupération des modèles du salon");
+
+    let role = member_role(hub, room_id, user_id).await?
+        .ok_or_else(|| ChatError::unauthorized("list_room_templates"))?;
+
+    let rows = query("
+        SELECT id, name, content, members_can_use, created_by, created_at
+        FROM room_templates
+        WHERE conversation_id = $1 AND (members_can_use OR $2)
+        ORDER BY name
+    ")
+    .bind(room_id)
+    .bind(is_moderator_role(&role))
+    .fetch_all(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("list_room_templates", e))?;
+
+    Ok(rows.into_iter()
+        .map(|row| {
+            let content: String = row.get("content");
+            RoomTemplate {
+                id: row.get("id"),
+                room_id,
+                name: row.get("name"),
+                variables: template_variables(&content),
+                content,
+                members_can_use: row.get("members_can_use"),
+                created_by: row.get("created_by"),
+                created_at: row.get("created_at"),
+            }
+        })
+        .collect())
+}
+
+/// Crée un modèle (modérateurs du salon)
+pub async fn create_room_template(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    name: &str,
+    content: &str,
+    members_can_use: bool
+) -> Result<RoomTemplate> {
+    tracing::info!(room_id = %room_id, user_id = %user_id, name = %name, "📋 Création d'un modèle de réponse");
+
+    validate_template_name(name)?;
+    validate_message_content(content, hub.config.limits.max_message_length)?;
+    let variables = template_variables(content);
+    for variable in &variables {
+        validate_template_name(variable)?;
+    }
+
+    require_template_manager(hub, room_id, user_id, "create_room_template").await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let template_count: i64 = query("SELECT COUNT(*) FROM room_templates WHERE conversation_id = $1")
+        .bind(room_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("count_room_templates", e))?
+        .get(0);
+
+    if template_count >= MAX_TEMPLATES_PER_ROOM {
+        return Err(ChatError::configuration_error("Nombre maximal de modèles atteint pour ce salon"));
+    }
+
+    let row = query("
+        INSERT INTO room_templates (conversation_id, name, content, members_can_use, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (conversation_id, name) DO NOTHING
+        RETURNING id, created_at
+    ")
+    .bind(room_id)
+    .bind(name)
+    .bind(content)
+    .bind(members_can_use)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("insert_room_template", e))?
+    .ok_or_else(|| ChatError::configuration_error("Un modèle porte déjà ce nom dans ce salon"))?;
+
+    let template_id: i64 = row.get("id");
+
+    hub.audit_sink.record(&mut *tx, "room_template_created", Some(user_id), json!({
+        "template_id": template_id,
+        "room_id": room_id,
+        "name": name,
+        "members_can_use": members_can_use,
+        "content": content
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    Ok(RoomTemplate {
+        id: template_id,
+        room_id,
+        name: name.to_string(),
+        content: content.to_string(),
+        variables,
+        members_can_use,
+        created_by: Some(user_id),
+        created_at: row.get("created_at"),
+    })
+}
+
+/// Supprime un modèle (modérateurs du salon)
+pub async fn delete_room_template(hub: &ChatHub, room_id: i64, user_id: i64, name: &str) -> Result<()> {
+    tracing::info!(room_id = %room_id, user_id = %user_id, name = %name, "🗑️ Suppression d'un modèle de réponse");
+
+    require_template_manager(hub, room_id, user_id, "delete_room_template").await?;
+
+    let mut tx = hub.db.begin().await
+        .map_err(|e| ChatError::from_sqlx_error("begin_transaction", e))?;
+
+    let template_id: i64 = query("
+        DELETE FROM room_templates
+        WHERE conversation_id = $1 AND name = $2
+        RETURNING id
+    ")
+    .bind(room_id)
+    .bind(name)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("delete_room_template", e))?
+    .map(|row| row.get("id"))
+    .ok_or_else(|| ChatError::not_found("room_template", name))?;
+
+    hub.audit_sink.record(&mut *tx, "room_template_deleted", Some(user_id), json!({
+        "template_id": template_id,
+        "room_id": room_id,
+        "name": name
+    })).await?;
+
+    tx.commit().await
+        .map_err(|e| ChatError::from_sqlx_error("commit_transaction", e))?;
+
+    Ok(())
+}
+
+// ================================================================
+// UTILISATION
+// ================================================================
+
+/// Rend le modèle et l'envoie comme message du membre dans le salon
+///
+/// Le message suit le chemin ordinaire (droits de publication, limites,
+/// diffusion) ; le nom du modèle est conservé dans les métadonnées. Le verdict
+/// du filtre (gravité, raison) est retourné avec l'identifiant du message.
+pub async fn send_room_template(
+    hub: &ChatHub,
+    room_id: i64,
+    user_id: i64,
+    username: &str,
+    name: &str,
+    values: HashMap<String, String>
+) -> Result<(i64, FilterVerdict)> {
+    tracing::info!(room_id = %room_id, user_id = %user_id, name = %name, "📋 Envoi d'un modèle de réponse");
+
+    let row = query("
+        SELECT t.content, t.members_can_use, c.name as room_name, cm.role
+        FROM room_templates t
+        JOIN conversations c ON c.id = t.conversation_id
+        LEFT JOIN conversation_members cm
+            ON cm.conversation_id = t.conversation_id AND cm.user_id = $3 AND cm.left_at IS NULL
+        WHERE t.conversation_id = $1 AND t.name = $2
+    ")
+    .bind(room_id)
+    .bind(name)
+    .bind(user_id)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("load_room_template", e))?
+    .ok_or_else(|| ChatError::not_found("room_template", name))?;
+
+    let Some(role) = row.get::<Option<String>, _>("role") else {
+        return Err(ChatError::unauthorized("send_room_template"));
+    };
+    if !row.get::<bool, _>("members_can_use") && !is_moderator_role(&role) {
+        return Err(ChatError::InsufficientPermissions {
+            action: "send_room_template".to_string(),
+            conversation_id: room_id.to_string(),
+        });
+    }
+
+    let values = template_values(values, username, row.get("room_name"))?;
+    let rendered = render_template(row.get("content"), &values)?;
+    validate_message_content(&rendered, hub.config.limits.max_message_length)?;
+    let verdict = ContentFilter::with_config(&hub.config.security.content_filter)?
+        .for_role(&Role::from_room_role(&role))
+        .check_content(&rendered);
+    let verdict = record_if_blocked(hub, user_id as i32, Some(room_id), verdict).await?;
+
+    let message_id = send_room_message(
+        hub,
+        room_id,
+        user_id,
+        username,
+        &verdict.content,
+        None,
+        Some(json!({ "template": name })),
+        None
+    ).await?.id;
+
+    Ok((message_id, verdict))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let rendered = render_template(
+            "Bonjour {{ customer }}, ici {{username}} ({{ticket}})",
+            &values(&[("customer", "Zoé"), ("username", "support"), ("ticket", "#42")])
+        ).unwrap();
+        assert_eq!(rendered, "Bonjour Zoé, ici support (#42)");
+
+        assert!(render_template("Ticket {{ticket}}", &HashMap::new()).is_err());
+        // Accolades non fermées : texte littéral
+        assert_eq!(render_template("a {{b", &HashMap::new()).unwrap(), "a {{b");
+    }
+
+    #[test]
+    fn test_values_are_not_reinterpreted() {
+        let rendered = render_template("{{a}}", &values(&[("a", "{{b}}")])).unwrap();
+        assert_eq!(rendered, "{{b}}");
+    }
+
+    #[test]
+    fn test_builtin_values_win_over_client() {
+        let values = template_values(values(&[("username", "admin"), ("order", "7")]), "alice", "support").unwrap();
+        assert_eq!(values["username"], "alice");
+        assert_eq!(values["room"], "support");
+        assert_eq!(values["order"], "7");
+
+        let too_long = HashMap::from([("order".to_string(), "x".repeat(MAX_TEMPLATE_VALUE_LENGTH + 1))]);
+        assert!(template_values(too_long, "alice", "support").is_err());
+    }
+
+    #[test]
+    fn test_client_variables_listed_once() {
+        assert_eq!(
+            template_variables("{{customer}} {{date}} {{ticket}} {{customer}}"),
+            vec!["customer".to_string(), "ticket".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_template_names() {
+        assert!(validate_template_name("refund_policy").is_ok());
+        assert!(validate_template_name("").is_err());
+        assert!(validate_template_name("Refund Policy").is_err());
+        assert!(validate_template_name(&"a".repeat(MAX_TEMPLATE_NAME_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Module de calcul des messages non lus
+//!
+//! Fournit en un seul appel le résumé des non lus pour le badge de l'application :
+//! - Une requête agrégée pour les salons (via `last_read_message_id`)
+//! - Une requête agrégée pour les DM (via le statut `read` des messages)
+//! - Les salons et DM en sourdine apparaissent dans le détail mais pas dans le total
+//!
+//! Les compteurs sont lus par `RoomRepository::unread_counts`.
+
+use sqlx::FromRow;
+use serde::Serialize;
+use crate::hub::common::ChatHub;
+use crate::validation::validate_user_id;
+use crate::error::Result;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct RoomUnread {
+    pub room_id: i64,
+    pub unread_count: i64,
+    pub is_muted: bool,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct DmUnread {
+    pub conversation_id: i64,
+    pub other_user_id: i64,
+    pub unread_count: i64,
+    pub is_muted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnreadSummary {
+    pub rooms: Vec<RoomUnread>,
+    pub direct_messages: Vec<DmUnread>,
+    /// Total pour le badge (hors salons et DM en sourdine)
+    pub total: i64,
+}
+
+impl UnreadSummary {
+    /// Construit le résumé à partir des compteurs agrégés
+    pub fn from_counts(rooms: Vec<RoomUnread>, direct_messages: Vec<DmUnread>) -> Self {
+        let rooms_total: i64 = rooms.iter()
+            .filter(|room| !room.is_muted)
+            .map(|room| room.unread_count)
+            .sum();
+        let dms_total: i64 = direct_messages.iter()
+            .filter(|dm| !dm.is_muted)
+            .map(|dm| dm.unread_count)
+            .sum();
+
+        Self {
+            rooms,
+            direct_messages,
+            total: rooms_total + dms_total,
+        }
+    }
+}
+
+// ================================================================
+// CALCUL DES NON LUS
+// ================================================================
+
+/// Résumé des messages non lus de tous les salons et DM d'un utilisateur
+pub async fn get_unread_summary(hub: &ChatHub, user_id: i64) -> Result<UnreadSummary> {
+    tracing::debug!(user_id = %user_id, "🔔 Calcul du résumé des non lus");
+
+    validate_user_id(user_id as i32)?;
+
+    let (rooms, direct_messages) = hub.room_repository.unread_counts(user_id).await?;
+    let summary = UnreadSummary::from_counts(rooms, direct_messages);
+
+    tracing::debug!(user_id = %user_id, total = %summary.total, "✅ Résumé des non lus calculé");
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded_rooms() -> Vec<RoomUnread> {
+        vec![
+            RoomUnread { room_id: 1, unread_count: 4, is_muted: false },
+            RoomUnread { room_id: 2, unread_count: 10, is_muted: true },
+            RoomUnread { room_id: 3, unread_count: 1, is_muted: false },
+        ]
+    }
+
+    fn seeded_dms() -> Vec<DmUnread> {
+        vec![
+            DmUnread { conversation_id: 7, other_user_id: 42, unread_count: 2, is_muted: false },
+            DmUnread { conversation_id: 8, other_user_id: 43, unread_count: 3, is_muted: false },
+        ]
+    }
+
+    #[test]
+    fn test_muted_rooms_excluded_from_total() {
+        let summary = UnreadSummary::from_counts(seeded_rooms(), Vec::new());
+        assert_eq!(summary.total, 5);
+
+        // Le salon muet reste présent dans le détail
+        let muted = summary.rooms.iter().find(|r| r.room_id == 2).unwrap();
+        assert_eq!(muted.unread_count, 10);
+    }
+
+    #[test]
+    fn test_muted_dms_excluded_from_total() {
+        let mut dms = seeded_dms();
+        dms[1].is_muted = true;
+        let summary = UnreadSummary::from_counts(Vec::new(), dms);
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.direct_messages.len(), 2);
+    }
+}
@@ -0,0 +1,404 @@
+//! Escalade des sanctions après des refus répétés du filtre de contenu
+//!
+//! Chaque message refusé par le filtre compte une violation pour son auteur.
+//! Les paliers de `[security.violation_escalation]` appliquent l'action du
+//! plus haut seuil atteint : avertissement, envoi suspendu, messages retenus
+//! pour examen, puis bannissement temporaire. Chaque action est tracée par le
+//! puits d'audit (`violation_<action>`, sans modérateur) et sa fin est
+//! annoncée par notification, dans le fuseau de l'utilisateur. Le compteur
+//! baisse d'une unité par `decay_interval` écoulé sans nouvelle violation.
+//!
+//! Compteur et échéances sont stockés par le `RoomRepository` du hub. Un
+//! utilisateur banni est refusé à la connexion comme à l'envoi.
+
+use std::time::Duration;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{query, Row};
+use chrono::{DateTime, Utc};
+use crate::close_codes::CloseReason;
+use crate::config::ViolationEscalationConfig;
+use crate::hub::common::ChatHub;
+use crate::hub::guests::is_guest;
+use crate::hub::profiles::get_user_timezone;
+use crate::hub::reports::send_to_moderators;
+use crate::error::{ChatError, Result};
+
+// ================================================================
+// PALIERS
+// ================================================================
+
+/// Action appliquée quand un palier est atteint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscalationAction {
+    Warn,
+    Mute,
+    FlagForReview,
+    TempBan,
+}
+
+impl EscalationAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Warn => "warn",
+            Self::Mute => "mute",
+            Self::FlagForReview => "flag_for_review",
+            Self::TempBan => "temp_ban",
+        }
+    }
+
+    /// Texte de la notification annonçant la fin de la sanction
+    pub fn until_notice(&self) -> Option<&'static str> {
+        match self {
+            Self::Warn => None,
+            Self::Mute => Some("Envoi de messages suspendu jusqu'au"),
+            Self::FlagForReview => Some("Messages soumis à examen jusqu'au"),
+            Self::TempBan => Some("Compte suspendu jusqu'au"),
+        }
+    }
+
+    /// Durée de la sanction (`None` pour un simple avertissement)
+    pub fn duration(&self, config: &ViolationEscalationConfig) -> Option<Duration> {
+        match self {
+            Self::Warn => None,
+            Self::Mute => Some(config.mute_duration),
+            Self::FlagForReview => Some(config.review_duration),
+            Self::TempBan => Some(config.ban_duration),
+        }
+    }
+}
+
+/// Action du plus haut palier atteint par `count` (0 désactive un palier)
+pub fn escalation_action(config: &ViolationEscalationConfig, count: u32) -> Option<EscalationAction> {
+    [
+        (config.ban_at, EscalationAction::TempBan),
+        (config.flag_at, EscalationAction::FlagForReview),
+        (config.mute_at, EscalationAction::Mute),
+        (config.warn_at, EscalationAction::Warn),
+    ]
+    .into_iter()
+    .find(|(threshold, _)| *threshold > 0 && count >= *threshold)
+    .map(|(_, action)| action)
+}
+
+/// Compteur après pardon : une violation retirée par `interval` écoulé depuis la dernière
+pub fn decayed_count(count: u32, last_violation_at: DateTime<Utc>, now: DateTime<Utc>, interval: Duration) -> u32 {
+    let elapsed = (now - last_violation_at).to_std().unwrap_or_default();
+    let forgiven = elapsed.as_secs() / interval.as_secs().max(1);
+    count.saturating_sub(forgiven.min(u32::MAX as u64) as u32)
+}
+
+/// Violation suivante ; `previous` : compteur et date de la dernière violation
+pub fn next_violation(
+    config: &ViolationEscalationConfig,
+    previous: Option<(u32, DateTime<Utc>)>,
+    now: DateTime<Utc>
+) -> RecordedViolation {
+    let count = previous
+        .map_or(0, |(count, last_violation_at)| decayed_count(count, last_violation_at, now, config.decay_interval))
+        + 1;
+    let action = escalation_action(config, count);
+    let until = action
+        .and_then(|action| action.duration(config))
+        .and_then(|duration| chrono::Duration::from_std(duration).ok())
+        .map(|duration| now + duration);
+    RecordedViolation { count, action, until }
+}
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Dossier de violations d'un utilisateur, consulté par les modérateurs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserViolations {
+    pub user_id: i32,
+    /// Compteur après pardon
+    pub count: u32,
+    pub last_violation_at: Option<DateTime<Utc>>,
+    pub last_action: Option<String>,
+    pub muted_until: Option<DateTime<Utc>>,
+    pub review_until: Option<DateTime<Utc>>,
+    pub banned_until: Option<DateTime<Utc>>,
+}
+
+/// Violation comptée : compteur après pardon, action du palier atteint et sa fin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedViolation {
+    pub count: u32,
+    pub action: Option<EscalationAction>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Échéances des sanctions d'un utilisateur
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SanctionDeadlines {
+    pub muted_until: Option<DateTime<Utc>>,
+    pub review_until: Option<DateTime<Utc>>,
+    pub banned_until: Option<DateTime<Utc>>,
+}
+
+impl SanctionDeadlines {
+    /// Échéances après la violation : celle de son action remplace la précédente
+    pub fn after(mut self, violation: &RecordedViolation) -> Self {
+        match violation.action {
+            Some(EscalationAction::Mute) => self.muted_until = violation.until,
+            Some(EscalationAction::FlagForReview) => self.review_until = violation.until,
+            Some(EscalationAction::TempBan) => self.banned_until = violation.until,
+            Some(EscalationAction::Warn) | None => {}
+        }
+        self
+    }
+}
+
+/// Situation d'un auteur avant l'envoi d'un message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Standing {
+    /// Ses messages sont retenus pour examen
+    pub under_review: bool,
+}
+
+// ================================================================
+// ENREGISTREMENT
+// ================================================================
+
+/// Compte une violation et applique l'action du palier atteint
+///
+/// `conversation_id` : salon du message refusé, dont les modérateurs sont
+/// prévenus d'une mise en examen. Retourne l'action appliquée.
+pub async fn record_violation(
+    hub: &ChatHub,
+    user_id: i32,
+    conversation_id: Option<i64>,
+    reason: &str
+) -> Result<Option<EscalationAction>> {
+    let config = &hub.config.security.violation_escalation;
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    let RecordedViolation { count, action, until } = hub.moderation_repository
+        .record_violation(user_id as i64, conversation_id, reason, config, &hub.audit_sink)
+        .await?;
+
+    let Some(action) = action else {
+        return Ok(None);
+    };
+    tracing::warn!(user_id = %user_id, violations = %count, action = %action.as_str(), "🚨 Sanction automatique après violation du filtre");
+
+    let notice = json!({
+        "type": "moderation_notice",
+        "data": {
+            "action": action.as_str(),
+            "violations": count,
+            "until": until,
+            "reason": reason
+        }
+    }).to_string();
+    hub.send_to_user_sessions(user_id, &notice).await;
+
+    // Échéance rappelée hors session (le banni est déconnecté), dans le fuseau de l'utilisateur
+    if let (Some(until), Some(message)) = (until, action.until_notice()) {
+        let timezone = get_user_timezone(hub, user_id as i64).await.unwrap_or_else(|e| {
+            tracing::warn!(user_id = %user_id, error = %e, "⚠️ Fuseau horaire illisible, UTC utilisé");
+            chrono_tz::Tz::UTC
+        });
+        if let Err(e) = hub.notifications.notify_system_event(user_id, "Sanction automatique", message, until, timezone).await {
+            tracing::warn!(user_id = %user_id, error = %e, "⚠️ Notification de sanction non envoyée");
+        }
+    }
+
+    match action {
+        EscalationAction::FlagForReview => {
+            if let Some(conversation_id) = conversation_id {
+                let text = json!({
+                    "type": "user_flagged",
+                    "data": {
+                        "userId": user_id,
+                        "roomId": conversation_id,
+                        "violations": count,
+                        "until": until
+                    }
+                }).to_string();
+                send_to_moderators(hub, conversation_id, &text).await?;
+            }
+        }
+        EscalationAction::TempBan => {
+            let retry_after_secs = Some(config.ban_duration.as_secs());
+            hub.disconnect_user(user_id, CloseReason::Banned { retry_after_secs }, Some(reason)).await;
+        }
+        EscalationAction::Warn | EscalationAction::Mute => {}
+    }
+
+    Ok(Some(action))
+}
+
+/// Compte une violation si le filtre a refusé le message, puis rend son verdict
+pub async fn record_if_blocked<T>(
+    hub: &ChatHub,
+    user_id: i32,
+    conversation_id: Option<i64>,
+    verdict: Result<T>
+) -> Result<T> {
+    if let Err(error @ (ChatError::InappropriateContent { .. } | ChatError::SpamDetected)) = &verdict {
+        if let Err(e) = record_violation(hub, user_id, conversation_id, &error.to_string()).await {
+            tracing::warn!(user_id = %user_id, error = %e, "⚠️ Enregistrement de la violation impossible");
+        }
+    }
+    verdict
+}
+
+// ================================================================
+// CONSULTATION
+// ================================================================
+
+/// Secondes restantes avant `until`, si l'échéance n'est pas passée
+fn remaining(until: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<u64> {
+    until
+        .filter(|until| *until > now)
+        .map(|until| (until - now).num_seconds().max(1) as u64)
+}
+
+fn suspended(retry_after: u64) -> ChatError {
+    ChatError::AccountSuspended {
+        reason: format!("Bannissement temporaire après des violations répétées ({}s restantes)", retry_after),
+    }
+}
+
+/// Refuse l'envoi d'un auteur suspendu ou banni ; indique s'il est en examen
+pub async fn check_standing(hub: &ChatHub, user_id: i32) -> Result<Standing> {
+    if !hub.config.security.violation_escalation.enabled || is_guest(user_id) {
+        return Ok(Standing::default());
+    }
+
+    let deadlines = hub.moderation_repository.sanction_deadlines(user_id as i64).await?;
+    let now = Utc::now();
+
+    if let Some(retry_after) = remaining(deadlines.banned_until, now) {
+        return Err(suspended(retry_after));
+    }
+    if let Some(retry_after) = remaining(deadlines.muted_until, now) {
+        return Err(ChatError::UserMuted { retry_after });
+    }
+    Ok(Standing { under_review: remaining(deadlines.review_until, now).is_some() })
+}
+
+/// Refuse la connexion d'un utilisateur banni temporairement
+pub async fn check_not_banned(hub: &ChatHub, user_id: i32) -> Result<()> {
+    if !hub.config.security.violation_escalation.enabled || is_guest(user_id) {
+        return Ok(());
+    }
+
+    let deadlines = hub.moderation_repository.sanction_deadlines(user_id as i64).await?;
+    match remaining(deadlines.banned_until, Utc::now()) {
+        Some(retry_after) => Err(suspended(retry_after)),
+        None => Ok(()),
+    }
+}
+
+/// Dossier de violations d'un utilisateur (équipe de modération globale)
+pub async fn get_user_violations(hub: &ChatHub, moderator_id: i32, user_id: i32) -> Result<UserViolations> {
+    let is_moderator: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM users WHERE id = $1 AND role::text IN ('moderator', 'admin', 'owner'))"
+    )
+    .bind(moderator_id as i64)
+    .fetch_one(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("check_moderator", e))?;
+    if !is_moderator {
+        return Err(ChatError::unauthorized("get_user_violations"));
+    }
+
+    let row = query("
+        SELECT count, last_violation_at, last_action, muted_until, review_until, banned_until
+        FROM user_violations WHERE user_id = $1
+    ")
+    .bind(user_id as i64)
+    .fetch_optional(&hub.db)
+    .await
+    .map_err(|e| ChatError::from_sqlx_error("get_user_violations", e))?;
+
+    let Some(row) = row else {
+        return Ok(UserViolations {
+            user_id,
+            count: 0,
+            last_violation_at: None,
+            last_action: None,
+            muted_until: None,
+            review_until: None,
+            banned_until: None,
+        });
+    };
+
+    let last_violation_at: DateTime<Utc> = row.get("last_violation_at");
+    Ok(UserViolations {
+        user_id,
+        count: decayed_count(
+            row.get::<i32, _>("count") as u32,
+            last_violation_at,
+            Utc::now(),
+            hub.config.security.violation_escalation.decay_interval
+        ),
+        last_violation_at: Some(last_violation_at),
+        last_action: row.get("last_action"),
+        muted_until: row.get("muted_until"),
+        review_until: row.get("review_until"),
+        banned_until: row.get("banned_until"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossing_thresholds_escalates_actions() {
+        let config = ViolationEscalationConfig::default();
+        let actions: Vec<Option<EscalationAction>> = (0..=9)
+            .map(|count| escalation_action(&config, count))
+            .collect();
+
+        assert_eq!(actions[0], None);
+        assert_eq!(actions[1], Some(EscalationAction::Warn));
+        assert_eq!(actions[2], Some(EscalationAction::Warn));
+        assert_eq!(actions[3], Some(EscalationAction::Mute));
+        assert_eq!(actions[5], Some(EscalationAction::FlagForReview));
+        assert_eq!(actions[8], Some(EscalationAction::TempBan));
+        assert_eq!(actions[9], Some(EscalationAction::TempBan));
+        // Chaque palier franchi est au moins aussi sévère que le précédent
+        assert!(actions.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Un palier désactivé est sauté
+        let no_mute = ViolationEscalationConfig { mute_at: 0, ..config };
+        assert_eq!(escalation_action(&no_mute, 4), Some(EscalationAction::Warn));
+        assert_eq!(EscalationAction::Mute.duration(&no_mute), Some(no_mute.mute_duration));
+        assert_eq!(EscalationAction::Warn.duration(&no_mute), None);
+        // Seules les sanctions à durée annoncent leur échéance
+        assert_eq!(EscalationAction::Warn.until_notice(), None);
+        assert!(EscalationAction::TempBan.until_notice().is_some());
+    }
+
+    #[test]
+    fn test_decay_reduces_count() {
+        let interval = Duration::from_secs(3600);
+        let last = Utc::now();
+        let after = |secs: i64| last + chrono::Duration::seconds(secs);
+
+        assert_eq!(decayed_count(5, last, after(0), interval), 5);
+        assert_eq!(decayed_count(5, last, after(3599), interval), 5);
+        assert_eq!(decayed_count(5, last, after(3600), interval), 4);
+        assert_eq!(decayed_count(5, last, after(3 * 3600 + 10), interval), 2);
+        assert_eq!(decayed_count(5, last, after(100 * 3600), interval), 0);
+        // Horloge en arrière : aucun pardon
+        assert_eq!(decayed_count(5, last, after(-60), interval), 5);
+
+        // Un utilisateur banni redescend sous les paliers après pardon
+        let config = ViolationEscalationConfig { decay_interval: interval, ..Default::default() };
+        let count = decayed_count(8, last, after(4 * 3600), config.decay_interval);
+        assert_eq!(escalation_action(&config, count), Some(EscalationAction::Mute));
+        let count = decayed_count(8, last, after(7 * 3600), config.decay_interval);
+        assert_eq!(escalation_action(&config, count), Some(EscalationAction::Warn));
+    }
+}
@@ -0,0 +1,151 @@
+//! Module de visibilité restreinte des messages de salon
+//!
+//! Un message peut être réservé à un sous-ensemble de membres (chuchotement
+//! de modération, information secrète d'un jeu, ...). L'auteur le voit
+//! toujours. Le message est persisté avec la liste `messages.visible_to` et
+//! exclu de l'historique des autres membres.
+
+use serde::Serialize;
+use crate::error::{ChatError, Result};
+use std::collections::BTreeSet;
+
+/// Nombre maximum de destinataires d'un message restreint
+pub const MAX_VISIBLE_TO: usize = 100;
+
+/// Clause SQL filtrant les messages visibles par `$user` (alias `m`)
+pub fn visibility_clause(user_param: usize) -> String {
+    format!(
+        "(m.visible_to IS NULL OR m.author_id = ${0} OR ${0} = ANY(m.visible_to))",
+        user_param
+    )
+}
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Visibilité d'un message : public ou réservé à certains membres
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MessageVisibility {
+    /// `None` = visible par tous les membres du salon
+    visible_to: Option<BTreeSet<i64>>,
+}
+
+impl MessageVisibility {
+    pub fn public() -> Self {
+        Self::default()
+    }
+
+    /// Construit la visibilité à partir de la liste fournie à l'envoi
+    pub fn from_request(visible_to: Option<Vec<i32>>, author_id: i64) -> Result<Self> {
+        let Some(ids) = visible_to else {
+            return Ok(Self::public());
+        };
+
+        let targets: BTreeSet<i64> = ids.into_iter()
+            .map(i64::from)
+            .filter(|&id| id != author_id)
+            .collect();
+
+        if targets.is_empty() {
+            return Err(ChatError::configuration_error("Un message restreint nécessite au moins un destinataire"));
+        }
+
+        if targets.len() > MAX_VISIBLE_TO {
+            return Err(ChatError::OutOfRange {
+                field: "visible_to".to_string(),
+                value: targets.len() as i64,
+                min: 1,
+                max: MAX_VISIBLE_TO as i64,
+            });
+        }
+
+        Ok(Self { visible_to: Some(targets) })
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.visible_to.is_some()
+    }
+
+    /// Destinataires ciblés (hors auteur), pour la persistance
+    pub fn targets(&self) -> Option<Vec<i64>> {
+        self.visible_to.as_ref().map(|ids| ids.iter().copied().collect())
+    }
+
+    /// Un membre peut-il voir le message ?
+    pub fn can_see(&self, user_id: i64, author_id: i64) -> bool {
+        match &self.visible_to {
+            None => true,
+            Some(ids) => user_id == author_id || ids.contains(&user_id),
+        }
+    }
+
+    /// Filtre les membres du salon destinataires de la diffusion
+    pub fn filter_recipients(&self, member_ids: Vec<i64>, author_id: i64) -> Vec<i64> {
+        member_ids.into_iter()
+            .filter(|&id| self.can_see(id, author_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTHOR: i64 = 1;
+
+    #[test]
+    fn test_only_targeted_members_receive() {
+        let visibility = MessageVisibility::from_request(Some(vec![3, 5]), AUTHOR).unwrap();
+        let members = vec![1, 2, 3, 4, 5, 6];
+
+        assert_eq!(visibility.filter_recipients(members, AUTHOR), vec![1, 3, 5]);
+        assert!(visibility.is_restricted());
+        assert_eq!(visibility.targets(), Some(vec![3, 5]));
+    }
+
+    #[test]
+    fn test_restricted_message_hidden_from_other_histories() {
+        // Reproduit la clause SQL appliquée à l'historique
+        let visibility = MessageVisibility::from_request(Some(vec![3]), AUTHOR).unwrap();
+        assert!(visibility.can_see(AUTHOR, AUTHOR));
+        assert!(visibility.can_see(3, AUTHOR));
+        assert!(!visibility.can_see(2, AUTHOR));
+
+        assert_eq!(
+            visibility_clause(2),
+            "(m.visible_to IS NULL OR m.author_id = $2 OR $2 = ANY(m.visible_to))"
+        );
+    }
+
+    #[test]
+    fn test_public_and_invalid_requests() {
+        let public = MessageVisibility::from_request(None, AUTHOR).unwrap();
+        assert!(!public.is_restricted());
+        assert_eq!(public.filter_recipients(vec![1, 2, 3], AUTHOR), vec![1, 2, 3]);
+
+        // L'auteur seul n'est pas un destinataire valide
+        assert!(MessageVisibility::from_request(Some(vec![1]), AUTHOR).is_err());
+        assert!(MessageVisibility::from_request(Some(Vec::new()), AUTHOR).is_err());
+    }
+
+    #[test]
+    fn test_whisper_refused_to_non_target_member() {
+        // Réagir, chercher et citer passent par la clause du lecteur : un membre
+        // hors des destinataires n'obtient ni accès, ni résultat, ni parent
+        let react = crate::hub::reactions::message_access_query();
+        assert!(react.contains(&visibility_clause(2)));
+        assert!(react.contains("cm.user_id IS NOT NULL"));
+
+        for in_room in [true, false] {
+            let search = crate::message_store::search_query(in_room);
+            assert!(search.contains(&visibility_clause(1)));
+            assert!(search.contains("c.is_public OR cm.user_id IS NOT NULL"));
+        }
+
+        assert!(crate::hub::quotes::quoted_parent_query().contains(&visibility_clause(3)));
+
+        // La mise en avant est publique : jamais pour un message restreint
+        assert!(crate::hub::highlights::highlight_candidate_query().contains("m.visible_to IS NULL"));
+    }
+}
@@ -34,23 +34,44 @@
 pub mod auth;
 pub mod cache;
 pub mod client;
+pub mod close_codes;
+pub mod compression;
 pub mod config;
+pub mod content_pipeline;
+pub mod db_pool;
+pub mod encryption;
 pub mod error;
+pub mod event_bridge;
+pub mod event_log;
 pub mod hub;
+pub mod link_policy;
+pub mod message_batcher;
 pub mod message_handler;
+pub mod message_quota;
+pub mod message_schema;
 pub mod message_store;
 pub mod messages;
 pub mod models;
 pub mod moderation;
 pub mod monitoring;
+pub mod object_store;
+pub mod pagination;
 pub mod permissions;
 pub mod presence;
+pub mod profile_cache;
 pub mod rate_limiter;
+pub mod room_id;
 pub mod security;
 pub mod services;
+pub mod sharded_map;
 pub mod utils;
 pub mod validation;
 pub mod websocket;
+pub mod word_packs;
+
+/// Harnais de test en mémoire pour les crates utilisatrices
+#[cfg(feature = "testing")]
+pub mod testing;
 
 // ================================================================
 // RÉEXPORTS PUBLICS
@@ -60,6 +81,7 @@ pub use auth::Claims;
 pub use config::ServerConfig;
 pub use error::{ChatError, Result};
 pub use hub::{ChatHub, HubStats};
+pub use pagination::Page;
 pub use models::*;
 
 /// Version du serveur
@@ -89,8 +111,11 @@ pub fn welcome_message() -> String {
 pub async fn initialize_server() -> Result<()> {
     tracing::info!("🚀 Initialisation du serveur Veza Chat v{}", VERSION);
     
+    // Tous les problèmes de configuration sont remontés ensemble
+    let config = ServerConfig::from_env()?;
+    tracing::info!(config = %config, "⚙️ Configuration chargée");
+    
     // TODO: Implémenter l'initialisation complète
-    // let config = ServerConfig::from_env()?;
     // let hub = ChatHub::new(db_pool, config).await?;
     
     Ok(())
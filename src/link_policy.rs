@@ -0,0 +1,498 @@
+//! Politique des liens sortants dans les messages
+//!
+//! Contre l'hameçonnage, les liens `http(s)://` d'un message sont confrontés
+//! à des listes de domaines (`[link_policy]`, surchargeables par salon) :
+//! - Un domaine refusé reçoit `denied_action`, un domaine hors de la liste
+//!   autorisée (si elle n'est pas vide) `unlisted_action`
+//! - `reject` refuse le message, `mask` remplace le lien dans le contenu,
+//!   `warn` l'accepte et le signale aux clients (`linkWarnings`)
+//! - Les liens raccourcis (`shortener_domains`) sont suivis avant la
+//!   vérification ; seules des adresses publiques sont contactées, à chaque
+//!   redirection (feature `link-expansion`). Un lien dont la destination n'a
+//!   pu être atteinte est traité comme un domaine non listé (au moins `warn`)
+//!
+//! Un domaine couvre ses sous-domaines : `example.com` vaut pour
+//! `www.example.com`, pas pour `badexample.com`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use url::Url;
+use crate::config::{LinkAction, LinkPolicyConfig, ServerConfig};
+use crate::content_pipeline::UrlAutoLink;
+use crate::error::{ChatError, Result};
+
+/// Texte remplaçant un lien masqué
+pub const MASKED_LINK: &str = "[lien masqué]";
+
+/// Le nom d'hôte relève-t-il du domaine (ou d'un de ses sous-domaines) ?
+pub fn domain_matches(host: &str, domain: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    !domain.is_empty()
+        && (host == domain || host.strip_suffix(&domain).is_some_and(|prefix| prefix.ends_with('.')))
+}
+
+/// Adresse qu'une expansion de lien peut contacter (protection SSRF)
+///
+/// Exclut le bouclage, les réseaux privés, partagés (100.64.0.0/10) et locaux
+/// au lien, les adresses de documentation, de diffusion et non spécifiées.
+pub fn is_public_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_unspecified()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_address(&IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // unique locale
+                || (first & 0xffc0) == 0xfe80 // locale au lien
+                || first == 0x2001 && v6.segments()[1] == 0x0db8) // documentation
+        }
+    }
+}
+
+// ================================================================
+// POLITIQUE
+// ================================================================
+
+/// Surcharges d'un salon (`conversations.link_policy`) ; `None` = réglage du serveur
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomLinkPolicy {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub allowed_domains: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_domains: Option<Vec<String>>,
+    #[serde(default)]
+    pub unlisted_action: Option<LinkAction>,
+    #[serde(default)]
+    pub denied_action: Option<LinkAction>,
+}
+
+/// Nombre maximal de domaines par liste d'un salon
+pub const MAX_ROOM_LINK_DOMAINS: usize = 200;
+
+impl RoomLinkPolicy {
+    pub fn validate(&self) -> Result<()> {
+        for domains in [&self.allowed_domains, &self.denied_domains].into_iter().flatten() {
+            if domains.len() > MAX_ROOM_LINK_DOMAINS {
+                return Err(ChatError::OutOfRange {
+                    field: "link_policy_domains".to_string(),
+                    value: domains.len() as i64,
+                    min: 0,
+                    max: MAX_ROOM_LINK_DOMAINS as i64,
+                });
+            }
+            if let Some(domain) = domains.iter().find(|d| d.trim().is_empty() || d.contains(['/', ':', ' '])) {
+                return Err(ChatError::configuration_error(&format!("Domaine invalide: {}", domain)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Politique effective d'un message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPolicy {
+    pub enabled: bool,
+    pub allowed_domains: Vec<String>,
+    pub denied_domains: Vec<String>,
+    pub unlisted_action: LinkAction,
+    pub denied_action: LinkAction,
+    pub shortener_domains: Vec<String>,
+}
+
+impl LinkPolicy {
+    pub fn from_config(config: &LinkPolicyConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            allowed_domains: config.allowed_domains.clone(),
+            denied_domains: config.denied_domains.clone(),
+            unlisted_action: config.unlisted_action,
+            denied_action: config.denied_action,
+            shortener_domains: if config.expand_shortened { config.shortener_domains.clone() } else { Vec::new() },
+        }
+    }
+
+    /// Applique les surcharges d'un salon
+    pub fn with_room(mut self, room: &RoomLinkPolicy) -> Self {
+        if let Some(enabled) = room.enabled {
+            self.enabled = enabled;
+        }
+        if let Some(allowed) = &room.allowed_domains {
+            self.allowed_domains = allowed.clone();
+        }
+        if let Some(denied) = &room.denied_domains {
+            self.denied_domains = denied.clone();
+        }
+        self.unlisted_action = room.unlisted_action.unwrap_or(self.unlisted_action);
+        self.denied_action = room.denied_action.unwrap_or(self.denied_action);
+        self
+    }
+
+    /// Action pour un nom d'hôte (`None` : lien illisible)
+    pub fn action_for(&self, host: Option<&str>) -> LinkAction {
+        let Some(host) = host else {
+            return self.unlisted_action.max(LinkAction::Warn);
+        };
+        if self.denied_domains.iter().any(|domain| domain_matches(host, domain)) {
+            self.denied_action
+        } else if !self.allowed_domains.is_empty()
+            && !self.allowed_domains.iter().any(|domain| domain_matches(host, domain))
+        {
+            self.unlisted_action
+        } else {
+            LinkAction::Allow
+        }
+    }
+
+    pub fn is_shortened(&self, url: &str) -> bool {
+        Url::parse(url).ok()
+            .and_then(|url| url.host_str().map(|host| self.shortener_domains.iter().any(|d| domain_matches(host, d))))
+            .unwrap_or(false)
+    }
+
+    /// Confronte les liens du contenu à la politique
+    ///
+    /// `expanded` : issue du suivi des liens raccourcis ; la destination est
+    /// vérifiée à leur place. Un lien refusé refuse tout le message.
+    pub fn review(&self, content: &str, expanded: &HashMap<String, LinkExpansion>) -> Result<LinkReview> {
+        let mut review = LinkReview { content: content.to_string(), warnings: Vec::new() };
+        if !self.enabled {
+            return Ok(review);
+        }
+
+        for link in UrlAutoLink::extract(content) {
+            let expansion = expanded.get(&link);
+            let target = match expansion {
+                Some(LinkExpansion::Resolved(target)) => target,
+                _ => &link,
+            };
+            let host = Url::parse(target).ok().and_then(|url| url.host_str().map(str::to_string));
+            let unresolved = expansion == Some(&LinkExpansion::Unresolved);
+            // Destination inconnue : traitée comme un domaine non listé
+            let action = if unresolved {
+                self.unlisted_action.max(LinkAction::Warn)
+            } else {
+                self.action_for(host.as_deref())
+            };
+            let domain = host.clone().unwrap_or_default();
+
+            match action {
+                LinkAction::Allow | LinkAction::Warn => {}
+                LinkAction::Reject => {
+                    return Err(ChatError::inappropriate_content_simple(&format!("Lien refusé: {}", domain)));
+                }
+                LinkAction::Mask => {
+                    review.content = review.content.replace(&link, MASKED_LINK);
+                }
+            }
+
+            if action != LinkAction::Allow && !review.warnings.iter().any(|w| w.url == link) {
+                review.warnings.push(LinkWarning {
+                    expanded_url: (target != &link).then(|| target.clone()),
+                    unresolved,
+                    url: link,
+                    domain,
+                    action,
+                });
+            }
+        }
+
+        Ok(review)
+    }
+}
+
+/// Lien signalé ou masqué
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkWarning {
+    pub url: String,
+    pub domain: String,
+    pub action: LinkAction,
+    /// Destination d'un lien raccourci
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded_url: Option<String>,
+    /// Lien raccourci dont la destination n'a pu être atteinte
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unresolved: bool,
+}
+
+/// Contenu après application de la politique
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkReview {
+    pub content: String,
+    pub warnings: Vec<LinkWarning>,
+}
+
+impl LinkReview {
+    /// Joint les liens signalés aux métadonnées du message
+    pub fn annotate_metadata(&self, metadata: &mut Value) {
+        if !self.warnings.is_empty() {
+            metadata["linkWarnings"] = json!(self.warnings);
+        }
+    }
+}
+
+// ================================================================
+// EXPANSION DES LIENS RACCOURCIS
+// ================================================================
+
+/// Issue du suivi d'un lien raccourci
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkExpansion {
+    /// Destination finale, atteinte sans erreur
+    Resolved(String),
+    /// Chaîne interrompue (erreur, adresse non publique, trop de redirections) ou non suivie
+    Unresolved,
+}
+
+/// Suivi des liens raccourcis jusqu'à leur destination
+pub trait LinkExpander: Send + Sync {
+    fn expand<'a>(&'a self, url: &'a str) -> BoxFuture<'a, LinkExpansion>;
+}
+
+/// Aucun lien n'est suivi
+#[derive(Debug, Default)]
+pub struct NoLinkExpansion;
+
+impl LinkExpander for NoLinkExpansion {
+    fn expand<'a>(&'a self, _url: &'a str) -> BoxFuture<'a, LinkExpansion> {
+        Box::pin(async { LinkExpansion::Unresolved })
+    }
+}
+
+/// Expansion choisie par la section `[link_policy]`
+pub fn link_expander_from_config(config: &ServerConfig) -> Arc<dyn LinkExpander> {
+    if !config.link_policy.expand_shortened {
+        return Arc::new(NoLinkExpansion);
+    }
+    http_expander(&config.link_policy)
+}
+
+#[cfg(feature = "link-expansion")]
+fn http_expander(config: &LinkPolicyConfig) -> Arc<dyn LinkExpander> {
+    Arc::new(HttpLinkExpander::new(config))
+}
+
+#[cfg(not(feature = "link-expansion"))]
+fn http_expander(_config: &LinkPolicyConfig) -> Arc<dyn LinkExpander> {
+    tracing::warn!("⚠️ Feature link-expansion absente, liens raccourcis signalés comme non suivis");
+    Arc::new(NoLinkExpansion)
+}
+
+/// Destinations des liens raccourcis du contenu
+pub async fn expand_shortened_links(policy: &LinkPolicy, expander: &dyn LinkExpander, content: &str) -> HashMap<String, LinkExpansion> {
+    let mut expanded = HashMap::new();
+    if !policy.enabled {
+        return expanded;
+    }
+    for link in UrlAutoLink::extract(content) {
+        if expanded.contains_key(&link) || !policy.is_shortened(&link) {
+            continue;
+        }
+        let expansion = expander.expand(&link).await;
+        expanded.insert(link, expansion);
+    }
+    expanded
+}
+
+#[cfg(feature = "link-expansion")]
+pub use http_expander::HttpLinkExpander;
+
+#[cfg(feature = "link-expansion")]
+mod http_expander {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    /// Suit les redirections une à une (requêtes `HEAD`) ; chaque saut est
+    /// résolu à l'avance et refusé s'il mène à une adresse non publique
+    pub struct HttpLinkExpander {
+        timeout: Duration,
+        max_redirects: u8,
+    }
+
+    impl HttpLinkExpander {
+        pub fn new(config: &LinkPolicyConfig) -> Self {
+            Self { timeout: config.expansion_timeout, max_redirects: config.max_redirects }
+        }
+
+        /// Adresse publique du lien, seule adresse que la requête pourra joindre
+        async fn public_address(url: &Url) -> Option<SocketAddr> {
+            if !matches!(url.scheme(), "http" | "https") {
+                return None;
+            }
+            let host = url.host_str()?;
+            let port = url.port_or_known_default()?;
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await.ok()?.collect();
+            // Un nom qui résout aussi vers une adresse interne est écarté en entier
+            if addresses.is_empty() || !addresses.iter().all(|addr| is_public_address(&addr.ip())) {
+                tracing::warn!(host = %host, "🛡️ Expansion de lien refusée : adresse non publique");
+                return None;
+            }
+            addresses.first().copied()
+        }
+
+        async fn next_hop(&self, url: &Url) -> Option<Option<Url>> {
+            let address = Self::public_address(url).await?;
+            let client = reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .timeout(self.timeout)
+                .resolve(url.host_str()?, address)
+                .build()
+                .ok()?;
+            let response = client.head(url.clone()).send().await.ok()?;
+            if !response.status().is_redirection() {
+                return Some(None);
+            }
+            let location = response.headers().get(reqwest::header::LOCATION)?.to_str().ok()?;
+            Some(Some(url.join(location).ok()?))
+        }
+    }
+
+    impl LinkExpander for HttpLinkExpander {
+        fn expand<'a>(&'a self, url: &'a str) -> BoxFuture<'a, LinkExpansion> {
+            Box::pin(async move {
+                let Ok(mut current) = Url::parse(url) else {
+                    return LinkExpansion::Unresolved;
+                };
+                // Un saut en échec ne laisse pas passer le lien pour sa destination
+                for _ in 0..=self.max_redirects {
+                    match self.next_hop(&current).await {
+                        Some(Some(next)) => current = next,
+                        Some(None) => return LinkExpansion::Resolved(current.to_string()),
+                        None => break,
+                    }
+                }
+                tracing::debug!(url = %url, "🔗 Lien raccourci non résolu");
+                LinkExpansion::Unresolved
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LinkPolicy {
+        LinkPolicy::from_config(&LinkPolicyConfig {
+            enabled: true,
+            allowed_domains: vec!["veza.example".to_string(), "github.com".to_string()],
+            denied_domains: vec!["evil.example".to_string(), "bad.veza.example".to_string()],
+            unlisted_action: LinkAction::Warn,
+            denied_action: LinkAction::Reject,
+            ..LinkPolicyConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_domain_matching_covers_subdomains_only() {
+        assert!(domain_matches("veza.example", "veza.example"));
+        assert!(domain_matches("docs.Veza.example.", "veza.example"));
+        assert!(!domain_matches("notveza.example", "veza.example"));
+        assert!(!domain_matches("veza.example.evil", "veza.example"));
+    }
+
+    #[test]
+    fn test_deny_list_wins_over_allow_list() {
+        let policy = policy();
+        assert_eq!(policy.action_for(Some("www.github.com")), LinkAction::Allow);
+        assert_eq!(policy.action_for(Some("bad.veza.example")), LinkAction::Reject);
+        assert_eq!(policy.action_for(Some("random.example")), LinkAction::Warn);
+        assert_eq!(policy.action_for(None), LinkAction::Warn);
+    }
+
+    #[test]
+    fn test_review_rejects_masks_and_warns() {
+        let policy = policy();
+        assert!(policy.review("voir https://evil.example/login", &HashMap::new()).is_err());
+
+        let review = policy.review("doc https://veza.example/a et https://random.example/b.", &HashMap::new()).unwrap();
+        assert_eq!(review.content, "doc https://veza.example/a et https://random.example/b.");
+        assert_eq!(review.warnings.len(), 1);
+        assert_eq!(review.warnings[0].domain, "random.example");
+
+        let masking = LinkPolicy { unlisted_action: LinkAction::Mask, ..policy };
+        let review = masking.review("clic https://random.example/b", &HashMap::new()).unwrap();
+        assert_eq!(review.content, format!("clic {}", MASKED_LINK));
+
+        let mut metadata = json!({});
+        review.annotate_metadata(&mut metadata);
+        assert_eq!(metadata["linkWarnings"][0]["action"], "mask");
+    }
+
+    #[test]
+    fn test_shortened_link_is_checked_at_destination() {
+        let policy = policy();
+        assert!(policy.is_shortened("https://bit.ly/abc"));
+        assert!(!policy.is_shortened("https://github.com/abc"));
+
+        let resolved = |target: &str| HashMap::from([("https://bit.ly/abc".to_string(), LinkExpansion::Resolved(target.to_string()))]);
+        assert!(policy.review("https://bit.ly/abc", &resolved("https://evil.example/x")).is_err());
+        assert!(policy.review("https://bit.ly/abc", &resolved("https://github.com/x")).unwrap().warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unresolved_shortened_link_is_treated_as_unlisted() {
+        let policy = policy();
+        let expanded = expand_shortened_links(&policy, &NoLinkExpansion, "https://bit.ly/abc").await;
+        assert_eq!(expanded["https://bit.ly/abc"], LinkExpansion::Unresolved);
+
+        let review = policy.review("https://bit.ly/abc", &expanded).unwrap();
+        assert_eq!(review.warnings.len(), 1);
+        assert!(review.warnings[0].unresolved);
+        assert_eq!(review.warnings[0].action, LinkAction::Warn);
+
+        // Liste autorisée stricte : refusé faute de destination connue
+        let strict = LinkPolicy { unlisted_action: LinkAction::Reject, ..policy };
+        assert!(strict.review("https://bit.ly/abc", &expanded).is_err());
+    }
+
+    #[test]
+    fn test_room_overrides_server_policy() {
+        let room = RoomLinkPolicy {
+            allowed_domains: Some(Vec::new()),
+            denied_action: Some(LinkAction::Mask),
+            ..RoomLinkPolicy::default()
+        };
+        let policy = policy().with_room(&room);
+        assert_eq!(policy.action_for(Some("random.example")), LinkAction::Allow);
+        assert_eq!(policy.action_for(Some("evil.example")), LinkAction::Mask);
+
+        let disabled = policy.with_room(&RoomLinkPolicy { enabled: Some(false), ..RoomLinkPolicy::default() });
+        assert!(disabled.review("https://evil.example", &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn test_only_public_addresses_are_fetched() {
+        for ip in ["127.0.0.1", "10.1.2.3", "192.168.0.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:10.0.0.1"] {
+            assert!(!is_public_address(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "2606:4700::1111"] {
+            assert!(is_public_address(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Regroupement des insertions de messages
+//!
+//! Sous forte charge, chaque message coûte un aller-retour `INSERT`. Le
+//! `MessageBatcher` accumule les messages pendant un court délai (ou jusqu'à
+//! une taille de lot) puis les insère en une seule requête multi-lignes.
+//!
+//! Garanties :
+//! - Un seul worker consomme une file FIFO : l'ordre d'envoi (donc l'ordre
+//!   par salon) est conservé dans l'attribution des identifiants
+//! - `shutdown()` vide la file avant de rendre la main
+//! - Un nonce client déjà stocké (ou répété dans le lot) n'est pas réinséré :
+//!   l'envoi reçoit la ligne existante, à vérifier par l'appelant
+
+use crate::error::{ChatError, Result};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+// ================================================================
+// STRUCTURES DE DONNÉES
+// ================================================================
+
+/// Paramètres de regroupement
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// Nombre de messages déclenchant une écriture immédiate
+    pub max_batch_size: usize,
+    /// Délai maximum d'attente avant écriture
+    pub max_delay: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 64,
+            max_delay: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Message en attente d'insertion
+#[derive(Debug, Clone)]
+pub struct PendingMessage {
+    pub uuid: Uuid,
+    pub conversation_id: i64,
+    pub author_id: i64,
+    pub content: String,
+    pub metadata: Value,
+    /// Nonce client servant à la déduplication des renvois
+    pub client_nonce: Option<String>,
+    /// Empreinte du contenu, renseignée avec le nonce
+    pub content_hash: Option<Vec<u8>>,
+}
+
+impl PendingMessage {
+    /// Clé d'unicité du nonce (index `idx_messages_client_nonce`)
+    fn nonce_key(&self) -> Option<(i64, i64, &str)> {
+        self.client_nonce.as_deref().map(|nonce| (self.author_id, self.conversation_id, nonce))
+    }
+}
+
+/// Résultat de l'insertion d'un message
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsertedMessage {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// UUID de la ligne stockée : différent de celui de l'envoi si son nonce l'était déjà
+    pub uuid: Uuid,
+    /// Empreinte du contenu de la ligne stockée
+    pub content_hash: Option<Vec<u8>>,
+}
+
+/// Destination des lots (PostgreSQL en production)
+pub trait BatchSink: Send + Sync + 'static {
+    /// Insère le lot et retourne les résultats dans l'ordre du lot
+    fn insert_batch<'a>(&'a self, batch: &'a [PendingMessage]) -> BoxFuture<'a, Result<Vec<InsertedMessage>>>;
+}
+
+type BatchRequest = (PendingMessage, oneshot::Sender<Result<InsertedMessage>>);
+
+// ================================================================
+// BATCHER
+// ================================================================
+
+pub struct MessageBatcher {
+    sender: std::sync::Mutex<Option<mpsc::UnboundedSender<BatchRequest>>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MessageBatcher {
+    /// Démarre le worker de regroupement (nécessite un runtime tokio)
+    pub fn spawn<S: BatchSink>(sink: S, config: BatchConfig) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let worker = tokio::spawn(run_worker(Arc::new(sink), config, receiver));
+
+        tracing::info!(
+            max_batch_size = %config.max_batch_size,
+            max_delay_ms = %config.max_delay.as_millis(),
+            "📦 Regroupement des insertions activé"
+        );
+
+        Self {
+            sender: std::sync::Mutex::new(Some(sender)),
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Ajoute un message au prochain lot et attend son identifiant
+    pub async fn submit(&self, message: PendingMessage) -> Result<InsertedMessage> {
+        let (reply, response) = oneshot::channel();
+
+        {
+            let sender = self.sender.lock()
+                .map_err(|_| ChatError::Internal { message: "Batcher empoisonné".to_string() })?;
+            let Some(sender) = sender.as_ref() else {
+                return Err(ChatError::Internal { message: "Batcher arrêté".to_string() });
+            };
+            sender.send((message, reply))
+                .map_err(|_| ChatError::Internal { message: "Batcher arrêté".to_string() })?;
+        }
+
+        response.await
+            .map_err(|_| ChatError::Internal { message: "Lot abandonné avant insertion".to_string() })?
+    }
+
+    /// Ferme la file et attend l'écriture des messages en attente
+    pub async fn shutdown(&self) {
+        if let Ok(mut sender) = self.sender.lock() {
+            sender.take();
+        }
+
+        if let Some(worker) = self.worker.lock().await.take() {
+            if let Err(e) = worker.await {
+                tracing::error!(error = %e, "❌ Arrêt anormal du worker de regroupement");
+            }
+        }
+
+        tracing::info!("📦 File d'insertion vidée");
+    }
+}
+
+async fn run_worker<S: BatchSink>(
+    sink: Arc<S>,
+    config: BatchConfig,
+    mut receiver: mpsc::UnboundedReceiver<BatchRequest>
+) {
+    let max_batch_size = config.max_batch_size.max(1);
+
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + config.max_delay;
+        let mut closed = false;
+
+        while batch.len() < max_batch_size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(request)) => batch.push(request),
+                Ok(None) => {
+                    closed = true;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+
+        flush(sink.as_ref(), batch).await;
+
+        if closed {
+            break;
+        }
+    }
+}
+
+async fn flush<S: BatchSink>(sink: &S, batch: Vec<BatchRequest>) {
+    let (messages, replies): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+
+    match sink.insert_batch(&messages).await {
+        Ok(inserted) if inserted.len() == replies.len() => {
+            tracing::debug!(batch_size = %inserted.len(), "📦 Lot de messages inséré");
+            for (reply, result) in replies.into_iter().zip(inserted) {
+                let _ = reply.send(Ok(result));
+            }
+        }
+        Ok(inserted) => {
+            tracing::error!(expected = %replies.len(), inserted = %inserted.len(), "❌ Lot partiellement inséré");
+            for reply in replies {
+                let _ = reply.send(Err(ChatError::Internal { message: "Lot partiellement inséré".to_string() }));
+            }
+        }
+        Err(e) => {
+            tracing::error!(batch_size = %replies.len(), error = %e, "❌ Échec de l'insertion groupée");
+            for reply in replies {
+                let _ = reply.send(Err(ChatError::Internal { message: format!("Échec de l'insertion groupée: {}", e) }));
+            }
+        }
+    }
+}
+
+// ================================================================
+// DESTINATION POSTGRESQL
+// ================================================================
+
+/// Insertion multi-lignes dans `messages`
+pub struct PgBatchSink {
+    db: PgPool,
+}
+
+impl PgBatchSink {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+impl BatchSink for PgBatchSink {
+    fn insert_batch<'a>(&'a self, batch: &'a [PendingMessage]) -> BoxFuture<'a, Result<Vec<InsertedMessage>>> {
+        Box::pin(async move {
+            // Un même nonce ne peut être écrit deux fois par une requête `ON CONFLICT DO UPDATE` :
+            // seul son premier envoi du lot est inséré, les suivants en reçoivent la ligne
+            let mut nonces_seen = HashSet::new();
+            let unique: Vec<&PendingMessage> = batch.iter()
+                .filter(|m| m.nonce_key().map_or(true, |key| nonces_seen.insert(key)))
+                .collect();
+
+            let uuids: Vec<Uuid> = unique.iter().map(|m| m.uuid).collect();
+            let authors: Vec<i64> = unique.iter().map(|m| m.author_id).collect();
+            let conversations: Vec<i64> = unique.iter().map(|m| m.conversation_id).collect();
+            let contents: Vec<String> = unique.iter().map(|m| m.content.clone()).collect();
+            let metadata: Vec<Value> = unique.iter().map(|m| m.metadata.clone()).collect();
+            let nonces: Vec<Option<String>> = unique.iter().map(|m| m.client_nonce.clone()).collect();
+            let hashes: Vec<Option<Vec<u8>>> = unique.iter().map(|m| m.content_hash.clone()).collect();
+
+            // ORDER BY ord : les identifiants sont attribués dans l'ordre du lot
+            let rows = sqlx::query("
+                INSERT INTO messages (uuid, author_id, conversation_id, content, metadata, client_nonce, content_hash, status)
+                SELECT b.uuid, b.author_id, b.conversation_id, b.content, b.metadata, b.client_nonce, b.content_hash, 'sent'::message_status
+                FROM UNNEST($1::uuid[], $2::bigint[], $3::bigint[], $4::text[], $5::jsonb[], $6::varchar[], $7::bytea[])
+                    WITH ORDINALITY AS b(uuid, author_id, conversation_id, content, metadata, client_nonce, content_hash, ord)
+                ORDER BY b.ord
+                ON CONFLICT (author_id, conversation_id, client_nonce) WHERE client_nonce IS NOT NULL
+                    DO UPDATE SET client_nonce = EXCLUDED.client_nonce
+                RETURNING id, uuid, author_id, conversation_id, client_nonce, content_hash, created_at
+            ")
+            .bind(&uuids)
+            .bind(&authors)
+            .bind(&conversations)
+            .bind(&contents)
+            .bind(&metadata)
+            .bind(&nonces)
+            .bind(&hashes)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("insert_message_batch", e))?;
+
+            // RETURNING n'a pas d'ordre garanti : réalignement par UUID, ou par nonce
+            // (la ligne d'un nonce déjà stocké porte l'UUID de son premier envoi)
+            let mut by_uuid = HashMap::new();
+            let mut by_nonce = HashMap::new();
+            for row in rows {
+                let inserted = InsertedMessage {
+                    id: row.get("id"),
+                    created_at: row.get("created_at"),
+                    uuid: row.get("uuid"),
+                    content_hash: row.get("content_hash"),
+                };
+                match row.get::<Option<String>, _>("client_nonce") {
+                    Some(nonce) => {
+                        by_nonce.insert((row.get::<i64, _>("author_id"), row.get::<i64, _>("conversation_id"), nonce), inserted);
+                    }
+                    None => {
+                        by_uuid.insert(inserted.uuid, inserted);
+                    }
+                }
+            }
+
+            Ok(batch.iter()
+                .filter_map(|m| match m.nonce_key() {
+                    Some((author_id, conversation_id, nonce)) => by_nonce.get(&(author_id, conversation_id, nonce.to_string())).cloned(),
+                    None => by_uuid.remove(&m.uuid),
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    /// Destination en mémoire enregistrant la composition des lots
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        batches: Arc<std::sync::Mutex<Vec<Vec<String>>>>,
+        next_id: Arc<AtomicI64>,
+    }
+
+    impl BatchSink for RecordingSink {
+        fn insert_batch<'a>(&'a self, batch: &'a [PendingMessage]) -> BoxFuture<'a, Result<Vec<InsertedMessage>>> {
+            Box::pin(async move {
+                self.batches.lock().unwrap().push(batch.iter().map(|m| m.content.clone()).collect());
+                Ok(batch.iter()
+                    .map(|m| InsertedMessage {
+                        id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+                        created_at: Utc::now(),
+                        uuid: m.uuid,
+                        content_hash: m.content_hash.clone(),
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    fn message(room_id: i64, content: &str) -> PendingMessage {
+        PendingMessage {
+            uuid: Uuid::new_v4(),
+            conversation_id: room_id,
+            author_id: 1,
+            content: content.to_string(),
+            metadata: serde_json::json!({}),
+            client_nonce: None,
+            content_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_by_size() {
+        let sink = RecordingSink::default();
+        let config = BatchConfig { max_batch_size: 3, max_delay: Duration::from_secs(60) };
+        let batcher = Arc::new(MessageBatcher::spawn(sink.clone(), config));
+
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                let batcher = batcher.clone();
+                tokio::spawn(async move { batcher.submit(message(1, &format!("m{}", i))).await })
+            })
+            .collect();
+
+        // Le lot plein part sans attendre le délai de 60 s
+        for handle in handles {
+            let inserted = tokio::time::timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+            assert!(inserted.is_ok());
+        }
+        assert_eq!(sink.batches.lock().unwrap().len(), 1);
+        assert_eq!(sink.batches.lock().unwrap()[0].len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flush_by_timeout() {
+        let sink = RecordingSink::default();
+        let config = BatchConfig { max_batch_size: 100, max_delay: Duration::from_millis(10) };
+        let batcher = MessageBatcher::spawn(sink.clone(), config);
+
+        let inserted = tokio::time::timeout(Duration::from_secs(1), batcher.submit(message(1, "seul"))).await;
+        assert_eq!(inserted.unwrap().unwrap().id, 1);
+        assert_eq!(sink.batches.lock().unwrap().as_slice(), &[vec!["seul".to_string()]]);
+    }
+
+    #[tokio::test]
+    async fn test_ordering_preserved_and_flushed_on_shutdown() {
+        let sink = RecordingSink::default();
+        let config = BatchConfig { max_batch_size: 4, max_delay: Duration::from_secs(60) };
+        let batcher = Arc::new(MessageBatcher::spawn(sink.clone(), config));
+
+        let mut handles = Vec::new();
+        for i in 0..6 {
+            let batcher = batcher.clone();
+            let room_id = (i % 2) as i64;
+            handles.push(tokio::spawn(async move { batcher.submit(message(room_id, &format!("m{}", i))).await }));
+            // Laisser chaque envoi atteindre la file dans l'ordre
+            tokio::task::yield_now().await;
+        }
+
+        // Le second lot (incomplet) n'est écrit qu'à l'arrêt
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        batcher.shutdown().await;
+
+        let ids: Vec<i64> = futures_util::future::join_all(handles).await
+            .into_iter()
+            .map(|h| h.unwrap().unwrap().id)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+
+        let flattened: Vec<String> = sink.batches.lock().unwrap().concat();
+        assert_eq!(flattened, (0..6).map(|i| format!("m{}", i)).collect::<Vec<_>>());
+
+        assert!(batcher.submit(message(1, "trop tard")).await.is_err());
+    }
+}
@@ -2,6 +2,7 @@ use std::sync::Arc;
 use crate::error::{ChatError, Result};
 use crate::hub::common::ChatHub;
 use crate::permissions::{Role, Permission, check_permission};
+use crate::room_id::RoomId;
 use crate::security::ContentFilter;
 use serde_json::json;
 use tokio::sync::mpsc::UnboundedSender;
@@ -27,18 +28,17 @@ impl MessageHandler {
         user_id: i32,
         username: &str,
         user_role: &Role,
-        room: &str,
+        room: &RoomId,
         content: &str,
     ) -> Result<()> {
         // Vérification des permissions
         check_permission(user_role, Permission::SendMessage)?;
 
-        // Validation et sanitisation du contenu
-        let clean_room = self.content_filter.validate_room_name(room)?;
+        // Sanitisation du contenu (le nom du salon est déjà canonique)
         let clean_content = self.content_filter.sanitize_content(content)?;
 
         // Vérification que l'utilisateur est dans le salon
-        if !self.is_user_in_room(user_id, &clean_room).await {
+        if !self.is_user_in_room(user_id, room).await {
             return Err(ChatError::configuration_error("Vous devez rejoindre le salon avant d'envoyer un message"));
         }
 
@@ -46,13 +46,13 @@ impl MessageHandler {
         tracing::info!(
             user_id = %user_id,
             username = %username,
-            room = %clean_room,
+            room = %room,
             message_length = %clean_content.len(),
             "📝 Message salon autorisé"
         );
 
         // Délégation à la logique métier
-        crate::hub::room::broadcast_to_room(&self.hub, user_id, username, &clean_room, &clean_content).await
+        crate::hub::room::broadcast_to_room(&self.hub, user_id, username, room, &clean_content).await
     }
 
     /// Gère les messages directs avec permissions
@@ -82,6 +82,12 @@ impl MessageHandler {
             return Ok(());
         }
 
+        // Même traitement silencieux si la confidentialité du destinataire l'exclut
+        if !crate::hub::direct_messages::dm_allowed(&self.hub.db, from_user as i64, to_user as i64).await? {
+            tracing::warn!(from_user = %from_user, to_user = %to_user, "🔒 Message DM écarté par la confidentialité du destinataire");
+            return Ok(());
+        }
+
         // Audit log
         tracing::info!(
             from_user = %from_user,
@@ -101,22 +107,19 @@ impl MessageHandler {
         user_id: i32,
         username: &str,
         user_role: &Role,
-        room: &str,
+        room: &RoomId,
         sender: &UnboundedSender<Message>,
     ) -> Result<()> {
         // Vérification des permissions
         check_permission(user_role, Permission::JoinRoom)?;
 
-        // Validation du nom de salon
-        let clean_room = self.content_filter.validate_room_name(room)?;
-
         // Vérification que le salon existe ou peut être créé
-        let room_exists = crate::hub::room::room_exists(&self.hub, &clean_room).await?;
+        let room_exists = crate::hub::room::room_exists(&self.hub, room).await?;
         
         if !room_exists {
             // Seuls les utilisateurs avec permission peuvent créer des salons
             if user_role.has_permission(&Permission::CreateRoom) {
-                tracing::info!(user_id = %user_id, room = %clean_room, "🏗️ Création d'un nouveau salon");
+                tracing::info!(user_id = %user_id, room = %room, "🏗️ Création d'un nouveau salon");
                 // Ici on pourrait créer le salon en base
             } else {
                 return Err(ChatError::configuration_error("Salon inexistant et vous n'avez pas la permission de le créer"));
@@ -127,18 +130,18 @@ impl MessageHandler {
         tracing::info!(
             user_id = %user_id,
             username = %username,
-            room = %clean_room,
+            room = %room,
             "👥 Jointure salon autorisée"
         );
 
         // Délégation à la logique métier
-        crate::hub::room::join_room(&self.hub, &clean_room, user_id).await?;
+        crate::hub::room::join_room(&self.hub, room, user_id).await?;
 
         // Envoi de confirmation
         let ack_msg = json!({
             "type": "join_ack",
             "data": {
-                "room": clean_room,
+                "room": room,
                 "status": "success",
                 "message": "Salon rejoint avec succès"
             }
@@ -155,29 +158,26 @@ impl MessageHandler {
         &self,
         user_id: i32,
         user_role: &Role,
-        room: &str,
+        room: &RoomId,
         limit: i64,
         sender: &UnboundedSender<Message>,
     ) -> Result<()> {
         // Vérification des permissions
         check_permission(user_role, Permission::ViewRoomHistory)?;
 
-        // Validation
-        let clean_room = self.content_filter.validate_room_name(room)?;
-
         // Vérification que l'utilisateur a accès au salon
-        if !self.is_user_in_room(user_id, &clean_room).await {
+        if !self.is_user_in_room(user_id, room).await {
             return Err(ChatError::configuration_error("Vous devez être membre du salon pour voir l'historique"));
         }
 
         // Délégation à la logique métier
-        let messages = crate::hub::room::fetch_room_history(&self.hub, &clean_room, limit).await?;
+        let messages = crate::hub::room::fetch_room_history(&self.hub, room, limit).await?;
 
         // Envoi de la réponse
         let history_msg = json!({
             "type": "room_history",
             "data": {
-                "room": clean_room,
+                "room": room,
                 "messages": messages,
                 "count": messages.len()
             }
@@ -188,7 +188,7 @@ impl MessageHandler {
 
         tracing::info!(
             user_id = %user_id,
-            room = %clean_room,
+            room = %room,
             message_count = %messages.len(),
             "📜 Historique salon envoyé"
         );
@@ -240,11 +240,8 @@ impl MessageHandler {
     }
 
     /// Vérifie si un utilisateur est dans un salon
-    async fn is_user_in_room(&self, user_id: i32, room: &str) -> bool {
-        let rooms = self.hub.rooms.read().await;
-        rooms.get(room)
-            .map(|users| users.contains(&user_id))
-            .unwrap_or(false)
+    async fn is_user_in_room(&self, user_id: i32, room: &RoomId) -> bool {
+        self.hub.rooms.with(room, |users| users.is_some_and(|users| users.contains(&user_id))).await
     }
 
     /// Vérifie si un utilisateur en a bloqué un autre
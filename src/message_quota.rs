@@ -0,0 +1,360 @@
+//! Quotas quotidiens de messages
+//!
+//! Chaque message envoyé (salon ou DM) consomme une unité du quota du jour de
+//! son auteur. Le plafond dépend du rang (`[quotas]`) : utilisateurs,
+//! modérateurs, administrateurs et bots ont chacun le leur, 0 = illimité.
+//! - Le jour suit `server.timezone` ; le compteur repart de zéro à minuit
+//! - Un envoi au-delà du plafond est refusé (`DailyQuotaExceeded`) avec
+//!   l'heure de remise à zéro
+//! - Le solde (`QuotaStatus`) accompagne les confirmations d'envoi et peut
+//!   être demandé à tout moment, pour prévenir l'utilisateur avant la limite
+//!
+//! `MemoryQuotaCounter` compte dans le processus (un seul nœud) ;
+//! `RedisQuotaCounter` (feature `redis-cache`) partage les compteurs entre nœuds.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Days, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use crate::config::{QuotaBackend, QuotaConfig, ServerConfig};
+use crate::error::{ChatError, Result};
+
+/// Catégorie de quota d'un compte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaClass {
+    User,
+    Moderator,
+    Admin,
+    Bot,
+}
+
+impl QuotaClass {
+    /// Catégorie d'après `users.role` et `users.is_bot`
+    pub fn from_account(role: &str, is_bot: bool) -> Self {
+        if is_bot {
+            return QuotaClass::Bot;
+        }
+        match role {
+            "admin" | "owner" | "super_admin" => QuotaClass::Admin,
+            "moderator" => QuotaClass::Moderator,
+            _ => QuotaClass::User,
+        }
+    }
+
+    /// Plafond quotidien (`None` = illimité)
+    pub fn limit(&self, config: &QuotaConfig) -> Option<u64> {
+        let limit = match self {
+            QuotaClass::User => config.user,
+            QuotaClass::Moderator => config.moderator,
+            QuotaClass::Admin => config.admin,
+            QuotaClass::Bot => config.bot,
+        };
+        (limit > 0).then_some(limit)
+    }
+}
+
+/// Jour de quota en cours et instant de sa remise à zéro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaDay {
+    pub date: NaiveDate,
+    pub resets_at: DateTime<Utc>,
+}
+
+impl QuotaDay {
+    /// Jour local de `now` dans le fuseau `tz`
+    pub fn at(now: DateTime<Utc>, tz: Tz) -> Self {
+        let date = now.with_timezone(&tz).date_naive();
+        let next = date.checked_add_days(Days::new(1)).unwrap_or(date);
+        // Minuit peut manquer un jour de changement d'heure : premier instant valide
+        let resets_at = next.and_hms_opt(0, 0, 0)
+            .and_then(|midnight| tz.from_local_datetime(&midnight).earliest())
+            .or_else(|| next.and_hms_opt(1, 0, 0).and_then(|one| tz.from_local_datetime(&one).earliest()))
+            .map(|local| local.with_timezone(&Utc))
+            .unwrap_or_else(|| now + chrono::Duration::days(1));
+        Self { date, resets_at }
+    }
+
+    /// Jour en cours selon la configuration
+    pub fn today(config: &ServerConfig) -> Self {
+        let tz = config.server.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+        Self::at(Utc::now(), tz)
+    }
+
+    /// Secondes restantes avant la remise à zéro
+    pub fn seconds_until_reset(&self, now: DateTime<Utc>) -> u64 {
+        (self.resets_at - now).num_seconds().max(0) as u64
+    }
+}
+
+/// Solde du quota quotidien d'un utilisateur
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaStatus {
+    pub class: QuotaClass,
+    pub used: u64,
+    /// `None` : illimité
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    pub resets_at: DateTime<Utc>,
+    /// Solde sous `quotas.warning_ratio` du plafond
+    pub warning: bool,
+}
+
+impl QuotaStatus {
+    pub fn new(class: QuotaClass, used: u64, limit: Option<u64>, day: &QuotaDay, warning_ratio: f64) -> Self {
+        let remaining = limit.map(|limit| limit.saturating_sub(used));
+        let warning = match (limit, remaining) {
+            (Some(limit), Some(remaining)) => (remaining as f64) <= limit as f64 * warning_ratio,
+            _ => false,
+        };
+        Self { class, used, limit, remaining, resets_at: day.resets_at, warning }
+    }
+
+    /// Erreur d'un envoi refusé faute de quota
+    pub fn exceeded_error(&self) -> ChatError {
+        ChatError::DailyQuotaExceeded {
+            used: self.used,
+            limit: self.limit.unwrap_or(0),
+            resets_at: self.resets_at.timestamp(),
+            retry_after: (self.resets_at - Utc::now()).num_seconds().max(0) as u64,
+        }
+    }
+}
+
+/// Compteurs de messages par utilisateur et par jour
+pub trait QuotaCounter: Send + Sync {
+    /// Compte un message et retourne le total du jour
+    fn increment<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<u64>>;
+
+    /// Annule un message compté (envoi refusé)
+    fn decrement<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<()>>;
+
+    /// Total du jour
+    fn current<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<u64>>;
+}
+
+/// Compteurs choisis par la section `[quotas]`
+pub fn quota_counter_from_config(config: &ServerConfig) -> Arc<dyn QuotaCounter> {
+    match config.quotas.backend {
+        #[cfg(feature = "redis-cache")]
+        QuotaBackend::Redis => match RedisQuotaCounter::new(&config.cache) {
+            Ok(counter) => return Arc::new(counter),
+            Err(e) => tracing::warn!(error = %e, "⚠️ Quotas Redis indisponibles, repli en mémoire"),
+        },
+        #[cfg(not(feature = "redis-cache"))]
+        QuotaBackend::Redis => tracing::warn!("⚠️ Feature redis-cache absente, quotas en mémoire"),
+        QuotaBackend::Memory => {}
+    }
+    Arc::new(MemoryQuotaCounter::default())
+}
+
+// ================================================================
+// COMPTEURS EN MÉMOIRE
+// ================================================================
+
+/// Compteurs locaux au processus ; seul le jour en cours est conservé
+#[derive(Debug, Default)]
+pub struct MemoryQuotaCounter {
+    counts: Mutex<HashMap<i64, (NaiveDate, u64)>>,
+}
+
+impl QuotaCounter for MemoryQuotaCounter {
+    fn increment<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let mut counts = self.counts.lock().await;
+            let entry = counts.entry(user_id).or_insert((day.date, 0));
+            if entry.0 != day.date {
+                *entry = (day.date, 0);
+            }
+            entry.1 += 1;
+            Ok(entry.1)
+        })
+    }
+
+    fn decrement<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut counts = self.counts.lock().await;
+            if let Some(entry) = counts.get_mut(&user_id).filter(|entry| entry.0 == day.date) {
+                entry.1 = entry.1.saturating_sub(1);
+            }
+            Ok(())
+        })
+    }
+
+    fn current<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let counts = self.counts.lock().await;
+            Ok(counts.get(&user_id).filter(|entry| entry.0 == day.date).map_or(0, |entry| entry.1))
+        })
+    }
+}
+
+// ================================================================
+// COMPTEURS REDIS
+// ================================================================
+
+#[cfg(feature = "redis-cache")]
+pub use redis_counter::RedisQuotaCounter;
+
+#[cfg(feature = "redis-cache")]
+mod redis_counter {
+    use super::*;
+    use redis::AsyncCommands;
+    use tokio::sync::OnceCell;
+    use crate::config::CacheConfig;
+
+    /// Compteurs partagés entre nœuds : une clé `INCR` par utilisateur et par
+    /// jour, expirée peu après la remise à zéro
+    pub struct RedisQuotaCounter {
+        client: redis::Client,
+        connection: OnceCell<redis::aio::ConnectionManager>,
+        key_prefix: String,
+    }
+
+    /// Marge de conservation d'un compteur après la remise à zéro
+    const EXPIRY_MARGIN: Duration = Duration::from_secs(3600);
+
+    fn redis_error(operation: &str, e: redis::RedisError) -> ChatError {
+        tracing::warn!(operation = %operation, error = %e, "⚠️ Erreur Redis des quotas");
+        ChatError::Cache { operation: operation.to_string() }
+    }
+
+    impl RedisQuotaCounter {
+        /// La connexion est ouverte au premier usage
+        pub fn new(cache: &CacheConfig) -> Result<Self> {
+            let client = redis::Client::open(cache.url.as_str())
+                .map_err(|e| ChatError::configuration_error(&format!("URL Redis invalide: {}", e)))?;
+            Ok(Self {
+                client,
+                connection: OnceCell::new(),
+                key_prefix: cache.key_prefix.clone(),
+            })
+        }
+
+        async fn connection(&self) -> Result<redis::aio::ConnectionManager> {
+            self.connection
+                .get_or_try_init(|| redis::aio::ConnectionManager::new(self.client.clone()))
+                .await
+                .cloned()
+                .map_err(|e| redis_error("connect", e))
+        }
+
+        fn key(&self, user_id: i64, day: &QuotaDay) -> String {
+            format!("{}quota:{}:{}", self.key_prefix, user_id, day.date)
+        }
+    }
+
+    impl QuotaCounter for RedisQuotaCounter {
+        fn increment<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<u64>> {
+            Box::pin(async move {
+                let mut conn = self.connection().await?;
+                let key = self.key(user_id, day);
+                let ttl = Duration::from_secs(day.seconds_until_reset(Utc::now())) + EXPIRY_MARGIN;
+
+                let (count,): (u64,) = redis::pipe()
+                    .atomic()
+                    .incr(&key, 1)
+                    .expire(&key, ttl.as_secs() as i64).ignore()
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| redis_error("quota_incr", e))?;
+                Ok(count)
+            })
+        }
+
+        fn decrement<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                let mut conn = self.connection().await?;
+                conn.decr::<_, _, i64>(self.key(user_id, day), 1).await
+                    .map_err(|e| redis_error("quota_decr", e))?;
+                Ok(())
+            })
+        }
+
+        fn current<'a>(&'a self, user_id: i64, day: &'a QuotaDay) -> BoxFuture<'a, Result<u64>> {
+            Box::pin(async move {
+                let mut conn = self.connection().await?;
+                let count: Option<u64> = conn.get(self.key(user_id, day)).await
+                    .map_err(|e| redis_error("quota_get", e))?;
+                Ok(count.unwrap_or(0))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_day_follows_configured_timezone() {
+        let day = QuotaDay::at(utc("2024-03-10T23:30:00Z"), Tz::UTC);
+        assert_eq!(day.date, NaiveDate::from_ymd_opt(2024, 3, 10).unwrap());
+        assert_eq!(day.resets_at, utc("2024-03-11T00:00:00Z"));
+        assert_eq!(day.seconds_until_reset(utc("2024-03-10T23:30:00Z")), 1800);
+
+        // 23h30 UTC = 9h30 le lendemain à Tokyo
+        let tokyo = QuotaDay::at(utc("2024-03-10T23:30:00Z"), chrono_tz::Asia::Tokyo);
+        assert_eq!(tokyo.date, NaiveDate::from_ymd_opt(2024, 3, 11).unwrap());
+        assert_eq!(tokyo.resets_at, utc("2024-03-11T15:00:00Z"));
+    }
+
+    #[test]
+    fn test_classes_have_separate_limits() {
+        let config = QuotaConfig { user: 100, moderator: 500, admin: 0, bot: 10_000, ..QuotaConfig::default() };
+
+        assert_eq!(QuotaClass::from_account("user", false).limit(&config), Some(100));
+        assert_eq!(QuotaClass::from_account("moderator", false).limit(&config), Some(500));
+        assert_eq!(QuotaClass::from_account("owner", false).limit(&config), None);
+        assert_eq!(QuotaClass::from_account("admin", true), QuotaClass::Bot);
+        assert_eq!(QuotaClass::Bot.limit(&config), Some(10_000));
+    }
+
+    #[test]
+    fn test_status_warns_near_the_limit() {
+        let day = QuotaDay::at(Utc::now(), Tz::UTC);
+        let fine = QuotaStatus::new(QuotaClass::User, 50, Some(100), &day, 0.1);
+        assert_eq!(fine.remaining, Some(50));
+        assert!(!fine.warning);
+
+        let close = QuotaStatus::new(QuotaClass::User, 95, Some(100), &day, 0.1);
+        assert!(close.warning);
+
+        let unlimited = QuotaStatus::new(QuotaClass::Admin, 5000, None, &day, 0.1);
+        assert_eq!(unlimited.remaining, None);
+        assert!(!unlimited.warning);
+
+        match close.exceeded_error() {
+            ChatError::DailyQuotaExceeded { limit, resets_at, .. } => {
+                assert_eq!(limit, 100);
+                assert_eq!(resets_at, day.resets_at.timestamp());
+            }
+            other => panic!("erreur inattendue: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_counter_resets_with_the_day() {
+        let counter = MemoryQuotaCounter::default();
+        let monday = QuotaDay::at(utc("2024-03-11T10:00:00Z"), Tz::UTC);
+        let tuesday = QuotaDay::at(utc("2024-03-12T10:00:00Z"), Tz::UTC);
+
+        assert_eq!(counter.increment(1, &monday).await.unwrap(), 1);
+        assert_eq!(counter.increment(1, &monday).await.unwrap(), 2);
+        assert_eq!(counter.increment(2, &monday).await.unwrap(), 1);
+        counter.decrement(1, &monday).await.unwrap();
+        assert_eq!(counter.current(1, &monday).await.unwrap(), 1);
+
+        assert_eq!(counter.current(1, &tuesday).await.unwrap(), 0);
+        assert_eq!(counter.increment(1, &tuesday).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,290 @@
+//! Schéma versionné des trames de message
+//!
+//! Les trames qui transportent des messages (`room_message`, `dm_message`,
+//! historiques, épingles) sont construites ici et annoncent leur version
+//! (`schemaVersion`, à la racine de la trame). Les évolutions sont additives :
+//! un champ nouveau est facultatif et n'existe qu'à partir de sa version.
+//!
+//! | Version | Champs ajoutés                                                   |
+//! |---------|------------------------------------------------------------------|
+//! | 1       | identifiant, auteur, contenu, horodatage, fil (`parentMessageId`) |
+//! | 2       | messages longs (`isLong`, `fullLength`), citations, ordre des épingles |
+//! | 3       | mentions, visibilité restreinte (`restricted`, `visibleTo`), mises en avant |
+//!
+//! Un client qui a négocié une version antérieure (`negotiate_schema`) reçoit
+//! des trames allégées des champs plus récents ; sans négociation, il reçoit
+//! la version courante.
+
+use std::borrow::Cow;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Version produite par ce serveur
+pub const CURRENT_SCHEMA_VERSION: u16 = 3;
+
+/// Plus ancienne version encore servie
+pub const MIN_SCHEMA_VERSION: u16 = 1;
+
+/// Version d'apparition des champs postérieurs à la version 1
+///
+/// Noms tels qu'ils circulent : camelCase pour les diffusions, snake_case pour
+/// les lignes d'historique.
+const FIELD_VERSIONS: &[(&str, u16)] = &[
+    ("isLong", 2),
+    ("fullLength", 2),
+    ("quote", 2),
+    ("pin_order", 2),
+    ("pinned_until", 2),
+    ("pinnedMessageIds", 2),
+    ("mentions", 3),
+    ("mention_count", 3),
+    ("restricted", 3),
+    ("visibleTo", 3),
+    ("highlightOf", 3),
+];
+
+/// Version d'apparition d'un champ (1 pour les champs d'origine)
+pub fn field_version(field: &str) -> u16 {
+    FIELD_VERSIONS.iter()
+        .find(|(name, _)| *name == field)
+        .map_or(MIN_SCHEMA_VERSION, |(_, version)| *version)
+}
+
+/// Version retenue pour une version demandée par le client
+pub fn negotiated_version(requested: u16) -> u16 {
+    requested.clamp(MIN_SCHEMA_VERSION, CURRENT_SCHEMA_VERSION)
+}
+
+// ================================================================
+// CONSTRUCTION
+// ================================================================
+
+/// Message diffusé en direct, commun aux salons et aux DM
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePayload<'a> {
+    pub id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<i64>,
+    pub author_id: i64,
+    pub username: &'a str,
+    pub content: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub parent_message_id: Option<i64>,
+    pub is_thread: bool,
+
+    // Version 2
+    pub is_long: bool,
+    pub full_length: Option<usize>,
+    pub quote: Option<Value>,
+
+    // Version 3 (salons uniquement)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mentions: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restricted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visible_to: Option<Option<Vec<i64>>>,
+}
+
+impl<'a> MessagePayload<'a> {
+    pub fn new(id: i64, author_id: i64, username: &'a str, content: &'a str, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            room_id: None,
+            conversation_id: None,
+            author_id,
+            username,
+            content,
+            timestamp,
+            parent_message_id: None,
+            is_thread: false,
+            is_long: false,
+            full_length: None,
+            quote: None,
+            mentions: None,
+            restricted: None,
+            visible_to: None,
+        }
+    }
+
+    pub fn in_room(mut self, room_id: i64) -> Self {
+        self.room_id = Some(room_id);
+        self
+    }
+
+    pub fn in_conversation(mut self, conversation_id: i64) -> Self {
+        self.conversation_id = Some(conversation_id);
+        self
+    }
+
+    pub fn with_parent(mut self, parent_message_id: Option<i64>) -> Self {
+        self.parent_message_id = parent_message_id;
+        self.is_thread = parent_message_id.is_some();
+        self
+    }
+
+    /// `full_length` : longueur du corps complet d'un message long tronqué
+    pub fn with_full_length(mut self, full_length: Option<usize>) -> Self {
+        self.is_long = full_length.is_some();
+        self.full_length = full_length;
+        self
+    }
+
+    pub fn with_quote(mut self, quote: Option<Value>) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// Mentions et visibilité d'un message de salon
+    pub fn with_audience(mut self, mentions: Value, visible_to: Option<Vec<i64>>) -> Self {
+        self.mentions = Some(mentions);
+        self.restricted = Some(visible_to.is_some());
+        self.visible_to = Some(visible_to);
+        self
+    }
+
+    /// Trame `kind` portant ce message
+    pub fn to_frame(&self, kind: &str) -> Value {
+        message_frame(kind, json!(self))
+    }
+}
+
+/// Trame de message à la version courante
+pub fn message_frame(kind: &str, data: Value) -> Value {
+    json!({
+        "type": kind,
+        "schemaVersion": CURRENT_SCHEMA_VERSION,
+        "data": data
+    })
+}
+
+// ================================================================
+// COMPATIBILITÉ
+// ================================================================
+
+fn strip_newer_fields(object: &mut Map<String, Value>, version: u16) {
+    object.retain(|field, _| field_version(field) <= version);
+}
+
+/// Trame ramenée à une version antérieure
+///
+/// Les champs plus récents sont retirés de `data` et des messages listés dans
+/// `data` (`messages`, `pinned`...). Une trame sans `schemaVersion` n'est pas
+/// une trame de message : elle est rendue telle quelle.
+pub fn downgrade(frame: &Value, version: u16) -> Value {
+    let mut frame = frame.clone();
+    let Some(frame_version) = frame.get("schemaVersion").and_then(Value::as_u64) else {
+        return frame;
+    };
+    let version = negotiated_version(version);
+    if u64::from(version) >= frame_version {
+        return frame;
+    }
+
+    frame["schemaVersion"] = json!(version);
+    if let Some(data) = frame.get_mut("data").and_then(Value::as_object_mut) {
+        strip_newer_fields(data, version);
+        for value in data.values_mut() {
+            if let Some(messages) = value.as_array_mut() {
+                for message in messages.iter_mut().filter_map(Value::as_object_mut) {
+                    strip_newer_fields(message, version);
+                }
+            }
+        }
+    }
+    frame
+}
+
+/// Trame sérialisée une fois, rendue à la demande pour les versions antérieures
+#[derive(Debug, Clone)]
+pub struct VersionedFrame {
+    frame: Value,
+    text: String,
+}
+
+impl VersionedFrame {
+    pub fn new(frame: Value) -> Self {
+        let text = frame.to_string();
+        Self { frame, text }
+    }
+
+    pub fn text_for(&self, version: u16) -> Cow<'_, str> {
+        if version >= CURRENT_SCHEMA_VERSION || self.frame.get("schemaVersion").is_none() {
+            Cow::Borrowed(&self.text)
+        } else {
+            Cow::Owned(downgrade(&self.frame, version).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room_frame() -> Value {
+        MessagePayload::new(10, 7, "alice", "bonjour @bob", Utc::now())
+            .in_room(1)
+            .with_parent(Some(9))
+            .with_full_length(Some(12_000))
+            .with_quote(Some(json!({ "messageId": 3 })))
+            .with_audience(json!([{ "username": "bob" }]), Some(vec![7, 8]))
+            .to_frame("room_message")
+    }
+
+    #[test]
+    fn test_current_version_gets_full_payload() {
+        let frame = room_frame();
+        assert_eq!(frame["schemaVersion"], CURRENT_SCHEMA_VERSION);
+        assert_eq!(downgrade(&frame, CURRENT_SCHEMA_VERSION), frame);
+
+        let data = &frame["data"];
+        assert_eq!(data["roomId"], 1);
+        assert_eq!(data["isThread"], true);
+        assert_eq!(data["fullLength"], 12_000);
+        assert_eq!(data["restricted"], true);
+        assert_eq!(data["visibleTo"], json!([7, 8]));
+        assert!(data.get("conversationId").is_none());
+    }
+
+    #[test]
+    fn test_old_version_gets_trimmed_payload() {
+        let v1 = downgrade(&room_frame(), 1);
+        assert_eq!(v1["schemaVersion"], 1);
+        let data = v1["data"].as_object().unwrap();
+        for field in ["isLong", "fullLength", "quote", "mentions", "restricted", "visibleTo"] {
+            assert!(!data.contains_key(field), "{} devrait être retiré", field);
+        }
+        assert_eq!(data["content"], "bonjour @bob");
+        assert_eq!(data["parentMessageId"], 9);
+
+        let v2 = downgrade(&room_frame(), 2);
+        assert_eq!(v2["data"]["isLong"], true);
+        assert!(v2["data"].get("mentions").is_none());
+    }
+
+    #[test]
+    fn test_history_rows_are_trimmed() {
+        let frame = message_frame("room_history", json!({
+            "roomId": 1,
+            "pinnedMessageIds": [4],
+            "messages": [{ "id": 4, "content": "x", "pin_order": 1, "mention_count": 0 }]
+        }));
+
+        let v1 = downgrade(&frame, 1);
+        assert!(v1["data"].get("pinnedMessageIds").is_none());
+        assert_eq!(v1["data"]["messages"][0], json!({ "id": 4, "content": "x" }));
+        assert_eq!(downgrade(&frame, 2)["data"]["messages"][0]["pin_order"], 1);
+    }
+
+    #[test]
+    fn test_other_frames_are_untouched() {
+        let frame = VersionedFrame::new(json!({ "type": "member_joined", "data": { "mentions": 1 } }));
+        assert_eq!(frame.text_for(1), frame.text_for(CURRENT_SCHEMA_VERSION));
+        assert_eq!(negotiated_version(0), MIN_SCHEMA_VERSION);
+        assert_eq!(negotiated_version(99), CURRENT_SCHEMA_VERSION);
+    }
+}
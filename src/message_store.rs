@@ -1,6 +1,19 @@
+use crate::config::{LimitsConfig, ModerationConfig, ServerConfig};
 use crate::error::{ChatError, Result};
-use chrono::{DateTime, Utc};
+use crate::hub::channels::is_moderator_role;
+use crate::pagination::Page;
+use crate::permissions::{check_message_action, MessageAction, Role};
+use crate::room_id::RoomId;
+use crate::hub::mentions::{dedup_mention_ids, DEFAULT_MAX_MENTIONS_PER_MESSAGE};
+use crate::hub::held_messages::held_clause;
+use crate::hub::message_policy::{MessagePolicy, ModificationContext, RoomMessagePolicy};
+use crate::hub::visibility::visibility_clause;
+use crate::validation::{attachment_file_ids, normalize_username};
+use crate::utils::{excerpt_preview, resolve_timezone, DEFAULT_LIST_PREVIEW_LENGTH};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
 use sqlx::PgPool;
 use std::collections::HashMap;
 
@@ -32,7 +45,7 @@ pub struct Message {
     pub author_username: String,
     
     // Pour les messages de salon
-    pub room_id: Option<String>,
+    pub room_id: Option<RoomId>,
     
     // Pour les messages directs
     pub recipient_id: Option<i32>,
@@ -76,14 +89,79 @@ pub struct MessageAttachment {
     pub uploaded_at: DateTime<Utc>,
 }
 
+/// Message visé par une édition ou une suppression
+struct StoredTarget {
+    author_id: i32,
+    room_id: Option<RoomId>,
+    content: String,
+    created_at: DateTime<Utc>,
+    reaction_count: i64,
+    reply_count: i64,
+    encrypted: bool,
+    overrides: RoomMessagePolicy,
+}
+
 /// Gestionnaire de stockage de messages séparé
 pub struct MessageStore {
     db: PgPool,
+    stats_timezone: Tz,
+    max_mentions: usize,
+    moderation: ModerationConfig,
+    message_policy: MessagePolicy,
+    preview_length: usize,
 }
 
 impl MessageStore {
     pub fn new(db: PgPool) -> Self {
-        Self { db }
+        Self {
+            db,
+            stats_timezone: Tz::UTC,
+            max_mentions: DEFAULT_MAX_MENTIONS_PER_MESSAGE,
+            moderation: ModerationConfig::default(),
+            message_policy: MessagePolicy::from_limits(&LimitsConfig::default()),
+            preview_length: DEFAULT_LIST_PREVIEW_LENGTH,
+        }
+    }
+
+    /// Magasin réglé par la configuration du serveur : fuseau des statistiques
+    /// (`server.timezone`), limites et droits de modération
+    pub fn from_config(db: PgPool, config: &ServerConfig) -> Self {
+        Self::new(db)
+            .with_stats_timezone(resolve_timezone(Some(&config.server.timezone)))
+            .with_max_mentions(config.limits.max_mentions_per_message)
+            .with_preview_length(config.limits.list_preview_length)
+            .with_moderation(config.moderation.clone())
+            .with_message_policy(&config.limits)
+    }
+
+    /// Définit le fuseau utilisé pour les bornes des statistiques (UTC par défaut)
+    pub fn with_stats_timezone(mut self, timezone: Tz) -> Self {
+        self.stats_timezone = timezone;
+        self
+    }
+
+    /// Nombre maximum d'utilisateurs mentionnés par message (`limits.max_mentions_per_message`)
+    pub fn with_max_mentions(mut self, max_mentions: usize) -> Self {
+        self.max_mentions = max_mentions;
+        self
+    }
+
+    /// Longueur des aperçus de `get_dm_conversations` (`limits.list_preview_length`)
+    pub fn with_preview_length(mut self, preview_length: usize) -> Self {
+        self.preview_length = preview_length.max(1);
+        self
+    }
+
+    /// Droits des rangs de modération sur les messages d'autrui (`[moderation]`)
+    pub fn with_moderation(mut self, moderation: ModerationConfig) -> Self {
+        self.moderation = moderation;
+        self
+    }
+
+    /// Fenêtres d'édition/suppression et verrous de l'auteur (`[limits]`)
+    pub fn with_message_policy(mut self, limits: &LimitsConfig) -> Self {
+        self.message_policy = MessagePolicy::from_limits(limits);
+        self
     }
 
     // ================================================
@@ -93,13 +171,17 @@ impl MessageStore {
     /// Envoyer un message dans un salon
     pub async fn send_room_message(
         &self,
-        room_id: &str,
+        room_id: &RoomId,
         author_id: i32,
         author_username: &str,
         content: &str,
         parent_message_id: Option<i64>,
         mentions: Vec<i32>,
     ) -> Result<Message> {
+        // Une liste trop longue (ex. @everyone développé) est refusée avant toute écriture
+        let mentions = dedup_mention_ids(mentions, self.max_mentions)?;
+        // Nom dénormalisé sur le message : jamais affiché tel que reçu
+        let author_username: &str = &normalize_username(author_username)?;
         let now = Utc::now();
         
         let message_id = sqlx::query_scalar!(
@@ -115,7 +197,7 @@ impl MessageStore {
             content,
             author_id,
             author_username,
-            room_id,
+            room_id.as_str(),
             now,
             "sent" as _,
             parent_message_id
@@ -154,11 +236,11 @@ impl MessageStore {
     /// Récupérer l'historique d'un salon avec pagination
     pub async fn get_room_history(
         &self,
-        room_id: &str,
+        room_id: &RoomId,
         limit: i64,
         before_id: Option<i64>,
         include_threads: bool,
-    ) -> Result<Vec<Message>> {
+    ) -> Result<Page<Message>> {
         let mut query = r#"
             SELECT m.*, 
                    COALESCE(array_agg(mm.user_id) FILTER (WHERE mm.user_id IS NOT NULL), ARRAY[]::int[]) as mention_ids
@@ -178,7 +260,8 @@ impl MessageStore {
         }
 
         query.push_str(" GROUP BY m.id ORDER BY m.created_at DESC");
-        query.push_str(&format!(" LIMIT {}", limit));
+        // Un message de plus que la limite signale une page plus ancienne
+        query.push_str(&format!(" LIMIT {}", limit + 1));
 
         let rows = sqlx::query(&query)
             .bind(room_id)
@@ -186,20 +269,15 @@ impl MessageStore {
             .await
             .map_err(ChatError::Database)?;
 
-        let mut messages = Vec::new();
-        for row in rows {
-            let message = self.row_to_message(row).await?;
-            messages.push(message);
-        }
-
-        Ok(messages)
+        let messages = self.rows_to_messages(rows).await?;
+        Ok(Page::from_overfetch(messages, limit, |message| message.id))
     }
 
     /// Épingler/désépingler un message dans un salon
     pub async fn pin_room_message(
         &self,
         message_id: i64,
-        room_id: &str,
+        room_id: &RoomId,
         moderator_id: i32,
         is_pinned: bool,
     ) -> Result<()> {
@@ -207,7 +285,7 @@ impl MessageStore {
         let exists = sqlx::query_scalar!(
             "SELECT EXISTS(SELECT 1 FROM messages WHERE id = $1 AND room_id = $2)",
             message_id,
-            room_id
+            room_id.as_str()
         )
         .fetch_one(&self.db)
         .await
@@ -222,7 +300,7 @@ impl MessageStore {
         if is_pinned {
             let pinned_count = sqlx::query_scalar!(
                 "SELECT COUNT(*) FROM messages WHERE room_id = $1 AND is_pinned = true",
-                room_id
+                room_id.as_str()
             )
             .fetch_one(&self.db)
             .await
@@ -265,7 +343,7 @@ impl MessageStore {
     }
 
     /// Récupérer les messages épinglés d'un salon
-    pub async fn get_pinned_messages(&self, room_id: &str) -> Result<Vec<Message>> {
+    pub async fn get_pinned_messages(&self, room_id: &RoomId) -> Result<Vec<Message>> {
         let rows = sqlx::query!(
             r#"
             SELECT m.*, 
@@ -278,19 +356,20 @@ impl MessageStore {
             GROUP BY m.id
             ORDER BY m.created_at DESC
             "#,
-            room_id
+            room_id.as_str()
         )
         .fetch_all(&self.db)
         .await
         .map_err(ChatError::Database)?;
 
-        let mut messages = Vec::new();
-        for row in rows {
-            let message = self.row_to_message_from_detailed_query(row).await?;
-            messages.push(message);
-        }
+        let message_ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+        let file_ids: Vec<Vec<i64>> = rows.iter().map(|row| message_file_ids(row.metadata.as_ref())).collect();
+        let details = self.page_details(&message_ids, &file_ids).await?;
 
-        Ok(messages)
+        Ok(rows.into_iter()
+            .zip(details)
+            .map(|(row, (reactions, attachments))| self.row_to_message_from_detailed_query(row, reactions, attachments))
+            .collect())
     }
 
     // ================================================
@@ -313,6 +392,8 @@ impl MessageStore {
             return Err(ChatError::PermissionDenied("Utilisateur bloqué".to_string()));
         }
 
+        let author_username: &str = &normalize_username(author_username)?;
+        let recipient_username: &str = &normalize_username(recipient_username)?;
         let now = Utc::now();
         
         let message_id = sqlx::query_scalar!(
@@ -359,7 +440,7 @@ impl MessageStore {
         user2_id: i32,
         limit: i64,
         before_id: Option<i64>,
-    ) -> Result<Vec<Message>> {
+    ) -> Result<Page<Message>> {
         let mut query = r#"
             SELECT m.*,
                    ARRAY[]::int[] as mention_ids
@@ -377,7 +458,8 @@ impl MessageStore {
         }
 
         query.push_str(" ORDER BY m.created_at DESC");
-        query.push_str(&format!(" LIMIT {}", limit));
+        // Un message de plus que la limite signale une page plus ancienne
+        query.push_str(&format!(" LIMIT {}", limit + 1));
 
         let rows = sqlx::query(&query)
             .bind(user1_id)
@@ -386,13 +468,8 @@ impl MessageStore {
             .await
             .map_err(ChatError::Database)?;
 
-        let mut messages = Vec::new();
-        for row in rows {
-            let message = self.row_to_message(row).await?;
-            messages.push(message);
-        }
-
-        Ok(messages)
+        let messages = self.rows_to_messages(rows).await?;
+        Ok(Page::from_overfetch(messages, limit, |message| message.id))
     }
 
     /// Marquer un message DM comme lu
@@ -425,34 +502,53 @@ impl MessageStore {
     }
 
     /// Récupérer les conversations DM d'un utilisateur
-    pub async fn get_dm_conversations(&self, user_id: i32) -> Result<Vec<DMConversation>> {
+    ///
+    /// L'aperçu du dernier message tient sur une ligne et est tronqué à
+    /// `preview_length` graphèmes ; la base n'en renvoie que le début, et
+    /// signale s'il a été coupé. Triées par dernier message (date qui change) :
+    /// pagination par décalage (`offset`).
+    pub async fn get_dm_conversations(&self, user_id: i32, limit: i64, offset: i64) -> Result<Page<DMConversation>> {
         let rows = sqlx::query!(
             r#"
-            SELECT 
-                CASE 
-                    WHEN author_id = $1 THEN recipient_id 
-                    ELSE author_id 
-                END as other_user_id,
-                CASE 
-                    WHEN author_id = $1 THEN recipient_username 
-                    ELSE author_username 
-                END as other_username,
-                MAX(created_at) as last_message_at,
-                COUNT(*) FILTER (WHERE recipient_id = $1 AND status != 'read') as unread_count,
-                (SELECT content FROM messages m2 
-                 WHERE m2.message_type = 'direct_message' 
-                   AND ((m2.author_id = $1 AND m2.recipient_id = other_user_id) OR 
-                        (m2.author_id = other_user_id AND m2.recipient_id = $1))
-                   AND m2.status != 'deleted'
-                 ORDER BY m2.created_at DESC LIMIT 1) as last_message_content
-            FROM messages
-            WHERE message_type = 'direct_message'
-              AND (author_id = $1 OR recipient_id = $1)
-              AND status != 'deleted'
-            GROUP BY other_user_id, other_username
-            ORDER BY last_message_at DESC
+            WITH conversations AS (
+                SELECT 
+                    CASE 
+                        WHEN author_id = $1 THEN recipient_id 
+                        ELSE author_id 
+                    END as other_user_id,
+                    CASE 
+                        WHEN author_id = $1 THEN recipient_username 
+                        ELSE author_username 
+                    END as other_username,
+                    MAX(created_at) as last_message_at,
+                    COUNT(*) FILTER (WHERE recipient_id = $1 AND status != 'read') as unread_count
+                FROM messages
+                WHERE message_type = 'direct_message'
+                  AND (author_id = $1 OR recipient_id = $1)
+                  AND status != 'deleted'
+                GROUP BY other_user_id, other_username
+            )
+            SELECT c.other_user_id, c.other_username, c.last_message_at, c.unread_count,
+                   last.content as "last_message_content?",
+                   last.truncated as "last_message_truncated?"
+            FROM conversations c
+            LEFT JOIN LATERAL (
+                SELECT LEFT(m2.content, $2) as content, char_length(m2.content) > $2 as truncated
+                FROM messages m2 
+                WHERE m2.message_type = 'direct_message' 
+                  AND ((m2.author_id = $1 AND m2.recipient_id = c.other_user_id) OR 
+                       (m2.author_id = c.other_user_id AND m2.recipient_id = $1))
+                  AND m2.status != 'deleted'
+                ORDER BY m2.created_at DESC LIMIT 1
+            ) last ON TRUE
+            ORDER BY c.last_message_at DESC, c.other_user_id
+            LIMIT $3 OFFSET $4
             "#,
-            user_id
+            user_id,
+            preview_source_length(self.preview_length),
+            // Un élément de plus que la limite signale une page suivante
+            limit + 1,
+            offset
         )
         .fetch_all(&self.db)
         .await
@@ -465,11 +561,13 @@ impl MessageStore {
                 other_username: row.other_username,
                 last_message_at: row.last_message_at,
                 unread_count: row.unread_count.unwrap_or(0) as u32,
-                last_message_preview: row.last_message_content,
+                last_message_preview: row.last_message_content.map(|content: String| {
+                    excerpt_preview(&content, self.preview_length, row.last_message_truncated.unwrap_or(false))
+                }),
             });
         }
 
-        Ok(conversations)
+        Ok(Page::from_offset(conversations, limit, offset))
     }
 
     // ================================================
@@ -571,35 +669,198 @@ impl MessageStore {
         Ok(reactions)
     }
 
+    /// Récupérer les réactions d'une liste de messages en une seule requête
+    ///
+    /// Les messages sans réaction sont absents de la table retournée.
+    pub async fn get_reactions_for_messages(&self, message_ids: &[i64]) -> Result<HashMap<i64, HashMap<String, Vec<i32>>>> {
+        if message_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query!(
+            "SELECT message_id, emoji, user_id FROM message_reactions WHERE message_id = ANY($1) ORDER BY created_at",
+            message_ids
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(ChatError::Database)?;
+
+        Ok(group_reactions(rows.into_iter().map(|row| (row.message_id, row.emoji, row.user_id))))
+    }
+
+    /// Pièces jointes de plusieurs messages en une requête (fichier → pièce jointe)
+    pub async fn get_attachments_for_files(&self, file_ids: &[i64]) -> Result<HashMap<i64, MessageAttachment>> {
+        if file_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, filename, original_filename, mime_type, file_size, file_path, created_at
+            FROM files
+            WHERE id = ANY($1)
+            "#,
+            file_ids
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(ChatError::Database)?;
+
+        Ok(rows.into_iter()
+            .map(|row| (row.id, MessageAttachment {
+                id: row.id,
+                filename: row.filename,
+                original_filename: row.original_filename,
+                mime_type: row.mime_type,
+                size_bytes: row.file_size,
+                url: row.file_path,
+                thumbnail_url: None,
+                uploaded_at: row.created_at,
+            }))
+            .collect())
+    }
+
+    /// Réactions et pièces jointes de chaque message d'une page, dans l'ordre de la page
+    ///
+    /// Une requête pour les réactions et une pour les pièces jointes, quelle que
+    /// soit la taille de la page (aucune pour une page vide ou sans fichier).
+    async fn page_details(&self, message_ids: &[i64], file_ids: &[Vec<i64>]) -> Result<Vec<(HashMap<String, Vec<i32>>, Vec<MessageAttachment>)>> {
+        let mut reactions = self.get_reactions_for_messages(message_ids).await?;
+        let attachments = self.get_attachments_for_files(&file_ids.concat()).await?;
+
+        Ok(message_ids.iter()
+            .zip(file_ids)
+            .map(|(message_id, files)| (
+                reactions.remove(message_id).unwrap_or_default(),
+                files.iter().filter_map(|file_id| attachments.get(file_id).cloned()).collect(),
+            ))
+            .collect())
+    }
+
     // ================================================
     // ÉDITION ET SUPPRESSION
     // ================================================
     
-    /// Éditer un message
+    /// L'utilisateur est-il modérateur du salon (rôle du membre dans le salon) ?
+    async fn is_room_moderator(&self, room_id: Option<&RoomId>, user_id: i32) -> Result<bool> {
+        let Some(room_id) = room_id else {
+            return Ok(false);
+        };
+
+        let role: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT cm.role FROM conversation_members cm
+            JOIN conversations c ON c.id = cm.conversation_id
+            WHERE c.name = $1 AND cm.user_id = $2 AND cm.left_at IS NULL
+            "#
+        )
+        .bind(room_id)
+        .bind(user_id as i64)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("check_room_moderator", e))?;
+
+        Ok(role.as_deref().is_some_and(is_moderator_role))
+    }
+
+    /// Charge le message à modifier avec ce qu'il faut pour appliquer `MessagePolicy`
+    async fn load_modification_target(&self, message_id: i64) -> Result<StoredTarget> {
+        use sqlx::Row;
+
+        let row = sqlx::query(
+            r#"
+            SELECT m.author_id, m.room_id, m.content, m.created_at,
+                   COALESCE(m.thread_count, 0) as thread_count,
+                   (SELECT COUNT(*) FROM message_reactions r WHERE r.message_id = m.id) as reaction_count,
+                   (m.encryption_key_id IS NOT NULL OR COALESCE(c.encrypt_at_rest, false)) as encrypted,
+                   c.edit_window_seconds, c.delete_window_seconds,
+                   c.edit_lock_after_reactions, c.edit_lock_after_replies
+            FROM messages m
+            LEFT JOIN conversations c ON c.name = m.room_id
+            WHERE m.id = $1 AND m.status != 'deleted'
+            "#
+        )
+        .bind(message_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ChatError::from_sqlx_error("load_message", e))?
+        .ok_or_else(|| ChatError::not_found("message", &message_id.to_string()))?;
+
+        let room_id: Option<String> = row.get("room_id");
+        Ok(StoredTarget {
+            author_id: row.get("author_id"),
+            room_id: room_id.as_deref().map(RoomId::new).transpose()?,
+            content: row.get("content"),
+            created_at: row.get("created_at"),
+            reaction_count: row.get("reaction_count"),
+            reply_count: row.get::<i32, _>("thread_count") as i64,
+            encrypted: row.get("encrypted"),
+            overrides: RoomMessagePolicy {
+                edit_window_seconds: row.get("edit_window_seconds"),
+                delete_window_seconds: row.get("delete_window_seconds"),
+                edit_lock_after_reactions: row.get("edit_lock_after_reactions"),
+                edit_lock_after_replies: row.get("edit_lock_after_replies"),
+            },
+        })
+    }
+
+    /// Vérifie le droit d'éditer ou de supprimer un message
+    ///
+    /// Le rang décide d'abord (voir `permissions::check_message_action`) ; l'auteur
+    /// reste ensuite soumis aux fenêtres et verrous de `MessagePolicy`, comme
+    /// dans `channels::edit_room_message` / `delete_room_message`.
+    async fn check_message_action(
+        &self,
+        action: MessageAction,
+        target: &StoredTarget,
+        user_id: i32,
+        user_role: &Role,
+    ) -> Result<()> {
+        let is_author = target.author_id == user_id;
+        let is_moderator = self.is_room_moderator(target.room_id.as_ref(), user_id).await?;
+
+        check_message_action(&self.moderation, user_role, action, is_author, is_moderator)?;
+        if !is_author {
+            return Ok(());
+        }
+
+        let policy = self.message_policy.clone().with_room_overrides(&target.overrides);
+        let ctx = ModificationContext {
+            is_author,
+            is_moderator,
+            created_at: target.created_at,
+            reaction_count: target.reaction_count,
+            reply_count: target.reply_count,
+        };
+        match action {
+            MessageAction::Edit => policy.check_edit(&ctx, Utc::now()),
+            MessageAction::Delete => policy.check_delete(&ctx, Utc::now()),
+        }
+    }
+
+    /// Éditer un message (auteur dans sa fenêtre d'édition, ou rang de modération autorisé dans ce salon)
+    ///
+    /// Sans accès aux clés, ce chemin refuse les messages scellés et ceux des
+    /// salons chiffrés au repos (voir `channels::edit_room_message`).
     pub async fn edit_message(
         &self,
         message_id: i64,
         user_id: i32,
+        user_role: &Role,
         new_content: &str,
     ) -> Result<Message> {
-        // Vérifier que l'utilisateur est l'auteur
-        let message = sqlx::query!(
-            "SELECT author_id, content FROM messages WHERE id = $1 AND status != 'deleted'",
-            message_id
-        )
-        .fetch_optional(&self.db)
-        .await
-        .map_err(ChatError::Database)?;
-
-        let message = message.ok_or_else(|| ChatError::configuration_error("Message non trouvé".to_string()))?;
-
-        if message.author_id != user_id {
-            return Err(ChatError::PermissionDenied("Seul l'auteur peut éditer ce message".to_string()));
+        let target = self.load_modification_target(message_id).await?;
+        if target.encrypted {
+            return Err(ChatError::EditForbidden {
+                reason: "message d'un salon chiffré au repos".to_string(),
+            });
         }
 
+        self.check_message_action(MessageAction::Edit, &target, user_id, user_role).await?;
+
         // Sauvegarder l'ancien contenu si c'est la première édition
-        let original_content = if message.content != new_content {
-            Some(message.content)
+        let original_content = if target.content != new_content {
+            Some(target.content)
         } else {
             None
         };
@@ -626,28 +887,18 @@ impl MessageStore {
     }
 
     /// Supprimer un message (soft delete)
+    ///
+    /// Auteur dans sa fenêtre de suppression, ou rang de modération dont la
+    /// portée couvre le salon du message.
     pub async fn delete_message(
         &self,
         message_id: i64,
         user_id: i32,
-        is_moderator: bool,
+        user_role: &Role,
     ) -> Result<()> {
-        // Vérifier les permissions
-        if !is_moderator {
-            let is_author = sqlx::query_scalar!(
-                "SELECT EXISTS(SELECT 1 FROM messages WHERE id = $1 AND author_id = $2)",
-                message_id,
-                user_id
-            )
-            .fetch_one(&self.db)
-            .await
-            .map_err(ChatError::Database)?
-            .unwrap_or(false);
+        let target = self.load_modification_target(message_id).await?;
 
-            if !is_author {
-                return Err(ChatError::PermissionDenied("Seul l'auteur ou un modérateur peut supprimer ce message".to_string()));
-            }
-        }
+        self.check_message_action(MessageAction::Delete, &target, user_id, user_role).await?;
 
         sqlx::query!(
             "UPDATE messages SET status = 'deleted', updated_at = $1 WHERE id = $2",
@@ -666,68 +917,37 @@ impl MessageStore {
     // ================================================
     
     /// Rechercher dans les messages
+    ///
+    /// Seuls les salons publics ou dont l'utilisateur est membre sont cherchés,
+    /// et les messages restreints uniquement par leurs destinataires et leur auteur.
+    /// Les salons archivés sont exclus sauf si `include_archived` est demandé.
+    /// Les salons chiffrés au repos et les messages scellés ne sont jamais cherchés.
+    /// Résultats du plus récent au plus ancien ; `before_id` : `next_cursor` de la page précédente.
     pub async fn search_messages(
         &self,
         query: &str,
         user_id: i32,
-        room_id: Option<&str>,
+        room_id: Option<&RoomId>,
+        include_archived: bool,
         limit: i64,
-    ) -> Result<Vec<Message>> {
-        let search_query = if let Some(room_id) = room_id {
-            // Recherche dans un salon spécifique
-            r#"
-            SELECT m.*, ARRAY[]::int[] as mention_ids
-            FROM messages m
-            WHERE m.room_id = $1
-              AND m.message_type = 'room_message'
-              AND m.status != 'deleted'
-              AND m.content ILIKE $2
-            ORDER BY m.created_at DESC
-            LIMIT $3
-            "#
-        } else {
-            // Recherche dans tous les messages accessibles à l'utilisateur
-            r#"
-            SELECT m.*, ARRAY[]::int[] as mention_ids
-            FROM messages m
-            WHERE m.status != 'deleted'
-              AND m.content ILIKE $2
-              AND (
-                  m.message_type = 'room_message' OR
-                  (m.message_type = 'direct_message' AND (m.author_id = $1 OR m.recipient_id = $1))
-              )
-            ORDER BY m.created_at DESC
-            LIMIT $3
-            "#
-        };
-
+        before_id: Option<i64>,
+    ) -> Result<Page<Message>> {
+        let search_query = search_query(room_id.is_some());
         let search_pattern = format!("%{}%", query);
         
-        let rows = if room_id.is_some() {
-            sqlx::query(search_query)
-                .bind(room_id.unwrap())
-                .bind(&search_pattern)
-                .bind(limit)
-                .fetch_all(&self.db)
-                .await
-                .map_err(ChatError::Database)?
-        } else {
-            sqlx::query(search_query)
-                .bind(user_id)
-                .bind(&search_pattern)
-                .bind(limit)
-                .fetch_all(&self.db)
-                .await
-                .map_err(ChatError::Database)?
-        };
-
-        let mut messages = Vec::new();
-        for row in rows {
-            let message = self.row_to_message(row).await?;
-            messages.push(message);
-        }
+        let rows = sqlx::query(&search_query)
+            .bind(user_id)
+            .bind(&search_pattern)
+            .bind(limit + 1)
+            .bind(include_archived)
+            .bind(before_id)
+            .bind(room_id)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("search_messages", e))?;
 
-        Ok(messages)
+        let messages = self.rows_to_messages(rows).await?;
+        Ok(Page::from_overfetch(messages, limit, |message| message.id))
     }
 
     // ================================================
@@ -750,17 +970,40 @@ impl MessageStore {
         .await
         .map_err(ChatError::Database)?;
 
-        self.row_to_message_from_detailed_query(row).await
+        let file_ids = vec![message_file_ids(row.metadata.as_ref())];
+        let (reactions, attachments) = self.page_details(&[message_id], &file_ids).await?
+            .pop()
+            .unwrap_or_default();
+        Ok(self.row_to_message_from_detailed_query(row, reactions, attachments))
     }
 
-    async fn row_to_message(&self, row: sqlx::Row) -> Result<Message> {
+    /// Convertit une page de résultats, réactions et pièces jointes chargées en bloc
+    ///
+    /// Les mentions viennent de la requête de la page (`mention_ids`) ; aucune
+    /// requête supplémentaire n'est faite par ligne (voir `page_details`).
+    async fn rows_to_messages(&self, rows: Vec<sqlx::postgres::PgRow>) -> Result<Vec<Message>> {
+        use sqlx::Row;
+
+        let message_ids = rows.iter()
+            .map(|row| row.try_get::<i64, _>("id"))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| ChatError::from_sqlx_error("database_operation", e))?;
+        let file_ids: Vec<Vec<i64>> = rows.iter()
+            .map(|row| message_file_ids(row.try_get::<Option<Value>, _>("metadata").ok().flatten().as_ref()))
+            .collect();
+        let details = self.page_details(&message_ids, &file_ids).await?;
+
+        rows.into_iter()
+            .zip(details)
+            .map(|(row, (reactions, attachments))| self.row_to_message(row, reactions, attachments))
+            .collect()
+    }
+
+    fn row_to_message(&self, row: sqlx::Row, reactions: HashMap<String, Vec<i32>>, attachments: Vec<MessageAttachment>) -> Result<Message> {
         use sqlx::Row;
         
         let message_id: i64 = row.try_get("id").map_err(|e| ChatError::from_sqlx_error("database_operation", e.into()))?;
         
-        // Récupérer les réactions
-        let reactions = self.get_message_reactions(message_id).await?;
-        
         // Récupérer les mentions (si disponibles)
         let mentions: Vec<i32> = row.try_get("mention_ids")
             .unwrap_or_else(|_| Vec::new());
@@ -795,22 +1038,19 @@ impl MessageStore {
             parent_message_id: row.try_get("parent_message_id").ok(),
             thread_count: row.try_get("thread_count").unwrap_or(0),
             reactions,
-            attachments: Vec::new(), // TODO: récupérer les attachments
+            attachments,
             mentions,
             is_flagged: row.try_get("is_flagged").unwrap_or(false),
             moderation_notes: row.try_get("moderation_notes").ok(),
         })
     }
 
-    async fn row_to_message_from_detailed_query(&self, row: sqlx::postgres::PgRow) -> Result<Message> {
+    fn row_to_message_from_detailed_query(&self, row: sqlx::postgres::PgRow, reactions: HashMap<String, Vec<i32>>, attachments: Vec<MessageAttachment>) -> Message {
         let message_id = row.id;
         
-        // Récupérer les réactions
-        let reactions = self.get_message_reactions(message_id).await?;
-        
         let mentions: Vec<i32> = row.mention_ids.unwrap_or_else(Vec::new);
 
-        Ok(Message {
+        Message {
             id: message_id,
             message_type: match row.message_type.as_str() {
                 "room_message" => MessageType::RoomMessage,
@@ -840,11 +1080,11 @@ impl MessageStore {
             parent_message_id: row.parent_message_id,
             thread_count: row.thread_count.unwrap_or(0),
             reactions,
-            attachments: Vec::new(), // TODO: récupérer les attachments
+            attachments,
             mentions,
             is_flagged: row.is_flagged.unwrap_or(false),
             moderation_notes: row.moderation_notes,
-        })
+        }
     }
 
     async fn is_user_blocked(&self, user1_id: i32, user2_id: i32) -> Result<bool> {
@@ -862,6 +1102,14 @@ impl MessageStore {
     }
 }
 
+/// Caractères lus en base pour un aperçu de `preview_length` graphèmes
+///
+/// Un graphème compte rarement plus de quatre points de code (émojis
+/// composés) ; au-delà, l'aperçu est plus court mais toujours marqué coupé.
+fn preview_source_length(preview_length: usize) -> i32 {
+    preview_length.saturating_mul(4).saturating_add(1).min(i32::MAX as usize) as i32
+}
+
 /// Représentation d'une conversation DM
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DMConversation {
@@ -887,6 +1135,9 @@ pub struct MessageStats {
 impl MessageStore {
     /// Obtenir les statistiques de messages
     pub async fn get_message_stats(&self) -> Result<MessageStats> {
+        // Jours comptés dans le fuseau de l'opérateur
+        let timezone = self.stats_timezone.name();
+
         let total_messages = sqlx::query_scalar!(
             "SELECT COUNT(*) FROM messages WHERE status != 'deleted'"
         )
@@ -912,7 +1163,10 @@ impl MessageStore {
         .unwrap_or(0);
 
         let messages_today = sqlx::query_scalar!(
-            "SELECT COUNT(*) FROM messages WHERE created_at >= CURRENT_DATE AND status != 'deleted'"
+            "SELECT COUNT(*) FROM messages
+             WHERE created_at >= date_trunc('day', NOW() AT TIME ZONE $1) AT TIME ZONE $1
+               AND status != 'deleted'",
+            timezone
         )
         .fetch_one(&self.db)
         .await
@@ -920,7 +1174,10 @@ impl MessageStore {
         .unwrap_or(0);
 
         let messages_this_week = sqlx::query_scalar!(
-            "SELECT COUNT(*) FROM messages WHERE created_at >= CURRENT_DATE - INTERVAL '7 days' AND status != 'deleted'"
+            "SELECT COUNT(*) FROM messages
+             WHERE created_at >= (date_trunc('day', NOW() AT TIME ZONE $1) - INTERVAL '7 days') AT TIME ZONE $1
+               AND status != 'deleted'",
+            timezone
         )
         .fetch_one(&self.db)
         .await
@@ -954,11 +1211,12 @@ impl MessageStore {
             SELECT author_id, author_username, COUNT(*) as message_count
             FROM messages 
             WHERE status != 'deleted'
-              AND created_at >= CURRENT_DATE - INTERVAL '30 days'
+              AND created_at >= (date_trunc('day', NOW() AT TIME ZONE $1) - INTERVAL '30 days') AT TIME ZONE $1
             GROUP BY author_id, author_username
             ORDER BY message_count DESC
             LIMIT 10
-            "#
+            "#,
+            timezone
         )
         .fetch_all(&self.db)
         .await
@@ -978,4 +1236,231 @@ impl MessageStore {
             active_users,
         })
     }
-} 
\ No newline at end of file
+} 
+
+/// Fichiers joints à un message (`metadata.attachments`)
+///
+/// Une métadonnée illisible donne un message sans pièce jointe plutôt
+/// qu'une page en échec.
+fn message_file_ids(metadata: Option<&Value>) -> Vec<i64> {
+    attachment_file_ids(metadata).unwrap_or_default()
+}
+
+/// Requête de recherche : `$1` utilisateur, `$2` motif, `$3` limite,
+/// `$4` archives incluses, `$5` curseur, `$6` salon (si `in_room`)
+///
+/// Un message de salon n'est trouvé que dans un salon public ou dont
+/// l'utilisateur est membre actif, s'il lui est visible et n'est pas retenu.
+pub(crate) fn search_query(in_room: bool) -> String {
+    let scope = if in_room {
+        "m.room_id = $6 AND m.message_type = 'room_message'"
+    } else {
+        "(m.message_type = 'room_message' OR
+              (m.message_type = 'direct_message' AND (m.author_id = $1 OR m.recipient_id = $1)))"
+    };
+    format!(r#"
+        SELECT m.*, ARRAY[]::int[] as mention_ids
+        FROM messages m
+        WHERE {scope}
+          AND m.status != 'deleted'
+          AND m.content ILIKE $2
+          AND (m.message_type != 'room_message' OR EXISTS (
+              SELECT 1 FROM conversations c
+              LEFT JOIN conversation_members cm
+                ON cm.conversation_id = c.id AND cm.user_id = $1 AND cm.left_at IS NULL
+              WHERE c.name = m.room_id AND (c.is_public OR cm.user_id IS NOT NULL)
+          ))
+          AND {visible} AND {held}
+          AND m.encryption_key_id IS NULL
+          AND NOT m.is_e2ee
+          AND NOT EXISTS (
+              SELECT 1 FROM conversations c WHERE c.name = m.room_id AND c.encrypt_at_rest
+          )
+          AND ($4 OR m.room_id IS NULL OR NOT EXISTS (
+              SELECT 1 FROM conversations c WHERE c.name = m.room_id AND c.is_archived
+          ))
+          AND ($5::bigint IS NULL OR m.id < $5)
+        ORDER BY m.id DESC
+        LIMIT $3
+    "#, scope = scope, visible = visibility_clause(1), held = held_clause(1))
+}
+
+/// Regroupe des lignes (message, emoji, utilisateur) par message puis par emoji
+pub fn group_reactions(rows: impl IntoIterator<Item = (i64, String, i32)>) -> HashMap<i64, HashMap<String, Vec<i32>>> {
+    let mut grouped: HashMap<i64, HashMap<String, Vec<i32>>> = HashMap::new();
+    for (message_id, emoji, user_id) in rows {
+        grouped.entry(message_id).or_default().entry(emoji).or_default().push(user_id);
+    }
+    grouped
+}
+
+/// Bornes UTC de "aujourd'hui" et "cette semaine" dans le fuseau de l'opérateur
+///
+/// Mêmes bornes que les requêtes de `get_message_stats`
+/// (`date_trunc('day', NOW() AT TIME ZONE tz) AT TIME ZONE tz`). La semaine
+/// commence sept jours locaux plus tôt, changement d'heure compris.
+pub fn stats_boundaries(now: DateTime<Utc>, timezone: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    let today = now.with_timezone(&timezone).date_naive();
+    (local_day_start(today, timezone), local_day_start(today - Duration::days(7), timezone))
+}
+
+/// Premier instant du jour local `date`
+///
+/// Minuit ambigu (retour à l'heure d'hiver) : sa première occurrence. Minuit
+/// inexistant (passage à l'heure d'été) : le premier instant valide qui suit.
+fn local_day_start(date: NaiveDate, timezone: Tz) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("minuit est toujours valide");
+    (0..=24 * 4)
+        .map(|quarter| midnight + Duration::minutes(15 * quarter))
+        .find_map(|local| timezone.from_local_datetime(&local).earliest())
+        .expect("un jour local compte au moins un instant valide")
+        .with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_boundaries_default_utc() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 15, 2, 30, 0).unwrap();
+        let (today, week) = stats_boundaries(now, Tz::UTC);
+        assert_eq!(today, Utc.with_ymd_and_hms(2024, 3, 15, 0, 0, 0).unwrap());
+        assert_eq!(week, Utc.with_ymd_and_hms(2024, 3, 8, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_stats_boundaries_shift_with_timezone() {
+        // 02:30 UTC le 15 = 22:30 le 14 à New York (UTC-4 en heure d'été)
+        let now = Utc.with_ymd_and_hms(2024, 3, 15, 2, 30, 0).unwrap();
+        let (today, _) = stats_boundaries(now, chrono_tz::America::New_York);
+        assert_eq!(today, Utc.with_ymd_and_hms(2024, 3, 14, 4, 0, 0).unwrap());
+
+        // À l'est de Greenwich, minuit local tombe la veille en UTC
+        let (today, _) = stats_boundaries(now, chrono_tz::Asia::Tokyo);
+        assert_eq!(today, Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_stats_boundaries_across_dst_changes() {
+        // Santiago, 8 septembre 2024 : minuit n'existe pas (00:00 → 01:00, UTC-4 → UTC-3)
+        let now = Utc.with_ymd_and_hms(2024, 9, 8, 12, 0, 0).unwrap();
+        let (today, week) = stats_boundaries(now, chrono_tz::America::Santiago);
+        assert_eq!(today, Utc.with_ymd_and_hms(2024, 9, 8, 4, 0, 0).unwrap());
+        assert_eq!(week, Utc.with_ymd_and_hms(2024, 9, 1, 4, 0, 0).unwrap());
+
+        // La Havane, 3 novembre 2024 : minuit survient deux fois (01:00 → 00:00)
+        let now = Utc.with_ymd_and_hms(2024, 11, 3, 12, 0, 0).unwrap();
+        let (today, _) = stats_boundaries(now, chrono_tz::America::Havana);
+        assert_eq!(today, Utc.with_ymd_and_hms(2024, 11, 3, 4, 0, 0).unwrap());
+
+        // Semaine à cheval sur le passage à l'heure d'été : sept jours locaux, pas 168 heures
+        let now = Utc.with_ymd_and_hms(2024, 3, 12, 12, 0, 0).unwrap();
+        let (today, week) = stats_boundaries(now, chrono_tz::America::New_York);
+        assert_eq!(today, Utc.with_ymd_and_hms(2024, 3, 12, 4, 0, 0).unwrap());
+        assert_eq!(week, Utc.with_ymd_and_hms(2024, 3, 5, 5, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_group_reactions_by_message_and_emoji() {
+        let grouped = group_reactions(vec![
+            (1, "👍".to_string(), 10),
+            (2, "🎉".to_string(), 10),
+            (1, "👍".to_string(), 11),
+            (1, "❤️".to_string(), 12),
+        ]);
+
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[&1]["👍"], vec![10, 11]);
+        assert_eq!(grouped[&1]["❤️"], vec![12]);
+        assert_eq!(grouped[&2]["🎉"], vec![10]);
+        // Message sans réaction : absent de la table
+        assert!(!grouped.contains_key(&3));
+    }
+
+    #[test]
+    fn test_message_file_ids_tolerate_bad_metadata() {
+        let metadata = serde_json::json!({ "attachments": [{ "id": 7, "sizeBytes": 10 }, { "id": 9 }] });
+        assert_eq!(message_file_ids(Some(&metadata)), vec![7, 9]);
+
+        assert!(message_file_ids(None).is_empty());
+        assert!(message_file_ids(Some(&serde_json::json!({}))).is_empty());
+        assert!(message_file_ids(Some(&serde_json::json!({ "attachments": "x" }))).is_empty());
+    }
+
+    /// Compte les requêtes journalisées par sqlx (cible `sqlx::query`)
+    struct QueryCounter(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for QueryCounter {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+            if event.metadata().target() == "sqlx::query" {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "nécessite PostgreSQL (VEZA_TEST_DATABASE_URL)"]
+    async fn test_room_history_query_count_is_constant() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let url = std::env::var("VEZA_TEST_DATABASE_URL").expect("VEZA_TEST_DATABASE_URL");
+        let db = PgPool::connect(&url).await.unwrap();
+        let store = MessageStore::new(db.clone());
+        let suffix = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let room = RoomId::new(&format!("history-{}", suffix)).unwrap();
+
+        let author_id: i64 = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password_hash) VALUES ($1, $2, 'x') RETURNING id"
+        )
+        .bind(format!("hist_{}", suffix))
+        .bind(format!("hist_{}@example.test", suffix))
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let author_id = author_id as i32;
+
+        // Chaque message porte une réaction et une pièce jointe
+        for i in 0..50 {
+            let message = store.send_room_message(&room, author_id, &format!("hist_{}", suffix), &format!("message {}", i), None, Vec::new()).await.unwrap();
+            store.add_reaction(message.id, author_id, "👍").await.unwrap();
+            let file_id: i64 = sqlx::query_scalar(
+                r#"
+                INSERT INTO files (uploaded_by, filename, original_filename, file_path, file_size, mime_type, checksum)
+                VALUES ($1, $2, $2, $3, 1, 'text/plain', '')
+                RETURNING id
+                "#
+            )
+            .bind(author_id as i64)
+            .bind(format!("piece-{}.txt", i))
+            .bind(format!("attachments/{}/{}", author_id, i))
+            .fetch_one(&db)
+            .await
+            .unwrap();
+            sqlx::query("UPDATE messages SET metadata = $2 WHERE id = $1")
+                .bind(message.id)
+                .bind(serde_json::json!({ "attachments": [{ "id": file_id }] }))
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+
+        // Connexion ouverte et requêtes préparées avant de compter
+        store.get_room_history(&room, 1, None, false).await.unwrap();
+
+        for limit in [1, 10, 50] {
+            let queries = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let subscriber = tracing_subscriber::registry().with(QueryCounter(queries.clone()));
+            let page = {
+                let _guard = tracing::subscriber::set_default(subscriber);
+                store.get_room_history(&room, limit, None, false).await.unwrap()
+            };
+
+            // Page, réactions, pièces jointes
+            assert_eq!(queries.load(std::sync::atomic::Ordering::SeqCst), 3, "page de {}", limit);
+            assert_eq!(page.items.len(), limit as usize);
+            assert!(page.items.iter().all(|message| message.reactions["👍"] == vec![author_id]));
+            assert!(page.items.iter().all(|message| message.attachments.len() == 1));
+        }
+    }
+}
@@ -1,18 +1,19 @@
 //file: backend/modules/chat_server/src/messages.rs
 
 use serde::Deserialize;
+use crate::room_id::RoomId;
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 pub enum WsInbound {
     #[serde(rename = "join_room")]
     Join {
-        room: String,
+        room: RoomId,
     },
 
     #[serde(rename = "room_message")]
     Message {
-        room: String,
+        room: RoomId,
         content: String,
     },
 
@@ -24,7 +25,7 @@ pub enum WsInbound {
 
     #[serde(rename = "room_history")]
     RoomHistory {
-        room: String,
+        room: RoomId,
         limit: i64,
     },
 
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
+use crate::close_codes::CloseReason;
+use crate::hub::reputation::{record_sanction, refresh_reputation};
 use crate::error::{ChatError, Result};
 use crate::hub::common::ChatHub;
 use crate::permissions::Role;
@@ -15,6 +17,18 @@ pub enum SanctionType {
     PermaBan,
 }
 
+impl SanctionType {
+    /// Fermeture imposée aux connexions de l'utilisateur sanctionné, le cas échéant
+    pub fn close_reason(&self, duration: Option<Duration>) -> Option<CloseReason> {
+        match self {
+            SanctionType::Kick => Some(CloseReason::Kicked),
+            SanctionType::TempBan => Some(CloseReason::Banned { retry_after_secs: duration.map(|d| d.as_secs()) }),
+            SanctionType::PermaBan => Some(CloseReason::Banned { retry_after_secs: None }),
+            SanctionType::Warning | SanctionType::Mute => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum SanctionReason {
     Spam,
@@ -125,6 +139,11 @@ impl ModerationSystem {
             expires_at,
         ).await?;
 
+        // Sanction déjà enregistrée : un échec de la réputation ne l'empêche pas de s'appliquer
+        if let Err(e) = record_sanction(&self.hub, target_user_id as i64, &sanction_type).await {
+            tracing::error!(target_user_id = %target_user_id, error = %e, "❌ Réputation non mise à jour après la sanction");
+        }
+
         // Appliquer les effets de la sanction
         self.enforce_sanction(target_user_id, &sanction_type, duration).await?;
 
@@ -274,8 +293,8 @@ impl ModerationSystem {
             });
         }
 
-        // Calculer un score de réputation basique
-        let reputation_score = 100 - (warning_count as i32 * 5) - (mute_count as i32 * 15) - (ban_count as i32 * 50);
+        // Score de réputation selon la formule `[reputation]`
+        let reputation_score = refresh_reputation(&self.hub, user_id as i64).await?.score;
 
         // Obtenir le nom d'utilisateur
         let username = self.get_username(user_id).await?;
@@ -365,14 +384,14 @@ impl ModerationSystem {
     fn check_moderator_permissions(&self, role: &Role, sanction: &SanctionType) -> Result<()> {
         match sanction {
             SanctionType::Warning | SanctionType::Mute => {
-                if matches!(role, Role::Admin | Role::Moderator) {
+                if matches!(role, Role::Admin | Role::SeniorModerator | Role::Moderator) {
                     Ok(())
                 } else {
                     Err(ChatError::unauthorized_simple("unauthorized_action"))
                 }
             },
             SanctionType::Kick | SanctionType::TempBan => {
-                if matches!(role, Role::Admin | Role::Moderator) {
+                if matches!(role, Role::Admin | Role::SeniorModerator | Role::Moderator) {
                     Ok(())
                 } else {
                     Err(ChatError::unauthorized_simple("unauthorized_action"))
@@ -431,8 +450,12 @@ impl ModerationSystem {
         Ok(row.get(0))
     }
 
-    async fn enforce_sanction(&self, user_id: i32, sanction_type: &SanctionType, _duration: Option<Duration>) -> Result<()> {
-        // Ici on appliquerait les effets réels (déconnecter, bloquer messages, etc.)
+    async fn enforce_sanction(&self, user_id: i32, sanction_type: &SanctionType, duration: Option<Duration>) -> Result<()> {
+        // Exclusion et bannissement : connexions fermées avec le code correspondant
+        if let Some(reason) = sanction_type.close_reason(duration) {
+            let closed = self.hub.disconnect_user(user_id, reason, None).await;
+            tracing::info!(user_id = %user_id, closed_connections = %closed, reason = %reason, "🔌 Connexions fermées par sanction");
+        }
         tracing::info!(user_id = %user_id, sanction_type = ?sanction_type, "⚖️ Sanction appliquée");
         Ok(())
     }
@@ -456,4 +479,21 @@ impl ModerationSystem {
             _ => None,
         }
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanction_close_codes() {
+        assert_eq!(SanctionType::Kick.close_reason(None).map(|r| r.code()), Some(4002));
+        assert_eq!(
+            SanctionType::TempBan.close_reason(Some(Duration::from_secs(3600))),
+            Some(CloseReason::Banned { retry_after_secs: Some(3600) })
+        );
+        assert_eq!(SanctionType::PermaBan.close_reason(None), Some(CloseReason::Banned { retry_after_secs: None }));
+        assert_eq!(SanctionType::Mute.close_reason(None), None);
+        assert_eq!(SanctionType::Warning.close_reason(None), None);
+    }
+}
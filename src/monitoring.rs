@@ -1,9 +1,15 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
-use crate::error::Result;
+use crate::config::{MetricsBackend, MetricsConfig};
+use crate::error::{ChatError, Result};
+use crate::sharded_map::LockWaitSnapshot;
+use crate::db_pool::{pool_exhausted_total, PoolStats};
 
 /// Métrique individuelle avec historique
 #[derive(Debug, Clone, Serialize)]
@@ -57,9 +63,14 @@ impl MetricsCollector {
 
     /// Incrémente un compteur
     pub async fn increment_counter(&self, name: &str, labels: HashMap<String, String>) {
+        self.add_to_counter(name, 1, labels).await;
+    }
+
+    /// Ajoute `delta` à un compteur
+    pub async fn add_to_counter(&self, name: &str, delta: u64, labels: HashMap<String, String>) {
         let key = self.create_key(name, &labels);
         let mut counters = self.counters.write().await;
-        *counters.entry(key.clone()).or_insert(0) += 1;
+        *counters.entry(key.clone()).or_insert(0) += delta;
         
         self.record_metric(name, counters.get(&key).unwrap_or(&0).clone() as f64, labels).await;
         
@@ -190,25 +201,357 @@ impl MetricsCollector {
     }
 }
 
+// ================================================================
+// PUITS DE MÉTRIQUES
+// ================================================================
+
+/// Destination des métriques émises par `ChatMetrics`
+///
+/// Découple l'instrumentation du format d'exposition : Prometheus (texte
+/// exposé par le serveur), StatsD (poussé en UDP) ou aucun.
+pub trait MetricsSink: Send + Sync {
+    /// Incrémente un compteur
+    fn counter(&self, name: &str, delta: u64, labels: &HashMap<String, String>);
+
+    /// Fixe la valeur d'une jauge
+    fn gauge(&self, name: &str, value: f64, labels: &HashMap<String, String>);
+
+    /// Ajoute une observation à un histogramme
+    fn histogram(&self, name: &str, value: f64, labels: &HashMap<String, String>);
+
+    /// Exposition textuelle, pour les puits interrogés (Prometheus)
+    fn render(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Puits par défaut : les métriques ne quittent pas le collecteur interne
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopSink;
+
+impl MetricsSink for NoopSink {
+    fn counter(&self, _name: &str, _delta: u64, _labels: &HashMap<String, String>) {}
+    fn gauge(&self, _name: &str, _value: f64, _labels: &HashMap<String, String>) {}
+    fn histogram(&self, _name: &str, _value: f64, _labels: &HashMap<String, String>) {}
+}
+
+/// Série Prometheus : labels triés pour une clé stable
+type SeriesKey = (String, BTreeMap<String, String>);
+
+fn series_key(name: &str, labels: &HashMap<String, String>) -> SeriesKey {
+    (name.to_string(), labels.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+}
+
+/// Échappe une valeur de label au format d'exposition Prometheus
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_series(name: &str, suffix: &str, labels: &BTreeMap<String, String>) -> String {
+    if labels.is_empty() {
+        return format!("{}{}", name, suffix);
+    }
+
+    let labels: Vec<String> = labels.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+        .collect();
+    format!("{}{}{{{}}}", name, suffix, labels.join(","))
+}
+
+#[derive(Debug, Default)]
+struct PrometheusState {
+    counters: BTreeMap<SeriesKey, u64>,
+    gauges: BTreeMap<SeriesKey, f64>,
+    /// (nombre d'observations, somme)
+    summaries: BTreeMap<SeriesKey, (u64, f64)>,
+}
+
+/// Agrège les métriques en mémoire et les expose au format texte Prometheus
+#[derive(Debug, Default)]
+pub struct PrometheusSink {
+    prefix: String,
+    state: Mutex<PrometheusState>,
+}
+
+impl PrometheusSink {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            state: Mutex::new(PrometheusState::default()),
+        }
+    }
+
+    fn metric_name(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", self.prefix, name)
+        }
+    }
+}
+
+impl MetricsSink for PrometheusSink {
+    fn counter(&self, name: &str, delta: u64, labels: &HashMap<String, String>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        *state.counters.entry(series_key(name, labels)).or_insert(0) += delta;
+    }
+
+    fn gauge(&self, name: &str, value: f64, labels: &HashMap<String, String>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.gauges.insert(series_key(name, labels), value);
+    }
+
+    fn histogram(&self, name: &str, value: f64, labels: &HashMap<String, String>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let summary = state.summaries.entry(series_key(name, labels)).or_insert((0, 0.0));
+        summary.0 += 1;
+        summary.1 += value;
+    }
+
+    fn render(&self) -> Option<String> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut output = String::new();
+        let mut declared = None;
+
+        // Les séries d'une même métrique sont contiguës (clés triées par nom)
+        let mut declare = |output: &mut String, name: &str, kind: &str| {
+            if declared.as_deref() != Some(name) {
+                let _ = writeln!(output, "# TYPE {} {}", name, kind);
+                declared = Some(name.to_string());
+            }
+        };
+
+        for ((name, labels), value) in &state.counters {
+            let name = self.metric_name(name);
+            declare(&mut output, &name, "counter");
+            let _ = writeln!(output, "{} {}", format_series(&name, "", labels), value);
+        }
+        for ((name, labels), value) in &state.gauges {
+            let name = self.metric_name(name);
+            declare(&mut output, &name, "gauge");
+            let _ = writeln!(output, "{} {}", format_series(&name, "", labels), value);
+        }
+        for ((name, labels), (count, sum)) in &state.summaries {
+            let name = self.metric_name(name);
+            declare(&mut output, &name, "summary");
+            let _ = writeln!(output, "{} {}", format_series(&name, "_count", labels), count);
+            let _ = writeln!(output, "{} {}", format_series(&name, "_sum", labels), sum);
+        }
+
+        Some(output)
+    }
+}
+
+/// Ligne StatsD avec tags au format DogStatsD (`nom:valeur|type|#clé:valeur`)
+pub fn format_statsd_line(prefix: &str, name: &str, value: f64, kind: &str, labels: &HashMap<String, String>) -> String {
+    let mut line = if prefix.is_empty() {
+        format!("{}:{}|{}", name, value, kind)
+    } else {
+        format!("{}.{}:{}|{}", prefix, name, value, kind)
+    };
+
+    if !labels.is_empty() {
+        let mut tags: Vec<String> = labels.iter()
+            .map(|(k, v)| format!("{}:{}", k, v.replace(['|', ',', '#', ':'], "_")))
+            .collect();
+        tags.sort();
+        line.push_str("|#");
+        line.push_str(&tags.join(","));
+    }
+
+    line
+}
+
+/// Pousse chaque métrique en UDP vers un agent StatsD
+///
+/// Envoi non bloquant et sans garantie : une métrique perdue ne doit jamais
+/// ralentir l'envoi d'un message.
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl StatsdSink {
+    pub fn connect(addr: std::net::SocketAddr, prefix: &str) -> Result<Self> {
+        let bind_addr = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(bind_addr)
+            .and_then(|socket| socket.connect(addr).map(|_| socket))
+            .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+            .map_err(|e| ChatError::configuration_error(&format!("Socket StatsD indisponible ({}): {}", addr, e)))?;
+
+        Ok(Self { socket, prefix: prefix.to_string() })
+    }
+
+    fn send(&self, name: &str, value: f64, kind: &str, labels: &HashMap<String, String>) {
+        let line = format_statsd_line(&self.prefix, name, value, kind, labels);
+        if let Err(e) = self.socket.send(line.as_bytes()) {
+            tracing::debug!(metric_name = %name, error = %e, "⚠️ Métrique StatsD non envoyée");
+        }
+    }
+}
+
+impl MetricsSink for StatsdSink {
+    fn counter(&self, name: &str, delta: u64, labels: &HashMap<String, String>) {
+        self.send(name, delta as f64, "c", labels);
+    }
+
+    fn gauge(&self, name: &str, value: f64, labels: &HashMap<String, String>) {
+        self.send(name, value, "g", labels);
+    }
+
+    fn histogram(&self, name: &str, value: f64, labels: &HashMap<String, String>) {
+        self.send(name, value, "h", labels);
+    }
+}
+
+/// Étiquettes réservées au collecteur interne (API JSON)
+///
+/// Une valeur par utilisateur créerait une série par utilisateur dans
+/// Prometheus ou StatsD : cardinalité non bornée.
+const COLLECTOR_ONLY_LABELS: &[&str] = &["user_id"];
+
+/// Étiquettes transmises au puits, sans celles propres à un utilisateur
+fn sink_labels(labels: &HashMap<String, String>) -> HashMap<String, String> {
+    labels.iter()
+        .filter(|(key, _)| !COLLECTOR_ONLY_LABELS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Métrique capturée par `RecordingSink`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMetric {
+    pub kind: MetricType,
+    pub name: String,
+    pub value: f64,
+    pub labels: HashMap<String, String>,
+}
+
+/// Puits en mémoire qui conserve chaque émission, pour les tests
+#[derive(Debug, Default)]
+pub struct RecordingSink {
+    records: Mutex<Vec<RecordedMetric>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Toutes les émissions, dans l'ordre
+    pub fn records(&self) -> Vec<RecordedMetric> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Émissions d'une métrique donnée
+    pub fn named(&self, name: &str) -> Vec<RecordedMetric> {
+        self.records().into_iter().filter(|record| record.name == name).collect()
+    }
+
+    fn push(&self, kind: MetricType, name: &str, value: f64, labels: &HashMap<String, String>) {
+        self.records.lock().unwrap_or_else(|e| e.into_inner()).push(RecordedMetric {
+            kind,
+            name: name.to_string(),
+            value,
+            labels: labels.clone(),
+        });
+    }
+}
+
+impl MetricsSink for RecordingSink {
+    fn counter(&self, name: &str, delta: u64, labels: &HashMap<String, String>) {
+        self.push(MetricType::Counter, name, delta as f64, labels);
+    }
+
+    fn gauge(&self, name: &str, value: f64, labels: &HashMap<String, String>) {
+        self.push(MetricType::Gauge, name, value, labels);
+    }
+
+    fn histogram(&self, name: &str, value: f64, labels: &HashMap<String, String>) {
+        self.push(MetricType::Histogram, name, value, labels);
+    }
+}
+
+/// Construit le puits choisi dans la section `[metrics]`
+pub fn sink_from_config(config: &MetricsConfig) -> Result<Arc<dyn MetricsSink>> {
+    Ok(match config.backend {
+        MetricsBackend::None => Arc::new(NoopSink),
+        MetricsBackend::Prometheus => Arc::new(PrometheusSink::new(&config.prefix)),
+        MetricsBackend::Statsd => Arc::new(StatsdSink::connect(config.statsd_addr, &config.prefix)?),
+    })
+}
+
+// ================================================================
+// MÉTRIQUES DU CHAT
+// ================================================================
+
 /// Métriques spécifiques au chat
+///
+/// Chaque émission passe par le collecteur interne (API JSON) et par le puits
+/// configuré.
 pub struct ChatMetrics {
     collector: MetricsCollector,
+    sink: Arc<dyn MetricsSink>,
+    /// Attentes de connexion expirées déjà exportées (`db_pool_exhausted_total`)
+    pool_exhausted_exported: AtomicU64,
 }
 
 impl ChatMetrics {
     pub fn new() -> Self {
+        Self::with_sink(Arc::new(NoopSink))
+    }
+
+    pub fn with_sink(sink: Arc<dyn MetricsSink>) -> Self {
         Self {
             collector: MetricsCollector::new(Duration::from_secs(3600)), // 1 heure de rétention
+            sink,
+            pool_exhausted_exported: AtomicU64::new(0),
         }
     }
 
+    /// Puits choisi par la configuration ; sans effet si celui-ci est indisponible
+    pub fn from_config(config: &MetricsConfig) -> Self {
+        match sink_from_config(config) {
+            Ok(sink) => Self::with_sink(sink),
+            Err(e) => {
+                tracing::warn!(error = %e, "⚠️ Puits de métriques indisponible, métriques non exportées");
+                Self::new()
+            }
+        }
+    }
+
+    /// Exposition textuelle du puits (Prometheus), `None` pour un puits poussé
+    pub fn render(&self) -> Option<String> {
+        self.sink.render()
+    }
+
+    async fn count(&self, name: &str, labels: HashMap<String, String>) {
+        self.count_by(name, 1, labels).await;
+    }
+
+    async fn count_by(&self, name: &str, delta: u64, labels: HashMap<String, String>) {
+        self.sink.counter(name, delta, &sink_labels(&labels));
+        self.collector.add_to_counter(name, delta, labels).await;
+    }
+
+    async fn gauge(&self, name: &str, value: f64, labels: HashMap<String, String>) {
+        self.sink.gauge(name, value, &sink_labels(&labels));
+        self.collector.set_gauge(name, value, labels).await;
+    }
+
+    async fn observe(&self, name: &str, value: f64, labels: HashMap<String, String>) {
+        self.sink.histogram(name, value, &sink_labels(&labels));
+        self.collector.record_histogram(name, value, labels).await;
+    }
+
     /// Connexion WebSocket établie
     pub async fn websocket_connected(&self, user_id: i32) {
         let labels = HashMap::from([
             ("event".to_string(), "connection".to_string()),
             ("user_id".to_string(), user_id.to_string()),
         ]);
-        self.collector.increment_counter("websocket_connections_total", labels).await;
+        self.count("websocket_connections_total", labels).await;
     }
 
     /// Connexion WebSocket fermée
@@ -217,7 +560,7 @@ impl ChatMetrics {
             ("event".to_string(), "disconnection".to_string()),
             ("user_id".to_string(), user_id.to_string()),
         ]);
-        self.collector.increment_counter("websocket_disconnections_total", labels).await;
+        self.count("websocket_disconnections_total", labels).await;
     }
 
     /// Message envoyé (salon ou DM)
@@ -230,7 +573,7 @@ impl ChatMetrics {
             labels.insert("room".to_string(), room.to_string());
         }
         
-        self.collector.increment_counter("messages_sent_total", labels).await;
+        self.count("messages_sent_total", labels).await;
     }
 
     /// Erreur survenue
@@ -239,7 +582,7 @@ impl ChatMetrics {
             ("error_type".to_string(), error_type.to_string()),
             ("context".to_string(), context.to_string()),
         ]);
-        self.collector.increment_counter("errors_total", labels).await;
+        self.count("errors_total", labels).await;
     }
 
     /// Rate limit déclenché
@@ -247,19 +590,56 @@ impl ChatMetrics {
         let labels = HashMap::from([
             ("user_id".to_string(), user_id.to_string()),
         ]);
-        self.collector.increment_counter("rate_limits_triggered_total", labels).await;
+        self.count("rate_limits_triggered_total", labels).await;
+    }
+
+    /// Événement abandonné par le pont NATS (file pleine ou pont arrêté)
+    pub async fn bridge_event_dropped(&self, reason: &str) {
+        let labels = HashMap::from([
+            ("reason".to_string(), reason.to_string()),
+        ]);
+        self.count("bridge_events_dropped_total", labels).await;
     }
 
     /// Utilisateurs actifs
     pub async fn active_users(&self, count: u64) {
         let labels = HashMap::new();
-        self.collector.set_gauge("active_users", count as f64, labels).await;
+        self.gauge("active_users", count as f64, labels).await;
     }
 
     /// Salons actifs
     pub async fn active_rooms(&self, count: u64) {
         let labels = HashMap::new();
-        self.collector.set_gauge("active_rooms", count as f64, labels).await;
+        self.gauge("active_rooms", count as f64, labels).await;
+    }
+
+    /// Attente des verrous d'une table du hub (`clients`, `rooms`) depuis le dernier export
+    pub async fn lock_wait(&self, map: &str, waits: &LockWaitSnapshot) {
+        let labels = HashMap::from([
+            ("map".to_string(), map.to_string()),
+        ]);
+        self.observe("hub_lock_wait_seconds", waits.mean_wait().as_secs_f64(), labels.clone()).await;
+        self.gauge("hub_lock_wait_max_seconds", waits.max_wait.as_secs_f64(), labels.clone()).await;
+        self.gauge("hub_lock_acquisitions", waits.acquisitions as f64, labels.clone()).await;
+        self.gauge("hub_lock_contended", waits.contended as f64, labels).await;
+    }
+
+    /// Occupation du pool PostgreSQL ; `acquire_wait` absent si aucune connexion n'a été obtenue
+    ///
+    /// `db_pool_exhausted_total` reçoit les attentes expirées de toutes les
+    /// requêtes depuis l'export précédent, sonde comprise.
+    pub async fn db_pool(&self, stats: &PoolStats, acquire_wait: Option<Duration>) {
+        self.gauge("db_pool_connections_in_use", stats.in_use as f64, HashMap::new()).await;
+        self.gauge("db_pool_connections_idle", stats.idle as f64, HashMap::new()).await;
+        self.gauge("db_pool_connections_max", stats.max as f64, HashMap::new()).await;
+        if let Some(wait) = acquire_wait {
+            self.observe("db_pool_acquire_wait_seconds", wait.as_secs_f64(), HashMap::new()).await;
+        }
+        let total = pool_exhausted_total();
+        let exhausted = total.saturating_sub(self.pool_exhausted_exported.swap(total, Ordering::Relaxed));
+        if exhausted > 0 {
+            self.count_by("db_pool_exhausted_total", exhausted, HashMap::new()).await;
+        }
     }
 
     /// Temps de traitement d'un message
@@ -267,7 +647,7 @@ impl ChatMetrics {
         let labels = HashMap::from([
             ("message_type".to_string(), message_type.to_string()),
         ]);
-        self.collector.record_histogram("message_processing_duration_seconds", duration.as_secs_f64(), labels).await;
+        self.observe("message_processing_duration_seconds", duration.as_secs_f64(), labels).await;
     }
 
     /// Taille d'un message
@@ -275,7 +655,7 @@ impl ChatMetrics {
         let labels = HashMap::from([
             ("message_type".to_string(), message_type.to_string()),
         ]);
-        self.collector.record_histogram("message_size_bytes", size_bytes as f64, labels).await;
+        self.observe("message_size_bytes", size_bytes as f64, labels).await;
     }
 
     /// Obtient toutes les métriques pour l'API de monitoring
@@ -294,13 +674,20 @@ impl ChatMetrics {
             ("operation".to_string(), operation_type.to_string()),
         ]);
         
-        self.collector.time_operation("database_operation_duration_seconds", labels, future).await
+        self.time("database_operation_duration_seconds", labels, future).await
     }
 
     /// Mesure le temps d'authentification
     pub async fn time_auth_operation<T>(&self, future: impl std::future::Future<Output = T>) -> T {
         let labels = HashMap::new();
-        self.collector.time_operation("auth_operation_duration_seconds", labels, future).await
+        self.time("auth_operation_duration_seconds", labels, future).await
+    }
+
+    async fn time<T>(&self, name: &str, labels: HashMap<String, String>, future: impl std::future::Future<Output = T>) -> T {
+        let start = Instant::now();
+        let result = future.await;
+        self.observe(name, start.elapsed().as_secs_f64(), labels).await;
+        result
     }
 }
 
@@ -369,4 +756,81 @@ impl MetricsExport {
         
         output
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_prometheus_sink_renders_text_format() {
+        let sink = PrometheusSink::new("veza_chat");
+        sink.counter("messages_sent_total", 1, &labels(&[("type", "room")]));
+        sink.counter("messages_sent_total", 2, &labels(&[("type", "room")]));
+        sink.gauge("active_users", 3.0, &HashMap::new());
+        sink.histogram("message_size_bytes", 10.0, &labels(&[("message_type", "room")]));
+        sink.histogram("message_size_bytes", 30.0, &labels(&[("message_type", "room")]));
+
+        let text = sink.render().unwrap();
+        assert!(text.contains("# TYPE veza_chat_messages_sent_total counter\n"));
+        assert!(text.contains("veza_chat_messages_sent_total{type=\"room\"} 3\n"));
+        assert!(text.contains("veza_chat_active_users 3\n"));
+        assert!(text.contains("# TYPE veza_chat_message_size_bytes summary\n"));
+        assert!(text.contains("veza_chat_message_size_bytes_count{message_type=\"room\"} 2\n"));
+        assert!(text.contains("veza_chat_message_size_bytes_sum{message_type=\"room\"} 40\n"));
+        assert_eq!(text.matches("# TYPE veza_chat_messages_sent_total").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_per_user_events_share_one_exported_series() {
+        let sink = Arc::new(PrometheusSink::new("veza_chat"));
+        let metrics = ChatMetrics::with_sink(sink.clone());
+        for user_id in 1..=3 {
+            metrics.websocket_connected(user_id).await;
+            metrics.websocket_disconnected(user_id).await;
+            metrics.rate_limit_triggered(user_id).await;
+        }
+
+        let text = metrics.render().unwrap();
+        assert!(!text.contains("user_id"));
+        assert!(text.contains("veza_chat_websocket_connections_total{event=\"connection\"} 3\n"));
+        assert!(text.contains("veza_chat_websocket_disconnections_total{event=\"disconnection\"} 3\n"));
+        assert!(text.contains("veza_chat_rate_limits_triggered_total 3\n"));
+    }
+
+    #[test]
+    fn test_statsd_line_format() {
+        assert_eq!(
+            format_statsd_line("veza_chat", "messages_sent_total", 1.0, "c", &labels(&[("type", "room"), ("room", "a|b")])),
+            "veza_chat.messages_sent_total:1|c|#room:a_b,type:room"
+        );
+        assert_eq!(format_statsd_line("", "active_users", 2.5, "g", &HashMap::new()), "active_users:2.5|g");
+    }
+
+    #[test]
+    fn test_noop_is_the_default_backend() {
+        assert!(ChatMetrics::from_config(&MetricsConfig::default()).render().is_none());
+
+        let config = MetricsConfig { backend: MetricsBackend::Prometheus, ..Default::default() };
+        assert!(ChatMetrics::from_config(&config).render().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_query_pool_timeouts_are_exported_as_exhausted() {
+        let sink = Arc::new(RecordingSink::new());
+        let metrics = ChatMetrics::with_sink(sink.clone());
+        let stats = PoolStats { size: 2, idle: 1, in_use: 1, max: 2 };
+        metrics.db_pool(&stats, Some(Duration::from_millis(1))).await;
+        let exported = |sink: &RecordingSink| sink.named("db_pool_exhausted_total").iter().map(|m| m.value).sum::<f64>();
+        let before = exported(&sink);
+
+        // Deux requêtes sans connexion, sonde de maintenance réussie
+        ChatError::from_sqlx_error("get_room_members", sqlx::Error::PoolTimedOut);
+        ChatError::from_sqlx_error("insert_message", sqlx::Error::PoolTimedOut);
+        metrics.db_pool(&stats, Some(Duration::from_millis(1))).await;
+        assert!(exported(&sink) - before >= 2.0);
+    }
+}
@@ -0,0 +1,114 @@
+//! Stockage des fichiers servis aux clients
+//!
+//! Les fichiers (images d'émojis personnalisés) sont écrits sous une clé
+//! (`emojis/server/parrot-<uuid>.png`) et exposés par une URL publique ; la
+//! diffusion elle-même relève du proxy frontal. `LocalObjectStore` écrit sur
+//! disque, un autre backend (S3...) peut être branché via `ObjectStore`.
+
+use std::path::{Component, Path, PathBuf};
+use futures_util::future::BoxFuture;
+use crate::config::ObjectStoreConfig;
+use crate::error::{ChatError, Result};
+
+/// Backend de stockage
+pub trait ObjectStore: Send + Sync {
+    /// Écrit un objet et retourne son URL publique
+    fn put<'a>(&'a self, key: &'a str, bytes: &'a [u8], content_type: &'a str) -> BoxFuture<'a, Result<String>>;
+
+    /// Supprime un objet (absent = succès)
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Clé relative sans remontée de répertoire (`..`, chemin absolu)
+pub fn is_safe_key(key: &str) -> bool {
+    !key.is_empty() && Path::new(key).components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Objets écrits dans un répertoire local
+#[derive(Debug, Clone)]
+pub struct LocalObjectStore {
+    root_dir: PathBuf,
+    public_base_url: String,
+}
+
+impl LocalObjectStore {
+    pub fn new(root_dir: PathBuf, public_base_url: &str) -> Self {
+        Self { root_dir, public_base_url: public_base_url.trim_end_matches('/').to_string() }
+    }
+
+    pub fn from_config(config: &ObjectStoreConfig) -> Self {
+        Self::new(config.root_dir.clone(), &config.public_base_url)
+    }
+
+    /// URL publique d'une clé
+    pub fn public_url(&self, key: &str) -> String {
+        format!("{}/{}", self.public_base_url, key)
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf> {
+        if !is_safe_key(key) {
+            return Err(ChatError::InvalidFormat {
+                field: "key".to_string(),
+                reason: format!("Clé d'objet invalide: {}", key),
+            });
+        }
+        Ok(self.root_dir.join(key))
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn put<'a>(&'a self, key: &'a str, bytes: &'a [u8], _content_type: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let path = self.path_for(key)?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await
+                    .map_err(|e| ChatError::UploadError { reason: e.to_string() })?;
+            }
+            tokio::fs::write(&path, bytes).await
+                .map_err(|e| ChatError::UploadError { reason: e.to_string() })?;
+
+            tracing::debug!(key = %key, size = %bytes.len(), "💾 Objet écrit");
+            Ok(self.public_url(key))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path_for(key)?).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(ChatError::UploadError { reason: e.to_string() }),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_cannot_escape_root() {
+        assert!(is_safe_key("emojis/server/parrot.png"));
+        assert!(!is_safe_key("../etc/passwd"));
+        assert!(!is_safe_key("/etc/passwd"));
+        assert!(!is_safe_key("emojis/../../secret"));
+        assert!(!is_safe_key(""));
+    }
+
+    #[tokio::test]
+    async fn test_local_store_round_trip() {
+        let root = std::env::temp_dir().join(format!("veza-objects-{}", uuid::Uuid::new_v4()));
+        let store = LocalObjectStore::new(root.clone(), "https://cdn.veza.example/objects/");
+
+        let url = store.put("emojis/server/parrot.png", b"png", "image/png").await.unwrap();
+        assert_eq!(url, "https://cdn.veza.example/objects/emojis/server/parrot.png");
+        assert_eq!(std::fs::read(root.join("emojis/server/parrot.png")).unwrap(), b"png");
+
+        store.delete("emojis/server/parrot.png").await.unwrap();
+        store.delete("emojis/server/parrot.png").await.unwrap();
+        assert!(store.put("../parrot.png", b"png", "image/png").await.is_err());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,125 @@
+//! Pagination des listes
+//!
+//! Toute méthode qui retourne une liste paginée (historiques, recherche,
+//! conversations, réactions, annuaire et membres des salons, file de
+//! modération) retourne une `Page` : le client sait s'il reste
+//! des éléments et avec quel curseur demander la suite, sans deviner à partir
+//! de la taille de la page.
+//! - `has_more` : il reste au moins un élément après cette page
+//! - `next_cursor` : à repasser tel quel (`before_id`, ou décalage selon la
+//!   méthode) ; vide quand la position ne tient pas dans un entier (annuaire
+//!   des salons : `RoomCursor::next_page`)
+//! - `total` : renseigné seulement quand le décompte est bon marché
+
+use serde::Serialize;
+
+/// Page d'une liste
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub has_more: bool,
+    pub next_cursor: Option<i64>,
+    pub total: Option<i64>,
+}
+
+impl<T> Page<T> {
+    /// Page vide, sans suite
+    pub fn empty() -> Self {
+        Self { items: Vec::new(), has_more: false, next_cursor: None, total: None }
+    }
+
+    /// Page lue avec `limit + 1` éléments (pagination par clé)
+    ///
+    /// L'élément en surplus prouve qu'il existe une suite ; il est retiré et le
+    /// curseur est la clé du dernier élément conservé.
+    pub fn from_overfetch(mut items: Vec<T>, limit: i64, cursor: impl Fn(&T) -> i64) -> Self {
+        let limit = limit.max(0) as usize;
+        let has_more = items.len() > limit;
+        items.truncate(limit);
+        let next_cursor = if has_more { items.last().map(cursor) } else { None };
+        Self { items, has_more, next_cursor, total: None }
+    }
+
+    /// Page lue avec `limit + 1` éléments à partir de `offset` (pagination par décalage)
+    ///
+    /// Réservée aux listes sans clé d'ordre stable exploitable (tri sur une date
+    /// modifiable) : le curseur est le décalage de la page suivante.
+    pub fn from_offset(mut items: Vec<T>, limit: i64, offset: i64) -> Self {
+        let limit = limit.max(0);
+        let has_more = items.len() as i64 > limit;
+        items.truncate(limit as usize);
+        let next_cursor = has_more.then_some(offset + limit);
+        Self { items, has_more, next_cursor, total: None }
+    }
+
+    /// Ajoute le nombre total d'éléments de la liste
+    pub fn with_total(mut self, total: i64) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    /// Transforme les éléments en conservant les métadonnées de pagination
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            has_more: self.has_more,
+            next_cursor: self.next_cursor,
+            total: self.total,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overfetch_trims_surplus_and_sets_cursor() {
+        // Historique du plus récent au plus ancien : la suite part du plus ancien conservé
+        let page = Page::from_overfetch(vec![10, 9, 8, 7], 3, |id| *id);
+        assert_eq!(page.items, vec![10, 9, 8]);
+        assert!(page.has_more);
+        assert_eq!(page.next_cursor, Some(8));
+
+        let last = Page::from_overfetch(vec![7], 3, |id| *id);
+        assert!(!last.has_more);
+        assert!(last.next_cursor.is_none());
+
+        // Page pleine sans surplus : c'est la dernière
+        let exact = Page::from_overfetch(vec![3, 2, 1], 3, |id| *id);
+        assert!(!exact.has_more);
+        assert!(exact.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_offset_cursor_points_to_next_page() {
+        let page = Page::from_offset(vec!["a", "b", "c"], 2, 4);
+        assert_eq!(page.items, vec!["a", "b"]);
+        assert_eq!(page.next_cursor, Some(6));
+
+        let last = Page::from_offset(vec!["a"], 2, 6).with_total(7);
+        assert!(!last.has_more);
+        assert!(last.next_cursor.is_none());
+        assert_eq!(last.total, Some(7));
+    }
+
+    #[test]
+    fn test_serialized_fields_are_camel_case() {
+        let page = Page::from_overfetch(vec![2, 1], 1, |id| *id).map(|id| id * 10);
+        assert_eq!(serde_json::to_value(&page).unwrap(), serde_json::json!({
+            "items": [20],
+            "hasMore": true,
+            "nextCursor": 2,
+            "total": null
+        }));
+    }
+}
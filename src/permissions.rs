@@ -1,9 +1,13 @@
+use crate::config::{MessageModerationRights, ModerationConfig, ModerationScope};
 use crate::error::{ChatError, Result};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Role {
     Admin,
+    /// Modérateur senior
+    SeniorModerator,
+    /// Modérateur junior
     Moderator,
     User,
     Guest,
@@ -23,6 +27,7 @@ pub enum Permission {
     SendMessage,
     EditMessage,
     DeleteMessage,
+    EditAnyMessage,
     DeleteAnyMessage,
     
     // Messages directs
@@ -54,6 +59,7 @@ impl Role {
                 Permission::SendMessage,
                 Permission::EditMessage,
                 Permission::DeleteMessage,
+                Permission::EditAnyMessage,
                 Permission::DeleteAnyMessage,
                 Permission::SendDirectMessage,
                 Permission::ViewDirectMessageHistory,
@@ -65,6 +71,24 @@ impl Role {
                 Permission::ViewStats,
                 Permission::ConfigureServer,
             ],
+            Role::SeniorModerator => vec![
+                Permission::CreateRoom,
+                Permission::ModerateRoom,
+                Permission::JoinRoom,
+                Permission::LeaveRoom,
+                Permission::ViewRoomHistory,
+                Permission::SendMessage,
+                Permission::EditMessage,
+                Permission::DeleteMessage,
+                Permission::EditAnyMessage,
+                Permission::DeleteAnyMessage,
+                Permission::SendDirectMessage,
+                Permission::ViewDirectMessageHistory,
+                Permission::BanUser,
+                Permission::MuteUser,
+                Permission::KickUser,
+                Permission::ViewLogs,
+            ],
             Role::Moderator => vec![
                 Permission::CreateRoom,
                 Permission::ModerateRoom,
@@ -74,6 +98,7 @@ impl Role {
                 Permission::SendMessage,
                 Permission::EditMessage,
                 Permission::DeleteMessage,
+                Permission::EditAnyMessage,
                 Permission::DeleteAnyMessage,
                 Permission::SendDirectMessage,
                 Permission::ViewDirectMessageHistory,
@@ -103,15 +128,79 @@ impl Role {
         self.get_permissions().contains(permission)
     }
 
+    /// Droits du rang sur les messages d'autrui (section `[moderation]`)
+    pub fn message_moderation(&self, config: &ModerationConfig) -> MessageModerationRights {
+        match self {
+            Role::Admin => config.admin,
+            Role::SeniorModerator => config.senior_moderator,
+            Role::Moderator => config.moderator,
+            Role::User | Role::Guest => MessageModerationRights::default(),
+        }
+    }
+
     pub fn from_string(role_str: &str) -> Result<Self> {
         match role_str.to_lowercase().as_str() {
             "admin" => Ok(Role::Admin),
-            "moderator" | "mod" => Ok(Role::Moderator),
+            "senior_moderator" | "senior_mod" => Ok(Role::SeniorModerator),
+            "moderator" | "mod" | "junior_moderator" | "junior_mod" => Ok(Role::Moderator),
             "user" => Ok(Role::User),
             "guest" => Ok(Role::Guest),
             _ => Err(ChatError::configuration_error(&format!("Rôle invalide: {}", role_str))),
         }
     }
+
+    /// Rôle équivalent à un rôle de membre de salon (`conversation_members.role`)
+    pub fn from_room_role(member_role: &str) -> Self {
+        match member_role {
+            "owner" | "admin" => Role::Admin,
+            "moderator" => Role::Moderator,
+            _ => Role::User,
+        }
+    }
+
+    /// Rôle équivalent au rôle global d'un compte (`users.role`)
+    pub fn from_user_role(user_role: &str) -> Self {
+        match user_role {
+            "owner" | "admin" => Role::Admin,
+            "moderator" => Role::Moderator,
+            "banned" => Role::Guest,
+            _ => Role::User,
+        }
+    }
+
+    /// Rôle effectif d'un membre : le plus élevé de son rôle global et de son rôle dans le salon
+    pub fn effective(user_role: &str, member_role: &str) -> Self {
+        let global = Role::from_user_role(user_role);
+        let room = Role::from_room_role(member_role);
+        if global.rank() >= room.rank() { global } else { room }
+    }
+
+    /// Nom accepté par `from_string`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::SeniorModerator => "senior_moderator",
+            Role::Moderator => "moderator",
+            Role::User => "user",
+            Role::Guest => "guest",
+        }
+    }
+
+    /// Rang dans la hiérarchie, de l'invité (0) à l'administrateur
+    pub fn rank(&self) -> u8 {
+        match self {
+            Role::Guest => 0,
+            Role::User => 1,
+            Role::Moderator => 2,
+            Role::SeniorModerator => 3,
+            Role::Admin => 4,
+        }
+    }
+
+    /// Le rôle égale ou dépasse `other`
+    pub fn is_at_least(&self, other: &Role) -> bool {
+        self.rank() >= other.rank()
+    }
 }
 
 pub fn check_permission(user_role: &Role, required_permission: Permission) -> Result<()> {
@@ -120,4 +209,106 @@ pub fn check_permission(user_role: &Role, required_permission: Permission) -> Re
     } else {
         Err(ChatError::unauthorized_simple("unauthorized_action"))
     }
-} 
\ No newline at end of file
+}
+
+/// Action sur un message existant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageAction {
+    Edit,
+    Delete,
+}
+
+/// Vérifie qu'un utilisateur peut éditer ou supprimer un message
+///
+/// L'auteur agit sur ses propres messages selon les permissions de son rôle.
+/// Pour les messages d'autrui, la portée configurée pour son rang décide ;
+/// `assigned_to_room` indique qu'il est modérateur du salon du message.
+pub fn check_message_action(
+    config: &ModerationConfig,
+    user_role: &Role,
+    action: MessageAction,
+    is_author: bool,
+    assigned_to_room: bool,
+) -> Result<()> {
+    let (own, any) = match action {
+        MessageAction::Edit => (Permission::EditMessage, Permission::EditAnyMessage),
+        MessageAction::Delete => (Permission::DeleteMessage, Permission::DeleteAnyMessage),
+    };
+    if is_author {
+        return check_permission(user_role, own);
+    }
+    check_permission(user_role, any)?;
+
+    let rights = user_role.message_moderation(config);
+    let scope = match action {
+        MessageAction::Edit => rights.edit,
+        MessageAction::Delete => rights.delete,
+    };
+    let allowed = match scope {
+        ModerationScope::None => false,
+        ModerationScope::AssignedRooms => assigned_to_room,
+        ModerationScope::Everywhere => true,
+    };
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(ChatError::unauthorized_simple("unauthorized_action"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delete(role: &Role, is_author: bool, assigned_to_room: bool) -> bool {
+        check_message_action(&ModerationConfig::default(), role, MessageAction::Delete, is_author, assigned_to_room).is_ok()
+    }
+
+    #[test]
+    fn test_senior_moderator_and_admin_delete_anywhere() {
+        assert!(delete(&Role::SeniorModerator, false, false));
+        assert!(delete(&Role::Admin, false, false));
+        assert!(delete(&Role::Admin, false, true));
+    }
+
+    #[test]
+    fn test_author_and_plain_users() {
+        assert!(delete(&Role::User, true, false));
+        assert!(!delete(&Role::User, false, true));
+        // Un invité ne supprime même pas ses propres messages
+        assert!(!delete(&Role::Guest, true, false));
+    }
+
+    #[test]
+    fn test_edit_scope_is_configurable() {
+        let mut config = ModerationConfig::default();
+        let edit = |config: &ModerationConfig, role: &Role, assigned: bool| {
+            check_message_action(config, role, MessageAction::Edit, false, assigned).is_ok()
+        };
+
+        assert!(!edit(&config, &Role::Admin, true));
+
+        config.senior_moderator.edit = ModerationScope::AssignedRooms;
+        assert!(edit(&config, &Role::SeniorModerator, true));
+        assert!(!edit(&config, &Role::SeniorModerator, false));
+        assert!(!edit(&config, &Role::Moderator, true));
+    }
+
+    #[test]
+    fn test_effective_role_is_highest_of_global_and_room() {
+        assert_eq!(Role::effective("user", "moderator"), Role::Moderator);
+        assert_eq!(Role::effective("admin", "member"), Role::Admin);
+        assert_eq!(Role::effective("banned", "member"), Role::User);
+        assert!(Role::SeniorModerator.is_at_least(&Role::Moderator));
+        assert!(!Role::User.is_at_least(&Role::Moderator));
+    }
+
+    #[test]
+    fn test_role_names() {
+        assert_eq!(Role::from_string("senior_mod").unwrap(), Role::SeniorModerator);
+        assert_eq!(Role::from_string("junior_moderator").unwrap(), Role::Moderator);
+        assert_eq!(Role::from_string("mod").unwrap(), Role::Moderator);
+        assert_eq!(Role::from_string(Role::SeniorModerator.as_str()).unwrap(), Role::SeniorModerator);
+    }
+}
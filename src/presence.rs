@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use serde_json::json;
-use crate::error::Result;
+use crate::error::{ChatError, Result};
+use crate::room_id::RoomId;
+
+/// Utilisateurs et salons observés au plus par un abonné
+pub const MAX_PRESENCE_SUBSCRIPTIONS: usize = 500;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum UserStatus {
@@ -50,11 +54,141 @@ impl UserPresence {
             self.status = UserStatus::Away;
         }
     }
+
+    /// Présence telle que la voient les autres : un utilisateur invisible paraît hors ligne
+    pub fn public_view(&self) -> serde_json::Value {
+        let visible = self.status != UserStatus::Invisible;
+        json!({
+            "user_id": self.user_id,
+            "username": self.username,
+            "status": if visible { self.status.clone() } else { UserStatus::Offline },
+            "status_message": if visible { self.status_message.clone() } else { None },
+            "current_room": if visible { self.current_room.clone() } else { None }
+        })
+    }
+}
+
+// ================================================================
+// ABONNEMENTS
+// ================================================================
+
+/// Utilisateurs et salons observés par un abonné
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PresenceSubscriptions {
+    pub users: HashSet<i32>,
+    pub rooms: HashSet<RoomId>,
+}
+
+impl PresenceSubscriptions {
+    pub fn len(&self) -> usize {
+        self.users.len() + self.rooms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Index des abonnements : par abonné, et abonnés par utilisateur ou salon observé
+#[derive(Debug, Default)]
+struct SubscriptionIndex {
+    by_subscriber: HashMap<i32, PresenceSubscriptions>,
+    user_watchers: HashMap<i32, HashSet<i32>>,
+    room_watchers: HashMap<RoomId, HashSet<i32>>,
+}
+
+impl SubscriptionIndex {
+    /// Ajoute des abonnements ; refusé en bloc au-delà de `max` par abonné
+    fn subscribe(&mut self, subscriber: i32, users: &[i32], rooms: &[RoomId], max: usize) -> Result<()> {
+        let current = self.by_subscriber.get(&subscriber).cloned().unwrap_or_default();
+        let added_users: HashSet<i32> = users.iter().copied()
+            .filter(|user_id| *user_id != subscriber && !current.users.contains(user_id))
+            .collect();
+        let added_rooms: HashSet<&RoomId> = rooms.iter().filter(|room| !current.rooms.contains(*room)).collect();
+
+        let total = current.len() + added_users.len() + added_rooms.len();
+        if total > max {
+            return Err(ChatError::QuotaExceeded {
+                quota_type: "abonnements de présence".to_string(),
+                used: total as u64,
+                limit: max as u64,
+            });
+        }
+
+        let subscriptions = self.by_subscriber.entry(subscriber).or_default();
+        for user_id in added_users {
+            subscriptions.users.insert(user_id);
+            self.user_watchers.entry(user_id).or_default().insert(subscriber);
+        }
+        for room in added_rooms {
+            subscriptions.rooms.insert(room.clone());
+            self.room_watchers.entry(room.clone()).or_default().insert(subscriber);
+        }
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, subscriber: i32, users: &[i32], rooms: &[RoomId]) {
+        let Some(subscriptions) = self.by_subscriber.get_mut(&subscriber) else {
+            return;
+        };
+        for user_id in users {
+            if subscriptions.users.remove(user_id) {
+                Self::forget(&mut self.user_watchers, user_id, subscriber);
+            }
+        }
+        for room in rooms {
+            if subscriptions.rooms.remove(room) {
+                Self::forget(&mut self.room_watchers, room, subscriber);
+            }
+        }
+        if subscriptions.is_empty() {
+            self.by_subscriber.remove(&subscriber);
+        }
+    }
+
+    /// Retire tous les abonnements d'un abonné (déconnexion)
+    fn remove_subscriber(&mut self, subscriber: i32) {
+        let Some(subscriptions) = self.by_subscriber.remove(&subscriber) else {
+            return;
+        };
+        for user_id in &subscriptions.users {
+            Self::forget(&mut self.user_watchers, user_id, subscriber);
+        }
+        for room in &subscriptions.rooms {
+            Self::forget(&mut self.room_watchers, room, subscriber);
+        }
+    }
+
+    fn forget<K: std::hash::Hash + Eq>(watchers: &mut HashMap<K, HashSet<i32>>, key: &K, subscriber: i32) {
+        if let Some(subscribers) = watchers.get_mut(key) {
+            subscribers.remove(&subscriber);
+            if subscribers.is_empty() {
+                watchers.remove(key);
+            }
+        }
+    }
+
+    /// Abonnés à prévenir d'un changement de `user_id`, membre de `rooms`
+    fn recipients(&self, user_id: i32, rooms: &[RoomId]) -> HashSet<i32> {
+        let mut recipients: HashSet<i32> = self.user_watchers.get(&user_id).cloned().unwrap_or_default();
+        for room in rooms {
+            if let Some(subscribers) = self.room_watchers.get(room) {
+                recipients.extend(subscribers);
+            }
+        }
+        recipients.remove(&user_id);
+        recipients
+    }
 }
 
 /// Gestionnaire de présence des utilisateurs
+///
+/// Les changements de présence ne sont pas diffusés à tous : un client
+/// s'abonne à des utilisateurs ou à des salons et ne reçoit que leurs mises à
+/// jour (au plus `MAX_PRESENCE_SUBSCRIPTIONS` abonnements).
 pub struct PresenceManager {
     users: Arc<RwLock<HashMap<i32, UserPresence>>>,
+    subscriptions: Arc<RwLock<SubscriptionIndex>>,
     away_threshold: Duration,
 }
 
@@ -62,10 +196,50 @@ impl PresenceManager {
     pub fn new() -> Self {
         Self {
             users: Arc::new(RwLock::new(HashMap::new())),
+            subscriptions: Arc::new(RwLock::new(SubscriptionIndex::default())),
             away_threshold: Duration::from_secs(300), // 5 minutes
         }
     }
 
+    /// Abonne `subscriber` à la présence d'utilisateurs et de salons
+    pub async fn subscribe(&self, subscriber: i32, users: &[i32], rooms: &[RoomId]) -> Result<()> {
+        self.subscriptions.write().await.subscribe(subscriber, users, rooms, MAX_PRESENCE_SUBSCRIPTIONS)?;
+        tracing::debug!(subscriber = %subscriber, users = %users.len(), rooms = %rooms.len(), "👀 Abonnement à la présence");
+        Ok(())
+    }
+
+    pub async fn unsubscribe(&self, subscriber: i32, users: &[i32], rooms: &[RoomId]) {
+        self.subscriptions.write().await.unsubscribe(subscriber, users, rooms);
+    }
+
+    /// Retire tous les abonnements d'un client qui se déconnecte
+    pub async fn remove_subscriber(&self, subscriber: i32) {
+        self.subscriptions.write().await.remove_subscriber(subscriber);
+    }
+
+    /// Abonnements courants d'un client
+    pub async fn subscriptions_of(&self, subscriber: i32) -> PresenceSubscriptions {
+        self.subscriptions.read().await.by_subscriber.get(&subscriber).cloned().unwrap_or_default()
+    }
+
+    /// Abonnés à prévenir d'un changement de `user_id`, membre de `rooms`
+    pub async fn subscribers_for(&self, user_id: i32, rooms: &[RoomId]) -> HashSet<i32> {
+        self.subscriptions.read().await.recipients(user_id, rooms)
+    }
+
+    /// Présence publique d'utilisateurs, pour l'instantané d'abonnement
+    ///
+    /// Les utilisateurs déconnectés ou invisibles figurent hors ligne.
+    pub async fn snapshot(&self, user_ids: &[i32]) -> Vec<serde_json::Value> {
+        let users = self.users.read().await;
+        user_ids.iter()
+            .map(|user_id| match users.get(user_id) {
+                Some(presence) => presence.public_view(),
+                None => json!({ "user_id": user_id, "status": UserStatus::Offline }),
+            })
+            .collect()
+    }
+
     /// Enregistre un utilisateur comme en ligne
     pub async fn user_online(&self, user_id: i32, username: String) {
         let mut users = self.users.write().await;
@@ -169,18 +343,13 @@ impl PresenceManager {
         }
     }
 
-    /// Génère un événement de présence pour diffusion
+    /// Génère un événement de présence pour diffusion (vue publique)
     pub fn create_presence_event(&self, presence: &UserPresence, event_type: &str) -> serde_json::Value {
+        let mut data = presence.public_view();
+        data["event"] = json!(event_type);
         json!({
             "type": "presence_update",
-            "data": {
-                "event": event_type,
-                "user_id": presence.user_id,
-                "username": presence.username,
-                "status": presence.status,
-                "status_message": presence.status_message,
-                "current_room": presence.current_room
-            }
+            "data": data
         })
     }
 }
@@ -203,11 +372,11 @@ impl NotificationManager {
         body: &str, 
         _data: Option<serde_json::Value>
     ) -> Result<()> {
-        // Implémentation des notifications push
-        tracing::info!(
+        // Implémentation des notifications push ; ni titre ni contenu au journal
+        tracing::debug!(
             user_id = %user_id, 
-            title = %title, 
-            body = %body,
+            title_length = %title.len(), 
+            body_length = %body.len(),
             "📱 Notification push envoyée"
         );
         
@@ -218,11 +387,7 @@ impl NotificationManager {
     /// Notification pour un nouveau message direct
     pub async fn notify_new_dm(&self, to_user: i32, from_username: &str, preview: &str) -> Result<()> {
         let title = format!("Nouveau message de {}", from_username);
-        let body = if preview.len() > 50 {
-            format!("{}...", &preview[..47])
-        } else {
-            preview.to_string()
-        };
+        let body = crate::utils::list_preview(preview, 50);
 
         self.send_push_notification(
             to_user, 
@@ -232,16 +397,31 @@ impl NotificationManager {
         ).await
     }
 
+    /// Notification système dont l'échéance est formatée dans le fuseau de l'utilisateur
+    ///
+    /// `message` précède la date : « Envoi de messages suspendu jusqu'au 01/07/2024 14:00 CEST ».
+    pub async fn notify_system_event(
+        &self,
+        user_id: i32,
+        title: &str,
+        message: &str,
+        at: chrono::DateTime<chrono::Utc>,
+        timezone: chrono_tz::Tz
+    ) -> Result<()> {
+        let body = format!("{} {}", message, crate::utils::format_in_timezone(at, timezone));
+
+        self.send_push_notification(
+            user_id,
+            title,
+            &body,
+            Some(json!({"type": "system", "at": at.to_rfc3339()}))
+        ).await
+    }
+
     /// Notification pour mention dans un salon
     pub async fn notify_room_mention(&self, user_id: i32, room: &str, from_username: &str, message: &str) -> Result<()> {
         let title = format!("Mention dans #{}", room);
-        let body = format!("{}: {}", from_username, 
-            if message.len() > 50 {
-                format!("{}...", &message[..47])
-            } else {
-                message.to_string()
-            }
-        );
+        let body = format!("{}: {}", from_username, crate::utils::list_preview(message, 50));
 
         self.send_push_notification(
             user_id, 
@@ -250,4 +430,59 @@ impl NotificationManager {
             Some(json!({"type": "mention", "room": room, "from": from_username}))
         ).await
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rooms(names: &[&str]) -> Vec<RoomId> {
+        names.iter().map(|name| RoomId::new(name).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_updates_reach_only_subscribers() {
+        let mut index = SubscriptionIndex::default();
+        index.subscribe(1, &[2], &[], 10).unwrap();
+        index.subscribe(3, &[], &rooms(&["general"]), 10).unwrap();
+
+        assert_eq!(index.recipients(2, &[]), HashSet::from([1]));
+        assert_eq!(index.recipients(2, &rooms(&["general"])), HashSet::from([1, 3]));
+        assert!(index.recipients(4, &rooms(&["random"])).is_empty());
+        // Un abonné ne reçoit pas ses propres mises à jour
+        assert!(index.recipients(3, &rooms(&["general"])).is_empty());
+    }
+
+    #[test]
+    fn test_unsubscribe_and_disconnect_clean_index() {
+        let mut index = SubscriptionIndex::default();
+        index.subscribe(1, &[2, 3], &rooms(&["general"]), 10).unwrap();
+        index.unsubscribe(1, &[2], &[]);
+        assert!(index.recipients(2, &[]).is_empty());
+        assert_eq!(index.recipients(3, &[]), HashSet::from([1]));
+
+        index.remove_subscriber(1);
+        assert!(index.by_subscriber.is_empty());
+        assert!(index.user_watchers.is_empty());
+        assert!(index.room_watchers.is_empty());
+    }
+
+    #[test]
+    fn test_subscriptions_are_bounded() {
+        let mut index = SubscriptionIndex::default();
+        index.subscribe(1, &[2, 3], &[], 3).unwrap();
+        // Déjà suivis : ne comptent pas deux fois
+        index.subscribe(1, &[2, 3], &rooms(&["general"]), 3).unwrap();
+        assert!(matches!(index.subscribe(1, &[4], &[], 3), Err(ChatError::QuotaExceeded { .. })));
+        assert_eq!(index.by_subscriber[&1].len(), 3);
+    }
+
+    #[test]
+    fn test_invisible_users_look_offline() {
+        let mut presence = UserPresence::new(2, "bob".to_string());
+        presence.status = UserStatus::Invisible;
+        presence.current_room = Some("general".to_string());
+        let view = presence.public_view();
+        assert_eq!(view["status"], json!(UserStatus::Offline));
+        assert!(view["current_room"].is_null());
+    }
+}
@@ -0,0 +1,258 @@
+//! Cache des profils utilisateur allégés
+//!
+//! Les trames de message ne portent que `author_username` ; les clients
+//! résolvent avatars, badges et présence par lots (`get_user_profiles`). La
+//! partie statique des profils est mise en cache pour éviter une requête par
+//! message :
+//! - Durée de vie `profiles.ttl`, invalidation à la mise à jour du profil
+//! - La présence n'est jamais mise en cache, elle est ajoutée à la lecture
+//!
+//! `MemoryProfileCache` garde les profils dans le processus (un seul nœud) ;
+//! `RedisProfileCache` (feature `redis-cache`) les partage entre nœuds.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use crate::cache::SmartCache;
+use crate::config::{ProfileCacheBackend, ProfileCacheConfig, ServerConfig};
+use crate::error::Result;
+
+/// Profils retenus au plus par le cache en mémoire
+const MEMORY_CAPACITY: usize = 10_000;
+
+/// Partie statique d'un profil utilisateur (la présence est ajoutée à la lecture)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserProfileEntry {
+    pub user_id: i64,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub role_badges: Vec<String>,
+    pub show_presence: bool,
+}
+
+/// Cache des profils par identifiant d'utilisateur
+pub trait ProfileCache: Send + Sync {
+    /// Profils en cache parmi `user_ids` (les absents sont omis)
+    fn get_many<'a>(&'a self, user_ids: &'a [i64]) -> BoxFuture<'a, Result<HashMap<i64, UserProfileEntry>>>;
+
+    /// Met en cache des profils lus en base
+    fn store<'a>(&'a self, entries: &'a [UserProfileEntry]) -> BoxFuture<'a, Result<()>>;
+
+    /// Oublie le profil d'un utilisateur (après modification)
+    fn invalidate(&self, user_id: i64) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Cache choisi par la section `[profiles]`
+///
+/// Redis inutilisable (URL invalide, feature absente) : repli sur la mémoire.
+pub fn profile_cache_from_config(config: &ServerConfig) -> Arc<dyn ProfileCache> {
+    match config.profiles.backend {
+        #[cfg(feature = "redis-cache")]
+        ProfileCacheBackend::Redis => match RedisProfileCache::new(&config.cache, &config.profiles) {
+            Ok(cache) => return Arc::new(cache),
+            Err(e) => tracing::warn!(error = %e, "⚠️ Cache des profils Redis indisponible, repli en mémoire"),
+        },
+        #[cfg(not(feature = "redis-cache"))]
+        ProfileCacheBackend::Redis => tracing::warn!("⚠️ Feature redis-cache absente, cache des profils en mémoire"),
+        ProfileCacheBackend::Memory => {}
+    }
+    Arc::new(MemoryProfileCache::from_config(&config.profiles))
+}
+
+// ================================================================
+// CACHE EN MÉMOIRE
+// ================================================================
+
+/// Cache local au processus
+pub struct MemoryProfileCache {
+    entries: SmartCache<i64, UserProfileEntry>,
+}
+
+impl MemoryProfileCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { entries: SmartCache::new(MEMORY_CAPACITY, ttl) }
+    }
+
+    pub fn from_config(config: &ProfileCacheConfig) -> Self {
+        Self::new(config.ttl)
+    }
+}
+
+impl ProfileCache for MemoryProfileCache {
+    fn get_many<'a>(&'a self, user_ids: &'a [i64]) -> BoxFuture<'a, Result<HashMap<i64, UserProfileEntry>>> {
+        Box::pin(async move {
+            let mut found = HashMap::new();
+            for user_id in user_ids {
+                if let Some(entry) = self.entries.get(user_id).await {
+                    found.insert(*user_id, entry);
+                }
+            }
+            Ok(found)
+        })
+    }
+
+    fn store<'a>(&'a self, entries: &'a [UserProfileEntry]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            for entry in entries {
+                self.entries.insert(entry.user_id, entry.clone()).await;
+            }
+            Ok(())
+        })
+    }
+
+    fn invalidate(&self, user_id: i64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.entries.remove(&user_id).await;
+            Ok(())
+        })
+    }
+}
+
+// ================================================================
+// CACHE REDIS
+// ================================================================
+
+#[cfg(feature = "redis-cache")]
+pub use redis_cache::RedisProfileCache;
+
+#[cfg(feature = "redis-cache")]
+mod redis_cache {
+    use super::*;
+    use redis::AsyncCommands;
+    use tokio::sync::OnceCell;
+    use crate::config::CacheConfig;
+    use crate::error::ChatError;
+
+    /// Cache partagé entre nœuds : une clé JSON expirante par profil
+    pub struct RedisProfileCache {
+        client: redis::Client,
+        connection: OnceCell<redis::aio::ConnectionManager>,
+        key_prefix: String,
+        ttl: Duration,
+    }
+
+    fn redis_error(operation: &str, e: redis::RedisError) -> ChatError {
+        tracing::warn!(operation = %operation, error = %e, "⚠️ Erreur Redis du cache des profils");
+        ChatError::Cache { operation: operation.to_string() }
+    }
+
+    impl RedisProfileCache {
+        /// La connexion est ouverte au premier usage
+        pub fn new(cache: &CacheConfig, profiles: &ProfileCacheConfig) -> Result<Self> {
+            let client = redis::Client::open(cache.url.as_str())
+                .map_err(|e| ChatError::configuration_error(&format!("URL Redis invalide: {}", e)))?;
+            Ok(Self {
+                client,
+                connection: OnceCell::new(),
+                key_prefix: cache.key_prefix.clone(),
+                ttl: profiles.ttl,
+            })
+        }
+
+        async fn connection(&self) -> Result<redis::aio::ConnectionManager> {
+            self.connection
+                .get_or_try_init(|| redis::aio::ConnectionManager::new(self.client.clone()))
+                .await
+                .cloned()
+                .map_err(|e| redis_error("connect", e))
+        }
+
+        fn key(&self, user_id: i64) -> String {
+            format!("{}profile:{}", self.key_prefix, user_id)
+        }
+    }
+
+    impl ProfileCache for RedisProfileCache {
+        fn get_many<'a>(&'a self, user_ids: &'a [i64]) -> BoxFuture<'a, Result<HashMap<i64, UserProfileEntry>>> {
+            Box::pin(async move {
+                if user_ids.is_empty() {
+                    return Ok(HashMap::new());
+                }
+                let mut conn = self.connection().await?;
+                let keys: Vec<String> = user_ids.iter().map(|id| self.key(*id)).collect();
+
+                let values: Vec<Option<String>> = redis::cmd("MGET")
+                    .arg(&keys)
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| redis_error("profiles_mget", e))?;
+
+                // Entrée illisible (format antérieur) : traitée comme absente
+                Ok(values.iter()
+                    .flatten()
+                    .filter_map(|value| serde_json::from_str::<UserProfileEntry>(value).ok())
+                    .map(|entry| (entry.user_id, entry))
+                    .collect())
+            })
+        }
+
+        fn store<'a>(&'a self, entries: &'a [UserProfileEntry]) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                if entries.is_empty() {
+                    return Ok(());
+                }
+                let mut conn = self.connection().await?;
+                let ttl = self.ttl.as_secs().max(1);
+
+                let mut pipe = redis::pipe();
+                for entry in entries {
+                    let value = serde_json::to_string(entry)
+                        .map_err(ChatError::from_json_error)?;
+                    pipe.set_ex(self.key(entry.user_id), value, ttl).ignore();
+                }
+                pipe.query_async::<_, ()>(&mut conn)
+                    .await
+                    .map_err(|e| redis_error("profiles_store", e))?;
+                Ok(())
+            })
+        }
+
+        fn invalidate(&self, user_id: i64) -> BoxFuture<'_, Result<()>> {
+            Box::pin(async move {
+                let mut conn = self.connection().await?;
+                conn.del::<_, i64>(self.key(user_id)).await
+                    .map_err(|e| redis_error("profiles_del", e))?;
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user_id: i64) -> UserProfileEntry {
+        UserProfileEntry {
+            user_id,
+            username: format!("user{}", user_id),
+            display_name: None,
+            avatar_url: None,
+            role_badges: vec!["verified".to_string()],
+            show_presence: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_returns_only_stored_profiles() {
+        let cache = MemoryProfileCache::new(Duration::from_secs(60));
+        cache.store(&[entry(1), entry(2)]).await.unwrap();
+
+        let found = cache.get_many(&[1, 2, 3]).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[&2], entry(2));
+        assert!(!found.contains_key(&3));
+    }
+
+    #[tokio::test]
+    async fn test_invalidated_profile_is_reloaded() {
+        let cache = MemoryProfileCache::new(Duration::from_secs(60));
+        cache.store(&[entry(1)]).await.unwrap();
+
+        cache.invalidate(1).await.unwrap();
+        assert!(cache.get_many(&[1]).await.unwrap().is_empty());
+    }
+}
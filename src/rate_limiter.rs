@@ -63,6 +63,13 @@ impl RateLimiter {
         bucket.can_send(self.messages_per_minute, self.window)
     }
 
+    /// Comme `check_and_update`, avec une limite multipliée (comptes de confiance)
+    pub async fn check_and_update_scaled(&self, user_id: i32, multiplier: u32) -> bool {
+        let mut buckets = self.buckets.write().await;
+        let bucket = buckets.entry(user_id).or_insert_with(UserBucket::new);
+        bucket.can_send(self.messages_per_minute.saturating_mul(multiplier.max(1) as usize), self.window)
+    }
+
     pub async fn cleanup_old_buckets(&self) {
         let mut buckets = self.buckets.write().await;
         let cutoff = Instant::now() - Duration::from_secs(300); // 5 minutes
@@ -0,0 +1,149 @@
+//! Identifiant textuel des salons
+//!
+//! Un `RoomId` est toujours canonique : construit une seule fois à la
+//! frontière (trame client, configuration, ligne de base), il est ensuite
+//! passé tel quel au hub, au stockage et aux gestionnaires sans nouvelle
+//! validation. Forme canonique : espaces de bord retirés, minuscules ;
+//! seuls lettres, chiffres, tirets et underscores sont admis.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Encode, Postgres, Type};
+use crate::error::{ChatError, Result};
+
+/// Longueur maximale d'un nom de salon (caractères)
+pub const MAX_ROOM_NAME_LENGTH: usize = 100;
+
+/// Nom de salon validé et canonique
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RoomId(String);
+
+impl RoomId {
+    /// Canonicalise puis valide un nom de salon
+    pub fn new(name: &str) -> Result<Self> {
+        let canonical = name.trim().to_lowercase();
+
+        if canonical.is_empty() {
+            return Err(ChatError::configuration_error("Le nom du salon ne peut pas être vide"));
+        }
+        if canonical.chars().count() > MAX_ROOM_NAME_LENGTH {
+            return Err(ChatError::configuration_error(&format!(
+                "Le nom du salon est trop long (max {} caractères)", MAX_ROOM_NAME_LENGTH
+            )));
+        }
+        if !canonical.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(ChatError::configuration_error("Le nom du salon ne peut contenir que des lettres, chiffres, tirets et underscores"));
+        }
+
+        Ok(Self(canonical))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RoomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for RoomId {
+    type Err = ChatError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Self::new(name)
+    }
+}
+
+impl TryFrom<String> for RoomId {
+    type Error = ChatError;
+
+    fn try_from(name: String) -> Result<Self> {
+        Self::new(&name)
+    }
+}
+
+impl From<RoomId> for String {
+    fn from(room: RoomId) -> Self {
+        room.0
+    }
+}
+
+impl AsRef<str> for RoomId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// ================================================================
+// BASE DE DONNÉES
+// ================================================================
+
+impl Type<Postgres> for RoomId {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Postgres> for RoomId {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        <&str as Encode<Postgres>>::encode(self.as_str(), buf)
+    }
+}
+
+/// Une valeur stockée invalide est une erreur de décodage, pas un salon
+impl<'r> Decode<'r, Postgres> for RoomId {
+    fn decode(value: PgValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        let name = <&str as Decode<Postgres>>::decode(value)?;
+        Self::new(name).map_err(|e| e.to_string().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_names_rejected() {
+        assert!(RoomId::new("").is_err());
+        assert!(RoomId::new("   ").is_err());
+        assert!(RoomId::new("salon général").is_err());
+        assert!(RoomId::new("../admin").is_err());
+        assert!(RoomId::new("dev;drop").is_err());
+        assert!(RoomId::new(&"a".repeat(MAX_ROOM_NAME_LENGTH + 1)).is_err());
+        assert!(RoomId::new(&"é".repeat(MAX_ROOM_NAME_LENGTH)).is_ok());
+        assert!("bad name".parse::<RoomId>().is_err());
+        assert!(serde_json::from_str::<RoomId>("\"bad name\"").is_err());
+    }
+
+    #[test]
+    fn test_differently_cased_names_are_equal() {
+        let lower = RoomId::new("dev-team").unwrap();
+        let upper: RoomId = "Dev-Team".parse().unwrap();
+        let padded = RoomId::new("  DEV-TEAM ").unwrap();
+        assert_eq!(lower, upper);
+        assert_eq!(lower, padded);
+        assert_eq!(upper.to_string(), "dev-team");
+
+        let rooms: std::collections::HashSet<RoomId> = [lower, upper, padded].into_iter().collect();
+        assert_eq!(rooms.len(), 1);
+    }
+
+    #[test]
+    fn test_serde_uses_canonical_string() {
+        let room: RoomId = serde_json::from_str("\"Général\"").unwrap();
+        assert_eq!(room.as_str(), "général");
+        assert_eq!(serde_json::to_string(&room).unwrap(), "\"général\"");
+    }
+}
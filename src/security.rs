@@ -1,6 +1,9 @@
+use crate::config::{ContentFilterConfig, FilterAction, FilterSeverity, SpamDetectionConfig};
+use crate::permissions::Role;
 use crate::error::{ChatError, Result};
+use crate::word_packs::forbidden_words;
 use regex::Regex;
-use std::collections::{HashSet, HashMap};
+use std::collections::HashMap;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 
@@ -58,34 +61,178 @@ pub enum SecurityAction {
     UploadFile,
     ChangeSettings,
     AdminAction,
+    /// Ajout ou retrait de réaction (un seul budget pour limiter le va-et-vient)
+    AddReaction,
+    /// Mention de masse (@everyone, @here, @role)
+    MassMention,
+    /// Signalement d'un message à la modération
+    ReportMessage,
+    /// Commande de diagnostic de connexion (ping_diag)
+    Diagnostic,
+    /// Message d'un invité (`guests.messages_per_minute`)
+    GuestMessage,
+    /// Récupération des clés E2EE d'un correspondant (par demandeur)
+    FetchKeyBundle,
+    /// Clé à usage unique remise, tous demandeurs confondus (par destinataire)
+    ClaimPrekey,
+}
+
+// ================================================================
+// FILTRE DE CONTENU
+// ================================================================
+
+/// Règle du filtre de contenu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterRule {
+    /// Patterns XSS, injection SQL, traversée de chemin, commandes
+    DangerousPatterns,
+    /// Mots liés à l'injection de code (`script`, `eval`, ...)
+    InjectionWords,
+    /// Vocabulaire d'arnaque et de spam
+    SpamWords,
+    /// Grossièretés
+    Profanity,
+    /// Harcèlement
+    Harassment,
+    /// Heuristiques de spam (répétitions, majuscules, ...)
+    Spam,
+    /// Score de toxicité
+    Toxicity,
+}
+
+impl FilterRule {
+    pub const ALL: [FilterRule; 7] = [
+        FilterRule::DangerousPatterns,
+        FilterRule::InjectionWords,
+        FilterRule::SpamWords,
+        FilterRule::Profanity,
+        FilterRule::Harassment,
+        FilterRule::Spam,
+        FilterRule::Toxicity,
+    ];
+
+    /// Nom utilisé dans la configuration (`security.content_filter.rules`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterRule::DangerousPatterns => "dangerous_patterns",
+            FilterRule::InjectionWords => "injection_words",
+            FilterRule::SpamWords => "spam_words",
+            FilterRule::Profanity => "profanity",
+            FilterRule::Harassment => "harassment",
+            FilterRule::Spam => "spam",
+            FilterRule::Toxicity => "toxicity",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rule| rule.as_str() == name)
+    }
+
+    /// Raison présentée à l'auteur
+    pub fn reason(&self) -> &'static str {
+        match self {
+            FilterRule::DangerousPatterns => "contenu potentiellement dangereux",
+            FilterRule::InjectionWords => "tentative d'injection",
+            FilterRule::SpamWords => "vocabulaire de spam",
+            FilterRule::Profanity => "grossièreté",
+            FilterRule::Harassment => "harcèlement",
+            FilterRule::Spam => "spam",
+            FilterRule::Toxicity => "propos toxiques",
+        }
+    }
+
+    pub fn default_severity(&self) -> FilterSeverity {
+        match self {
+            FilterRule::Profanity => FilterSeverity::Low,
+            FilterRule::SpamWords | FilterRule::Spam => FilterSeverity::Medium,
+            FilterRule::DangerousPatterns
+            | FilterRule::InjectionWords
+            | FilterRule::Harassment
+            | FilterRule::Toxicity => FilterSeverity::High,
+        }
+    }
+}
+
+/// Résultat du filtrage d'un contenu accepté
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterVerdict {
+    /// Contenu nettoyé, passages masqués le cas échéant
+    pub content: String,
+    /// Gravité la plus haute relevée (`None` : aucune règle déclenchée)
+    pub severity: Option<FilterSeverity>,
+    /// Action correspondant à cette gravité
+    pub action: Option<FilterAction>,
+    /// Raison de la règle la plus grave
+    pub reason: Option<String>,
+    /// Toutes les règles déclenchées
+    pub rules: Vec<FilterRule>,
+    /// Règles déclenchées par des citations exemptées, à examiner par la modération
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exempted_rules: Vec<FilterRule>,
+}
+
+impl FilterVerdict {
+    pub fn is_clean(&self) -> bool {
+        self.severity.is_none()
+    }
+
+    /// Une citation exemptée a déclenché des règles
+    pub fn needs_review(&self) -> bool {
+        !self.exempted_rules.is_empty()
+    }
+}
+
+/// Texte d'une ligne citée (`> ...`) s'il reprend mot pour mot un contenu fourni
+fn vouched_quote<'a>(line: &'a str, vouched: &[&str]) -> Option<&'a str> {
+    let text = line.trim_start().strip_prefix('>')?.trim();
+    (!text.is_empty() && vouched.iter().any(|source| source.contains(text))).then_some(text)
 }
 
 /// Filtre de contenu amélioré avec détection ML
 pub struct ContentFilter {
-    forbidden_words: HashSet<String>,
+    config: ContentFilterConfig,
+    forbidden_words: Vec<(FilterRule, Regex)>,
     dangerous_patterns: Vec<Regex>,
     spam_detector: SpamDetector,
     toxicity_detector: ToxicityDetector,
+    /// Rôle de l'auteur dispensé de la détection de spam
+    spam_bypass: bool,
+    /// Termes légitimes d'un salon, prioritaires sur les mots interdits
+    allowlist: Vec<Regex>,
+}
+
+/// La plage est entièrement couverte par l'un des termes autorisés
+fn is_allowlisted(allowed: &[std::ops::Range<usize>], found: std::ops::Range<usize>) -> bool {
+    allowed.iter().any(|span| span.start <= found.start && found.end <= span.end)
 }
 
 impl ContentFilter {
     pub fn new() -> Result<Self> {
-        // Mots interdits étendus
-        let forbidden_words = vec![
-            // Spam/Scam
-            "click here", "urgent", "limited time", "act now", "free money",
-            "viagra", "casino", "lottery", "winner", "congratulations",
-            
-            // Injection/Exploitation
+        Self::with_config(&ContentFilterConfig::default())
+    }
+
+    /// Filtre dont la gravité des règles et les actions suivent la configuration
+    pub fn with_config(config: &ContentFilterConfig) -> Result<Self> {
+        Self::with_locales(config, &[])
+    }
+
+    /// Comme `with_config`, avec les listes de mots de langues supplémentaires
+    /// (langues d'un salon) en plus de celles de la configuration
+    pub fn with_locales(config: &ContentFilterConfig, extra_locales: &[String]) -> Result<Self> {
+        // Injection/Exploitation, indépendant de la langue
+        let injection_words = [
             "script", "eval", "onclick", "onerror", "javascript",
             "vbscript", "expression", "import", "alert",
-            
-            // Profanité (exemples)
-            "spam", "fuck", "shit", "bitch", "damn",
-            
-            // Harcèlement
-            "kill yourself", "kys", "suicide", "die",
-        ].into_iter().map(|s| s.to_lowercase()).collect();
+        ].map(|word| (FilterRule::InjectionWords, word.to_string()));
+
+        // Spam, grossièretés et harcèlement : union des langues
+        let forbidden_words = injection_words.into_iter()
+            .chain(forbidden_words(config, extra_locales)?)
+            .map(|(rule, word)| Regex::new(&format!("(?i){}", regex::escape(&word))).map(|regex| (rule, regex)))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| ChatError::configuration_error(&format!("Regex invalide: {}", e)))?;
 
         // Patterns XSS/Injection renforcés
         let dangerous_patterns = vec![
@@ -108,57 +255,228 @@ impl ContentFilter {
             r"\.\./",
             r"\.\.\\",
             
-            // Command Injection
-            r"[\s;|&`$(){}[\]<>]",
+            // Command Injection (métacaractères shell ; les espaces restent permis)
+            r"[;|&`$(){}\[\]<>]",
         ].into_iter()
-        .map(|pattern| Regex::new(pattern))
+        // Insensible à la casse : le masquage s'applique au contenu d'origine
+        .map(|pattern| Regex::new(&format!("(?i){}", pattern)))
         .collect::<std::result::Result<Vec<_>, _>>()
         .map_err(|e| ChatError::configuration_error(&format!("Regex invalide: {}", e)))?;
 
         Ok(Self {
+            config: config.clone(),
             forbidden_words,
             dangerous_patterns,
-            spam_detector: SpamDetector::new(),
+            spam_detector: SpamDetector::with_config(&config.spam),
             toxicity_detector: ToxicityDetector::new(),
+            spam_bypass: false,
+            allowlist: Vec::new(),
         })
     }
 
+    /// Applique la dispense de détection de spam si le rôle de l'auteur y a droit
+    pub fn for_role(mut self, role: &Role) -> Self {
+        self.spam_bypass = self.config.spam.bypass_roles.iter()
+            .any(|name| Role::from_string(name).is_ok_and(|bypass| &bypass == role));
+        self
+    }
+
+    /// Exempte les termes d'un salon (noms d'équipe, jargon) des mots interdits
+    ///
+    /// Une occurrence de mot interdit comprise dans un terme autorisé, ou égale
+    /// à celui-ci, est ignorée ; ailleurs dans le message, le mot reste filtré.
+    /// Les patterns dangereux, le spam et la toxicité ne sont pas concernés.
+    pub fn with_allowlist(mut self, terms: &[String]) -> Self {
+        self.allowlist = terms.iter()
+            .map(|term| term.trim())
+            .filter(|term| !term.is_empty())
+            .filter_map(|term| Regex::new(&format!("(?i){}", regex::escape(term))).ok())
+            .collect();
+        self
+    }
+
+    /// Plages du contenu couvertes par un terme autorisé
+    fn allowed_spans(&self, content: &str) -> Vec<std::ops::Range<usize>> {
+        self.allowlist.iter()
+            .flat_map(|term| term.find_iter(content).map(|found| found.range()))
+            .collect()
+    }
+
+    /// Gravité configurée d'une règle
+    pub fn severity_of(&self, rule: FilterRule) -> FilterSeverity {
+        self.config.rules.get(rule.as_str())
+            .copied()
+            .unwrap_or_else(|| rule.default_severity())
+    }
+
+    /// Valide le contenu ; le contenu retourné est nettoyé et éventuellement masqué
     pub fn validate_content(&mut self, content: &str) -> Result<String> {
+        self.check_content(content).map(|verdict| verdict.content)
+    }
+
+    /// Classe le contenu et applique l'action de la gravité la plus haute
+    ///
+    /// Un rejet retourne une erreur ; sinon les passages relevant d'une règle
+    /// masquée sont remplacés et le verdict porte la gravité et la raison.
+    pub fn check_content(&mut self, content: &str) -> Result<FilterVerdict> {
         // 1. Longueur
         if content.len() > 4000 {
             return Err(ChatError::message_too_long(content.len(), 4000));
         }
 
-        // 2. Patterns dangereux
-        let content_lower = content.to_lowercase();
-        for pattern in &self.dangerous_patterns {
-            if pattern.is_match(&content_lower) {
-                tracing::warn!(content = %content, "🚨 Contenu dangereux détecté");
-                return Err(ChatError::inappropriate_content_simple("inappropriate_content"));
+        // 2 à 5. Patterns dangereux, mots interdits, spam, toxicité
+        let rules = self.triggered_rules(content);
+
+        // 6. Action de la règle la plus grave
+        let Some(worst) = rules.iter().copied().max_by_key(|rule| self.severity_of(*rule)) else {
+            return Ok(FilterVerdict {
+                content: self.sanitize_html(content),
+                severity: None,
+                action: None,
+                reason: None,
+                rules,
+                exempted_rules: Vec::new(),
+            });
+        };
+        let severity = self.severity_of(worst);
+        let action = self.config.action_for(severity);
+
+        if action == FilterAction::Reject {
+            return Err(match worst {
+                FilterRule::Spam => ChatError::SpamDetected,
+                _ => ChatError::inappropriate_content_simple(&format!("{} (gravité {})", worst.reason(), severity.as_str())),
+            });
+        }
+
+        // 7. Masquage des règles concernées, puis sanitisation
+        let mut masked = content.to_string();
+        for rule in &rules {
+            if self.config.action_for(self.severity_of(*rule)) == FilterAction::Mask {
+                masked = self.mask(*rule, &masked);
             }
         }
 
-        // 3. Mots interdits
-        for word in &self.forbidden_words {
-            if content_lower.contains(word) {
-                tracing::warn!(word = %word, "🚫 Mot interdit détecté");
-                return Err(ChatError::inappropriate_content_simple("inappropriate_content"));
+        if action == FilterAction::Warn {
+            tracing::info!(rule = %worst.as_str(), severity = %severity.as_str(), "⚠️ Contenu accepté avec avertissement");
+        }
+
+        Ok(FilterVerdict {
+            content: self.sanitize_html(&masked),
+            severity: Some(severity),
+            action: Some(action),
+            reason: Some(worst.reason().to_string()),
+            rules,
+            exempted_rules: Vec::new(),
+        })
+    }
+
+    /// Règles déclenchées par un contenu, sans appliquer d'action
+    fn triggered_rules(&self, content: &str) -> Vec<FilterRule> {
+        // Patterns dangereux
+        let mut rules = Vec::new();
+        if self.dangerous_patterns.iter().any(|pattern| pattern.is_match(content)) {
+            tracing::warn!(content = %content, "🚨 Contenu dangereux détecté");
+            rules.push(FilterRule::DangerousPatterns);
+        }
+
+        // Mots interdits, hors termes autorisés du salon
+        let allowed = self.allowed_spans(content);
+        for (rule, word) in &self.forbidden_words {
+            if !rules.contains(rule) && word.find_iter(content).any(|found| !is_allowlisted(&allowed, found.range())) {
+                tracing::warn!(word = %word.as_str(), rule = %rule.as_str(), "🚫 Mot interdit détecté");
+                rules.push(*rule);
             }
         }
 
-        // 4. Détection de spam
+        // Détection de spam
         if self.spam_detector.is_spam(content).unwrap_or(false) {
-            return Err(ChatError::SpamDetected);
+            if self.spam_bypass {
+                tracing::info!(content_length = %content.len(), "🛡️ Spam détecté, ignoré pour un rôle de confiance");
+            } else {
+                rules.push(FilterRule::Spam);
+            }
         }
 
-        // 5. Détection de toxicité
+        // Détection de toxicité
         if self.toxicity_detector.is_toxic(content).unwrap_or(false) {
-            return Err(ChatError::inappropriate_content_simple("inappropriate_content"));
+            rules.push(FilterRule::Toxicity);
         }
 
-        // 6. Sanitisation
-        let sanitized = self.sanitize_html(content);
-        Ok(sanitized)
+        rules
+    }
+
+    /// Comme `check_content`, en exemptant les citations vérifiées
+    ///
+    /// `vouched` : textes fournis par le serveur (message signalé, extrait
+    /// cité). Une ligne `> ...` n'est exemptée que si son texte figure mot pour
+    /// mot dans l'un d'eux ; le reste du contenu, y compris une citation
+    /// inventée, passe le filtre complet. Les citations exemptées ne sont ni
+    /// rejetées ni masquées, leurs règles sont relevées dans `exempted_rules`
+    /// pour les modérateurs.
+    pub fn check_content_with_exemptions(&mut self, content: &str, vouched: &[&str]) -> Result<FilterVerdict> {
+        if content.len() > 4000 {
+            return Err(ChatError::message_too_long(content.len(), 4000));
+        }
+
+        let lines: Vec<&str> = content.split('\n').collect();
+        let quoted: Vec<Option<&str>> = lines.iter()
+            .map(|line| vouched_quote(line, vouched))
+            .collect();
+        if !self.config.exempt_quoted_content || quoted.iter().all(Option::is_none) {
+            return self.check_content(content);
+        }
+
+        // Texte propre à l'auteur : les citations exemptées deviennent des lignes vides
+        let own: Vec<&str> = lines.iter().zip(&quoted)
+            .map(|(line, quote)| if quote.is_some() { "" } else { *line })
+            .collect();
+        let mut verdict = self.check_content(&own.join("\n"))?;
+
+        let mut exempted_rules = Vec::new();
+        for quote in quoted.iter().flatten() {
+            for rule in self.triggered_rules(quote) {
+                if !exempted_rules.contains(&rule) {
+                    exempted_rules.push(rule);
+                }
+            }
+        }
+        if !exempted_rules.is_empty() {
+            tracing::info!(rules = ?exempted_rules, "💬 Citation exemptée du filtre, signalée aux modérateurs");
+        }
+
+        // Recomposition ligne à ligne (masquage et sanitisation préservent les sauts de ligne)
+        verdict.content = verdict.content.split('\n').zip(lines.iter().zip(&quoted))
+            .map(|(own_line, (line, quote))| match quote {
+                Some(_) => self.sanitize_html(line),
+                None => own_line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        verdict.exempted_rules = exempted_rules;
+        Ok(verdict)
+    }
+
+    /// Remplace par des astérisques les passages relevant de la règle
+    fn mask(&self, rule: FilterRule, content: &str) -> String {
+        let stars = |caps: &regex::Captures| "*".repeat(caps[0].chars().count());
+
+        match rule {
+            FilterRule::DangerousPatterns => self.dangerous_patterns.iter()
+                .fold(content.to_string(), |text, pattern| pattern.replace_all(&text, stars).into_owned()),
+            // Règles portant sur le message entier
+            FilterRule::Spam | FilterRule::Toxicity => content.chars()
+                .map(|c| if c.is_whitespace() { c } else { '*' })
+                .collect(),
+            _ => self.forbidden_words.iter()
+                .filter(|(word_rule, _)| *word_rule == rule)
+                .fold(content.to_string(), |text, (_, word)| {
+                    let allowed = self.allowed_spans(&text);
+                    word.replace_all(&text, |caps: &regex::Captures| match caps.get(0) {
+                        Some(found) if is_allowlisted(&allowed, found.range()) => found.as_str().to_string(),
+                        _ => stars(caps),
+                    }).into_owned()
+                }),
+        }
     }
 
     fn sanitize_html(&self, content: &str) -> String {
@@ -179,19 +497,26 @@ pub struct SpamDetector {
     repetition_threshold: f32,
     caps_threshold: f32,
     emoji_threshold: f32,
+    min_length: usize,
 }
 
 impl SpamDetector {
     pub fn new() -> Self {
+        Self::with_config(&SpamDetectionConfig::default())
+    }
+
+    /// Détecteur aux seuils de la configuration (`security.content_filter.spam`)
+    pub fn with_config(config: &SpamDetectionConfig) -> Self {
         Self {
-            repetition_threshold: 0.7, // 70% de répétition
-            caps_threshold: 0.5,       // 50% de majuscules
-            emoji_threshold: 0.3,      // 30% d'emojis
+            repetition_threshold: config.repetition_threshold,
+            caps_threshold: config.caps_threshold,
+            emoji_threshold: config.special_chars_threshold,
+            min_length: config.min_length,
         }
     }
 
     pub fn is_spam(&self, content: &str) -> Result<bool> {
-        if content.len() < 10 {
+        if content.is_empty() || content.len() < self.min_length {
             return Ok(false);
         }
 
@@ -320,10 +645,16 @@ impl ToxicityDetector {
     }
 }
 
+/// Fenêtre glissante utilisée pour les limites de burst
+const BURST_WINDOW: Duration = Duration::from_secs(10);
+
 /// Rate limiter avancé par action
 pub struct AdvancedRateLimiter {
     limits: HashMap<SecurityAction, RateLimit>,
     user_actions: HashMap<(i32, SecurityAction), Vec<SystemTime>>,
+    /// Débit maximal d'un salon, tous auteurs confondus (`None` : pas de limite)
+    room_limit: Option<RateLimit>,
+    room_messages: HashMap<i64, Vec<SystemTime>>,
 }
 
 #[derive(Clone)]
@@ -367,13 +698,101 @@ impl AdvancedRateLimiter {
             window_duration: Duration::from_secs(60),
             burst_limit: Some(10),
         });
+        
+        limits.insert(SecurityAction::AddReaction, RateLimit {
+            max_count: 40,
+            window_duration: Duration::from_secs(60),
+            burst_limit: Some(8),
+        });
+        
+        limits.insert(SecurityAction::MassMention, RateLimit {
+            max_count: 3,
+            window_duration: Duration::from_secs(600), // 10 minutes
+            burst_limit: None,
+        });
+        
+        limits.insert(SecurityAction::ReportMessage, RateLimit {
+            max_count: 10,
+            window_duration: Duration::from_secs(600), // 10 minutes
+            burst_limit: Some(3),
+        });
+        
+        limits.insert(SecurityAction::Diagnostic, RateLimit {
+            max_count: 30,
+            window_duration: Duration::from_secs(60),
+            burst_limit: Some(5),
+        });
+        
+        limits.insert(SecurityAction::GuestMessage, RateLimit {
+            max_count: 3,
+            window_duration: Duration::from_secs(60),
+            burst_limit: None,
+        });
+        
+        limits.insert(SecurityAction::FetchKeyBundle, RateLimit {
+            max_count: 20,
+            window_duration: Duration::from_secs(60),
+            burst_limit: Some(5),
+        });
+        
+        limits.insert(SecurityAction::ClaimPrekey, RateLimit {
+            max_count: 30,
+            window_duration: Duration::from_secs(60),
+            burst_limit: None,
+        });
 
         Self {
             limits,
             user_actions: HashMap::new(),
+            room_limit: None,
+            room_messages: HashMap::new(),
         }
     }
 
+    /// Limite les messages d'un salon par minute (0 : pas de limite)
+    pub fn with_room_limit(mut self, messages_per_minute: u32) -> Self {
+        self.room_limit = (messages_per_minute > 0).then(|| RateLimit {
+            max_count: messages_per_minute,
+            window_duration: Duration::from_secs(60),
+            burst_limit: None,
+        });
+        self
+    }
+
+    /// Limite les messages d'un invité par minute (0 : aucun message)
+    pub fn with_guest_limit(mut self, messages_per_minute: u32) -> Self {
+        self.limits.insert(SecurityAction::GuestMessage, RateLimit {
+            max_count: messages_per_minute,
+            window_duration: Duration::from_secs(60),
+            burst_limit: None,
+        });
+        self
+    }
+
+    /// Vérifie le débit du salon, à appeler après la limite propre à l'auteur
+    pub fn check_room_limit(&mut self, room_id: i64) -> Result<()> {
+        let Some(limit) = &self.room_limit else {
+            return Ok(());
+        };
+        let now = SystemTime::now();
+
+        let messages = self.room_messages.entry(room_id).or_default();
+        messages.retain(|time| now.duration_since(*time).unwrap_or(Duration::ZERO) <= limit.window_duration);
+
+        if messages.len() >= limit.max_count as usize {
+            tracing::warn!(room_id = %room_id, count = %messages.len(), limit = %limit.max_count, "🌊 Débit du salon dépassé");
+            return Err(ChatError::RoomRateLimitExceeded {
+                room_id: room_id.to_string(),
+                current: messages.len() as u32,
+                limit: limit.max_count,
+                retry_after: Self::retry_after(messages, now, limit.window_duration),
+            });
+        }
+
+        messages.push(now);
+        Ok(())
+    }
+
     pub fn check_limit(&mut self, user_id: i32, action: &SecurityAction) -> Result<()> {
         let limit = self.limits.get(action)
             .ok_or_else(|| ChatError::configuration_error("Action non configurée"))?;
@@ -388,18 +807,33 @@ impl AdvancedRateLimiter {
         // Vérifier la limite principale
         if actions.len() >= limit.max_count as usize {
             tracing::warn!(user_id = %user_id, action = ?action, count = %actions.len(), limit = %limit.max_count, "⏰ Rate limit dépassé");
-            return Err(ChatError::rate_limit_exceeded_simple("rate_limit"));
+            let retry_after = Self::retry_after(actions, now, limit.window_duration);
+            return Err(ChatError::RateLimitExceeded {
+                action: format!("{:?}", action),
+                current: actions.len() as u32,
+                limit: limit.max_count,
+                window: retry_after,
+            });
         }
 
         // Vérifier la limite de burst si configurée
         if let Some(burst_limit) = limit.burst_limit {
             let recent_actions = actions.iter()
-                .filter(|time| now.duration_since(**time).unwrap_or(Duration::ZERO) <= Duration::from_secs(10))
+                .filter(|time| now.duration_since(**time).unwrap_or(Duration::ZERO) <= BURST_WINDOW)
                 .count();
             
             if recent_actions >= burst_limit as usize {
                 tracing::warn!(user_id = %user_id, action = ?action, burst_count = %recent_actions, burst_limit = %burst_limit, "💥 Burst limit dépassé");
-                return Err(ChatError::rate_limit_exceeded_simple("rate_limit"));
+                let recent: Vec<SystemTime> = actions.iter()
+                    .filter(|time| now.duration_since(**time).unwrap_or(Duration::ZERO) <= BURST_WINDOW)
+                    .copied()
+                    .collect();
+                return Err(ChatError::RateLimitExceeded {
+                    action: format!("{:?}", action),
+                    current: recent_actions as u32,
+                    limit: burst_limit,
+                    window: Self::retry_after(&recent, now, BURST_WINDOW),
+                });
             }
         }
 
@@ -407,6 +841,15 @@ impl AdvancedRateLimiter {
         actions.push(now);
         Ok(())
     }
+
+    /// Secondes avant que l'action la plus ancienne de la fenêtre n'expire
+    fn retry_after(actions: &[SystemTime], now: SystemTime, window: Duration) -> u64 {
+        actions.iter()
+            .min()
+            .map(|oldest| window.saturating_sub(now.duration_since(*oldest).unwrap_or(Duration::ZERO)))
+            .map(|remaining| remaining.as_secs().max(1))
+            .unwrap_or(1)
+    }
 }
 
 /// Gestionnaire de sessions sécurisé
@@ -415,13 +858,80 @@ pub struct SessionManager {
     max_sessions_per_user: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SessionInfo {
+    #[serde(skip_serializing)]
     pub token_hash: String,
     pub created_at: SystemTime,
     pub last_activity: SystemTime,
     pub ip_address: String,
     pub user_agent: Option<String>,
+    pub client_version: Option<String>,
+}
+
+/// Longueur maximale conservée pour le User-Agent
+pub const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Longueur maximale conservée pour la version du client
+pub const MAX_CLIENT_VERSION_LENGTH: usize = 64;
+
+/// En-tête HTTP portant la version du client
+pub const CLIENT_VERSION_HEADER: &str = "x-client-version";
+
+/// Métadonnées de connexion capturées lors du handshake WebSocket
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionMetadata {
+    pub user_agent: Option<String>,
+    pub client_version: Option<String>,
+}
+
+impl ConnectionMetadata {
+    /// Construit les métadonnées à partir des valeurs brutes des en-têtes
+    pub fn from_headers(user_agent: Option<&str>, client_version: Option<&str>) -> Self {
+        Self {
+            user_agent: user_agent.and_then(|ua| sanitize_header(ua, MAX_USER_AGENT_LENGTH)),
+            client_version: client_version.and_then(|v| sanitize_header(v, MAX_CLIENT_VERSION_LENGTH)),
+        }
+    }
+
+    /// Extrait les métadonnées de la requête de handshake
+    pub fn from_request<B>(request: &tungstenite::http::Request<B>) -> Self {
+        let header = |name: &str| request.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok());
+
+        Self::from_headers(header("user-agent"), header(CLIENT_VERSION_HEADER))
+    }
+}
+
+/// Réponse HTTP refusant un handshake (429 + `Retry-After` pour une limitation)
+///
+/// À retourner depuis le callback de `accept_hdr_async`, avant toute
+/// authentification.
+pub fn handshake_rejection(error: &ChatError) -> tungstenite::handshake::server::ErrorResponse {
+    use tungstenite::http::{header::RETRY_AFTER, HeaderValue, StatusCode};
+
+    let mut response = tungstenite::handshake::server::ErrorResponse::new(Some(error.public_message()));
+    *response.status_mut() = StatusCode::from_u16(error.http_status()).unwrap_or(StatusCode::FORBIDDEN);
+    if let Some(retry_after) = error.retry_after() {
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
+}
+
+/// Nettoie un en-tête : caractères de contrôle retirés, longueur tronquée
+fn sanitize_header(value: &str, max_chars: usize) -> Option<String> {
+    let cleaned: String = value.chars()
+        .filter(|c| !c.is_control())
+        .take(max_chars)
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.is_empty() {
+        None
+    } else {
+        Some(cleaned.to_string())
+    }
 }
 
 impl SessionManager {
@@ -433,6 +943,17 @@ impl SessionManager {
     }
 
     pub fn create_session(&mut self, user_id: i32, token: &str, ip: &str) -> Result<()> {
+        self.create_session_with_metadata(user_id, token, ip, ConnectionMetadata::default())
+    }
+
+    /// Crée une session en conservant le User-Agent et la version du client
+    pub fn create_session_with_metadata(
+        &mut self,
+        user_id: i32,
+        token: &str,
+        ip: &str,
+        metadata: ConnectionMetadata
+    ) -> Result<()> {
         let token_hash = self.hash_token(token);
         
         // Vérifier le nombre de sessions
@@ -449,7 +970,8 @@ impl SessionManager {
             created_at: SystemTime::now(),
             last_activity: SystemTime::now(),
             ip_address: ip.to_string(),
-            user_agent: None,
+            user_agent: metadata.user_agent,
+            client_version: metadata.client_version,
         };
 
         self.active_sessions.insert(user_id, session);
@@ -478,6 +1000,18 @@ impl SessionManager {
         }
     }
 
+    /// Session active d'un utilisateur
+    pub fn get_session(&self, user_id: i32) -> Option<&SessionInfo> {
+        self.active_sessions.get(&user_id)
+    }
+
+    /// Liste des sessions actives (vue d'administration)
+    pub fn list_sessions(&self) -> Vec<(i32, &SessionInfo)> {
+        self.active_sessions.iter()
+            .map(|(user_id, session)| (*user_id, session))
+            .collect()
+    }
+
     fn hash_token(&self, token: &str) -> String {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
@@ -491,8 +1025,12 @@ impl SessionManager {
 /// Moniteur d'IP pour détecter les comportements suspects
 pub struct IpMonitor {
     ip_actions: HashMap<String, Vec<(SystemTime, SecurityAction)>>,
-    blacklisted_ips: HashSet<String>,
+    /// IP bloquées et leur date d'expiration (`None` = blocage permanent)
+    blacklisted_ips: HashMap<String, Option<SystemTime>>,
     suspicious_threshold: u32,
+    /// Nouvelles connexions par IP sur la fenêtre (`None` = pas de limite)
+    connection_limit: Option<RateLimit>,
+    connection_attempts: HashMap<String, Vec<SystemTime>>,
 }
 
 impl IpMonitor {
@@ -501,18 +1039,64 @@ impl IpMonitor {
         let blacklisted_ips = vec![
             "0.0.0.0",
             "255.255.255.255",
-        ].into_iter().map(|s| s.to_string()).collect();
+        ].into_iter().map(|s| (s.to_string(), None)).collect();
 
         Self {
             ip_actions: HashMap::new(),
             blacklisted_ips,
             suspicious_threshold: 100, // 100 actions par minute
+            connection_limit: None,
+            connection_attempts: HashMap::new(),
         }
     }
 
+    /// Limite les nouvelles connexions par IP sur `window` (0 : pas de limite)
+    ///
+    /// Indépendant de `max_connections_per_user` : une IP qui ouvre et ferme
+    /// ses connexions en boucle est limitée même sans en garder aucune ouverte.
+    pub fn with_connection_limit(mut self, connections: u32, window: Duration) -> Self {
+        self.connection_limit = (connections > 0).then(|| RateLimit {
+            max_count: connections,
+            window_duration: window,
+            burst_limit: None,
+        });
+        self
+    }
+
+    /// Admission d'une nouvelle connexion, à vérifier au handshake avant toute authentification
+    ///
+    /// Une IP bloquée est refusée ; au-delà de la limite, la tentative est
+    /// refusée sans être comptée.
+    pub fn check_connection(&mut self, ip: &str) -> Result<()> {
+        if self.is_blacklisted(ip) {
+            tracing::error!(ip = %ip, "🚫 Connexion d'une IP blacklistée refusée");
+            return Err(ChatError::unauthorized_simple("connect"));
+        }
+        let Some(limit) = &self.connection_limit else {
+            return Ok(());
+        };
+        let now = SystemTime::now();
+
+        let attempts = self.connection_attempts.entry(ip.to_string()).or_default();
+        attempts.retain(|time| now.duration_since(*time).unwrap_or(Duration::ZERO) <= limit.window_duration);
+
+        if attempts.len() >= limit.max_count as usize {
+            tracing::warn!(ip = %ip, count = %attempts.len(), limit = %limit.max_count, "🌊 Trop de nouvelles connexions depuis cette IP");
+            return Err(ChatError::ConnectionRateExceeded {
+                ip: ip.to_string(),
+                limit: limit.max_count,
+                window: limit.window_duration.as_secs(),
+                retry_after: AdvancedRateLimiter::retry_after(attempts, now, limit.window_duration),
+            });
+        }
+
+        attempts.push(now);
+        Ok(())
+    }
+
     pub fn check_ip(&mut self, ip: &str, action: &SecurityAction) -> Result<()> {
         // Vérifier la liste noire
-        if self.blacklisted_ips.contains(ip) {
+        if self.is_blacklisted(ip) {
             tracing::error!(ip = %ip, "🚫 IP blacklistée détectée");
             return Err(ChatError::unauthorized_simple("unauthorized_action"));
         }
@@ -536,7 +1120,311 @@ impl IpMonitor {
     }
 
     pub fn blacklist_ip(&mut self, ip: &str) {
-        self.blacklisted_ips.insert(ip.to_string());
-        tracing::warn!(ip = %ip, "🚫 IP ajoutée à la liste noire");
+        self.blacklist_ip_until(ip, None);
     }
-} 
\ No newline at end of file
+
+    /// Bloque une IP jusqu'à `expires_at` (`None` = blocage permanent)
+    pub fn blacklist_ip_until(&mut self, ip: &str, expires_at: Option<SystemTime>) {
+        self.blacklisted_ips.insert(ip.to_string(), expires_at);
+        tracing::warn!(ip = %ip, temporary = %expires_at.is_some(), "🚫 IP ajoutée à la liste noire");
+    }
+
+    /// Retire une IP de la liste noire
+    pub fn unblacklist_ip(&mut self, ip: &str) -> bool {
+        let removed = self.blacklisted_ips.remove(ip).is_some();
+        if removed {
+            tracing::info!(ip = %ip, "✅ IP retirée de la liste noire");
+        }
+        removed
+    }
+
+    /// Indique si une IP est bloquée (les blocages expirés sont levés au passage)
+    pub fn is_blacklisted(&mut self, ip: &str) -> bool {
+        match self.blacklisted_ips.get(ip) {
+            Some(None) => true,
+            Some(Some(expires_at)) if *expires_at > SystemTime::now() => true,
+            Some(Some(_)) => {
+                self.blacklisted_ips.remove(ip);
+                tracing::info!(ip = %ip, "⏰ Blocage temporaire expiré");
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Supprime les blocages temporaires expirés (et les tentatives de connexion hors fenêtre)
+    pub fn purge_expired(&mut self) -> usize {
+        let now = SystemTime::now();
+        if let Some(limit) = &self.connection_limit {
+            self.connection_attempts.retain(|_, attempts| attempts.iter()
+                .any(|time| now.duration_since(*time).unwrap_or(Duration::ZERO) <= limit.window_duration));
+        }
+
+        let before = self.blacklisted_ips.len();
+        self.blacklisted_ips.retain(|_, expires_at| expires_at.map_or(true, |at| at > now));
+        before - self.blacklisted_ips.len()
+    }
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normal_reacting_allowed() {
+        let mut limiter = AdvancedRateLimiter::new();
+        for _ in 0..5 {
+            assert!(limiter.check_limit(1, &SecurityAction::AddReaction).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rapid_reaction_toggling_throttled() {
+        let mut limiter = AdvancedRateLimiter::new();
+        let results: Vec<_> = (0..20)
+            .map(|_| limiter.check_limit(1, &SecurityAction::AddReaction))
+            .collect();
+
+        assert!(results.iter().take(8).all(|r| r.is_ok()));
+        match results.last() {
+            Some(Err(ChatError::RateLimitExceeded { window, .. })) => assert!(*window >= 1),
+            other => panic!("throttling attendu, obtenu {:?}", other),
+        }
+
+        // Le budget est propre à chaque utilisateur
+        assert!(limiter.check_limit(2, &SecurityAction::AddReaction).is_ok());
+    }
+
+    #[test]
+    fn test_room_limit_caps_many_users() {
+        let mut limiter = AdvancedRateLimiter::new().with_room_limit(3);
+        // Chaque auteur reste sous sa propre limite, le salon sature
+        for user_id in 1..=3 {
+            assert!(limiter.check_limit(user_id, &SecurityAction::SendMessage).is_ok());
+            assert!(limiter.check_room_limit(10).is_ok());
+        }
+        assert!(limiter.check_limit(4, &SecurityAction::SendMessage).is_ok());
+        match limiter.check_room_limit(10) {
+            Err(ChatError::RoomRateLimitExceeded { room_id, limit: 3, retry_after, .. }) => {
+                assert_eq!(room_id, "10");
+                assert!(retry_after >= 1);
+            }
+            other => panic!("limite du salon attendue, obtenu {:?}", other),
+        }
+
+        // Les autres salons gardent leur budget ; sans limite, rien n'est compté
+        assert!(limiter.check_room_limit(11).is_ok());
+        let mut unlimited = AdvancedRateLimiter::new().with_room_limit(0);
+        assert!((0..100).all(|_| unlimited.check_room_limit(10).is_ok()));
+    }
+
+    #[test]
+    fn test_temporary_ip_block_expires() {
+        let mut monitor = IpMonitor::new();
+        monitor.blacklist_ip_until("203.0.113.7", Some(SystemTime::now() + Duration::from_secs(60)));
+        monitor.blacklist_ip_until("203.0.113.8", Some(SystemTime::now() - Duration::from_secs(1)));
+
+        assert!(monitor.check_ip("203.0.113.7", &SecurityAction::SendMessage).is_err());
+        assert!(monitor.check_ip("203.0.113.8", &SecurityAction::SendMessage).is_ok());
+        assert_eq!(monitor.purge_expired(), 0);
+
+        assert!(monitor.unblacklist_ip("203.0.113.7"));
+        assert!(monitor.check_ip("203.0.113.7", &SecurityAction::SendMessage).is_ok());
+    }
+
+    #[test]
+    fn test_connection_rate_per_ip() {
+        let mut monitor = IpMonitor::new().with_connection_limit(5, Duration::from_secs(60));
+        let results: Vec<_> = (0..8).map(|_| monitor.check_connection("203.0.113.9")).collect();
+
+        assert!(results.iter().take(5).all(|r| r.is_ok()));
+        match &results[7] {
+            Err(ChatError::ConnectionRateExceeded { limit: 5, retry_after, .. }) => assert!(*retry_after >= 1),
+            other => panic!("limitation attendue, obtenu {:?}", other),
+        }
+        assert!(monitor.check_connection("203.0.113.10").is_ok());
+
+        let response = handshake_rejection(results[7].as_ref().unwrap_err());
+        assert_eq!(response.status().as_u16(), 429);
+        assert!(response.headers().contains_key("retry-after"));
+
+        // Une IP bloquée est refusée même sans limite de débit
+        let mut unlimited = IpMonitor::new();
+        assert!((0..100).all(|_| unlimited.check_connection("203.0.113.9").is_ok()));
+        unlimited.blacklist_ip("203.0.113.9");
+        assert!(matches!(unlimited.check_connection("203.0.113.9"), Err(ChatError::Unauthorized { .. })));
+    }
+
+    #[test]
+    fn test_connection_metadata_sanitized() {
+        let long_agent = "Mozilla/5.0 ".repeat(100);
+        let metadata = ConnectionMetadata::from_headers(Some(&long_agent), Some(" 2.4.1\u{0}\n "));
+        assert_eq!(metadata.user_agent.unwrap().chars().count(), MAX_USER_AGENT_LENGTH);
+        assert_eq!(metadata.client_version.as_deref(), Some("2.4.1"));
+
+        assert_eq!(ConnectionMetadata::from_headers(Some("   "), None), ConnectionMetadata::default());
+    }
+
+    #[test]
+    fn test_session_keeps_connection_metadata() {
+        let request = tungstenite::http::Request::builder()
+            .header("User-Agent", "VezaDesktop/1.2")
+            .header(CLIENT_VERSION_HEADER, "1.2.0")
+            .body(())
+            .unwrap();
+
+        let mut sessions = SessionManager::new();
+        sessions.create_session_with_metadata(7, "token", "192.0.2.10", ConnectionMetadata::from_request(&request)).unwrap();
+
+        let session = sessions.get_session(7).unwrap();
+        assert_eq!(session.user_agent.as_deref(), Some("VezaDesktop/1.2"));
+        assert_eq!(session.client_version.as_deref(), Some("1.2.0"));
+        assert_eq!(sessions.list_sessions().len(), 1);
+    }
+
+    #[test]
+    fn test_low_severity_is_masked() {
+        let mut filter = ContentFilter::new().unwrap();
+        let verdict = filter.check_content("Damn!").unwrap();
+
+        assert_eq!(verdict.content, "****!");
+        assert_eq!(verdict.severity, Some(FilterSeverity::Low));
+        assert_eq!(verdict.action, Some(FilterAction::Mask));
+        assert_eq!(verdict.rules, vec![FilterRule::Profanity]);
+    }
+
+    #[test]
+    fn test_medium_severity_warns_but_allows() {
+        let mut filter = ContentFilter::new().unwrap();
+        let verdict = filter.check_content("casino,damn").unwrap();
+
+        // La gravité la plus haute décide ; la grossièreté reste masquée
+        assert_eq!(verdict.severity, Some(FilterSeverity::Medium));
+        assert_eq!(verdict.action, Some(FilterAction::Warn));
+        assert_eq!(verdict.reason.as_deref(), Some("vocabulaire de spam"));
+        assert_eq!(verdict.content, "casino,****");
+
+        assert!(filter.check_content("bonjour à tous").unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_high_severity_rejects_and_config_overrides() {
+        let mut filter = ContentFilter::new().unwrap();
+        assert!(matches!(
+            filter.check_content("kys"),
+            Err(ChatError::InappropriateContent { ref reason }) if reason.contains("high")
+        ));
+
+        let mut config = ContentFilterConfig::default();
+        config.rules.insert("profanity".to_string(), FilterSeverity::High);
+        config.medium_action = FilterAction::Reject;
+        let mut strict = ContentFilter::with_config(&config).unwrap();
+        assert!(strict.check_content("damn").is_err());
+        assert!(strict.check_content("casino").is_err());
+    }
+
+    #[test]
+    fn test_french_word_pack() {
+        let mut config = ContentFilterConfig::default();
+        config.locales = vec!["fr".to_string()];
+        let mut french = ContentFilter::with_config(&config).unwrap();
+
+        let verdict = french.check_content("putain de bug").unwrap();
+        assert_eq!(verdict.rules, vec![FilterRule::Profanity]);
+        assert_eq!(verdict.content, "****** de bug");
+        assert!(french.check_content("oh shit").unwrap().is_clean());
+
+        // Langue d'un salon ajoutée à celles de la configuration
+        let mut room = ContentFilter::with_locales(&ContentFilterConfig::default(), &["fr".to_string()]).unwrap();
+        assert_eq!(room.check_content("merde").unwrap().rules, vec![FilterRule::Profanity]);
+        assert_eq!(room.check_content("shit").unwrap().rules, vec![FilterRule::Profanity]);
+    }
+
+    #[test]
+    fn test_allowlisted_term_passes_other_words_still_block() {
+        let allowlist = vec!["Die Hard".to_string(), "damn".to_string()];
+        let mut filter = ContentFilter::new().unwrap().with_allowlist(&allowlist);
+
+        assert!(filter.check_content("On regarde die hard ce soir").unwrap().is_clean());
+        assert!(filter.check_content("Damn, quel film").unwrap().is_clean());
+        // Hors du terme autorisé, le mot reste interdit
+        assert!(filter.check_content("Die Hard, et toi die").is_err());
+        assert!(filter.check_content("kys").is_err());
+        assert_eq!(filter.check_content("shit").unwrap().content, "****");
+
+        // Sans liste, le même message est refusé
+        assert!(ContentFilter::new().unwrap().check_content("On regarde die hard ce soir").is_err());
+    }
+
+    #[test]
+    fn test_custom_spam_thresholds() {
+        let shouting = "HELLO WORLD";
+
+        // Seuils par défaut : majuscules détectées, contenu court ignoré
+        assert!(SpamDetector::new().is_spam(shouting).unwrap());
+        assert!(!SpamDetector::new().is_spam("AAAA").unwrap());
+
+        let mut lenient = SpamDetectionConfig::default();
+        lenient.caps_threshold = 1.0;
+        assert!(!SpamDetector::with_config(&lenient).is_spam(shouting).unwrap());
+
+        // Spam court analysé quand la longueur minimale est levée
+        let mut short = SpamDetectionConfig::default();
+        short.min_length = 0;
+        assert!(SpamDetector::with_config(&short).is_spam("AAAA").unwrap());
+        assert!(!SpamDetector::with_config(&short).is_spam("").unwrap());
+    }
+
+    #[test]
+    fn test_trusted_role_bypasses_spam_detection() {
+        let mut config = ContentFilterConfig::default();
+        config.medium_action = FilterAction::Reject;
+        config.spam.bypass_roles = vec!["moderator".to_string()];
+        let announcement = "VENEZ TOUS CE SOIR";
+
+        let mut filter = ContentFilter::with_config(&config).unwrap();
+        assert!(matches!(filter.check_content(announcement), Err(ChatError::SpamDetected)));
+
+        let mut trusted = ContentFilter::with_config(&config).unwrap().for_role(&Role::Moderator);
+        assert!(trusted.check_content(announcement).unwrap().is_clean());
+
+        // Les autres règles s'appliquent toujours
+        assert!(trusted.check_content("kys").is_err());
+        let mut user = ContentFilter::with_config(&config).unwrap().for_role(&Role::User);
+        assert!(user.check_content(announcement).is_err());
+    }
+
+    #[test]
+    fn test_vouched_quote_is_exempt_but_flagged() {
+        let reported = "kys, personne ne t'aime";
+        let mut filter = ContentFilter::new().unwrap();
+
+        let verdict = filter.check_content_with_exemptions("Il m'a écrit :\n> kys", &[reported]).unwrap();
+        assert!(verdict.is_clean());
+        assert!(verdict.needs_review());
+        assert_eq!(verdict.exempted_rules, vec![FilterRule::Harassment]);
+        assert!(verdict.content.ends_with("kys"));
+
+        // Sans exemption configurée, la citation est filtrée comme le reste
+        let mut config = ContentFilterConfig::default();
+        config.exempt_quoted_content = false;
+        let mut strict = ContentFilter::with_config(&config).unwrap();
+        assert!(strict.check_content_with_exemptions("> kys", &[reported]).is_err());
+    }
+
+    #[test]
+    fn test_exemption_cannot_bypass_filter() {
+        let reported = "kys";
+        let mut filter = ContentFilter::new().unwrap();
+
+        // Citation absente du contenu fourni par le serveur
+        assert!(filter.check_content_with_exemptions("> die", &[reported]).is_err());
+        // Texte propre à l'auteur hors citation
+        assert!(filter.check_content_with_exemptions("> kys\nkys aussi", &[reported]).is_err());
+        // Aucune source : filtrage habituel
+        assert!(filter.check_content_with_exemptions("> kys", &[]).is_err());
+        // L'auteur garde le masquage habituel sur son propre texte
+        let verdict = filter.check_content_with_exemptions("> kys\ndamn", &[reported]).unwrap();
+        assert_eq!(verdict.content.split('\n').last(), Some("****"));
+    }
+}
@@ -2,10 +2,30 @@
 
 use crate::error::{ChatError, Result};
 use crate::models::*;
-use sqlx::PgPool;
+use crate::presence::{PresenceManager, UserStatus};
+use crate::profile_cache::{ProfileCache, UserProfileEntry};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::collections::HashSet;
 use std::sync::Arc;
 use uuid::Uuid;
 
+/// Nombre maximum de profils résolus en un seul appel
+pub const MAX_PROFILE_BATCH: usize = 200;
+
+/// Profil allégé renvoyé aux clients pour enrichir les messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfile {
+    pub user_id: i64,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub role_badges: Vec<String>,
+    /// `None` si l'utilisateur masque sa présence
+    pub presence: Option<UserStatus>,
+}
+
 /// Service de gestion des utilisateurs
 pub struct UserService {
     db: Arc<PgPool>,
@@ -55,8 +75,116 @@ impl UserService {
         tracing::warn!("get_user_by_username: Fonction temporairement désactivée (migration DB requise)");
         Ok(None)
     }
+    /// Résout en un seul appel les profils d'une liste d'utilisateurs
+    ///
+    /// Les profils sont lus depuis le cache (`[profiles]`) puis complétés par une
+    /// unique requête pour les manquants ; un cache indisponible est contourné.
+    /// La présence est ajoutée à la lecture et masquée si l'utilisateur l'a
+    /// désactivée ou s'il est invisible.
+    pub async fn get_user_profiles(
+        &self,
+        user_ids: &[i64],
+        cache: &dyn ProfileCache,
+        presence: &PresenceManager,
+    ) -> Result<Vec<UserProfile>> {
+        let mut seen = HashSet::new();
+        let ids: Vec<i64> = user_ids.iter()
+            .copied()
+            .filter(|id| seen.insert(*id))
+            .collect();
+
+        if ids.len() > MAX_PROFILE_BATCH {
+            return Err(ChatError::OutOfRange {
+                field: "user_ids".to_string(),
+                value: ids.len() as i64,
+                min: 0,
+                max: MAX_PROFILE_BATCH as i64,
+            });
+        }
+
+        let mut cached = cache.get_many(&ids).await.unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "⚠️ Cache des profils indisponible, lecture en base");
+            Default::default()
+        });
+        let missing: Vec<i64> = ids.iter().copied().filter(|id| !cached.contains_key(id)).collect();
+
+        if !missing.is_empty() {
+            let rows = sqlx::query(
+                r#"
+                SELECT id, username, display_name, avatar_url, role::text AS role,
+                       is_verified, show_presence
+                FROM users
+                WHERE id = ANY($1)
+                "#
+            )
+            .bind(&missing)
+            .fetch_all(&*self.db)
+            .await
+            .map_err(|e| ChatError::from_sqlx_error("get_user_profiles", e))?;
+
+            let mut loaded = Vec::with_capacity(rows.len());
+            for row in rows {
+                let role: String = row.get("role");
+                let mut role_badges = Vec::new();
+                if role != "user" {
+                    role_badges.push(role);
+                }
+                if row.get::<bool, _>("is_verified") {
+                    role_badges.push("verified".to_string());
+                }
+
+                loaded.push(UserProfileEntry {
+                    user_id: row.get("id"),
+                    username: row.get("username"),
+                    display_name: row.get("display_name"),
+                    avatar_url: row.get("avatar_url"),
+                    role_badges,
+                    show_presence: row.get("show_presence"),
+                });
+            }
+
+            if let Err(e) = cache.store(&loaded).await {
+                tracing::warn!(error = %e, "⚠️ Profils non mis en cache");
+            }
+            cached.extend(loaded.into_iter().map(|entry| (entry.user_id, entry)));
+        }
+
+        // Ordre de la demande ; les utilisateurs inconnus sont omis
+        let entries: Vec<UserProfileEntry> = ids.iter().filter_map(|id| cached.remove(id)).collect();
+
+        let mut profiles = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let status = if entry.show_presence {
+                let user_id = i32::try_from(entry.user_id).ok();
+                match user_id {
+                    Some(id) => Some(
+                        presence.get_user_presence(id).await
+                            .map(|p| p.status)
+                            .unwrap_or(UserStatus::Offline)
+                    ),
+                    None => None,
+                }
+            } else {
+                None
+            };
+
+            profiles.push(UserProfile {
+                user_id: entry.user_id,
+                username: entry.username,
+                display_name: entry.display_name,
+                avatar_url: entry.avatar_url,
+                role_badges: entry.role_badges,
+                // Un utilisateur invisible apparaît hors ligne
+                presence: status.map(|s| if s == UserStatus::Invisible { UserStatus::Offline } else { s }),
+            });
+        }
+
+        tracing::debug!(requested = %ids.len(), resolved = %profiles.len(), "👤 Profils résolus");
+        Ok(profiles)
+    }
 }
 
+
 /// Service de gestion des messages
 pub struct MessageService {
     db: Arc<PgPool>,
@@ -0,0 +1,332 @@
+//! Table partagée découpée en fragments verrouillés séparément
+//!
+//! `ChatHub::clients`, `ChatHub::sessions` et `ChatHub::rooms` sont lus et modifiés par toutes les
+//! connexions ; derrière un seul `RwLock`, chaque enregistrement bloquait
+//! toutes les diffusions. Ici, chaque clé appartient à un fragment (hachage de
+//! la clé modulo le nombre de fragments) et seules les opérations sur un même
+//! fragment s'attendent :
+//! - Les opérations sur une clé ne verrouillent que son fragment
+//! - Les parcours (`keys`, `values`, `for_each_mut`) verrouillent les fragments
+//!   un par un, jamais tous à la fois : ils voient un état cohérent par
+//!   fragment, pas une photographie globale
+//! - Le temps d'attente de chaque verrou est mesuré, ainsi que le nombre
+//!   d'acquisitions qui ont trouvé le fragment déjà verrouillé (`lock_waits`)
+
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Nombre de fragments par défaut (`limits.state_shards`)
+pub const DEFAULT_SHARD_COUNT: usize = 16;
+
+// ================================================================
+// ATTENTE DES VERROUS
+// ================================================================
+
+/// Compteurs d'attente des verrous d'une table
+#[derive(Debug, Default)]
+pub struct LockWaitStats {
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    total_wait_nanos: AtomicU64,
+    max_wait_nanos: AtomicU64,
+}
+
+/// Attente cumulée des verrous sur une période
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LockWaitSnapshot {
+    pub acquisitions: u64,
+    /// Acquisitions qui ont dû attendre un autre détenteur du fragment
+    pub contended: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl LockWaitSnapshot {
+    /// Attente moyenne par acquisition
+    pub fn mean_wait(&self) -> Duration {
+        if self.acquisitions == 0 {
+            return Duration::ZERO;
+        }
+        self.total_wait / self.acquisitions as u32
+    }
+}
+
+impl LockWaitStats {
+    fn record(&self, waited: Duration, contended: bool) {
+        let nanos = waited.as_nanos().min(u64::MAX as u128) as u64;
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if contended {
+            self.contended.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_wait_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_wait_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Attente depuis la création ou le dernier `take`
+    pub fn snapshot(&self) -> LockWaitSnapshot {
+        LockWaitSnapshot {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            total_wait: Duration::from_nanos(self.total_wait_nanos.load(Ordering::Relaxed)),
+            max_wait: Duration::from_nanos(self.max_wait_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Comme `snapshot`, puis remet les compteurs à zéro (export périodique)
+    pub fn take(&self) -> LockWaitSnapshot {
+        LockWaitSnapshot {
+            acquisitions: self.acquisitions.swap(0, Ordering::Relaxed),
+            contended: self.contended.swap(0, Ordering::Relaxed),
+            total_wait: Duration::from_nanos(self.total_wait_nanos.swap(0, Ordering::Relaxed)),
+            max_wait: Duration::from_nanos(self.max_wait_nanos.swap(0, Ordering::Relaxed)),
+        }
+    }
+}
+
+// ================================================================
+// TABLE FRAGMENTÉE
+// ================================================================
+
+/// Table clé-valeur dont chaque fragment a son propre verrou
+#[derive(Debug)]
+pub struct ShardedMap<K, V> {
+    shards: Box<[RwLock<HashMap<K, V>>]>,
+    hasher: RandomState,
+    waits: LockWaitStats,
+}
+
+impl<K: Hash + Eq, V> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARD_COUNT)
+    }
+}
+
+impl<K: Hash + Eq, V> ShardedMap<K, V> {
+    /// Table vide ; au moins un fragment
+    pub fn new(shard_count: usize) -> Self {
+        Self {
+            shards: (0..shard_count.max(1)).map(|_| RwLock::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+            waits: LockWaitStats::default(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_of(&self, key: &K) -> usize {
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    async fn read_shard(&self, index: usize) -> RwLockReadGuard<'_, HashMap<K, V>> {
+        if let Ok(guard) = self.shards[index].try_read() {
+            self.waits.record(Duration::ZERO, false);
+            return guard;
+        }
+        let started = Instant::now();
+        let guard = self.shards[index].read().await;
+        self.waits.record(started.elapsed(), true);
+        guard
+    }
+
+    async fn write_shard(&self, index: usize) -> RwLockWriteGuard<'_, HashMap<K, V>> {
+        if let Ok(guard) = self.shards[index].try_write() {
+            self.waits.record(Duration::ZERO, false);
+            return guard;
+        }
+        let started = Instant::now();
+        let guard = self.shards[index].write().await;
+        self.waits.record(started.elapsed(), true);
+        guard
+    }
+
+    /// Fragment de la clé en lecture (plusieurs lectures sur une même clé)
+    pub async fn read(&self, key: &K) -> RwLockReadGuard<'_, HashMap<K, V>> {
+        self.read_shard(self.shard_of(key)).await
+    }
+
+    /// Fragment de la clé en écriture (opération en plusieurs étapes sur une même clé)
+    pub async fn write(&self, key: &K) -> RwLockWriteGuard<'_, HashMap<K, V>> {
+        self.write_shard(self.shard_of(key)).await
+    }
+
+    pub async fn contains_key(&self, key: &K) -> bool {
+        self.read(key).await.contains_key(key)
+    }
+
+    /// Lit la valeur de la clé sans la copier
+    pub async fn with<R>(&self, key: &K, f: impl FnOnce(Option<&V>) -> R) -> R {
+        f(self.read(key).await.get(key))
+    }
+
+    /// Modifie la valeur de la clé, si elle existe
+    pub async fn with_mut<R>(&self, key: &K, f: impl FnOnce(Option<&mut V>) -> R) -> R {
+        f(self.write(key).await.get_mut(key))
+    }
+
+    /// Modifie la valeur de la clé, créée par défaut si besoin
+    pub async fn update<R>(&self, key: K, f: impl FnOnce(&mut V) -> R) -> R
+    where
+        V: Default,
+    {
+        let mut shard = self.write(&key).await;
+        f(shard.entry(key).or_default())
+    }
+
+    pub async fn insert(&self, key: K, value: V) -> Option<V> {
+        self.write(&key).await.insert(key, value)
+    }
+
+    pub async fn remove(&self, key: &K) -> Option<V> {
+        self.write(key).await.remove(key)
+    }
+
+    /// Retire la clé si sa valeur satisfait `predicate` (vérifié sous le verrou)
+    pub async fn remove_if(&self, key: &K, predicate: impl FnOnce(&V) -> bool) -> Option<V> {
+        let mut shard = self.write(key).await;
+        if shard.get(key).is_some_and(predicate) {
+            shard.remove(key)
+        } else {
+            None
+        }
+    }
+
+    /// Nombre d'entrées (fragments comptés l'un après l'autre)
+    pub async fn len(&self) -> usize {
+        let mut len = 0;
+        for index in 0..self.shards.len() {
+            len += self.read_shard(index).await.len();
+        }
+        len
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Applique `f` à chaque entrée, un fragment à la fois
+    pub async fn for_each_mut(&self, mut f: impl FnMut(&K, &mut V)) {
+        for index in 0..self.shards.len() {
+            for (key, value) in self.write_shard(index).await.iter_mut() {
+                f(key, value);
+            }
+        }
+    }
+
+    /// Résultats de `f` sur chaque entrée (les `None` sont écartés), un fragment à la fois
+    pub async fn filter_map<T>(&self, mut f: impl FnMut(&K, &V) -> Option<T>) -> Vec<T> {
+        let mut found = Vec::new();
+        for index in 0..self.shards.len() {
+            found.extend(self.read_shard(index).await.iter().filter_map(|(key, value)| f(key, value)));
+        }
+        found
+    }
+
+    /// Attente des verrous de la table
+    pub fn lock_waits(&self) -> &LockWaitStats {
+        &self.waits
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> ShardedMap<K, V> {
+    pub async fn get(&self, key: &K) -> Option<V> {
+        self.read(key).await.get(key).cloned()
+    }
+
+    /// Valeurs des clés présentes, chaque fragment concerné verrouillé une fois
+    pub async fn get_many(&self, keys: impl IntoIterator<Item = K>) -> Vec<V> {
+        let mut by_shard: HashMap<usize, Vec<K>> = HashMap::new();
+        for key in keys {
+            by_shard.entry(self.shard_of(&key)).or_default().push(key);
+        }
+
+        let mut values = Vec::new();
+        for (index, keys) in by_shard {
+            let shard = self.read_shard(index).await;
+            values.extend(keys.iter().filter_map(|key| shard.get(key).cloned()));
+        }
+        values
+    }
+
+    pub async fn keys(&self) -> Vec<K> {
+        self.filter_map(|key, _| Some(key.clone())).await
+    }
+
+    pub async fn values(&self) -> Vec<V> {
+        self.filter_map(|_, value| Some(value.clone())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_operations_span_all_shards() {
+        let map: ShardedMap<i32, Vec<i32>> = ShardedMap::new(4);
+        for id in 0..100 {
+            map.insert(id, vec![id]).await;
+        }
+        map.update(7, |members| members.push(70)).await;
+        map.update(200, |members| members.push(1)).await;
+
+        assert_eq!(map.len().await, 101);
+        assert_eq!(map.get(&7).await, Some(vec![7, 70]));
+        assert_eq!(map.get_many([1, 2, 500]).await.len(), 2);
+        assert!(map.remove_if(&200, |members| members.is_empty()).await.is_none());
+        assert_eq!(map.remove(&200).await, Some(vec![1]));
+
+        let mut keys = map.keys().await;
+        keys.sort_unstable();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+        assert!(map.lock_waits().snapshot().acquisitions > 0);
+    }
+
+    /// Tâches qui gardent le verrou de leur clé pendant un court travail
+    async fn contended_run(map: Arc<ShardedMap<i32, u32>>, keys: &[i32]) {
+        let tasks: Vec<_> = keys.iter().map(|&id| {
+            let map = Arc::clone(&map);
+            tokio::spawn(async move {
+                let mut shard = map.write(&id).await;
+                *shard.entry(id).or_default() += 1;
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            })
+        }).collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sharding_removes_contention_of_single_lock() {
+        let single = Arc::new(ShardedMap::new(1));
+        let sharded = Arc::new(ShardedMap::new(DEFAULT_SHARD_COUNT));
+
+        // Une clé par fragment
+        let mut keys: Vec<i32> = Vec::new();
+        let mut used = std::collections::HashSet::new();
+        for id in 0.. {
+            if used.insert(sharded.shard_of(&id)) {
+                keys.push(id);
+            }
+            if keys.len() == DEFAULT_SHARD_COUNT {
+                break;
+            }
+        }
+
+        contended_run(Arc::clone(&single), &keys).await;
+        contended_run(Arc::clone(&sharded), &keys).await;
+
+        // Verrou unique : chaque tâche attend la précédente ; fragmenté : aucune attente
+        assert_eq!(single.lock_waits().snapshot().contended, keys.len() as u64 - 1);
+        assert_eq!(sharded.lock_waits().snapshot().contended, 0);
+        assert_eq!(sharded.lock_waits().snapshot().acquisitions, keys.len() as u64);
+        assert_eq!(sharded.len().await, keys.len());
+    }
+}
@@ -0,0 +1,1694 @@
+//! Harnais de test pour les crates utilisatrices (feature `testing`)
+//!
+//! Permet de monter un hub sans PostgreSQL ni Redis :
+//! - `ChatHub::new_for_testing()` : pool paresseux jamais connecté, cache désactivé,
+//!   journal de reprise et quotas en mémoire ; regroupement des insertions,
+//!   salons par défaut et quotas quotidiens (comptes lus en base) désactivés
+//! - `InMemoryRoomRepository` : dépôt des salons en mémoire, installé sur le
+//!   hub à la place de `PgRoomRepository` ; `repositories()` y ajoute les
+//!   dépôts DM, réactions, fichiers et modération en mémoire, sur le même état
+//! - `TestHarness` : clients factices et capture des trames sortantes (la trame
+//!   de poignée de main est mise de côté dans `TestClient::handshake`), invités
+//!   compris (`connect_guest`) ; `serve_rooms` / `serve_dms` font lire des
+//!   trames entrantes par la boucle de connexion du serveur
+//!
+//! Les tests appellent les fonctions du hub elles-mêmes
+//! (`channels::send_room_message`, `archive_room`, `review_held_message`,
+//! `get_missed_events`, `guests::send_guest_message`, ...) : droits, filtres
+//! et diffusion sont ceux du serveur, seul le stockage change. Les fonctions
+//! qui interrogent la base hors des dépôts restent inutilisables avec ce hub.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::RwLock;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use crate::client::Client;
+use crate::config::{BlockedDmHistory, LimitsConfig, QuotaBackend, ReplayBackend, ServerConfig, ViolationEscalationConfig};
+use crate::db_pool;
+use crate::error::{ChatError, Result};
+use crate::event_bridge::{BridgePublisher, EventBridge};
+use crate::auth::{issue_guest_claims, GUEST_ROLE};
+use crate::encryption::MessageCipher;
+use crate::hub::attachment_repository::AttachmentRepository;
+use crate::hub::attachments::{NewAttachment, StorageUsage};
+use crate::hub::audit_sink::AuditSink;
+use crate::hub::channels::{check_archive_change, check_pin_rights, check_room_modification, is_moderator_role, plan_pin_order, RoomPostPolicy};
+use crate::hub::channel_websocket::serve_room_connection;
+use crate::hub::common::{ChatHub, Repositories};
+use crate::hub::direct_messages_websocket::serve_dm_connection;
+use crate::hub::dedup::{DedupKey, SentMessage};
+use crate::hub::dm_repository::{DmRepository, InsertedDmMessage, NewDmMessage};
+use crate::hub::direct_messages::{DmConversation, DmMessage, DmParticipant, DmPrivacy, StartEligibility};
+use crate::hub::e2ee::{KeyBundle, KeyBundleUpload};
+use crate::hub::presence_subscriptions::PresenceRelation;
+use crate::hub::reactions::{AddedReaction, ReactionUser, ReactionView};
+use crate::hub::read_receipts::{SeenByMember, SeenByMode};
+use crate::hub::room_directory::{RoomCursor, RoomFilter, RoomInfo};
+use crate::hub::unread::{DmUnread, RoomUnread};
+use crate::hub::guests::GuestAccess;
+use crate::hub::held_messages::{check_review_rights, RoomFilterMode};
+use crate::hub::memberships::PersistedMembership;
+use crate::hub::message_policy::{MessagePolicy, ModificationContext};
+use crate::hub::mentions::{
+    added_mass_mentions, check_mass_mention_permission, collect_recipients, diff_mentions, MentionKind, ParsedMention,
+    ResolvedMentions,
+};
+use crate::hub::onboarding::DefaultRoom;
+use crate::hub::missed_events::{MembershipChange, MessageChange, ReactionChange};
+use crate::hub::moderation_repository::ModerationRepository;
+use crate::hub::reaction_repository::ReactionRepository;
+use crate::hub::violations::{next_violation, RecordedViolation, SanctionDeadlines};
+use crate::hub::room_repository::{
+    ArchiveState, ChangeBound, DeletedMessage, EditedMessage, InsertedRoomMessage, NewRoomMessage, PostingContext, ReviewedMessage, RoomRepository,
+    WriteContext,
+};
+use crate::monitoring::{ChatMetrics, MetricsSink, NoopSink};
+use crate::permissions::{MessageAction, Role};
+use crate::room_id::RoomId;
+
+// ================================================================
+// HUB DE TEST
+// ================================================================
+
+impl ChatHub {
+    /// Hub sans base ni cache externe, pour les tests d'intégration
+    ///
+    /// Doit être appelé depuis un runtime Tokio (`#[tokio::test]`).
+    pub fn new_for_testing() -> Arc<Self> {
+        Self::new_for_testing_with_config(ServerConfig::default())
+    }
+
+    /// Variante avec configuration personnalisée (limites, fonctionnalités, ...)
+    pub fn new_for_testing_with_config(config: ServerConfig) -> Arc<Self> {
+        Self::new_for_testing_with_metrics(config, Arc::new(NoopSink))
+    }
+
+    /// Variante dont les métriques sont écrites dans le puits fourni (ex. `RecordingSink`)
+    pub fn new_for_testing_with_metrics(config: ServerConfig, sink: Arc<dyn MetricsSink>) -> Arc<Self> {
+        Self::testing_hub(config, ChatMetrics::with_sink(sink), None, InMemoryRoomRepository::new())
+    }
+
+    /// Variante dont les événements partent vers le publieur fourni (ex. `RecordingPublisher`)
+    ///
+    /// Réglages du pont : `integrations.nats` de la configuration, ou par défaut.
+    pub fn new_for_testing_with_bridge(config: ServerConfig, publisher: Arc<dyn BridgePublisher>) -> Arc<Self> {
+        let bridge = EventBridge::spawn(publisher, &config.integrations.nats.clone().unwrap_or_default());
+        Self::testing_hub(config, ChatMetrics::new(), Some(bridge), InMemoryRoomRepository::new())
+    }
+
+    fn testing_hub(
+        mut config: ServerConfig,
+        metrics: ChatMetrics,
+        bridge: Option<EventBridge>,
+        rooms: InMemoryRoomRepository
+    ) -> Arc<Self> {
+        config.cache.enabled = false;
+        config.database.insert_batching = false;
+        config.replay.backend = ReplayBackend::Memory;
+        config.quotas.backend = QuotaBackend::Memory;
+        // Lisent les comptes en base, hors du dépôt des salons
+        config.quotas.enabled = false;
+
+        // Aucune connexion n'est ouverte tant qu'aucune requête n'est exécutée
+        config.database.max_connections = 1;
+        config.database.min_connections = 0;
+        let db = db_pool::connect_lazy(&config.database)
+            .expect("URL de base de données de test invalide");
+
+        Self::with_repositories(db, config, metrics, bridge, rooms.repositories())
+    }
+}
+
+// ================================================================
+// DÉPÔT DES SALONS EN MÉMOIRE
+// ================================================================
+
+/// Message stocké par le dépôt en mémoire
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredMessage {
+    pub id: i64,
+    pub room_id: i64,
+    pub author_id: i64,
+    pub content: String,
+    pub parent_message_id: Option<i64>,
+    pub visible_to: Option<Vec<i64>>,
+    pub metadata: Value,
+    pub created_at: DateTime<Utc>,
+    /// Motif de retenue : hors historique jusqu'à l'examen
+    pub held_reason: Option<String>,
+    /// Date de suppression (message rejeté à l'examen)
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    dedup_key: Option<DedupKey>,
+}
+
+/// Salon du dépôt en mémoire (public, non chiffré, réglages par défaut)
+#[derive(Debug, Clone)]
+struct MemoryRoom {
+    id: i64,
+    name: RoomId,
+    is_public: bool,
+    created_at: DateTime<Utc>,
+    is_archived: bool,
+    post_policy: RoomPostPolicy,
+    filter_mode: RoomFilterMode,
+    guest_access: GuestAccess,
+    seen_by_mode: SeenByMode,
+}
+
+/// Adhésion (équivalent d'une ligne de `conversation_members`)
+#[derive(Debug, Clone)]
+struct MemoryMembership {
+    id: i64,
+    room_id: i64,
+    user_id: i64,
+    role: String,
+    joined_at: DateTime<Utc>,
+    left_at: Option<DateTime<Utc>>,
+    last_read_message_id: Option<i64>,
+    is_muted: bool,
+}
+
+impl MemoryMembership {
+    fn is_active(&self) -> bool {
+        self.left_at.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    rooms: HashMap<i64, MemoryRoom>,
+    memberships: Vec<MemoryMembership>,
+    usernames: HashMap<i64, String>,
+    messages: Vec<StoredMessage>,
+    guest_messages: Vec<StoredMessage>,
+    /// Messages directs (`room_id` : conversation)
+    dm_messages: Vec<StoredMessage>,
+    message_counts: HashMap<i64, i64>,
+    violations: HashMap<i64, MemoryViolations>,
+    key_bundles: HashMap<i64, KeyBundleUpload>,
+    /// Fichiers téléversés : identifiant -> (propriétaire, taille)
+    files: HashMap<i64, (i64, u64)>,
+    /// Clés des objets des fichiers reçus par `store_attachment`
+    object_keys: HashMap<i64, String>,
+    /// Conversations DM par paire ordonnée (plus petit identifiant d'abord)
+    dm_conversations: HashMap<(i64, i64), DmConversation>,
+    /// Paires (bloqueur, bloqué)
+    blocks: HashSet<(i64, i64)>,
+    /// Administrateurs globaux
+    admins: HashSet<i64>,
+    /// Politiques de messages directs (`everyone` par défaut)
+    dm_privacy: HashMap<i64, DmPrivacy>,
+    /// Messages épinglés et leur position d'affichage
+    pins: HashMap<i64, Option<i32>>,
+    /// Destinataires individuels des mentions, par message
+    mentions: HashMap<i64, Vec<i64>>,
+    /// Réactions (message, réacteur, emoji, date), dans l'ordre d'ajout
+    reactions: Vec<(i64, i64, String, DateTime<Utc>)>,
+    /// Blocages DM masquant les réactions (bloqueur, bloqué)
+    reaction_hiders: HashSet<(i64, i64)>,
+    last_id: i64,
+}
+
+/// Dossier de violations (équivalent d'une ligne de `user_violations`)
+#[derive(Debug, Default)]
+struct MemoryViolations {
+    /// Compteur et date de la dernière violation
+    last: Option<(u32, DateTime<Utc>)>,
+    deadlines: SanctionDeadlines,
+}
+
+impl MemoryState {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn active_membership(&self, room_id: i64, user_id: i64) -> Option<&MemoryMembership> {
+        self.memberships.iter().find(|m| m.room_id == room_id && m.user_id == user_id && m.is_active())
+    }
+
+    /// Conversations actives de l'utilisateur
+    fn user_rooms(&self, user_id: i64) -> Vec<i64> {
+        self.memberships.iter()
+            .filter(|m| m.user_id == user_id && m.is_active())
+            .map(|m| m.room_id)
+            .collect()
+    }
+
+    /// Conversation DM de la paire, créée si absente
+    fn dm_conversation(&mut self, user_a: i64, user_b: i64) -> DmConversation {
+        let pair = (user_a.min(user_b), user_a.max(user_b));
+        if let Some(conversation) = self.dm_conversations.get(&pair) {
+            return conversation.clone();
+        }
+        let now = Utc::now();
+        let conversation = DmConversation {
+            id: self.next_id(),
+            uuid: Uuid::new_v4(),
+            user1_id: pair.0,
+            user2_id: pair.1,
+            is_blocked: false,
+            blocked_by: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.dm_conversations.insert(pair, conversation.clone());
+        conversation
+    }
+
+    /// Auteur du message si `viewer_id` y a accès (même règle que
+    /// `reactions::message_access_query`)
+    fn accessible_author(&self, message_id: i64, viewer_id: i64) -> Option<i64> {
+        if let Some(message) = self.messages.iter().find(|message| message.id == message_id) {
+            let author = message.author_id == viewer_id;
+            let in_room = self.rooms.get(&message.room_id).is_some_and(|room| room.is_public)
+                || self.active_membership(message.room_id, viewer_id).is_some();
+            let visible = message.visible_to.as_ref().is_none_or(|ids| ids.contains(&viewer_id));
+            return ((in_room || author) && (visible || author) && (message.held_reason.is_none() || author))
+                .then_some(message.author_id);
+        }
+        let message = self.dm_messages.iter().find(|message| message.id == message_id)?;
+        self.dm_conversations.values()
+            .any(|c| c.id == message.room_id && (c.user1_id == viewer_id || c.user2_id == viewer_id))
+            .then_some(message.author_id)
+    }
+
+    /// Droit d'éditer ou de supprimer un message du salon (`channels::check_room_modification`)
+    ///
+    /// Le rôle global est `admin` pour les administrateurs déclarés, `user` sinon.
+    fn check_modification(&self, ctx: WriteContext<'_>, room_id: i64, message_id: i64, user_id: i64, action: MessageAction) -> Result<()> {
+        let Some(message) = self.messages.iter()
+            .find(|m| m.id == message_id && m.room_id == room_id && m.deleted_at.is_none()) else {
+            return Err(ChatError::not_found("message", &message_id.to_string()));
+        };
+        let member_role = self.active_membership(room_id, user_id).map(|m| m.role.as_str());
+        let user_role = if self.admins.contains(&user_id) { "admin" } else { "user" };
+        let modification = ModificationContext {
+            is_author: message.author_id == user_id,
+            is_moderator: member_role.is_some_and(is_moderator_role),
+            created_at: message.created_at,
+            reaction_count: self.reactions.iter().filter(|(id, ..)| *id == message_id).count() as i64,
+            reply_count: self.messages.iter().filter(|reply| reply.parent_message_id == Some(message_id)).count() as i64,
+        };
+        check_room_modification(
+            ctx.moderation,
+            &Role::effective(user_role, member_role.unwrap_or("member")),
+            member_role,
+            &modification,
+            &MessagePolicy::from_limits(ctx.limits),
+            action,
+            Utc::now(),
+        )
+    }
+
+    /// Destinataires individuels des mentions, comme `mentions::resolve_room_mentions`
+    fn resolve_mentions(&self, max_mentions: usize, room_id: i64, author_id: i64, mentions: &[ParsedMention]) -> ResolvedMentions {
+        let mut resolved: Vec<(i64, MentionKind)> = Vec::new();
+        for mention in mentions {
+            let mut members: Vec<&MemoryMembership> = self.memberships.iter()
+                .filter(|m| m.room_id == room_id && m.is_active())
+                .filter(|m| match mention.kind {
+                    MentionKind::User => self.usernames.get(&m.user_id) == Some(&mention.target),
+                    MentionKind::Role => m.role == mention.target,
+                    MentionKind::Everyone | MentionKind::Here => false,
+                })
+                .collect();
+            members.sort_by_key(|m| m.joined_at);
+            resolved.extend(members.into_iter().take(max_mentions + 1).map(|m| (m.user_id, mention.kind.clone())));
+        }
+        ResolvedMentions {
+            recipients: collect_recipients(resolved, author_id, max_mentions),
+            everyone: mentions.iter().any(|m| m.kind == MentionKind::Everyone),
+            here: mentions.iter().any(|m| m.kind == MentionKind::Here),
+        }
+    }
+
+    /// Membres actifs, hors auteur, dont le marqueur atteint le message
+    fn readers(&self, room_id: i64, message_id: i64, author_id: i64) -> impl Iterator<Item = &MemoryMembership> {
+        self.memberships.iter().filter(move |m| {
+            m.room_id == room_id && m.is_active() && m.user_id != author_id
+                && m.last_read_message_id.is_some_and(|last_read| last_read >= message_id)
+        })
+    }
+
+    /// Même règle que `direct_messages::dm_allowed`
+    fn dm_allowed(&self, sender_id: i64, recipient_id: i64) -> bool {
+        let sender_rooms = self.user_rooms(sender_id);
+        let shares_room = self.user_rooms(recipient_id).iter().any(|room| sender_rooms.contains(room));
+        self.dm_privacy.get(&recipient_id).copied().unwrap_or_default().allows(shares_room)
+    }
+
+    /// Occupation calculée sur les fichiers du propriétaire
+    fn storage_usage(&self, owner_id: i64, limits: &LimitsConfig) -> StorageUsage {
+        let (used_bytes, file_count) = self.files.values()
+            .filter(|(owner, _)| *owner == owner_id)
+            .fold((0, 0), |(bytes, count), (_, size)| (bytes + size, count + 1));
+        StorageUsage::new(used_bytes, file_count, limits)
+    }
+
+    fn username(&self, user_id: i64) -> String {
+        self.usernames.get(&user_id).cloned().unwrap_or_else(|| format!("user{}", user_id))
+    }
+}
+
+/// Dépôt des salons en mémoire, identifiants croissants à partir de 1
+///
+/// Les salons, utilisateurs et adhésions se déclarent avec `create_room`,
+/// `add_user` et `add_member`, les contacts et blocages avec `add_contact` et
+/// `block_user`, la confidentialité des DM avec `set_dm_privacy`, les
+/// administrateurs globaux avec `add_admin`, les clés E2EE avec
+/// `add_key_bundle`, les fichiers téléversés avec `add_file`, les marqueurs
+/// de lecture avec `set_read_state`, les réactions avec `add_reaction`. Un
+/// salon est public sauf `set_private` ; sa liste « vu par » se règle avec
+/// `set_seen_by_mode`. Les mentions de salon sont résolues à l'envoi et à
+/// l'édition (`mentioned_users`), pas à l'approbation d'un message retenu.
+/// L'occupation du stockage est la somme des fichiers de l'utilisateur,
+/// déclarés ou téléversés.
+/// Politique de publication avec `set_post_policy` ; les réactions ajoutées
+/// par `reactions::add_reaction` suivent la même règle que le serveur, sans
+/// palette, émojis personnalisés ni règles de mise en avant.
+/// Ni citations, ni chiffrement au repos, ni présence des correspondants DM
+/// (toujours hors ligne), ni dédoublonnage des messages directs, et rien
+/// n'est audité.
+///
+/// Les autres dépôts en mémoire (`repositories`) partagent son état : ce
+/// qui est déclaré ici vaut pour les DM, réactions, fichiers et sanctions.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRoomRepository {
+    state: Arc<RwLock<MemoryState>>,
+}
+
+/// Conversations privées en mémoire (voir `InMemoryRoomRepository`)
+#[derive(Debug, Clone)]
+pub struct InMemoryDmRepository {
+    state: Arc<RwLock<MemoryState>>,
+}
+
+/// Réactions en mémoire (voir `InMemoryRoomRepository`)
+#[derive(Debug, Clone)]
+pub struct InMemoryReactionRepository {
+    state: Arc<RwLock<MemoryState>>,
+}
+
+/// Fichiers téléversés en mémoire (voir `InMemoryRoomRepository`)
+#[derive(Debug, Clone)]
+pub struct InMemoryAttachmentRepository {
+    state: Arc<RwLock<MemoryState>>,
+}
+
+/// Violations et sanctions en mémoire (voir `InMemoryRoomRepository`)
+#[derive(Debug, Clone)]
+pub struct InMemoryModerationRepository {
+    state: Arc<RwLock<MemoryState>>,
+}
+
+impl InMemoryRoomRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Jeu complet de dépôts en mémoire sur l'état de ce dépôt
+    pub fn repositories(&self) -> Repositories {
+        Repositories {
+            rooms: Arc::new(self.clone()),
+            dms: Arc::new(InMemoryDmRepository { state: self.state.clone() }),
+            reactions: Arc::new(InMemoryReactionRepository { state: self.state.clone() }),
+            attachments: Arc::new(InMemoryAttachmentRepository { state: self.state.clone() }),
+            moderation: Arc::new(InMemoryModerationRepository { state: self.state.clone() }),
+        }
+    }
+
+    /// Salon public ouvert, filtre désactivé et fermé aux invités
+    pub async fn create_room(&self, room_id: i64, name: &str) {
+        let name = RoomId::new(name).expect("nom de salon invalide");
+        self.state.write().await.rooms.insert(room_id, MemoryRoom {
+            id: room_id,
+            name,
+            is_public: true,
+            created_at: Utc::now(),
+            is_archived: false,
+            post_policy: RoomPostPolicy::default(),
+            filter_mode: RoomFilterMode::default(),
+            guest_access: GuestAccess::default(),
+            seen_by_mode: SeenByMode::default(),
+        });
+    }
+
+    /// Nom d'utilisateur affiché dans les messages relus (`user<id>` sinon)
+    pub async fn add_user(&self, user_id: i64, username: &str) {
+        self.state.write().await.usernames.insert(user_id, username.to_string());
+    }
+
+    /// Adhésion active au salon avec le rôle donné (`member`, `moderator`, ...)
+    pub async fn add_member(&self, room_id: i64, user_id: i64, role: &str) {
+        let mut state = self.state.write().await;
+        if let Some(id) = state.active_membership(room_id, user_id).map(|m| m.id) {
+            let membership = state.memberships.iter_mut().find(|m| m.id == id).expect("adhésion connue");
+            membership.role = role.to_string();
+            return;
+        }
+        let id = state.next_id();
+        state.memberships.push(MemoryMembership {
+            id,
+            room_id,
+            user_id,
+            role: role.to_string(),
+            joined_at: Utc::now(),
+            left_at: None,
+            last_read_message_id: None,
+            is_muted: false,
+        });
+    }
+
+    /// Marqueur de lecture et sourdine d'un membre actif du salon
+    pub async fn set_read_state(&self, room_id: i64, user_id: i64, last_read_message_id: Option<i64>, is_muted: bool) {
+        let mut state = self.state.write().await;
+        if let Some(membership) = state.memberships.iter_mut()
+            .find(|m| m.room_id == room_id && m.user_id == user_id && m.is_active()) {
+            membership.last_read_message_id = last_read_message_id;
+            membership.is_muted = is_muted;
+        }
+    }
+
+    /// Départ du salon : l'adhésion est close
+    pub async fn remove_member(&self, room_id: i64, user_id: i64) {
+        let mut state = self.state.write().await;
+        if let Some(membership) = state.memberships.iter_mut()
+            .find(|m| m.room_id == room_id && m.user_id == user_id && m.is_active()) {
+            membership.left_at = Some(Utc::now());
+        }
+    }
+
+    /// Salon privé : visible dans l'annuaire de ses seuls membres
+    pub async fn set_private(&self, room_id: i64) {
+        if let Some(room) = self.state.write().await.rooms.get_mut(&room_id) {
+            room.is_public = false;
+        }
+    }
+
+    /// Politique de publication du salon (`everyone` par défaut)
+    pub async fn set_post_policy(&self, room_id: i64, policy: RoomPostPolicy) {
+        if let Some(room) = self.state.write().await.rooms.get_mut(&room_id) {
+            room.post_policy = policy;
+        }
+    }
+
+    /// Mode de filtrage du salon (`off` par défaut)
+    pub async fn set_filter_mode(&self, room_id: i64, mode: RoomFilterMode) {
+        if let Some(room) = self.state.write().await.rooms.get_mut(&room_id) {
+            room.filter_mode = mode;
+        }
+    }
+
+    /// Visibilité de la liste « vu par » du salon (`count_only` par défaut)
+    pub async fn set_seen_by_mode(&self, room_id: i64, mode: SeenByMode) {
+        if let Some(room) = self.state.write().await.rooms.get_mut(&room_id) {
+            room.seen_by_mode = mode;
+        }
+    }
+
+    /// Accès des invités au salon (`closed` par défaut)
+    pub async fn set_guest_access(&self, room_id: i64, access: GuestAccess) {
+        if let Some(room) = self.state.write().await.rooms.get_mut(&room_id) {
+            room.guest_access = access;
+        }
+    }
+
+    /// Conversation DM entre deux utilisateurs
+    pub async fn add_contact(&self, user_a: i64, user_b: i64) {
+        self.state.write().await.dm_conversation(user_a, user_b);
+    }
+
+    /// Qui peut envoyer des messages directs à l'utilisateur
+    pub async fn set_dm_privacy(&self, user_id: i64, privacy: DmPrivacy) {
+        self.state.write().await.dm_privacy.insert(user_id, privacy);
+    }
+
+    /// Administrateur global (rôle `admin`)
+    pub async fn add_admin(&self, user_id: i64) {
+        self.state.write().await.admins.insert(user_id);
+    }
+
+    /// Blocage de `blocked_id` par `blocker_id`
+    pub async fn block_user(&self, blocker_id: i64, blocked_id: i64) {
+        self.state.write().await.blocks.insert((blocker_id, blocked_id));
+    }
+
+    /// Réaction de `user_id` à un message de salon ou direct
+    pub async fn add_reaction(&self, message_id: i64, user_id: i64, emoji: &str) {
+        self.state.write().await.reactions.push((message_id, user_id, emoji.to_string(), Utc::now()));
+    }
+
+    /// Clés E2EE publiées par l'utilisateur, clés à usage unique comprises
+    pub async fn add_key_bundle(&self, user_id: i64, mut upload: KeyBundleUpload) {
+        upload.one_time_prekeys.sort_by_key(|prekey| prekey.key_id);
+        self.state.write().await.key_bundles.insert(user_id, upload);
+    }
+
+    /// Fichier téléversé par `owner_id`, pièce jointe possible de ses messages
+    pub async fn add_file(&self, file_id: i64, owner_id: i64, size_bytes: u64) {
+        self.state.write().await.files.insert(file_id, (owner_id, size_bytes));
+    }
+
+    pub async fn is_archived(&self, room_id: i64) -> bool {
+        self.state.read().await.rooms.get(&room_id).is_some_and(|room| room.is_archived)
+    }
+
+    /// Historique d'un salon, du plus ancien au plus récent (messages retenus ou supprimés exclus)
+    pub async fn room_history(&self, room_id: i64) -> Vec<StoredMessage> {
+        self.state.read().await.messages.iter()
+            .filter(|message| message.room_id == room_id && message.held_reason.is_none() && message.deleted_at.is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Destinataires individuels enregistrés pour les mentions du message
+    pub async fn mentioned_users(&self, message_id: i64) -> Vec<i64> {
+        self.state.read().await.mentions.get(&message_id).cloned().unwrap_or_default()
+    }
+
+    /// Messages directs de la conversation, du plus ancien au plus récent
+    pub async fn dm_history(&self, conversation_id: i64) -> Vec<StoredMessage> {
+        self.state.read().await.dm_messages.iter()
+            .filter(|message| message.room_id == conversation_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Messages d'invités conservés (`guests.persist_messages`)
+    pub async fn guest_messages(&self, room_id: i64) -> Vec<StoredMessage> {
+        self.state.read().await.guest_messages.iter()
+            .filter(|message| message.room_id == room_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Nombre de messages de salon stockés, retenus et supprimés compris
+    pub async fn len(&self) -> usize {
+        self.state.read().await.messages.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.state.read().await.messages.is_empty()
+    }
+}
+
+/// Borne exclue du rattrapage, comme `(changed_at, id) > ($2, $3)`
+fn after_bound(at: DateTime<Utc>, id: i64, bound: ChangeBound) -> bool {
+    (at, id) > bound
+}
+
+/// Message stocké sous le nonce de la clé (index `idx_messages_client_nonce`)
+fn same_nonce(message: &StoredMessage, key: &DedupKey) -> bool {
+    message.dedup_key.as_ref().is_some_and(|stored| {
+        stored.author_id == key.author_id && stored.conversation_id == key.conversation_id && stored.nonce == key.nonce
+    })
+}
+
+impl RoomRepository for InMemoryRoomRepository {
+    fn posting_context<'a>(&'a self, room_id: i64, user_id: i64) -> BoxFuture<'a, Result<Option<PostingContext>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let (Some(membership), Some(room)) = (state.active_membership(room_id, user_id), state.rooms.get(&room_id)) else {
+                return Ok(None);
+            };
+            Ok(Some(PostingContext {
+                member_role: membership.role.clone(),
+                post_policy: room.post_policy,
+                slow_mode: Default::default(),
+                is_archived: room.is_archived,
+                filter_mode: room.filter_mode,
+                languages: Vec::new(),
+                filter_allowlist: Vec::new(),
+                max_attachments: None,
+                max_attachments_size: None,
+                encrypt_at_rest: false,
+            }))
+        })
+    }
+
+    fn count_members<'a>(&'a self, room_id: i64, user_ids: &'a [i64]) -> BoxFuture<'a, Result<i64>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            Ok(user_ids.iter().filter(|user_id| state.active_membership(room_id, **user_id).is_some()).count() as i64)
+        })
+    }
+
+    fn member_ids<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Vec<i64>>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.memberships.iter()
+                .filter(|m| m.room_id == room_id && m.is_active())
+                .map(|m| m.user_id)
+                .collect())
+        })
+    }
+
+    fn persisted_memberships<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<Vec<PersistedMembership>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let mut memberships: Vec<&MemoryMembership> = state.memberships.iter()
+                .filter(|m| m.user_id == user_id && m.is_active())
+                .collect();
+            memberships.sort_by_key(|m| (m.joined_at, m.id));
+            Ok(memberships.into_iter()
+                .filter_map(|m| state.rooms.get(&m.room_id))
+                .filter(|room| !room.is_archived)
+                .map(|room| PersistedMembership { room_id: room.id, room_name: room.name.clone() })
+                .collect())
+        })
+    }
+
+    fn guest_access<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<GuestAccess>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.rooms.get(&room_id)
+                .filter(|room| !room.is_archived)
+                .map_or(GuestAccess::Closed, |room| room.guest_access))
+        })
+    }
+
+    fn guest_posting_context<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Option<PostingContext>>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.rooms.get(&room_id)
+                .filter(|room| !room.is_archived)
+                .map(|room| PostingContext {
+                    member_role: GUEST_ROLE.to_string(),
+                    post_policy: room.post_policy,
+                    slow_mode: Default::default(),
+                    is_archived: false,
+                    filter_mode: room.filter_mode,
+                    languages: Vec::new(),
+                    filter_allowlist: Vec::new(),
+                    max_attachments: None,
+                    max_attachments_size: None,
+                    encrypt_at_rest: false,
+                }))
+        })
+    }
+
+    fn seen_by_context<'a>(&'a self, room_id: i64, message_id: i64, requester_id: i64) -> BoxFuture<'a, Result<Option<(SeenByMode, i64)>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let (Some(room), Some(_)) = (state.rooms.get(&room_id), state.active_membership(room_id, requester_id)) else {
+                return Ok(None);
+            };
+            Ok(state.messages.iter()
+                .find(|message| message.id == message_id && message.room_id == room_id && message.deleted_at.is_none())
+                .map(|message| (room.seen_by_mode, message.author_id)))
+        })
+    }
+
+    fn count_seen_by<'a>(&'a self, room_id: i64, message_id: i64, author_id: i64) -> BoxFuture<'a, Result<i64>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            Ok(state.readers(room_id, message_id, author_id).count() as i64)
+        })
+    }
+
+    fn list_seen_by<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        author_id: i64,
+        after_user_id: i64,
+        limit: i64
+    ) -> BoxFuture<'a, Result<Vec<SeenByMember>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let mut readers: Vec<SeenByMember> = state.readers(room_id, message_id, author_id)
+                .filter(|membership| membership.user_id > after_user_id)
+                .map(|membership| SeenByMember {
+                    user_id: membership.user_id,
+                    username: state.username(membership.user_id),
+                    last_read_message_id: membership.last_read_message_id.unwrap_or_default(),
+                })
+                .collect();
+            readers.sort_by_key(|reader| reader.user_id);
+            readers.truncate(limit as usize);
+            Ok(readers)
+        })
+    }
+
+    fn list_directory<'a>(
+        &'a self,
+        requester_id: i64,
+        filter: &'a RoomFilter,
+        limit: i64,
+        cursor: Option<RoomCursor>
+    ) -> BoxFuture<'a, Result<Vec<RoomInfo>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            // Aucun administrateur global en mémoire
+            let mut rooms: Vec<RoomInfo> = state.rooms.values()
+                .filter(|room| !room.is_archived)
+                .map(|room| RoomInfo {
+                    id: room.id,
+                    name: room.name.as_str().to_string(),
+                    topic: None,
+                    member_count: state.memberships.iter().filter(|m| m.room_id == room.id && m.is_active()).count() as i64,
+                    is_public: room.is_public,
+                    is_member: state.active_membership(room.id, requester_id).is_some(),
+                    last_activity_at: state.messages.iter()
+                        .filter(|message| message.room_id == room.id && message.deleted_at.is_none())
+                        .map(|message| message.created_at)
+                        .max()
+                        .unwrap_or(room.created_at),
+                })
+                .filter(|room| filter.admits(room, false))
+                .filter(|room| cursor.map_or(true, |c| (filter.order.sort_key(room), room.id) < (c.sort_key, c.room_id)))
+                .collect();
+            rooms.sort_by_key(|room| std::cmp::Reverse((filter.order.sort_key(room), room.id)));
+            rooms.truncate(limit.max(0) as usize);
+            Ok(rooms)
+        })
+    }
+
+    fn unread_counts<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<(Vec<RoomUnread>, Vec<DmUnread>)>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let rooms = state.memberships.iter()
+                .filter(|m| m.user_id == user_id && m.is_active())
+                .filter_map(|m| {
+                    let unread_count = state.messages.iter()
+                        .filter(|message| message.room_id == m.room_id && message.author_id != user_id)
+                        .filter(|message| message.id > m.last_read_message_id.unwrap_or(0))
+                        .filter(|message| message.deleted_at.is_none() && message.held_reason.is_none())
+                        .filter(|message| message.visible_to.as_ref().is_none_or(|ids| ids.contains(&user_id)))
+                        .count() as i64;
+                    (unread_count > 0).then_some(RoomUnread { room_id: m.room_id, unread_count, is_muted: m.is_muted })
+                })
+                .collect();
+            // Pas de DM en mémoire
+            Ok((rooms, Vec::new()))
+        })
+    }
+
+    fn find_duplicate<'a>(&'a self, key: &'a DedupKey, window: Duration) -> BoxFuture<'a, Result<Option<SentMessage>>> {
+        Box::pin(async move {
+            let cutoff = chrono::Duration::from_std(window).ok().and_then(|window| Utc::now().checked_sub_signed(window));
+            let mut state = self.state.write().await;
+            let mut existing = None;
+            for message in state.messages.iter_mut().filter(|message| same_nonce(message, key)) {
+                // Nonce libéré hors fenêtre ou message supprimé, comme `dedup::find_duplicate`
+                if message.deleted_at.is_some() || cutoff.is_some_and(|cutoff| message.created_at <= cutoff) {
+                    message.dedup_key = None;
+                } else {
+                    existing = Some(message);
+                }
+            }
+            let Some(message) = existing else {
+                return Ok(None);
+            };
+            key.check_retry(message.dedup_key.as_ref().map(|stored| stored.content_hash.as_slice()))?;
+            Ok(Some(SentMessage { id: message.id, created_at: message.created_at }))
+        })
+    }
+
+    fn insert_message<'a>(&'a self, message: NewRoomMessage<'a>, ctx: WriteContext<'a>) -> BoxFuture<'a, Result<InsertedRoomMessage>> {
+        Box::pin(async move {
+            // Contrôles de `mentions::process_room_mentions`, hors message retenu
+            if message.hold_reason.is_none() && !message.mentions.is_empty() {
+                check_mass_mention_permission(message.mentions, message.member_role, message.room_id)?;
+                if message.mentions.iter().any(|m| m.kind.is_mass()) {
+                    ctx.check_mass_mention_limit(message.author_id).await?;
+                }
+            }
+            let mut state = self.state.write().await;
+            // Index unique des nonces : un renvoi simultané reçoit la ligne existante
+            if let Some(key) = &message.dedup_key {
+                if let Some(stored) = state.messages.iter().find(|stored| same_nonce(stored, key)) {
+                    key.check_retry(stored.dedup_key.as_ref().map(|stored| stored.content_hash.as_slice()))?;
+                    return Ok(InsertedRoomMessage {
+                        id: stored.id,
+                        created_at: stored.created_at,
+                        quote: None,
+                        mentions: ResolvedMentions::default(),
+                        duplicate: true,
+                    });
+                }
+            }
+            let stored = StoredMessage {
+                id: state.next_id(),
+                room_id: message.room_id,
+                author_id: message.author_id,
+                content: message.content.stored.clone(),
+                parent_message_id: message.parent_message_id,
+                visible_to: message.visible_to,
+                metadata: message.metadata,
+                created_at: Utc::now(),
+                held_reason: message.hold_reason.map(str::to_string),
+                deleted_at: None,
+                dedup_key: message.dedup_key,
+            };
+            // Message retenu : mentions traitées à l'approbation
+            let mentions = if stored.held_reason.is_some() {
+                ResolvedMentions::default()
+            } else {
+                state.resolve_mentions(ctx.limits.max_mentions_per_message, stored.room_id, stored.author_id, message.mentions)
+            };
+            state.mentions.insert(stored.id, mentions.recipients.iter().map(|r| r.user_id).collect());
+            let inserted = InsertedRoomMessage {
+                id: stored.id,
+                created_at: stored.created_at,
+                quote: None,
+                mentions,
+                duplicate: false,
+            };
+            state.messages.push(stored);
+            Ok(inserted)
+        })
+    }
+
+    fn insert_guest_message<'a>(&'a self, room_id: i64, guest_id: i32, _guest_name: &'a str, content: &'a str) -> BoxFuture<'a, Result<SentMessage>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let stored = StoredMessage {
+                id: state.next_id(),
+                room_id,
+                author_id: guest_id as i64,
+                content: content.to_string(),
+                parent_message_id: None,
+                visible_to: None,
+                metadata: Value::Null,
+                created_at: Utc::now(),
+                held_reason: None,
+                deleted_at: None,
+                dedup_key: None,
+            };
+            let sent = SentMessage { id: stored.id, created_at: stored.created_at };
+            state.guest_messages.push(stored);
+            Ok(sent)
+        })
+    }
+
+    fn default_rooms<'a>(&'a self, names: &'a [RoomId]) -> BoxFuture<'a, Result<Vec<DefaultRoom>>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.rooms.values()
+                .filter(|room| !room.is_archived && names.contains(&room.name))
+                .map(|room| DefaultRoom { id: room.id, name: room.name.clone() })
+                .collect())
+        })
+    }
+
+    fn muted_members<'a>(&'a self, room_id: i64, user_ids: &'a [i64]) -> BoxFuture<'a, Result<HashSet<i64>>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.memberships.iter()
+                .filter(|m| m.room_id == room_id && m.is_active() && m.is_muted && user_ids.contains(&m.user_id))
+                .map(|m| m.user_id)
+                .collect())
+        })
+    }
+
+    fn known_rooms<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<(HashSet<i64>, i64)>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let memberships = state.memberships.iter().filter(|m| m.user_id == user_id);
+            let known_rooms = memberships.clone().map(|m| m.room_id).collect();
+            let active_room_count = memberships
+                .filter(|m| m.is_active() && state.rooms.get(&m.room_id).is_some_and(|room| !room.is_archived))
+                .count() as i64;
+            Ok((known_rooms, active_room_count))
+        })
+    }
+
+    fn add_room_member<'a>(&'a self, room_id: i64, user_id: i64, _audit: &'a AuditSink) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let room = state.rooms.get(&room_id)
+                .ok_or_else(|| ChatError::not_found("salon", &room_id.to_string()))?;
+            if room.is_archived {
+                return Err(ChatError::ConversationArchived { id: room_id.to_string() });
+            }
+            if state.active_membership(room_id, user_id).is_some() {
+                return Err(ChatError::configuration_error("Utilisateur déjà membre du salon"));
+            }
+            let id = state.next_id();
+            state.memberships.push(MemoryMembership {
+                id,
+                room_id,
+                user_id,
+                role: "member".to_string(),
+                joined_at: Utc::now(),
+                left_at: None,
+                last_read_message_id: None,
+                is_muted: false,
+            });
+            Ok(())
+        })
+    }
+
+    fn edit_message<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        user_id: i64,
+        new_content: &'a str,
+        mentions: &'a [ParsedMention],
+        ctx: WriteContext<'a>
+    ) -> BoxFuture<'a, Result<EditedMessage>> {
+        Box::pin(async move {
+            let (old_content, member_role) = {
+                let state = self.state.read().await;
+                state.check_modification(ctx, room_id, message_id, user_id, MessageAction::Edit)?;
+                let message = state.messages.iter().find(|m| m.id == message_id).expect("message vérifié");
+                let member_role = state.active_membership(room_id, user_id).map_or("member", |m| m.role.as_str()).to_string();
+                (message.content.clone(), member_role)
+            };
+
+            // Seules les mentions de masse ajoutées sont contrôlées, comme `update_room_mentions`
+            let added_mass = added_mass_mentions(&old_content, mentions);
+            if !added_mass.is_empty() {
+                check_mass_mention_permission(&added_mass, &member_role, room_id)?;
+                ctx.check_mass_mention_limit(user_id).await?;
+            }
+
+            let mut state = self.state.write().await;
+            let message = state.messages.iter_mut().find(|m| m.id == message_id).expect("message vérifié");
+            message.content = new_content.to_string();
+            let (author_id, visible_to, is_held) = (message.author_id, message.visible_to.clone(), message.held_reason.is_some());
+
+            // Les mentions d'un message retenu ne sont traitées qu'à l'approbation
+            let added_mentions = (!is_held).then(|| {
+                let resolved = state.resolve_mentions(ctx.limits.max_mentions_per_message, room_id, author_id, mentions);
+                let existing = state.mentions.remove(&message_id).unwrap_or_default();
+                let diff = diff_mentions(&existing, &resolved.recipients);
+                state.mentions.insert(message_id, resolved.recipients.iter().map(|r| r.user_id).collect());
+                ResolvedMentions {
+                    recipients: diff.added,
+                    everyone: added_mass.iter().any(|m| m.kind == MentionKind::Everyone),
+                    here: added_mass.iter().any(|m| m.kind == MentionKind::Here),
+                }
+            });
+            Ok(EditedMessage { visible_to, is_held, added_mentions })
+        })
+    }
+
+    fn delete_message<'a>(&'a self, room_id: i64, message_id: i64, user_id: i64, ctx: WriteContext<'a>) -> BoxFuture<'a, Result<DeletedMessage>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            state.check_modification(ctx, room_id, message_id, user_id, MessageAction::Delete)?;
+            let message = state.messages.iter_mut().find(|m| m.id == message_id).expect("message vérifié");
+            message.deleted_at = Some(Utc::now());
+            let deleted = DeletedMessage {
+                author_id: message.author_id,
+                visible_to: message.visible_to.clone(),
+                is_held: message.held_reason.is_some(),
+            };
+            state.pins.remove(&message_id);
+            Ok(deleted)
+        })
+    }
+
+    fn set_pinned<'a>(
+        &'a self,
+        room_id: i64,
+        message_id: i64,
+        user_id: i64,
+        pin: bool,
+        _pinned_until: Option<DateTime<Utc>>,
+        _audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            check_pin_rights(state.active_membership(room_id, user_id).map(|m| m.role.as_str()), "pin_message")?;
+            if !state.messages.iter().any(|m| m.id == message_id && m.room_id == room_id && (!pin || m.deleted_at.is_none())) {
+                return Err(ChatError::not_found("message", &message_id.to_string()));
+            }
+            if pin {
+                state.pins.insert(message_id, None);
+            } else {
+                state.pins.remove(&message_id);
+            }
+            Ok(())
+        })
+    }
+
+    fn reorder_pins<'a>(&'a self, room_id: i64, actor_id: i64, ordered_message_ids: &'a [i64], _audit: &'a AuditSink) -> BoxFuture<'a, Result<Vec<i64>>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            check_pin_rights(state.active_membership(room_id, actor_id).map(|m| m.role.as_str()), "reorder_pins")?;
+
+            // Positions d'abord, puis les épingles sans position de la plus récente à la plus ancienne
+            let mut pinned: Vec<(Option<i32>, &StoredMessage)> = state.messages.iter()
+                .filter(|m| m.room_id == room_id)
+                .filter_map(|m| state.pins.get(&m.id).map(|order| (*order, m)))
+                .collect();
+            pinned.sort_by_key(|(order, m)| (order.is_none(), *order, std::cmp::Reverse((m.created_at, m.id))));
+            let current: Vec<i64> = pinned.iter().map(|(_, m)| m.id).collect();
+
+            let planned = plan_pin_order(&current, ordered_message_ids)?;
+            for (position, message_id) in planned.iter().enumerate() {
+                state.pins.insert(*message_id, Some(position as i32));
+            }
+            Ok(planned)
+        })
+    }
+
+    fn set_archived<'a>(&'a self, room_id: i64, user_id: i64, archived: bool, _audit: &'a AuditSink) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let member_role = state.active_membership(room_id, user_id).map(|m| m.role.clone());
+            let room = state.rooms.get_mut(&room_id)
+                .ok_or_else(|| ChatError::not_found("salon", &room_id.to_string()))?;
+
+            check_archive_change(&ArchiveState { is_archived: room.is_archived, member_role }, archived)?;
+            room.is_archived = archived;
+            Ok(())
+        })
+    }
+
+    fn review_held_message<'a>(
+        &'a self,
+        message_id: i64,
+        moderator_id: i64,
+        approve: bool,
+        _note: Option<&'a str>,
+        _ctx: WriteContext<'a>
+    ) -> BoxFuture<'a, Result<ReviewedMessage>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let index = state.messages.iter()
+                .position(|message| message.id == message_id && message.held_reason.is_some() && message.deleted_at.is_none())
+                .ok_or_else(|| ChatError::not_found("held_message", &message_id.to_string()))?;
+
+            let room_id = state.messages[index].room_id;
+            let can_review = state.active_membership(room_id, moderator_id).is_some_and(|m| is_moderator_role(&m.role));
+            check_review_rights(can_review)?;
+
+            let username = state.username(state.messages[index].author_id);
+            let message = &mut state.messages[index];
+            message.held_reason = None;
+            if !approve {
+                message.deleted_at = Some(Utc::now());
+            }
+
+            Ok(ReviewedMessage {
+                room_id,
+                author_id: message.author_id,
+                username,
+                content: message.content.clone(),
+                parent_message_id: message.parent_message_id,
+                visible_to: message.visible_to.clone(),
+                metadata: message.metadata.clone(),
+                created_at: message.created_at,
+                mentions: approve.then(ResolvedMentions::default),
+            })
+        })
+    }
+
+    fn missed_messages<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64, _cipher: Option<&'a MessageCipher>) -> BoxFuture<'a, Result<Vec<MessageChange>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let rooms = state.user_rooms(user_id);
+            let mut changes: Vec<MessageChange> = state.messages.iter()
+                .filter(|message| rooms.contains(&message.room_id))
+                .filter(|message| message.visible_to.as_ref().is_none_or(|ids| message.author_id == user_id || ids.contains(&user_id)))
+                .filter(|message| message.held_reason.is_none() || message.author_id == user_id)
+                .map(|message| MessageChange {
+                    id: message.id,
+                    conversation_id: message.room_id,
+                    author_id: message.author_id,
+                    username: state.username(message.author_id),
+                    content: message.content.clone(),
+                    parent_message_id: message.parent_message_id,
+                    e2ee_header: None,
+                    created_at: message.created_at,
+                    edited_at: None,
+                    deleted_at: message.deleted_at,
+                })
+                .filter(|change| { let (at, _, id) = change.key(); after_bound(at, id, after) })
+                .collect();
+            changes.sort_by_key(MessageChange::key);
+            changes.truncate(limit.max(0) as usize);
+            Ok(changes)
+        })
+    }
+
+    fn missed_reactions<'a>(&'a self, _user_id: i64, _after: ChangeBound, _limit: i64) -> BoxFuture<'a, Result<Vec<ReactionChange>>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    fn missed_memberships<'a>(&'a self, user_id: i64, after: ChangeBound, limit: i64) -> BoxFuture<'a, Result<Vec<MembershipChange>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let rooms = state.user_rooms(user_id);
+            let mut changes: Vec<MembershipChange> = state.memberships.iter()
+                .filter(|m| rooms.contains(&m.room_id))
+                .map(|m| MembershipChange {
+                    id: m.id,
+                    conversation_id: m.room_id,
+                    user_id: m.user_id,
+                    joined_at: m.joined_at,
+                    left_at: m.left_at,
+                })
+                .filter(|change| { let (at, _, id) = change.key(); after_bound(at, id, after) })
+                .collect();
+            changes.sort_by_key(MembershipChange::key);
+            changes.truncate(limit.max(0) as usize);
+            Ok(changes)
+        })
+    }
+}
+
+impl DmRepository for InMemoryDmRepository {
+    fn presence_relation<'a>(&'a self, viewer_id: i64, user_id: i64) -> BoxFuture<'a, Result<PresenceRelation>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let viewer_rooms = state.user_rooms(viewer_id);
+            Ok(PresenceRelation {
+                contact: state.dm_conversations.contains_key(&(viewer_id.min(user_id), viewer_id.max(user_id))),
+                shares_room: state.user_rooms(user_id).iter().any(|room| viewer_rooms.contains(room)),
+                blocked: state.blocks.contains(&(viewer_id, user_id)) || state.blocks.contains(&(user_id, viewer_id)),
+            })
+        })
+    }
+
+    fn claim_key_bundle<'a>(&'a self, requester_id: i64, target_id: i64) -> BoxFuture<'a, Result<Option<(KeyBundle, i64)>>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            if requester_id != target_id && !state.dm_allowed(requester_id, target_id) {
+                return Ok(None);
+            }
+            let Some(upload) = state.key_bundles.get_mut(&target_id) else {
+                return Ok(None);
+            };
+            let one_time_prekey = (requester_id != target_id && !upload.one_time_prekeys.is_empty())
+                .then(|| upload.one_time_prekeys.remove(0));
+            Ok(Some((KeyBundle {
+                user_id: target_id,
+                identity_key: upload.identity_key.clone(),
+                signed_prekey_id: upload.signed_prekey_id,
+                signed_prekey: upload.signed_prekey.clone(),
+                signed_prekey_signature: upload.signed_prekey_signature.clone(),
+                one_time_prekey,
+            }, upload.one_time_prekeys.len() as i64)))
+        })
+    }
+
+    fn dm_target<'a>(&'a self, user_id: i64, target_id: i64) -> BoxFuture<'a, Result<Option<(DmParticipant, StartEligibility)>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let Some(username) = state.usernames.get(&target_id) else {
+                return Ok(None);
+            };
+            let conversation_blocked = state.dm_conversations
+                .get(&(user_id.min(target_id), user_id.max(target_id)))
+                .is_some_and(|conversation| conversation.is_blocked);
+            Ok(Some((
+                DmParticipant { user_id: target_id, username: username.clone(), is_online: false, last_seen: None },
+                StartEligibility {
+                    target_exists: true,
+                    blocked_by_target: state.blocks.contains(&(target_id, user_id)),
+                    blocked_by_user: state.blocks.contains(&(user_id, target_id)),
+                    conversation_blocked,
+                },
+            )))
+        })
+    }
+
+    fn open_dm_conversation<'a>(&'a self, user1_id: i64, user2_id: i64, _audit: &'a AuditSink) -> BoxFuture<'a, Result<DmConversation>> {
+        Box::pin(async move {
+            Ok(self.state.write().await.dm_conversation(user1_id, user2_id))
+        })
+    }
+
+    fn dm_conversation<'a>(&'a self, conversation_id: i64, user_id: i64) -> BoxFuture<'a, Result<Option<DmConversation>>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.dm_conversations.values()
+                .find(|c| c.id == conversation_id && (c.user1_id == user_id || c.user2_id == user_id))
+                .cloned())
+        })
+    }
+
+    fn dm_allowed<'a>(&'a self, sender_id: i64, recipient_id: i64) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.dm_allowed(sender_id, recipient_id))
+        })
+    }
+
+    fn insert_dm_message<'a>(&'a self, message: NewDmMessage<'a>, _cipher: Option<&'a MessageCipher>) -> BoxFuture<'a, Result<InsertedDmMessage>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let stored = StoredMessage {
+                id: state.next_id(),
+                room_id: message.conversation_id,
+                author_id: message.author_id,
+                content: message.content.stored.clone(),
+                parent_message_id: message.parent_message_id,
+                visible_to: None,
+                metadata: message.metadata,
+                created_at: Utc::now(),
+                held_reason: None,
+                deleted_at: None,
+                dedup_key: message.dedup_key,
+            };
+            let inserted = InsertedDmMessage { id: stored.id, created_at: stored.created_at, quote: None, duplicate: false };
+            state.dm_messages.push(stored);
+            Ok(inserted)
+        })
+    }
+
+    fn dm_history_page<'a>(&'a self, conversation_id: i64, before_message_id: Option<i64>, limit: i64) -> BoxFuture<'a, Result<Vec<DmMessage>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let mut messages: Vec<&StoredMessage> = state.dm_messages.iter()
+                .filter(|message| message.room_id == conversation_id && before_message_id.is_none_or(|before| message.id < before))
+                .collect();
+            messages.sort_by_key(|message| std::cmp::Reverse((message.created_at, message.id)));
+            Ok(messages.into_iter().take(limit as usize).map(|message| {
+                let mut reactions: Vec<(&str, i64)> = Vec::new();
+                for (_, _, emoji, _) in state.reactions.iter().filter(|(id, ..)| *id == message.id) {
+                    match reactions.iter_mut().find(|(known, _)| *known == emoji) {
+                        Some((_, count)) => *count += 1,
+                        None => reactions.push((emoji, 1)),
+                    }
+                }
+                reactions.sort();
+                DmMessage {
+                    id: message.id,
+                    uuid: Uuid::from_u64_pair(0, message.id as u64),
+                    author_id: message.author_id,
+                    author_username: state.username(message.author_id),
+                    conversation_id,
+                    content: message.content.clone(),
+                    parent_message_id: message.parent_message_id,
+                    thread_count: state.dm_messages.iter().filter(|reply| reply.parent_message_id == Some(message.id)).count() as i32,
+                    status: if message.deleted_at.is_some() { "deleted" } else { "sent" }.to_string(),
+                    is_edited: false,
+                    edit_count: 0,
+                    is_pinned: state.pins.contains_key(&message.id),
+                    metadata: message.metadata.clone(),
+                    created_at: message.created_at,
+                    updated_at: message.created_at,
+                    edited_at: None,
+                    reactions: Some(Value::Array(reactions.into_iter()
+                        .map(|(emoji, count)| serde_json::json!({ "emoji": emoji, "count": count }))
+                        .collect())),
+                    mention_count: 0,
+                }
+            }).collect())
+        })
+    }
+
+    fn set_dm_block<'a>(
+        &'a self,
+        conversation_id: i64,
+        user_id: i64,
+        block: bool,
+        hide_reactions: bool,
+        history: BlockedDmHistory,
+        _audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let Some(conversation) = state.dm_conversations.values_mut()
+                .find(|c| c.id == conversation_id && (c.user1_id == user_id || c.user2_id == user_id)) else {
+                return Err(ChatError::unauthorized("block_dm_conversation"));
+            };
+            conversation.is_blocked = block;
+            conversation.blocked_by = block.then_some(user_id);
+            conversation.updated_at = Utc::now();
+            let other_id = if conversation.user1_id == user_id { conversation.user2_id } else { conversation.user1_id };
+
+            // Un seul réglage par conversation, comme `dm_conversations.hide_reactions`
+            state.reaction_hiders.remove(&(user_id, other_id));
+            state.reaction_hiders.remove(&(other_id, user_id));
+            if block && hide_reactions {
+                state.reaction_hiders.insert((user_id, other_id));
+            }
+            if block && history == BlockedDmHistory::Delete {
+                state.dm_messages.retain(|message| message.room_id != conversation_id);
+            }
+            Ok(())
+        })
+    }
+
+    // Aucun dédoublonnage des messages directs
+    fn find_duplicate<'a>(&'a self, _key: &'a DedupKey, _window: Duration) -> BoxFuture<'a, Result<Option<SentMessage>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+impl ReactionRepository for InMemoryReactionRepository {
+    fn message_reactions<'a>(&'a self, message_id: i64, viewer_id: i64) -> BoxFuture<'a, Result<Option<ReactionView>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            let Some(author_id) = state.accessible_author(message_id, viewer_id) else {
+                return Ok(None);
+            };
+            Ok(Some(ReactionView {
+                author_id,
+                hidden_by_viewer: state.reaction_hiders.iter()
+                    .filter(|(blocker, _)| *blocker == viewer_id)
+                    .map(|(_, blocked)| *blocked)
+                    .collect(),
+                reactions: state.reactions.iter()
+                    .filter(|(id, ..)| *id == message_id)
+                    .map(|(_, user_id, emoji, created_at)| (emoji.clone(), ReactionUser {
+                        user_id: *user_id,
+                        username: state.username(*user_id),
+                        created_at: *created_at,
+                    }))
+                    .collect(),
+            }))
+        })
+    }
+
+    fn insert_reaction<'a>(&'a self, message_id: i64, user_id: i64, emoji: &'a str, _audit: &'a AuditSink) -> BoxFuture<'a, Result<AddedReaction>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let Some(author_id) = state.accessible_author(message_id, user_id) else {
+                return Err(ChatError::unauthorized("add_reaction"));
+            };
+
+            // Même règle que `reactions::check_reaction_policy` ; messages directs ouverts
+            let mut audience = match state.messages.iter().find(|message| message.id == message_id) {
+                Some(message) => {
+                    let room_id = message.room_id;
+                    let policy = state.rooms.get(&room_id).map_or_else(RoomPostPolicy::default, |room| room.post_policy);
+                    let member_role = state.active_membership(room_id, user_id).map(|m| m.role.as_str());
+                    let allowed = match (policy, member_role) {
+                        (RoomPostPolicy::Everyone, _) => true,
+                        (_, Some(role)) => policy.can_react(role),
+                        (_, None) => false,
+                    };
+                    if !allowed {
+                        return Err(ChatError::unauthorized("add_reaction"));
+                    }
+                    let mut audience: Vec<i64> = state.memberships.iter()
+                        .filter(|m| m.room_id == room_id && m.is_active())
+                        .map(|m| m.user_id)
+                        .collect();
+                    audience.push(author_id);
+                    audience
+                }
+                None => {
+                    let conversation_id = state.dm_messages.iter()
+                        .find(|message| message.id == message_id)
+                        .map(|message| message.room_id);
+                    state.dm_conversations.values()
+                        .filter(|c| Some(c.id) == conversation_id)
+                        .flat_map(|c| [c.user1_id, c.user2_id])
+                        .collect()
+                }
+            };
+
+            let own = state.reactions.iter().filter(|(id, reactor, ..)| *id == message_id && *reactor == user_id);
+            if own.clone().count() >= 10 {
+                return Err(ChatError::configuration_error("Limite de réactions par message atteinte"));
+            }
+            if own.clone().any(|(.., existing, _)| existing == emoji) {
+                return Err(ChatError::configuration_error("Réaction déjà présente"));
+            }
+            state.reactions.push((message_id, user_id, emoji.to_string(), Utc::now()));
+
+            audience.sort_unstable();
+            audience.dedup();
+            Ok(AddedReaction { audience, highlight_rule: false })
+        })
+    }
+}
+
+impl AttachmentRepository for InMemoryAttachmentRepository {
+    fn stored_file_sizes<'a>(&'a self, owner_id: i64, file_ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<(i64, u64)>>> {
+        Box::pin(async move {
+            let state = self.state.read().await;
+            Ok(file_ids.iter()
+                .filter_map(|id| state.files.get(id).filter(|(owner, _)| *owner == owner_id).map(|(_, size)| (*id, *size)))
+                .collect())
+        })
+    }
+
+    fn storage_usage<'a>(&'a self, user_id: i64, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<StorageUsage>> {
+        Box::pin(async move { Ok(self.state.read().await.storage_usage(user_id, limits)) })
+    }
+
+    fn store_attachment<'a>(&'a self, attachment: NewAttachment<'a>, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<(i64, DateTime<Utc>)>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            state.storage_usage(attachment.user_id, limits).check_upload(attachment.size_bytes)?;
+            let id = state.next_id();
+            state.files.insert(id, (attachment.user_id, attachment.size_bytes));
+            state.object_keys.insert(id, attachment.object_key.to_string());
+            Ok((id, Utc::now()))
+        })
+    }
+
+    fn delete_attachment<'a>(&'a self, user_id: i64, file_id: i64, limits: &'a LimitsConfig) -> BoxFuture<'a, Result<(String, StorageUsage)>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            if !state.files.get(&file_id).is_some_and(|(owner, _)| *owner == user_id) {
+                return Err(ChatError::not_found("pièce jointe", &file_id.to_string()));
+            }
+            state.files.remove(&file_id);
+            let object_key = state.object_keys.remove(&file_id).unwrap_or_default();
+            Ok((object_key, state.storage_usage(user_id, limits)))
+        })
+    }
+}
+
+impl ModerationRepository for InMemoryModerationRepository {
+    fn moderator_ids<'a>(&'a self, room_id: i64) -> BoxFuture<'a, Result<Vec<i64>>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.memberships.iter()
+                .filter(|m| m.room_id == room_id && m.is_active() && is_moderator_role(&m.role))
+                .map(|m| m.user_id)
+                .collect())
+        })
+    }
+
+    fn reputation_score<'a>(&'a self, _user_id: i64) -> BoxFuture<'a, Result<Option<i32>>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    fn count_author_message<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<i64>> {
+        Box::pin(async move {
+            let mut state = self.state.write().await;
+            let count = state.message_counts.entry(user_id).or_default();
+            *count += 1;
+            Ok(*count)
+        })
+    }
+
+    fn record_violation<'a>(
+        &'a self,
+        user_id: i64,
+        _conversation_id: Option<i64>,
+        _reason: &'a str,
+        escalation: &'a ViolationEscalationConfig,
+        _audit: &'a AuditSink
+    ) -> BoxFuture<'a, Result<RecordedViolation>> {
+        Box::pin(async move {
+            let now = Utc::now();
+            let mut state = self.state.write().await;
+            let entry = state.violations.entry(user_id).or_default();
+            let violation = next_violation(escalation, entry.last, now);
+            entry.last = Some((violation.count, now));
+            entry.deadlines = entry.deadlines.after(&violation);
+            Ok(violation)
+        })
+    }
+
+    fn sanction_deadlines<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<SanctionDeadlines>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.violations.get(&user_id).map(|entry| entry.deadlines).unwrap_or_default())
+        })
+    }
+
+    fn is_global_admin<'a>(&'a self, user_id: i64) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            Ok(self.state.read().await.admins.contains(&user_id))
+        })
+    }
+}
+
+// ================================================================
+// CLIENTS FACTICES
+// ================================================================
+
+/// Client connecté au hub de test, capture les trames qui lui sont envoyées
+pub struct TestClient {
+    pub user_id: i32,
+    pub username: String,
+    /// Trame `feature_flags` reçue à la connexion
+    pub handshake: Option<Value>,
+    /// Session enregistrée auprès du hub
+    session: Client,
+    receiver: UnboundedReceiver<Message>,
+}
+
+impl TestClient {
+    /// Prochaine trame JSON déjà reçue, sans attendre
+    pub fn try_next_frame(&mut self) -> Option<Value> {
+        while let Ok(message) = self.receiver.try_recv() {
+            if let Some(frame) = parse_frame(message) {
+                return Some(frame);
+            }
+        }
+        None
+    }
+
+    /// Attend la prochaine trame JSON (au plus `timeout`)
+    pub async fn next_frame(&mut self, timeout: Duration) -> Option<Value> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let message = tokio::time::timeout_at(deadline, self.receiver.recv()).await.ok()??;
+            if let Some(frame) = parse_frame(message) {
+                return Some(frame);
+            }
+        }
+    }
+
+    /// Attend la fermeture de la connexion par le serveur : code et raison JSON
+    ///
+    /// Les trames texte reçues entre-temps sont écartées.
+    pub async fn next_close(&mut self, timeout: Duration) -> Option<(u16, Value)> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let message = tokio::time::timeout_at(deadline, self.receiver.recv()).await.ok()??;
+            if let Message::Close(Some(frame)) = message {
+                let reason = serde_json::from_str(&frame.reason).unwrap_or(Value::Null);
+                return Some((u16::from(frame.code), reason));
+            }
+        }
+    }
+
+    /// Vide et retourne toutes les trames JSON déjà reçues
+    pub fn drain_frames(&mut self) -> Vec<Value> {
+        std::iter::from_fn(|| self.try_next_frame()).collect()
+    }
+}
+
+/// Seules les trames texte JSON sont exposées (ping/pong ignorés)
+fn parse_frame(message: Message) -> Option<Value> {
+    match message {
+        Message::Text(text) => serde_json::from_str(&text).ok(),
+        _ => None,
+    }
+}
+
+// ================================================================
+// HARNAIS
+// ================================================================
+
+/// Hub de test, son dépôt des salons en mémoire et ses clients factices
+pub struct TestHarness {
+    pub hub: Arc<ChatHub>,
+    /// Dépôt installé sur `hub` : salons, adhésions et messages stockés
+    pub rooms: InMemoryRoomRepository,
+}
+
+impl TestHarness {
+    pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// Variante avec configuration personnalisée (limites, fonctionnalités, ...)
+    pub fn with_config(config: ServerConfig) -> Self {
+        Self::with_metrics(config, Arc::new(NoopSink))
+    }
+
+    /// Variante dont les métriques sont écrites dans le puits fourni (ex. `RecordingSink`)
+    pub fn with_metrics(config: ServerConfig, sink: Arc<dyn MetricsSink>) -> Self {
+        let rooms = InMemoryRoomRepository::new();
+        let hub = ChatHub::testing_hub(config, ChatMetrics::with_sink(sink), None, rooms.clone());
+        Self { hub, rooms }
+    }
+
+    /// Variante dont les événements partent vers le publieur fourni (ex. `RecordingPublisher`)
+    pub fn with_bridge(config: ServerConfig, publisher: Arc<dyn BridgePublisher>) -> Self {
+        let rooms = InMemoryRoomRepository::new();
+        let bridge = EventBridge::spawn(publisher, &config.integrations.nats.clone().unwrap_or_default());
+        let hub = ChatHub::testing_hub(config, ChatMetrics::new(), Some(bridge), rooms.clone());
+        Self { hub, rooms }
+    }
+
+    /// Connecte un client factice et retourne sa capture de trames
+    pub async fn connect(&self, user_id: i32, username: &str) -> TestClient {
+        self.try_connect(user_id, username).await.expect("nom d'utilisateur refusé par le hub")
+    }
+
+    /// Comme `connect`, mais retourne le refus du hub (nom invalide ou réservé)
+    ///
+    /// Le hub replace l'utilisateur dans les salons dont il est membre dans le
+    /// dépôt (`rooms_restored`, après la trame de poignée de main).
+    pub async fn try_connect(&self, user_id: i32, username: &str) -> Result<TestClient> {
+        let (sender, receiver) = unbounded_channel();
+        self.register_session(Client::new(user_id, username.to_string(), sender), receiver).await
+    }
+
+    /// Enregistre la session auprès du hub et capture la poignée de main
+    async fn register_session(&self, session: Client, receiver: UnboundedReceiver<Message>) -> Result<TestClient> {
+        let (user_id, username) = (session.user_id, session.username.clone());
+        self.hub.register(user_id, session.clone()).await?;
+
+        // Le hub retient le nom normalisé
+        let username = self.hub.clients.with(&user_id, |client| client.map(|client| client.username.clone())).await
+            .unwrap_or(username);
+
+        let mut client = TestClient { user_id, username, handshake: None, session, receiver };
+        client.handshake = client.try_next_frame();
+        Ok(client)
+    }
+
+    /// Comme `try_connect`, précédé de l'admission de l'IP au handshake
+    ///
+    /// Un refus survient avant l'enregistrement du client.
+    pub async fn try_connect_from(&self, ip: &str, user_id: i32, username: &str) -> Result<TestClient> {
+        self.hub.admit_connection(ip).await?;
+        self.try_connect(user_id, username).await
+    }
+
+    /// Connecte un invité (jeton `issue_guest_claims`) ; refusé sans `guests.enabled`
+    pub async fn connect_guest(&self, display_name: Option<&str>) -> Result<TestClient> {
+        let claims = issue_guest_claims(&self.hub.config, display_name)?;
+        let (sender, receiver) = unbounded_channel();
+        self.register_session(Client::from_claims(&claims, sender), receiver).await
+    }
+
+    /// Ferme toutes les sessions de l'utilisateur, comme autant de connexions perdues
+    pub async fn disconnect(&self, user_id: i32) {
+        let sessions = self.hub.sessions.get(&user_id).await.unwrap_or_default();
+        for session in &sessions {
+            self.hub.unregister(session).await;
+        }
+        if sessions.is_empty() {
+            if let Some(client) = self.hub.clients.get(&user_id).await {
+                self.hub.unregister(&client).await;
+            }
+        }
+    }
+
+    /// Ferme la seule session de `client`
+    pub async fn disconnect_session(&self, client: &TestClient) {
+        self.hub.unregister(&client.session).await;
+    }
+
+    /// Fait lire `frames` par la connexion salons de `client`, comme reçues du socket
+    ///
+    /// Retourne le nombre de trames traitées ; la session est fermée ensuite.
+    pub async fn serve_rooms(&self, client: &TestClient, frames: Vec<Message>) -> usize {
+        let stream = futures_util::stream::iter(frames.into_iter().map(Ok));
+        serve_room_connection(self.hub.clone(), client.session.clone(), stream).await
+    }
+
+    /// Comme `serve_rooms`, pour la connexion messages directs
+    pub async fn serve_dms(&self, client: &TestClient, frames: Vec<Message>) -> usize {
+        let stream = futures_util::stream::iter(frames.into_iter().map(Ok));
+        serve_dm_connection(self.hub.clone(), client.session.clone(), stream).await
+    }
+}
+
+impl Default for TestHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,8 +1,13 @@
 //! Utilitaires généraux
 
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
+/// Longueur par défaut des aperçus de listes (`limits.list_preview_length`, en graphèmes)
+pub const DEFAULT_LIST_PREVIEW_LENGTH: usize = 120;
+
 /// Génère un nouvel UUID v4
 pub fn generate_id() -> Uuid {
     Uuid::new_v4()
@@ -40,6 +45,41 @@ pub fn truncate_text(text: &str, max_len: usize) -> String {
     }
 }
 
+/// Aperçu d'un message pour une vue en liste
+///
+/// Retours à la ligne et blancs successifs réduits à une espace, puis au plus
+/// `max_graphemes` graphèmes, points de suspension compris : un émoji composé
+/// ou une lettre accentuée n'est jamais coupé.
+pub fn list_preview(content: &str, max_graphemes: usize) -> String {
+    excerpt_preview(content, max_graphemes, false)
+}
+
+/// Aperçu d'un début de message lu en base (`truncated` : la source a été coupée)
+///
+/// Un extrait coupé porte toujours les points de suspension, même s'il tient
+/// dans `max_graphemes`.
+pub fn excerpt_preview(content: &str, max_graphemes: usize, truncated: bool) -> String {
+    let one_line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !truncated && one_line.graphemes(true).count() <= max_graphemes {
+        return one_line;
+    }
+
+    let kept: String = one_line.graphemes(true).take(max_graphemes.saturating_sub(1)).collect();
+    format!("{}…", kept.trim_end())
+}
+
+/// Résout un fuseau horaire IANA, UTC si absent ou invalide
+pub fn resolve_timezone(timezone: Option<&str>) -> Tz {
+    timezone
+        .and_then(|tz| tz.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// Formate un timestamp UTC dans le fuseau de l'utilisateur (notifications, emails)
+pub fn format_in_timezone(timestamp: DateTime<Utc>, timezone: Tz) -> String {
+    timestamp.with_timezone(&timezone).format("%d/%m/%Y %H:%M %Z").to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,4 +105,44 @@ mod tests {
         assert_eq!(truncate_text("hello", 10), "hello");
         assert_eq!(truncate_text("hello world test", 10), "hello w...");
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_long_message_preview_is_bounded() {
+        let long = format!("Bonjour\n\nà tous, {}", "très long message ".repeat(250));
+        let preview = list_preview(&long, 120);
+        assert_eq!(preview.graphemes(true).count(), 120);
+        assert!(preview.starts_with("Bonjour à tous, très"));
+        assert!(preview.ends_with('…'));
+        assert!(!preview.contains('\n'));
+
+        // Famille (séquence ZWJ) et « é » décomposé : jamais coupés
+        let family = "👨‍👩‍👧".repeat(10);
+        assert_eq!(list_preview(&family, 4), format!("{}…", "👨‍👩‍👧".repeat(3)));
+        let decomposed = "e\u{301}".repeat(10);
+        assert_eq!(list_preview(&decomposed, 3), "e\u{301}e\u{301}…");
+    }
+
+    #[test]
+    fn test_short_message_preview_is_intact() {
+        assert_eq!(list_preview("salut 👋", 120), "salut 👋");
+        assert_eq!(list_preview("ligne 1\r\nligne 2", 120), "ligne 1 ligne 2");
+        assert_eq!(list_preview(&"a".repeat(120), 120), "a".repeat(120));
+    }
+
+    #[test]
+    fn test_cut_excerpt_preview_always_ends_with_ellipsis() {
+        // Extrait coupé en base plus court que l'aperçu : émojis de sept points de code
+        let family = "👨‍👩‍👧‍👦".repeat(2);
+        assert_eq!(excerpt_preview(&family, 4, true), format!("{}…", family));
+        assert_eq!(excerpt_preview(&family, 4, false), family);
+        assert_eq!(excerpt_preview(&"a".repeat(10), 4, true), "aaa…");
+    }
+
+    #[test]
+    fn test_format_in_timezone() {
+        let ts = DateTime::parse_from_rfc3339("2024-07-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(format_in_timezone(ts, resolve_timezone(Some("Europe/Paris"))), "01/07/2024 14:00 CEST");
+        assert_eq!(format_in_timezone(ts, resolve_timezone(None)), "01/07/2024 12:00 UTC");
+        assert_eq!(format_in_timezone(ts, resolve_timezone(Some("Mars/Olympus"))), "01/07/2024 12:00 UTC");
+    }
+}
@@ -1,3 +1,5 @@
+use serde_json::Value;
+use crate::config::LimitsConfig;
 use crate::error::{ChatError, Result};
 
 pub fn validate_message_content(content: &str, max_size: usize) -> Result<()> {
@@ -9,6 +11,9 @@ pub fn validate_message_content(content: &str, max_size: usize) -> Result<()> {
         return Err(ChatError::message_too_long(content.len(), max_size));
     }
 
+    // Refus explicite avant l'insertion (PostgreSQL rejette NUL dans un TEXT)
+    validate_unicode_text("content", content)?;
+
     // Vérifier les caractères de contrôle dangereux
     if content.chars().any(|c| c.is_control() && c != '\n' && c != '\r' && c != '\t') {
         return Err(ChatError::configuration_error("Caractères de contrôle non autorisés"));
@@ -17,38 +22,617 @@ pub fn validate_message_content(content: &str, max_size: usize) -> Result<()> {
     Ok(())
 }
 
-pub fn validate_room_name(room: &str) -> Result<()> {
-    if room.is_empty() {
-        return Err(ChatError::configuration_error("Le nom du salon ne peut pas être vide"));
+pub fn validate_user_id(user_id: i32) -> Result<()> {
+    if user_id <= 0 {
+        return Err(ChatError::configuration_error("L'ID utilisateur doit être positif"));
+    }
+    Ok(())
+}
+
+/// Limite d'une requête qui n'en précise pas : `default_page_size` s'applique
+pub const UNSPECIFIED_LIMIT: i64 = 0;
+
+/// Taille de page effective d'une requête paginée
+///
+/// `UNSPECIFIED_LIMIT` donne `default_page_size` ; une limite hors des bornes
+/// (`min_page_size`, `max_page_size`) y est ramenée plutôt que refusée. Seule
+/// une limite négative est une erreur.
+pub fn validate_limit(limit: i64, limits: &LimitsConfig) -> Result<i64> {
+    if limit < 0 {
+        return Err(ChatError::configuration_error("La limite doit être positive"));
+    }
+    
+    if limit == UNSPECIFIED_LIMIT {
+        return Ok(limits.default_page_size);
+    }
+    
+    Ok(limit.clamp(limits.min_page_size, limits.max_page_size))
+}
+
+/// Limites des pièces jointes d'un message : configuration, surchargée par le salon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentLimits {
+    pub max_count: usize,
+    pub max_total_size: u64,
+    /// Taille d'une pièce seule (`max_file_size`, jamais surchargée)
+    pub max_file_size: u64,
+}
+
+impl AttachmentLimits {
+    pub fn from_config(limits: &LimitsConfig) -> Self {
+        Self {
+            max_count: limits.max_attachments_per_message,
+            max_total_size: limits.max_attachments_total_size,
+            max_file_size: limits.max_file_size,
+        }
+    }
+
+    /// Applique les surcharges d'un salon (`NULL` : limite de la configuration)
+    ///
+    /// Une surcharge ne fait que resserrer : la configuration reste le plafond,
+    /// y compris pour une valeur enregistrée avant ce plafond.
+    pub fn with_room_overrides(mut self, max_count: Option<i32>, max_total_size: Option<i64>) -> Self {
+        if let Some(max_count) = max_count {
+            self.max_count = self.max_count.min(max_count.max(0) as usize);
+        }
+        if let Some(max_total_size) = max_total_size {
+            self.max_total_size = self.max_total_size.min(max_total_size.max(0) as u64);
+        }
+        self
+    }
+
+    /// Refuse un message portant plus de `max_count` pièces jointes
+    pub fn check_count(&self, count: usize) -> Result<()> {
+        if count > self.max_count {
+            return Err(ChatError::TooManyAttachments { count, max: self.max_count });
+        }
+        Ok(())
+    }
+
+    /// Vérifie les pièces jointes d'un message d'après leurs tailles stockées
+    pub fn check(&self, sizes: &[u64]) -> Result<()> {
+        self.check_count(sizes.len())?;
+
+        let mut total_size: u64 = 0;
+        for &size in sizes {
+            if size > self.max_file_size {
+                return Err(ChatError::FileTooLarge { size, max_size: self.max_file_size });
+            }
+            total_size = total_size.saturating_add(size);
+        }
+
+        if total_size > self.max_total_size {
+            return Err(ChatError::AttachmentsTooLarge { total_size, max_total_size: self.max_total_size });
+        }
+        Ok(())
+    }
+}
+
+/// Fichiers téléversés désignés par `metadata.attachments`
+///
+/// Chaque pièce jointe porte l'`id` retourné par `upload_attachment` ; la
+/// taille qu'annonce le client (`sizeBytes`) n'est pas lue.
+pub fn attachment_file_ids(metadata: Option<&Value>) -> Result<Vec<i64>> {
+    let Some(attachments) = metadata.and_then(|metadata| metadata.get("attachments")) else {
+        return Ok(Vec::new());
+    };
+    let attachments = attachments.as_array().ok_or_else(|| ChatError::InvalidFormat {
+        field: "attachments".to_string(),
+        reason: "liste attendue".to_string(),
+    })?;
+
+    attachments.iter()
+        .map(|attachment| attachment.get("id").and_then(Value::as_i64).ok_or_else(|| ChatError::InvalidFormat {
+            field: "attachments.id".to_string(),
+            reason: "identifiant de fichier manquant".to_string(),
+        }))
+        .collect()
+}
+
+pub fn validate_display_name(display_name: &str) -> Result<()> {
+    if display_name.trim().is_empty() {
+        return Err(ChatError::configuration_error("Le nom d'affichage ne peut pas être vide"));
     }
 
-    if room.len() > 100 {
-        return Err(ChatError::configuration_error("Le nom du salon est trop long (max 100 caractères)"));
+    if display_name.chars().count() > 100 {
+        return Err(ChatError::configuration_error("Le nom d'affichage est trop long (max 100 caractères)"));
     }
 
-    // Vérifier que le nom ne contient que des caractères alphanumériques, tirets et underscores
-    if !room.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
-        return Err(ChatError::configuration_error("Le nom du salon ne peut contenir que des lettres, chiffres, tirets et underscores"));
+    if display_name.chars().any(|c| c.is_control()) {
+        return Err(ChatError::configuration_error("Caractères de contrôle non autorisés"));
     }
 
     Ok(())
 }
 
-pub fn validate_user_id(user_id: i32) -> Result<()> {
-    if user_id <= 0 {
-        return Err(ChatError::configuration_error("L'ID utilisateur doit être positif"));
+pub fn validate_bio(bio: &str) -> Result<()> {
+    if bio.chars().count() > 500 {
+        return Err(ChatError::configuration_error("La bio est trop longue (max 500 caractères)"));
+    }
+
+    if bio.chars().any(|c| c.is_control() && c != '\n') {
+        return Err(ChatError::configuration_error("Caractères de contrôle non autorisés"));
     }
+
     Ok(())
 }
 
-pub fn validate_limit(limit: i64) -> Result<i64> {
-    if limit <= 0 {
-        return Err(ChatError::configuration_error("La limite doit être positive"));
+/// L'avatar doit avoir été déposé dans le stockage objet : seules les URL sous
+/// `object_store.public_base_url` sont acceptées, vers une clé sans remontée
+/// de répertoire, ni requête, ni caractère encodé
+pub fn validate_avatar_url(avatar_url: &str, public_base_url: &str) -> Result<()> {
+    let key = avatar_url
+        .strip_prefix(public_base_url.trim_end_matches('/'))
+        .and_then(|rest| rest.strip_prefix('/'))
+        .ok_or_else(|| ChatError::configuration_error("L'avatar doit être déposé dans le stockage objet"))?;
+
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/');
+    if !key.chars().all(allowed) || !crate::object_store::is_safe_key(key) {
+        return Err(ChatError::configuration_error("URL d'avatar invalide"));
     }
-    
-    if limit > 1000 {
-        return Err(ChatError::configuration_error("La limite ne peut pas dépasser 1000"));
+
+    Ok(())
+}
+
+pub fn validate_timezone(timezone: &str) -> Result<()> {
+    // Vérifié contre la base IANA (ex: "Europe/Paris", "UTC")
+    timezone.parse::<chrono_tz::Tz>()
+        .map_err(|_| ChatError::configuration_error("Fuseau horaire inconnu"))?;
+
+    Ok(())
+}
+
+// ================================================================
+// VALIDATION UNICODE
+// ================================================================
+
+/// Rejette les caractères que PostgreSQL ne peut pas stocker dans une colonne TEXT
+pub fn validate_unicode_text(field: &str, text: &str) -> Result<()> {
+    if let Some(position) = text.chars().position(|c| c == '\0') {
+        return Err(ChatError::InvalidFormat {
+            field: field.to_string(),
+            reason: format!("caractère NUL (U+0000) interdit à la position {}", position),
+        });
     }
-    
-    Ok(limit)
-} 
\ No newline at end of file
+
+    Ok(())
+}
+
+/// Position (en octets) du premier échappement `\uXXXX` formant un surrogate isolé
+pub fn find_lone_surrogate_escape(json: &str) -> Option<usize> {
+    let bytes = json.as_bytes();
+    let escape_at = |i: usize| -> Option<u16> {
+        if bytes.get(i) != Some(&b'\\') || bytes.get(i + 1) != Some(&b'u') {
+            return None;
+        }
+        json.get(i + 2..i + 6).and_then(|hex| u16::from_str_radix(hex, 16).ok())
+    };
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+
+        let Some(unit) = escape_at(i) else {
+            // Échappement simple (\n, \\, ...) : sauter le caractère échappé
+            i += 2;
+            continue;
+        };
+
+        match unit {
+            0xD800..=0xDBFF => match escape_at(i + 6) {
+                Some(0xDC00..=0xDFFF) => i += 12,
+                _ => return Some(i),
+            },
+            0xDC00..=0xDFFF => return Some(i),
+            _ => i += 6,
+        }
+    }
+
+    None
+}
+
+/// Chemin JSON (`data.content`, `mentions[2]`) de la chaîne contenant l'octet `offset`
+///
+/// Parcours lexical sans validation : le document peut être invalide par ailleurs.
+/// Chaîne hors de tout objet ou tableau : `"message"`.
+fn json_path_at(json: &str, offset: usize) -> String {
+    enum Frame {
+        Object { key: Option<String> },
+        Array { index: usize },
+    }
+
+    let bytes = json.as_bytes();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                let end = end.min(bytes.len());
+
+                // Dans un objet, une chaîne suivie de `:` est une clé
+                let followed_by_colon = bytes[(end + 1).min(bytes.len())..].iter()
+                    .find(|b| !b.is_ascii_whitespace()) == Some(&b':');
+                if let (true, Some(Frame::Object { key })) = (followed_by_colon, stack.last_mut()) {
+                    *key = Some(json[start..end].to_string());
+                }
+
+                if (start..end).contains(&offset) {
+                    break;
+                }
+                i = end + 1;
+            }
+            b'{' => {
+                stack.push(Frame::Object { key: None });
+                i += 1;
+            }
+            b'[' => {
+                stack.push(Frame::Array { index: 0 });
+                i += 1;
+            }
+            b'}' | b']' => {
+                stack.pop();
+                i += 1;
+            }
+            b',' => {
+                match stack.last_mut() {
+                    Some(Frame::Object { key }) => *key = None,
+                    Some(Frame::Array { index }) => *index += 1,
+                    None => {}
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    let mut path = String::new();
+    for frame in &stack {
+        match frame {
+            Frame::Object { key: Some(key) } => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            Frame::Object { key: None } => break,
+            Frame::Array { index } => path.push_str(&format!("[{}]", index)),
+        }
+    }
+
+    if path.is_empty() { "message".to_string() } else { path }
+}
+
+/// Parse un message JSON client avec une erreur explicite pour les surrogates isolés
+///
+/// `field` désigne le chemin JSON de la chaîne fautive.
+pub fn parse_client_json(message: &str) -> Result<serde_json::Value> {
+    if let Some(offset) = find_lone_surrogate_escape(message) {
+        return Err(ChatError::InvalidFormat {
+            field: json_path_at(message, offset),
+            reason: format!("surrogate UTF-16 isolé à l'octet {}", offset),
+        });
+    }
+
+    let value: serde_json::Value = serde_json::from_str(message)
+        .map_err(|e| ChatError::configuration_error(&format!("JSON invalide: {}", e)))?;
+
+    Ok(value)
+}
+
+// ================================================================
+// NOMS D'UTILISATEUR
+// ================================================================
+
+/// Longueur minimale d'un nom d'utilisateur (en caractères)
+pub const MIN_USERNAME_LENGTH: usize = 3;
+
+/// Longueur maximale d'un nom d'utilisateur (en caractères)
+pub const MAX_USERNAME_LENGTH: usize = 32;
+
+/// Noms réservés par défaut (`security.reserved_usernames`)
+pub const DEFAULT_RESERVED_USERNAMES: &[&str] = &[
+    "admin", "administrator", "root", "system", "server", "moderator", "mod",
+    "support", "staff", "everyone", "here", "owner", "veza", "bot",
+];
+
+/// Caractère invisible ou de contrôle bidirectionnel (usurpation visuelle)
+fn is_invisible_or_bidi(c: char) -> bool {
+    matches!(c,
+        '\u{00AD}'                     // trait d'union conditionnel
+        | '\u{034F}'                   // graphème de liaison
+        | '\u{061C}'                   // marque de lettre arabe
+        | '\u{115F}' | '\u{1160}'      // remplissages Hangul
+        | '\u{180E}'                   // séparateur de voyelles mongol
+        | '\u{200B}'..='\u{200F}'      // espaces de largeur nulle, LRM/RLM
+        | '\u{202A}'..='\u{202E}'      // enchâssements et forçages bidi
+        | '\u{2060}'..='\u{2064}'      // liant de mots, opérateurs invisibles
+        | '\u{2066}'..='\u{2069}'      // isolats bidi
+        | '\u{3164}'                   // remplissage Hangul
+        | '\u{FE00}'..='\u{FE0F}'      // sélecteurs de variante
+        | '\u{FEFF}'                   // BOM / espace insécable de largeur nulle
+        | '\u{FFA0}'                   // remplissage Hangul demi-chasse
+    )
+}
+
+/// Ramène un homoglyphe courant (cyrillique, grec, pleine chasse) à sa lettre latine
+fn fold_confusable(c: char) -> char {
+    match c {
+        // Pleine chasse : ！ à ～ correspondent à ! à ~
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        // Cyrillique
+        'а' => 'a', 'А' => 'A', 'в' => 'b', 'В' => 'B', 'с' => 'c', 'С' => 'C',
+        'е' => 'e', 'Е' => 'E', 'һ' => 'h', 'Н' => 'H', 'і' => 'i', 'І' => 'I',
+        'ј' => 'j', 'Ј' => 'J', 'к' => 'k', 'К' => 'K', 'М' => 'M', 'о' => 'o',
+        'О' => 'O', 'р' => 'p', 'Р' => 'P', 'ԛ' => 'q', 'ѕ' => 's', 'Ѕ' => 'S',
+        'Т' => 'T', 'у' => 'y', 'У' => 'Y', 'х' => 'x', 'Х' => 'X', 'ԁ' => 'd',
+        'ԝ' => 'w', 'ɡ' => 'g',
+        // Grec
+        'α' => 'a', 'Α' => 'A', 'Β' => 'B', 'Ε' => 'E', 'Ζ' => 'Z', 'Η' => 'H',
+        'ι' => 'i', 'Ι' => 'I', 'Κ' => 'K', 'Μ' => 'M', 'Ν' => 'N', 'ο' => 'o',
+        'Ο' => 'O', 'ρ' => 'p', 'Ρ' => 'P', 'Τ' => 'T', 'υ' => 'u', 'Υ' => 'Y',
+        'Χ' => 'X', 'ν' => 'v',
+        _ => c,
+    }
+}
+
+/// Normalise et valide un nom d'utilisateur avant affichage ou dénormalisation
+///
+/// Les caractères invisibles et bidirectionnels sont refusés, les homoglyphes
+/// courants ramenés à l'ASCII ; le résultat ne contient que des lettres et
+/// chiffres ASCII, `_`, `-` et `.`. Le balisage (`<`, `>`, `&`, ...) est donc refusé.
+pub fn normalize_username(username: &str) -> Result<String> {
+    let invalid = |reason: String| ChatError::InvalidFormat {
+        field: "username".to_string(),
+        reason,
+    };
+
+    let username = username.trim();
+
+    if let Some(c) = username.chars().find(|&c| is_invisible_or_bidi(c) || c.is_control()) {
+        return Err(invalid(format!("caractère invisible ou de contrôle interdit (U+{:04X})", c as u32)));
+    }
+
+    let normalized: String = username.chars().map(fold_confusable).collect();
+
+    let length = normalized.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+        return Err(invalid(format!(
+            "longueur de {} caractères hors limites ({}-{})",
+            length, MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        )));
+    }
+
+    if let Some(c) = normalized.chars().find(|&c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))) {
+        return Err(invalid(format!("caractère '{}' non autorisé", c.escape_default())));
+    }
+
+    if !normalized.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(invalid("doit commencer par une lettre ou un chiffre".to_string()));
+    }
+
+    Ok(normalized)
+}
+
+/// Forme de comparaison : minuscules, sans séparateurs (« Ad_Min » ~ « admin »)
+pub fn username_skeleton(username: &str) -> String {
+    username.chars()
+        .map(fold_confusable)
+        .filter(|c| !matches!(c, '_' | '-' | '.'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Refuse un nom d'utilisateur réservé, y compris sous une forme déguisée
+pub fn check_reserved_username(username: &str, reserved: &[String]) -> Result<()> {
+    let skeleton = username_skeleton(username);
+    if reserved.iter().any(|name| username_skeleton(name) == skeleton) {
+        return Err(ChatError::InvalidFormat {
+            field: "username".to_string(),
+            reason: "nom d'utilisateur réservé".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lone_surrogate_rejected_cleanly() {
+        let raw = r#"{"type":"send_message","data":{"content":"a\ud800b"}}"#;
+        assert!(matches!(
+            parse_client_json(raw),
+            Err(ChatError::InvalidFormat { ref field, .. }) if field == "data.content"
+        ));
+
+        // Surrogate bas sans surrogate haut
+        assert!(find_lone_surrogate_escape(r#""\udc00""#).is_some());
+
+        // Surrogate encodé directement en UTF-8 (CESU / WTF-8) : refusé par le décodage
+        assert!(std::str::from_utf8(b"a\xED\xA0\x80b").is_err());
+    }
+
+    #[test]
+    fn test_lone_surrogate_reports_json_path() {
+        let field_of = |raw: &str| match parse_client_json(raw) {
+            Err(ChatError::InvalidFormat { field, .. }) => field,
+            other => panic!("erreur attendue, obtenu {:?}", other),
+        };
+
+        assert_eq!(field_of(r#"{"type":"edit","data":{"id":1,"new_content":"\udc00"}}"#), "data.new_content");
+        assert_eq!(field_of(r#"{"data":{"attachments":[{"name":"a"},{"name":"\ud800"}]}}"#), "data.attachments[1].name");
+        assert_eq!(field_of(r#"{"data":{"tags":["ok","x\\\"\ud800"]}}"#), "data.tags[1]");
+        // Surrogate dans une clé : chemin jusqu'à cette clé
+        assert_eq!(field_of(r#"{"data":{"\ud800":1}}"#), "data.\\ud800");
+        assert_eq!(field_of(r#""\ud800""#), "message");
+    }
+
+    #[test]
+    fn test_valid_pairs_and_escaped_backslashes_accepted() {
+        let emoji = r#"{"content":"\ud83d\ude00"}"#;
+        assert_eq!(find_lone_surrogate_escape(emoji), None);
+        assert_eq!(parse_client_json(emoji).unwrap()["content"], "😀");
+
+        // "\\ud800" est un antislash littéral suivi de texte
+        assert_eq!(find_lone_surrogate_escape(r#""\\ud800""#), None);
+    }
+
+    #[test]
+    fn test_embedded_nul_rejected_before_insert() {
+        let value = parse_client_json(r#"{"content":"a\u0000b"}"#).unwrap();
+        let content = value["content"].as_str().unwrap();
+
+        assert!(matches!(
+            validate_message_content(content, 1000),
+            Err(ChatError::InvalidFormat { ref field, .. }) if field == "content"
+        ));
+        assert!(validate_unicode_text("content", "a\0b").is_err());
+        assert!(validate_message_content("salut\n😀", 1000).is_ok());
+    }
+
+    #[test]
+    fn test_username_with_rtl_override_rejected() {
+        // « admin » affiché à l'envers grâce à U+202E
+        assert!(matches!(
+            normalize_username("user\u{202E}nimda"),
+            Err(ChatError::InvalidFormat { ref field, .. }) if field == "username"
+        ));
+        assert!(normalize_username("bo\u{200B}b").is_err());
+        assert!(normalize_username("\u{2067}alice").is_err());
+    }
+
+    #[test]
+    fn test_username_with_markup_rejected() {
+        assert!(normalize_username("<script>alert(1)</script>").is_err());
+        assert!(normalize_username("bob<b>").is_err());
+        assert!(normalize_username("a&amp;b").is_err());
+        assert!(normalize_username("alice\"onclick").is_err());
+    }
+
+    #[test]
+    fn test_username_homoglyphs_normalized() {
+        // « аlice » avec un « а » cyrillique
+        assert_eq!(normalize_username("\u{0430}lice").unwrap(), "alice");
+        assert_eq!(normalize_username("ｂｏｂ").unwrap(), "bob");
+        assert_eq!(normalize_username("  user-123  ").unwrap(), "user-123");
+        assert!(normalize_username("us").is_err());
+        assert!(normalize_username(&"a".repeat(MAX_USERNAME_LENGTH + 1)).is_err());
+        assert!(normalize_username("_alice").is_err());
+    }
+
+    #[test]
+    fn test_reserved_usernames_rejected() {
+        let reserved: Vec<String> = DEFAULT_RESERVED_USERNAMES.iter().map(|n| n.to_string()).collect();
+
+        assert!(check_reserved_username("Admin", &reserved).is_err());
+        assert!(check_reserved_username("ad_min", &reserved).is_err());
+        // « аdmin » cyrillique, une fois normalisé
+        let disguised = normalize_username("\u{0430}dmin").unwrap();
+        assert!(check_reserved_username(&disguised, &reserved).is_err());
+        assert!(check_reserved_username("alice", &reserved).is_ok());
+        assert!(check_reserved_username("admin", &[]).is_ok());
+    }
+
+    #[test]
+    fn test_avatar_must_come_from_object_store() {
+        let base = "https://cdn.veza.example/objects/";
+
+        assert!(validate_avatar_url("https://cdn.veza.example/objects/avatars/42-a1b2.png", base).is_ok());
+        assert!(validate_avatar_url("/objects/avatars/42.webp", "/objects").is_ok());
+
+        assert!(validate_avatar_url("https://tracker.example/pixel.png", base).is_err());
+        assert!(validate_avatar_url("https://cdn.veza.example/objects.evil/a.png", base).is_err());
+        assert!(validate_avatar_url("https://cdn.veza.example/objects/../admin.png", base).is_err());
+        assert!(validate_avatar_url("https://cdn.veza.example/objects/%2e%2e/admin.png", base).is_err());
+        assert!(validate_avatar_url("https://cdn.veza.example/objects/a.png?track=1", base).is_err());
+        assert!(validate_avatar_url("https://cdn.veza.example/objects/", base).is_err());
+    }
+
+    #[test]
+    fn test_limit_clamped_to_configured_bounds() {
+        let mut limits = LimitsConfig::default();
+        limits.max_page_size = 200;
+        limits.min_page_size = 5;
+        limits.default_page_size = 30;
+
+        assert_eq!(validate_limit(10_000, &limits).unwrap(), 200);
+        assert_eq!(validate_limit(2, &limits).unwrap(), 5);
+        assert_eq!(validate_limit(120, &limits).unwrap(), 120);
+        assert_eq!(validate_limit(UNSPECIFIED_LIMIT, &limits).unwrap(), 30);
+        assert!(validate_limit(-1, &limits).is_err());
+    }
+
+    #[test]
+    fn test_attachment_count_limit() {
+        let mut limits = LimitsConfig::default();
+        limits.max_attachments_per_message = 3;
+        let limits = AttachmentLimits::from_config(&limits);
+
+        assert!(limits.check(&[]).is_ok());
+        assert!(limits.check(&[1024; 3]).is_ok());
+        assert!(matches!(limits.check(&[1024; 4]), Err(ChatError::TooManyAttachments { count: 4, max: 3 })));
+        assert!(matches!(limits.check_count(4), Err(ChatError::TooManyAttachments { count: 4, max: 3 })));
+    }
+
+    #[test]
+    fn test_attachment_total_size_limit() {
+        let mut limits = LimitsConfig::default();
+        limits.max_file_size = 100;
+        limits.max_attachments_total_size = 250;
+        let limits = AttachmentLimits::from_config(&limits);
+
+        assert!(limits.check(&[100, 100, 50]).is_ok());
+        assert!(matches!(
+            limits.check(&[100, 100, 51]),
+            Err(ChatError::AttachmentsTooLarge { total_size: 251, max_total_size: 250 })
+        ));
+        // Une pièce seule reste soumise à max_file_size
+        assert!(matches!(limits.check(&[101]), Err(ChatError::FileTooLarge { size: 101, max_size: 100 })));
+    }
+
+    #[test]
+    fn test_attachment_file_ids_ignore_declared_sizes() {
+        let metadata = serde_json::json!({ "attachments": [{ "id": 7, "sizeBytes": 1 }, { "id": 9 }] });
+        assert_eq!(attachment_file_ids(Some(&metadata)).unwrap(), vec![7, 9]);
+        assert!(attachment_file_ids(None).unwrap().is_empty());
+        assert!(attachment_file_ids(Some(&serde_json::json!({ "text": "x" }))).unwrap().is_empty());
+        // Une taille seule ne désigne aucun fichier stocké
+        assert!(attachment_file_ids(Some(&serde_json::json!({ "attachments": [{ "sizeBytes": 10 }] }))).is_err());
+        assert!(attachment_file_ids(Some(&serde_json::json!({ "attachments": 3 }))).is_err());
+    }
+
+    #[test]
+    fn test_room_overrides_attachment_limits() {
+        let mut limits = LimitsConfig::default();
+        limits.max_file_size = 100;
+        limits.max_attachments_per_message = 3;
+        limits.max_attachments_total_size = 300;
+        let sizes = [100, 100, 100];
+
+        let server = AttachmentLimits::from_config(&limits);
+        assert!(server.check(&sizes).is_ok());
+
+        // Salon plus strict : moins de pièces et de volume, taille d'une pièce inchangée
+        let strict_room = server.with_room_overrides(Some(2), Some(200));
+        assert!(matches!(strict_room.check(&sizes), Err(ChatError::TooManyAttachments { count: 3, max: 2 })));
+        assert!(matches!(
+            server.with_room_overrides(None, Some(200)).check(&sizes),
+            Err(ChatError::AttachmentsTooLarge { total_size: 300, max_total_size: 200 })
+        ));
+        assert_eq!(strict_room.max_file_size, 100);
+
+        // Une surcharge au-dessus de la configuration reste plafonnée par celle-ci
+        assert_eq!(server.with_room_overrides(Some(30), Some(3000)), server);
+        assert_eq!(server.with_room_overrides(None, None), server);
+    }
+}
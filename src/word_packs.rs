@@ -0,0 +1,148 @@
+//! Listes de mots interdits par langue
+//!
+//! Le filtre de contenu applique l'union des listes des langues configurées
+//! (`security.content_filter.locales`), complétée par celles d'un salon
+//! (`conversations.languages`). Les langues `en` et `fr` sont intégrées ; un
+//! pack de la configuration (`[security.content_filter.word_packs.<langue>]`)
+//! ajoute ses mots à la liste intégrée de même langue ou définit une nouvelle
+//! langue.
+
+use serde::{Deserialize, Serialize};
+use crate::config::ContentFilterConfig;
+use crate::error::{ChatError, Result};
+use crate::security::FilterRule;
+
+/// Mots interdits d'une langue, par règle
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WordPack {
+    pub spam_words: Vec<String>,
+    pub profanity: Vec<String>,
+    pub harassment: Vec<String>,
+}
+
+impl WordPack {
+    fn from_static(spam_words: &[&str], profanity: &[&str], harassment: &[&str]) -> Self {
+        let owned = |words: &[&str]| words.iter().map(|word| word.to_string()).collect();
+        Self {
+            spam_words: owned(spam_words),
+            profanity: owned(profanity),
+            harassment: owned(harassment),
+        }
+    }
+
+    /// Pack intégré d'une langue
+    pub fn builtin(locale: &str) -> Option<Self> {
+        match locale {
+            "en" => Some(Self::from_static(
+                &["click here", "urgent", "limited time", "act now", "free money",
+                  "viagra", "casino", "lottery", "winner", "congratulations"],
+                &["spam", "fuck", "shit", "bitch", "damn"],
+                &["kill yourself", "kys", "suicide", "die"],
+            )),
+            // Mots choisis pour ne pas apparaître à l'intérieur de mots courants
+            "fr" => Some(Self::from_static(
+                &["cliquez ici", "offre limitée", "argent facile", "argent gratuit",
+                  "casino", "loterie", "vous avez gagné"],
+                &["putain", "merde", "connard", "connasse", "salope", "enculé", "fdp"],
+                &["suicide-toi", "tue-toi", "va crever", "crève"],
+            )),
+            _ => None,
+        }
+    }
+
+    /// Mots du pack avec leur règle
+    pub fn rules(&self) -> impl Iterator<Item = (FilterRule, &str)> + '_ {
+        [
+            (FilterRule::SpamWords, &self.spam_words),
+            (FilterRule::Profanity, &self.profanity),
+            (FilterRule::Harassment, &self.harassment),
+        ]
+        .into_iter()
+        .flat_map(|(rule, words)| words.iter().map(move |word| (rule, word.as_str())))
+    }
+
+    fn extend(&mut self, other: &WordPack) {
+        self.spam_words.extend(other.spam_words.iter().cloned());
+        self.profanity.extend(other.profanity.iter().cloned());
+        self.harassment.extend(other.harassment.iter().cloned());
+    }
+}
+
+/// Langue intégrée ou définie dans la configuration
+pub fn is_known_locale(config: &ContentFilterConfig, locale: &str) -> bool {
+    WordPack::builtin(locale).is_some() || config.word_packs.contains_key(locale)
+}
+
+/// Pack d'une langue : liste intégrée complétée par la configuration
+pub fn resolve_pack(config: &ContentFilterConfig, locale: &str) -> Result<WordPack> {
+    let configured = config.word_packs.get(locale);
+    let mut pack = match (WordPack::builtin(locale), configured) {
+        (None, None) => return Err(ChatError::configuration_error(&format!("Langue de filtrage inconnue: {}", locale))),
+        (builtin, _) => builtin.unwrap_or_default(),
+    };
+    if let Some(configured) = configured {
+        pack.extend(configured);
+    }
+    Ok(pack)
+}
+
+/// Union des mots des langues de la configuration et de `extra_locales`, sans doublon
+pub fn forbidden_words(config: &ContentFilterConfig, extra_locales: &[String]) -> Result<Vec<(FilterRule, String)>> {
+    let mut words: Vec<(FilterRule, String)> = Vec::new();
+    let mut locales: Vec<&str> = Vec::new();
+    for locale in config.locales.iter().chain(extra_locales) {
+        if locales.contains(&locale.as_str()) {
+            continue;
+        }
+        locales.push(locale);
+
+        for (rule, word) in resolve_pack(config, locale)?.rules() {
+            let word = word.trim().to_lowercase();
+            if !word.is_empty() && !words.iter().any(|(known_rule, known)| *known_rule == rule && *known == word) {
+                words.push((rule, word));
+            }
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(locales: &[&str]) -> ContentFilterConfig {
+        let mut config = ContentFilterConfig::default();
+        config.locales = locales.iter().map(|locale| locale.to_string()).collect();
+        config
+    }
+
+    #[test]
+    fn test_union_of_locales() {
+        let words = forbidden_words(&config(&["fr", "en"]), &["fr".to_string()]).unwrap();
+        assert!(words.contains(&(FilterRule::Profanity, "putain".to_string())));
+        assert!(words.contains(&(FilterRule::Profanity, "shit".to_string())));
+        // Mot présent dans les deux langues : une seule fois
+        assert_eq!(words.iter().filter(|(_, word)| word == "casino").count(), 1);
+
+        let french = forbidden_words(&config(&["fr"]), &[]).unwrap();
+        assert!(!french.iter().any(|(_, word)| word == "shit"));
+    }
+
+    #[test]
+    fn test_configured_pack_extends_builtin_and_adds_locales() {
+        let mut config = config(&["fr", "de"]);
+        assert!(forbidden_words(&config, &[]).is_err());
+
+        let pack: WordPack = toml::from_str(r#"profanity = ["Scheiße"]"#).unwrap();
+        config.word_packs.insert("de".to_string(), pack);
+        config.word_packs.insert("fr".to_string(), WordPack { profanity: vec!["bordel".to_string()], ..WordPack::default() });
+
+        let words = forbidden_words(&config, &[]).unwrap();
+        assert!(words.contains(&(FilterRule::Profanity, "scheiße".to_string())));
+        assert!(words.contains(&(FilterRule::Profanity, "bordel".to_string())));
+        assert!(words.contains(&(FilterRule::Profanity, "merde".to_string())));
+        assert!(is_known_locale(&config, "de"));
+        assert!(!is_known_locale(&config, "es"));
+    }
+}
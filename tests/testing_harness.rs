@@ -0,0 +1,2036 @@
+//! Exemples d'utilisation du harnais de test (`cargo test --features testing`)
+//!
+//! Les flux salon passent par les fonctions du hub ; seuls les salons,
+//! adhésions et messages sont stockés en mémoire (`harness.rooms`).
+
+#![cfg(feature = "testing")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chat_server::client::{AckMode, Client};
+use chat_server::close_codes::CloseReason;
+use chat_server::config::{AuditConfig, BlockedDmHistory, ServerConfig};
+use chat_server::error::{ChatError, Result};
+use chat_server::event_bridge::RecordingPublisher;
+use chat_server::message_schema::CURRENT_SCHEMA_VERSION;
+use chat_server::hub::{add_reaction, delete_attachment, get_storage_usage, upload_attachment, Departure, FeatureFlag, GuestAccess, RaidScope, RoomFilterMode, cleanup_empty_rooms, expire_departures, get_message_reactions, get_unread_summary, report_lock_waits};
+use chat_server::hub::channels::{
+    archive_room, delete_room_message, edit_room_message, pin_message, reorder_pins, send_room_message, unarchive_room, RoomPostPolicy,
+};
+use chat_server::hub::audit_sink::AuditSink;
+use chat_server::hub::dedup::SentMessage;
+use chat_server::hub::direct_messages::{block_dm_conversation, fetch_history, get_or_create_dm_conversation, send_dm_message, start_conversation, DmPrivacy};
+use chat_server::hub::guests::{join_room_as_guest, send_guest_message};
+use chat_server::hub::ip_bans::{ban_ip, unban_ip};
+use chat_server::hub::e2ee::{fetch_key_bundle, KeyBundleUpload, OneTimePrekey};
+use chat_server::hub::held_messages::review_held_message;
+use chat_server::hub::profiles::{update_user_profile, ProfileUpdate};
+use chat_server::hub::read_receipts::{get_message_seen_by, SeenByMode};
+use chat_server::hub::room_directory::{list_rooms, RoomCursor, RoomFilter, RoomInfo};
+use chat_server::hub::presence_subscriptions::{set_presence_status, subscribe_presence};
+use chat_server::presence::UserStatus;
+use chat_server::hub::missed_events::{get_missed_events, HubEventKind, MissedCursor};
+use chat_server::monitoring::{MetricType, RecordingSink};
+use chat_server::pagination::Page;
+use chat_server::room_id::RoomId;
+use chat_server::testing::{InMemoryRoomRepository, TestClient, TestHarness};
+use tokio_tungstenite::tungstenite::Message;
+
+const FRAME_TIMEOUT: Duration = Duration::from_millis(200);
+
+const GENERAL: i64 = 10;
+const RANDOM: i64 = 11;
+const SUPPORT: i64 = 20;
+
+/// Salon du dépôt et ses membres (`member`)
+async fn create_room(harness: &TestHarness, room_id: i64, name: &str, members: &[(i64, &str)]) {
+    harness.rooms.create_room(room_id, name).await;
+    for (user_id, username) in members {
+        harness.rooms.add_user(*user_id, username).await;
+        harness.rooms.add_member(room_id, *user_id, "member").await;
+    }
+}
+
+/// Message texte simple, par `channels::send_room_message`
+async fn send(harness: &TestHarness, room_id: i64, author_id: i64, username: &str, content: &str) -> Result<SentMessage> {
+    send_room_message(&harness.hub, room_id, author_id, username, content, None, None, None).await
+}
+
+fn general() -> RoomId {
+    RoomId::new("general").unwrap()
+}
+
+#[tokio::test]
+async fn test_room_message_reaches_members() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+
+    let sent = send(&harness, GENERAL, 1, "alice", "salut bob").await.unwrap();
+
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message");
+    assert_eq!(frame["data"]["id"], sent.id);
+    assert_eq!(frame["data"]["roomId"], GENERAL);
+    assert_eq!(frame["data"]["username"], "alice");
+    assert_eq!(frame["data"]["content"], "salut bob");
+
+    // L'auteur reçoit aussi sa trame, un non-membre rien
+    assert_eq!(alice.drain_frames().len(), 1);
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    let history = harness.rooms.room_history(GENERAL).await;
+    assert_eq!(history.iter().map(|m| (m.id, m.content.as_str())).collect::<Vec<_>>(), vec![(sent.id, "salut bob")]);
+}
+
+#[tokio::test]
+async fn test_retry_with_same_nonce_returns_stored_message() {
+    let harness = TestHarness::new();
+    let mut bob = harness.connect(2, "bob").await;
+    harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    let with_nonce = |nonce: &str| Some(serde_json::json!({ "nonce": nonce }));
+
+    let first = send_room_message(&harness.hub, GENERAL, 1, "alice", "ok", None, with_nonce("n-1"), None).await.unwrap();
+    let retry = send_room_message(&harness.hub, GENERAL, 1, "alice", "ok", None, with_nonce("n-1"), None).await.unwrap();
+    assert_eq!(retry, first);
+    assert_eq!(bob.drain_frames().len(), 1);
+
+    // Même nonce, autre contenu : refusé ; « ok » volontaire sous un autre nonce : enregistré
+    let reused = send_room_message(&harness.hub, GENERAL, 1, "alice", "ko", None, with_nonce("n-1"), None).await;
+    assert!(matches!(reused, Err(ChatError::Conflict { .. })));
+    let repeat = send_room_message(&harness.hub, GENERAL, 1, "alice", "ok", None, with_nonce("n-2"), None).await.unwrap();
+    assert_ne!(repeat.id, first.id);
+    assert_eq!(harness.rooms.room_history(GENERAL).await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_invalid_or_unauthorized_send_is_rejected() {
+    let harness = TestHarness::new();
+    let mut bob = harness.connect(2, "bob").await;
+    harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(2, "bob")]).await;
+
+    // Non-membre du salon
+    let intruder = send(&harness, GENERAL, 1, "alice", "intrus").await;
+    assert!(matches!(intruder, Err(ChatError::Unauthorized { .. })));
+
+    // Contenu invalide (NUL)
+    harness.rooms.add_member(GENERAL, 1, "member").await;
+    assert!(send(&harness, GENERAL, 1, "alice", "a\0b").await.is_err());
+
+    assert!(harness.rooms.is_empty().await);
+    assert!(bob.drain_frames().is_empty());
+}
+
+#[tokio::test]
+async fn test_attachments_are_checked_against_stored_sizes() {
+    let mut config = ServerConfig::default();
+    config.limits.max_file_size = 100;
+    config.limits.max_attachments_total_size = 150;
+    let harness = TestHarness::with_config(config);
+    harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_file(7, 1, 80).await;
+    harness.rooms.add_file(8, 1, 90).await;
+    harness.rooms.add_file(9, 2, 10).await;
+    let attach = |files: serde_json::Value| Some(serde_json::json!({ "attachments": files }));
+
+    // Tailles annoncées sous la limite, fichiers stockés au-delà
+    let understated = attach(serde_json::json!([{ "id": 7, "sizeBytes": 1 }, { "id": 8, "sizeBytes": 1 }]));
+    let result = send_room_message(&harness.hub, GENERAL, 1, "alice", "deux", None, understated, None).await;
+    assert!(matches!(result, Err(ChatError::AttachmentsTooLarge { total_size: 170, max_total_size: 150 })));
+
+    // Fichier d'un autre utilisateur ou pièce sans fichier : refusés
+    let foreign = send_room_message(&harness.hub, GENERAL, 1, "alice", "vol", None, attach(serde_json::json!([{ "id": 9 }])), None).await;
+    assert!(matches!(foreign, Err(ChatError::InvalidFormat { .. })));
+    let declared = send_room_message(&harness.hub, GENERAL, 1, "alice", "nu", None, attach(serde_json::json!([{ "sizeBytes": 5 }])), None).await;
+    assert!(matches!(declared, Err(ChatError::InvalidFormat { .. })));
+    assert!(harness.rooms.is_empty().await);
+
+    // Le message stocké porte la taille réelle
+    let sent = send_room_message(&harness.hub, GENERAL, 1, "alice", "une", None, attach(serde_json::json!([{ "id": 7, "sizeBytes": 1 }])), None).await.unwrap();
+    let history = harness.rooms.room_history(GENERAL).await;
+    assert_eq!(history.iter().map(|m| m.id).collect::<Vec<_>>(), vec![sent.id]);
+    assert_eq!(history[0].metadata["attachments"][0]["sizeBytes"], 80);
+}
+
+#[tokio::test]
+async fn test_storage_quota_rejects_uploads_over_it_until_a_deletion_frees_space() {
+    let root = std::env::temp_dir().join(format!("veza-quota-{}", uuid::Uuid::new_v4()));
+    let mut config = ServerConfig::default();
+    config.limits.max_storage_per_user = 10;
+    config.object_store.root_dir = root.clone();
+    let harness = TestHarness::with_config(config);
+
+    let first = upload_attachment(&harness.hub, 1, "a.txt", "text/plain", b"abcdef").await.unwrap();
+    match upload_attachment(&harness.hub, 1, "b.txt", "text/plain", b"ghijk").await {
+        Err(ChatError::StorageQuotaExceeded { used, requested, quota }) => assert_eq!((used, requested, quota), (6, 5, 10)),
+        other => panic!("quota attendu: {:?}", other),
+    }
+
+    // Exactement au quota : accepté ; le quota d'un autre utilisateur est distinct
+    let second = upload_attachment(&harness.hub, 1, "c.txt", "text/plain", b"ghij").await.unwrap();
+    assert_eq!(get_storage_usage(&harness.hub, 1).await.unwrap().remaining_bytes(), Some(0));
+    upload_attachment(&harness.hub, 2, "d.txt", "text/plain", b"abcdef").await.unwrap();
+
+    // La suppression libère la place et l'objet
+    let usage = delete_attachment(&harness.hub, 1, first.id).await.unwrap();
+    assert_eq!((usage.used_bytes, usage.file_count), (4, 1));
+    assert!(!root.join(format!("attachments/1/{}", first.uuid)).exists());
+    upload_attachment(&harness.hub, 1, "b.txt", "text/plain", b"ghijk").await.unwrap();
+
+    // Le fichier d'un autre utilisateur ne peut pas être supprimé
+    assert!(matches!(delete_attachment(&harness.hub, 2, second.id).await, Err(ChatError::NotFound { .. })));
+
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[tokio::test]
+async fn test_read_state_reaches_every_session_of_reader() {
+    let harness = TestHarness::new();
+    let mut phone = harness.connect(1, "alice").await;
+    let mut laptop = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+
+    let delivered = chat_server::hub::broadcast_read_state(&harness.hub, 1, 10, 99).await;
+    assert_eq!(delivered, 2);
+
+    for session in [&mut phone, &mut laptop] {
+        let frame = session.next_frame(FRAME_TIMEOUT).await.expect("read_state attendu");
+        assert_eq!(frame["type"], "read_state");
+        assert_eq!(frame["data"]["conversationId"], 10);
+        assert_eq!(frame["data"]["lastReadMessageId"], 99);
+    }
+
+    // Le correspondant n'est pas concerné par l'état de lecture
+    assert!(bob.drain_frames().is_empty());
+}
+
+#[tokio::test]
+async fn test_unread_summary_counts_visible_messages_and_skips_muted_rooms() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol")]).await;
+    create_room(&harness, RANDOM, "random", &[(1, "alice"), (2, "bob")]).await;
+    let read = send(&harness, GENERAL, 2, "bob", "déjà lu").await.unwrap();
+    send(&harness, GENERAL, 2, "bob", "un").await.unwrap();
+    send(&harness, GENERAL, 2, "bob", "deux").await.unwrap();
+    send(&harness, GENERAL, 1, "alice", "le mien").await.unwrap();
+    // Chuchotement à carol : jamais compté pour alice
+    send_room_message(&harness.hub, GENERAL, 2, "bob", "psst", None, None, Some(vec![3])).await.unwrap();
+    send(&harness, RANDOM, 2, "bob", "en sourdine").await.unwrap();
+    harness.rooms.set_read_state(GENERAL, 1, Some(read.id), false).await;
+    harness.rooms.set_read_state(RANDOM, 1, None, true).await;
+
+    let summary = get_unread_summary(&harness.hub, 1).await.unwrap();
+    let mut rooms = summary.rooms.iter().map(|room| (room.room_id, room.unread_count, room.is_muted)).collect::<Vec<_>>();
+    rooms.sort();
+    assert_eq!(rooms, vec![(GENERAL, 2, false), (RANDOM, 1, true)]);
+    // Le salon en sourdine reste dans le détail, hors du total
+    assert_eq!(summary.total, 2);
+
+    // Carol n'a rien lu : chuchotement compris
+    assert_eq!(get_unread_summary(&harness.hub, 3).await.unwrap().total, 5);
+}
+
+#[tokio::test]
+async fn test_seen_by_counts_readers_and_pages_identities_when_enabled() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol"), (4, "dave")]).await;
+    let sent = send(&harness, GENERAL, 1, "alice", "qui a lu ?").await.unwrap();
+    let later = send(&harness, GENERAL, 1, "alice", "et ça ?").await.unwrap();
+    harness.rooms.set_read_state(GENERAL, 1, Some(later.id), false).await;
+    harness.rooms.set_read_state(GENERAL, 2, Some(sent.id), false).await;
+    harness.rooms.set_read_state(GENERAL, 3, Some(later.id), false).await;
+
+    // Par défaut : compteur seul, auteur exclu
+    let seen = get_message_seen_by(&harness.hub, GENERAL, sent.id, 4, 10, None).await.unwrap();
+    assert_eq!(seen.seen_count, 2);
+    assert!(seen.members.is_none());
+    assert_eq!(get_message_seen_by(&harness.hub, GENERAL, later.id, 4, 10, None).await.unwrap().seen_count, 1);
+
+    harness.rooms.set_seen_by_mode(GENERAL, SeenByMode::Members).await;
+    let first = get_message_seen_by(&harness.hub, GENERAL, sent.id, 4, 1, None).await.unwrap();
+    assert_eq!(first.members.unwrap().iter().map(|m| m.username.as_str()).collect::<Vec<_>>(), vec!["bob"]);
+    assert_eq!(first.next_cursor, Some(2));
+    let last = get_message_seen_by(&harness.hub, GENERAL, sent.id, 4, 1, first.next_cursor).await.unwrap();
+    assert_eq!(last.members.unwrap()[0].user_id, 3);
+    assert!(last.next_cursor.is_none());
+
+    // Hors du salon : le message n'existe pas pour le demandeur
+    harness.rooms.add_user(5, "eve").await;
+    let outsider = get_message_seen_by(&harness.hub, GENERAL, sent.id, 5, 10, None).await;
+    assert!(matches!(outsider, Err(ChatError::NotFound { .. })));
+}
+
+#[tokio::test]
+async fn test_disabled_reactions_are_rejected_and_announced() {
+    let mut config = ServerConfig::default();
+    config.features.message_reactions = false;
+    let harness = TestHarness::with_config(config);
+
+    let result = add_reaction(&harness.hub, 1, 1, "👍").await;
+    assert!(matches!(
+        result,
+        Err(ChatError::FeatureNotAvailable { ref feature, .. }) if feature == "reactions"
+    ));
+
+    let alice = harness.connect(1, "alice").await;
+    let handshake = alice.handshake.expect("poignée de main attendue");
+    assert_eq!(handshake["type"], "feature_flags");
+    assert_eq!(handshake["data"]["reactions"], false);
+    assert_eq!(handshake["data"]["threads"], true);
+
+    // Surcharge à chaud : les nouvelles connexions voient l'état effectif
+    harness.hub.feature_flags.write().await.set_override(FeatureFlag::Reactions, Some(true));
+    let bob = harness.connect(2, "bob").await;
+    assert_eq!(bob.handshake.unwrap()["data"]["reactions"], true);
+}
+
+#[tokio::test]
+async fn test_spoofing_usernames_are_refused_at_registration() {
+    let harness = TestHarness::new();
+
+    // Forçage droite-à-gauche (U+202E) : « nimda » s'affiche « admin »
+    let rtl = harness.try_connect(1, "user\u{202E}nimda").await;
+    assert!(matches!(rtl, Err(ChatError::InvalidFormat { ref field, .. }) if field == "username"));
+
+    assert!(harness.try_connect(2, "<img src=x>").await.is_err());
+
+    // Homoglyphe cyrillique ramené à l'ASCII
+    let alice = harness.connect(4, "\u{0430}lice").await;
+    assert_eq!(alice.username, "alice");
+    assert!(harness.hub.clients.get(&1).await.is_none());
+}
+
+#[tokio::test]
+async fn test_reserved_names_are_refused_on_create_or_rename_only() {
+    let harness = guest_harness(3);
+
+    // Compte existant : la connexion n'est pas refusée
+    let admin = harness.connect(3, "Ad_min").await;
+    assert_eq!(admin.username, "Ad_min");
+
+    let rename = ProfileUpdate { display_name: Some("Ad_min".to_string()), ..ProfileUpdate::default() };
+    let refused = update_user_profile(&harness.hub, 4, rename).await;
+    assert!(matches!(refused, Err(ChatError::InvalidFormat { ref field, .. }) if field == "username"));
+
+    assert!(matches!(harness.connect_guest(Some("\u{0430}dmin")).await, Err(ChatError::InvalidFormat { .. })));
+    assert!(harness.connect_guest(Some("visiteur")).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_message_send_emits_metrics_through_sink() {
+    let sink = Arc::new(RecordingSink::new());
+    let harness = TestHarness::with_metrics(ServerConfig::default(), sink.clone());
+
+    harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice")]).await;
+    send(&harness, GENERAL, 1, "alice", "bonjour").await.unwrap();
+
+    let sent = sink.named("messages_sent_total");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].kind, MetricType::Counter);
+    assert_eq!(sent[0].value, 1.0);
+    assert_eq!(sent[0].labels.get("room").map(String::as_str), Some("10"));
+
+    let size = sink.named("message_size_bytes");
+    assert_eq!(size.len(), 1);
+    assert_eq!(size[0].kind, MetricType::Histogram);
+    assert_eq!(size[0].value, "bonjour".len() as f64);
+}
+
+#[tokio::test]
+async fn test_session_lock_waits_are_exported_per_map() {
+    let sink = Arc::new(RecordingSink::new());
+    let harness = TestHarness::with_metrics(ServerConfig::default(), sink.clone());
+
+    let alice = harness.connect(1, "alice").await;
+    harness.connect(2, "bob").await;
+    harness.disconnect_session(&alice).await;
+    report_lock_waits(&harness.hub).await;
+
+    let acquisitions = sink.named("hub_lock_acquisitions");
+    let maps: Vec<_> = acquisitions.iter()
+        .map(|record| record.labels.get("map").map(String::as_str).unwrap_or(""))
+        .collect();
+    assert_eq!(maps, vec!["clients", "sessions", "rooms"]);
+    // Enregistrements et fermeture : les sessions passent par leurs fragments
+    assert!(acquisitions[1].value >= 3.0);
+    assert_eq!(sink.named("hub_lock_contended").len(), 3);
+}
+
+#[tokio::test]
+async fn test_persisted_rooms_are_restored_on_reconnect() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(2, "bob")]).await;
+    create_room(&harness, RANDOM, "random", &[]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "rooms_restored");
+
+    harness.rooms.add_member(GENERAL, 1, "member").await;
+    harness.rooms.add_member(RANDOM, 1, "member").await;
+    let mut alice = harness.connect(1, "alice").await;
+
+    let restored = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(restored["type"], "rooms_restored");
+    assert_eq!(restored["data"]["rooms"][0]["roomId"], GENERAL);
+    assert_eq!(restored["data"]["rooms"][1]["roomName"], "random");
+
+    let joined = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(joined["type"], "member_joined");
+    assert_eq!(joined["data"]["roomId"], GENERAL);
+    assert_eq!(joined["data"]["username"], "alice");
+    assert_eq!(joined["data"]["restored"], true);
+
+    // Absent des salons en mémoire après la déconnexion, replacé à la reconnexion
+    harness.disconnect(1).await;
+    assert!(!harness.hub.rooms.get(&general()).await.unwrap_or_default().contains(&1));
+    bob.drain_frames();
+    let mut alice = harness.connect(1, "alice").await;
+    assert_eq!(alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "rooms_restored");
+    assert!(harness.hub.rooms.get(&general()).await.unwrap_or_default().contains(&1));
+
+    send(&harness, GENERAL, 1, "alice", "me revoilà").await.unwrap();
+    let frames = bob.drain_frames();
+    assert!(frames.iter().any(|f| f["type"] == "room_message" && f["data"]["content"] == "me revoilà"));
+}
+
+#[tokio::test]
+async fn test_transient_joins_are_not_restored() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(2, "bob")]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    bob.drain_frames();
+
+    // Présence en mémoire sans adhésion (consultation passagère)
+    harness.connect(1, "alice").await;
+    harness.hub.rooms.update(general(), |members| members.push(1)).await;
+    harness.disconnect(1).await;
+
+    let mut alice = harness.connect(1, "alice").await;
+    assert!(alice.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(bob.drain_frames().is_empty());
+    assert!(matches!(send(&harness, GENERAL, 1, "alice", "coucou").await, Err(ChatError::Unauthorized { .. })));
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_with_server_restart() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+
+    harness.hub.shutdown().await;
+
+    let (code, reason) = alice.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!(code, 4000);
+    assert_eq!(reason["reason"], "server_restart");
+    assert_eq!(reason["retry"], "backoff");
+    assert!(harness.hub.clients.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_kick_closes_every_session_with_kicked() {
+    let harness = TestHarness::new();
+    let mut phone = harness.connect(1, "alice").await;
+    let mut laptop = harness.connect(1, "alice").await;
+
+    assert_eq!(harness.hub.disconnect_user(1, CloseReason::Kicked, Some("Spam")).await, 2);
+
+    for session in [&mut phone, &mut laptop] {
+        let (code, reason) = session.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+        assert_eq!(code, 4002);
+        assert_eq!(reason["retry"], "never");
+        assert_eq!(reason["message"], "Spam");
+    }
+}
+
+#[tokio::test]
+async fn test_closing_one_session_keeps_the_others() {
+    let harness = TestHarness::new();
+    let phone = harness.connect(1, "alice").await;
+    let mut laptop = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    harness.rooms.add_contact(1, 2).await;
+    subscribe_presence(&harness.hub, 2, &[1], &[]).await.unwrap();
+
+    laptop.drain_frames();
+    bob.drain_frames();
+
+    harness.disconnect_session(&phone).await;
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(harness.hub.clients.get(&1).await.is_some());
+    assert_eq!(harness.hub.send_to_user_sessions(1, r#"{"type":"ping"}"#).await, 1);
+    assert_eq!(laptop.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "ping");
+
+    harness.disconnect_session(&laptop).await;
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["data"]["event"], "offline");
+    assert!(harness.hub.clients.get(&1).await.is_none());
+}
+
+#[tokio::test]
+async fn test_oldest_session_is_closed_as_replaced() {
+    let mut config = ServerConfig::default();
+    config.limits.max_connections_per_user = 1;
+    let harness = TestHarness::with_config(config);
+
+    let mut first = harness.connect(1, "alice").await;
+    let mut second = harness.connect(1, "alice").await;
+
+    let (code, reason) = first.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!(code, 4005);
+    assert_eq!(reason["reason"], "session_replaced");
+    assert!(second.next_close(FRAME_TIMEOUT).await.is_none());
+}
+
+#[tokio::test]
+async fn test_expired_token_and_dead_heartbeat_are_closed() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+
+    harness.hub.clients.with_mut(&1, |client| client.unwrap().token_expires_at = Some(0)).await;
+    if let Some(stale) = Instant::now().checked_sub(Duration::from_secs(86_400)) {
+        harness.hub.clients.with(&2, |client| *client.unwrap().last_heartbeat.write().unwrap() = stale).await;
+    }
+
+    harness.hub.cleanup_dead_connections().await;
+
+    let (code, reason) = alice.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!((code, reason["retry"].as_str()), (4001, Some("reauthenticate")));
+
+    let (code, reason) = bob.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!((code, reason["retry"].as_str()), (4006, Some("immediate")));
+}
+
+#[tokio::test]
+async fn test_idle_connection_is_closed_as_idle_timeout() {
+    let mut config = ServerConfig::default();
+    config.server.idle_timeout = Duration::from_secs(600);
+    let harness = TestHarness::with_config(config);
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+
+    if let Some(stale) = Instant::now().checked_sub(Duration::from_secs(601)) {
+        harness.hub.clients.with(&1, |client| *client.unwrap().last_activity.write().unwrap() = stale).await;
+    }
+    harness.hub.cleanup_dead_connections().await;
+
+    let (code, reason) = alice.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!(code, 4007);
+    assert_eq!(reason["reason"], "idle_timeout");
+    assert_eq!(reason["retry"], "backoff");
+    assert!(bob.next_close(FRAME_TIMEOUT).await.is_none());
+}
+
+#[tokio::test]
+async fn test_force_disconnect_and_ban_close_codes() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+
+    harness.hub.disconnect_user(1, CloseReason::ForceDisconnect, Some("Maintenance")).await;
+    let (code, reason) = alice.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!(code, 4008);
+    assert_eq!(reason["reason"], "force_disconnect");
+    assert_eq!(reason["message"], "Maintenance");
+
+    // Bannissement temporaire : pas de reconnexion automatique avant `retryAfter`
+    harness.hub.disconnect_user(2, CloseReason::Banned { retry_after_secs: Some(3600) }, None).await;
+    let (code, reason) = bob.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!(code, 4003);
+    assert_eq!(reason["retry"], "never");
+    assert_eq!(reason["retryAfter"], 3600);
+    assert!(harness.hub.clients.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_old_schema_client_receives_trimmed_message() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+
+    assert_eq!(harness.hub.negotiate_schema(2, 1).await, 1);
+    send(&harness, GENERAL, 1, "alice", "salut @bob").await.unwrap();
+
+    // Version courante : trame complète
+    let full = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(full["schemaVersion"], CURRENT_SCHEMA_VERSION);
+    assert_eq!(full["data"]["mentions"][0]["target"], "bob");
+    assert_eq!(full["data"]["isLong"], false);
+
+    // Version 1 : mêmes champs d'origine, sans les champs plus récents
+    let trimmed = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(trimmed["schemaVersion"], 1);
+    assert_eq!(trimmed["data"]["content"], full["data"]["content"]);
+    assert_eq!(trimmed["data"]["id"], full["data"]["id"]);
+    for field in ["mentions", "isLong", "fullLength", "quote", "restricted", "visibleTo"] {
+        assert!(trimmed["data"].get(field).is_none(), "{} ne devrait pas être envoyé", field);
+    }
+}
+
+#[tokio::test]
+async fn test_flagged_message_is_held_for_its_author_only() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+
+    let held = send(&harness, GENERAL, 1, "alice", "VENEZ TOUS CE SOIR").await.unwrap();
+
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "message_held");
+    assert_eq!(frame["data"]["id"], held.id);
+    assert_eq!(frame["data"]["roomId"], GENERAL);
+    assert!(frame["data"]["reason"].as_str().is_some_and(|reason| !reason.is_empty()));
+
+    // Invisible des autres membres et de l'historique
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(harness.rooms.room_history(GENERAL).await.is_empty());
+    assert_eq!(harness.rooms.len().await, 1);
+
+    // Un message propre passe normalement
+    send(&harness, GENERAL, 1, "alice", "bonjour à tous").await.unwrap();
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "room_message");
+}
+
+#[tokio::test]
+async fn test_flagged_edit_is_rejected_and_keeps_the_original() {
+    let harness = TestHarness::new();
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+    let sent = send(&harness, GENERAL, 1, "alice", "bonjour à tous").await.unwrap();
+    bob.drain_frames();
+
+    // Message propre édité en contenu signalé : refusé, rien n'est diffusé
+    let refused = edit_room_message(&harness.hub, GENERAL, sent.id, 1, "VENEZ TOUS CE SOIR").await;
+    assert!(matches!(refused, Err(ChatError::InappropriateContent { .. })));
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    let history: Vec<String> = harness.rooms.room_history(GENERAL).await.into_iter().map(|message| message.content).collect();
+    assert_eq!(history, ["bonjour à tous"]);
+
+    // Une édition propre passe
+    edit_room_message(&harness.hub, GENERAL, sent.id, 1, "bonsoir à tous").await.unwrap();
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "room_message_edited");
+}
+
+/// Escalade active : avertissement, envoi suspendu puis bannissement
+fn escalation_harness(mute_at: u32, ban_at: u32) -> TestHarness {
+    let mut config = ServerConfig::default();
+    let escalation = &mut config.security.violation_escalation;
+    escalation.enabled = true;
+    escalation.warn_at = 1;
+    escalation.mute_at = mute_at;
+    escalation.flag_at = 0;
+    escalation.ban_at = ban_at;
+    TestHarness::with_config(config)
+}
+
+#[tokio::test]
+async fn test_repeated_filter_violations_mute_the_author() {
+    let harness = escalation_harness(2, 0);
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+
+    assert!(matches!(send(&harness, GENERAL, 1, "alice", "kys").await, Err(ChatError::InappropriateContent { .. })));
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "moderation_notice");
+    assert_eq!(frame["data"]["action"], "warn");
+    // Un avertissement n'empêche pas d'écrire
+    send(&harness, GENERAL, 1, "alice", "pardon").await.unwrap();
+    bob.drain_frames();
+
+    assert!(send(&harness, GENERAL, 1, "alice", "kys").await.is_err());
+    let frame = alice.drain_frames().into_iter().find(|f| f["type"] == "moderation_notice").expect("sanction annoncée");
+    assert_eq!(frame["data"]["action"], "mute");
+    assert_eq!(frame["data"]["violations"], 2);
+    assert!(frame["data"]["until"].is_string());
+
+    // Envoi suspendu, même pour un message propre ; les autres membres écrivent toujours
+    assert!(matches!(send(&harness, GENERAL, 1, "alice", "bonjour").await, Err(ChatError::UserMuted { .. })));
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    send(&harness, GENERAL, 2, "bob", "bonjour").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_temp_banned_user_cannot_reconnect() {
+    let harness = escalation_harness(0, 2);
+    let mut alice = harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice")]).await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+
+    assert!(send(&harness, GENERAL, 1, "alice", "kys").await.is_err());
+    assert!(send(&harness, GENERAL, 1, "alice", "kys").await.is_err());
+
+    let (code, reason) = alice.next_close(FRAME_TIMEOUT).await.expect("fermeture attendue");
+    assert_eq!(code, 4003);
+    assert_eq!(reason["retryAfter"], 86400);
+    assert!(harness.hub.clients.is_empty().await);
+
+    // Le bannissement vaut jusqu'à son échéance, à la reconnexion comme à l'envoi
+    assert!(matches!(harness.try_connect(1, "alice").await, Err(ChatError::AccountSuspended { .. })));
+    assert!(matches!(send(&harness, GENERAL, 1, "alice", "bonjour").await, Err(ChatError::AccountSuspended { .. })));
+    // Escalade désactivée : aucune sanction
+    let harness = TestHarness::new();
+    harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice")]).await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+    for _ in 0..3 {
+        assert!(matches!(send(&harness, GENERAL, 1, "alice", "kys").await, Err(ChatError::InappropriateContent { .. })));
+    }
+    send(&harness, GENERAL, 1, "alice", "bonjour").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_held_message_is_broadcast_on_approval() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol")]).await;
+    harness.rooms.add_member(GENERAL, 3, "moderator").await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+
+    let held = send(&harness, GENERAL, 1, "alice", "VENEZ TOUS CE SOIR").await.unwrap();
+    let rejected = send(&harness, GENERAL, 1, "alice", "VENEZ TOUS DEMAIN").await.unwrap();
+    alice.drain_frames();
+    carol.drain_frames();
+
+    review_held_message(&harness.hub, held.id, 3, true, None).await.unwrap();
+
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message");
+    assert_eq!(frame["data"]["id"], held.id);
+    assert_eq!(frame["data"]["content"], "VENEZ TOUS CE SOIR");
+
+    let frames = alice.drain_frames();
+    assert!(frames.iter().any(|f| f["type"] == "room_message"));
+    assert!(frames.iter().any(|f| f["type"] == "message_approved" && f["data"]["id"] == held.id));
+
+    // Rejet : l'auteur est prévenu, le message n'est jamais diffusé
+    review_held_message(&harness.hub, rejected.id, 3, false, Some("Hors sujet")).await.unwrap();
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "message_rejected");
+    assert_eq!(frame["data"]["reason"], "Hors sujet");
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    let history = harness.rooms.room_history(GENERAL).await;
+    assert_eq!(history.iter().map(|m| m.id).collect::<Vec<_>>(), vec![held.id]);
+    assert!(review_held_message(&harness.hub, held.id, 3, true, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_premoderated_room_holds_untrusted_members_until_approval() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_member(GENERAL, 2, "moderator").await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Approve).await;
+
+    // Message propre d'un membre : en attente, visible de son seul auteur
+    let pending = send(&harness, GENERAL, 1, "alice", "bonjour à tous").await.unwrap();
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "message_held");
+    assert_eq!(frame["data"]["id"], pending.id);
+    // Le modérateur est prévenu, sans recevoir le message
+    let flagged = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(flagged["type"], "message_flagged");
+    assert_eq!(flagged["data"]["messageId"], pending.id);
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(harness.rooms.room_history(GENERAL).await.is_empty());
+
+    // Les modérateurs ne sont pas retenus
+    let announcement = send(&harness, GENERAL, 2, "bob", "bienvenue").await.unwrap();
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message");
+    assert_eq!(frame["data"]["id"], announcement.id);
+    bob.drain_frames();
+
+    review_held_message(&harness.hub, pending.id, 2, true, None).await.unwrap();
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message");
+    assert_eq!(frame["data"]["id"], pending.id);
+    assert!(alice.drain_frames().iter().any(|f| f["type"] == "message_approved"));
+}
+
+#[tokio::test]
+async fn test_premoderated_room_trusted_roles_review_rights_and_rejection() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol"), (4, "dave")]).await;
+    harness.rooms.add_member(GENERAL, 2, "moderator").await;
+    harness.rooms.add_member(GENERAL, 4, "owner").await;
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Approve).await;
+
+    // Propriétaire et modérateur publient sans examen
+    for (author_id, username) in [(4, "dave"), (2, "bob")] {
+        let sent = send(&harness, GENERAL, author_id, username, "annonce").await.unwrap();
+        let frame = carol.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+        assert_eq!(frame["type"], "room_message");
+        assert_eq!(frame["data"]["id"], sent.id);
+    }
+    alice.drain_frames();
+    bob.drain_frames();
+
+    let since = chrono::Utc::now();
+    let pending = send(&harness, GENERAL, 1, "alice", "hors sujet").await.unwrap();
+    assert_eq!(alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "message_held");
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "message_flagged");
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // L'auteur retrouve son message en attente au rattrapage, pas les autres membres
+    let own = get_missed_events(&harness.hub, 1, &MissedCursor::since(since), 10).await.unwrap();
+    assert_eq!(own.events.iter().map(|event| event.data["messageId"].as_i64().unwrap()).collect::<Vec<_>>(), vec![pending.id]);
+    assert!(get_missed_events(&harness.hub, 3, &MissedCursor::since(since), 10).await.unwrap().events.is_empty());
+
+    // Un simple membre n'examine pas ; le message reste en attente
+    let refused = review_held_message(&harness.hub, pending.id, 3, true, None).await;
+    assert!(matches!(refused, Err(ChatError::Unauthorized { ref action }) if action == "review_held_message"));
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(alice.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // Rejet : seul l'auteur est prévenu, avec le motif
+    review_held_message(&harness.hub, pending.id, 2, false, Some("Hors sujet")).await.unwrap();
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "message_rejected");
+    assert_eq!(frame["data"]["id"], pending.id);
+    assert_eq!(frame["data"]["roomId"], GENERAL);
+    assert_eq!(frame["data"]["reason"], "Hors sujet");
+    assert!(alice.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // Rejeté : ni historique, ni rattrapage, ni second examen
+    assert!(harness.rooms.room_history(GENERAL).await.iter().all(|m| m.id != pending.id));
+    assert!(get_missed_events(&harness.hub, 1, &MissedCursor::since(since), 10).await.unwrap().events.is_empty());
+    assert!(matches!(
+        review_held_message(&harness.hub, pending.id, 2, true, None).await,
+        Err(ChatError::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_announcement_room_lets_members_react_but_not_post() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_member(GENERAL, 1, "moderator").await;
+    harness.rooms.set_post_policy(GENERAL, RoomPostPolicy::Announcement).await;
+
+    // Simple membre : publication refusée, rien n'est stocké ni diffusé
+    let refused = send(&harness, GENERAL, 2, "bob", "je peux parler ?").await;
+    assert!(matches!(
+        refused,
+        Err(ChatError::InsufficientPermissions { ref action, .. }) if action == "send_room_message"
+    ));
+    assert!(harness.rooms.is_empty().await);
+    assert!(alice.drain_frames().is_empty());
+
+    // Modérateur : publie ; le membre réagit et tout le salon voit la réaction
+    let announcement = send(&harness, GENERAL, 1, "alice", "maintenance ce soir").await.unwrap();
+    bob.drain_frames();
+    alice.drain_frames();
+    add_reaction(&harness.hub, announcement.id, 2, "👍").await.unwrap();
+    for member in [&mut alice, &mut bob] {
+        let frame = member.next_frame(FRAME_TIMEOUT).await.expect("reaction_update attendu");
+        assert_eq!(frame["type"], "reaction_update");
+        assert_eq!(frame["data"]["messageId"], announcement.id);
+        assert_eq!(frame["data"]["userId"], 2);
+        assert_eq!(frame["data"]["action"], "added");
+    }
+    assert_eq!(reaction_counts(&harness, announcement.id, 1).await, vec![("👍".to_string(), 1)]);
+
+    // Non-membre : ni publication, ni réaction
+    harness.rooms.add_user(3, "carol").await;
+    assert!(matches!(add_reaction(&harness.hub, announcement.id, 3, "👍").await, Err(ChatError::Unauthorized { .. })));
+
+    // Modérateurs uniquement : le membre perd aussi la réaction
+    harness.rooms.set_post_policy(GENERAL, RoomPostPolicy::ModeratorsOnly).await;
+    assert!(matches!(add_reaction(&harness.hub, announcement.id, 2, "🎉").await, Err(ChatError::Unauthorized { .. })));
+    add_reaction(&harness.hub, announcement.id, 1, "🎉").await.unwrap();
+    assert_eq!(
+        reaction_counts(&harness, announcement.id, 1).await,
+        vec![("🎉".to_string(), 1), ("👍".to_string(), 1)]
+    );
+}
+
+#[tokio::test]
+async fn test_archived_room_blocks_send() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice")]).await;
+    harness.rooms.add_member(GENERAL, 1, "owner").await;
+    send(&harness, GENERAL, 1, "alice", "dernier message").await.unwrap();
+    alice.drain_frames();
+
+    archive_room(&harness.hub, GENERAL, 1).await.unwrap();
+    let frames = alice.drain_frames();
+    let updated = frames.iter().find(|f| f["type"] == "room_updated").expect("room_updated attendu");
+    assert_eq!(updated["data"]["roomId"], GENERAL);
+    assert_eq!(updated["data"]["isArchived"], true);
+    assert_eq!(updated["data"]["updatedBy"], 1);
+    assert!(harness.rooms.is_archived(GENERAL).await);
+
+    let err = send(&harness, GENERAL, 1, "alice", "encore là ?").await.unwrap_err();
+    assert!(matches!(err, ChatError::ConversationArchived { .. }));
+    assert!(archive_room(&harness.hub, GENERAL, 1).await.is_err());
+
+    // L'historique reste consultable
+    assert_eq!(harness.rooms.room_history(GENERAL).await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_unarchived_room_accepts_send() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (3, "carol")]).await;
+    harness.rooms.add_member(GENERAL, 1, "owner").await;
+    archive_room(&harness.hub, GENERAL, 1).await.unwrap();
+    unarchive_room(&harness.hub, GENERAL, 1).await.unwrap();
+
+    let frames = alice.drain_frames();
+    assert_eq!(frames.last().expect("trame attendue")["data"]["isArchived"], false);
+    carol.drain_frames();
+
+    send(&harness, GENERAL, 1, "alice", "de retour").await.unwrap();
+    assert_eq!(carol.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "room_message");
+}
+
+#[tokio::test]
+async fn test_only_room_moderators_archive_or_unarchive() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_member(GENERAL, 2, "moderator").await;
+
+    // Simple membre et non-membre : refusés, rien n'est diffusé
+    for actor in [1, 3] {
+        let refused = archive_room(&harness.hub, GENERAL, actor).await;
+        assert!(matches!(refused, Err(ChatError::Unauthorized { ref action }) if action == "archive_room"));
+    }
+    assert!(!harness.rooms.is_archived(GENERAL).await);
+    assert!(alice.drain_frames().is_empty());
+
+    archive_room(&harness.hub, GENERAL, 2).await.unwrap();
+    assert!(harness.rooms.is_archived(GENERAL).await);
+    alice.drain_frames();
+
+    let refused = unarchive_room(&harness.hub, GENERAL, 1).await;
+    assert!(matches!(refused, Err(ChatError::Unauthorized { ref action }) if action == "unarchive_room"));
+    assert!(harness.rooms.is_archived(GENERAL).await);
+    assert!(alice.drain_frames().is_empty());
+
+    // Un modérateur parti du salon perd ses droits
+    harness.rooms.remove_member(GENERAL, 2).await;
+    assert!(matches!(unarchive_room(&harness.hub, GENERAL, 2).await, Err(ChatError::Unauthorized { .. })));
+    assert!(harness.rooms.is_archived(GENERAL).await);
+
+    // Seul l'archivage accepté a été diffusé
+    let frames = bob.drain_frames();
+    assert_eq!(frames.iter().map(|f| f["type"].as_str().unwrap()).collect::<Vec<_>>(), vec!["system_message", "room_updated"]);
+    assert!(carol.drain_frames().is_empty());
+}
+
+#[tokio::test]
+async fn test_junior_moderators_delete_in_assigned_rooms_and_admins_anywhere() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol")]).await;
+    create_room(&harness, RANDOM, "random", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_member(GENERAL, 2, "moderator").await;
+    harness.rooms.add_user(4, "dave").await;
+    harness.rooms.add_admin(4).await;
+    let mut carol = harness.connect(3, "carol").await;
+    let in_general = send(&harness, GENERAL, 1, "alice", "un").await.unwrap();
+    let other_in_general = send(&harness, GENERAL, 1, "alice", "deux").await.unwrap();
+    let in_random = send(&harness, RANDOM, 1, "alice", "trois").await.unwrap();
+    carol.drain_frames();
+
+    // Modérateur de general : supprime dans ce salon, pas ailleurs
+    delete_room_message(&harness.hub, GENERAL, in_general.id, 2).await.unwrap();
+    let frame = carol.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message_deleted");
+    assert_eq!(frame["data"]["messageId"], in_general.id);
+    let refused = delete_room_message(&harness.hub, RANDOM, in_random.id, 2).await;
+    assert!(matches!(refused, Err(ChatError::Unauthorized { .. })));
+
+    // Simple membre : refusé sur le message d'autrui
+    let refused = delete_room_message(&harness.hub, GENERAL, other_in_general.id, 3).await;
+    assert!(matches!(refused, Err(ChatError::Unauthorized { .. })));
+
+    // Administrateur global : partout, sans être membre du salon
+    delete_room_message(&harness.hub, RANDOM, in_random.id, 4).await.unwrap();
+
+    let general: Vec<String> = harness.rooms.room_history(GENERAL).await.into_iter().map(|message| message.content).collect();
+    assert_eq!(general, ["deux"]);
+    assert!(harness.rooms.room_history(RANDOM).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_deletion_reaches_only_the_message_audience() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol")]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    let whisper = send_room_message(&harness.hub, GENERAL, 1, "alice", "pour bob", None, None, Some(vec![2])).await.unwrap();
+    bob.drain_frames();
+    assert!(carol.drain_frames().is_empty());
+
+    // Message restreint : le destinataire apprend la suppression, pas le reste du salon
+    delete_room_message(&harness.hub, GENERAL, whisper.id, 1).await.unwrap();
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message_deleted");
+    assert_eq!(frame["data"]["messageId"], whisper.id);
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // Message retenu : seul son auteur connaît son existence
+    harness.rooms.set_filter_mode(GENERAL, RoomFilterMode::Flag).await;
+    let mut alice = harness.connect(1, "alice").await;
+    let held = send(&harness, GENERAL, 1, "alice", "VENEZ TOUS CE SOIR").await.unwrap();
+    alice.drain_frames();
+    delete_room_message(&harness.hub, GENERAL, held.id, 1).await.unwrap();
+    assert_eq!(alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "room_message_deleted");
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+}
+
+/// Trames `mention` reçues depuis le dernier relevé
+fn mention_frames(client: &mut TestClient) -> Vec<serde_json::Value> {
+    client.drain_frames().into_iter().filter(|frame| frame["type"] == "mention").collect()
+}
+
+#[tokio::test]
+async fn test_edited_mentions_notify_added_users_only_and_drop_removed_ones() {
+    let harness = TestHarness::new();
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol")]).await;
+
+    let sent = send(&harness, GENERAL, 1, "alice", "salut @bob").await.unwrap();
+    let notified = mention_frames(&mut bob);
+    assert_eq!(notified.len(), 1);
+    assert_eq!(notified[0]["data"]["messageId"], sent.id);
+    assert!(mention_frames(&mut carol).is_empty());
+
+    // Mention ajoutée : seule carol est notifiée, bob ne l'est pas une seconde fois
+    edit_room_message(&harness.hub, GENERAL, sent.id, 1, "salut @bob et @carol").await.unwrap();
+    let notified = mention_frames(&mut carol);
+    assert_eq!(notified.len(), 1);
+    assert_eq!(notified[0]["data"]["messageId"], sent.id);
+    assert!(mention_frames(&mut bob).is_empty());
+    assert_eq!(harness.rooms.mentioned_users(sent.id).await, [2, 3]);
+
+    // Mention retirée : la ligne de bob disparaît, personne n'est notifié
+    edit_room_message(&harness.hub, GENERAL, sent.id, 1, "salut @carol").await.unwrap();
+    assert!(mention_frames(&mut bob).is_empty());
+    assert!(mention_frames(&mut carol).is_empty());
+    assert_eq!(harness.rooms.mentioned_users(sent.id).await, [3]);
+}
+
+#[tokio::test]
+async fn test_reordered_pins_are_returned_and_broadcast_in_order() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_member(GENERAL, 1, "moderator").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut pinned = Vec::new();
+    for content in ["un", "deux", "trois"] {
+        let sent = send(&harness, GENERAL, 1, "alice", content).await.unwrap();
+        pin_message(&harness.hub, GENERAL, sent.id, 1, true, None).await.unwrap();
+        pinned.push(sent.id);
+    }
+    let (first, second, third) = (pinned[0], pinned[1], pinned[2]);
+    assert!(matches!(pin_message(&harness.hub, GENERAL, first, 2, false, None).await, Err(ChatError::Unauthorized { .. })));
+    bob.drain_frames();
+
+    // Épingles listées en tête, les autres de la plus récente à la plus ancienne
+    assert_eq!(reorder_pins(&harness.hub, GENERAL, &[first], 1).await.unwrap(), vec![first, third, second]);
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "pins_updated");
+    assert_eq!(frame["data"]["messageIds"], serde_json::json!([first, third, second]));
+
+    // Un désépinglage retire le message de l'ordre, qui est conservé pour les autres
+    pin_message(&harness.hub, GENERAL, third, 1, false, None).await.unwrap();
+    assert_eq!(reorder_pins(&harness.hub, GENERAL, &[second], 1).await.unwrap(), vec![second, first]);
+
+    assert!(matches!(reorder_pins(&harness.hub, GENERAL, &[first], 2).await, Err(ChatError::Unauthorized { .. })));
+    assert!(matches!(reorder_pins(&harness.hub, GENERAL, &[third], 1).await, Err(ChatError::NotFound { .. })));
+    assert!(reorder_pins(&harness.hub, GENERAL, &[first, first], 1).await.is_err());
+}
+
+#[tokio::test]
+async fn test_directory_hides_private_and_archived_rooms() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    create_room(&harness, SUPPORT, "staff", &[(2, "bob")]).await;
+    harness.rooms.set_private(SUPPORT).await;
+    create_room(&harness, RANDOM, "random", &[(2, "bob")]).await;
+    harness.rooms.add_member(RANDOM, 2, "owner").await;
+    archive_room(&harness.hub, RANDOM, 2).await.unwrap();
+    let names = |rooms: Page<RoomInfo>| rooms.items.into_iter().map(|room| room.name).collect::<Vec<_>>();
+
+    // Non-membre : ni le salon privé, ni le salon archivé, même par préfixe
+    let listed = list_rooms(&harness.hub, 1, RoomFilter::default(), 10, None).await.unwrap();
+    assert_eq!(names(listed), vec!["general"]);
+    let by_prefix = RoomFilter { name_prefix: Some("sta".to_string()), ..Default::default() };
+    assert!(list_rooms(&harness.hub, 1, by_prefix.clone(), 10, None).await.unwrap().is_empty());
+
+    // Membre du salon privé : listé, sauf avec `public_only`
+    assert_eq!(names(list_rooms(&harness.hub, 2, by_prefix, 10, None).await.unwrap()), vec!["staff"]);
+    let public_only = RoomFilter { public_only: true, ..Default::default() };
+    assert_eq!(names(list_rooms(&harness.hub, 2, public_only, 10, None).await.unwrap()), vec!["general"]);
+
+    // Deux salons visibles, pages d'un seul : la seconde reprend après la première
+    let order = RoomFilter::default().order;
+    let first = list_rooms(&harness.hub, 2, RoomFilter::default(), 1, None).await.unwrap();
+    assert_eq!(first.len(), 1);
+    assert!(first.has_more);
+    assert_eq!(first.next_cursor, None);
+
+    let encoded = RoomCursor::next_page(&first, order).expect("curseur de la page suivante").encode();
+    let cursor = RoomCursor::decode(&encoded).unwrap();
+    let second = list_rooms(&harness.hub, 2, RoomFilter::default(), 1, Some(cursor)).await.unwrap();
+    assert_eq!(second.len(), 1);
+    assert!(!second.has_more);
+    assert!(RoomCursor::next_page(&second, order).is_none());
+
+    let mut seen = names(first);
+    seen.extend(names(second));
+    seen.sort();
+    assert_eq!(seen, vec!["general", "staff"]);
+}
+
+#[tokio::test]
+async fn test_archive_change_is_broadcast_to_room_members() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_member(GENERAL, 2, "moderator").await;
+
+    archive_room(&harness.hub, GENERAL, 2).await.unwrap();
+
+    // Chaque membre connecté, acteur compris, reçoit l'annonce puis l'état du salon
+    for member in [&mut alice, &mut bob] {
+        let announced = member.next_frame(FRAME_TIMEOUT).await.expect("system_message attendu");
+        assert_eq!(announced["type"], "system_message");
+        assert_eq!(announced["data"]["roomId"], GENERAL);
+        assert_eq!(announced["data"]["event"], "room_archived");
+        assert_eq!(announced["data"]["actorId"], 2);
+
+        let updated = member.next_frame(FRAME_TIMEOUT).await.expect("room_updated attendu");
+        assert_eq!(updated["type"], "room_updated");
+        assert_eq!(updated["data"], serde_json::json!({ "roomId": GENERAL, "isArchived": true, "updatedBy": 2 }));
+    }
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // Déjà archivé : refus, aucune nouvelle diffusion
+    assert!(archive_room(&harness.hub, GENERAL, 2).await.is_err());
+    assert!(alice.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    unarchive_room(&harness.hub, GENERAL, 2).await.unwrap();
+    let frames = alice.drain_frames();
+    assert_eq!(frames.iter().map(|f| f["type"].as_str().unwrap()).collect::<Vec<_>>(), vec!["system_message", "room_updated"]);
+    assert_eq!(frames[0]["data"]["event"], "room_unarchived");
+    assert_eq!(frames[1]["data"]["isArchived"], false);
+    assert!(!harness.rooms.is_archived(GENERAL).await);
+
+    // Non archivé : refus
+    assert!(unarchive_room(&harness.hub, GENERAL, 2).await.is_err());
+}
+
+#[tokio::test]
+async fn test_presence_reaches_only_subscribers() {
+    let harness = TestHarness::new();
+    let _alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    harness.rooms.add_contact(1, 2).await;
+    harness.rooms.add_contact(2, 4).await;
+
+    let snapshot = subscribe_presence(&harness.hub, 2, &[1, 4], &[]).await.unwrap();
+    assert_eq!(snapshot["type"], "presence_snapshot");
+    let users = snapshot["data"]["users"].as_array().unwrap();
+    assert_eq!(users[0]["user_id"], 1);
+    assert_eq!(users[0]["status"], "Online");
+    assert_eq!(users[1]["status"], "Offline");
+
+    harness.disconnect(1).await;
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "presence_update");
+    assert_eq!(frame["data"]["event"], "offline");
+    assert_eq!(frame["data"]["user_id"], 1);
+    assert!(carol.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    let _alice = harness.connect(1, "alice").await;
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["data"]["event"], "online");
+}
+
+#[tokio::test]
+async fn test_presence_requires_a_contact_or_shared_room_without_block() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    create_room(&harness, SUPPORT, "support", &[(1, "alice"), (4, "dave"), (5, "eve")]).await;
+    let _alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let mut carol = harness.connect(3, "carol").await;
+    let mut dave = harness.connect(4, "dave").await;
+    let mut eve = harness.connect(5, "eve").await;
+
+    // Inconnus : refusé
+    assert!(matches!(subscribe_presence(&harness.hub, 3, &[1], &[]).await, Err(ChatError::Unauthorized { .. })));
+    harness.rooms.add_contact(1, 3).await;
+    subscribe_presence(&harness.hub, 3, &[1], &[]).await.unwrap();
+
+    // Salon commun, mais blocage : refusé
+    harness.rooms.block_user(1, 4).await;
+    assert!(matches!(subscribe_presence(&harness.hub, 4, &[1], &[]).await, Err(ChatError::Unauthorized { .. })));
+
+    // Salon courant montré aux seuls membres de ce salon
+    harness.hub.presence.set_user_room(1, Some("support".to_string())).await;
+    let snapshot = subscribe_presence(&harness.hub, 2, &[1], &[]).await.unwrap();
+    assert!(snapshot["data"]["users"][0]["current_room"].is_null());
+    let snapshot = subscribe_presence(&harness.hub, 5, &[1], &[]).await.unwrap();
+    assert_eq!(snapshot["data"]["users"][0]["current_room"], "support");
+
+    for client in [&mut bob, &mut carol, &mut dave, &mut eve] {
+        client.drain_frames();
+    }
+    set_presence_status(&harness.hub, 1, UserStatus::Away, None).await.unwrap();
+    for client in [&mut bob, &mut carol] {
+        let frame = client.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+        assert_eq!(frame["data"]["event"], "status_changed");
+        assert!(frame["data"]["current_room"].is_null());
+    }
+    assert_eq!(eve.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["data"]["current_room"], "support");
+    assert!(dave.next_frame(FRAME_TIMEOUT).await.is_none());
+}
+
+#[tokio::test]
+async fn test_connection_flood_is_rejected_at_handshake() {
+    let mut config = ServerConfig::default();
+    config.limits.connections_per_ip = 3;
+    config.limits.connection_window = Duration::from_secs(60);
+    let harness = TestHarness::with_config(config);
+
+    // Connexions ouvertes puis fermées : seul le rythme compte
+    for user_id in 1..=3 {
+        harness.try_connect_from("203.0.113.9", user_id, &format!("user{}", user_id)).await.unwrap();
+        harness.disconnect(user_id).await;
+    }
+
+    let err = harness.try_connect_from("203.0.113.9", 4, "user4").await.err().expect("connexion refusée attendue");
+    assert_eq!(err.http_status(), 429);
+    assert!(matches!(err, ChatError::ConnectionRateExceeded { limit: 3, .. }));
+    assert!(err.retry_after().is_some_and(|secs| secs >= 1));
+    // Refus avant tout enregistrement du client
+    assert!(!harness.hub.clients.contains_key(&4).await);
+
+    // Les autres IP gardent leur budget
+    harness.try_connect_from("198.51.100.2", 4, "user4").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_banned_ip_is_refused_until_unbanned() {
+    let mut config = ServerConfig::default();
+    config.security.persist_ip_bans = false;
+    let harness = TestHarness::with_config(config);
+    harness.rooms.add_admin(1).await;
+
+    assert!(matches!(ban_ip(&harness.hub, 2, "2001:db8::1", "spam", None).await, Err(ChatError::Unauthorized { .. })));
+    assert!(ban_ip(&harness.hub, 1, "not-an-ip", "spam", None).await.is_err());
+
+    // Adresse normalisée avant d'être bannie
+    let banned = ban_ip(&harness.hub, 1, " 2001:DB8::1 ", "spam", None).await.unwrap();
+    assert_eq!(banned.ip_address, "2001:db8::1");
+    assert!(harness.try_connect_from("2001:db8::1", 3, "carol").await.is_err());
+    assert!(!harness.hub.clients.contains_key(&3).await);
+
+    assert!(matches!(unban_ip(&harness.hub, 2, "2001:db8::1").await, Err(ChatError::Unauthorized { .. })));
+    unban_ip(&harness.hub, 1, "2001:DB8::1").await.unwrap();
+    harness.try_connect_from("2001:db8::1", 3, "carol").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_room_message_is_published_to_nats_subject() {
+    let publisher = Arc::new(RecordingPublisher::new());
+    let harness = TestHarness::with_bridge(ServerConfig::default(), publisher.clone());
+    let mut bob = harness.connect(2, "bob").await;
+    let _alice = harness.connect(1, "alice").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+
+    let sent = send(&harness, GENERAL, 1, "alice", "vers le pont").await.unwrap();
+    // La diffusion aux clients n'attend pas la publication
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "room_message");
+
+    let deadline = Instant::now() + FRAME_TIMEOUT;
+    while publisher.published().is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let published = publisher.published();
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].0, "chat.room.10");
+    assert_eq!(published[0].1["type"], "room_message");
+    assert_eq!(published[0].1["data"]["id"], sent.id);
+}
+
+#[tokio::test]
+async fn test_missed_events_cover_the_disconnect_window() {
+    let harness = TestHarness::new();
+    let _alice = harness.connect(1, "alice").await;
+    let _bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    create_room(&harness, RANDOM, "random", &[(1, "alice"), (2, "bob")]).await;
+    create_room(&harness, 12, "hors-adhesion", &[(2, "bob")]).await;
+
+    send(&harness, GENERAL, 2, "bob", "avant la coupure").await.unwrap();
+    harness.disconnect(1).await;
+    let since = chrono::Utc::now();
+
+    // Activité pendant la coupure, dans deux salons et hors de ses salons
+    let first = send(&harness, GENERAL, 2, "bob", "un").await.unwrap();
+    let second = send(&harness, RANDOM, 2, "bob", "deux").await.unwrap();
+    let third = send(&harness, GENERAL, 2, "bob", "trois").await.unwrap();
+    send(&harness, 12, 2, "bob", "ailleurs").await.unwrap();
+
+    let _alice = harness.connect(1, "alice").await;
+    let page = get_missed_events(&harness.hub, 1, &MissedCursor::since(since), 2).await.unwrap();
+    assert!(page.has_more);
+    assert!(page.events.iter().all(|event| event.kind == HubEventKind::MessageCreated));
+    assert_eq!(
+        page.events.iter().map(|event| (event.conversation_id, event.data["messageId"].as_i64().unwrap())).collect::<Vec<_>>(),
+        vec![(GENERAL, first.id), (RANDOM, second.id)]
+    );
+
+    // La suite reprend après le curseur, sans doublon
+    let cursor = MissedCursor::decode(&page.cursor.encode()).unwrap();
+    let rest = get_missed_events(&harness.hub, 1, &cursor, 2).await.unwrap();
+    assert!(!rest.has_more);
+    assert_eq!(rest.events.len(), 1);
+    assert_eq!(rest.events[0].data["messageId"], third.id);
+    assert_eq!(rest.events[0].data["content"], "trois");
+    assert_eq!(rest.events[0].data["username"], "bob");
+
+    assert!(get_missed_events(&harness.hub, 1, &rest.cursor, 2).await.unwrap().events.is_empty());
+}
+
+#[tokio::test]
+async fn test_send_ack_mode_is_a_connection_default() {
+    let harness = TestHarness::new();
+    let _alice = harness.connect(1, "alice").await;
+
+    assert_eq!(harness.hub.ack_mode_of(1).await, AckMode::Confirm);
+    harness.hub.set_ack_mode(1, AckMode::FireAndForget).await.unwrap();
+    assert_eq!(harness.hub.ack_mode_of(1).await, AckMode::FireAndForget);
+    // Le drapeau de la commande l'emporte toujours
+    assert_eq!(harness.hub.ack_mode_of(1).await.for_command(Some(true)), AckMode::Confirm);
+
+    // Réglage propre à la connexion : une reconnexion repart en `Confirm`
+    harness.disconnect(1).await;
+    assert!(harness.hub.set_ack_mode(1, AckMode::FireAndForget).await.is_err());
+    let _alice = harness.connect(1, "alice").await;
+    assert_eq!(harness.hub.ack_mode_of(1).await, AckMode::Confirm);
+}
+
+fn joined_rooms(client: &mut TestClient) -> Vec<i64> {
+    client.drain_frames().iter()
+        .filter(|frame| frame["type"] == "room_joined")
+        .filter_map(|frame| frame["data"]["roomId"].as_i64())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_default_rooms_are_joined_once_within_the_room_cap() {
+    const ANNOUNCEMENTS: i64 = 12;
+    let mut config = ServerConfig::default();
+    config.features.default_rooms = ["general", "announcements", "random"].iter()
+        .map(|name| RoomId::new(name).unwrap())
+        .collect();
+    config.limits.max_rooms_per_user = 2;
+    let harness = TestHarness::with_config(config);
+    create_room(&harness, GENERAL, "general", &[(2, "bob")]).await;
+    create_room(&harness, ANNOUNCEMENTS, "announcements", &[]).await;
+    create_room(&harness, RANDOM, "random", &[]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    bob.drain_frames();
+
+    // Ordre de la configuration, dans la limite de salons
+    let mut alice = harness.connect(1, "alice").await;
+    assert_eq!(joined_rooms(&mut alice), vec![GENERAL, ANNOUNCEMENTS]);
+    let frame = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "member_joined");
+    assert_eq!(frame["data"]["userId"], 1);
+    assert!(harness.hub.rooms.get(&general()).await.expect("salon en mémoire").contains(&1));
+
+    // Salon quitté jamais rejoint à nouveau ; la place libérée revient au suivant
+    harness.rooms.remove_member(GENERAL, 1).await;
+    harness.disconnect(1).await;
+    let mut alice = harness.connect(1, "alice").await;
+    assert_eq!(joined_rooms(&mut alice), vec![RANDOM]);
+
+    harness.disconnect(1).await;
+    let mut alice = harness.connect(1, "alice").await;
+    assert!(joined_rooms(&mut alice).is_empty());
+}
+
+#[tokio::test]
+async fn test_reconnect_within_grace_keeps_rooms_without_flapping() {
+    let mut config = ServerConfig::default();
+    config.limits.reconnect_grace = Duration::from_secs(60);
+    let harness = TestHarness::with_config(config);
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    harness.connect(1, "alice").await;
+    subscribe_presence(&harness.hub, 2, &[1], &[]).await.unwrap();
+    bob.drain_frames();
+
+    harness.disconnect(1).await;
+    assert!(harness.hub.departures.lock().await.contains_key(&1));
+    assert_eq!(expire_departures(&harness.hub).await, 0);
+    let _alice = harness.connect(1, "alice").await;
+
+    // Ni départ, ni présence, ni retour annoncés, toujours présent
+    assert!(bob.drain_frames().is_empty());
+    assert!(!harness.hub.departures.lock().await.contains_key(&1));
+    assert!(harness.hub.rooms.get(&general()).await.expect("salon en mémoire").contains(&1));
+    send(&harness, GENERAL, 1, "alice", "de retour").await.unwrap();
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.unwrap()["type"], "room_message");
+
+    // Une expulsion ne bénéficie d'aucun délai
+    harness.hub.disconnect_user(1, CloseReason::Kicked, None).await;
+    assert!(!harness.hub.departures.lock().await.contains_key(&1));
+    let members = harness.hub.rooms.get(&general()).await.expect("salon en mémoire");
+    assert!(!members.contains(&1));
+    assert!(members.contains(&2));
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["data"]["event"], "offline");
+}
+
+#[tokio::test]
+async fn test_grace_expiry_spares_a_user_who_reconnected() {
+    let mut config = ServerConfig::default();
+    config.limits.reconnect_grace = Duration::from_secs(60);
+    let harness = TestHarness::with_config(config);
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    let _alice = harness.connect(1, "alice").await;
+    bob.drain_frames();
+
+    // Départ échu encore enregistré alors qu'une session est ouverte
+    harness.hub.departures.lock().await.insert(1, Departure::new("alice".to_string(), Instant::now()));
+    assert_eq!(expire_departures(&harness.hub).await, 0);
+
+    assert!(harness.hub.departures.lock().await.is_empty());
+    assert!(harness.hub.rooms.get(&general()).await.expect("salon en mémoire").contains(&1));
+    assert!(bob.drain_frames().is_empty());
+}
+
+#[tokio::test]
+async fn test_empty_room_removed_from_hub_after_grace() {
+    let mut config = ServerConfig::default();
+    config.limits.empty_room_grace = Duration::from_millis(20);
+    let harness = TestHarness::with_config(config);
+    create_room(&harness, GENERAL, "general", &[(1, "alice")]).await;
+    harness.connect(1, "alice").await;
+    harness.disconnect(1).await;
+    assert_eq!(harness.hub.rooms.get(&general()).await, Some(Vec::new()));
+
+    // Premier passage : vu vide, conservé pendant le délai
+    assert_eq!(cleanup_empty_rooms(&harness.hub).await, 0);
+    assert!(harness.hub.rooms.contains_key(&general()).await);
+
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(cleanup_empty_rooms(&harness.hub).await, 1);
+    assert!(!harness.hub.rooms.contains_key(&general()).await);
+    assert_eq!(harness.hub.empty_rooms.lock().await.pending(), 0);
+}
+
+#[tokio::test]
+async fn test_room_rejoined_within_grace_is_kept() {
+    let mut config = ServerConfig::default();
+    config.limits.empty_room_grace = Duration::from_millis(50);
+    let harness = TestHarness::with_config(config);
+    create_room(&harness, GENERAL, "general", &[(1, "alice")]).await;
+    harness.connect(1, "alice").await;
+    harness.disconnect(1).await;
+    assert_eq!(cleanup_empty_rooms(&harness.hub).await, 0);
+
+    // Retour avant la fin du délai : la même entrée est repeuplée et oubliée du suivi
+    let _alice = harness.connect(1, "alice").await;
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(cleanup_empty_rooms(&harness.hub).await, 0);
+    assert_eq!(harness.hub.rooms.get(&general()).await, Some(vec![1]));
+    assert_eq!(harness.hub.empty_rooms.lock().await.pending(), 0);
+}
+
+#[tokio::test]
+async fn test_member_left_broadcast_when_grace_expires() {
+    let mut config = ServerConfig::default();
+    config.limits.reconnect_grace = Duration::from_millis(1);
+    let harness = TestHarness::with_config(config);
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    let mut bob = harness.connect(2, "bob").await;
+    harness.connect(1, "alice").await;
+    subscribe_presence(&harness.hub, 2, &[1], &[]).await.unwrap();
+    bob.drain_frames();
+
+    harness.disconnect(1).await;
+    assert!(bob.drain_frames().is_empty());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(expire_departures(&harness.hub).await, 1);
+
+    let offline = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(offline["type"], "presence_update");
+    assert_eq!(offline["data"]["event"], "offline");
+    let left = bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(left["type"], "member_left");
+    assert_eq!(left["data"]["roomName"], "general");
+    assert_eq!(left["data"]["userId"], 1);
+    assert_eq!(left["data"]["username"], "alice");
+    let members = harness.hub.rooms.get(&general()).await.expect("salon en mémoire");
+    assert!(!members.contains(&1));
+    assert!(members.contains(&2));
+}
+
+fn guest_harness(messages_per_minute: u32) -> TestHarness {
+    let mut config = ServerConfig::default();
+    config.guests.enabled = true;
+    config.guests.messages_per_minute = messages_per_minute;
+    TestHarness::with_config(config)
+}
+
+#[tokio::test]
+async fn test_guest_reads_public_room_without_posting() {
+    // Invités désactivés par défaut
+    assert!(TestHarness::new().connect_guest(None).await.is_err());
+
+    let harness = guest_harness(3);
+    harness.connect(1, "alice").await;
+    create_room(&harness, SUPPORT, "support", &[(1, "alice")]).await;
+    create_room(&harness, 21, "prive", &[]).await;
+    harness.rooms.set_guest_access(SUPPORT, GuestAccess::ReadOnly).await;
+
+    let mut guest = harness.connect_guest(Some("visiteur")).await.unwrap();
+    assert!(guest.user_id < 0);
+    assert_eq!(guest.username, "guest-visiteur");
+    assert!(join_room_as_guest(&harness.hub, 21, guest.user_id).await.is_err());
+    assert_eq!(join_room_as_guest(&harness.hub, SUPPORT, guest.user_id).await.unwrap(), GuestAccess::ReadOnly);
+
+    send(&harness, SUPPORT, 1, "alice", "bienvenue").await.unwrap();
+    let frame = guest.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["type"], "room_message");
+    assert_eq!(frame["data"]["content"], "bienvenue");
+
+    // Lecture seule : aucune publication
+    let refused = send_guest_message(&harness.hub, SUPPORT, guest.user_id, "bonjour").await;
+    assert!(matches!(refused, Err(ChatError::Unauthorized { .. })));
+}
+
+#[tokio::test]
+async fn test_guest_connection_claiming_a_member_id_stays_a_guest() {
+    let harness = guest_harness(3);
+    let mut alice = harness.connect(1, "alice").await;
+    create_room(&harness, SUPPORT, "support", &[(1, "alice")]).await;
+    harness.rooms.set_guest_access(SUPPORT, GuestAccess::ReadOnly).await;
+    let mut guest = harness.connect_guest(Some("visiteur")).await.unwrap();
+    guest.drain_frames();
+    alice.drain_frames();
+
+    // Se présenter comme alice est refusé ; sans userId, la trame reste celle d'un invité
+    harness.serve_rooms(&guest, vec![
+        text_frame(serde_json::json!({ "type": "join_room", "data": { "roomId": SUPPORT, "userId": 1 } })),
+        text_frame(serde_json::json!({ "type": "send_message", "data": { "roomId": SUPPORT, "userId": 1, "content": "usurpé" } })),
+        text_frame(serde_json::json!({ "type": "join_room", "data": { "roomId": SUPPORT } })),
+        text_frame(serde_json::json!({ "type": "send_message", "data": { "roomId": SUPPORT, "content": "bonjour" } })),
+    ]).await;
+
+    let frames = guest.drain_frames();
+    let types: Vec<_> = frames.iter().map(|frame| frame["type"].as_str().unwrap_or("")).collect();
+    assert_eq!(types, vec!["error", "error", "room_joined", "error"]);
+    assert!(frames[0]["data"]["error"].as_str().unwrap().contains("user_id_mismatch"));
+    assert_eq!(frames[2]["data"]["guest"], true);
+    assert_eq!(frames[2]["data"]["userId"], guest.user_id);
+    assert_eq!(frames[3]["data"]["action"], "send_message");
+
+    // Lecture seule respectée : rien n'est publié, ni au nom d'alice ni en invité
+    assert!(harness.rooms.room_history(SUPPORT).await.is_empty());
+    assert!(harness.rooms.guest_messages(SUPPORT).await.is_empty());
+    assert!(alice.drain_frames().iter().all(|frame| frame["type"] != "room_message"));
+}
+
+#[tokio::test]
+async fn test_guest_cannot_send_direct_messages() {
+    let harness = guest_harness(3);
+    let guest = harness.connect_guest(None).await.unwrap();
+    let guest_id = guest.user_id as i64;
+
+    // Refusé avant toute requête : le hub de test n'a pas de base
+    let sent = send_dm_message(&harness.hub, 1, guest_id, &guest.username, "salut", None, None).await;
+    assert!(matches!(sent, Err(ChatError::Unauthorized { .. })));
+    let opened = get_or_create_dm_conversation(&harness.hub, 1, guest_id).await;
+    assert!(matches!(opened, Err(ChatError::Unauthorized { .. })));
+}
+
+#[tokio::test]
+async fn test_dm_privacy_withholds_messages_without_revealing_it() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    harness.rooms.add_user(3, "carol").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let from_alice = get_or_create_dm_conversation(&harness.hub, 1, 2).await.unwrap().id;
+    let from_carol = get_or_create_dm_conversation(&harness.hub, 3, 2).await.unwrap().id;
+    bob.drain_frames();
+
+    // Tout le monde par défaut
+    let sent = send_dm_message(&harness.hub, from_carol, 3, "carol", "salut", None, None).await.unwrap();
+    assert!(sent.is_some());
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "dm_message");
+
+    // Salon commun exigé : carol est écartée sans erreur, alice passe
+    harness.rooms.set_dm_privacy(2, DmPrivacy::SharedRooms).await;
+    assert!(send_dm_message(&harness.hub, from_carol, 3, "carol", "tu es là ?", None, None).await.unwrap().is_none());
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+    assert!(send_dm_message(&harness.hub, from_alice, 1, "alice", "oui", None, None).await.unwrap().is_some());
+    assert_eq!(bob.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["type"], "dm_message");
+
+    // Personne
+    harness.rooms.set_dm_privacy(2, DmPrivacy::Nobody).await;
+    assert!(send_dm_message(&harness.hub, from_alice, 1, "alice", "et maintenant ?", None, None).await.unwrap().is_none());
+    assert!(bob.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // Rien n'est stocké pour les messages écartés
+    assert_eq!(harness.rooms.dm_history(from_carol).await.len(), 1);
+    assert_eq!(harness.rooms.dm_history(from_alice).await.len(), 1);
+
+    // Hors de la conversation : introuvable
+    let outsider = send_dm_message(&harness.hub, from_alice, 3, "carol", "coucou", None, None).await;
+    assert!(matches!(outsider, Err(ChatError::NotFound { .. })));
+}
+
+/// Contenus de l'historique DM vus par `user_id`, du plus ancien au plus récent
+async fn dm_contents(harness: &TestHarness, conversation_id: i64, user_id: i64) -> Vec<String> {
+    let page = fetch_history(&harness.hub, conversation_id, user_id, 50, None).await.unwrap();
+    page.items.into_iter().rev().map(|message| message.content).collect()
+}
+
+/// Alice et Bob échangent deux messages, puis Alice bloque Bob
+async fn blocked_dm_exchange(mode: BlockedDmHistory) -> (TestHarness, i64) {
+    let mut config = ServerConfig::default();
+    config.security.blocked_dm_history = mode;
+    let harness = TestHarness::with_config(config);
+    harness.rooms.add_user(1, "alice").await;
+    harness.rooms.add_user(2, "bob").await;
+    let conversation_id = get_or_create_dm_conversation(&harness.hub, 1, 2).await.unwrap().id;
+    send_dm_message(&harness.hub, conversation_id, 1, "alice", "salut", None, None).await.unwrap();
+    send_dm_message(&harness.hub, conversation_id, 2, "bob", "ça va ?", None, None).await.unwrap();
+    assert_eq!(dm_contents(&harness, conversation_id, 2).await, ["salut", "ça va ?"]);
+
+    block_dm_conversation(&harness.hub, conversation_id, 1, true, false).await.unwrap();
+    (harness, conversation_id)
+}
+
+#[tokio::test]
+async fn test_blocked_dm_history_follows_the_configured_mode() {
+    // Conservé : rien ne change, et Bob ne peut rien en déduire
+    let (harness, conversation_id) = blocked_dm_exchange(BlockedDmHistory::Keep).await;
+    assert_eq!(dm_contents(&harness, conversation_id, 1).await, ["salut", "ça va ?"]);
+    assert_eq!(dm_contents(&harness, conversation_id, 2).await, ["salut", "ça va ?"]);
+
+    // Masqué pour Alice seulement, jusqu'au déblocage
+    let (harness, conversation_id) = blocked_dm_exchange(BlockedDmHistory::HideForBlocker).await;
+    assert!(dm_contents(&harness, conversation_id, 1).await.is_empty());
+    assert_eq!(dm_contents(&harness, conversation_id, 2).await, ["salut", "ça va ?"]);
+    block_dm_conversation(&harness.hub, conversation_id, 1, false, false).await.unwrap();
+    assert_eq!(dm_contents(&harness, conversation_id, 1).await, ["salut", "ça va ?"]);
+
+    // Supprimé au blocage, pour les deux et définitivement
+    let (harness, conversation_id) = blocked_dm_exchange(BlockedDmHistory::Delete).await;
+    assert!(dm_contents(&harness, conversation_id, 1).await.is_empty());
+    assert!(dm_contents(&harness, conversation_id, 2).await.is_empty());
+    block_dm_conversation(&harness.hub, conversation_id, 1, false, false).await.unwrap();
+    assert!(dm_contents(&harness, conversation_id, 1).await.is_empty());
+
+    // Hors de la conversation : refusé
+    let outsider = fetch_history(&harness.hub, conversation_id, 3, 50, None).await;
+    assert!(matches!(outsider, Err(ChatError::Unauthorized { .. })));
+}
+
+#[tokio::test]
+async fn test_dm_repository_is_used_without_a_hub() {
+    let dms = InMemoryRoomRepository::new().repositories().dms;
+    let audit = AuditSink::new(AuditConfig::default());
+
+    let conversation = dms.open_dm_conversation(1, 2, &audit).await.unwrap();
+    assert_eq!(dms.open_dm_conversation(2, 1, &audit).await.unwrap().id, conversation.id);
+
+    // Le sort de l'historique est passé en paramètre, sans configuration de hub
+    dms.set_dm_block(conversation.id, 1, true, false, BlockedDmHistory::Delete, &audit).await.unwrap();
+    let blocked = dms.dm_conversation(conversation.id, 2).await.unwrap().expect("participant");
+    assert!(blocked.is_blocked);
+    assert_eq!(blocked.blocked_by, Some(1));
+    assert!(dms.dm_conversation(conversation.id, 3).await.unwrap().is_none());
+}
+
+/// (emoji, nombre) des réactions d'un message telles que les voit `viewer_id`
+async fn reaction_counts(harness: &TestHarness, message_id: i64, viewer_id: i64) -> Vec<(String, i64)> {
+    get_message_reactions(&harness.hub, message_id, viewer_id).await.unwrap()
+        .reactions.into_iter()
+        .map(|summary| (summary.emoji, summary.count))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_blocking_with_hidden_reactions_hides_them_from_the_blocker_only() {
+    let harness = TestHarness::new();
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob"), (3, "carol")]).await;
+    let from_alice = send(&harness, GENERAL, 1, "alice", "mon message").await.unwrap();
+    let from_carol = send(&harness, GENERAL, 3, "carol", "le sien").await.unwrap();
+    harness.rooms.add_reaction(from_alice.id, 2, "👍").await;
+    harness.rooms.add_reaction(from_alice.id, 3, "👍").await;
+    harness.rooms.add_reaction(from_alice.id, 2, "🎉").await;
+    harness.rooms.add_reaction(from_carol.id, 2, "👍").await;
+
+    let conversation = get_or_create_dm_conversation(&harness.hub, 1, 2).await.unwrap();
+    block_dm_conversation(&harness.hub, conversation.id, 1, true, true).await.unwrap();
+
+    // Alice ne voit plus les réactions de Bob sur son message, Bob si
+    assert_eq!(reaction_counts(&harness, from_alice.id, 1).await, vec![("👍".to_string(), 1)]);
+    assert_eq!(
+        reaction_counts(&harness, from_alice.id, 2).await,
+        vec![("👍".to_string(), 2), ("🎉".to_string(), 1)]
+    );
+    // Sur le message de Carol, rien n'est masqué
+    assert_eq!(reaction_counts(&harness, from_carol.id, 1).await, vec![("👍".to_string(), 1)]);
+
+    // Le déblocage rend les réactions de nouveau visibles
+    block_dm_conversation(&harness.hub, conversation.id, 1, false, false).await.unwrap();
+    assert_eq!(
+        reaction_counts(&harness, from_alice.id, 1).await,
+        vec![("👍".to_string(), 2), ("🎉".to_string(), 1)]
+    );
+
+    // Hors de la conversation : refusé
+    let outsider = block_dm_conversation(&harness.hub, conversation.id, 3, true, true).await;
+    assert!(matches!(outsider, Err(ChatError::Unauthorized { .. })));
+}
+
+#[tokio::test]
+async fn test_start_conversation_refuses_blocks_and_reuses_the_conversation() {
+    let harness = TestHarness::new();
+    for (user_id, username) in [(1, "alice"), (2, "bob"), (3, "carol"), (4, "dave")] {
+        harness.rooms.add_user(user_id, username).await;
+    }
+
+    let (conversation, participant) = start_conversation(&harness.hub, 1, 2).await.unwrap();
+    assert_eq!((conversation.user1_id, conversation.user2_id), (1, 2));
+    assert_eq!(participant.username, "bob");
+    let (again, _) = start_conversation(&harness.hub, 2, 1).await.unwrap();
+    assert_eq!(again.id, conversation.id);
+
+    // Blocage dans un sens comme dans l'autre : refusé
+    harness.rooms.block_user(3, 1).await;
+    assert!(matches!(start_conversation(&harness.hub, 1, 3).await, Err(ChatError::Unauthorized { .. })));
+    harness.rooms.block_user(1, 4).await;
+    assert!(matches!(start_conversation(&harness.hub, 1, 4).await, Err(ChatError::Unauthorized { .. })));
+
+    assert!(matches!(start_conversation(&harness.hub, 1, 99).await, Err(ChatError::NotFound { .. })));
+    assert!(start_conversation(&harness.hub, 1, 1).await.is_err());
+}
+
+#[tokio::test]
+async fn test_guest_messages_are_labeled_and_rate_limited() {
+    let harness = guest_harness(2);
+    let mut alice = harness.connect(1, "alice").await;
+    create_room(&harness, SUPPORT, "support", &[(1, "alice")]).await;
+    harness.rooms.set_guest_access(SUPPORT, GuestAccess::Limited).await;
+    let guest = harness.connect_guest(None).await.unwrap();
+    join_room_as_guest(&harness.hub, SUPPORT, guest.user_id).await.unwrap();
+
+    let sent = send_guest_message(&harness.hub, SUPPORT, guest.user_id, "une question").await.unwrap();
+    // Non conservé par défaut
+    assert_eq!(sent, None);
+    assert!(harness.rooms.guest_messages(SUPPORT).await.is_empty());
+    let frame = alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue");
+    assert_eq!(frame["data"]["guest"], true);
+    assert_eq!(frame["data"]["persisted"], false);
+
+    send_guest_message(&harness.hub, SUPPORT, guest.user_id, "deux").await.unwrap();
+    let limited = send_guest_message(&harness.hub, SUPPORT, guest.user_id, "trois").await;
+    assert!(matches!(limited, Err(ChatError::RateLimitExceeded { limit: 2, .. })));
+
+    // La limite invitée ne touche pas les comptes
+    for content in ["un", "deux", "trois"] {
+        send(&harness, SUPPORT, 1, "alice", content).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_guest_messages_are_screened_like_member_messages() {
+    let harness = guest_harness(10);
+    let mut alice = harness.connect(1, "alice").await;
+    create_room(&harness, SUPPORT, "support", &[(1, "alice")]).await;
+    harness.rooms.set_guest_access(SUPPORT, GuestAccess::Limited).await;
+    harness.rooms.set_filter_mode(SUPPORT, RoomFilterMode::Flag).await;
+    let mut guest = harness.connect_guest(None).await.unwrap();
+    join_room_as_guest(&harness.hub, SUPPORT, guest.user_id).await.unwrap();
+
+    // Refusé par le filtre, ou retenu pour un membre : jamais diffusé
+    for content in ["kys", "VENEZ TOUS CE SOIR"] {
+        let refused = send_guest_message(&harness.hub, SUPPORT, guest.user_id, content).await;
+        assert!(matches!(refused, Err(ChatError::InappropriateContent { .. })), "{} aurait dû être refusé", content);
+    }
+    assert!(alice.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    // Salon pré-modéré : aucun message d'invité
+    harness.rooms.set_filter_mode(SUPPORT, RoomFilterMode::Approve).await;
+    let refused = send_guest_message(&harness.hub, SUPPORT, guest.user_id, "une question").await;
+    assert!(matches!(refused, Err(ChatError::InsufficientPermissions { .. })));
+
+    // Mode anti-raid : un invité est toujours restreint
+    harness.rooms.set_filter_mode(SUPPORT, RoomFilterMode::Off).await;
+    harness.hub.anti_raid.lock().await.activate(RaidScope::Room(SUPPORT), Instant::now() + Duration::from_secs(60));
+    let refused = send_guest_message(&harness.hub, SUPPORT, guest.user_id, "une question").await;
+    assert!(matches!(refused, Err(ChatError::InsufficientPermissions { .. })));
+    assert!(alice.next_frame(FRAME_TIMEOUT).await.is_none());
+
+    harness.hub.anti_raid.lock().await.activate(RaidScope::Room(SUPPORT), Instant::now());
+    send_guest_message(&harness.hub, SUPPORT, guest.user_id, "une question").await.unwrap();
+    assert_eq!(alice.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["data"]["guest"], true);
+    // Relayé aux invités présents, auteur compris (`forward_to_guests`)
+    assert_eq!(guest.next_frame(FRAME_TIMEOUT).await.expect("trame attendue")["data"]["content"], "une question");
+}
+
+#[tokio::test]
+async fn test_guest_ids_do_not_repeat() {
+    let harness = guest_harness(1);
+    let mut ids = std::collections::HashSet::new();
+    for _ in 0..50 {
+        let guest = harness.connect_guest(None).await.unwrap();
+        assert!(guest.user_id < 0);
+        assert!(ids.insert(guest.user_id), "identifiant invité réutilisé");
+    }
+}
+
+#[tokio::test]
+async fn test_slow_session_is_kept_when_its_frame_is_dropped() {
+    let harness = TestHarness::new();
+    let (client, _lanes) = Client::with_priority_lanes(1, "alice".to_string(), 1);
+    harness.hub.register(1, client).await.unwrap();
+
+    // File ordinaire pleine : trames abandonnées, session conservée
+    let chatter = serde_json::json!({ "type": "room_message", "data": {} }).to_string();
+    harness.hub.send_to_user_sessions(1, &chatter).await;
+    assert_eq!(harness.hub.send_to_user_sessions(1, &chatter).await, 0);
+
+    // La trame critique suivante atteint toujours la session
+    let notice = serde_json::json!({ "type": "moderation_notice", "data": {} }).to_string();
+    assert_eq!(harness.hub.send_to_user_sessions(1, &notice).await, 1);
+}
+
+fn key_bundle(prekey_ids: &[i64]) -> KeyBundleUpload {
+    KeyBundleUpload {
+        identity_key: "aWRlbnRpdHk=".to_string(),
+        signed_prekey_id: 1,
+        signed_prekey: "cHJla2V5".to_string(),
+        signed_prekey_signature: "c2lnbmF0dXJl".to_string(),
+        one_time_prekeys: prekey_ids.iter()
+            .map(|&key_id| OneTimePrekey { key_id, public_key: format!("b3Rr{}", key_id) })
+            .collect(),
+    }
+}
+
+#[tokio::test]
+async fn test_fetched_prekeys_are_consumed_once() {
+    let harness = TestHarness::new();
+    harness.rooms.add_key_bundle(1, key_bundle(&[2, 1])).await;
+    let mut alice = harness.connect(1, "alice").await;
+
+    // Ses propres clés : aucune clé à usage unique consommée
+    let own = fetch_key_bundle(&harness.hub, 1, 1).await.unwrap();
+    assert!(own.one_time_prekey.is_none());
+    assert!(alice.drain_frames().is_empty());
+
+    let first = fetch_key_bundle(&harness.hub, 2, 1).await.unwrap();
+    assert_eq!(first.identity_key, "aWRlbnRpdHk=");
+    assert_eq!(first.one_time_prekey.map(|prekey| prekey.key_id), Some(1));
+    let second = fetch_key_bundle(&harness.hub, 3, 1).await.unwrap();
+    assert_eq!(second.one_time_prekey.map(|prekey| prekey.key_id), Some(2));
+    let exhausted = fetch_key_bundle(&harness.hub, 2, 1).await.unwrap();
+    assert!(exhausted.one_time_prekey.is_none());
+
+    let low = alice.drain_frames();
+    assert_eq!(low.len(), 3);
+    assert!(low.iter().all(|frame| frame["type"] == "e2ee_prekeys_low"));
+    assert_eq!(low[2]["data"]["remaining"], 0);
+
+    assert!(matches!(fetch_key_bundle(&harness.hub, 2, 4).await, Err(ChatError::NotFound { .. })));
+}
+
+#[tokio::test]
+async fn test_key_bundle_fetches_are_rate_limited_per_requester_and_target() {
+    let harness = TestHarness::new();
+    harness.rooms.add_key_bundle(1, key_bundle(&[])).await;
+    harness.rooms.add_key_bundle(2, key_bundle(&[])).await;
+
+    // Par demandeur : rafale bornée, quel que soit le destinataire
+    for _ in 0..5 {
+        fetch_key_bundle(&harness.hub, 3, 1).await.unwrap();
+    }
+    assert!(matches!(fetch_key_bundle(&harness.hub, 3, 2).await, Err(ChatError::RateLimitExceeded { .. })));
+
+    // Par destinataire : 30 remises par minute, tous demandeurs confondus
+    for requester in 4..29 {
+        fetch_key_bundle(&harness.hub, requester, 1).await.unwrap();
+    }
+    assert!(matches!(fetch_key_bundle(&harness.hub, 29, 1).await, Err(ChatError::RateLimitExceeded { .. })));
+    fetch_key_bundle(&harness.hub, 29, 2).await.unwrap();
+}
+
+/// Trame texte JSON telle qu'envoyée par un client
+fn text_frame(frame: serde_json::Value) -> Message {
+    Message::Text(frame.to_string())
+}
+
+#[tokio::test]
+async fn test_room_connection_routes_frames_through_the_inbox() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+    let send_frame = |content: &str| serde_json::json!({
+        "type": "send_message",
+        "data": { "roomId": GENERAL, "userId": 1, "username": "alice", "content": content }
+    });
+
+    let processed = harness.serve_rooms(&alice, vec![
+        text_frame(send_frame("premier")),
+        text_frame(serde_json::json!({
+            "type": "batch",
+            "commands": [send_frame("second"), { "type": "ping_diag", "correlationId": "c-1" }]
+        })),
+        Message::Close(None),
+        text_frame(send_frame("après fermeture")),
+    ]).await;
+    assert_eq!(processed, 2);
+
+    // Ordre de réception conservé, rien après la fermeture
+    let contents: Vec<_> = bob.drain_frames().into_iter()
+        .filter(|frame| frame["type"] == "room_message")
+        .map(|frame| frame["data"]["content"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(contents, vec!["premier", "second"]);
+
+    let batch = alice.drain_frames().into_iter()
+        .find(|frame| frame["type"] == "batch_result")
+        .expect("résultat du lot attendu");
+    let results = batch["data"]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result["ok"] == true));
+    assert_eq!(results[1]["response"]["type"], "pong_diag");
+    assert_eq!(results[1]["response"]["data"]["correlationId"], "c-1");
+
+    // La session est retirée du hub à la fin de la lecture
+    assert!(harness.hub.clients.get(&1).await.is_none());
+}
+
+#[tokio::test]
+async fn test_dm_connection_routes_frames_through_the_inbox() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+
+    let processed = harness.serve_dms(&alice, vec![
+        text_frame(serde_json::json!({ "type": "ping_diag", "correlationId": "d-1" })),
+        Message::Text("pas du json".to_string()),
+        text_frame(serde_json::json!({
+            "type": "batch",
+            "commands": [{ "type": "ping_diag", "correlationId": "d-2" }]
+        })),
+    ]).await;
+    assert_eq!(processed, 3);
+
+    let frames = alice.drain_frames();
+    let types: Vec<_> = frames.iter().map(|frame| frame["type"].as_str().unwrap_or("")).collect();
+    assert_eq!(types, vec!["pong_diag", "error", "batch_result"]);
+    assert_eq!(frames[0]["data"]["correlationId"], "d-1");
+    assert_eq!(frames[1]["data"]["action"], "parse");
+    assert_eq!(frames[2]["data"]["results"][0]["response"]["data"]["correlationId"], "d-2");
+}
+
+#[tokio::test]
+async fn test_ping_diag_is_limited_per_connection_user() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    let ping = |data: serde_json::Value| text_frame(serde_json::json!({ "type": "ping_diag", "data": data }));
+
+    // Rafale d'alice sans userId, puis une tentative au nom de bob
+    let mut frames: Vec<_> = (0..6).map(|i| ping(serde_json::json!({ "correlationId": i }))).collect();
+    frames.push(ping(serde_json::json!({ "userId": 2, "correlationId": "rotation" })));
+    harness.serve_rooms(&alice, frames).await;
+
+    let replies = alice.drain_frames();
+    let types: Vec<_> = replies.iter().map(|frame| frame["type"].as_str().unwrap_or("")).collect();
+    assert_eq!(types, vec!["pong_diag", "pong_diag", "pong_diag", "pong_diag", "pong_diag", "error", "error"]);
+    assert!(replies[5]["data"]["error"].as_str().unwrap().contains("Diagnostic"));
+    assert!(replies[6]["data"]["error"].as_str().unwrap().contains("user_id_mismatch"));
+
+    // Le compteur de bob est intact
+    bob.drain_frames();
+    harness.serve_rooms(&bob, vec![ping(serde_json::json!({ "correlationId": "b-1" }))]).await;
+    let replies = bob.drain_frames();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0]["type"], "pong_diag");
+}
+
+#[tokio::test]
+async fn test_frames_claiming_another_user_are_refused() {
+    let harness = TestHarness::new();
+    let mut alice = harness.connect(1, "alice").await;
+    let mut bob = harness.connect(2, "bob").await;
+    create_room(&harness, GENERAL, "general", &[(1, "alice"), (2, "bob")]).await;
+
+    // La connexion d'alice se fait passer pour bob, en salon puis en DM
+    harness.serve_rooms(&alice, vec![
+        text_frame(serde_json::json!({
+            "type": "send_message",
+            "data": { "roomId": GENERAL, "userId": 2, "username": "bob", "content": "usurpé" }
+        })),
+        text_frame(serde_json::json!({
+            "type": "send_message",
+            "data": { "roomId": GENERAL, "username": "bob", "content": "sans userId" }
+        })),
+    ]).await;
+    harness.serve_dms(&alice, vec![
+        text_frame(serde_json::json!({
+            "type": "send_dm_message",
+            "data": { "conversationId": 1, "userId": 2, "username": "bob", "content": "usurpé" }
+        })),
+    ]).await;
+
+    let errors: Vec<_> = alice.drain_frames().into_iter()
+        .filter(|frame| frame["type"] == "error")
+        .collect();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|frame| frame["data"]["error"].as_str().unwrap().contains("user_id_mismatch")));
+
+    // Seule la trame sans userId passe, au nom d'alice
+    let messages: Vec<_> = bob.drain_frames().into_iter()
+        .filter(|frame| frame["type"] == "room_message")
+        .collect();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["data"]["content"], "sans userId");
+    assert_eq!(messages[0]["data"]["authorId"], 1);
+    assert_eq!(messages[0]["data"]["username"], "alice");
+}